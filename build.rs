@@ -2,20 +2,124 @@
 #![allow(missing_docs, clippy::missing_docs_in_private_items)]
 
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 fn main() {
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let src_path = PathBuf::from(&manifest_dir);
 
-    // Compile musubi_impl.c which includes musubi.h with MU_IMPLEMENTATION
+    // Tell cargo to rerun if these files change
+    println!("cargo:rerun-if-changed=src/musubi_impl.c");
+    println!("cargo:rerun-if-changed=musubi.h");
+    println!("cargo:rerun-if-changed=unidata.h");
+    println!("cargo:rerun-if-env-changed=MUSUBI_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=MUSUBI_STATIC");
+    println!("cargo:rerun-if-env-changed=MUSUBI_INCLUDE_DIR");
+
+    // `rust-backend` and `system` take precedence, so they work without
+    // `default-features = false`
+    if env::var_os("CARGO_FEATURE_RUST_BACKEND").is_some() {
+        // the `mu_*` symbols are provided by src/backend.rs
+    } else if env::var_os("CARGO_FEATURE_SYSTEM").is_some() {
+        link_system(&src_path);
+    } else if env::var_os("CARGO_FEATURE_VENDORED").is_some() {
+        build_vendored(&src_path);
+    } else {
+        panic!("musubi-rs: enable one of the `vendored`, `system` or `rust-backend` features");
+    }
+}
+
+/// Compile musubi_impl.c which includes musubi.h with MU_IMPLEMENTATION
+fn build_vendored(src_path: &Path) {
     cc::Build::new()
         .file(src_path.join("src/musubi_impl.c"))
         .include(src_path.parent().unwrap()) // Include parent dir for musubi.h
         .compile("musubi");
+}
 
-    // Tell cargo to rerun if these files change
-    println!("cargo:rerun-if-changed=src/musubi_impl.c");
-    println!("cargo:rerun-if-changed=musubi.h");
-    println!("cargo:rerun-if-changed=unidata.h");
+/// Link against a system-installed libmusubi.
+///
+/// `MUSUBI_LIB_DIR` skips pkg-config and links from the given directory,
+/// `MUSUBI_STATIC` selects static linking in that case. The version is then
+/// read from the `musubi.h` installed with the library, in
+/// `MUSUBI_INCLUDE_DIR` or else `include` next to the library directory.
+fn link_system(src_path: &Path) {
+    let (major, minor) = header_version(&src_path.join("musubi.h"));
+    let check = |version: &str, from: &str| {
+        let mut parts = version.trim().split('.').map(|p| p.parse::<u32>().ok());
+        if (parts.next().flatten(), parts.next().flatten()) != (Some(major), Some(minor)) {
+            panic!(
+                "musubi-rs: system libmusubi {} ({from}) does not match bindings {major}.{minor}",
+                version.trim()
+            );
+        }
+    };
+
+    if let Some(dir) = env::var_os("MUSUBI_LIB_DIR") {
+        let dir = PathBuf::from(dir);
+        let include = match env::var_os("MUSUBI_INCLUDE_DIR") {
+            Some(include) => PathBuf::from(include),
+            None => dir.join("../include"),
+        };
+        let header = include.join("musubi.h");
+        println!("cargo:rerun-if-changed={}", header.display());
+        let (lib_major, lib_minor) = header_version(&header);
+        check(
+            &format!("{lib_major}.{lib_minor}"),
+            &header.display().to_string(),
+        );
+        let kind = match env::var_os("MUSUBI_STATIC") {
+            Some(_) => "static",
+            None => "dylib",
+        };
+        println!("cargo:rustc-link-search=native={}", dir.display());
+        println!("cargo:rustc-link-lib={kind}=musubi");
+        return;
+    }
+
+    check(&pkg_config(&["--modversion", "musubi"]), "pkg-config");
+    for flag in pkg_config(&["--libs", "musubi"]).split_whitespace() {
+        if let Some(dir) = flag.strip_prefix("-L") {
+            println!("cargo:rustc-link-search=native={dir}");
+        } else if let Some(lib) = flag.strip_prefix("-l") {
+            println!("cargo:rustc-link-lib={lib}");
+        }
+    }
+}
+
+fn pkg_config(args: &[&str]) -> String {
+    let pkg_config = env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".into());
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG");
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+    let output = Command::new(&pkg_config)
+        .args(args)
+        .output()
+        .unwrap_or_else(|e| panic!("musubi-rs: failed to run {pkg_config}: {e}"));
+    if !output.status.success() {
+        panic!(
+            "musubi-rs: `{pkg_config} {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).expect("pkg-config output is not UTF-8")
+}
+
+/// Read `MU_VERSION_MAJOR` and `MU_VERSION_MINOR` from a musubi header.
+fn header_version(header: &Path) -> (u32, u32) {
+    let text = fs::read_to_string(header).unwrap_or_else(|e| {
+        panic!(
+            "musubi-rs: failed to read {}: {e}; set MUSUBI_INCLUDE_DIR to the directory of musubi.h",
+            header.display()
+        )
+    });
+    let define = |name: &str| {
+        text.lines()
+            .filter_map(|l| l.trim().strip_prefix("#define ")?.strip_prefix(name))
+            .find_map(|rest| rest.trim().parse::<u32>().ok())
+            .unwrap_or_else(|| panic!("{name} not found in {}", header.display()))
+    };
+    (define("MU_VERSION_MAJOR"), define("MU_VERSION_MINOR"))
 }
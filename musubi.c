@@ -14,6 +14,7 @@
 #define LUAMOD_API               LUALIB_API
 #define lua_setuservalue(L, idx) lua_setfenv(L, idx)
 #define lua_getuservalue(L, idx) lua_getfenv(L, idx)
+#define lua_rawlen(L, idx)       lua_objlen(L, idx)
 #define luaL_setfuncs(L, l, n)   (assert(n == 0), luaL_register(L, NULL, l))
 #define luaL_setmetatable(L, name) \
     (luaL_getmetatable((L), (name)), lua_setmetatable(L, -2))
@@ -88,6 +89,8 @@ static int Lmu_config_new(lua_State *L) {
 
     mu_Config *config = (mu_Config *)lua_newuserdata(L, sizeof(mu_Config));
     mu_initconfig(config);
+    lua_createtable(L, 0, 1); /* anchors strings referenced by config */
+    lua_setuservalue(L, -2);
     luaL_getmetatable(L, LMU_CONFIG_TYPE);
     lua_pushvalue(L, -1);
     lua_setmetatable(L, -3);
@@ -173,6 +176,107 @@ static int Lmu_config_ambi_width(lua_State *L) {
     return lua_settop(L, 1), 1;
 }
 
+static int Lmu_config_markup(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->markup = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_inline_code(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->inline_code = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_normalize(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->normalize = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_bidi_isolate(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->bidi_isolate = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_auto_location(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->auto_location = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_dim_unlabeled(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->dim_unlabeled = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_caret_only(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->caret_only = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_one_based_columns(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->one_based_columns = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_auto_priority(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->auto_priority = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_max_depth(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->max_depth = (int)luaL_optinteger(L, 2, 0);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_gutter_bar(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->gutter_bar = lua_toboolean(L, 2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_collapse_notes(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    config->collapse_notes = (int)luaL_optinteger(L, 2, 0);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_line_no_sep(lua_State *L) {
+    mu_Config  *config = lmu_checkconfig(L, 1);
+    size_t      len = 0;
+    const char *sep = luaL_optlstring(L, 2, "", &len);
+    config->line_no_sep = mu_lslice(sep, len);
+    lua_getuservalue(L, 1);
+    lua_pushvalue(L, 2);
+    lua_setfield(L, -2, "line_no_sep");
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_tab_stops(lua_State *L) {
+    mu_Config *config = lmu_checkconfig(L, 1);
+    int        i, n = 0;
+    if (!lua_isnoneornil(L, 2)) {
+        luaL_checktype(L, 2, LUA_TTABLE);
+        n = (int)lua_rawlen(L, 2);
+        luaL_argcheck(L, n <= MU_TAB_STOPS_SIZE, 2, "too many tab stops");
+    }
+    for (i = 0; i < MU_TAB_STOPS_SIZE; ++i) {
+        config->tab_stops[i] = 0;
+        if (i >= n) continue;
+        lua_rawgeti(L, 2, i + 1);
+        config->tab_stops[i] = (int)luaL_checkinteger(L, -1);
+        lua_pop(L, 1);
+    }
+    return lua_settop(L, 1), 1;
+}
+
 static int Lmu_config_label_attach(lua_State *L) {
     const char *opts[] = {"middle", "start", "end", NULL};
     mu_Config  *config = lmu_checkconfig(L, 1);
@@ -187,6 +291,27 @@ static int Lmu_config_index_type(lua_State *L) {
     return lua_settop(L, 1), 1;
 }
 
+static int Lmu_config_overlap(lua_State *L) {
+    const char *opts[] = {"merge", "stack", "hide", NULL};
+    mu_Config  *config = lmu_checkconfig(L, 1);
+    config->overlap = luaL_checkoption(L, 2, "merge", opts);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_footer(lua_State *L) {
+    const char *opts[] = {"border", "omit", "blank", "labeled", NULL};
+    mu_Config  *config = lmu_checkconfig(L, 1);
+    config->footer = luaL_checkoption(L, 2, "border", opts);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_config_source_order(lua_State *L) {
+    const char *opts[] = {"cache", "labels", "name", "primary", NULL};
+    mu_Config  *config = lmu_checkconfig(L, 1);
+    config->source_order = luaL_checkoption(L, 2, "cache", opts);
+    return lua_settop(L, 1), 1;
+}
+
 static int Lmu_config_color(lua_State *L) {
     mu_Config *config = lmu_checkconfig(L, 1);
     if (lua_toboolean(L, 2)) config->color = mu_default_color;
@@ -218,8 +343,25 @@ static void lmu_openconfig(lua_State *L) {
         ENTRY(tab_width),
         ENTRY(limit_width),
         ENTRY(ambi_width),
+        ENTRY(markup),
+        ENTRY(inline_code),
+        ENTRY(normalize),
+        ENTRY(bidi_isolate),
+        ENTRY(auto_location),
+        ENTRY(dim_unlabeled),
+        ENTRY(caret_only),
+        ENTRY(one_based_columns),
+        ENTRY(auto_priority),
+        ENTRY(max_depth),
+        ENTRY(gutter_bar),
+        ENTRY(collapse_notes),
+        ENTRY(line_no_sep),
+        ENTRY(tab_stops),
         ENTRY(label_attach),
         ENTRY(index_type),
+        ENTRY(overlap),
+        ENTRY(footer),
+        ENTRY(source_order),
         ENTRY(color),
         ENTRY(char_set),
 #undef ENTRY
@@ -266,6 +408,7 @@ static void lmu_checkerror(lua_State *L, int err) {
     case MU_ERRPARAM: luaL_error(L, "musubi: invalid parameter"); break;
     case MU_ERRSRC:   luaL_error(L, "musubi: source out of range"); break;
     case MU_ERRFILE:  luaL_error(L, "musubi: file operation failed"); break;
+    case MU_ERRMEM:   luaL_error(L, "musubi: not enough memory"); break;
 
     /* LCOV_EXCL_START */
     default: luaL_error(L, "musubi: unknown error(%d)", err); break;
@@ -293,6 +436,10 @@ static void lmu_report_settitle(lua_State *L, lmu_Report *lr, int count) {
         msg = luaL_checklstring(L, 3, &msglen);
         if (strcasecmp(custom_level, "error") == 0) level = MU_ERROR;
         else if (strcasecmp(custom_level, "warning") == 0) level = MU_WARNING;
+        else if (strcasecmp(custom_level, "info") == 0) level = MU_INFO;
+        else if (strcasecmp(custom_level, "note") == 0) level = MU_NOTE;
+        else if (strcasecmp(custom_level, "hint") == 0) level = MU_HINT;
+        else if (strcasecmp(custom_level, "advice") == 0) level = MU_ADVICE;
         lmu_checkerror(L, mu_title(lr->R, level, mu_lslice(custom_level, cllen),
                                    mu_lslice(msg, msglen)));
         lua_getuservalue(L, 1);
@@ -405,6 +552,14 @@ static void lmu_pushcolorkind(lua_State *L, mu_ColorKind kind) {
     case MU_COLOR_UNIMPORTANT:    lua_pushliteral(L, "unimportant"); break;
     case MU_COLOR_NOTE:           lua_pushliteral(L, "note"); break;
     case MU_COLOR_LABEL:          lua_pushliteral(L, "label"); break;
+    case MU_COLOR_EMPHASIS:       lua_pushliteral(L, "emphasis"); break;
+    case MU_COLOR_INLINE_CODE:    lua_pushliteral(L, "inline_code"); break;
+    case MU_COLOR_CONTEXT:        lua_pushliteral(L, "context"); break;
+    case MU_COLOR_INFO:           lua_pushliteral(L, "info"); break;
+    case MU_COLOR_HINT:           lua_pushliteral(L, "hint"); break;
+    case MU_COLOR_ADVICE:         lua_pushliteral(L, "advice"); break;
+    case MU_COLOR_EXPECTED:       lua_pushliteral(L, "expected"); break;
+    case MU_COLOR_FOUND:          lua_pushliteral(L, "found"); break;
     default:                      lua_pushliteral(L, "unknown"); break;
     } /* LCOV_EXCL_STOP */
 }
@@ -454,6 +609,15 @@ static int Lmu_report_color(lua_State *L) {
     return lua_settop(L, 1), 1;
 }
 
+static int Lmu_report_colorkind(lua_State *L) {
+    const char  *opts[] = {"label", "expected", "found", NULL};
+    mu_ColorKind kinds[] = {MU_COLOR_LABEL, MU_COLOR_EXPECTED, MU_COLOR_FOUND};
+    mu_Report   *R = lmu_checkreport(L, 1)->R;
+    int          opt = luaL_checkoption(L, 2, NULL, opts);
+    lmu_checkerror(L, mu_colorkind(R, kinds[opt]));
+    return lua_settop(L, 1), 1;
+}
+
 static int Lmu_report_primary(lua_State *L) {
     mu_Report *R = lmu_checkreport(L, 1)->R;
     lmu_checkerror(L, mu_primary(R));
@@ -474,6 +638,32 @@ static int Lmu_report_priority(lua_State *L) {
     return lua_settop(L, 1), 1;
 }
 
+static int Lmu_report_msgref(lua_State *L) {
+    mu_Report *R = lmu_checkreport(L, 1)->R;
+    size_t     start = (size_t)luaL_checkinteger(L, 2);
+    size_t     end = (size_t)luaL_checkinteger(L, 3);
+    mu_Id      label_id = (mu_Id)luaL_checkinteger(L, 4);
+    lmu_checkerror(L, mu_msgref(R, start - 1, end, label_id - 1));
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_report_context(lua_State *L) {
+    mu_Report *R = lmu_checkreport(L, 1)->R;
+    size_t     start = (size_t)luaL_checkinteger(L, 2);
+    size_t     end = (size_t)luaL_optinteger(L, 3, start - 1);
+    mu_Id      src_id = (mu_Id)luaL_optinteger(L, 4, 1);
+    lmu_checkerror(L, mu_context(R, start - 1, end, src_id - 1));
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_report_location(lua_State *L) {
+    mu_Report *R = lmu_checkreport(L, 1)->R;
+    size_t     pos = (size_t)luaL_checkinteger(L, 2);
+    mu_Id      src_id = (mu_Id)luaL_optinteger(L, 3, 1);
+    lmu_checkerror(L, mu_location(R, pos - 1, src_id - 1));
+    return lua_settop(L, 1), 1;
+}
+
 static int Lmu_report_help(lua_State *L) {
     lmu_Report *lr = lmu_checkreport(L, 1);
     size_t      len;
@@ -496,6 +686,38 @@ static int Lmu_report_note(lua_State *L) {
     return lua_settop(L, 1), 1;
 }
 
+static int Lmu_report_suggest(lua_State *L) {
+    lmu_Report *lr = lmu_checkreport(L, 1);
+    size_t      start = (size_t)luaL_checkinteger(L, 2);
+    size_t      end = (size_t)luaL_checkinteger(L, 3);
+    size_t      len, msglen = 0;
+    const char *repl = luaL_checklstring(L, 4, &len);
+    const char *msg = luaL_optlstring(L, 5, NULL, &msglen);
+    mu_Id       src_id = (mu_Id)luaL_optinteger(L, 6, 1);
+    lmu_checkerror(L, mu_suggest(lr->R, start - 1, end, src_id - 1,
+                                 mu_lslice(repl, len), mu_lslice(msg, msglen)));
+    lua_getuservalue(L, 1);
+    lua_pushvalue(L, 4);
+    luaL_ref(L, -2); /* store the replacement string */
+    if (msg != NULL) lua_pushvalue(L, 5), luaL_ref(L, -2);
+    return lua_settop(L, 1), 1;
+}
+
+static int Lmu_report_suggestpart(lua_State *L) {
+    lmu_Report *lr = lmu_checkreport(L, 1);
+    size_t      start = (size_t)luaL_checkinteger(L, 2);
+    size_t      end = (size_t)luaL_checkinteger(L, 3);
+    size_t      len;
+    const char *repl = luaL_checklstring(L, 4, &len);
+    mu_Id       src_id = (mu_Id)luaL_optinteger(L, 5, 1);
+    lmu_checkerror(L, mu_suggestpart(lr->R, start - 1, end, src_id - 1,
+                                     mu_lslice(repl, len)));
+    lua_getuservalue(L, 1);
+    lua_pushvalue(L, 4);
+    luaL_ref(L, -2); /* store the replacement string */
+    return lua_settop(L, 1), 1;
+}
+
 static int Lmu_report_source(lua_State *L) {
     lmu_Report *lr = lmu_checkreport(L, 1);
     mu_Source  *src;
@@ -594,13 +816,19 @@ static void lmu_openreport(lua_State *L) {
         ENTRY(label),
         ENTRY(message),
         ENTRY(color),
+        ENTRY(colorkind),
         ENTRY(primary),
         ENTRY(order),
         ENTRY(priority),
+        ENTRY(msgref),
+        ENTRY(context),
+        ENTRY(location),
         ENTRY(source),
         ENTRY(file),
         ENTRY(render),
         ENTRY(help),
+        ENTRY(suggest),
+        ENTRY(suggestpart),
         ENTRY(note),
 #undef ENTRY
         {NULL, NULL},
@@ -689,6 +917,20 @@ static int Lmu_cache_file(lua_State *L) {
     return lua_settop(L, 1), 1;
 }
 
+static int Lmu_cache_newline(lua_State *L) {
+    mu_Cache  **cache = (mu_Cache **)luaL_checkudata(L, 1, LMU_CACHE_TYPE);
+    const char *opts[] = {"auto", "lf", "crlf", "cr", "mixed", NULL};
+    mu_Source  *src = mu_getsource(*cache, (unsigned)luaL_checkinteger(L, 2) - 1);
+    luaL_argcheck(L, src != NULL, 2, "source id out of range");
+    if (!lua_isnoneornil(L, 3)) {
+        mu_sourcenewline(src, luaL_checkoption(L, 3, NULL, opts));
+        return lua_settop(L, 1), 1;
+    }
+    if (!src->inited && src->init) lmu_checkerror(L, src->init(src));
+    src->inited = 1;
+    return lua_pushstring(L, opts[mu_newlinekind(src)]), 1;
+}
+
 static int Lmu_cache_render(lua_State *L) {
     mu_Cache  **cache = (mu_Cache **)luaL_checkudata(L, 1, LMU_CACHE_TYPE);
     lmu_Report *lr = lmu_checkreport(L, 2);
@@ -699,6 +941,7 @@ static int Lmu_cache_render(lua_State *L) {
     lua_settop(L, 3);
     lua_pushvalue(L, 1), lua_remove(L, 1);
     lua_getuservalue(L, 1);
+    mu_relayout(lr->R); /* the last cache may have been collected */
     if (ty == LUA_TNONE) {
         luaL_Buffer B;
         luaL_buffinit(L, &B);
@@ -721,6 +964,7 @@ static void lmu_opencache(lua_State *L) {
         ENTRY(delete),
         ENTRY(source),
         ENTRY(file),
+        ENTRY(newline),
         ENTRY(render),
 #undef ENTRY
         {NULL, NULL},
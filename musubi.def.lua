@@ -31,6 +31,14 @@
 --- | "unimportant"     # Secondary/context text
 --- | "note"            # Note/help message footer
 --- | "label"           # Label text and arrows
+--- | "emphasis"        # `**emphasized**` message text (with markup enabled)
+--- | "inline_code"     # `code` in title, notes and help (with inline_code enabled)
+--- | "context"         # Unlabeled text inside a context span (dimmed)
+--- | "info"            # Info level indicator
+--- | "hint"            # Hint level indicator
+--- | "advice"          # Advice level indicator
+--- | "expected"        # Label drawn with the "expected" color kind
+--- | "found"           # Label drawn with the "found" color kind
 
 -------------------------------------------------------------------------------
 -- ColorGenerator
@@ -91,8 +99,25 @@ local ColorGenerator = {}
 --- @field tab_width fun(self: Config, width: integer): Config  # Tab expansion width in spaces (default: 4)
 --- @field limit_width fun(self: Config, width?: integer): Config  # Max line width, 0=unlimited (default: 0)
 --- @field ambi_width fun(self: Config, width: integer): Config  # Ambiguous character width: 1 or 2 (default: 1)
+--- @field markup fun(self: Config, enable: boolean): Config  # Draw `**text**` in messages emphasized (default: false)
+--- @field inline_code fun(self: Config, enable: boolean): Config  # Style `code` in title, notes and help (default: false)
+--- @field normalize fun(self: Config, enable: boolean): Config  # Count columns as on NFC-normalized text (default: false)
+--- @field bidi_isolate fun(self: Config, enable: boolean): Config  # Wrap source lines with RTL text in FSI/PDI (default: false)
+--- @field auto_location fun(self: Config, enable: boolean): Config  # Show line:col of the primary label in headers (default: true)
+--- @field dim_unlabeled fun(self: Config, enable: boolean): Config  # Draw unlabeled source text with the unimportant color (default: true)
+--- @field caret_only fun(self: Config, enable: boolean): Config  # Draw labels spanning one character as a single caret with the arrow attached (default: false)
+--- @field one_based_columns fun(self: Config, enable: boolean): Config  # Count the column in the header location from 1 instead of 0 (default: true)
+--- @field auto_priority fun(self: Config, enable: boolean): Config  # Let primary labels win over other labels of the same priority (default: false)
+--- @field max_depth fun(self: Config, depth?: integer): Config  # Max margin columns of multi-line labels, 0=unlimited (default: 0)
+--- @field gutter_bar fun(self: Config, enabled?: boolean): Config  # Draw a bar in the level color left of the lines (default: false)
+--- @field collapse_notes fun(self: Config, lines?: integer): Config  # Max lines of each help or note, 0=all (default: 0)
+--- @field line_no_sep fun(self: Config, sep?: string): Config  # Separator between digit groups of line numbers (default: "")
+--- @field tab_stops fun(self: Config, stops?: integer[]): Config  # Ascending tab stop columns, then uniform `tab_width` (default: none)
 --- @field label_attach fun(self: Config, attach: "middle"|"start"|"end"): Config  # Label attachment point (default: "middle")
 --- @field index_type fun(self: Config, index_type: "byte"|"char"): Config  # Position indexing type (default: "char")
+--- @field overlap fun(self: Config, overlap: "merge"|"stack"|"hide"): Config  # How overlapping labels on one line are drawn (default: "merge")
+--- @field footer fun(self: Config, footer: "border"|"omit"|"blank"|"labeled"): Config  # How the closing border of reports is drawn (default: "border")
+--- @field source_order fun(self: Config, order: "cache"|"labels"|"name"|"primary"): Config  # Order of the sources in a report (default: "cache")
 --- @field color fun(self: Config, enable: boolean): Config  # Enable ANSI color codes (default: true)
 --- @field char_set fun(self: Config, char_set: "ascii"|"unicode"): Config  # Glyph set for drawing (default: "unicode")
 local Config = {}
@@ -132,8 +157,9 @@ local Config = {}
 --- ```
 ---
 --- # Label Attachment
---- After calling :label(), subsequent calls to :message(), :color(), :order(),
---- :priority() apply to that label until the next :label() call or :render().
+--- After calling :label(), subsequent calls to :message(), :color(),
+--- :colorkind(), :order(), :priority(), :msgref() apply to that label until
+--- the next :label() call or :render().
 ---
 --- # Source Management
 --- Report maintains an internal Cache. Sources added with :source()/:file()
@@ -152,11 +178,17 @@ local Config = {}
 --- @field label fun(self: Report, start: integer, end?: integer, src_id?: integer): Report  # Add label span (subsequent calls modify this label)
 --- @field message fun(self: Report, message: string, width?: integer): Report  # Set message for current label
 --- @field color fun(self: Report, color: Color|string|function): Report  # Set color for current label (Color object, code string, or function)
+--- @field colorkind fun(self: Report, kind: "label"|"expected"|"found"): Report  # Draw current label with another color kind
 --- @field primary fun(self: Report): Report  # Mark current label as primary
 --- @field order fun(self: Report, order: integer): Report  # Set display order for current label
 --- @field priority fun(self: Report, priority: integer): Report  # Set priority for current label
+--- @field msgref fun(self: Report, start: integer, end: integer, label: integer): Report  # Color bytes start..end of the current message like label #label
+--- @field context fun(self: Report, start: integer, end?: integer, src_id?: integer): Report  # Show the whole region as dimmed context around the labels
+--- @field location fun(self: Report, pos: integer, src_id?: integer): Report  # Show line:col of position pos in the header instead of the primary label's
 --- @field note fun(self: Report, note: string): Report  # Add footer note
 --- @field help fun(self: Report, help: string): Report  # Add help text
+--- @field suggest fun(self: Report, start: integer, end: integer, text: string, msg?: string, src_id?: integer): Report  # Suggest replacing start..end with text, shown with the edited line
+--- @field suggestpart fun(self: Report, start: integer, end: integer, text: string, src_id?: integer): Report  # Add another edit to the last suggestion
 --- @field source fun(self: Report, code: string|file*, name?: string, offset?: integer): Report  # Add in-memory or file source to internal Cache
 --- @field file fun(self: Report, name: string, offset?: integer): Report  # Add file source to internal Cache
 --- @field render fun(self: Report, writer?: function): string  # Render diagnostic (returns string if no writer, else calls writer(chunk) repeatedly)
@@ -166,7 +198,10 @@ local Report = {}
 --- @alias LevelKind
 --- | "error"   # Error level
 --- | "warning" # Warning level
---- | "advice"  # Advice/info level
+--- | "info"    # Info level
+--- | "note"    # Note level
+--- | "hint"    # Hint level
+--- | "advice"  # Advice level
 --- | string    # Any other custom level string
 
 -------------------------------------------------------------------------------
@@ -219,6 +254,7 @@ local Report = {}
 --- @field delete fun(self: Cache)  # Manually free resources (normally GC'd)
 --- @field source fun(self: Cache, code: string|file*, name?: string, offset?: integer): Cache  # Add in-memory source or file handle
 --- @field file fun(self: Cache, name: string, offset?: integer): Cache  # Add file source by path
+--- @field newline fun(self: Cache, src_id: integer, kind?: "auto"|"lf"|"crlf"|"cr"): Cache|"auto"|"lf"|"crlf"|"cr"|"mixed"  # Declare the newline convention of a source (before rendering), or get the declared/detected one
 --- @field render fun(self: Cache, report: Report, writer?: function): string  # Render diagnostic with this Cache's sources
 --- @operator len: integer  # Returns number of sources in Cache
 local Cache = {}
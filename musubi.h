@@ -51,28 +51,80 @@
 #define MU_S(x) #x
 #define MU_VERSION \
     MU_S(MU_VERSION_MAJOR) "." MU_S(MU_VERSION_MINOR) "." MU_S(MU_VERSION_PATCH)
+#define MU_VERSION_NUM \
+    (MU_VERSION_MAJOR * 10000 + MU_VERSION_MINOR * 100 + MU_VERSION_PATCH)
 
 #define MU_CHUNK_MAX_SIZE  63
-#define MU_COLOR_CODE_SIZE 32
+#define MU_COLOR_CODE_SIZE 64
+#define MU_TAB_STOPS_SIZE  16
+#define MU_LINE_NO_SIZE    64
+#define MU_MAX_LABELS      (1u << 30)
+
+#define MU_FEATURE_STDIO 0x01 /* mu_addfile() is available */
+#define MU_FEATURE_DEBUG 0x02 /* error tracing to stderr is compiled in */
+
+#define MU_STRUCT_CONFIG    0 /* public structs, for mu_structsize() */
+#define MU_STRUCT_ALLOCATOR 1
+#define MU_STRUCT_CACHE     2
+#define MU_STRUCT_SOURCE    3
+#define MU_STRUCT_LINE      4
+#define MU_STRUCT_COLORGEN  5
 
 #define MU_OK       (0)  /* No error */
 #define MU_ERRPARAM (-1) /* invalid parameter */
 #define MU_ERRSRC   (-2) /* source ID out of range */
 #define MU_ERRLINE  (-3) /* fetch line error */
 #define MU_ERRFILE  (-4) /* errors in file source */
+#define MU_ERRMEM   (-5) /* memory allocation failed during rendering */
 
 MU_NS_BEGIN
 
-typedef enum mu_Level { MU_ERROR, MU_WARNING, MU_CUSTOM_LEVEL } mu_Level;
+typedef enum mu_Level {
+    MU_ERROR,
+    MU_WARNING,
+    MU_CUSTOM_LEVEL,
+    MU_INFO,
+    MU_NOTE,
+    MU_HINT,
+    MU_ADVICE
+} mu_Level;
 
 typedef enum mu_IndexType { MU_INDEX_BYTE, MU_INDEX_CHAR } mu_IndexType;
 
+typedef enum mu_Newline {
+    MU_NEWLINE_AUTO, /* "\n", "\r\n" and "\r" all end a line */
+    MU_NEWLINE_LF,   /* only "\n" ends a line */
+    MU_NEWLINE_CRLF, /* like MU_NEWLINE_AUTO, but reported as "\r\n" */
+    MU_NEWLINE_CR,   /* only "\r" ends a line */
+    MU_NEWLINE_MIXED /* detected more than one convention */
+} mu_Newline;
+
 typedef enum mu_LabelAttach {
     MU_ATTACH_MIDDLE,
     MU_ATTACH_START,
     MU_ATTACH_END
 } mu_LabelAttach;
 
+typedef enum mu_Overlap {
+    MU_OVERLAP_MERGE, /* one underline row, drawn by the winning label */
+    MU_OVERLAP_STACK, /* overlapping underlines on rows of their own */
+    MU_OVERLAP_HIDE   /* drop labels overlapped by a winning label */
+} mu_Overlap;
+
+typedef enum mu_Footer {
+    MU_FOOTER_BORDER, /* close the margin with a corner */
+    MU_FOOTER_OMIT,   /* no line after the report */
+    MU_FOOTER_BLANK,  /* an empty line after the report */
+    MU_FOOTER_LABELED /* a corner followed by `[ end CODE ]` */
+} mu_Footer;
+
+typedef enum mu_SourceOrder {
+    MU_SOURCE_ORDER_CACHE,  /* the order the sources were added to the cache */
+    MU_SOURCE_ORDER_LABELS, /* the order the sources are first labeled in */
+    MU_SOURCE_ORDER_NAME,   /* sorted by source name */
+    MU_SOURCE_ORDER_PRIMARY /* the source of the primary label first */
+} mu_SourceOrder;
+
 typedef enum mu_ColorKind {
     MU_COLOR_RESET,
     MU_COLOR_ERROR,
@@ -82,9 +134,30 @@ typedef enum mu_ColorKind {
     MU_COLOR_SKIPPED_MARGIN,
     MU_COLOR_UNIMPORTANT,
     MU_COLOR_NOTE,
-    MU_COLOR_LABEL
+    MU_COLOR_LABEL,
+    MU_COLOR_EMPHASIS,
+    MU_COLOR_INLINE_CODE,
+    MU_COLOR_CONTEXT,
+    MU_COLOR_INFO,
+    MU_COLOR_HINT,
+    MU_COLOR_ADVICE,
+    MU_COLOR_EXPECTED,
+    MU_COLOR_FOUND
 } mu_ColorKind;
 
+typedef enum mu_LineKind {
+    MU_LINE_HEADER,
+    MU_LINE_REFERENCE,
+    MU_LINE_EMPTY,
+    MU_LINE_SOURCE,
+    MU_LINE_UNDERLINE,
+    MU_LINE_ARROW,
+    MU_LINE_ELLIPSIS,
+    MU_LINE_HELP,
+    MU_LINE_NOTE,
+    MU_LINE_FOOTER
+} mu_LineKind;
+
 typedef enum mu_Draw {
     MU_DRAW_SPACE,
     MU_DRAW_NEWLINE,
@@ -141,30 +214,61 @@ MU_STATIC mu_Slice mu_lslice(const char *p, size_t len)
 { mu_Slice s; s.p = p, s.e = p + len; return s; }
 /* clang-format on */
 
+/* library information */
+
+MU_API unsigned mu_version(void);  /* MU_VERSION_NUM of the compiled library */
+MU_API unsigned mu_features(void); /* MU_FEATURE_* flags */
+MU_API size_t   mu_structsize(unsigned which); /* size of a MU_STRUCT_*, or 0 */
+
 /* report construction and configuration */
 
 MU_API mu_Report *mu_new(mu_Allocf *allocf, void *ud);
 MU_API void       mu_reset(mu_Report *R);
 MU_API void       mu_delete(mu_Report *R);
 
+/* config must outlive R, or be NULL to restore the default config */
 MU_API int mu_config(mu_Report *R, const mu_Config *config);
 MU_API int mu_label(mu_Report *R, size_t start, size_t end, mu_Id src_id);
 MU_API int mu_message(mu_Report *R, mu_Slice msg, int width);
 MU_API int mu_color(mu_Report *R, mu_Color *color, void *ud);
+MU_API int mu_colorkind(mu_Report *R, mu_ColorKind kind);
 MU_API int mu_primary(mu_Report *R);
 MU_API int mu_order(mu_Report *R, int order);
 MU_API int mu_priority(mu_Report *R, int priority);
+MU_API int mu_msgref(mu_Report *R, size_t start, size_t end, mu_Id label_id);
+MU_API int mu_setspan(mu_Report *R, mu_Id label_id, size_t start, size_t end);
+MU_API int mu_context(mu_Report *R, size_t start, size_t end, mu_Id src_id);
+MU_API int mu_location(mu_Report *R, size_t pos, mu_Id src_id);
 
 MU_API int mu_title(mu_Report *R, mu_Level l, mu_Slice custom, mu_Slice msg);
 MU_API int mu_code(mu_Report *R, mu_Slice code);
 MU_API int mu_help(mu_Report *R, mu_Slice help_msg);
 MU_API int mu_note(mu_Report *R, mu_Slice note_msg);
 
+/* msg.p may be NULL to show "replace with `replacement`" */
+MU_API int mu_suggest(mu_Report *R, size_t start, size_t end, mu_Id src_id,
+                      mu_Slice replacement, mu_Slice msg);
+/* add another edit to the last suggestion */
+MU_API int mu_suggestpart(mu_Report *R, size_t start, size_t end,
+                          mu_Id src_id, mu_Slice replacement);
+
 /* rendering */
 
 MU_API int mu_writer(mu_Report *R, mu_Writer *writer, void *ud);
 MU_API int mu_render(mu_Report *R, const mu_Cache *cache);
 
+/* mu_render keeps the label layout for the next render with the same cache;
+ * call mu_relayout if that cache was freed or its sources changed */
+MU_API void mu_relayout(mu_Report *R);
+
+MU_API mu_LineKind mu_linekind(const mu_Report *R); /* line being written */
+MU_API int mu_msglabel(const mu_Report *R); /* label of the message being
+                                               written, or -1 */
+
+/* source and 0-based line shown by the source, underline or arrow line being
+ * written; returns 0 for other lines */
+MU_API int mu_sourceline(const mu_Report *R, mu_Id *src_id, unsigned *line_no);
+
 /* custom configuration */
 
 typedef mu_Chunk mu_Charset[MU_DRAW_COUNT];
@@ -176,6 +280,9 @@ MU_API mu_Chunk mu_default_color(void *ud, mu_ColorKind kind);
 
 MU_API void mu_initconfig(mu_Config *config);
 
+/* format line_no for the margin, into buf (of size bytes) or elsewhere */
+typedef mu_Slice mu_LineNo(void *ud, unsigned line_no, char *buf, size_t size);
+
 struct mu_Config {
     int compact;            /* whether to use compact mode */
     int cross_gap;          /* show crossing gaps in cross arrows */
@@ -187,14 +294,34 @@ struct mu_Config {
     int tab_width;          /* number of spaces per tab */
     int limit_width;        /* maximum line width, or 0 for no limit */
     int ambiwidth;          /* how to treat ambiguous width characters */
+    int markup;             /* whether `**` marks emphasis in messages */
+    int inline_code;        /* style `code` in title, notes and help */
+    int normalize;          /* count columns as on NFC-normalized text */
+    int bidi_isolate;       /* wrap source lines with RTL text in FSI/PDI */
+    int auto_location;      /* show line:col of the primary label in headers */
+    int dim_unlabeled;      /* draw unlabeled source text as unimportant */
+    int caret_only;         /* draw one-char labels as a single caret */
+    int one_based_columns;  /* count header columns from 1 instead of 0 */
+    int auto_priority;      /* primary labels win ties of priority */
+    int max_depth;          /* max margin columns of multi-line labels, 0=all */
+    int gutter_bar;         /* draw a bar colored by the level left of lines */
+    int collapse_notes;     /* max lines of each help or note, 0 for all */
+    int tab_stops[MU_TAB_STOPS_SIZE]; /* ascending tab stops, 0-terminated */
 
     mu_LabelAttach label_attach; /* where to attach inline labels */
     mu_IndexType   index_type;   /* index type for label positions */
+    mu_Overlap     overlap;      /* how to draw overlapping inline labels */
+    mu_Footer      footer;       /* how to draw the closing border */
+    mu_SourceOrder source_order; /* order of the sources in a report */
 
     mu_Color *color;    /* a color function or NULL for no color */
     void     *color_ud; /* user data for the color function */
 
     const mu_Charset *char_set; /* character set to use */
+
+    mu_Slice   line_no_sep; /* separator of digit groups in line numbers */
+    mu_LineNo *line_no;     /* a line number formatter or NULL for decimal */
+    void      *line_no_ud;  /* user data for the line number formatter */
 };
 
 /* color generator */
@@ -220,6 +347,12 @@ MU_API unsigned mu_sourcecount(const mu_Cache *C);
 
 typedef struct mu_Source mu_Source;
 
+MU_API mu_Source *mu_getsource(const mu_Cache *C, unsigned src_id);
+
+/* replace source src_id of a cache of several sources with src, a source
+ * of no cache; returns the old source, to delete with mu_delcache */
+MU_API mu_Source *mu_setsource(mu_Cache *C, unsigned src_id, mu_Source *src);
+
 MU_API mu_Source *mu_addsource(mu_Cache **pC, size_t size, mu_Slice name);
 MU_API mu_Source *mu_addmemory(mu_Cache **pC, mu_Slice data, mu_Slice name);
 
@@ -228,6 +361,9 @@ MU_API mu_Source *mu_addfile(mu_Cache **pC, FILE *fp, mu_Slice name);
 #endif /* !MU_NO_STDIO */
 
 #define mu_sourceoffset(src, offset) ((src)->line_no_offset = (offset))
+#define mu_sourcenewline(src, kind)  ((src)->newline = (kind))
+
+MU_API mu_Newline mu_newlinekind(const mu_Source *src);
 
 typedef struct mu_Line mu_Line;
 typedef const mu_Line *mu_CL;
@@ -243,6 +379,7 @@ MU_API unsigned mu_lineforbytes(mu_Source *src, size_t byte_pos, mu_CL *out);
 typedef struct mu_Allocator {
     void      *ud;     /* userdata for allocf */
     mu_Allocf *allocf; /* custom allocation function */
+    void      *onerr;  /* jmp_buf to escape on failure (NULL to abort) */
 } mu_Allocator;
 
 struct mu_Cache {
@@ -259,6 +396,9 @@ struct mu_Source {
 
     int line_no_offset; /* line number offset for this source */
     int inited;         /* whether init is called */
+    int newline;        /* newline convention (mu_Newline) */
+    int newlines;       /* bit set of newline conventions seen */
+    int cr_pending;     /* whether the last update ended with a "\r" */
 
     int (*init)(mu_Source *src);
     void (*free)(mu_Source *src);
@@ -267,6 +407,10 @@ struct mu_Source {
     unsigned (*line_count)(mu_Source *src);
     unsigned (*line_for_chars)(mu_Source *src, size_t char_pos, mu_CL *out);
     unsigned (*line_for_bytes)(mu_Source *src, size_t byte_pos, mu_CL *out);
+    /* maps a label position in the original text to the displayed text
+     * (NULL if lines are displayed as they are) */
+    size_t (*map_pos)(mu_Source *src, size_t pos, mu_IndexType index_type,
+                      int end);
 };
 
 struct mu_Line {
@@ -286,6 +430,7 @@ MU_NS_END
 
 #include <assert.h>
 #include <limits.h>
+#include <setjmp.h>
 #include <stdarg.h>
 #include <stdlib.h>
 #include <string.h>
@@ -323,18 +468,31 @@ typedef const struct mu_LabelInfo *mu_CLI;
 typedef const struct mu_LineLabel *mu_CLL;
 
 typedef struct mu_Label {
-    void     *ud;        /* user data for the color */
-    mu_Color *color;     /* the color used for this label */
-    mu_Slice  message;   /* the message to display */
-    size_t    start_pos; /* start position in the source */
-    size_t    end_pos;   /* end position in the source */
-    mu_Id     src_id;    /* source id this label belongs to */
-    mu_Width  width;     /* display width of the message, must >= 0 */
-    int       primary;   /* whether this is a primary label */
-    int       order;     /* order in vertical sorting */
-    int       priority;  /* priority when merging overlapping labels */
+    void        *ud;        /* user data for the color */
+    mu_Color    *color;     /* the color used for this label */
+    mu_Slice     message;   /* the message to display */
+    size_t       start_pos; /* start position in the source */
+    size_t       end_pos;   /* end position in the source */
+    mu_Id        src_id;    /* source id this label belongs to */
+    mu_Width     width;     /* display width of the message, must >= 0 */
+    int          primary;   /* whether this is a primary label */
+    int          order;     /* order in vertical sorting */
+    int          priority;  /* priority when merging overlapping labels */
+    mu_Slice     ref;       /* part of the message referring to another label */
+    mu_Id        ref_label; /* index of the label referred by `ref` */
+    mu_ColorKind kind;      /* color kind drawn with, 0 for MU_COLOR_LABEL */
+    unsigned     src_rank;  /* position of the source in the render order */
 } mu_Label;
 
+typedef struct mu_Suggestion {
+    mu_Slice replacement; /* text replacing the span */
+    mu_Slice message;     /* help message, or NULL for the default one */
+    size_t   start_pos;   /* start position in the source */
+    size_t   end_pos;     /* end position in the source */
+    mu_Id    src_id;      /* source id the span belongs to */
+    int      part;        /* whether it is an edit of the previous one */
+} mu_Suggestion;
+
 typedef struct mu_LabelInfo {
     const mu_Label *label;      /* label associated with this info */
     int             multi;      /* whether this label spans multiple lines */
@@ -346,9 +504,16 @@ typedef struct mu_Group {
     mu_Source    *src;          /* source of this group */
     mu_LabelInfo *labels;       /* labels in this group */
     mu_LabelInfo *multi_labels; /* multi-line labels in this group */
+    mu_LabelInfo *overflow;     /* multi-line labels beyond `max_depth` */
     mu_LabelInfo  primary;      /* primary label of this group */
+    int           loc_priority; /* priority of the label giving the location */
     unsigned      first_line;   /* first line of this group */
     unsigned      last_line;    /* last line of this group */
+    int           has_ctx;      /* whether a context span is in this group */
+    unsigned      ctx_first;    /* first line of the context span */
+    unsigned      ctx_last;     /* last line of the context span */
+    int           has_loc;      /* whether `loc_char` is set by mu_location */
+    size_t        loc_char;     /* character position shown in headers */
 } mu_Group;
 
 typedef struct mu_LineLabel {
@@ -356,7 +521,8 @@ typedef struct mu_LineLabel {
     mu_Col col;  /* column position in this line */
 
     /* draw_msg is 0 only if the label is multi and this line is the start */
-    int draw_msg; /* whether to draw the message in this line */
+    int      draw_msg; /* whether to draw the message in this line */
+    unsigned ul_row;   /* underline row of the label in MU_OVERLAP_STACK */
 } mu_LineLabel;
 
 typedef struct mu_Cluster {
@@ -369,6 +535,7 @@ typedef struct mu_Cluster {
     mu_Col   start_col;     /* start column of this cluster */
     mu_Col   end_col;       /* end column of this cluster */
     mu_Width max_msg_width; /* maximum message width in this line */
+    unsigned ul_rows;       /* number of underline rows in MU_OVERLAP_STACK */
 } mu_Cluster;
 
 typedef struct mu_Data {
@@ -390,13 +557,27 @@ struct mu_Report {
     mu_Cluster     *clusters;        /* current label clusters for rendering */
     mu_LineLabel   *ll_cache;        /* line label cache used to fill cluster */
     mu_Width       *width_cache;     /* current line width cache */
+    mu_Width       *fix_marks;       /* underline ranges of suggestion line */
     mu_Width        line_no_width;   /* maximum width of line number */
     mu_Width        ellipsis_width;  /* display width of ellipsis */
 
+    /* layout kept between renders, valid while `layout_cache` is set */
+    const mu_Cache *layout_cache; /* cache the groups were made from */
+    unsigned        layout_srcs;  /* source count of `layout_cache` */
+    mu_IndexType    layout_index; /* index type the groups were made with */
+    int             layout_ctx;   /* context lines the groups were made with */
+    int             layout_depth; /* max depth the groups were made with */
+    mu_SourceOrder  layout_order; /* source order the groups were made with */
+
     const mu_Group   *cur_group;   /* current group being rendered */
     const mu_Cluster *cur_cluster; /* current cluster being rendered */
     mu_CL             cur_line;    /* current line being rendered */
+    unsigned          cur_line_no; /* line number of `cur_line` */
     mu_Source        *cur_src;     /* current source in callback */
+    mu_LineKind       cur_kind;    /* kind of the line being written */
+    int               cur_msg;     /* label of the message being written */
+    unsigned          cur_ul_row;  /* stacked underline row being written */
+    int               in_context;  /* whether current line is in a context */
 
     /* report details */
     mu_Level  level;        /* predefined report level */
@@ -404,14 +585,19 @@ struct mu_Report {
     mu_Slice  custom_level; /* custom level shown in header */
     mu_Slice  title;        /* main title shown in header */
     mu_Label *labels;       /* labels involved in the report */
+    mu_Label *contexts;     /* enclosing regions shown as dimmed context */
     mu_Slice *helps;        /* help messages shown in footer */
     mu_Slice *notes;        /* note messages shown in footer */
+    mu_Suggestion *suggestions; /* fix suggestions shown in footer */
+    int            has_loc;     /* whether the location is set */
+    mu_Id          loc_src;     /* source of the location in headers */
+    size_t         loc_pos;     /* position of the location in headers */
 };
 
 /* array */
 
 #define MU_MIN_CAPACITY 8
-#define MU_MAX_CAPACITY (1u << 30)
+#define MU_MAX_CAPACITY MU_MAX_LABELS
 
 #define muA_rawH(A)    (assert(A), ((mu_ArrayHeader *)A - 1))
 #define muA_size(A)    ((A) ? muA_rawH(A)->size : 0)
@@ -438,17 +624,22 @@ static void muA_delete_(mu_Allocator *A, void *pa, size_t esize) {
     A->allocf(A->ud, h, 0, sizeof(mu_ArrayHeader) + h->capacity * esize);
 }
 
+static void muA_nomem(mu_Allocator *A) {
+    if (A->onerr) longjmp(*(jmp_buf *)A->onerr, 1);
+    abort();
+}
+
 static void muA_reserve_(mu_Allocator *A, void **pa, size_t esize, unsigned n) {
     mu_ArrayHeader *nh, *h = *pa ? muA_rawH((void **)*pa) : NULL;
     unsigned        desired = n + (h ? h->size : 0);
-    if (desired > MU_MAX_CAPACITY) abort();
+    if (desired > MU_MAX_CAPACITY) muA_nomem(A);
     if (h == NULL || desired > h->capacity) {
         size_t old_size = h ? sizeof(mu_ArrayHeader) + h->capacity * esize : 0;
         unsigned newcapa = MU_MIN_CAPACITY;
         while ((newcapa += newcapa >> 1) < desired) {}
         nh = (mu_ArrayHeader *)A->allocf(
             A->ud, h, sizeof(mu_ArrayHeader) + newcapa * esize, old_size);
-        if (nh == NULL) abort();
+        if (nh == NULL) muA_nomem(A);
         if (h == NULL) nh->size = 0;
         nh->capacity = newcapa;
         *pa = (void *)(nh + 1);
@@ -471,14 +662,26 @@ static mu_Slice muD_snprintf(char *buf, size_t bufsize, const char *fmt, ...) {
     return mu_lslice(buf, n > 0 ? mu_min((size_t)n, bufsize - 1) : 0);
 }
 
+/* length of the character at s.p; each byte of an invalid sequence is a
+ * character of its own, displayed as U+FFFD */
+static size_t muD_charlen(mu_Slice s) {
+    static const utfint least[] = {0, 0, 0x80, 0x800, 0x10000};
+    utfint c = *s.p & 0xFF;
+    size_t n = 1, i;
+    if ((c & 0xE0) == 0xC0) n = 2;
+    else if ((c & 0xF0) == 0xE0) n = 3;
+    else if ((c & 0xF8) == 0xF0) n = 4;
+    if (n == 1 || n > muD_bytelen(s)) return 1;
+    for (c &= 0xFF >> (n + 1), i = 1; i < n; ++i) {
+        if ((s.p[i] & 0xC0) != 0x80) return 1;
+        c = (c << 6) | (s.p[i] & 0x3F);
+    }
+    if (c < least[n] || c > 0x10FFFF || (c >= 0xD800 && c <= 0xDFFF)) return 1;
+    return n;
+}
+
 static void muD_advance(mu_Slice *s) {
-    utfint c;
-    if (s->p >= s->e) return;
-    if ((c = *s->p & 0xFF) < 0x80) s->p += 1;
-    else if ((c & 0xE0) == 0xC0 && s->p + 1 < s->e) s->p += 2;
-    else if ((c & 0xF0) == 0xE0 && s->p + 2 < s->e) s->p += 3;
-    else if ((c & 0xF8) == 0xF0 && s->p + 3 < s->e) s->p += 4;
-    else s->p += 1; /* invalid byte, skip */
+    if (s->p < s->e) s->p += muD_charlen(*s);
 }
 
 static size_t muD_checkend(mu_Slice s) {
@@ -495,18 +698,13 @@ static size_t muD_checkend(mu_Slice s) {
 
 static utfint muD_decode(mu_Slice *s) {
     utfint ch;
+    size_t n, i;
     if (s->p >= s->e) return 0;
-    ch = *s->p++ & 0xFF;
-    if (ch < 0x80) return ch;
-    if ((ch & 0xE0) == 0xC0 && s->p < s->e)
-        return s->p += 1, ((ch & 0x1F) << 6) | (s->p[-1] & 0x3F);
-    if ((ch & 0xF0) == 0xE0 && s->p + 1 < s->e)
-        return s->p += 2, ((ch & 0x0F) << 12) | ((s->p[-2] & 0x3F) << 6)
-                              | (s->p[-1] & 0x3F);
-    if ((ch & 0xF8) == 0xF0 && s->p + 2 < s->e)
-        return s->p += 3, ((ch & 0x07) << 18) | ((s->p[-3] & 0x3F) << 12)
-                              | ((s->p[-2] & 0x3F) << 6) | (s->p[-1] & 0x3F);
-    return ch; /* invalid, fallback */
+    n = muD_charlen(*s), ch = *s->p & 0xFF;
+    if (n == 1) return s->p += 1, (ch < 0x80 ? ch : 0xFFFD);
+    for (ch &= 0xFF >> (n + 1), i = 1; i < n; ++i)
+        ch = (ch << 6) | (s->p[i] & 0x3F);
+    return s->p += n, ch;
 }
 
 static utfint muD_rdecode(mu_Slice *s) {
@@ -542,6 +740,36 @@ static mu_Width muD_strwidth(mu_Slice s, mu_Width ambi) {
     return w;
 }
 
+static int muD_hasrtl(mu_Slice s) {
+    while (s.p < s.e) {
+        utfint ch = muD_decode(&s);
+        if ((ch >= 0x590 && ch <= 0x8FF) || (ch >= 0xFB1D && ch <= 0xFDFF)
+            || (ch >= 0xFE70 && ch <= 0xFEFF) || (ch >= 0x10800 && ch <= 0x10FFF)
+            || (ch >= 0x1E800 && ch <= 0x1EFFF) || ch == 0x200F
+            || (ch >= 0x202A && ch <= 0x202E) || (ch >= 0x2066 && ch <= 0x2068))
+            return 1;
+    }
+    return 0;
+}
+
+static const char *muD_markup(mu_Slice s) {
+    const char *p = s.p;
+    if (p == NULL) return NULL;
+    while ((p = (const char *)memchr(p, '*', (size_t)(s.e - p))) != NULL) {
+        if (p + 1 < s.e && p[1] == '*') return p;
+        p += 1;
+    }
+    return NULL;
+}
+
+static mu_Width muD_markupwidth(mu_Slice s, mu_Width ambi) {
+    mu_Width    w = 0;
+    const char *p;
+    while ((p = muD_markup(s)) != NULL)
+        w += muD_strwidth(mu_lslice(s.p, p - s.p), ambi), s.p = p + 2;
+    return w + muD_strwidth(s, ambi);
+}
+
 static mu_Width muD_keep_suffix(mu_Slice *s, mu_Width width, mu_Width ambi) {
     mu_Width    cw;
     const char *end = s->e, *prev = s->e;
@@ -590,20 +818,38 @@ static int muW_chunk(mu_Report *R, mu_Chunk chunk)
 { return muW_write(R, mu_lslice((chunk) + 1, (size_t)((chunk)[0] & 0xFF))); }
 /* clang-format on */
 
+/* write source text, with U+FFFD in place of each invalid byte */
+static int muW_text(mu_Report *R, mu_Slice s) {
+    const char *p = s.p;
+    while (s.p < s.e) {
+        const char *c = s.p;
+        muD_advance(&s);
+        if (s.p - c > 1 || (*c & 0x80) == 0) continue;
+        if (p < c) muX(muW_write(R, mu_lslice(p, (size_t)(c - p))));
+        muX(muW_write(R, mu_literal("\xEF\xBF\xBD")));
+        p = s.p;
+    }
+    return p < s.e ? muW_write(R, mu_lslice(p, (size_t)(s.e - p))) : MU_OK;
+}
+
+/* write text with each `oldc` replaced by `newc`, and U+FFFD in place of
+ * each invalid byte */
 static int muW_replace(mu_Report *R, mu_Slice s, char oldc, char newc) {
     while (s.p < s.e) {
         const char *p = (const char *)memchr(s.p, oldc, muD_bytelen(s));
         if (p == NULL) break;
-        muX(muW_write(R, mu_lslice(s.p, (size_t)(p - s.p))));
+        muX(muW_text(R, mu_lslice(s.p, (size_t)(p - s.p))));
         muX(muW_write(R, mu_lslice(&newc, 1)));
         s.p = p + 1;
     }
-    return s.p < s.e ? muW_write(R, s) : MU_OK;
+    return s.p < s.e ? muW_text(R, s) : MU_OK;
 }
 
 static int muW_color(mu_Report *R, mu_ColorKind k) {
     mu_Color *color = R->config->color;
     void     *ud = R->config->color_ud;
+    if (k == MU_COLOR_LABEL && R->cur_color_label && R->cur_color_label->kind)
+        k = R->cur_color_label->kind;
     if (R->cur_color_label && R->cur_color_label->color)
         color = R->cur_color_label->color, ud = R->cur_color_label->ud;
     if (color) {
@@ -621,6 +867,29 @@ static int muW_color(mu_Report *R, mu_ColorKind k) {
     return (R->cur_color_kind = k), MU_OK;
 }
 
+static int muW_markup(mu_Report *R, mu_Slice s, mu_ColorKind k, int code) {
+    const mu_Config *c = R->config;
+    const char      *p;
+    int              em = 0, in_code = 0;
+    if (!(code = code && c->inline_code) && !c->markup) return muW_write(R, s);
+    for (p = s.p; p < s.e; ++p) {
+        if (code && *p == '`') {
+            const char *e = p + in_code; /* closing backtick is part of code */
+            if (s.p < e) muX(muW_write(R, mu_lslice(s.p, e - s.p)));
+            in_code = !in_code, s.p = e;
+        } else if (c->markup && !in_code && p + 1 < s.e && p[0] == '*'
+                   && p[1] == '*') {
+            if (s.p < p) muX(muW_write(R, mu_lslice(s.p, p - s.p)));
+            em = !em, s.p = ++p + 1;
+        } else continue;
+        muX(muW_color(R, in_code ? MU_COLOR_INLINE_CODE
+                         : em    ? MU_COLOR_EMPHASIS
+                                 : k));
+    }
+    muX(muW_write(R, s));
+    return em || in_code ? muW_color(R, k) : MU_OK;
+}
+
 static int muW_use_color(mu_Report *R, const mu_Label *label, mu_ColorKind k) {
     if (R->cur_color_kind != MU_COLOR_RESET && R->cur_color_label != label)
         muX(muW_color(R, MU_COLOR_RESET));
@@ -628,6 +897,18 @@ static int muW_use_color(mu_Report *R, const mu_Label *label, mu_ColorKind k) {
     return muW_color(R, k);
 }
 
+static int muW_message(mu_Report *R, const mu_Label *label) {
+    mu_Slice msg = label->message, head, tail;
+    if (!label->ref.p) return muW_markup(R, msg, MU_COLOR_RESET, 0);
+    head = mu_lslice(msg.p, (size_t)(label->ref.p - msg.p));
+    tail = mu_lslice(label->ref.e, (size_t)(msg.e - label->ref.e));
+    muX(muW_markup(R, head, MU_COLOR_RESET, 0));
+    muX(muW_use_color(R, &R->labels[label->ref_label], MU_COLOR_LABEL));
+    muX(muW_write(R, label->ref));
+    muX(muW_use_color(R, NULL, MU_COLOR_RESET));
+    return muW_markup(R, tail, MU_COLOR_RESET, 0);
+}
+
 static int muW_draw(mu_Report *R, mu_Draw cs, int count) {
     const mu_Chunk chunk = (*R->config->char_set)[cs];
     if (chunk[0] == 1) {
@@ -662,6 +943,9 @@ static int muM_contains(size_t pos, mu_CL line)
 
 static mu_Col muM_col(size_t pos, mu_CLL ll, mu_CL line)
 { return ll->info->multi ? ll->col : (mu_Col)(pos - line->offset); }
+
+static mu_Width muM_msgwidth(const mu_Config *c, mu_Slice msg)
+{ return (c->markup ? muD_markupwidth : muD_strwidth)(msg, c->ambiwidth); }
 /* clang-format on */
 
 static mu_Width muM_marginwidth(mu_Report *R) {
@@ -673,6 +957,10 @@ static void muM_level(mu_Level l, mu_ColorKind *k, mu_Slice *s) {
     switch (l) {
     case MU_ERROR:   *k = MU_COLOR_ERROR, *s = mu_literal("Error"); break;
     case MU_WARNING: *k = MU_COLOR_WARNING, *s = mu_literal("Warning"); break;
+    case MU_INFO:    *k = MU_COLOR_INFO, *s = mu_literal("Info"); break;
+    case MU_NOTE:    *k = MU_COLOR_NOTE, *s = mu_literal("Note"); break;
+    case MU_HINT:    *k = MU_COLOR_HINT, *s = mu_literal("Hint"); break;
+    case MU_ADVICE:  *k = MU_COLOR_ADVICE, *s = mu_literal("Advice"); break;
     default:         *k = MU_COLOR_KIND; break;
     }
 }
@@ -685,18 +973,45 @@ static int muM_line_in_labels(mu_CL line, mu_CLI li) {
     return 0;
 }
 
-static void muM_calc_linenowidth(mu_Report *R) {
+static mu_Slice muM_lineno(const mu_Report *R, unsigned line_no, char *buf) {
+    const mu_Config *c = R->config;
+    size_t           sep = muD_bytelen(c->line_no_sep), i, len = 0;
+    char             digits[16];
+    mu_Slice         s;
+    if (c->line_no)
+        return c->line_no(c->line_no_ud, line_no, buf, MU_LINE_NO_SIZE);
+    s = muD_snprintf(digits, sizeof(digits), "%u", line_no);
+    if (sep > 8) sep = 8; /* 3 separators and 10 digits fit in buf */
+    for (i = 0; i < muD_bytelen(s); ++i) {
+        if (i > 0 && sep && (muD_bytelen(s) - i) % 3 == 0)
+            memcpy(buf + len, c->line_no_sep.p, sep), len += sep;
+        buf[len++] = s.p[i];
+    }
+    return mu_lslice(buf, len);
+}
+
+static mu_Width muM_numwidth(const mu_Report *R, unsigned line_no) {
     const unsigned limits[] = {10U,       100U,       1000U,
                                10000U,    100000U,    1000000U,
                                10000000U, 100000000U, 1000000000U};
 
-    unsigned i, size, line_no;
-    mu_Width w, max_width = 0;
+    char     buf[MU_LINE_NO_SIZE];
+    mu_Width w;
+    if (R->config->line_no || muD_bytelen(R->config->line_no_sep)) {
+        mu_Slice s = muM_lineno(R, line_no, buf);
+        return muD_strwidth(s, R->config->ambiwidth);
+    }
+    for (w = 0; w < 9 && line_no >= limits[w]; ++w) {}
+    return w + 1;
+}
+
+static void muM_calc_linenowidth(mu_Report *R) {
+    unsigned i, size;
+    mu_Width max_width = 0;
     for (i = 0, size = muA_size(R->groups); i < size; ++i) {
         mu_Group *g = &R->groups[i];
-        line_no = g->last_line + g->src->line_no_offset + 1;
-        for (w = 0; line_no >= limits[w]; ++w) {}
-        max_width = mu_max(max_width, w + 1);
+        unsigned  line_no = g->last_line + g->src->line_no_offset + 1;
+        max_width = mu_max(max_width, muM_numwidth(R, line_no));
     }
     R->line_no_width = max_width;
 }
@@ -726,10 +1041,40 @@ static void muC_collect_multi(mu_Report *R) {
             col = (mu_Col)(last - line->offset), draw_msg = 1;
         else continue;
         ll = muA_push(R, R->ll_cache);
-        ll->info = li, ll->col = col, ll->draw_msg = draw_msg;
+        ll->info = li, ll->col = col, ll->draw_msg = draw_msg, ll->ul_row = 0;
     }
 }
 
+static int muC_cmp_priority(mu_Report *R, const mu_Label *l,
+                            const mu_Label *r) {
+    if (l->priority != r->priority) return l->priority < r->priority ? -1 : 1;
+    return R->config->auto_priority ? l->primary - r->primary : 0;
+}
+
+static int muC_is_inline(mu_CLI li, mu_CL line) {
+    return li->start_char >= line->offset
+        && muM_lastchar(li) < muM_lineend(line) + 1;
+}
+
+static int muC_is_hidden(mu_Report *R, mu_CLI li) {
+    mu_CLI   lj, labels = R->cur_group->labels;
+    unsigned i, size;
+    int      cmp;
+    if (R->config->overlap != MU_OVERLAP_HIDE) return 0;
+    for (i = 0, size = muA_size(labels); i < size; ++i) {
+        lj = &labels[i];
+        if (lj == li || !muC_is_inline(lj, R->cur_line)
+            || lj->start_char > muM_lastchar(li)
+            || li->start_char > muM_lastchar(lj))
+            continue;
+        cmp = muC_cmp_priority(R, lj->label, li->label);
+        if (cmp == 0 && muM_infolen(lj) != muM_infolen(li))
+            cmp = muM_infolen(lj) < muM_infolen(li) ? 1 : -1;
+        if (cmp > 0 || (cmp == 0 && lj < li)) return 1;
+    }
+    return 0;
+}
+
 static void muC_collect_inline(mu_Report *R) {
     unsigned i, size;
     mu_CLI   li, labels = R->cur_group->labels;
@@ -738,9 +1083,7 @@ static void muC_collect_inline(mu_Report *R) {
     for (i = 0, size = muA_size(labels); i < size; ++i) {
         mu_LineLabel *ll;
         li = &labels[i];
-        if (!(li->start_char >= line->offset
-              && muM_lastchar(li) < muM_lineend(line) + 1))
-            continue;
+        if (!muC_is_inline(li, line) || muC_is_hidden(R, li)) continue;
         switch (R->config->label_attach) {
         case MU_ATTACH_START: pos = li->start_char; break;
         case MU_ATTACH_END:   pos = muM_lastchar(li); break;
@@ -748,6 +1091,7 @@ static void muC_collect_inline(mu_Report *R) {
         }
         ll = muA_push(R, R->ll_cache);
         ll->info = li, ll->col = (mu_Col)(pos - line->offset), ll->draw_msg = 1;
+        ll->ul_row = 0;
     }
 }
 
@@ -770,14 +1114,20 @@ static int muC_fill_llcache(mu_Report *R) {
     return !muA_isempty(R->ll_cache);
 }
 
+static mu_Width muC_tabwidth(const mu_Config *config, mu_Width width) {
+    int i;
+    for (i = 0; i < MU_TAB_STOPS_SIZE && config->tab_stops[i] > 0; ++i)
+        if (config->tab_stops[i] > width) return config->tab_stops[i] - width;
+    return config->tab_width - (width % config->tab_width);
+}
+
 static void muC_fill_widthcache(mu_Report *R, unsigned len, mu_Slice data) {
     mu_Width chwidth, width = 0, **wc = &R->width_cache;
     utfint   prev = 0;
     muA_reset(*wc), (void)muA_reserve(R, *wc, len + 1);
     while (data.p < data.e) {
         utfint ch = muD_decode(&data);
-        if (ch == '\t')
-            chwidth = R->config->tab_width - (width % R->config->tab_width);
+        if (ch == '\t') chwidth = muC_tabwidth(R->config, width);
         else if (prev == 0x200D) chwidth = 0;
         else if (ch >= 0x1F3FB && ch <= 0x1F3FF) chwidth = 0;
         else if ((prev >= 0x1F1E6 && prev <= 0x1F1FF)
@@ -835,7 +1185,7 @@ static void muC_fill_clusters(mu_Report *R) {
         if (ll->info->multi) {
             if (!c->margin_label.info) c->margin_label = *ll, is_margin = 1;
             if ((R->config->limit_width <= 0 || !is_margin) && ll->draw_msg)
-                end_col = line->len + line->newline;
+                end_col = line->len + (line->newline != 0);
         }
         if (!is_margin) *muA_push(R, c->line_labels) = *ll;
         c->arrow_len = mu_max(c->arrow_len, end_col + extra_arrow_len);
@@ -844,6 +1194,11 @@ static void muC_fill_clusters(mu_Report *R) {
     }
 }
 
+static int muC_is_caret(mu_Report *R, mu_CLI li) {
+    return R->config->caret_only && !li->multi && li->label->width
+        && muM_infolen(li) == 1;
+}
+
 static int muC_has_underlines(mu_Report *R) {
     const mu_Cluster *c = R->cur_cluster;
 
@@ -851,10 +1206,22 @@ static int muC_has_underlines(mu_Report *R) {
     if (!R->config->underlines) return 0;
     for (i = 0, size = muA_size(c->line_labels); i < size; ++i) {
         mu_CLL ll = muC_get_ll(R, i);
-        if (!ll->info->multi && muM_infolen(ll->info)) return 1;
+        if (!ll->info->multi && muM_infolen(ll->info)
+            && !muC_is_caret(R, ll->info))
+            return 1;
     }
     return 0;
 }
+
+static int muC_skip_underline(mu_Report *R, unsigned row) {
+    const mu_Cluster *c = R->cur_cluster;
+
+    unsigned i, size;
+    if (row > 0) return muC_is_caret(R, muC_get_ll(R, row)->info);
+    for (i = 0, size = muA_size(c->line_labels); i < size; ++i)
+        if (!muC_is_caret(R, muC_get_ll(R, i)->info)) return 0;
+    return 1;
+}
 static mu_CLL muC_nextlabel(mu_Report *R, unsigned *row) {
     const mu_Cluster *c = R->cur_cluster;
 
@@ -939,6 +1306,27 @@ static void muC_sort_labels(mu_Report *R) {
     qsort(lls, muA_size(lls), sizeof(mu_LineLabel), muC_cmp_cluster);
 }
 
+static void muC_stack_underlines(mu_Report *R, mu_Cluster *c) {
+    mu_LineLabel *ll, *lj;
+    unsigned      i, j, size;
+    c->ul_rows = 1;
+    if (R->config->overlap != MU_OVERLAP_STACK) return;
+    for (i = 0, size = muA_size(c->line_labels); i < size; ++i) {
+        ll = &c->line_labels[i];
+        if (ll->info->multi || muC_is_caret(R, ll->info)) continue;
+        for (j = 0; j < i; ++j) {
+            lj = &c->line_labels[j];
+            if (lj->ul_row != ll->ul_row || lj->info->multi
+                || muC_is_caret(R, lj->info)
+                || lj->info->start_char > muM_lastchar(ll->info)
+                || ll->info->start_char > muM_lastchar(lj->info))
+                continue;
+            ll->ul_row += 1, j = (unsigned)-1; /* rescan on the next row */
+        }
+        c->ul_rows = mu_max(c->ul_rows, ll->ul_row + 1);
+    }
+}
+
 static void muC_update_margin(mu_Report *R, mu_Cluster *c) {
     mu_LineLabel **lls = &c->line_labels, *start, *end, *cur;
 
@@ -955,11 +1343,13 @@ static void muC_update_margin(mu_Report *R, mu_Cluster *c) {
     muA_rawH(*lls)->size += 1;
 }
 
-static mu_CLI muC_update_highlight(size_t pos, mu_CLI l, mu_CLI r) {
+static mu_CLI muC_update_highlight(mu_Report *R, size_t pos, mu_CLI l,
+                                   mu_CLI r) {
+    int cmp;
     if (pos < r->start_char || pos >= r->end_char) return l;
     if (l == NULL) return r;
-    if (l->label->priority != r->label->priority)
-        return l->label->priority < r->label->priority ? r : l;
+    if ((cmp = muC_cmp_priority(R, l->label, r->label)) != 0)
+        return cmp < 0 ? r : l;
     return muM_infolen(r) < muM_infolen(l) ? r : l;
 }
 
@@ -971,11 +1361,11 @@ static mu_CLI muC_get_highlight(mu_Report *R, mu_Col col) {
     mu_CLI   r = NULL;
     unsigned i, size;
     if (c->margin_label.info)
-        r = muC_update_highlight(pos, r, c->margin_label.info);
+        r = muC_update_highlight(R, pos, r, c->margin_label.info);
     for (i = 0, size = muA_size(g->multi_labels); i < size; ++i)
-        r = muC_update_highlight(pos, r, &g->multi_labels[i]);
+        r = muC_update_highlight(R, pos, r, &g->multi_labels[i]);
     for (i = 0, size = muA_size(c->line_labels); i < size; ++i)
-        r = muC_update_highlight(pos, r, c->line_labels[i].info);
+        r = muC_update_highlight(R, pos, r, c->line_labels[i].info);
     return r;
 }
 
@@ -986,7 +1376,8 @@ static mu_CLI muC_get_vbar(mu_Report *R, unsigned row, mu_Col col) {
     mu_CLL   ll;
     for (i = 0, size = muA_size(c->line_labels); i < size; ++i)
         if ((((ll = muC_get_ll(R, i))->info->label->width || ll->info->multi)
-             && c->margin_label.info != ll->info && ll->col == col && row <= i))
+             && c->margin_label.info != ll->info && ll->col == col && row <= i
+             && ll->ul_row <= R->cur_ul_row && !muC_is_caret(R, ll->info)))
             return ll->info;
     return NULL;
 }
@@ -995,17 +1386,18 @@ static mu_CLI muC_get_underline(mu_Report *R, mu_Col col) {
     const mu_Cluster *c = R->cur_cluster;
 
     size_t   pos = R->cur_line->offset + col, len, rlen = 0;
-    int      pri, rpri = 0;
+    int      cmp;
     mu_CLI   r = NULL;
     unsigned i, size;
     for (i = 0, size = muA_size(c->line_labels); i < size; ++i) {
         mu_CLL ll = &c->line_labels[i];
-        if (!(!ll->info->multi
+        if (!(!ll->info->multi && !muC_is_caret(R, ll->info)
+              && ll->ul_row == R->cur_ul_row
               && mu_asc(ll->info->start_char, pos, muM_lastchar(ll->info))))
             continue;
-        len = muM_infolen(ll->info), pri = ll->info->label->priority;
-        if (!r || pri > rpri || (pri == rpri && len < rlen))
-            r = ll->info, rlen = len, rpri = pri;
+        len = muM_infolen(ll->info);
+        cmp = r ? muC_cmp_priority(R, ll->info->label, r->label) : 1;
+        if (cmp > 0 || (cmp == 0 && len < rlen)) r = ll->info, rlen = len;
     }
     return r;
 }
@@ -1015,8 +1407,10 @@ static mu_CLI muC_get_underline(mu_Report *R, mu_Col col) {
 #define MU_MAX_POS (~(size_t)0)
 
 /* clang-format off */
-static void muG_cleanup(mu_Report *R, mu_Group *g)
-{ muA_delete(R, g->multi_labels), muA_delete(R, g->labels); }
+static void muG_cleanup(mu_Report *R, mu_Group *g) {
+    muA_delete(R, g->overflow);
+    muA_delete(R, g->multi_labels), muA_delete(R, g->labels);
+}
 /* clang-format on */
 
 typedef struct mu_LocCtx {
@@ -1024,25 +1418,39 @@ typedef struct mu_LocCtx {
     char       buff[256];
 } mu_LocCtx;
 
-static mu_Slice muG_calc_location(mu_LocCtx *ctx) {
+static mu_Slice muG_format_pos(mu_LocCtx *ctx, size_t pos) {
     const mu_Group *g = ctx->R->cur_group;
 
     unsigned line_no = 0, col = 0;
     mu_CL    line = NULL;
-    size_t   pos = g->primary.start_char;
     assert(pos != MU_MAX_POS);
     line_no = g->src->line_for_chars(g->src, pos, &line);
     assert(line != NULL);
     col = (unsigned)(pos - line->offset + 1);
+    if (ctx->R->config->normalize) { /* combining marks don't take a column */
+        mu_Slice s = g->src->get_line(g->src, line_no);
+        unsigned i, n = col - 1;
+        for (i = 0; i < n && s.p < s.e; ++i)
+            if (muD_width(muD_decode(&s), 1) == 0) --col;
+    }
+    if (!ctx->R->config->one_based_columns) --col;
     line_no += g->src->line_no_offset + 1;
     return muD_snprintf(ctx->buff, sizeof(ctx->buff), "%u:%u", line_no, col);
 }
 
+static mu_Slice muG_calc_location(mu_LocCtx *ctx) {
+    const mu_Group *g = ctx->R->cur_group;
+    if (g->has_loc) return muG_format_pos(ctx, g->loc_char);
+    if (!ctx->R->config->auto_location) return mu_lslice(ctx->buff, 0);
+    return muG_format_pos(ctx, g->primary.start_char);
+}
+
 static int muG_trim_name(mu_Report *R, mu_Slice *name, mu_Slice loc) {
     int ellipsis = 0;
     if (R->config->limit_width > 0) {
         mu_Width id = muD_strwidth(*name, R->config->ambiwidth);
-        mu_Width fixed = (int)muD_bytelen(loc) + R->line_no_width + 9;
+        mu_Width fixed = (int)muD_bytelen(loc) + R->line_no_width + 8
+                       + (loc.p < loc.e);
         mu_Width limited = R->config->limit_width;
         if (id + fixed > limited) {
             mu_Width ambi = R->config->ambiwidth;
@@ -1057,7 +1465,7 @@ static int muG_trim_name(mu_Report *R, mu_Slice *name, mu_Slice loc) {
 static int muG_cmp_labels(const void *lhf, const void *rhf) {
     const mu_Label *l = *(mu_Label **)lhf, *r = *(mu_Label **)rhf;
     if (l->order != r->order) return mu_cmp(l->order, r->order);
-    if (l->src_id != r->src_id) return mu_cmp(l->src_id, r->src_id);
+    if (l->src_rank != r->src_rank) return mu_cmp(l->src_rank, r->src_rank);
     return mu_cmp(l, r);
 }
 
@@ -1065,6 +1473,7 @@ static size_t muG_calc_charpos(mu_Report *R, size_t pos, int end, unsigned *l) {
     mu_Source *src = R->cur_group->src;
     mu_CL      line = NULL;
     size_t     r;
+    if (src->map_pos) pos = src->map_pos(src, pos, R->config->index_type, end);
     if (R->config->index_type == MU_INDEX_BYTE) {
         unsigned line_no = src->line_for_bytes(src, pos - end, &line);
         mu_Slice s = src->get_line(src, line_no);
@@ -1078,7 +1487,9 @@ static size_t muG_calc_charpos(mu_Report *R, size_t pos, int end, unsigned *l) {
         *l = src->line_for_chars(src, pos - end, &line);
         r = (assert(line != NULL), mu_max(line->offset, pos));
     }
-    return mu_min(r, muM_lineend(line) + line->newline);
+    if (r > muM_lineend(line)) /* the newline takes a single column */
+        r = muM_lineend(line) + (end && line->newline);
+    return r;
 }
 
 static mu_LabelInfo muG_init_info(mu_Report *R, const mu_Label *label) {
@@ -1098,22 +1509,72 @@ static mu_LabelInfo muG_init_info(mu_Report *R, const mu_Label *label) {
         g->first_line = mu_min(g->first_line, first_line);
         g->last_line = mu_max(g->last_line, last_line);
     }
-    if (!g->primary.label)
-        g->primary.start_char = mu_min(info.start_char, g->primary.start_char);
+    if (!g->primary.label /* else highest priority, then first label */
+        && (label->priority > g->loc_priority
+            || (label->priority == g->loc_priority
+                && info.start_char < g->primary.start_char)))
+        g->primary.start_char = info.start_char,
+        g->loc_priority = label->priority;
     return info;
 }
 
+static int muG_cmp_name(const mu_Cache *C, mu_Id l, mu_Id r) {
+    mu_Slice ln = C->sources[l]->name, rn = C->sources[r]->name;
+    size_t   llen = ln.e - ln.p, rlen = rn.e - rn.p;
+    int      c = llen && rlen ? memcmp(ln.p, rn.p, mu_min(llen, rlen)) : 0;
+    if (c != 0) return c;
+    return llen != rlen ? mu_cmp(llen, rlen) : mu_cmp(l, r);
+}
+
+static void muG_rank_sources(mu_Report *R, const mu_Cache *C) {
+    mu_Label *labels = R->labels;
+    unsigned  i, j, len = muA_size(labels), primary = 0;
+    for (i = 1; i < len; ++i) /* first primary label, else highest priority */
+        if (!labels[primary].primary
+            && (labels[i].primary
+                || labels[i].priority > labels[primary].priority))
+            primary = i;
+    for (i = 0; i < len; ++i) {
+        mu_Id id = labels[i].src_id;
+        switch (R->config->source_order) {
+        case MU_SOURCE_ORDER_LABELS:
+            for (j = 0; labels[j].src_id != id;) ++j;
+            labels[i].src_rank = j;
+            break;
+        case MU_SOURCE_ORDER_NAME:
+            for (labels[i].src_rank = 0, j = 0; j < len; ++j)
+                labels[i].src_rank += muG_cmp_name(C, labels[j].src_id, id) < 0;
+            break;
+        case MU_SOURCE_ORDER_PRIMARY:
+            labels[i].src_rank = id == labels[primary].src_id ? 0 : id + 1;
+            break;
+        default: labels[i].src_rank = id; break;
+        }
+    }
+}
+
+static int muG_initsrc(mu_Report *R, mu_Source *src) {
+    if (!src->inited && src->init) muX((R->cur_src = src)->init(src));
+    R->cur_src = NULL, src->inited = 1;
+    return MU_OK;
+}
+
 static int muG_init(mu_Report *R, const mu_Cache *C, const mu_Label *label) {
     mu_Source   *src = C->sources[label->src_id];
     mu_Group    *g = muA_last(R->groups);
     mu_LabelInfo li, **labels;
     if (g == NULL || g->src != src) {
-        if (!src->inited && src->init) muX((R->cur_src = src)->init(src));
-        R->cur_src = NULL, src->inited = 1;
+        muX(muG_initsrc(R, src));
         g = muA_push(R, R->groups);
         memset(g, 0, sizeof(mu_Group));
         g->primary.start_char = MU_MAX_POS;
+        g->loc_priority = INT_MIN;
         g->src = src;
+        if (R->has_loc && R->loc_src == label->src_id) {
+            unsigned line;
+            R->cur_group = g, g->has_loc = 1;
+            g->loc_char = muG_calc_charpos(R, R->loc_pos, 0, &line);
+        }
     }
     R->cur_group = g, li = muG_init_info(R, label);
     if (label->primary) g->primary = li;
@@ -1127,17 +1588,85 @@ static int muG_cmp_li(const void *lhf, const void *rhf) {
     return llen != rlen ? mu_cmp(rlen, llen) : mu_cmp(l->label, r->label);
 }
 
+static int muG_cmp_pos(const void *lhf, const void *rhf) {
+    mu_CLI l = (mu_CLI)lhf, r = (mu_CLI)rhf;
+    if (l->start_char != r->start_char)
+        return mu_cmp(l->start_char, r->start_char);
+    return mu_cmp(l->label, r->label);
+}
+
+static void muG_limit_depth(mu_Report *R, mu_Group *g) {
+    mu_LabelInfo *li = g->multi_labels;
+    unsigned      i, kept = 0, size = muA_size(li);
+    for (i = 0; i < size; ++i) {
+        if (kept < (unsigned)R->config->max_depth || li[i].label->primary)
+            li[kept++] = li[i];
+        else *muA_push(R, g->overflow) = li[i];
+    }
+    if (li) muA_rawH(li)->size = kept;
+    qsort(g->overflow, muA_size(g->overflow), sizeof(mu_LabelInfo),
+          muG_cmp_pos);
+}
+
+static void muG_context(mu_Report *R, const mu_Cache *C, const mu_Label *ctx) {
+    unsigned i, size, first, last;
+    for (i = 0, size = muA_size(R->groups); i < size; ++i) {
+        mu_Group *g = &R->groups[i];
+        if (g->src != C->sources[ctx->src_id]) continue;
+        R->cur_group = g;
+        muG_calc_charpos(R, ctx->start_pos, 0, &first);
+        if (ctx->start_pos >= ctx->end_pos) last = first;
+        else muG_calc_charpos(R, ctx->end_pos, 1, &last);
+        if (g->has_ctx) {
+            g->ctx_first = mu_min(g->ctx_first, first);
+            g->ctx_last = mu_max(g->ctx_last, last);
+        } else g->has_ctx = 1, g->ctx_first = first, g->ctx_last = last;
+        g->first_line = mu_min(g->first_line, first);
+        g->last_line = mu_max(g->last_line, last);
+    }
+}
+
+static void muG_reset(mu_Report *R) {
+    unsigned i, size;
+    R->layout_cache = NULL, R->cur_group = NULL;
+    muA_reset(R->sorted_labels);
+    for (i = 0, size = muA_size(R->groups); i < size; ++i)
+        muG_cleanup(R, &R->groups[i]);
+    muA_reset(R->groups);
+}
+
+static int muG_reusable(const mu_Report *R, const mu_Cache *C) {
+    unsigned i, size;
+    if (R->layout_cache != C || R->layout_srcs != mu_sourcecount(C)
+        || R->layout_index != R->config->index_type
+        || R->layout_ctx != R->config->context_lines
+        || R->layout_depth != R->config->max_depth
+        || R->layout_order != R->config->source_order)
+        return 0;
+    for (i = 0, size = muA_size(R->groups); i < size; ++i) {
+        const mu_Group *g = &R->groups[i]; /* sources may be replaced */
+        mu_CLI li = muA_isempty(g->labels) ? g->multi_labels : g->labels;
+        if (!muA_isempty(li) && mu_getsource(C, li->label->src_id) != g->src)
+            return 0;
+    }
+    return 1;
+}
+
 static int muG_make_groups(mu_Report *R, const mu_Cache *C) {
     unsigned i, len = muA_size(R->labels);
     assert(muA_isempty(R->sorted_labels) && muA_isempty(R->groups));
     muA_reserve(R, R->sorted_labels, len);
+    muG_rank_sources(R, C);
     for (i = 0; i < len; ++i) *muA_push(R, R->sorted_labels) = &R->labels[i];
     qsort(R->sorted_labels, len, sizeof(mu_Label *), muG_cmp_labels);
     for (i = 0; i < len; ++i) muX(muG_init(R, C, R->sorted_labels[i]));
+    for (i = 0, len = muA_size(R->contexts); i < len; ++i)
+        muG_context(R, C, &R->contexts[i]);
     for (i = 0, len = muA_size(R->groups); i < len; ++i) {
         mu_Group     *g = &R->groups[i];
         mu_LabelInfo *li = g->multi_labels;
         qsort(li, muA_size(li), sizeof(mu_LabelInfo), muG_cmp_li);
+        if (R->config->max_depth > 0) muG_limit_depth(R, g);
         if (R->config->context_lines > 0) {
             unsigned ctx = R->config->context_lines;
             unsigned limit = g->src->line_count(g->src) - 1;
@@ -1145,6 +1674,11 @@ static int muG_make_groups(mu_Report *R, const mu_Cache *C) {
             g->last_line = mu_min(g->last_line + ctx, limit);
         }
     }
+    R->layout_cache = C, R->layout_srcs = mu_sourcecount(C);
+    R->layout_index = R->config->index_type;
+    R->layout_ctx = R->config->context_lines;
+    R->layout_depth = R->config->max_depth;
+    R->layout_order = R->config->source_order;
     return MU_OK;
 }
 
@@ -1268,10 +1802,8 @@ static void muR_cleanup(mu_Report *R) {
     if (!R) return;
     R->cur_color_label = NULL;
     R->cur_color_kind = MU_COLOR_RESET;
-    muA_reset(R->sorted_labels);
-    for (i = 0, size = muA_size(R->groups); i < size; ++i)
-        muG_cleanup(R, &R->groups[i]);
-    muA_reset(R->groups);
+    R->cur_msg = -1;
+    R->cur_ul_row = UINT_MAX;
     for (i = 0, size = muA_size(R->clusters); i < size; ++i)
         muC_cleanup(R, &R->clusters[i]);
     muA_reset(R->clusters);
@@ -1283,6 +1815,7 @@ static int muR_header(mu_Report *R) {
     mu_ColorKind level_color;
     mu_Slice     level_slice = R->custom_level;
     muM_level(R->level, &level_color, &level_slice);
+    R->cur_kind = MU_LINE_HEADER;
     muX(muW_color(R, level_color));
     if (R->code.p) {
         muX(muW_draw(R, MU_DRAW_LBOX, 1));
@@ -1295,18 +1828,33 @@ static int muR_header(mu_Report *R) {
     muX(muW_color(R, MU_COLOR_RESET));
     if (R->title.p) {
         muX(muW_draw(R, MU_DRAW_SPACE, 1));
-        muX(muW_write(R, R->title));
+        muX(muW_markup(R, R->title, MU_COLOR_RESET, 1));
     }
     return muW_draw(R, MU_DRAW_NEWLINE, 1);
 }
 
+/* start a line with `count` columns of `draw`, the first one taken by the
+ * gutter bar if it is enabled */
+static int muR_gutter(mu_Report *R, mu_ColorKind k, mu_Draw draw, int count) {
+    if (R->config->gutter_bar) {
+        mu_ColorKind level_color;
+        mu_Slice     level_slice;
+        muM_level(R->level, &level_color, &level_slice);
+        muX(muW_color(R, level_color));
+        muX(muW_draw(R, MU_DRAW_VBAR, 1));
+        --count;
+    }
+    muX(muW_color(R, k));
+    return muW_draw(R, draw, count);
+}
+
 static int muR_reference(mu_Report *R, unsigned i) {
     mu_LocCtx ctx;
     mu_Slice  name = R->cur_group->src->name;
     mu_Slice  loc = (ctx.R = R, muG_calc_location(&ctx));
     int       ellipsis = muG_trim_name(R, &name, loc);
-    muX(muW_color(R, MU_COLOR_MARGIN));
-    muX(muW_draw(R, MU_DRAW_SPACE, R->line_no_width + 2));
+    R->cur_kind = MU_LINE_REFERENCE;
+    muX(muR_gutter(R, MU_COLOR_MARGIN, MU_DRAW_SPACE, R->line_no_width + 2));
     muX(muW_draw(R, i ? MU_DRAW_VBAR : MU_DRAW_LTOP, 1));
     muX(muW_draw(R, MU_DRAW_HBAR, 1));
     muX(muW_draw(R, MU_DRAW_LBOX, 1));
@@ -1317,8 +1865,10 @@ static int muR_reference(mu_Report *R, unsigned i) {
         muX(muW_draw(R, MU_DRAW_ELLIPSIS, 1));
     }
     muX(muW_replace(R, name, '\t', ' '));
-    muX(muW_draw(R, MU_DRAW_COLON, 1));
-    muX(muW_write(R, loc));
+    if (loc.p < loc.e) {
+        muX(muW_draw(R, MU_DRAW_COLON, 1));
+        muX(muW_write(R, loc));
+    }
     muX(muW_draw(R, MU_DRAW_SPACE, 1));
     muX(muW_color(R, MU_COLOR_MARGIN));
     muX(muW_draw(R, MU_DRAW_RBOX, 1));
@@ -1327,28 +1877,27 @@ static int muR_reference(mu_Report *R, unsigned i) {
 }
 
 static int muR_empty_line(mu_Report *R) {
-    muW_color(R, MU_COLOR_MARGIN);
-    muX(muW_draw(R, MU_DRAW_SPACE, R->line_no_width + 2));
+    R->cur_kind = MU_LINE_EMPTY;
+    muX(muR_gutter(R, MU_COLOR_MARGIN, MU_DRAW_SPACE, R->line_no_width + 2));
     muX(muW_draw(R, MU_DRAW_VBAR, 1));
     muW_color(R, MU_COLOR_RESET);
     return muW_draw(R, MU_DRAW_NEWLINE, 1);
 }
 
 static int muR_lineno(mu_Report *R, unsigned line_no, int is_ellipsis) {
-    char     buf[32];
+    char     buf[MU_LINE_NO_SIZE];
     mu_Slice ln;
     if (line_no && !is_ellipsis) {
-        line_no += R->cur_group->src->line_no_offset;
-        ln = muD_snprintf(buf, sizeof(buf), "%u", line_no);
-        muX(muW_color(R, MU_COLOR_MARGIN));
-        muX(muW_draw(R, MU_DRAW_SPACE,
-                     R->line_no_width - (int)muD_bytelen(ln) + 1));
+        ln = muM_lineno(R, line_no, buf);
+        muX(muR_gutter(R, MU_COLOR_MARGIN, MU_DRAW_SPACE,
+                       R->line_no_width
+                           - muD_strwidth(ln, R->config->ambiwidth) + 1));
         muX(muW_write(R, ln));
         muX(muW_draw(R, MU_DRAW_SPACE, 1));
         muX(muW_draw(R, MU_DRAW_LINE_MARGIN, 1));
     } else {
-        muX(muW_color(R, MU_COLOR_SKIPPED_MARGIN));
-        muX(muW_draw(R, MU_DRAW_SPACE, R->line_no_width + 2));
+        muX(muR_gutter(R, MU_COLOR_SKIPPED_MARGIN, MU_DRAW_SPACE,
+                       R->line_no_width + 2));
         muX(muW_draw(R, is_ellipsis ? MU_DRAW_VBAR_GAP : MU_DRAW_VBAR, 1));
     }
     muX(muW_color(R, MU_COLOR_RESET));
@@ -1381,9 +1930,14 @@ static int muR_line(mu_Report *R, mu_Slice data) {
     const mu_Width   *wc = R->width_cache;
     const char       *s;
 
-    mu_CLI hl, color = NULL;
-    mu_Col i;
+    mu_CLI       hl, color = NULL;
+    mu_Col       i;
+    int          isolate = R->config->bidi_isolate && muD_hasrtl(data);
+    mu_ColorKind plain = MU_COLOR_RESET;
+    if (R->in_context) plain = MU_COLOR_CONTEXT;
+    else if (R->config->dim_unlabeled) plain = MU_COLOR_UNIMPORTANT;
     for (i = 0; i < c->start_col; ++i) muD_advance(&data);
+    if (isolate) muX(muW_write(R, mu_literal("\xE2\x81\xA8"))); /* FSI */
     for (s = data.p; i < c->end_col && data.p < data.e; ++i) {
         const char *p = data.p;
         hl = muC_get_highlight(R, i);
@@ -1391,8 +1945,8 @@ static int muR_line(mu_Report *R, mu_Slice data) {
         if (hl != color || *p == '\t') {
             if (s < p) {
                 if (color) muX(muW_use_color(R, color->label, MU_COLOR_LABEL));
-                else muX(muW_use_color(R, NULL, MU_COLOR_UNIMPORTANT));
-                muX(muW_write(R, mu_lslice(s, p - s)));
+                else muX(muW_use_color(R, NULL, plain));
+                muX(muW_text(R, mu_lslice(s, p - s)));
             }
             if (*p == '\t') muX(muW_draw(R, MU_DRAW_SPACE, wc[i + 1] - wc[i]));
             color = hl, s = p + (*p == '\t');
@@ -1400,9 +1954,10 @@ static int muR_line(mu_Report *R, mu_Slice data) {
     }
     if (s < data.p) {
         if (color) muX(muW_use_color(R, color->label, MU_COLOR_LABEL));
-        else muX(muW_use_color(R, NULL, MU_COLOR_UNIMPORTANT));
-        muX(muW_write(R, mu_lslice(s, data.p - s)));
+        else muX(muW_use_color(R, NULL, plain));
+        muX(muW_text(R, mu_lslice(s, data.p - s)));
     }
+    if (isolate) muX(muW_write(R, mu_literal("\xE2\x81\xA9"))); /* PDI */
     return muW_use_color(R, NULL, MU_COLOR_RESET);
 }
 
@@ -1413,6 +1968,7 @@ static int muR_underline(mu_Report *R, int row, int draw_underline) {
     int    has_ul = (draw_underline && R->config->underlines);
     mu_CLL ll = muC_get_ll(R, row);
     mu_Col col, col_max = R->cur_line->len;
+    R->cur_kind = MU_LINE_UNDERLINE;
     muX(muR_lineno(R, 0, 0));
     muX(muR_margin(R, ll, MU_MARGIN_NONE));
     if (c->start_col > 0) muX(muW_draw(R, MU_DRAW_SPACE, R->ellipsis_width));
@@ -1447,12 +2003,21 @@ static int muR_underline(mu_Report *R, int row, int draw_underline) {
     return muW_draw(R, MU_DRAW_NEWLINE, 1);
 }
 
+static int muR_underlines(mu_Report *R) {
+    const mu_Cluster *c = R->cur_cluster;
+    for (R->cur_ul_row = 0; R->cur_ul_row < c->ul_rows; ++R->cur_ul_row)
+        muX(muR_underline(R, 0, 1));
+    R->cur_ul_row = UINT_MAX;
+    return MU_OK;
+}
+
 static int muR_arrow(mu_Report *R, int row, int draw_underline) {
     const mu_Width   *wc = R->width_cache;
     const mu_Cluster *c = R->cur_cluster;
 
     mu_Col col, col_max = R->cur_line->len;
     mu_CLL ll = muC_get_ll(R, row);
+    R->cur_kind = MU_LINE_ARROW;
     muX(muR_lineno(R, 0, 0));
     muX(muR_margin(R, ll, MU_MARGIN_ARROW));
     if (c->start_col > 0) {
@@ -1469,7 +2034,8 @@ static int muR_arrow(mu_Report *R, int row, int draw_underline) {
         if (col == ll->col && c->margin_label.info != ll->info) {
             mu_Draw draw = MU_DRAW_RBOT;
             int     is_multi = ll->info->multi;
-            if (!is_multi) draw = MU_DRAW_LBOT;
+            if (muC_is_caret(R, ll->info)) draw = MU_DRAW_UARROW;
+            else if (!is_multi) draw = MU_DRAW_LBOT;
             else if (ll->draw_msg) draw = (lw ? MU_DRAW_MBOT : MU_DRAW_RBOT);
             muX(muW_use_color(R, ll->info->label, MU_COLOR_LABEL));
             muX(muW_draw(R, draw, 1));
@@ -1498,7 +2064,9 @@ static int muR_arrow(mu_Report *R, int row, int draw_underline) {
     muX(muW_use_color(R, NULL, MU_COLOR_RESET));
     if (ll->draw_msg) {
         muX(muW_draw(R, MU_DRAW_SPACE, 1));
-        muX(muW_write(R, ll->info->label->message));
+        R->cur_msg = (int)(ll->info->label - R->labels);
+        muX(muW_message(R, ll->info->label));
+        R->cur_msg = -1;
     }
     return muW_draw(R, MU_DRAW_NEWLINE, 1);
 }
@@ -1506,8 +2074,9 @@ static int muR_arrow(mu_Report *R, int row, int draw_underline) {
 static int muR_singlecluster(mu_Report *R, unsigned line_no, mu_Slice data) {
     const mu_Cluster *c = R->cur_cluster;
 
-    unsigned row = 0;
-    muX(muR_lineno(R, line_no + 1, 0));
+    unsigned row = 0, offset = (unsigned)R->cur_group->src->line_no_offset;
+    R->cur_kind = MU_LINE_SOURCE;
+    muX(muR_lineno(R, line_no + offset + 1, 0));
     muX(muR_margin(R, NULL, MU_MARGIN_LINE));
     if (c->start_col > 0) {
         muX(muW_color(R, MU_COLOR_UNIMPORTANT));
@@ -1522,14 +2091,14 @@ static int muR_singlecluster(mu_Report *R, unsigned line_no, mu_Slice data) {
     }
     muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
     if (R->config->compact) {
-        if (muC_has_underlines(R)) muX(muR_underline(R, 0, 1));
+        if (muC_has_underlines(R)) muX(muR_underlines(R));
         else if (muC_nextlabel(R, &row)) muX(muR_arrow(R, row++, 1));
         while (muC_nextlabel(R, &row)) muX(muR_arrow(R, row++, 0));
     } else if (!muA_isempty(c->line_labels)) {
-        muX(muR_underline(R, 0, 1));
+        if (!muC_skip_underline(R, 0)) muX(muR_underlines(R));
         if (muC_nextlabel(R, &row)) muX(muR_arrow(R, row++, 0));
         while (muC_nextlabel(R, &row)) {
-            muX(muR_underline(R, row, 0));
+            if (!muC_skip_underline(R, row)) muX(muR_underline(R, row, 0));
             muX(muR_arrow(R, row++, 0));
         }
     }
@@ -1548,6 +2117,7 @@ static int muR_clusters(mu_Report *R, unsigned line_no) {
         R->cur_cluster = c;
         if (R->config->limit_width > 0) muC_calc_colrange(R, c);
         if (R->config->minimise_crossings) muC_sort_labels(R);
+        muC_stack_underlines(R, c);
         muC_update_margin(R, c);
         muX(muR_singlecluster(R, line_no, data));
     }
@@ -1558,17 +2128,50 @@ static int muR_skippedline(mu_Report *R, unsigned line_no) {
     const mu_Group *g = R->cur_group;
     mu_CL           line = R->cur_line;
     if (muM_line_in_labels(line, g->multi_labels)) {
+        R->cur_kind = MU_LINE_ELLIPSIS;
         muX(muR_lineno(R, 0, 1));
         R->cur_cluster = NULL;
         muX(muR_margin(R, NULL, MU_MARGIN_ELLIPSIS));
         muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
     } else if (!R->config->compact && line_no > g->first_line) {
+        R->cur_kind = MU_LINE_EMPTY;
         muX(muR_lineno(R, 0, 0));
         muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
     }
     return MU_OK;
 }
 
+static int muR_overflow(mu_Report *R) {
+    const mu_Group *g = R->cur_group;
+
+    mu_LocCtx ctx;
+    unsigned  i, size = muA_size(g->overflow);
+    if (size && !R->config->compact) {
+        R->cur_kind = MU_LINE_EMPTY;
+        muX(muR_lineno(R, 0, 0));
+        muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
+    }
+    for (ctx.R = R, i = 0; i < size; ++i) {
+        mu_CLI li = &g->overflow[i];
+        R->cur_kind = MU_LINE_NOTE;
+        muX(muR_lineno(R, 0, 0));
+        muX(muW_use_color(R, li->label, MU_COLOR_LABEL));
+        muX(muW_draw(R, MU_DRAW_RARROW, 1));
+        muX(muW_draw(R, MU_DRAW_SPACE, 1));
+        muX(muW_write(R, muG_format_pos(&ctx, li->start_char)));
+        muX(muW_use_color(R, NULL, MU_COLOR_RESET));
+        if (li->label->width) {
+            muX(muW_draw(R, MU_DRAW_COLON, 1));
+            muX(muW_draw(R, MU_DRAW_SPACE, 1));
+            R->cur_msg = (int)(li->label - R->labels);
+            muX(muW_message(R, li->label));
+            R->cur_msg = -1;
+        }
+        muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
+    }
+    return MU_OK;
+}
+
 static int muR_lines(mu_Report *R) {
     const mu_Group *g = R->cur_group;
     unsigned        line_no, rendered_line;
@@ -1576,7 +2179,9 @@ static int muR_lines(mu_Report *R) {
     int context = R->config->context_lines;
     for (line_no = g->first_line; line_no <= g->last_line; ++line_no) {
         mu_CL line = g->src->get_line_info(g->src, line_no);
-        R->cur_line = line;
+        R->cur_line = line, R->cur_line_no = line_no;
+        R->in_context =
+            g->has_ctx && mu_asc(g->ctx_first, line_no, g->ctx_last);
         if (muC_fill_llcache(R)) {
             if (context < 0 && rendered_line + 1 < line_no) {
                 context = R->config->context_lines;
@@ -1585,10 +2190,10 @@ static int muR_lines(mu_Report *R) {
             }
             muX(muR_clusters(R, line_no));
             context = R->config->context_lines, rendered_line = line_no;
-        } else if (context == 0) {
+        } else if (context == 0 && !R->in_context) {
             muX(muR_skippedline(R, line_no));
             context = -1; /* makes loop may rollback when new label found */
-        } else if (context > 0) {
+        } else if (context > 0 || R->in_context) {
             mu_Cluster *c = (muA_reset(R->clusters), muC_new_cluster(R));
             mu_Slice    data = g->src->get_line(g->src, line_no);
             R->cur_cluster = c;
@@ -1597,62 +2202,291 @@ static int muR_lines(mu_Report *R) {
                 c->min_col = 0, muC_calc_colrange(R, c);
             }
             muX(muR_singlecluster(R, line_no, data));
-            context -= 1, rendered_line = line_no; /* decrement context line */
+            if (!R->in_context) context -= 1; /* decrement context line */
+            rendered_line = line_no;
         }
     }
     return MU_OK;
 }
 
+/* the line in place of the lines from `p` to `e` of a collapsed message */
+static int muR_collapsed(mu_Report *R, mu_Slice t, const char *p,
+                         const char *e) {
+    char     buf[32];
+    unsigned rest = 1;
+    for (; p < e; ++p) rest += (*p == '\n');
+    muX(muR_lineno(R, 0, 0));
+    muX(muW_color(R, MU_COLOR_NOTE));
+    muX(muW_draw(R, MU_DRAW_SPACE, (int)muD_bytelen(t) + 2));
+    muX(muW_draw(R, MU_DRAW_ELLIPSIS, 1));
+    muX(muW_write(R, muD_snprintf(buf, sizeof(buf), " %u more line%s", rest,
+                                  rest > 1 ? "s" : "")));
+    muX(muW_color(R, MU_COLOR_RESET));
+    return muW_draw(R, MU_DRAW_NEWLINE, 1);
+}
+
+static int muR_footer_msg(mu_Report *R, mu_Slice t, mu_Slice m) {
+    mu_Slice msg;
+    int      n = 0, limit = R->config->collapse_notes;
+    for (msg = m;; msg.p = msg.e + 1) {
+        size_t msglen = (size_t)(m.e - msg.p);
+        if (!(msg.e = memchr(msg.p, '\n', msglen))) msg.e = m.e;
+        if (limit > 0 && n++ == limit) return muR_collapsed(R, t, msg.p, m.e);
+        muX(muR_lineno(R, 0, 0));
+        muX(muW_color(R, MU_COLOR_NOTE));
+        if (msg.p > m.p)
+            muX(muW_draw(R, MU_DRAW_SPACE, (int)muD_bytelen(t) + 2));
+        else {
+            muX(muW_write(R, t));
+            muX(muW_draw(R, MU_DRAW_COLON, 1));
+            muX(muW_draw(R, MU_DRAW_SPACE, 1));
+        }
+        muX(muW_markup(R, msg, MU_COLOR_NOTE, 1));
+        muX(muW_color(R, MU_COLOR_RESET));
+        muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
+        if (msg.e >= m.e) break;
+    }
+    return MU_OK;
+}
+
+static unsigned muR_fixcount(const mu_Report *R) {
+    unsigned i, n = 0, size = muA_size(R->suggestions);
+    for (i = 0; i < size; ++i) n += !R->suggestions[i].part;
+    return n;
+}
+
 static int muR_help_or_note(mu_Report *R, int is_help, const mu_Slice *msgs) {
     const mu_Slice st = is_help ? mu_literal("Help") : mu_literal("Note");
 
     char     buf[32];
-    unsigned i, size;
-    for (i = 0, size = muA_size(msgs); i < size; ++i) {
-        mu_Slice t = st, msg;
-        if (size > 1) t = muD_snprintf(buf, sizeof(buf), "%s %u", st.p, i + 1);
+    unsigned i, size = muA_size(msgs), total = size;
+    if (is_help) total += muR_fixcount(R); /* numbered together */
+    for (i = 0; i < size; ++i) {
+        mu_Slice t = st;
+        if (total > 1) t = muD_snprintf(buf, sizeof(buf), "%s %u", st.p, i + 1);
         if (!R->config->compact) {
+            R->cur_kind = MU_LINE_EMPTY;
             muX(muR_lineno(R, 0, 0));
             muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
         }
-        for (msg = msgs[i];; msg.p = msg.e + 1) {
-            size_t msglen = (size_t)(msgs[i].e - msg.p);
-            if (!(msg.e = memchr(msg.p, '\n', msglen))) msg.e = msgs[i].e;
-            muX(muR_lineno(R, 0, 0));
-            muX(muW_color(R, MU_COLOR_NOTE));
-            if (msg.p > msgs[i].p)
-                muX(muW_draw(R, MU_DRAW_SPACE, (int)muD_bytelen(t) + 2));
-            else {
-                muX(muW_write(R, t));
-                muX(muW_draw(R, MU_DRAW_COLON, 1));
-                muX(muW_draw(R, MU_DRAW_SPACE, 1));
-            }
-            muX(muW_write(R, msg));
-            muX(muW_color(R, MU_COLOR_RESET));
-            muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
-            if (msg.e >= msgs[i].e) break;
+        R->cur_kind = is_help ? MU_LINE_HELP : MU_LINE_NOTE;
+        muX(muR_footer_msg(R, t, msgs[i]));
+    }
+    return MU_OK;
+}
+
+static unsigned muR_fixpos(mu_Report *R, mu_Source *src, size_t pos,
+                           mu_Slice *data, const char **p) {
+    mu_CL    line = NULL;
+    unsigned line_no;
+    if (R->config->index_type == MU_INDEX_BYTE) {
+        line_no = src->line_for_bytes(src, pos, &line);
+        *data = src->get_line(src, line_no), *p = data->p;
+        assert(line != NULL);
+        if (pos > line->byte_offset)
+            *p += mu_min(pos - line->byte_offset, muD_bytelen(*data));
+    } else {
+        mu_Slice s;
+        size_t   i;
+        line_no = src->line_for_chars(src, pos, &line);
+        s = *data = src->get_line(src, line_no);
+        for (i = (assert(line != NULL), line->offset); i < pos && s.p < s.e; ++i)
+            muD_advance(&s);
+        *p = s.p;
+    }
+    return line_no;
+}
+
+static int muR_fixwidth(mu_Report *R, const mu_Cache *C) {
+    unsigned i, size, added = 0;
+    for (i = 0, size = muA_size(R->suggestions); i < size; ++i) {
+        const mu_Suggestion *s = &R->suggestions[i];
+
+        mu_Source  *src = C->sources[s->src_id];
+        mu_Slice    data, repl = s->replacement;
+        const char *p;
+        unsigned    line_no;
+        muX(muG_initsrc(R, src));
+        line_no = muR_fixpos(R, src, s->start_pos, &data, &p);
+        if (!s->part) added = 0; /* earlier edits may share the line */
+        while ((p = memchr(repl.p, '\n', muD_bytelen(repl))) != NULL)
+            repl.p = p + 1, ++added; /* lines added by the replacement */
+        line_no += added + src->line_no_offset + 1;
+        R->line_no_width = mu_max(R->line_no_width, muM_numwidth(R, line_no));
+    }
+    return MU_OK;
+}
+
+static int muR_fixtext(mu_Report *R, mu_Slice s, mu_Width *col) {
+    const char *p = s.p;
+    while (s.p < s.e) {
+        const char *c = s.p;
+        utfint      ch = muD_decode(&s);
+        mu_Width    w;
+        if (ch != '\t') {
+            *col += muD_width(ch, R->config->ambiwidth);
+            continue;
         }
+        if (p < c) muX(muW_text(R, mu_lslice(p, (size_t)(c - p))));
+        w = muC_tabwidth(R->config, *col);
+        muX(muW_draw(R, MU_DRAW_SPACE, w));
+        *col += w, p = s.p;
+    }
+    return p < s.e ? muW_text(R, mu_lslice(p, (size_t)(s.e - p))) : MU_OK;
+}
+
+static void muR_fixmark(mu_Report *R, mu_Width from, mu_Width to) {
+    if (from >= to) return;
+    *muA_push(R, R->fix_marks) = from;
+    *muA_push(R, R->fix_marks) = to;
+}
+
+static int muR_fixmarks(mu_Report *R) {
+    unsigned i, size = muA_size(R->fix_marks);
+    mu_Width col = 0, pad;
+    if (size == 0) return MU_OK;
+    muX(muR_lineno(R, 0, 0));
+    for (i = 0; i < size; i += 2) {
+        mu_Width from = R->fix_marks[i], to = R->fix_marks[i + 1];
+        for (pad = from - col; pad > 0; pad -= mu_min(pad, 80))
+            muX(muW_draw(R, MU_DRAW_SPACE, mu_min(pad, 80))); /* pads <= 80 */
+        muX(muW_color(R, MU_COLOR_NOTE));
+        muX(muW_draw(R, MU_DRAW_UNDERLINE, to - from));
+        muX(muW_color(R, MU_COLOR_RESET));
+        col = to;
+    }
+    muA_reset(R->fix_marks);
+    return muW_draw(R, MU_DRAW_NEWLINE, 1);
+}
+
+static int muR_fixtitle(mu_Report *R, mu_Slice t, const mu_Suggestion *s,
+                        int multi) {
+    if (s->message.p) return muR_footer_msg(R, t, s->message);
+    muX(muR_lineno(R, 0, 0));
+    muX(muW_color(R, MU_COLOR_NOTE));
+    muX(muW_write(R, t));
+    muX(muW_draw(R, MU_DRAW_COLON, 1));
+    muX(muW_draw(R, MU_DRAW_SPACE, 1));
+    if (multi)
+        muX(muW_write(R, mu_literal("apply these changes")));
+    else if (s->replacement.p == s->replacement.e)
+        muX(muW_write(R, mu_literal("remove this")));
+    else {
+        muX(muW_write(R, mu_literal("replace with ")));
+        if (R->config->inline_code) muX(muW_color(R, MU_COLOR_INLINE_CODE));
+        muX(muW_write(R, mu_literal("`")));
+        muX(muW_replace(R, s->replacement, '\n', ' '));
+        muX(muW_write(R, mu_literal("`")));
+    }
+    muX(muW_color(R, MU_COLOR_RESET));
+    return muW_draw(R, MU_DRAW_NEWLINE, 1);
+}
+
+static int muR_fixedit(mu_Report *R, const mu_Suggestion *s, unsigned offset,
+                       unsigned *line_no, mu_Width *col) {
+    mu_Slice part, repl = s->replacement;
+    for (part = repl;; part.p = part.e + 1) {
+        size_t   partlen = (size_t)(repl.e - part.p);
+        mu_Width from = *col;
+        if (!(part.e = memchr(part.p, '\n', partlen))) part.e = repl.e;
+        muX(muR_fixtext(R, part, col));
+        muR_fixmark(R, from, *col);
+        if (part.e >= repl.e) break;
+        muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
+        muX(muR_fixmarks(R));
+        *col = 0;
+        muX(muR_lineno(R, ++*line_no + offset + 1, 0));
     }
     return MU_OK;
 }
 
-static int muR_footer(mu_Report *R) {
+static int muR_suggestion(mu_Report *R, const mu_Cache *C, unsigned i,
+                          unsigned n) {
+    const mu_Suggestion *s = &R->suggestions[i];
+
+    char     buf[32];
+    mu_Slice t = mu_literal("Help");
+    unsigned size = muA_size(R->suggestions);
+    if (muA_size(R->helps) + muR_fixcount(R) > 1)
+        t = muD_snprintf(buf, sizeof(buf), "Help %u", n);
+    if (!R->config->compact) {
+        R->cur_kind = MU_LINE_EMPTY;
+        muX(muR_lineno(R, 0, 0));
+        muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
+    }
+    R->cur_kind = MU_LINE_HELP;
+    muX(muR_fixtitle(R, t, s, i + 1 < size && R->suggestions[i + 1].part));
+    muA_reset(R->fix_marks);
+    for (;;) { /* one block of lines per run of edits sharing a line */
+        mu_Source  *src = C->sources[s->src_id];
+        unsigned    offset = (unsigned)src->line_no_offset, line_no;
+        mu_Width    col = 0;
+        mu_Slice    data;
+        const char *p, *q;
+        line_no = muR_fixpos(R, src, s->start_pos, &data, &p);
+        muX(muR_lineno(R, line_no + offset + 1, 0));
+        muX(muR_fixtext(R, mu_lslice(data.p, (size_t)(p - data.p)), &col));
+        for (;;) {
+            const mu_Suggestion *next = s + 1;
+            size_t end = mu_max(s->start_pos, s->end_pos), off;
+            unsigned end_no;
+            muX(muR_fixedit(R, s, offset, &line_no, &col));
+            /* the tail may come from another line, fetched only now */
+            end_no = muR_fixpos(R, src, end, &data, &p);
+            if (++i >= size || !next->part || next->src_id != s->src_id
+                || next->start_pos < end)
+                break;
+            off = (size_t)(p - data.p); /* fetching may reuse the buffer */
+            if (muR_fixpos(R, src, next->start_pos, &data, &q) != end_no) {
+                muR_fixpos(R, src, end, &data, &p);
+                break;
+            }
+            p = data.p + off, s = next;
+            muX(muR_fixtext(R, mu_lslice(p, (size_t)(q - p)), &col));
+        }
+        muX(muR_fixtext(R, mu_lslice(p, (size_t)(data.e - p)), &col));
+        muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
+        muX(muR_fixmarks(R));
+        if (i >= size || !R->suggestions[i].part) return MU_OK;
+        s = &R->suggestions[i];
+    }
+}
+
+static int muR_footer(mu_Report *R, const mu_Cache *C) {
+    unsigned i, n = muA_size(R->helps), size = muA_size(R->suggestions);
     muX(muR_help_or_note(R, 1, R->helps));
+    for (i = 0; i < size; ++i)
+        if (!R->suggestions[i].part) muX(muR_suggestion(R, C, i, ++n));
     muX(muR_help_or_note(R, 0, R->notes));
-    if (!muA_isempty(R->groups) && !R->config->compact) {
-        muX(muW_color(R, MU_COLOR_MARGIN));
-        muX(muW_draw(R, MU_DRAW_HBAR, R->line_no_width + 2));
-        muX(muW_draw(R, MU_DRAW_RBOT, 1));
+    if ((muA_isempty(R->groups) && size == 0) || R->config->compact
+        || R->config->footer == MU_FOOTER_OMIT)
+        return MU_OK;
+    R->cur_kind = MU_LINE_FOOTER;
+    if (R->config->footer == MU_FOOTER_BLANK)
+        return muW_draw(R, MU_DRAW_NEWLINE, 1);
+    muX(muR_gutter(R, MU_COLOR_MARGIN, MU_DRAW_HBAR, R->line_no_width + 2));
+    muX(muW_draw(R, MU_DRAW_RBOT, 1));
+    if (R->config->footer == MU_FOOTER_LABELED) {
+        muX(muW_draw(R, MU_DRAW_HBAR, 1));
+        muX(muW_draw(R, MU_DRAW_LBOX, 1));
         muX(muW_color(R, MU_COLOR_RESET));
-        muX(muW_draw(R, MU_DRAW_NEWLINE, 1));
+        muX(muW_write(R, mu_literal(" end ")));
+        if (R->code.p) {
+            muX(muW_write(R, R->code));
+            muX(muW_draw(R, MU_DRAW_SPACE, 1));
+        }
+        muX(muW_color(R, MU_COLOR_MARGIN));
+        muX(muW_draw(R, MU_DRAW_RBOX, 1));
     }
-    return MU_OK;
+    muX(muW_color(R, MU_COLOR_RESET));
+    return muW_draw(R, MU_DRAW_NEWLINE, 1);
 }
 
 static int muR_report(mu_Report *R, const mu_Cache *cache) {
     unsigned i, size;
-    muX(muG_make_groups(R, cache));
+    if (!R->layout_cache) muX(muG_make_groups(R, cache));
     muM_calc_linenowidth(R);
+    muX(muR_fixwidth(R, cache));
     muX(muR_header(R));
     for (i = 0, size = muA_size(R->groups); i < size; ++i) {
         mu_Group *g = &R->groups[i];
@@ -1661,8 +2495,9 @@ static int muR_report(mu_Report *R, const mu_Cache *cache) {
         muX(muR_reference(R, i));
         if (!R->config->compact) muX(muR_empty_line(R));
         muX(muR_lines(R));
+        muX(muR_overflow(R));
     }
-    muX(muR_footer(R));
+    muX(muR_footer(R, cache));
     return MU_OK;
 }
 
@@ -1680,6 +2515,9 @@ static void muM_free(mu_Allocator *alloc, void *p, size_t size)
 MU_API unsigned mu_sourcecount(const mu_Cache *C)
 { return C ? muS_issrc(C) ? 1 : muA_size(C->sources) : 0; }
 
+MU_API mu_Source *mu_getsource(const mu_Cache *C, unsigned src_id)
+{ return src_id < mu_sourcecount(C) ? C->sources[src_id] : NULL; }
+
 MU_API mu_Source *mu_source(mu_Report *R)
 { return R && R->cur_src ? R->cur_src : NULL; }
 
@@ -1687,6 +2525,15 @@ MU_API unsigned mu_linecount(mu_Source *src)
 { return src ? (unsigned)muA_size(src->lines) : 0; }
 /* clang-format on */
 
+MU_API mu_Source *mu_setsource(mu_Cache *C, unsigned src_id, mu_Source *src) {
+    mu_Source *old;
+    if (!C || !src || !C->sources || muS_issrc(C)
+        || src_id >= muA_size(C->sources))
+        return NULL;
+    old = C->sources[src_id], C->sources[src_id] = src;
+    return old;
+}
+
 static void *muM_default_allocf(void *ud, void *p, size_t nsize, size_t osize) {
     (void)ud, (void)osize;
     if (p && nsize == 0) return free(p), (void *)NULL;
@@ -1697,6 +2544,7 @@ static mu_Allocator muM_initalloc(mu_Allocf *allocf, void *ud) {
     mu_Allocator alloc;
     alloc.allocf = allocf ? allocf : muM_default_allocf;
     alloc.ud = ud;
+    alloc.onerr = NULL;
     return alloc;
 }
 
@@ -1752,28 +2600,62 @@ MU_API mu_Source *mu_addsource(mu_Cache **pC, size_t size, mu_Slice name) {
     return (mu_Source *)src;
 }
 
+static unsigned muS_newline(const mu_Source *src, mu_Slice data, int *kind) {
+    int k = src->newline;
+    if (*data.p == '\n' && k != MU_NEWLINE_CR)
+        return *kind = MU_NEWLINE_LF, 1;
+    if (*data.p != '\r' || k == MU_NEWLINE_LF) return 0;
+    if (k != MU_NEWLINE_CR && data.p + 1 < data.e && data.p[1] == '\n')
+        return *kind = MU_NEWLINE_CRLF, 2;
+    return *kind = MU_NEWLINE_CR, 1;
+}
+
 MU_API void mu_updatelines(mu_Source *src, mu_Slice data) {
     mu_Line *next, *current = muA_last(src->lines);
     if (current == NULL) {
         current = muA_push(&src->cache, src->lines);
         memset(current, 0, sizeof(mu_Line));
     }
+    if (src->cr_pending && data.p < data.e) {
+        int crlf = (*data.p == '\n'); /* "\r\n" split between updates */
+        if (crlf) ++current->offset, ++current->byte_offset, ++data.p;
+        if (crlf) current[-1].newline = 2;
+        src->newlines |= 1 << (crlf ? MU_NEWLINE_CRLF : MU_NEWLINE_CR);
+        src->cr_pending = 0;
+    }
     while (data.p < data.e) {
         const char *start = data.p;
-
-        int is_newline = (*data.p == '\n');
-        if (is_newline) {
-            size_t offset = muM_lineend(current) + 1;
-            size_t byte_offset = current->byte_offset + current->byte_len + 1;
-            current->newline = 1;
+        int         kind;
+        unsigned    nl = muS_newline(src, data, &kind);
+        if (nl) {
+            size_t offset = muM_lineend(current) + nl;
+            size_t byte_offset = current->byte_offset + current->byte_len + nl;
+            current->newline = nl, data.p += nl;
             next = muA_push(&src->cache, src->lines);
             memset(next, 0, sizeof(mu_Line));
             next->offset = offset, next->byte_offset = byte_offset;
             current = next;
+            if (kind == MU_NEWLINE_CR && data.p == data.e
+                && src->newline != MU_NEWLINE_CR)
+                src->cr_pending = 1;
+            else src->newlines |= 1 << kind;
+            continue;
         }
         muD_advance(&data);
-        if (!is_newline)
-            current->len += 1, current->byte_len += (unsigned)(data.p - start);
+        current->len += 1, current->byte_len += (unsigned)(data.p - start);
+    }
+}
+
+MU_API mu_Newline mu_newlinekind(const mu_Source *src) {
+    int seen = src->newlines | (src->cr_pending << MU_NEWLINE_CR);
+    if (src->newline != MU_NEWLINE_AUTO && src->newline != MU_NEWLINE_MIXED)
+        return (mu_Newline)src->newline;
+    switch (seen) {
+    case 0:                     return MU_NEWLINE_AUTO;
+    case 1 << MU_NEWLINE_LF:    return MU_NEWLINE_LF;
+    case 1 << MU_NEWLINE_CRLF:  return MU_NEWLINE_CRLF;
+    case 1 << MU_NEWLINE_CR:    return MU_NEWLINE_CR;
+    default:                    return MU_NEWLINE_MIXED;
     }
 }
 
@@ -1989,6 +2871,14 @@ MU_API mu_Chunk mu_default_color(void *ud, mu_ColorKind kind) {
     case MU_COLOR_SKIPPED_MARGIN: return "\x0b\x1b[38;5;240m";
     case MU_COLOR_UNIMPORTANT:    return "\x0b\x1b[38;5;249m";
     case MU_COLOR_NOTE:           return "\x0b\x1b[38;5;115m";
+    case MU_COLOR_EMPHASIS:       return "\x04\x1b[1m";
+    case MU_COLOR_INLINE_CODE:    return "\x0b\x1b[38;5;180m";
+    case MU_COLOR_CONTEXT:        return "\x04\x1b[2m";
+    case MU_COLOR_INFO:           return "\x05\x1b[34m";
+    case MU_COLOR_HINT:           return "\x05\x1b[36m";
+    case MU_COLOR_ADVICE:         return "\x05\x1b[32m";
+    case MU_COLOR_EXPECTED:       return "\x05\x1b[32m";
+    case MU_COLOR_FOUND:          return "\x05\x1b[31m";
     case MU_COLOR_LABEL:          /* FALLTHROUGH */
     default:                      return "\x05\x1b[39m";
     }
@@ -2005,8 +2895,24 @@ static mu_Config muM_default = {
     /* .tab_width          = */ 4,
     /* .limit_width        = */ 0,
     /* .ambiwidth          = */ 1,
+    /* .markup             = */ 0,
+    /* .inline_code        = */ 0,
+    /* .normalize          = */ 0,
+    /* .bidi_isolate       = */ 0,
+    /* .auto_location      = */ 1,
+    /* .dim_unlabeled      = */ 1,
+    /* .caret_only         = */ 0,
+    /* .one_based_columns  = */ 1,
+    /* .auto_priority      = */ 0,
+    /* .max_depth          = */ 0,
+    /* .gutter_bar         = */ 0,
+    /* .collapse_notes     = */ 0,
+    /* .tab_stops          = */ {0},
     /* .label_attach       = */ MU_ATTACH_MIDDLE,
     /* .index_type         = */ MU_INDEX_CHAR,
+    /* .overlap            = */ MU_OVERLAP_MERGE,
+    /* .footer             = */ MU_FOOTER_BORDER,
+    /* .source_order       = */ MU_SOURCE_ORDER_CACHE,
     /* .color              = */ mu_default_color,
     /* .color_ud           = */ NULL,
 #ifdef _WIN32
@@ -2014,6 +2920,9 @@ static mu_Config muM_default = {
 #else
     /* .char_set         = */ &muM_unicode_charset,
 #endif /* _WIN32 */
+    /* .line_no_sep      = */ {NULL, NULL},
+    /* .line_no          = */ NULL,
+    /* .line_no_ud       = */ NULL,
 };
 
 /* clang-format off */
@@ -2022,10 +2931,38 @@ MU_API void mu_initconfig(mu_Config *config)
 
 static mu_Label *muM_checklabel(mu_Report *R)
 { return R ? muA_last(R->labels) : NULL; }
+
+static mu_Label *muM_layoutlabel(mu_Report *R) /* changes the grouping */
+{ return R ? (R->layout_cache = NULL, muA_last(R->labels)) : NULL; }
 /* clang-format on */
 
 /* API */
 
+MU_API unsigned mu_version(void) { return MU_VERSION_NUM; }
+
+MU_API unsigned mu_features(void) {
+    unsigned features = 0;
+#if !MU_NO_STDIO
+    features |= MU_FEATURE_STDIO;
+#endif /* !MU_NO_STDIO */
+#if !MU_NO_DEBUG
+    features |= MU_FEATURE_DEBUG;
+#endif /* !MU_NO_DEBUG */
+    return features;
+}
+
+MU_API size_t mu_structsize(unsigned which) {
+    switch (which) {
+    case MU_STRUCT_CONFIG: return sizeof(mu_Config);
+    case MU_STRUCT_ALLOCATOR: return sizeof(mu_Allocator);
+    case MU_STRUCT_CACHE: return sizeof(mu_Cache);
+    case MU_STRUCT_SOURCE: return sizeof(mu_Source);
+    case MU_STRUCT_LINE: return sizeof(mu_Line);
+    case MU_STRUCT_COLORGEN: return sizeof(mu_ColorGen);
+    default: return 0;
+    }
+}
+
 MU_API int mu_writer(mu_Report *R, mu_Writer *writer, void *ud) {
     if (!R) return MU_ERRPARAM;
     return R->writer = writer, R->writer_ud = ud, MU_OK;
@@ -2033,14 +2970,54 @@ MU_API int mu_writer(mu_Report *R, mu_Writer *writer, void *ud) {
 
 MU_API int mu_render(mu_Report *R, const mu_Cache *cache) {
     unsigned i, size, src_count;
+    jmp_buf  onerr;
+    int      ret;
     if (!R || !cache) return MU_ERRPARAM;
     if (R->writer == NULL) return MU_OK;
     src_count = mu_sourcecount(cache);
     for (i = 0, size = muA_size(R->labels); i < size; ++i) {
         mu_Label *label = &R->labels[i];
         if (label->src_id >= src_count) return MU_ERRSRC;
+        if (label->ref.p && label->ref_label >= size) return MU_ERRPARAM;
     }
-    return muR_cleanup(R), muR_report(R, cache);
+    for (i = 0, size = muA_size(R->contexts); i < size; ++i)
+        if (R->contexts[i].src_id >= src_count) return MU_ERRSRC;
+    for (i = 0, size = muA_size(R->suggestions); i < size; ++i)
+        if (R->suggestions[i].src_id >= src_count) return MU_ERRSRC;
+    muR_cleanup(R);
+    if (!muG_reusable(R, cache)) muG_reset(R);
+    R->alloc.onerr = &onerr;
+    if (setjmp(onerr) == 0)
+        ret = muR_report(R, cache);
+    else
+        ret = MU_ERRMEM, R->cur_src = NULL;
+    R->alloc.onerr = NULL;
+    return ret;
+}
+
+MU_API void mu_relayout(mu_Report *R) {
+    if (R) muG_reset(R);
+}
+
+MU_API mu_LineKind mu_linekind(const mu_Report *R) {
+    return R ? R->cur_kind : MU_LINE_HEADER;
+}
+
+MU_API int mu_msglabel(const mu_Report *R) { return R ? R->cur_msg : -1; }
+
+MU_API int mu_sourceline(const mu_Report *R, mu_Id *src_id,
+                         unsigned *line_no) {
+    const mu_Group *g = R ? R->cur_group : NULL;
+    mu_CLI          li;
+    if (!g
+        || (R->cur_kind != MU_LINE_SOURCE && R->cur_kind != MU_LINE_UNDERLINE
+            && R->cur_kind != MU_LINE_ARROW))
+        return 0;
+    li = muA_isempty(g->labels) ? g->multi_labels : g->labels;
+    if (muA_isempty(li)) return 0;
+    if (src_id) *src_id = li->label->src_id;
+    if (line_no) *line_no = R->cur_line_no;
+    return 1;
 }
 
 MU_API mu_Report *mu_new(mu_Allocf *allocf, void *ud) {
@@ -2050,19 +3027,24 @@ MU_API mu_Report *mu_new(mu_Allocf *allocf, void *ud) {
     memset(R, 0, sizeof(mu_Report));
     R->alloc = alloc;
     R->config = &muM_default;
+    R->cur_msg = -1;
     return R;
 }
 
 MU_API void mu_reset(mu_Report *R) {
     if (!R) return;
     muR_cleanup(R);
+    muG_reset(R);
     R->level = MU_ERROR;
     R->code = mu_lslice(NULL, 0);
     R->custom_level = mu_lslice(NULL, 0);
     R->title = mu_lslice(NULL, 0);
+    R->has_loc = 0;
     muA_reset(R->labels);
+    muA_reset(R->contexts);
     muA_reset(R->helps);
     muA_reset(R->notes);
+    muA_reset(R->suggestions);
 }
 
 MU_API void mu_delete(mu_Report *R) {
@@ -2073,23 +3055,27 @@ MU_API void mu_delete(mu_Report *R) {
     muA_delete(R, R->clusters);
     muA_delete(R, R->ll_cache);
     muA_delete(R, R->width_cache);
+    muA_delete(R, R->fix_marks);
     muA_delete(R, R->labels);
+    muA_delete(R, R->contexts);
     muA_delete(R, R->helps);
     muA_delete(R, R->notes);
+    muA_delete(R, R->suggestions);
     muM_free(&R->alloc, R, sizeof(mu_Report));
 }
 
 MU_API int mu_config(mu_Report *R, const mu_Config *config) {
     unsigned i, size;
     mu_Chunk ellipsis;
-    if (!R || !config) return MU_ERRPARAM;
+    if (!R) return MU_ERRPARAM;
+    if (!config) config = &muM_default;
     R->config = config;
     ellipsis = (*config->char_set)[MU_DRAW_ELLIPSIS];
     R->ellipsis_width =
         muD_strwidth(mu_lslice(ellipsis + 1, *ellipsis), config->ambiwidth);
     for (i = 0, size = muA_size(R->labels); i < size; ++i) {
         mu_Label *label = &R->labels[i];
-        label->width = muD_strwidth(label->message, config->ambiwidth);
+        label->width = muM_msgwidth(config, label->message);
     }
     return MU_OK;
 }
@@ -2110,6 +3096,7 @@ MU_API int mu_code(mu_Report *R, mu_Slice code) {
 MU_API int mu_label(mu_Report *R, size_t start, size_t end, mu_Id src_id) {
     mu_Label *label;
     if (!R) return MU_ERRPARAM;
+    R->layout_cache = NULL; /* pushing may move the grouped labels */
     label = muA_push(R, R->labels);
     memset(label, 0, sizeof(mu_Label));
     label->start_pos = start;
@@ -2121,9 +3108,9 @@ MU_API int mu_label(mu_Report *R, size_t start, size_t end, mu_Id src_id) {
 MU_API int mu_message(mu_Report *R, mu_Slice msg, int width) {
     mu_Label *label = muM_checklabel(R);
     if (!label || !msg.p) return MU_ERRPARAM;
-    label->message = msg;
+    label->message = msg, label->ref.p = NULL;
     if (width > 0) label->width = width;
-    else label->width = muD_strwidth(label->message, R->config->ambiwidth);
+    else label->width = muM_msgwidth(R->config, label->message);
     return MU_OK;
 }
 
@@ -2133,24 +3120,67 @@ MU_API int mu_color(mu_Report *R, mu_Color *color, void *ud) {
     return label->color = color, label->ud = ud, MU_OK;
 }
 
-MU_API int mu_primary(mu_Report *R) {
+MU_API int mu_colorkind(mu_Report *R, mu_ColorKind kind) {
     mu_Label *label = muM_checklabel(R);
     if (!label) return MU_ERRPARAM;
+    return label->kind = kind, MU_OK;
+}
+
+MU_API int mu_primary(mu_Report *R) {
+    mu_Label *label = muM_layoutlabel(R);
+    if (!label) return MU_ERRPARAM;
     return label->primary = 1, MU_OK;
 }
 
 MU_API int mu_order(mu_Report *R, int order) {
-    mu_Label *label = muM_checklabel(R);
+    mu_Label *label = muM_layoutlabel(R);
     if (!label) return MU_ERRPARAM;
     return label->order = order, MU_OK;
 }
 
 MU_API int mu_priority(mu_Report *R, int priority) {
-    mu_Label *label = muM_checklabel(R);
+    mu_Label *label = muM_layoutlabel(R);
     if (!label) return MU_ERRPARAM;
     return label->priority = priority, MU_OK;
 }
 
+MU_API int mu_msgref(mu_Report *R, size_t start, size_t end, mu_Id label_id) {
+    mu_Label *label = muM_checklabel(R);
+    if (!label || !label->message.p || start > end
+        || end > muD_bytelen(label->message))
+        return MU_ERRPARAM;
+    label->ref = mu_lslice(label->message.p + start, end - start);
+    return label->ref_label = label_id, MU_OK;
+}
+
+MU_API int mu_setspan(mu_Report *R, mu_Id label_id, size_t start, size_t end) {
+    mu_Label *label;
+    if (!R || label_id >= muA_size(R->labels)) return MU_ERRPARAM;
+    R->layout_cache = NULL; /* the label may move to another group */
+    label = &R->labels[label_id];
+    label->start_pos = start, label->end_pos = end;
+    return MU_OK;
+}
+
+MU_API int mu_location(mu_Report *R, size_t pos, mu_Id src_id) {
+    if (!R) return MU_ERRPARAM;
+    R->layout_cache = NULL; /* the location is resolved with the groups */
+    R->has_loc = 1, R->loc_src = src_id, R->loc_pos = pos;
+    return MU_OK;
+}
+
+MU_API int mu_context(mu_Report *R, size_t start, size_t end, mu_Id src_id) {
+    mu_Label *ctx;
+    if (!R) return MU_ERRPARAM;
+    R->layout_cache = NULL;
+    ctx = muA_push(R, R->contexts);
+    memset(ctx, 0, sizeof(mu_Label));
+    ctx->start_pos = start;
+    ctx->end_pos = end;
+    ctx->src_id = src_id;
+    return MU_OK;
+}
+
 MU_API int mu_help(mu_Report *R, mu_Slice help_msg) {
     if (!R || !help_msg.p) return MU_ERRPARAM;
     return *muA_push(R, R->helps) = help_msg, MU_OK;
@@ -2161,6 +3191,29 @@ MU_API int mu_note(mu_Report *R, mu_Slice note_msg) {
     return *muA_push(R, R->notes) = note_msg, MU_OK;
 }
 
+MU_API int mu_suggest(mu_Report *R, size_t start, size_t end, mu_Id src_id,
+                      mu_Slice replacement, mu_Slice msg) {
+    mu_Suggestion *s;
+    if (!R || !replacement.p) return MU_ERRPARAM;
+    s = muA_push(R, R->suggestions);
+    s->replacement = replacement, s->message = msg;
+    s->start_pos = start, s->end_pos = end, s->src_id = src_id;
+    s->part = 0;
+    return MU_OK;
+}
+
+MU_API int mu_suggestpart(mu_Report *R, size_t start, size_t end,
+                          mu_Id src_id, mu_Slice replacement) {
+    mu_Suggestion *s;
+    if (!R || !replacement.p || muA_isempty(R->suggestions))
+        return MU_ERRPARAM;
+    s = muA_push(R, R->suggestions);
+    s->replacement = replacement, s->message = mu_lslice(NULL, 0);
+    s->start_pos = start, s->end_pos = end, s->src_id = src_id;
+    s->part = 1;
+    return MU_OK;
+}
+
 MU_NS_END
 
 #endif /* MU_IMPLEMENTATION */
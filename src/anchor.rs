@@ -0,0 +1,202 @@
+//! Positions in the sources of a cache that follow the edits of the text.
+
+use std::io;
+use std::ops::Range;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{AddToCache, Cache, LabelSpan, OwnedSource, char_to_byte, ffi, source_len};
+
+/// A position in a source of a [`Cache`], made by [`Cache::anchor`], that
+/// moves with the edits made by [`Cache::apply_edit`].
+///
+/// Diagnostics kept by an editor across edits hold anchors instead of
+/// positions, so they keep pointing at the same code when text is inserted
+/// or removed before it. Clones share the same position.
+#[derive(Debug, Clone)]
+pub struct Anchor {
+    src_id: ffi::mu_Id,
+    pos: Arc<AtomicUsize>,
+}
+
+impl Anchor {
+    /// The ID of the source of the anchor.
+    #[inline]
+    pub fn src_id(&self) -> usize {
+        self.src_id.0 as usize
+    }
+
+    /// The current position of the anchor, in characters.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.pos.load(Ordering::Relaxed)
+    }
+
+    /// A span from this anchor to `end`, in the source of this anchor.
+    ///
+    /// The span is empty if `end` moved before this anchor.
+    #[inline]
+    pub fn span_to(&self, end: &Anchor) -> LabelSpan {
+        let start = self.offset();
+        LabelSpan {
+            start,
+            end: end.offset().max(start),
+            src_id: self.src_id,
+        }
+    }
+}
+
+impl From<&Anchor> for LabelSpan {
+    /// An empty span at the anchor, drawn as a caret.
+    #[inline]
+    fn from(anchor: &Anchor) -> Self {
+        anchor.span_to(anchor)
+    }
+}
+
+/// The text of an edited source, with a copy of the name of the source it
+/// replaces.
+struct EditedText {
+    text: Vec<u8>,
+    _name: Option<Vec<u8>>,
+}
+
+impl AsRef<[u8]> for EditedText {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.text
+    }
+}
+
+/// The text of `src`, with its newlines.
+fn source_text(src: *mut ffi::mu_Source) -> Vec<u8> {
+    let mut text = Vec::with_capacity(source_len(src));
+    // SAFETY: src comes from Cache::source, which initialized it
+    unsafe {
+        let count = (*src).line_count.map_or(0, |f| f(src));
+        let cr = ffi::mu_newlinekind(src) == ffi::mu_Newline::MU_NEWLINE_CR;
+        for line_no in 0..count {
+            let Some(line) = (*src).get_line_info.map(|f| *f(src, line_no)) else {
+                break;
+            };
+            let data: &[u8] = (*src).get_line.map_or(&[][..], |f| f(src, line_no).into());
+            text.extend_from_slice(data);
+            text.extend_from_slice(match line.newline {
+                0 => b"",
+                1 if cr => b"\r",
+                1 => b"\n",
+                _ => b"\r\n",
+            });
+        }
+    }
+    text
+}
+
+impl<K> Cache<K> {
+    /// An anchor at `offset` in source `src_id`, counted in characters.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Config, Level, Report};
+    /// let mut cache = Cache::new()
+    ///     .with_source(("use std::io;\n", "lib.rs"))
+    ///     .with_source(("fn main() {\n    let x = y;\n}\n", "main.rs"));
+    /// let (start, end) = (cache.anchor(1, 24), cache.anchor(1, 25));
+    ///
+    /// cache.apply_edit(1, 0..0, "// entry point\n")?;
+    /// assert_eq!(start.offset(), 39);
+    ///
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(start.span_to(&end))
+    ///     .render_to_string(&cache)?;
+    /// assert!(output.contains("main.rs:3:13"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn anchor(&mut self, src_id: impl Into<ffi::mu_Id>, offset: usize) -> Anchor {
+        let anchor = Anchor {
+            src_id: src_id.into(),
+            pos: Arc::new(AtomicUsize::new(offset)),
+        };
+        self.anchors.retain(|(_, pos)| pos.strong_count() > 0);
+        (self.anchors).push((anchor.src_id, Arc::downgrade(&anchor.pos)));
+        anchor
+    }
+
+    /// Replace `range` of source `src_id` with `text`, and move the anchors
+    /// of the source to follow the edit.
+    ///
+    /// `range` counts characters, like labels with the default
+    /// [`IndexType`](crate::IndexType). The source is replaced by a copy of
+    /// its text with the edit applied, keeping its name, line number offset
+    /// and newlines. Anchors before or at the start of `range` stay, those
+    /// after it move by the change of length, and those inside it move to
+    /// its start.
+    ///
+    /// # Errors
+    /// Returns an error if `src_id` is out of range or the source fails to
+    /// load.
+    pub fn apply_edit(
+        &mut self,
+        src_id: impl Into<ffi::mu_Id>,
+        range: Range<usize>,
+        text: &str,
+    ) -> io::Result<()> {
+        let src_id = src_id.into();
+        let Some(src) = self.source(src_id) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot load source {}", src_id.0),
+            ));
+        };
+        let mut edited = source_text(src);
+        let end = char_to_byte(src, range.end).min(edited.len());
+        let start = char_to_byte(src, range.start).min(end);
+        edited.splice(start..end, text.bytes());
+
+        // the name may borrow from the old source, like the path of a file
+        // source, so the new source keeps a copy of it
+        // SAFETY: src is a valid source, its name is valid while it lives
+        let name = unsafe { (*src).name };
+        let name = (!name.p.is_null()).then(|| <&[u8]>::from(name).to_vec());
+        let slice = name
+            .as_deref()
+            .map_or_else(ffi::mu_Slice::default, Into::into);
+        let edited = EditedText {
+            text: edited,
+            _name: name,
+        };
+        let mut single = ptr::null_mut();
+        let new = OwnedSource::new(edited).add_to_cache(&mut single);
+        // SAFETY: src and new are valid sources, new belongs to no cache, and
+        // the old source is deleted once no longer in the cache; the name
+        // bytes are on the heap, owned by new
+        unsafe {
+            (*new).name = slice;
+            (*new).line_no_offset = (*src).line_no_offset;
+            (*new).newline = (*src).newline;
+            let old = ffi::mu_setsource(self.inner, src_id.0, new);
+            let old = match old.is_null() {
+                true => std::mem::replace(&mut self.inner, single),
+                false => &mut (*old).cache,
+            };
+            ffi::mu_delcache(old);
+        }
+
+        let len = text.chars().count();
+        for (_, pos) in self.anchors.iter().filter(|(id, _)| *id == src_id) {
+            let Some(pos) = pos.upgrade() else { continue };
+            let p = pos.load(Ordering::Relaxed);
+            if p > range.start {
+                let moved = p.max(range.end) - range.end + range.start;
+                pos.store(
+                    moved + if p >= range.end { len } else { 0 },
+                    Ordering::Relaxed,
+                );
+            }
+        }
+        Ok(())
+    }
+}
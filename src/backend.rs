@@ -0,0 +1,3864 @@
+//! Pure-Rust implementation of the musubi C API (feature `rust-backend`).
+//!
+//! Exports the same `mu_*` symbols as `musubi.h`, so the declarations in
+//! [`crate::ffi`] link against this module instead of the C library and the
+//! safe wrapper works unchanged. The renderer mirrors the C implementation
+//! function by function (`muR_line` becomes [`Render::line`], and so on) and
+//! must produce byte-identical output: the snapshot tests run against both
+//! backends.
+//!
+//! Differences from the C library:
+//! - Report memory comes from the Rust global allocator; a custom `allocf`
+//!   passed to `mu_new` is ignored, so rendering never returns `MU_ERRMEM`.
+//! - Caches and sources honor `allocf` but keep their line and source
+//!   tables in Rust vectors.
+//! - `mu_addfile` and debug tracing are not provided (see `mu_features`).
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::ffi::{c_char, c_int, c_uint, c_void};
+use std::mem::{offset_of, size_of};
+use std::ptr;
+
+use crate::ffi::{
+    self, MU_OK, mu_Allocator, mu_CL, mu_Cache, mu_ColorKind, mu_Config, mu_Draw, mu_Footer, mu_Id,
+    mu_IndexType, mu_LabelAttach, mu_Level, mu_Line, mu_LineKind, mu_Newline, mu_Overlap, mu_Slice,
+    mu_Source, mu_SourceOrder,
+};
+
+include!("unidata_generated.rs");
+
+type RangeTable = (u32, u32, u32);
+type Res = Result<(), c_int>;
+
+const MIN_FILENAME_WIDTH: i32 = 12;
+const MAX_POS: usize = usize::MAX;
+const ALLOC_ALIGN: usize = 16;
+
+fn check(code: c_int) -> Res {
+    if code == MU_OK { Ok(()) } else { Err(code) }
+}
+
+/// View a slice passed through the C API as bytes.
+fn bytes<'a>(s: &mu_Slice) -> &'a [u8] {
+    if s.p.is_null() || s.e <= s.p {
+        return &[];
+    }
+    // SAFETY: the API requires slices to stay valid while the report or
+    // source referencing them is alive, and `p..e` is a single allocation
+    unsafe { std::slice::from_raw_parts(s.p as *const u8, s.e.offset_from(s.p) as usize) }
+}
+
+fn to_slice(s: &[u8]) -> mu_Slice {
+    s.into()
+}
+
+/// View a chunk (a length byte followed by data) as bytes.
+fn chunk<'a>(c: ffi::mu_Chunk) -> &'a [u8] {
+    if c.is_null() {
+        return &[];
+    }
+    // SAFETY: chunks come from character sets and color callbacks, which
+    // must return a length byte followed by at least that many bytes
+    unsafe {
+        let len = *(c as *const u8) as usize;
+        std::slice::from_raw_parts((c as *const u8).add(1), len)
+    }
+}
+
+/* data & slice & unicode utils */
+
+/// Length of the character at `s[p]`; each byte of an invalid sequence is a
+/// character of its own, displayed as U+FFFD.
+fn char_len(s: &[u8], p: usize) -> usize {
+    let n = match s[p] {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => return 1,
+    };
+    match s.get(p..p + n) {
+        Some(c) if std::str::from_utf8(c).is_ok() => n,
+        _ => 1,
+    }
+}
+
+fn advance(s: &[u8], p: usize) -> usize {
+    if p >= s.len() { p } else { p + char_len(s, p) }
+}
+
+fn decode(s: &[u8], p: &mut usize) -> u32 {
+    if *p >= s.len() {
+        return 0;
+    }
+    let n = char_len(s, *p);
+    let mut ch = s[*p] as u32;
+    if n == 1 {
+        *p += 1;
+        return if ch < 0x80 { ch } else { 0xFFFD };
+    }
+    ch &= 0xFF >> (n + 1);
+    for &b in &s[*p + 1..*p + n] {
+        ch = (ch << 6) | (b & 0x3F) as u32;
+    }
+    *p += n;
+    ch
+}
+
+/// Decode the last character of `s[..*e]`, moving `*e` to its start.
+fn rdecode(s: &[u8], e: &mut usize) -> u32 {
+    let end = *e;
+    while *e > 0 && (s[*e - 1] & 0xC0) == 0x80 {
+        *e -= 1;
+    }
+    *e = e.saturating_sub(1);
+    let mut p = *e;
+    decode(&s[..end], &mut p)
+}
+
+fn find(t: &[RangeTable], ch: u32) -> bool {
+    let (mut begin, mut end) = (0, t.len());
+    while begin < end {
+        let mid = (begin + end) / 2;
+        let (first, last, step) = t[mid];
+        if last < ch {
+            begin = mid + 1;
+        } else if first > ch {
+            end = mid;
+        } else {
+            return (ch - first).is_multiple_of(step);
+        }
+    }
+    false
+}
+
+fn char_width(ch: u32, ambiwidth: i32) -> i32 {
+    if find(ZEROWIDTH_TABLE, ch) {
+        0
+    } else if find(DOUBLEWIDTH_TABLE, ch) {
+        2
+    } else if find(AMBIWIDTH_TABLE, ch) {
+        ambiwidth
+    } else {
+        1
+    }
+}
+
+fn strwidth(s: &[u8], ambi: i32) -> i32 {
+    let (mut w, mut p) = (0, 0);
+    while p < s.len() {
+        w += char_width(decode(s, &mut p), ambi);
+    }
+    w
+}
+
+/// Whether `s` contains right-to-left text or explicit directional controls.
+fn hasrtl(s: &[u8]) -> bool {
+    let mut p = 0;
+    while p < s.len() {
+        let ch = decode(s, &mut p);
+        if matches!(ch, 0x590..=0x8FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF)
+            || matches!(ch, 0x1E800..=0x1EFFF | 0x200F | 0x202A..=0x202E | 0x2066..=0x2068)
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Position of the next `**` emphasis marker in `s`.
+fn markup(s: &[u8]) -> Option<usize> {
+    s.windows(2).position(|w| w == b"**")
+}
+
+fn markupwidth(mut s: &[u8], ambi: i32) -> i32 {
+    let mut w = 0;
+    while let Some(p) = markup(s) {
+        w += strwidth(&s[..p], ambi);
+        s = &s[p + 2..];
+    }
+    w + strwidth(s, ambi)
+}
+
+fn msgwidth(cfg: &mu_Config, msg: &[u8]) -> i32 {
+    if cfg.markup != 0 {
+        markupwidth(msg, cfg.ambiwidth)
+    } else {
+        strwidth(msg, cfg.ambiwidth)
+    }
+}
+
+/// Keep the longest suffix of `s` that fits in `width` columns.
+fn keep_suffix(s: &mut &[u8], mut width: i32, ambi: i32) -> i32 {
+    let (mut e, mut prev) = (s.len(), s.len());
+    while e > 0 && width != 0 {
+        let cw = char_width(rdecode(s, &mut e), ambi);
+        if width < cw {
+            break;
+        }
+        if cw != 0 {
+            prev = e;
+        }
+        width -= cw;
+    }
+    *s = &s[prev..];
+    width
+}
+
+/* color generator */
+
+/// # Safety
+/// `cg` must point to a valid color generator.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_initcolorgen(cg: *mut ffi::mu_ColorGen, min_brightness: f32) {
+    // SAFETY: guaranteed by the caller
+    let cg = unsafe { &mut *cg };
+    cg.state = [30000, 15000, 35000];
+    cg.min_brightness = min_brightness;
+}
+
+/// # Safety
+/// `cg` and `out` must be null or valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_gencolor(cg: *mut ffi::mu_ColorGen, out: *mut ffi::mu_ColorCode) {
+    if cg.is_null() || out.is_null() {
+        return;
+    }
+    // SAFETY: both pointers are checked above and valid per the contract
+    let (cg, out) = unsafe { (&mut *cg, &mut *out) };
+    for (i, state) in cg.state.iter_mut().enumerate() {
+        *state = state.wrapping_add((40503 * (i as u32 * 4 + 1130)) as u16);
+    }
+    let mb = cg.min_brightness;
+    let mut code = 16.0f32;
+    code += (cg.state[2] as f32 / 65535.0 * (1.0 - mb) + mb) * 5.0;
+    code += (cg.state[1] as f32 / 65535.0 * (1.0 - mb) + mb) * 30.0;
+    code += (cg.state[0] as f32 / 65535.0 * (1.0 - mb) + mb) * 180.0;
+    let s = format!("\x1b[38;5;{}m", code as i32);
+    out[0] = s.len() as c_char;
+    for (dst, &b) in out[1..].iter_mut().zip(s.as_bytes()) {
+        *dst = b as c_char;
+    }
+    out[s.len() + 1] = 0;
+}
+
+/// # Safety
+/// `ud` must be a chunk produced by [`mu_gencolor`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_fromcolorcode(ud: *mut c_void, k: mu_ColorKind) -> ffi::mu_Chunk {
+    if k == mu_ColorKind::MU_COLOR_RESET {
+        return c"\x04\x1b[0m".as_ptr();
+    }
+    ud as ffi::mu_Chunk
+}
+
+/* report */
+
+struct Label {
+    ud: *mut c_void,      /* user data for the color */
+    color: ffi::mu_Color, /* the color used for this label */
+    message: mu_Slice,    /* the message to display */
+    start_pos: usize,     /* start position in the source */
+    end_pos: usize,       /* end position in the source */
+    src_id: u32,          /* source id this label belongs to */
+    width: i32,           /* display width of the message, must >= 0 */
+    primary: bool,        /* whether this is a primary label */
+    order: i32,           /* order in vertical sorting */
+    priority: i32,        /* priority when merging overlapping labels */
+    /// Byte range of the message referring to another label, and its index.
+    msg_ref: Option<(usize, usize, u32)>,
+    kind: mu_ColorKind, /* color kind drawn with, RESET for MU_COLOR_LABEL */
+}
+
+/// Backing object of `mu_Report` in the Rust backend.
+struct Report {
+    config: *const mu_Config,
+    writer: ffi::mu_Writer,
+    writer_ud: *mut c_void,
+    ellipsis_width: i32,
+    cur_src: Cell<*mut mu_Source>,      /* current source in callback */
+    cur_kind: Cell<mu_LineKind>,        /* kind of the line being written */
+    cur_msg: Cell<c_int>,               /* label of the message being written */
+    cur_line: Cell<Option<(u32, u32)>>, /* source and line being rendered */
+    layout: Cell<Option<Layout>>,       /* groups kept between renders */
+
+    level: mu_Level,
+    code: mu_Slice,
+    custom_level: mu_Slice,
+    title: mu_Slice,
+    labels: Vec<Label>,
+    contexts: Vec<(usize, usize, u32)>, /* start, end and source of context spans */
+    helps: Vec<mu_Slice>,
+    notes: Vec<mu_Slice>,
+    suggestions: Vec<Suggestion>,
+    location: Option<(usize, u32)>, /* position and source shown in headers */
+}
+
+/// A fix suggestion added by `mu_suggest`.
+struct Suggestion {
+    replacement: mu_Slice, /* text replacing the span */
+    message: mu_Slice,     /* help message, or NULL for the default one */
+    start_pos: usize,      /* start position in the source */
+    end_pos: usize,        /* end position in the source */
+    src_id: u32,           /* source id the span belongs to */
+    part: bool,            /* whether it is an edit of the previous one */
+}
+
+/// Reference to a label info in the current group.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct InfoId {
+    multi: bool, /* whether it is in `multi_labels` */
+    idx: u32,
+}
+
+#[derive(Clone, Copy)]
+struct LabelInfo {
+    label: usize,      /* index of the label in the report */
+    multi: bool,       /* whether this label spans multiple lines */
+    start_char: usize, /* start character position of this label */
+    end_char: usize,   /* end character position of this label */
+}
+
+struct Group {
+    src: *mut mu_Source,
+    labels: Vec<LabelInfo>,
+    multi_labels: Vec<LabelInfo>,
+    overflow: Vec<LabelInfo>, /* multi-line labels beyond `max_depth` */
+    primary: Option<usize>,   /* primary label of this group */
+    primary_start: usize,     /* location shown in the reference line */
+    loc_priority: i32,        /* priority of the label giving the location */
+    first_line: u32,
+    last_line: u32,
+    ctx: Option<(u32, u32)>, /* first and last line of the context span */
+    loc_char: Option<usize>, /* position set by `mu_location` */
+}
+
+/// Groups of a report, with what they were made from.
+struct Layout {
+    cache: *const mu_Cache,
+    src_count: u32,
+    index_type: mu_IndexType,
+    context_lines: i32,
+    max_depth: i32,
+    source_order: mu_SourceOrder,
+    groups: Vec<Group>,
+}
+
+impl Layout {
+    fn reusable(
+        &self,
+        r: &Report,
+        cache: *const mu_Cache,
+        src_count: u32,
+        cfg: &mu_Config,
+    ) -> bool {
+        self.cache == cache
+            && self.src_count == src_count
+            && self.index_type == cfg.index_type
+            && self.context_lines == cfg.context_lines
+            && self.max_depth == cfg.max_depth
+            && self.source_order == cfg.source_order
+            /* sources may be replaced */
+            && self.groups.iter().all(|g| {
+                let li = g.labels.first().or(g.multi_labels.first());
+                li.is_none_or(|li| source_at(cache, r.labels[li.label].src_id) == g.src)
+            })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct LineLabel {
+    info: InfoId, /* label info associated with this label */
+    col: u32,     /* column position in this line */
+
+    /* draw_msg is false only if the label is multi and this line is the start */
+    draw_msg: bool,
+    ul_row: u32, /* underline row of the label in MU_OVERLAP_STACK */
+}
+
+struct Cluster {
+    margin_label: Option<LineLabel>,
+    line_labels: Vec<LineLabel>,
+    arrow_len: u32,
+    min_col: u32,
+    start_col: u32,
+    end_col: u32,
+    max_msg_width: i32,
+    ul_rows: u32, /* number of underline rows in MU_OVERLAP_STACK */
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Margin {
+    None,
+    Line,
+    Arrow,
+    Ellipsis,
+}
+
+#[derive(Clone, Copy)]
+struct MarginInfo {
+    t: Margin,
+    is_start: bool,
+    ptr_is_start: bool,
+    first_char: usize,
+    last_char: usize,
+    report: Option<usize>, /* row of the current rendering arrow */
+    hbar: Option<InfoId>,
+    ptr: Option<InfoId>,
+    li: InfoId,
+    corner: Option<InfoId>,
+    vbar: Option<InfoId>,
+}
+
+fn lastchar(li: &LabelInfo) -> usize {
+    li.end_char - (li.end_char > li.start_char) as usize
+}
+
+fn infolen(li: &LabelInfo) -> usize {
+    li.end_char.wrapping_sub(li.start_char)
+}
+
+fn lineend(line: &mu_Line) -> usize {
+    line.offset + line.len as usize
+}
+
+fn is_inline(li: &LabelInfo, line: &mu_Line) -> bool {
+    li.start_char >= line.offset && lastchar(li) < lineend(line) + 1
+}
+
+fn contains(pos: usize, line: &mu_Line) -> bool {
+    pos >= line.offset && pos < lineend(line) + 1
+}
+
+/* source callbacks */
+
+fn src_get_line<'a>(src: *mut mu_Source, line_no: u32) -> &'a [u8] {
+    // SAFETY: sources in a cache stay valid while rendering, and the returned
+    // slice is only used until the next `get_line` call on this source
+    unsafe {
+        let f = (*src).get_line.expect("source without get_line");
+        bytes(&f(src, line_no))
+    }
+}
+
+fn line_or_default(line: mu_CL) -> mu_Line {
+    if line.is_null() {
+        mu_Line {
+            offset: 0,
+            byte_offset: 0,
+            len: 0,
+            byte_len: 0,
+            newline: 0,
+        }
+    } else {
+        // SAFETY: non-null lines returned by a source are readable
+        unsafe { *line }
+    }
+}
+
+fn src_line_info(src: *mut mu_Source, line_no: u32) -> mu_Line {
+    // SAFETY: sources in a cache stay valid while rendering
+    line_or_default(unsafe { ((*src).get_line_info.unwrap())(src, line_no) })
+}
+
+fn src_line_for_chars(src: *mut mu_Source, pos: usize) -> (u32, mu_Line) {
+    let mut line = ptr::null();
+    // SAFETY: sources in a cache stay valid while rendering
+    let no = unsafe { ((*src).line_for_chars.unwrap())(src, pos, &mut line) };
+    (no, line_or_default(line))
+}
+
+fn src_line_for_bytes(src: *mut mu_Source, pos: usize) -> (u32, mu_Line) {
+    let mut line = ptr::null();
+    // SAFETY: sources in a cache stay valid while rendering
+    let no = unsafe { ((*src).line_for_bytes.unwrap())(src, pos, &mut line) };
+    (no, line_or_default(line))
+}
+
+fn src_line_count(src: *mut mu_Source) -> u32 {
+    // SAFETY: sources in a cache stay valid while rendering
+    unsafe { ((*src).line_count.unwrap())(src) }
+}
+
+fn src_line_no_offset(src: *mut mu_Source) -> i32 {
+    // SAFETY: sources in a cache stay valid while rendering
+    unsafe { (*src).line_no_offset }
+}
+
+/// Color and name of the level of `r`, like `muM_level`.
+fn level_info(r: &Report) -> (mu_ColorKind, &[u8]) {
+    match r.level {
+        mu_Level::MU_ERROR => (mu_ColorKind::MU_COLOR_ERROR, b"Error"),
+        mu_Level::MU_WARNING => (mu_ColorKind::MU_COLOR_WARNING, b"Warning"),
+        mu_Level::MU_INFO => (mu_ColorKind::MU_COLOR_INFO, b"Info"),
+        mu_Level::MU_NOTE => (mu_ColorKind::MU_COLOR_NOTE, b"Note"),
+        mu_Level::MU_HINT => (mu_ColorKind::MU_COLOR_HINT, b"Hint"),
+        mu_Level::MU_ADVICE => (mu_ColorKind::MU_COLOR_ADVICE, b"Advice"),
+        _ => (mu_ColorKind::MU_COLOR_KIND, bytes(&r.custom_level)),
+    }
+}
+
+fn lineno_text(cfg: &mu_Config, line_no: u32) -> Vec<u8> {
+    if let Some(f) = cfg.line_no {
+        let mut buf = [0 as c_char; ffi::sizes::LINE_NO];
+        // SAFETY: the formatter is called as in C, with a buffer of LINE_NO bytes
+        let s = unsafe { f(cfg.line_no_ud, line_no, buf.as_mut_ptr(), buf.len()) };
+        return bytes(&s).to_vec();
+    }
+    let digits = line_no.to_string().into_bytes();
+    let sep = &bytes(&cfg.line_no_sep)[..bytes(&cfg.line_no_sep).len().min(8)];
+    let mut out = Vec::with_capacity(digits.len() * (sep.len() + 1));
+    for (i, &d) in digits.iter().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.extend_from_slice(sep);
+        }
+        out.push(d);
+    }
+    out
+}
+
+fn numwidth(cfg: &mu_Config, line_no: u32) -> i32 {
+    if cfg.line_no.is_some() || !bytes(&cfg.line_no_sep).is_empty() {
+        return strwidth(&lineno_text(cfg, line_no), cfg.ambiwidth);
+    }
+    let mut w = 0;
+    let mut limit = 10u64;
+    while line_no as u64 >= limit && w < 9 {
+        w += 1;
+        limit *= 10;
+    }
+    w + 1
+}
+
+/* rendering */
+
+struct Render<'a> {
+    r: &'a Report,
+    cfg: &'a mu_Config,
+    cur_color_label: Option<usize>,
+    cur_color_kind: mu_ColorKind,
+    groups: Vec<Group>,
+    clusters: Vec<Cluster>,
+    ll_cache: Vec<LineLabel>,
+    /* slots past `wc_len` keep widths of earlier lines, as in C */
+    width_cache: Vec<i32>,
+    wc_len: usize,
+    fix_marks: Vec<(i32, i32)>, /* underline ranges of suggestion line */
+    line_no_width: i32,
+    cur_group: usize,
+    cur_cluster: Option<usize>,
+    cur_ul_row: u32, /* stacked underline row being written */
+    cur_line: mu_Line,
+    in_context: bool, /* whether current line is in a context span */
+}
+
+impl<'a> Render<'a> {
+    fn new(r: &'a Report) -> Self {
+        Self {
+            r,
+            // SAFETY: the config set by `mu_config` must outlive the report
+            cfg: unsafe { &*r.config },
+            cur_color_label: None,
+            cur_color_kind: mu_ColorKind::MU_COLOR_RESET,
+            groups: Vec::new(),
+            clusters: Vec::new(),
+            ll_cache: Vec::new(),
+            width_cache: Vec::new(),
+            wc_len: 0,
+            fix_marks: Vec::new(),
+            line_no_width: 0,
+            cur_group: 0,
+            cur_cluster: None,
+            cur_ul_row: u32::MAX,
+            cur_line: line_or_default(ptr::null()),
+            in_context: false,
+        }
+    }
+
+    fn group(&self) -> &Group {
+        &self.groups[self.cur_group]
+    }
+
+    fn info(&self, id: InfoId) -> &LabelInfo {
+        let g = self.group();
+        let list = if id.multi { &g.multi_labels } else { &g.labels };
+        &list[id.idx as usize]
+    }
+
+    fn label(&self, id: InfoId) -> &'a Label {
+        &self.r.labels[self.info(id).label]
+    }
+
+    fn cluster(&self) -> &Cluster {
+        &self.clusters[self.cur_cluster.expect("no current cluster")]
+    }
+
+    fn wc(&self, i: u32) -> i32 {
+        self.width_cache.get(i as usize).copied().unwrap_or(0)
+    }
+
+    /* writer */
+
+    fn write(&mut self, s: &[u8]) -> Res {
+        let writer = self.r.writer.expect("report without writer");
+        // SAFETY: the writer and its userdata are set by `mu_writer`, and
+        // `s` is valid for reads of `s.len()` bytes
+        check(unsafe { writer(self.r.writer_ud, s.as_ptr() as *const c_char, s.len()) })
+    }
+
+    /// Write source text, with U+FFFD in place of each invalid byte.
+    fn text(&mut self, s: &[u8]) -> Res {
+        let (mut p, mut i) = (0, 0);
+        while i < s.len() {
+            let c = i;
+            i = advance(s, i);
+            if i - c > 1 || s[c] < 0x80 {
+                continue;
+            }
+            if p < c {
+                self.write(&s[p..c])?;
+            }
+            self.write("\u{FFFD}".as_bytes())?;
+            p = i;
+        }
+        if p < s.len() {
+            self.write(&s[p..])
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write text with each `oldc` replaced by `newc`, and U+FFFD in place
+    /// of each invalid byte.
+    fn replace(&mut self, mut s: &[u8], oldc: u8, newc: u8) -> Res {
+        while let Some(p) = s.iter().position(|&b| b == oldc) {
+            self.text(&s[..p])?;
+            self.write(&[newc])?;
+            s = &s[p + 1..];
+        }
+        if s.is_empty() { Ok(()) } else { self.text(s) }
+    }
+
+    fn markup(&mut self, s: &[u8], k: mu_ColorKind, code: bool) -> Res {
+        use mu_ColorKind::*;
+        let code = code && self.cfg.inline_code != 0;
+        let em_markup = self.cfg.markup != 0;
+        if !code && !em_markup {
+            return self.write(s);
+        }
+        let (mut em, mut in_code) = (false, false);
+        let (mut start, mut p) = (0, 0);
+        while p < s.len() {
+            if code && s[p] == b'`' {
+                let e = p + in_code as usize; /* closing backtick is part of code */
+                if start < e {
+                    self.write(&s[start..e])?;
+                }
+                in_code = !in_code;
+                start = e;
+            } else if em_markup && !in_code && s[p..].starts_with(b"**") {
+                if start < p {
+                    self.write(&s[start..p])?;
+                }
+                em = !em;
+                p += 1;
+                start = p + 1;
+            } else {
+                p += 1;
+                continue;
+            }
+            self.color(if in_code {
+                MU_COLOR_INLINE_CODE
+            } else if em {
+                MU_COLOR_EMPHASIS
+            } else {
+                k
+            })?;
+            p += 1;
+        }
+        self.write(&s[start..])?;
+        if em || in_code { self.color(k) } else { Ok(()) }
+    }
+
+    fn message(&mut self, label: usize) -> Res {
+        use mu_ColorKind::*;
+        let lb = &self.r.labels[label];
+        let msg = bytes(&lb.message);
+        let Some((start, end, ref_label)) = lb.msg_ref else {
+            return self.markup(msg, MU_COLOR_RESET, false);
+        };
+        self.markup(&msg[..start], MU_COLOR_RESET, false)?;
+        self.use_color(Some(ref_label as usize), MU_COLOR_LABEL)?;
+        self.write(&msg[start..end])?;
+        self.use_color(None, MU_COLOR_RESET)?;
+        self.markup(&msg[end..], MU_COLOR_RESET, false)
+    }
+
+    fn color(&mut self, mut k: mu_ColorKind) -> Res {
+        use mu_ColorKind::{MU_COLOR_LABEL, MU_COLOR_RESET};
+        let (mut color, mut ud) = (self.cfg.color, self.cfg.color_ud);
+        if let Some(l) = self.cur_color_label {
+            let label = &self.r.labels[l];
+            if k == MU_COLOR_LABEL && label.kind != MU_COLOR_RESET {
+                k = label.kind;
+            }
+            if label.color.is_some() {
+                color = label.color;
+                ud = label.ud;
+            }
+        }
+        if let Some(color) = color {
+            if self.cur_color_kind != MU_COLOR_RESET && k != self.cur_color_kind {
+                // SAFETY: color callbacks are set through `mu_config`/`mu_color`
+                let code = unsafe { color(ud, MU_COLOR_RESET) };
+                self.write(chunk(code))?;
+            }
+            if k != MU_COLOR_RESET && k != self.cur_color_kind {
+                // SAFETY: color callbacks are set through `mu_config`/`mu_color`
+                let code = unsafe { color(ud, k) };
+                self.write(chunk(code))?;
+            }
+        }
+        if k == MU_COLOR_RESET {
+            self.cur_color_label = None;
+        }
+        self.cur_color_kind = k;
+        Ok(())
+    }
+
+    fn use_color(&mut self, label: Option<usize>, k: mu_ColorKind) -> Res {
+        if self.cur_color_kind != mu_ColorKind::MU_COLOR_RESET && self.cur_color_label != label {
+            self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        }
+        self.cur_color_label = label;
+        self.color(k)
+    }
+
+    fn use_label_color(&mut self, id: InfoId) -> Res {
+        self.use_color(Some(self.info(id).label), mu_ColorKind::MU_COLOR_LABEL)
+    }
+
+    fn reset_color(&mut self) -> Res {
+        self.use_color(None, mu_ColorKind::MU_COLOR_RESET)
+    }
+
+    fn draw(&mut self, cs: mu_Draw, count: i32) -> Res {
+        // SAFETY: the character set of a config must hold MU_DRAW_COUNT chunks
+        let c = chunk(unsafe { (*self.cfg.char_set)[cs as usize] });
+        if c.len() == 1 {
+            const PADDING_BUF_SIZE: usize = 80;
+            debug_assert!(count <= PADDING_BUF_SIZE as i32);
+            if count > 0 {
+                let pad = [c[0]; PADDING_BUF_SIZE];
+                self.write(&pad[..(count as usize).min(PADDING_BUF_SIZE)])?;
+            }
+        } else {
+            for _ in 0..count {
+                self.write(c)?;
+            }
+        }
+        Ok(())
+    }
+
+    /* misc utils */
+
+    fn col(&self, pos: usize, ll: &LineLabel) -> u32 {
+        if self.info(ll.info).multi {
+            ll.col
+        } else {
+            pos.wrapping_sub(self.cur_line.offset) as u32
+        }
+    }
+
+    fn marginwidth(&self) -> i32 {
+        let size = self.group().multi_labels.len() as i32;
+        (if size != 0 { size + 1 } else { 0 }) * if self.cfg.compact != 0 { 1 } else { 2 }
+    }
+
+    fn calc_linenowidth(&mut self) {
+        let mut max_width = 0;
+        for g in &self.groups {
+            let offset = src_line_no_offset(g.src) as u32;
+            let line_no = g.last_line.wrapping_add(offset).wrapping_add(1);
+            max_width = max_width.max(numwidth(self.cfg, line_no));
+        }
+        self.line_no_width = max_width;
+    }
+
+    /* label cluster */
+
+    fn collect_multi(&mut self) {
+        let line = self.cur_line;
+        for (i, li) in self.groups[self.cur_group].multi_labels.iter().enumerate() {
+            let (col, draw_msg);
+            if contains(li.start_char, &line) {
+                col = (li.start_char - line.offset) as u32;
+                draw_msg = false;
+            } else if contains(lastchar(li), &line) {
+                col = (lastchar(li) - line.offset) as u32;
+                draw_msg = true;
+            } else {
+                continue;
+            }
+            let info = InfoId {
+                multi: true,
+                idx: i as u32,
+            };
+            self.ll_cache.push(LineLabel {
+                info,
+                col,
+                draw_msg,
+                ul_row: 0,
+            });
+        }
+    }
+
+    fn is_hidden(&self, i: usize) -> bool {
+        if self.cfg.overlap != mu_Overlap::MU_OVERLAP_HIDE {
+            return false;
+        }
+        let labels = &self.group().labels;
+        let (li, id) = (
+            &labels[i],
+            InfoId {
+                multi: false,
+                idx: i as u32,
+            },
+        );
+        labels.iter().enumerate().any(|(j, lj)| {
+            if j == i
+                || !is_inline(lj, &self.cur_line)
+                || lj.start_char > lastchar(li)
+                || li.start_char > lastchar(lj)
+            {
+                return false;
+            }
+            let cmp = self
+                .cmp_priority(
+                    InfoId {
+                        multi: false,
+                        idx: j as u32,
+                    },
+                    id,
+                )
+                .then_with(|| infolen(li).cmp(&infolen(lj)));
+            cmp.is_gt() || (cmp.is_eq() && j < i)
+        })
+    }
+
+    fn collect_inline(&mut self) {
+        let line = self.cur_line;
+        for (i, li) in self.groups[self.cur_group].labels.iter().enumerate() {
+            if !is_inline(li, &line) || self.is_hidden(i) {
+                continue;
+            }
+            let pos = match self.cfg.label_attach {
+                mu_LabelAttach::MU_ATTACH_START => li.start_char,
+                mu_LabelAttach::MU_ATTACH_END => lastchar(li),
+                _ => (li.start_char + li.end_char) / 2,
+            };
+            let info = InfoId {
+                multi: false,
+                idx: i as u32,
+            };
+            let col = pos.wrapping_sub(line.offset) as u32;
+            self.ll_cache.push(LineLabel {
+                info,
+                col,
+                draw_msg: true,
+                ul_row: 0,
+            });
+        }
+    }
+
+    fn cmp_ll(&self, l: &LineLabel, r: &LineLabel) -> Ordering {
+        let (li, ri) = (self.info(l.info), self.info(r.info));
+        let (ll, rl) = (&self.r.labels[li.label], &self.r.labels[ri.label]);
+        ll.order
+            .cmp(&rl.order)
+            /* C compares columns by their difference as a signed int */
+            .then((l.col.wrapping_sub(r.col) as i32).cmp(&0))
+            .then(infolen(li).cmp(&infolen(ri)))
+            .then(li.label.cmp(&ri.label))
+    }
+
+    fn fill_llcache(&mut self) -> bool {
+        self.ll_cache.clear();
+        self.collect_multi();
+        self.collect_inline();
+        let mut lls = std::mem::take(&mut self.ll_cache);
+        lls.sort_by(|l, r| self.cmp_ll(l, r));
+        self.ll_cache = lls;
+        !self.ll_cache.is_empty()
+    }
+
+    fn push_width(&mut self, width: i32) {
+        match self.width_cache.get_mut(self.wc_len) {
+            Some(slot) => *slot = width,
+            None => self.width_cache.push(width),
+        }
+        self.wc_len += 1;
+    }
+
+    fn tabwidth(&self, width: i32) -> i32 {
+        let tab_width = self.cfg.tab_width;
+        (self.cfg.tab_stops.iter())
+            .take_while(|&&stop| stop > 0)
+            .find(|&&stop| stop > width)
+            .map_or(tab_width - (width % tab_width), |stop| stop - width)
+    }
+
+    fn fill_widthcache(&mut self, len: u32, data: &[u8]) {
+        let ambi = self.cfg.ambiwidth;
+        let (mut width, mut prev, mut p) = (0, 0, 0);
+        self.wc_len = 0;
+        while p < data.len() {
+            let mut ch = decode(data, &mut p);
+            let chwidth;
+            if ch == '\t' as u32 {
+                chwidth = self.tabwidth(width);
+            } else if prev == 0x200D || (0x1F3FB..=0x1F3FF).contains(&ch) {
+                /* joined by ZWJ, or skin tone modifier */
+                chwidth = 0;
+            } else if (0x1F1E6..=0x1F1FF).contains(&prev) && (0x1F1E6..=0x1F1FF).contains(&ch) {
+                /* regional indicator */
+                width += 1;
+                chwidth = 0;
+                ch = 0;
+            } else {
+                chwidth = char_width(ch, ambi);
+            }
+            self.push_width(width);
+            width += chwidth;
+            prev = ch;
+        }
+        self.push_width(width);
+        while self.wc_len < len as usize + 1 {
+            self.push_width(width);
+        }
+    }
+
+    fn new_cluster(&mut self) -> usize {
+        self.clusters.push(Cluster {
+            margin_label: None,
+            line_labels: Vec::new(),
+            arrow_len: 0,
+            min_col: u32::MAX,
+            start_col: 0,
+            end_col: self.cur_line.len,
+            max_msg_width: 0,
+            ul_rows: 0,
+        });
+        self.clusters.len() - 1
+    }
+
+    fn extrawidth(&self, ll: Option<&LineLabel>) -> i32 {
+        if self.cfg.compact != 0 {
+            return 1;
+        }
+        if let Some(ll) = ll {
+            let info = self.info(ll.info);
+            if info.end_char <= info.start_char {
+                return 1;
+            }
+        }
+        2
+    }
+
+    fn fill_clusters(&mut self) {
+        let extra_arrow_len = self.extrawidth(None);
+        let (mut min_start, mut max_end) = (i32::MAX, i32::MIN);
+        let mut limited = self.cfg.limit_width;
+        let line = self.cur_line;
+
+        self.clusters.clear();
+        let mut c = self.new_cluster();
+        if limited > 0 {
+            limited -= self.line_no_width + 4 + self.marginwidth();
+        }
+        for i in 0..self.ll_cache.len() {
+            let ll = self.ll_cache[i];
+            let info = *self.info(ll.info);
+            let start_col = self.col(info.start_char, &ll);
+            let mut end_col = self.col(info.end_char.wrapping_sub(1), &ll).wrapping_add(1);
+            let label = self.r.labels[info.label].width;
+            let mut is_margin = false;
+            if self.cfg.limit_width > 0 {
+                let cl = &self.clusters[c];
+                let empty = cl.line_labels.is_empty() && cl.margin_label.is_none();
+                min_start = min_start.min(self.wc(start_col));
+                max_end = max_end.max(self.wc(end_col));
+                let msg = if ll.draw_msg && label != 0 {
+                    extra_arrow_len + 1 + label
+                } else {
+                    0
+                };
+                let cur = (max_end - min_start) + msg;
+                if cur > limited && !empty {
+                    min_start = i32::MAX;
+                    max_end = i32::MIN;
+                    c = self.new_cluster();
+                }
+            }
+            let cl = &mut self.clusters[c];
+            if info.multi {
+                if cl.margin_label.is_none() {
+                    cl.margin_label = Some(ll);
+                    is_margin = true;
+                }
+                if (self.cfg.limit_width <= 0 || !is_margin) && ll.draw_msg {
+                    end_col = line.len + (line.newline != 0) as u32;
+                }
+            }
+            if !is_margin {
+                cl.line_labels.push(ll);
+            }
+            cl.arrow_len = cl
+                .arrow_len
+                .max(end_col.wrapping_add(extra_arrow_len as u32));
+            cl.min_col = cl.min_col.min(start_col);
+            cl.max_msg_width = cl.max_msg_width.max(label);
+        }
+    }
+
+    fn is_caret(&self, id: InfoId) -> bool {
+        let info = self.info(id);
+        self.cfg.caret_only != 0 && !info.multi && self.label(id).width != 0 && infolen(info) == 1
+    }
+
+    fn has_underlines(&self) -> bool {
+        if self.cfg.underlines == 0 {
+            return false;
+        }
+        self.cluster().line_labels.iter().any(|ll| {
+            let info = self.info(ll.info);
+            !info.multi && infolen(info) != 0 && !self.is_caret(ll.info)
+        })
+    }
+
+    fn skip_underline(&self, row: usize) -> bool {
+        let c = self.cluster();
+        if row > 0 {
+            return self.is_caret(c.line_labels[row].info);
+        }
+        c.line_labels.iter().all(|ll| self.is_caret(ll.info))
+    }
+
+    fn is_margin_info(&self, id: InfoId) -> bool {
+        self.cur_cluster
+            .and_then(|c| self.clusters[c].margin_label)
+            .is_some_and(|m| m.info == id)
+    }
+
+    fn nextlabel(&self, row: &mut usize) -> Option<LineLabel> {
+        let c = self.cluster();
+        while *row < c.line_labels.len() {
+            let ll = c.line_labels[*row];
+            if self.label(ll.info).width != 0 || (ll.info.multi && !self.is_margin_info(ll.info)) {
+                return Some(ll);
+            }
+            *row += 1;
+        }
+        None
+    }
+
+    fn widthindex(&self, width: i32, mut l: u32, mut u: u32) -> u32 {
+        let delta = self.wc(l);
+        let start = l;
+        while l < u {
+            let m = l + ((u - l) >> 1);
+            if self.wc(m) - delta <= width {
+                l = m + 1;
+            } else {
+                u = m;
+            }
+        }
+        l - (l > start && self.wc(l) - delta > width) as u32
+    }
+
+    fn calc_colrange(&mut self, ci: usize) {
+        let len = self.wc_len as u32 - 1;
+        let c = &self.clusters[ci];
+        let (arrow_len, min_col, max_msg_width) = (c.arrow_len, c.min_col, c.max_msg_width);
+        let line_part = arrow_len.min(len); /* arrow_len in line part */
+        let ellipsis_width = self.r.ellipsis_width;
+
+        let mut balance = 0;
+        let margin = self.marginwidth();
+        let fixed = self.line_no_width + 4 + margin; /* line_no+edge + margin */
+        let limited = self.cfg.limit_width - fixed;
+        let extra = 0.max(arrow_len as i32 - len as i32);
+        let arrow = self.wc(line_part) + extra;
+
+        let edge = arrow + 1 + max_msg_width; /* +1 for space */
+        let line_width = self.wc(len);
+        if edge <= limited && line_width <= limited {
+            return;
+        }
+
+        let (start_col, end_col);
+        let essential = (arrow - self.wc(min_col)) + 1 + max_msg_width;
+        if essential + ellipsis_width >= limited {
+            start_col = min_col;
+            end_col = self.widthindex(1 + max_msg_width - ellipsis_width, line_part, len);
+        } else {
+            let skip = edge - limited + ellipsis_width;
+            if skip <= 0 {
+                start_col = 0;
+                end_col = self.widthindex(limited - arrow - ellipsis_width, line_part, len);
+            } else {
+                if line_width > edge {
+                    let avail = line_width - edge;
+                    let desired = (limited - essential) / 2;
+                    balance = desired + 0.max(desired - avail);
+                }
+                let mut sc = self.widthindex(skip + balance, 0, line_part);
+                if self.wc(sc) < skip + balance {
+                    sc = self.widthindex(skip + balance + 1, 0, line_part);
+                }
+                start_col = sc;
+                end_col =
+                    self.widthindex(1 + max_msg_width + balance - ellipsis_width, line_part, len);
+            }
+        }
+        let c = &mut self.clusters[ci];
+        c.start_col = start_col;
+        c.end_col = end_col;
+    }
+
+    fn cmp_cluster(&self, l: &LineLabel, r: &LineLabel) -> Ordering {
+        let (li, ri) = (self.info(l.info), self.info(r.info));
+        let (ll, rl) = (&self.r.labels[li.label], &self.r.labels[ri.label]);
+        if ll.order != rl.order {
+            return ll.order.cmp(&rl.order);
+        }
+        if li.multi != ri.multi {
+            return li.multi.cmp(&ri.multi);
+        }
+        if !li.multi && l.col != r.col {
+            return r.col.cmp(&l.col);
+        }
+        if l.draw_msg != r.draw_msg {
+            return r.draw_msg.cmp(&l.draw_msg);
+        }
+        let (llen, rlen) = (infolen(li), infolen(ri));
+        if llen == rlen {
+            li.label.cmp(&ri.label)
+        } else if l.draw_msg {
+            llen.cmp(&rlen)
+        } else {
+            rlen.cmp(&llen)
+        }
+    }
+
+    fn sort_labels(&mut self) {
+        let ci = self.cur_cluster.expect("no current cluster");
+        let mut lls = std::mem::take(&mut self.clusters[ci].line_labels);
+        lls.sort_by(|l, r| self.cmp_cluster(l, r));
+        self.clusters[ci].line_labels = lls;
+    }
+
+    fn stack_underlines(&mut self, ci: usize) {
+        self.clusters[ci].ul_rows = 1;
+        if self.cfg.overlap != mu_Overlap::MU_OVERLAP_STACK {
+            return;
+        }
+        let mut lls = std::mem::take(&mut self.clusters[ci].line_labels);
+        let mut rows = 1;
+        let stacked = |ll: &LineLabel| !ll.info.multi && !self.is_caret(ll.info);
+        for i in 0..lls.len() {
+            if !stacked(&lls[i]) {
+                continue;
+            }
+            let li = self.info(lls[i].info);
+            let mut j = 0;
+            while j < i {
+                let lj = self.info(lls[j].info);
+                if lls[j].ul_row == lls[i].ul_row
+                    && stacked(&lls[j])
+                    && lj.start_char <= lastchar(li)
+                    && li.start_char <= lastchar(lj)
+                {
+                    lls[i].ul_row += 1;
+                    j = 0; /* rescan on the next row */
+                } else {
+                    j += 1;
+                }
+            }
+            rows = rows.max(lls[i].ul_row + 1);
+        }
+        self.clusters[ci].ul_rows = rows;
+        self.clusters[ci].line_labels = lls;
+    }
+
+    fn update_margin(&mut self, ci: usize) {
+        let Some(ll) = self.clusters[ci].margin_label else {
+            return;
+        };
+        if !ll.draw_msg || self.label(ll.info).width == 0 {
+            return;
+        }
+        let lls = &self.clusters[ci].line_labels;
+        let mut cur = lls.len();
+        while cur > 0 {
+            if !self.info(lls[cur - 1].info).multi {
+                break;
+            }
+            if self.cmp_cluster(&lls[cur - 1], &ll) == Ordering::Less {
+                break;
+            }
+            cur -= 1;
+        }
+        self.clusters[ci].line_labels.insert(cur, ll);
+    }
+
+    fn cmp_priority(&self, l: InfoId, r: InfoId) -> Ordering {
+        let (l, r) = (self.label(l), self.label(r));
+        l.priority
+            .cmp(&r.priority)
+            .then_with(|| match self.cfg.auto_priority {
+                0 => Ordering::Equal,
+                _ => l.primary.cmp(&r.primary),
+            })
+    }
+
+    fn update_highlight(&self, pos: usize, l: Option<InfoId>, r: InfoId) -> Option<InfoId> {
+        let ri = self.info(r);
+        if pos < ri.start_char || pos >= ri.end_char {
+            return l;
+        }
+        let Some(l) = l else {
+            return Some(r);
+        };
+        match self.cmp_priority(l, r) {
+            Ordering::Less => return Some(r),
+            Ordering::Greater => return Some(l),
+            Ordering::Equal => {}
+        }
+        Some(if infolen(ri) < infolen(self.info(l)) {
+            r
+        } else {
+            l
+        })
+    }
+
+    fn get_highlight(&self, col: u32) -> Option<InfoId> {
+        let c = self.cluster();
+        let pos = self.cur_line.offset + col as usize;
+        let mut r = None;
+        if let Some(m) = c.margin_label {
+            r = self.update_highlight(pos, r, m.info);
+        }
+        for i in 0..self.group().multi_labels.len() {
+            r = self.update_highlight(
+                pos,
+                r,
+                InfoId {
+                    multi: true,
+                    idx: i as u32,
+                },
+            );
+        }
+        for ll in &c.line_labels {
+            r = self.update_highlight(pos, r, ll.info);
+        }
+        r
+    }
+
+    fn get_vbar(&self, row: usize, col: u32) -> Option<InfoId> {
+        let c = self.cluster();
+        c.line_labels.iter().enumerate().find_map(|(i, ll)| {
+            let info = self.info(ll.info);
+            ((self.label(ll.info).width != 0 || info.multi)
+                && !self.is_margin_info(ll.info)
+                && ll.col == col
+                && row <= i
+                && ll.ul_row <= self.cur_ul_row
+                && !self.is_caret(ll.info))
+            .then_some(ll.info)
+        })
+    }
+
+    fn get_underline(&self, col: u32) -> Option<InfoId> {
+        let pos = self.cur_line.offset + col as usize;
+        let (mut r, mut rlen) = (None, 0);
+        for ll in &self.cluster().line_labels {
+            let info = self.info(ll.info);
+            if info.multi
+                || self.is_caret(ll.info)
+                || ll.ul_row != self.cur_ul_row
+                || pos < info.start_char
+                || pos > lastchar(info)
+            {
+                continue;
+            }
+            let len = infolen(info);
+            let cmp = r.map_or(Ordering::Greater, |r| self.cmp_priority(ll.info, r));
+            if cmp.is_gt() || (cmp.is_eq() && len < rlen) {
+                (r, rlen) = (Some(ll.info), len);
+            }
+        }
+        r
+    }
+
+    /* source group */
+
+    fn format_pos(&self, pos: usize) -> String {
+        let g = self.group();
+        debug_assert!(pos != MAX_POS);
+        let (line_no, line) = src_line_for_chars(g.src, pos);
+        let mut col = pos.wrapping_sub(line.offset).wrapping_add(1) as u32;
+        if self.cfg.normalize != 0 {
+            /* combining marks don't take a column */
+            let s = src_get_line(g.src, line_no);
+            let mut p = 0;
+            for _ in 0..col - 1 {
+                if p >= s.len() {
+                    break;
+                }
+                if char_width(decode(s, &mut p), 1) == 0 {
+                    col -= 1;
+                }
+            }
+        }
+        if self.cfg.one_based_columns == 0 {
+            col -= 1;
+        }
+        let offset = src_line_no_offset(g.src) as u32;
+        let line_no = line_no.wrapping_add(offset).wrapping_add(1);
+        let mut loc = format!("{line_no}:{col}");
+        loc.truncate(255);
+        loc
+    }
+
+    fn calc_location(&self) -> String {
+        if let Some(pos) = self.group().loc_char {
+            return self.format_pos(pos);
+        }
+        if self.cfg.auto_location == 0 {
+            return String::new();
+        }
+        self.format_pos(self.group().primary_start)
+    }
+
+    fn trim_name(&self, name: &mut &[u8], loc: &str) -> i32 {
+        let mut ellipsis = 0;
+        if self.cfg.limit_width > 0 {
+            let ambi = self.cfg.ambiwidth;
+            let id = strwidth(name, ambi);
+            let fixed = loc.len() as i32 + self.line_no_width + 8 + !loc.is_empty() as i32;
+            let limited = self.cfg.limit_width;
+            if id + fixed > limited {
+                let avail = (limited - fixed - self.r.ellipsis_width).max(MIN_FILENAME_WIDTH);
+                if avail < id {
+                    ellipsis = keep_suffix(name, avail, ambi) + 1;
+                }
+            }
+        }
+        ellipsis
+    }
+
+    fn calc_charpos(&self, pos: usize, end: bool, l: &mut u32) -> usize {
+        let src = self.group().src;
+        // SAFETY: sources in a cache stay valid while rendering
+        let pos = match unsafe { (*src).map_pos } {
+            // SAFETY: as above
+            Some(map_pos) => unsafe { map_pos(src, pos, self.cfg.index_type, end as c_int) },
+            None => pos,
+        };
+        let r;
+        let line;
+        if self.cfg.index_type == mu_IndexType::MU_INDEX_BYTE {
+            let line_no;
+            (line_no, line) = src_line_for_bytes(src, pos.wrapping_sub(end as usize));
+            let s = src_get_line(src, line_no);
+            let mut count = line.offset;
+            *l = line_no;
+            if pos > line.byte_offset {
+                let s = &s[..s.len().min(pos - line.byte_offset)];
+                let mut p = 0;
+                while p < s.len() {
+                    p = advance(s, p);
+                    count += 1;
+                }
+            }
+            r = count;
+        } else {
+            (*l, line) = src_line_for_chars(src, pos.wrapping_sub(end as usize));
+            r = line.offset.max(pos);
+        }
+        if r > lineend(&line) {
+            /* the newline takes a single column */
+            return lineend(&line) + (end && line.newline != 0) as usize;
+        }
+        r
+    }
+
+    fn init_info(&mut self, label: usize) -> LabelInfo {
+        let lb = &self.r.labels[label];
+        let (mut first_line, mut last_line) = (0, 0);
+        let start_char = self.calc_charpos(lb.start_pos, false, &mut first_line);
+        let end_char = if lb.start_pos >= lb.end_pos {
+            last_line = first_line;
+            start_char
+        } else {
+            self.calc_charpos(lb.end_pos, true, &mut last_line)
+        };
+        let info = LabelInfo {
+            label,
+            multi: first_line != last_line,
+            start_char,
+            end_char,
+        };
+        let priority = lb.priority;
+        let g = &mut self.groups[self.cur_group];
+        if g.labels.is_empty() && g.multi_labels.is_empty() {
+            g.first_line = first_line;
+            g.last_line = last_line;
+        } else {
+            g.first_line = g.first_line.min(first_line);
+            g.last_line = g.last_line.max(last_line);
+        }
+        /* else highest priority, then first label */
+        if g.primary.is_none()
+            && (priority > g.loc_priority
+                || (priority == g.loc_priority && info.start_char < g.primary_start))
+        {
+            g.primary_start = info.start_char;
+            g.loc_priority = priority;
+        }
+        info
+    }
+
+    fn init_src(&self, src: *mut mu_Source) -> Res {
+        // SAFETY: sources in a cache stay valid while rendering
+        unsafe {
+            if (*src).inited == 0
+                && let Some(init) = (*src).init
+            {
+                self.r.cur_src.set(src);
+                check(init(src))?;
+            }
+            self.r.cur_src.set(ptr::null_mut());
+            (*src).inited = 1;
+        }
+        Ok(())
+    }
+
+    fn init_group(&mut self, cache: *const mu_Cache, label: usize) -> Res {
+        let src = source_at(cache, self.r.labels[label].src_id);
+        if self.groups.last().is_none_or(|g| g.src != src) {
+            self.init_src(src)?;
+            self.groups.push(Group {
+                src,
+                labels: Vec::new(),
+                multi_labels: Vec::new(),
+                overflow: Vec::new(),
+                primary: None,
+                primary_start: MAX_POS,
+                loc_priority: i32::MIN,
+                first_line: 0,
+                last_line: 0,
+                ctx: None,
+                loc_char: None,
+            });
+            self.cur_group = self.groups.len() - 1;
+            let src_id = self.r.labels[label].src_id;
+            if let Some((pos, _)) = self.r.location.filter(|&(_, id)| id == src_id) {
+                let mut line = 0;
+                let loc_char = self.calc_charpos(pos, false, &mut line);
+                self.groups[self.cur_group].loc_char = Some(loc_char);
+            }
+        }
+        self.cur_group = self.groups.len() - 1;
+        let li = self.init_info(label);
+        let g = &mut self.groups[self.cur_group];
+        if self.r.labels[label].primary {
+            g.primary = Some(label);
+            g.primary_start = li.start_char;
+        }
+        if li.multi {
+            g.multi_labels.push(li);
+        } else {
+            g.labels.push(li);
+        }
+        Ok(())
+    }
+
+    fn limit_depth(&mut self, gi: usize) {
+        let depth = self.cfg.max_depth as usize;
+        let g = &mut self.groups[gi];
+        let mut kept = 0;
+        for li in std::mem::take(&mut g.multi_labels) {
+            if kept < depth || self.r.labels[li.label].primary {
+                g.multi_labels.push(li);
+                kept += 1;
+            } else {
+                g.overflow.push(li);
+            }
+        }
+        g.overflow
+            .sort_by(|l, r| l.start_char.cmp(&r.start_char).then(l.label.cmp(&r.label)));
+    }
+
+    fn context(&mut self, src: *mut mu_Source, start: usize, end: usize) {
+        for i in 0..self.groups.len() {
+            if self.groups[i].src != src {
+                continue;
+            }
+            self.cur_group = i;
+            let (mut first, mut last) = (0, 0);
+            self.calc_charpos(start, false, &mut first);
+            if start >= end {
+                last = first;
+            } else {
+                self.calc_charpos(end, true, &mut last);
+            }
+            let g = &mut self.groups[i];
+            g.ctx = Some(match g.ctx {
+                Some((f, l)) => (f.min(first), l.max(last)),
+                None => (first, last),
+            });
+            g.first_line = g.first_line.min(first);
+            g.last_line = g.last_line.max(last);
+        }
+    }
+
+    fn make_groups(&mut self, cache: *const mu_Cache) -> Res {
+        let labels = &self.r.labels;
+        let rank = rank_sources(labels, self.cfg.source_order, cache);
+        let mut sorted: Vec<usize> = (0..labels.len()).collect();
+        sorted.sort_by(|&l, &r| {
+            let (ll, rl) = (&labels[l], &labels[r]);
+            ll.order
+                .cmp(&rl.order)
+                .then(rank[l].cmp(&rank[r]))
+                .then(l.cmp(&r))
+        });
+        for label in sorted {
+            self.init_group(cache, label)?;
+        }
+        for &(start, end, src_id) in &self.r.contexts {
+            self.context(source_at(cache, src_id), start, end);
+        }
+        let context = self.cfg.context_lines;
+        for i in 0..self.groups.len() {
+            self.groups[i]
+                .multi_labels
+                .sort_by(|l, r| infolen(r).cmp(&infolen(l)).then(l.label.cmp(&r.label)));
+            if self.cfg.max_depth > 0 {
+                self.limit_depth(i);
+            }
+            let g = &mut self.groups[i];
+            if context > 0 {
+                let ctx = context as u32;
+                let limit = src_line_count(g.src).wrapping_sub(1);
+                g.first_line = g.first_line.saturating_sub(ctx);
+                g.last_line = g.last_line.wrapping_add(ctx).min(limit);
+            }
+        }
+        Ok(())
+    }
+
+    /* margin rendering */
+
+    fn decide_margin(&self, mi: &mut MarginInfo) {
+        let li = *self.info(mi.li);
+        let last_char = lastchar(&li);
+        if last_char >= mi.first_char && li.start_char <= mi.last_char {
+            let margin = self.cur_cluster.and_then(|c| self.clusters[c].margin_label);
+            let is_margin = margin.is_some_and(|m| m.info == mi.li);
+            let is_end = mi.first_char <= last_char && last_char <= mi.last_char;
+            let report = mi
+                .report
+                .and_then(|row| self.cluster().line_labels.get(row));
+            if is_margin && mi.t == Margin::Line {
+                mi.ptr = Some(mi.li);
+                mi.ptr_is_start = mi.is_start;
+            } else if !mi.is_start && (!is_end || mi.t == Margin::Line) {
+                mi.vbar = Some(mi.li);
+            } else if report.is_some_and(|r| r.info == mi.li) {
+                if mi.t != Margin::Arrow && !mi.is_start {
+                    mi.vbar = Some(mi.li);
+                } else if is_margin {
+                    mi.vbar = margin.map(|m| m.info);
+                }
+                if mi.t == Margin::Arrow && (!is_margin || !mi.is_start) {
+                    mi.hbar = Some(mi.li);
+                    mi.corner = Some(mi.li);
+                }
+            } else if let Some(row) = mi.report {
+                let mut info_is_below = false;
+                if !is_margin || !mi.is_start {
+                    for (i, ll) in self.cluster().line_labels.iter().enumerate() {
+                        if ll.info == mi.li {
+                            break;
+                        }
+                        info_is_below = i == row;
+                        if info_is_below {
+                            break;
+                        }
+                    }
+                }
+                if mi.is_start != info_is_below
+                    && (mi.is_start || !is_margin || self.r.labels[li.label].width != 0)
+                {
+                    mi.vbar = Some(mi.li);
+                }
+            }
+        }
+        if mi.hbar.is_none() && mi.ptr.is_some() && mi.t == Margin::Line && Some(mi.li) != mi.ptr {
+            mi.hbar = mi.ptr;
+        }
+    }
+
+    fn draw_margin(&mut self, mi: &MarginInfo) -> Res {
+        use mu_Draw::*;
+        let compact = self.cfg.compact != 0;
+        if let Some(corner) = mi.corner {
+            self.use_label_color(corner)?;
+            self.draw(
+                if mi.is_start {
+                    MU_DRAW_LTOP
+                } else {
+                    MU_DRAW_LBOT
+                },
+                1,
+            )?;
+            if !compact {
+                self.draw(MU_DRAW_HBAR, 1)?;
+            }
+        } else if let (Some(vbar), Some(_)) = (mi.vbar, mi.hbar) {
+            self.use_label_color(vbar)?;
+            self.draw(
+                if self.cfg.cross_gap != 0 {
+                    MU_DRAW_VBAR
+                } else {
+                    MU_DRAW_XBAR
+                },
+                1,
+            )?;
+            if !compact {
+                self.draw(MU_DRAW_HBAR, 1)?;
+            }
+        } else if let Some(ptr) = mi.ptr
+            && mi.t == Margin::Line
+        {
+            let mut draw = MU_DRAW_HBAR;
+            self.use_label_color(ptr)?;
+            if mi.li == ptr {
+                draw = if mi.ptr_is_start {
+                    MU_DRAW_LTOP
+                } else if self.label(mi.li).width == 0 {
+                    MU_DRAW_LBOT
+                } else {
+                    MU_DRAW_LCROSS
+                };
+            }
+            self.draw(draw, 1)?;
+            if !compact {
+                self.draw(MU_DRAW_HBAR, 1)?;
+            }
+        } else if let Some(hbar) = mi.hbar {
+            self.use_label_color(hbar)?;
+            self.draw(MU_DRAW_HBAR, if compact { 1 } else { 2 })?;
+        } else if let Some(vbar) = mi.vbar {
+            let draw = if mi.t == Margin::Ellipsis {
+                MU_DRAW_VBAR_GAP
+            } else {
+                MU_DRAW_VBAR
+            };
+            self.use_label_color(vbar)?;
+            self.draw(draw, 1)?;
+            if !compact {
+                self.draw(MU_DRAW_SPACE, 1)?;
+            }
+        } else {
+            self.reset_color()?;
+            self.draw(MU_DRAW_SPACE, if compact { 1 } else { 2 })?;
+        }
+        Ok(())
+    }
+
+    fn draw_margin_tail(&mut self, mi: &MarginInfo) -> Res {
+        use mu_Draw::*;
+        let compact = self.cfg.compact != 0;
+        if let Some(hbar) = mi.hbar
+            && (mi.t != Margin::Line || mi.hbar != mi.ptr)
+        {
+            self.use_label_color(hbar)?;
+            self.draw(MU_DRAW_HBAR, 1)?;
+            if !compact {
+                self.draw(MU_DRAW_HBAR, 1)?;
+            }
+        } else if let Some(ptr) = mi.ptr
+            && mi.t == Margin::Line
+        {
+            let draw = if self.cfg.multiline_arrows != 0 {
+                MU_DRAW_RARROW
+            } else {
+                MU_DRAW_HBAR
+            };
+            self.use_label_color(ptr)?;
+            self.draw(draw, 1)?;
+            if !compact {
+                self.draw(MU_DRAW_SPACE, 1)?;
+            }
+        } else {
+            self.reset_color()?;
+            self.draw(MU_DRAW_SPACE, if compact { 1 } else { 2 })?;
+        }
+        Ok(())
+    }
+
+    /* rendering */
+
+    fn header(&mut self) -> Res {
+        use mu_Draw::*;
+        let r = self.r;
+        let (level_color, level_slice) = level_info(r);
+        r.cur_kind.set(mu_LineKind::MU_LINE_HEADER);
+        self.color(level_color)?;
+        if !r.code.p.is_null() {
+            self.draw(MU_DRAW_LBOX, 1)?;
+            self.write(bytes(&r.code))?;
+            self.draw(MU_DRAW_RBOX, 1)?;
+            self.draw(MU_DRAW_SPACE, 1)?;
+        }
+        self.write(level_slice)?;
+        self.draw(MU_DRAW_COLON, 1)?;
+        self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        if !r.title.p.is_null() {
+            self.draw(MU_DRAW_SPACE, 1)?;
+            self.markup(bytes(&r.title), mu_ColorKind::MU_COLOR_RESET, true)?;
+        }
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    /// Start a line with `count` columns of `draw`, the first one taken by
+    /// the gutter bar if it is enabled.
+    fn gutter(&mut self, k: mu_ColorKind, draw: mu_Draw, mut count: i32) -> Res {
+        if self.cfg.gutter_bar != 0 {
+            self.color(level_info(self.r).0)?;
+            self.draw(mu_Draw::MU_DRAW_VBAR, 1)?;
+            count -= 1;
+        }
+        self.color(k)?;
+        self.draw(draw, count)
+    }
+
+    fn reference(&mut self, i: usize) -> Res {
+        use mu_Draw::*;
+        let src = self.group().src;
+        // SAFETY: sources in a cache stay valid while rendering
+        let mut name = bytes(unsafe { &(*src).name });
+        let loc = self.calc_location();
+        let ellipsis = self.trim_name(&mut name, &loc);
+        self.r.cur_kind.set(mu_LineKind::MU_LINE_REFERENCE);
+        self.gutter(
+            mu_ColorKind::MU_COLOR_MARGIN,
+            MU_DRAW_SPACE,
+            self.line_no_width + 2,
+        )?;
+        self.draw(if i != 0 { MU_DRAW_VBAR } else { MU_DRAW_LTOP }, 1)?;
+        self.draw(MU_DRAW_HBAR, 1)?;
+        self.draw(MU_DRAW_LBOX, 1)?;
+        self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        self.draw(MU_DRAW_SPACE, 1)?;
+        if ellipsis != 0 {
+            self.draw(MU_DRAW_SPACE, ellipsis - 1)?;
+            self.draw(MU_DRAW_ELLIPSIS, 1)?;
+        }
+        self.replace(name, b'\t', b' ')?;
+        if !loc.is_empty() {
+            self.draw(MU_DRAW_COLON, 1)?;
+            self.write(loc.as_bytes())?;
+        }
+        self.draw(MU_DRAW_SPACE, 1)?;
+        self.color(mu_ColorKind::MU_COLOR_MARGIN)?;
+        self.draw(MU_DRAW_RBOX, 1)?;
+        self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    fn empty_line(&mut self) -> Res {
+        use mu_Draw::*;
+        self.r.cur_kind.set(mu_LineKind::MU_LINE_EMPTY);
+        self.gutter(
+            mu_ColorKind::MU_COLOR_MARGIN,
+            MU_DRAW_SPACE,
+            self.line_no_width + 2,
+        )?;
+        self.draw(MU_DRAW_VBAR, 1)?;
+        let _ = self.color(mu_ColorKind::MU_COLOR_RESET);
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    fn lineno(&mut self, line_no: u32, is_ellipsis: bool) -> Res {
+        use mu_Draw::*;
+        if line_no != 0 && !is_ellipsis {
+            let ln = lineno_text(self.cfg, line_no);
+            let width = strwidth(&ln, self.cfg.ambiwidth);
+            let pad = self.line_no_width - width + 1;
+            self.gutter(mu_ColorKind::MU_COLOR_MARGIN, MU_DRAW_SPACE, pad)?;
+            self.write(&ln)?;
+            self.draw(MU_DRAW_SPACE, 1)?;
+            self.draw(MU_DRAW_LINE_MARGIN, 1)?;
+        } else {
+            let pad = self.line_no_width + 2;
+            self.gutter(mu_ColorKind::MU_COLOR_SKIPPED_MARGIN, MU_DRAW_SPACE, pad)?;
+            self.draw(
+                if is_ellipsis {
+                    MU_DRAW_VBAR_GAP
+                } else {
+                    MU_DRAW_VBAR
+                },
+                1,
+            )?;
+        }
+        self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        if self.cfg.compact != 0 {
+            Ok(())
+        } else {
+            self.draw(MU_DRAW_SPACE, 1)
+        }
+    }
+
+    fn margin(&mut self, report: Option<usize>, t: Margin) -> Res {
+        let size = self.group().multi_labels.len();
+        if size == 0 {
+            return Ok(());
+        }
+        let line = self.cur_line;
+        let c = self.cur_cluster.map(|c| &self.clusters[c]);
+        let mut mi = MarginInfo {
+            t,
+            is_start: false,
+            ptr_is_start: false,
+            first_char: line.offset + c.map_or(0, |c| c.min_col as usize),
+            last_char: line.offset + c.map_or(line.len, |c| c.end_col) as usize,
+            report,
+            hbar: None,
+            ptr: None,
+            li: InfoId {
+                multi: true,
+                idx: 0,
+            },
+            corner: None,
+            vbar: None,
+        };
+        for i in 0..size {
+            mi.li = InfoId {
+                multi: true,
+                idx: i as u32,
+            };
+            mi.corner = None;
+            mi.vbar = None;
+            let start = self.info(mi.li).start_char;
+            mi.is_start = mi.first_char <= start && start <= mi.last_char;
+            self.decide_margin(&mut mi);
+            self.draw_margin(&mi)?;
+        }
+        self.draw_margin_tail(&mi)
+    }
+
+    fn write_colored(&mut self, color: Option<InfoId>, s: &[u8]) -> Res {
+        match color {
+            Some(id) => self.use_label_color(id)?,
+            None if self.in_context => self.use_color(None, mu_ColorKind::MU_COLOR_CONTEXT)?,
+            None if self.cfg.dim_unlabeled != 0 => {
+                self.use_color(None, mu_ColorKind::MU_COLOR_UNIMPORTANT)?
+            }
+            None => self.use_color(None, mu_ColorKind::MU_COLOR_RESET)?,
+        }
+        self.text(s)
+    }
+
+    fn line(&mut self, data: &[u8]) -> Res {
+        let (start_col, end_col) = (self.cluster().start_col, self.cluster().end_col);
+        let mut color = None;
+        let mut p = 0;
+        let mut i = 0;
+        let isolate = self.cfg.bidi_isolate != 0 && hasrtl(data);
+        while i < start_col {
+            p = advance(data, p);
+            i += 1;
+        }
+        if isolate {
+            self.write("\u{2068}".as_bytes())?; /* FSI */
+        }
+        let mut s = p;
+        while i < end_col && p < data.len() {
+            let cp = p;
+            let hl = self.get_highlight(i);
+            p = advance(data, p);
+            let is_tab = data[cp] == b'\t';
+            if hl != color || is_tab {
+                if s < cp {
+                    self.write_colored(color, &data[s..cp])?;
+                }
+                if is_tab {
+                    self.draw(mu_Draw::MU_DRAW_SPACE, self.wc(i + 1) - self.wc(i))?;
+                }
+                color = hl;
+                s = cp + is_tab as usize;
+            }
+            i += 1;
+        }
+        if s < p {
+            self.write_colored(color, &data[s..p])?;
+        }
+        if isolate {
+            self.write("\u{2069}".as_bytes())?; /* PDI */
+        }
+        self.reset_color()
+    }
+
+    fn underline(&mut self, row: usize, draw_underline: bool) -> Res {
+        use mu_Draw::*;
+        let has_ul = draw_underline && self.cfg.underlines != 0;
+        let col_max = self.cur_line.len;
+        let (start_col, arrow_len) = (self.cluster().start_col, self.cluster().arrow_len);
+        self.r.cur_kind.set(mu_LineKind::MU_LINE_UNDERLINE);
+        self.lineno(0, false)?;
+        self.margin(Some(row), Margin::None)?;
+        if start_col > 0 {
+            self.draw(MU_DRAW_SPACE, self.r.ellipsis_width)?;
+        }
+        for col in start_col..arrow_len {
+            let vbar = self.get_vbar(row, col);
+            let underline = if has_ul {
+                self.get_underline(col)
+            } else {
+                None
+            };
+            let w = if col < col_max {
+                self.wc(col + 1) - self.wc(col)
+            } else {
+                1
+            };
+            if let (Some(vbar), Some(_)) = (vbar, underline) {
+                let info = *self.info(vbar);
+                let pos = self.cur_line.offset + col as usize;
+                let draw = if info.multi || infolen(&info) == 0 {
+                    MU_DRAW_SUNDERBAR
+                } else if pos == info.start_char {
+                    MU_DRAW_LUNDERBAR
+                } else if pos == lastchar(&info) {
+                    MU_DRAW_RUNDERBAR
+                } else {
+                    MU_DRAW_MUNDERBAR
+                };
+                self.use_label_color(vbar)?;
+                self.draw(draw, 1)?;
+                self.draw(MU_DRAW_UNDERLINE, w - 1)?;
+            } else if let Some(vbar) = vbar {
+                let uarrow = vbar.multi && draw_underline && self.cfg.multiline_arrows != 0;
+                self.use_label_color(vbar)?;
+                self.draw(if uarrow { MU_DRAW_UARROW } else { MU_DRAW_VBAR }, 1)?;
+                self.draw(MU_DRAW_SPACE, w - 1)?;
+            } else if let Some(underline) = underline {
+                self.use_label_color(underline)?;
+                self.draw(MU_DRAW_UNDERLINE, w)?;
+            } else {
+                self.reset_color()?;
+                self.draw(MU_DRAW_SPACE, w)?;
+            }
+        }
+        self.reset_color()?;
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    fn underlines(&mut self) -> Res {
+        self.cur_ul_row = 0;
+        while self.cur_ul_row < self.cluster().ul_rows {
+            self.underline(0, true)?;
+            self.cur_ul_row += 1;
+        }
+        self.cur_ul_row = u32::MAX;
+        Ok(())
+    }
+
+    fn arrow(&mut self, row: usize, draw_underline: bool) -> Res {
+        use mu_Draw::*;
+        let col_max = self.cur_line.len;
+        let ll = self.cluster().line_labels[row];
+        let (start_col, arrow_len) = (self.cluster().start_col, self.cluster().arrow_len);
+        let is_margin = self.is_margin_info(ll.info);
+        let lw = self.label(ll.info).width;
+        let is_multi = ll.info.multi;
+        self.r.cur_kind.set(mu_LineKind::MU_LINE_ARROW);
+        self.lineno(0, false)?;
+        self.margin(Some(row), Margin::Arrow)?;
+        if start_col > 0 {
+            let e = is_margin || !ll.draw_msg;
+            self.color(if e {
+                mu_ColorKind::MU_COLOR_UNIMPORTANT
+            } else {
+                mu_ColorKind::MU_COLOR_RESET
+            })?;
+            self.draw(
+                if e { MU_DRAW_HBAR } else { MU_DRAW_SPACE },
+                self.r.ellipsis_width,
+            )?;
+        }
+        for col in start_col..arrow_len {
+            let w = if col < col_max {
+                self.wc(col + 1) - self.wc(col)
+            } else {
+                1
+            };
+            let after_col = ll.draw_msg && lw != 0 && col > ll.col;
+            let is_hbar = (col > ll.col) != is_multi || after_col;
+            let vbar = self.get_vbar(row, col);
+            if col == ll.col && !is_margin {
+                let draw = if self.is_caret(ll.info) {
+                    MU_DRAW_UARROW
+                } else if !is_multi {
+                    MU_DRAW_LBOT
+                } else if ll.draw_msg {
+                    if lw != 0 { MU_DRAW_MBOT } else { MU_DRAW_RBOT }
+                } else {
+                    MU_DRAW_RBOT
+                };
+                self.use_label_color(ll.info)?;
+                self.draw(draw, 1)?;
+                if ll.draw_msg && lw != 0 && self.cfg.align_messages == 0 && !is_multi {
+                    self.draw(MU_DRAW_HBAR, self.extrawidth(Some(&ll)))?;
+                    break;
+                }
+                self.draw(MU_DRAW_HBAR, w - 1)?;
+            } else if let Some(vbar) = vbar
+                && col != ll.col
+            {
+                let (mut draw, mut pad) = (MU_DRAW_VBAR, MU_DRAW_SPACE);
+                if is_hbar {
+                    draw = if self.cfg.cross_gap != 0 {
+                        MU_DRAW_VBAR
+                    } else {
+                        MU_DRAW_XBAR
+                    };
+                    pad = MU_DRAW_HBAR;
+                } else if vbar.multi && draw_underline {
+                    draw = MU_DRAW_UARROW;
+                }
+                self.use_label_color(vbar)?;
+                self.draw(draw, 1)?;
+                self.draw(pad, w - 1)?;
+            } else if is_hbar {
+                self.use_label_color(ll.info)?;
+                self.draw(MU_DRAW_HBAR, w)?;
+            } else {
+                self.reset_color()?;
+                self.draw(MU_DRAW_SPACE, w)?;
+            }
+        }
+        self.reset_color()?;
+        if ll.draw_msg {
+            self.draw(MU_DRAW_SPACE, 1)?;
+            let label = self.info(ll.info).label;
+            self.r.cur_msg.set(label as c_int);
+            self.message(label)?;
+            self.r.cur_msg.set(-1);
+        }
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    fn singlecluster(&mut self, line_no: u32, data: &[u8]) -> Res {
+        use mu_Draw::*;
+        let mut row = 0;
+        let offset = src_line_no_offset(self.group().src) as u32;
+        self.r.cur_kind.set(mu_LineKind::MU_LINE_SOURCE);
+        self.lineno(line_no.wrapping_add(offset).wrapping_add(1), false)?;
+        self.margin(None, Margin::Line)?;
+        if self.cluster().start_col > 0 {
+            self.color(mu_ColorKind::MU_COLOR_UNIMPORTANT)?;
+            self.draw(MU_DRAW_ELLIPSIS, 1)?;
+            self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        }
+        self.line(data)?;
+        if self.cluster().end_col < self.cur_line.len {
+            self.color(mu_ColorKind::MU_COLOR_UNIMPORTANT)?;
+            self.draw(MU_DRAW_ELLIPSIS, 1)?;
+            self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        }
+        self.draw(MU_DRAW_NEWLINE, 1)?;
+        if self.cfg.compact != 0 {
+            if self.has_underlines() {
+                self.underlines()?;
+            } else if self.nextlabel(&mut row).is_some() {
+                self.arrow(row, true)?;
+                row += 1;
+            }
+            while self.nextlabel(&mut row).is_some() {
+                self.arrow(row, false)?;
+                row += 1;
+            }
+        } else if !self.cluster().line_labels.is_empty() {
+            if !self.skip_underline(0) {
+                self.underlines()?;
+            }
+            if self.nextlabel(&mut row).is_some() {
+                self.arrow(row, false)?;
+                row += 1;
+            }
+            while self.nextlabel(&mut row).is_some() {
+                if !self.skip_underline(row) {
+                    self.underline(row, false)?;
+                }
+                self.arrow(row, false)?;
+                row += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn clusters(&mut self, line_no: u32) -> Res {
+        let data = src_get_line(self.group().src, line_no);
+        self.fill_widthcache(self.cur_line.len, data);
+        self.fill_clusters();
+        for i in 0..self.clusters.len() {
+            self.cur_cluster = Some(i);
+            if self.cfg.limit_width > 0 {
+                self.calc_colrange(i);
+            }
+            if self.cfg.minimise_crossings != 0 {
+                self.sort_labels();
+            }
+            self.stack_underlines(i);
+            self.update_margin(i);
+            self.singlecluster(line_no, data)?;
+        }
+        Ok(())
+    }
+
+    fn skippedline(&mut self, line_no: u32) -> Res {
+        let line = self.cur_line;
+        let g = self.group();
+        let in_labels = g
+            .multi_labels
+            .iter()
+            .any(|li| li.start_char <= line.offset && line.offset <= lastchar(li));
+        if in_labels {
+            self.r.cur_kind.set(mu_LineKind::MU_LINE_ELLIPSIS);
+            self.lineno(0, true)?;
+            self.cur_cluster = None;
+            self.margin(None, Margin::Ellipsis)?;
+            self.draw(mu_Draw::MU_DRAW_NEWLINE, 1)?;
+        } else if self.cfg.compact == 0 && line_no > g.first_line {
+            self.r.cur_kind.set(mu_LineKind::MU_LINE_EMPTY);
+            self.lineno(0, false)?;
+            self.draw(mu_Draw::MU_DRAW_NEWLINE, 1)?;
+        }
+        Ok(())
+    }
+
+    fn lines(&mut self) -> Res {
+        let (src, src_id, first_line, last_line, ctx) = {
+            let g = self.group();
+            let li = g.labels.first().or(g.multi_labels.first());
+            let src_id = li.map(|li| self.r.labels[li.label].src_id);
+            (g.src, src_id, g.first_line, g.last_line, g.ctx)
+        };
+        let mut context = self.cfg.context_lines;
+        let mut rendered_line = first_line;
+        let mut line_no = first_line;
+        while line_no <= last_line {
+            self.cur_line = src_line_info(src, line_no);
+            self.r.cur_line.set(src_id.map(|id| (id, line_no)));
+            self.in_context = ctx.is_some_and(|(f, l)| f <= line_no && line_no <= l);
+            if self.fill_llcache() {
+                if context < 0 && rendered_line + 1 < line_no {
+                    context = self.cfg.context_lines;
+                    let back = context.min(line_no.wrapping_sub(rendered_line) as i32) + 1;
+                    line_no = line_no.wrapping_sub(back as u32).wrapping_add(1);
+                    continue; /* rollback to show leading context */
+                }
+                self.clusters(line_no)?;
+                context = self.cfg.context_lines;
+                rendered_line = line_no;
+            } else if context == 0 && !self.in_context {
+                self.skippedline(line_no)?;
+                context = -1; /* makes loop may rollback when new label found */
+            } else if context > 0 || self.in_context {
+                self.clusters.clear();
+                let c = self.new_cluster();
+                let data = src_get_line(src, line_no);
+                self.cur_cluster = Some(c);
+                if self.cfg.limit_width > 0 {
+                    self.fill_widthcache(self.cur_line.len, data);
+                    self.clusters[c].min_col = 0;
+                    self.calc_colrange(c);
+                }
+                self.singlecluster(line_no, data)?;
+                if !self.in_context {
+                    context -= 1; /* decrement context line */
+                }
+                rendered_line = line_no;
+            }
+            line_no += 1;
+        }
+        Ok(())
+    }
+
+    fn overflow(&mut self) -> Res {
+        use mu_Draw::*;
+        let size = self.group().overflow.len();
+        if size > 0 && self.cfg.compact == 0 {
+            self.r.cur_kind.set(mu_LineKind::MU_LINE_EMPTY);
+            self.lineno(0, false)?;
+            self.draw(MU_DRAW_NEWLINE, 1)?;
+        }
+        for i in 0..size {
+            let li = self.group().overflow[i];
+            self.r.cur_kind.set(mu_LineKind::MU_LINE_NOTE);
+            self.lineno(0, false)?;
+            self.use_color(Some(li.label), mu_ColorKind::MU_COLOR_LABEL)?;
+            self.draw(MU_DRAW_RARROW, 1)?;
+            self.draw(MU_DRAW_SPACE, 1)?;
+            let loc = self.format_pos(li.start_char);
+            self.write(loc.as_bytes())?;
+            self.reset_color()?;
+            if self.r.labels[li.label].width != 0 {
+                self.draw(MU_DRAW_COLON, 1)?;
+                self.draw(MU_DRAW_SPACE, 1)?;
+                self.r.cur_msg.set(li.label as c_int);
+                self.message(li.label)?;
+                self.r.cur_msg.set(-1);
+            }
+            self.draw(MU_DRAW_NEWLINE, 1)?;
+        }
+        Ok(())
+    }
+
+    /// The line in place of the `rest` lines of a collapsed message.
+    fn collapsed(&mut self, t: &str, rest: usize) -> Res {
+        use mu_Draw::*;
+        self.lineno(0, false)?;
+        self.color(mu_ColorKind::MU_COLOR_NOTE)?;
+        self.draw(MU_DRAW_SPACE, t.len() as i32 + 2)?;
+        self.draw(MU_DRAW_ELLIPSIS, 1)?;
+        let s = if rest > 1 { "s" } else { "" };
+        self.write(format!(" {rest} more line{s}").as_bytes())?;
+        self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    fn footer_msg(&mut self, t: &str, m: &[u8]) -> Res {
+        use mu_Draw::*;
+        let limit = self.cfg.collapse_notes.max(0) as usize;
+        let count = m.split(|&b| b == b'\n').count();
+        for (n, part) in m.split(|&b| b == b'\n').enumerate() {
+            if limit > 0 && n == limit {
+                return self.collapsed(t, count - n);
+            }
+            self.lineno(0, false)?;
+            self.color(mu_ColorKind::MU_COLOR_NOTE)?;
+            if n > 0 {
+                self.draw(MU_DRAW_SPACE, t.len() as i32 + 2)?;
+            } else {
+                self.write(t.as_bytes())?;
+                self.draw(MU_DRAW_COLON, 1)?;
+                self.draw(MU_DRAW_SPACE, 1)?;
+            }
+            self.markup(part, mu_ColorKind::MU_COLOR_NOTE, true)?;
+            self.color(mu_ColorKind::MU_COLOR_RESET)?;
+            self.draw(MU_DRAW_NEWLINE, 1)?;
+        }
+        Ok(())
+    }
+
+    fn fixcount(&self) -> usize {
+        self.r.suggestions.iter().filter(|s| !s.part).count()
+    }
+
+    fn help_or_note(&mut self, is_help: bool, msgs: &[mu_Slice]) -> Res {
+        use mu_Draw::*;
+        let st = if is_help { "Help" } else { "Note" };
+        let size = msgs.len();
+        let total = size + if is_help { self.fixcount() } else { 0 }; /* numbered together */
+        for (i, msg) in msgs.iter().enumerate() {
+            let t = if total > 1 {
+                format!("{st} {}", i + 1)
+            } else {
+                st.to_string()
+            };
+            if self.cfg.compact == 0 {
+                self.r.cur_kind.set(mu_LineKind::MU_LINE_EMPTY);
+                self.lineno(0, false)?;
+                self.draw(MU_DRAW_NEWLINE, 1)?;
+            }
+            let kind = if is_help {
+                mu_LineKind::MU_LINE_HELP
+            } else {
+                mu_LineKind::MU_LINE_NOTE
+            };
+            self.r.cur_kind.set(kind);
+            self.footer_msg(&t, bytes(msg))?;
+        }
+        Ok(())
+    }
+
+    /// Line of `pos` in `src`, its data, and the byte offset of `pos` in it.
+    fn fixpos(&self, src: *mut mu_Source, pos: usize) -> (u32, &'a [u8], usize) {
+        if self.cfg.index_type == mu_IndexType::MU_INDEX_BYTE {
+            let (line_no, line) = src_line_for_bytes(src, pos);
+            let data = src_get_line(src, line_no);
+            (
+                line_no,
+                data,
+                pos.saturating_sub(line.byte_offset).min(data.len()),
+            )
+        } else {
+            let (line_no, line) = src_line_for_chars(src, pos);
+            let data = src_get_line(src, line_no);
+            let mut p = 0;
+            for _ in line.offset..pos {
+                if p >= data.len() {
+                    break;
+                }
+                p = advance(data, p);
+            }
+            (line_no, data, p)
+        }
+    }
+
+    fn fixwidth(&mut self, cache: *const mu_Cache) -> Res {
+        let mut added = 0u32;
+        for s in &self.r.suggestions {
+            let src = source_at(cache, s.src_id);
+            self.init_src(src)?;
+            let (line_no, _, _) = self.fixpos(src, s.start_pos);
+            if !s.part {
+                added = 0; /* earlier edits may share the line */
+            }
+            /* lines added by the replacement */
+            added += bytes(&s.replacement)
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count() as u32;
+            let offset = src_line_no_offset(src) as u32;
+            let line_no = line_no
+                .wrapping_add(added)
+                .wrapping_add(offset)
+                .wrapping_add(1);
+            self.line_no_width = self.line_no_width.max(numwidth(self.cfg, line_no));
+        }
+        Ok(())
+    }
+
+    fn fixtext(&mut self, s: &[u8], col: &mut i32) -> Res {
+        use mu_Draw::*;
+        let (mut p, mut i) = (0, 0);
+        while i < s.len() {
+            let c = i;
+            let ch = decode(s, &mut i);
+            if ch != '\t' as u32 {
+                *col += char_width(ch, self.cfg.ambiwidth);
+                continue;
+            }
+            if p < c {
+                self.text(&s[p..c])?;
+            }
+            let w = self.tabwidth(*col);
+            self.draw(MU_DRAW_SPACE, w)?;
+            *col += w;
+            p = i;
+        }
+        if p < s.len() {
+            self.text(&s[p..])
+        } else {
+            Ok(())
+        }
+    }
+
+    fn fixmark(&mut self, from: i32, to: i32) {
+        if from < to {
+            self.fix_marks.push((from, to));
+        }
+    }
+
+    fn fixmarks(&mut self) -> Res {
+        use mu_Draw::*;
+        if self.fix_marks.is_empty() {
+            return Ok(());
+        }
+        self.lineno(0, false)?;
+        let mut col = 0;
+        for (from, to) in std::mem::take(&mut self.fix_marks) {
+            let mut pad = from - col;
+            while pad > 0 {
+                /* draw pads at most 80 */
+                self.draw(MU_DRAW_SPACE, pad.min(80))?;
+                pad -= pad.min(80);
+            }
+            self.color(mu_ColorKind::MU_COLOR_NOTE)?;
+            self.draw(MU_DRAW_UNDERLINE, to - from)?;
+            self.color(mu_ColorKind::MU_COLOR_RESET)?;
+            col = to;
+        }
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    fn fixtitle(&mut self, t: &str, s: &Suggestion, multi: bool) -> Res {
+        use mu_Draw::*;
+        if !s.message.p.is_null() {
+            return self.footer_msg(t, bytes(&s.message));
+        }
+        self.lineno(0, false)?;
+        self.color(mu_ColorKind::MU_COLOR_NOTE)?;
+        self.write(t.as_bytes())?;
+        self.draw(MU_DRAW_COLON, 1)?;
+        self.draw(MU_DRAW_SPACE, 1)?;
+        let replacement = bytes(&s.replacement);
+        if multi {
+            self.write(b"apply these changes")?;
+        } else if replacement.is_empty() {
+            self.write(b"remove this")?;
+        } else {
+            self.write(b"replace with ")?;
+            if self.cfg.inline_code != 0 {
+                self.color(mu_ColorKind::MU_COLOR_INLINE_CODE)?;
+            }
+            self.write(b"`")?;
+            self.replace(replacement, b'\n', b' ')?;
+            self.write(b"`")?;
+        }
+        self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    fn fixedit(&mut self, s: &Suggestion, offset: u32, line_no: &mut u32, col: &mut i32) -> Res {
+        use mu_Draw::*;
+        let mut parts = bytes(&s.replacement).split(|&b| b == b'\n').peekable();
+        while let Some(part) = parts.next() {
+            let from = *col;
+            self.fixtext(part, col)?;
+            self.fixmark(from, *col);
+            if parts.peek().is_none() {
+                break;
+            }
+            self.draw(MU_DRAW_NEWLINE, 1)?;
+            self.fixmarks()?;
+            *col = 0;
+            *line_no += 1;
+            self.lineno(line_no.wrapping_add(offset).wrapping_add(1), false)?;
+        }
+        Ok(())
+    }
+
+    fn suggestion(&mut self, cache: *const mu_Cache, mut i: usize, n: usize) -> Res {
+        use mu_Draw::*;
+        let suggestions = &self.r.suggestions;
+        let t = if self.r.helps.len() + self.fixcount() > 1 {
+            format!("Help {n}")
+        } else {
+            "Help".to_string()
+        };
+        if self.cfg.compact == 0 {
+            self.r.cur_kind.set(mu_LineKind::MU_LINE_EMPTY);
+            self.lineno(0, false)?;
+            self.draw(MU_DRAW_NEWLINE, 1)?;
+        }
+        self.r.cur_kind.set(mu_LineKind::MU_LINE_HELP);
+        let multi = suggestions.get(i + 1).is_some_and(|s| s.part);
+        self.fixtitle(&t, &suggestions[i], multi)?;
+        self.fix_marks.clear();
+        loop {
+            /* one block of lines per run of edits sharing a line */
+            let mut s = &suggestions[i];
+            let src = source_at(cache, s.src_id);
+            let offset = src_line_no_offset(src) as u32;
+            let (mut line_no, mut data, mut p) = self.fixpos(src, s.start_pos);
+            self.lineno(line_no.wrapping_add(offset).wrapping_add(1), false)?;
+            let mut col = 0;
+            self.fixtext(&data[..p], &mut col)?;
+            loop {
+                let end = s.start_pos.max(s.end_pos);
+                self.fixedit(s, offset, &mut line_no, &mut col)?;
+                /* the tail may come from another line, fetched only now */
+                let end_no;
+                (end_no, data, p) = self.fixpos(src, end);
+                i += 1;
+                let Some(next) = suggestions.get(i) else {
+                    break;
+                };
+                if !next.part || next.src_id != s.src_id || next.start_pos < end {
+                    break;
+                }
+                let (next_no, next_data, q) = self.fixpos(src, next.start_pos);
+                if next_no != end_no {
+                    (_, data, p) = self.fixpos(src, end);
+                    break;
+                }
+                /* fetching may reuse the buffer */
+                data = next_data;
+                s = next;
+                self.fixtext(&data[p..q], &mut col)?;
+            }
+            self.fixtext(&data[p..], &mut col)?;
+            self.draw(MU_DRAW_NEWLINE, 1)?;
+            self.fixmarks()?;
+            if suggestions.get(i).is_none_or(|s| !s.part) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn footer(&mut self, cache: *const mu_Cache) -> Res {
+        use mu_Draw::*;
+        let size = self.r.suggestions.len();
+        let mut n = self.r.helps.len();
+        self.help_or_note(true, &self.r.helps)?;
+        for i in 0..size {
+            if !self.r.suggestions[i].part {
+                n += 1;
+                self.suggestion(cache, i, n)?;
+            }
+        }
+        self.help_or_note(false, &self.r.notes)?;
+        if (self.groups.is_empty() && size == 0)
+            || self.cfg.compact != 0
+            || self.cfg.footer == mu_Footer::MU_FOOTER_OMIT
+        {
+            return Ok(());
+        }
+        self.r.cur_kind.set(mu_LineKind::MU_LINE_FOOTER);
+        if self.cfg.footer == mu_Footer::MU_FOOTER_BLANK {
+            return self.draw(MU_DRAW_NEWLINE, 1);
+        }
+        self.gutter(
+            mu_ColorKind::MU_COLOR_MARGIN,
+            MU_DRAW_HBAR,
+            self.line_no_width + 2,
+        )?;
+        self.draw(MU_DRAW_RBOT, 1)?;
+        if self.cfg.footer == mu_Footer::MU_FOOTER_LABELED {
+            self.draw(MU_DRAW_HBAR, 1)?;
+            self.draw(MU_DRAW_LBOX, 1)?;
+            self.color(mu_ColorKind::MU_COLOR_RESET)?;
+            self.write(b" end ")?;
+            if !self.r.code.p.is_null() {
+                self.write(bytes(&self.r.code))?;
+                self.draw(MU_DRAW_SPACE, 1)?;
+            }
+            self.color(mu_ColorKind::MU_COLOR_MARGIN)?;
+            self.draw(MU_DRAW_RBOX, 1)?;
+        }
+        self.color(mu_ColorKind::MU_COLOR_RESET)?;
+        self.draw(MU_DRAW_NEWLINE, 1)
+    }
+
+    fn report(&mut self, cache: *const mu_Cache) -> Res {
+        self.calc_linenowidth();
+        self.fixwidth(cache)?;
+        self.header()?;
+        for i in 0..self.groups.len() {
+            if i > 0 && self.cfg.compact == 0 {
+                self.empty_line()?;
+            }
+            self.cur_group = i;
+            self.reference(i)?;
+            if self.cfg.compact == 0 {
+                self.empty_line()?;
+            }
+            self.lines()?;
+            self.overflow()?;
+        }
+        self.footer(cache)
+    }
+}
+
+/* source */
+
+/// Line table of a source, stored in `mu_Source::lines`.
+type Lines = Vec<mu_Line>;
+/// Source table of a multi-source cache, stored in `mu_Cache::sources`.
+type Sources = Vec<*mut mu_Source>;
+
+unsafe extern "C" fn default_allocf(
+    _ud: *mut c_void,
+    p: *mut c_void,
+    nsize: usize,
+    osize: usize,
+) -> *mut c_void {
+    use std::alloc::{Layout, alloc, dealloc, realloc};
+    // SAFETY: blocks are always freed and resized with the size they were
+    // allocated with, which every caller in this module passes as `osize`
+    unsafe {
+        let old = Layout::from_size_align_unchecked(osize, ALLOC_ALIGN);
+        if nsize == 0 {
+            if !p.is_null() {
+                dealloc(p as *mut u8, old);
+            }
+            ptr::null_mut()
+        } else if p.is_null() {
+            alloc(Layout::from_size_align_unchecked(nsize, ALLOC_ALIGN)) as *mut c_void
+        } else {
+            realloc(p as *mut u8, old, nsize) as *mut c_void
+        }
+    }
+}
+
+fn initalloc(allocf: ffi::mu_Allocf, ud: *mut c_void) -> mu_Allocator {
+    mu_Allocator {
+        ud,
+        allocf: allocf.or(Some(default_allocf)),
+        onerr: ptr::null_mut(),
+    }
+}
+
+fn alloc_zeroed(alloc: &mu_Allocator, size: usize) -> *mut u8 {
+    // SAFETY: allocf is either the default allocator or a user-provided one
+    // following the `mu_Allocf` contract
+    let p = unsafe { (alloc.allocf.unwrap())(alloc.ud, ptr::null_mut(), size, 0) } as *mut u8;
+    if !p.is_null() {
+        // SAFETY: p points to a fresh block of `size` bytes
+        unsafe { ptr::write_bytes(p, 0, size) };
+    }
+    p
+}
+
+/// Whether the cache is the embedded cache of a single source.
+///
+/// # Safety
+/// `c` must point to a valid cache.
+unsafe fn is_source(c: *const mu_Cache) -> bool {
+    let self_ = (c as *const u8).wrapping_add(offset_of!(mu_Source, self_));
+    // SAFETY: guaranteed by the caller
+    unsafe { (*c).sources as *const u8 == self_ }
+}
+
+/// Position of the source of each label in the render order.
+fn rank_sources(labels: &[Label], order: mu_SourceOrder, cache: *const mu_Cache) -> Vec<usize> {
+    // first primary label, else highest priority
+    let primary = labels.iter().enumerate().fold(0, |p, (i, l)| {
+        let lp = &labels[p];
+        match !lp.primary && (l.primary || l.priority > lp.priority) {
+            true => i,
+            false => p,
+        }
+    });
+    // SAFETY: `mu_render` checked the cache and every label's source id
+    let name = |id| bytes(unsafe { &(*source_at(cache, id)).name });
+    let cmp_name = |l: u32, r: u32| name(l).cmp(name(r)).then(l.cmp(&r));
+    (labels.iter())
+        .map(|label| {
+            let id = label.src_id;
+            match order {
+                mu_SourceOrder::MU_SOURCE_ORDER_CACHE => id as usize,
+                mu_SourceOrder::MU_SOURCE_ORDER_LABELS => {
+                    labels.iter().position(|l| l.src_id == id).unwrap_or(0)
+                }
+                mu_SourceOrder::MU_SOURCE_ORDER_NAME => (labels.iter())
+                    .filter(|l| cmp_name(l.src_id, id) == Ordering::Less)
+                    .count(),
+                mu_SourceOrder::MU_SOURCE_ORDER_PRIMARY if id == labels[primary].src_id => 0,
+                mu_SourceOrder::MU_SOURCE_ORDER_PRIMARY => id as usize + 1,
+            }
+        })
+        .collect()
+}
+
+fn source_at(c: *const mu_Cache, id: u32) -> *mut mu_Source {
+    // SAFETY: `mu_render` checked the cache and every label's source id
+    unsafe {
+        if is_source(c) {
+            c as *mut mu_Source
+        } else {
+            (&*((*c).sources as *const Sources))[id as usize]
+        }
+    }
+}
+
+/// # Safety
+/// `src` must be a valid source.
+unsafe fn lines<'a>(src: *mut mu_Source) -> &'a mut Lines {
+    // SAFETY: `lines` is either null or a leaked `Box<Lines>`
+    unsafe {
+        if (*src).lines.is_null() {
+            (*src).lines = Box::into_raw(Box::<Lines>::default()) as *mut mu_Line;
+        }
+        &mut *((*src).lines as *mut Lines)
+    }
+}
+
+/// # Safety
+/// `c` must be null or a valid cache.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_sourcecount(c: *const mu_Cache) -> c_uint {
+    if c.is_null() {
+        return 0;
+    }
+    // SAFETY: c is a valid cache per the contract
+    unsafe {
+        if is_source(c) {
+            1
+        } else if (*c).sources.is_null() {
+            0
+        } else {
+            (*((*c).sources as *const Sources)).len() as c_uint
+        }
+    }
+}
+
+/// # Safety
+/// `c` must be null or a valid cache.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_getsource(c: *const mu_Cache, src_id: c_uint) -> *mut mu_Source {
+    // SAFETY: c is null or a valid cache per the contract
+    if src_id < unsafe { mu_sourcecount(c) } {
+        source_at(c, src_id)
+    } else {
+        ptr::null_mut()
+    }
+}
+
+/// # Safety
+/// `c` must be null or a valid cache, and `src` null or a source of no
+/// cache.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_setsource(
+    c: *mut mu_Cache,
+    src_id: c_uint,
+    src: *mut mu_Source,
+) -> *mut mu_Source {
+    // SAFETY: c is null or a valid cache per the contract
+    if c.is_null() || src.is_null() || unsafe { is_source(c) || (*c).sources.is_null() } {
+        return ptr::null_mut();
+    }
+    // SAFETY: c is a cache of several sources, whose table is a `Sources`
+    let sources = unsafe { &mut *((*c).sources as *mut Sources) };
+    match sources.get_mut(src_id as usize) {
+        Some(old) => std::mem::replace(old, src),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_source(r: *mut ffi::mu_Report) -> *mut mu_Source {
+    if r.is_null() {
+        return ptr::null_mut();
+    }
+    // SAFETY: r is a valid report per the contract
+    unsafe { (*(r as *const Report)).cur_src.get() }
+}
+
+/// # Safety
+/// `src` must be null or a valid source.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_linecount(src: *mut mu_Source) -> c_uint {
+    // SAFETY: src is a valid source per the contract, and `lines` is either
+    // null or a leaked `Box<Lines>`
+    unsafe {
+        if src.is_null() || (*src).lines.is_null() {
+            0
+        } else {
+            (*((*src).lines as *const Lines)).len() as c_uint
+        }
+    }
+}
+
+/// # Safety
+/// `allocf` must follow the `mu_Allocf` contract for `ud`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_newcache(allocf: ffi::mu_Allocf, ud: *mut c_void) -> *mut mu_Cache {
+    let alloc = initalloc(allocf, ud);
+    let cache = alloc_zeroed(&alloc, size_of::<mu_Cache>()) as *mut mu_Cache;
+    if cache.is_null() {
+        return ptr::null_mut();
+    }
+    // SAFETY: cache is a fresh zeroed block large enough for a mu_Cache
+    unsafe {
+        (*cache).size = size_of::<mu_Cache>();
+        (*cache).alloc = alloc;
+    }
+    cache
+}
+
+/// # Safety
+/// `c` must be null or a cache that is not used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_delcache(c: *mut mu_Cache) {
+    if c.is_null() {
+        return;
+    }
+    // SAFETY: c is a valid cache owned by the caller; its sources and tables
+    // were created by this module
+    unsafe {
+        if is_source(c) {
+            /* cache is a source? */
+            let src = c as *mut mu_Source;
+            if (*src).inited != 0
+                && let Some(free) = (*src).free
+            {
+                free(src);
+            }
+            if !(*src).lines.is_null() {
+                drop(Box::from_raw((*src).lines as *mut Lines));
+            }
+        } else if !(*c).sources.is_null() {
+            let sources = Box::from_raw((*c).sources as *mut Sources);
+            for &src in sources.iter() {
+                mu_delcache(&mut (*src).cache);
+            }
+        }
+        let alloc = (*c).alloc;
+        (alloc.allocf.unwrap())(alloc.ud, c as *mut c_void, 0, (*c).size);
+    }
+}
+
+/// # Safety
+/// `pc` must be null or point to a null or valid cache pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_addsource(
+    pc: *mut *mut mu_Cache,
+    size: usize,
+    name: mu_Slice,
+) -> *mut mu_Source {
+    // SAFETY: pc and *pc are valid per the contract
+    unsafe {
+        let has_cache = !pc.is_null() && !(*pc).is_null();
+        let alloc = if has_cache {
+            (**pc).alloc
+        } else {
+            initalloc(None, ptr::null_mut())
+        };
+        if has_cache && is_source(*pc) {
+            let src = *pc as *mut mu_Source;
+            *pc = mu_newcache(alloc.allocf, alloc.ud);
+            if (*pc).is_null() {
+                return ptr::null_mut();
+            }
+            (**pc).sources = Box::into_raw(Box::new(vec![src])) as *mut *mut mu_Source;
+        }
+        let size = size.max(size_of::<mu_Source>());
+        let src = alloc_zeroed(&alloc, size) as *mut mu_Source;
+        if src.is_null() {
+            return ptr::null_mut();
+        }
+        (*src).cache.size = size;
+        (*src).cache.alloc = alloc;
+        (*src).cache.sources = &raw mut (*src).self_ as *mut *mut mu_Source;
+        (*src).self_ = &raw mut (*src).cache;
+        (*src).name = if name.p.is_null() {
+            to_slice(b"<unknown>")
+        } else {
+            name
+        };
+        (*src).get_line_info = Some(mu_getline);
+        (*src).line_count = Some(mu_linecount);
+        (*src).line_for_chars = Some(mu_lineforchars);
+        (*src).line_for_bytes = Some(mu_lineforbytes);
+        if pc.is_null() {
+            return src;
+        }
+        if (*pc).is_null() {
+            *pc = &raw mut (*src).cache;
+        } else {
+            let sources = &mut (**pc).sources;
+            if sources.is_null() {
+                *sources = Box::into_raw(Box::<Sources>::default()) as *mut *mut mu_Source;
+            }
+            (*(*sources as *mut Sources)).push(src);
+        }
+        src
+    }
+}
+
+/// # Safety
+/// `src` must be a valid source and `data` a valid slice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_updatelines(src: *mut mu_Source, data: mu_Slice) {
+    // SAFETY: src is a valid source per the contract
+    let lines = unsafe { lines(src) };
+    if lines.is_empty() {
+        lines.push(line_or_default(ptr::null()));
+    }
+    let data = bytes(&data);
+    // SAFETY: src is a valid source per the contract
+    let src = unsafe { &mut *src };
+    let mut p = 0;
+    if src.cr_pending != 0 && !data.is_empty() {
+        /* "\r\n" split between updates */
+        let crlf = data[0] == b'\n';
+        if crlf {
+            let n = lines.len();
+            lines[n - 2].newline = 2;
+            lines[n - 1].offset += 1;
+            lines[n - 1].byte_offset += 1;
+            p = 1;
+        }
+        let kind = if crlf {
+            mu_Newline::MU_NEWLINE_CRLF
+        } else {
+            mu_Newline::MU_NEWLINE_CR
+        };
+        src.newlines |= 1 << kind as u32;
+        src.cr_pending = 0;
+    }
+    while p < data.len() {
+        let start = p;
+        if let Some((nl, kind)) = newline_at(src.newline, data, p) {
+            let current = lines.last_mut().unwrap();
+            let offset = lineend(current) + nl as usize;
+            let byte_offset = current.byte_offset + current.byte_len as usize + nl as usize;
+            current.newline = nl;
+            p += nl as usize;
+            let mut next = line_or_default(ptr::null());
+            next.offset = offset;
+            next.byte_offset = byte_offset;
+            lines.push(next);
+            if kind == mu_Newline::MU_NEWLINE_CR
+                && p == data.len()
+                && src.newline != mu_Newline::MU_NEWLINE_CR as c_int
+            {
+                src.cr_pending = 1;
+            } else {
+                src.newlines |= 1 << kind as u32;
+            }
+            continue;
+        }
+        p = advance(data, p);
+        let current = lines.last_mut().unwrap();
+        current.len += 1;
+        current.byte_len += (p - start) as c_uint;
+    }
+}
+
+/// The length and kind of the newline at `data[p]`, if any, for a source
+/// with the `declared` newline convention.
+fn newline_at(declared: c_int, data: &[u8], p: usize) -> Option<(c_uint, mu_Newline)> {
+    use mu_Newline::*;
+    let (lf, cr) = (MU_NEWLINE_LF as c_int, MU_NEWLINE_CR as c_int);
+    match data[p] {
+        b'\n' if declared != cr => Some((1, MU_NEWLINE_LF)),
+        b'\r' if declared != lf => {
+            if declared != cr && data.get(p + 1) == Some(&b'\n') {
+                Some((2, MU_NEWLINE_CRLF))
+            } else {
+                Some((1, MU_NEWLINE_CR))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// # Safety
+/// `src` must be a valid source.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_newlinekind(src: *const mu_Source) -> mu_Newline {
+    use mu_Newline::*;
+    // SAFETY: src is a valid source per the contract
+    let src = unsafe { &*src };
+    let seen = src.newlines | (src.cr_pending << MU_NEWLINE_CR as u32);
+    let kinds = [MU_NEWLINE_LF, MU_NEWLINE_CRLF, MU_NEWLINE_CR];
+    if let Some(&kind) = kinds.iter().find(|&&k| k as c_int == src.newline) {
+        return kind;
+    }
+    match kinds.into_iter().find(|&k| seen == 1 << k as u32) {
+        Some(kind) => kind,
+        None if seen == 0 => MU_NEWLINE_AUTO,
+        None => MU_NEWLINE_MIXED,
+    }
+}
+
+/// # Safety
+/// `src` must be a valid source.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_getline(src: *mut mu_Source, line_no: c_uint) -> mu_CL {
+    // SAFETY: src is a valid source per the contract
+    let lines = unsafe { lines(src) };
+    match lines.len() {
+        0 => ptr::null(),
+        size => &lines[(line_no as usize).min(size - 1)],
+    }
+}
+
+/// # Safety
+/// `src` must be a valid source and `out` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_lineforchars(
+    src: *mut mu_Source,
+    char_pos: usize,
+    out: *mut mu_CL,
+) -> c_uint {
+    // SAFETY: src is a valid source per the contract
+    let l = unsafe { lines(src) }.partition_point(|line| line.offset <= char_pos);
+    let l = l.saturating_sub(1) as c_uint;
+    // SAFETY: src and out are valid per the contract
+    unsafe { *out = mu_getline(src, l) };
+    l
+}
+
+/// # Safety
+/// `src` must be a valid source and `out` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_lineforbytes(
+    src: *mut mu_Source,
+    byte_pos: usize,
+    out: *mut mu_CL,
+) -> c_uint {
+    // SAFETY: src is a valid source per the contract
+    let l = unsafe { lines(src) }.partition_point(|line| line.byte_offset < byte_pos);
+    let l = l.saturating_sub(1) as c_uint;
+    // SAFETY: src and out are valid per the contract
+    unsafe { *out = mu_getline(src, l) };
+    l
+}
+
+#[repr(C)]
+struct MemorySource {
+    base: mu_Source,
+    data: mu_Slice,
+}
+
+unsafe extern "C" fn memory_init(src: *mut mu_Source) -> c_int {
+    // SAFETY: src was created by mu_addmemory
+    unsafe { mu_updatelines(src, (*(src as *mut MemorySource)).data) };
+    MU_OK
+}
+
+unsafe extern "C" fn memory_get_line(src: *mut mu_Source, line_no: c_uint) -> mu_Slice {
+    // SAFETY: src was created by mu_addmemory, and its data outlives it
+    unsafe {
+        let data = bytes(&(*(src as *mut MemorySource)).data);
+        let line = *mu_getline(src, line_no);
+        to_slice(&data[line.byte_offset..][..line.byte_len as usize])
+    }
+}
+
+/// # Safety
+/// See [`mu_addsource`]; `data` must outlive the cache.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_addmemory(
+    pc: *mut *mut mu_Cache,
+    data: mu_Slice,
+    name: mu_Slice,
+) -> *mut mu_Source {
+    // SAFETY: forwarded contract
+    let msrc = unsafe { mu_addsource(pc, size_of::<MemorySource>(), name) } as *mut MemorySource;
+    if msrc.is_null() {
+        return ptr::null_mut();
+    }
+    // SAFETY: msrc is a fresh block of at least size_of::<MemorySource>()
+    unsafe {
+        (*msrc).data = data;
+        (*msrc).base.init = Some(memory_init);
+        (*msrc).base.get_line = Some(memory_get_line);
+        &raw mut (*msrc).base
+    }
+}
+
+/* config */
+
+struct SyncCharset(ffi::mu_Charset);
+// SAFETY: the chunks point to immutable static data
+unsafe impl Sync for SyncCharset {}
+
+static ASCII_CHARSET: SyncCharset = SyncCharset([
+    /* MU_DRAW_SPACE      */ c"\x01 ".as_ptr(),
+    /* MU_DRAW_NEWLINE    */ c"\x01\n".as_ptr(),
+    /* MU_DRAW_LBOX       */ c"\x01[".as_ptr(),
+    /* MU_DRAW_RBOX       */ c"\x01]".as_ptr(),
+    /* MU_DRAW_COLON      */ c"\x01:".as_ptr(),
+    /* MU_DRAW_HBAR       */ c"\x01-".as_ptr(),
+    /* MU_DRAW_VBAR       */ c"\x01|".as_ptr(),
+    /* MU_DRAW_XBAR       */ c"\x01+".as_ptr(),
+    /* MU_DRAW_VBAR_GAP   */ c"\x01:".as_ptr(),
+    /* MU_DRAW_LINE_MARGIN*/ c"\x01|".as_ptr(),
+    /* MU_DRAW_UARROW     */ c"\x01^".as_ptr(),
+    /* MU_DRAW_RARROW     */ c"\x01>".as_ptr(),
+    /* MU_DRAW_LTOP       */ c"\x01,".as_ptr(),
+    /* MU_DRAW_MTOP       */ c"\x01v".as_ptr(),
+    /* MU_DRAW_RTOP       */ c"\x01.".as_ptr(),
+    /* MU_DRAW_LBOT       */ c"\x01`".as_ptr(),
+    /* MU_DRAW_MBOT       */ c"\x01^".as_ptr(),
+    /* MU_DRAW_RBOT       */ c"\x01'".as_ptr(),
+    /* MU_DRAW_LCROSS     */ c"\x01|".as_ptr(),
+    /* MU_DRAW_RCROSS     */ c"\x01|".as_ptr(),
+    /* MU_DRAW_LUNDERBAR  */ c"\x01|".as_ptr(),
+    /* MU_DRAW_MUNDERBAR  */ c"\x01|".as_ptr(),
+    /* MU_DRAW_RUNDERBAR  */ c"\x01|".as_ptr(),
+    /* MU_DRAW_SUNDERBAR  */ c"\x01^".as_ptr(),
+    /* MU_DRAW_UNDERLINE  */ c"\x01^".as_ptr(),
+    /* MU_DRAW_ELLIPSIS   */ c"\x03...".as_ptr(),
+]);
+
+static UNICODE_CHARSET: SyncCharset = SyncCharset([
+    /* MU_DRAW_SPACE      */ c"\x01 ".as_ptr(),
+    /* MU_DRAW_NEWLINE    */ c"\x01\n".as_ptr(),
+    /* MU_DRAW_LBOX       */ c"\x01[".as_ptr(),
+    /* MU_DRAW_RBOX       */ c"\x01]".as_ptr(),
+    /* MU_DRAW_COLON      */ c"\x01:".as_ptr(),
+    /* MU_DRAW_HBAR       */ c"\x03─".as_ptr(),
+    /* MU_DRAW_VBAR       */ c"\x03│".as_ptr(),
+    /* MU_DRAW_XBAR       */ c"\x03┼".as_ptr(),
+    /* MU_DRAW_VBAR_GAP   */ c"\x03┆".as_ptr(),
+    /* MU_DRAW_LINE_MARGIN*/ c"\x03┤".as_ptr(),
+    /* MU_DRAW_UARROW     */ c"\x03▲".as_ptr(),
+    /* MU_DRAW_RARROW     */ c"\x03▶".as_ptr(),
+    /* MU_DRAW_LTOP       */ c"\x03╭".as_ptr(),
+    /* MU_DRAW_MTOP       */ c"\x03┬".as_ptr(),
+    /* MU_DRAW_RTOP       */ c"\x03╮".as_ptr(),
+    /* MU_DRAW_LBOT       */ c"\x03╰".as_ptr(),
+    /* MU_DRAW_MBOT       */ c"\x03┴".as_ptr(),
+    /* MU_DRAW_RBOT       */ c"\x03╯".as_ptr(),
+    /* MU_DRAW_LCROSS     */ c"\x03├".as_ptr(),
+    /* MU_DRAW_RCROSS     */ c"\x03┤".as_ptr(),
+    /* MU_DRAW_LUNDERBAR  */ c"\x03┌".as_ptr(),
+    /* MU_DRAW_MUNDERBAR  */ c"\x03┬".as_ptr(),
+    /* MU_DRAW_RUNDERBAR  */ c"\x03┐".as_ptr(),
+    /* MU_DRAW_SUNDERBAR  */ c"\x03△".as_ptr(),
+    /* MU_DRAW_UNDERLINE  */ c"\x03─".as_ptr(),
+    /* MU_DRAW_ELLIPSIS   */ c"\x03…".as_ptr(),
+]);
+
+#[unsafe(no_mangle)]
+pub extern "C" fn mu_ascii() -> *const ffi::mu_Charset {
+    &ASCII_CHARSET.0
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn mu_unicode() -> *const ffi::mu_Charset {
+    &UNICODE_CHARSET.0
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn mu_default_color(_ud: *mut c_void, kind: mu_ColorKind) -> ffi::mu_Chunk {
+    use mu_ColorKind::*;
+    match kind {
+        MU_COLOR_RESET => c"\x04\x1b[0m".as_ptr(),
+        MU_COLOR_ERROR => c"\x05\x1b[31m".as_ptr(),
+        MU_COLOR_WARNING => c"\x05\x1b[33m".as_ptr(),
+        MU_COLOR_KIND => c"\x0b\x1b[38;5;147m".as_ptr(),
+        MU_COLOR_MARGIN => c"\x0b\x1b[38;5;246m".as_ptr(),
+        MU_COLOR_SKIPPED_MARGIN => c"\x0b\x1b[38;5;240m".as_ptr(),
+        MU_COLOR_UNIMPORTANT => c"\x0b\x1b[38;5;249m".as_ptr(),
+        MU_COLOR_NOTE => c"\x0b\x1b[38;5;115m".as_ptr(),
+        MU_COLOR_LABEL => c"\x05\x1b[39m".as_ptr(),
+        MU_COLOR_EMPHASIS => c"\x04\x1b[1m".as_ptr(),
+        MU_COLOR_INLINE_CODE => c"\x0b\x1b[38;5;180m".as_ptr(),
+        MU_COLOR_CONTEXT => c"\x04\x1b[2m".as_ptr(),
+        MU_COLOR_INFO => c"\x05\x1b[34m".as_ptr(),
+        MU_COLOR_HINT => c"\x05\x1b[36m".as_ptr(),
+        MU_COLOR_ADVICE => c"\x05\x1b[32m".as_ptr(),
+        MU_COLOR_EXPECTED => c"\x05\x1b[32m".as_ptr(),
+        MU_COLOR_FOUND => c"\x05\x1b[31m".as_ptr(),
+    }
+}
+
+unsafe extern "C" fn default_color(ud: *mut c_void, kind: mu_ColorKind) -> ffi::mu_Chunk {
+    mu_default_color(ud, kind)
+}
+
+struct SyncConfig(mu_Config);
+// SAFETY: the default config only points to immutable static data
+unsafe impl Sync for SyncConfig {}
+
+static DEFAULT_CONFIG: SyncConfig = SyncConfig(mu_Config {
+    compact: 0,
+    cross_gap: 1,
+    multiline_arrows: 1,
+    underlines: 1,
+    minimise_crossings: 1,
+    align_messages: 1,
+    context_lines: 0,
+    tab_width: 4,
+    limit_width: 0,
+    ambiwidth: 1,
+    markup: 0,
+    inline_code: 0,
+    normalize: 0,
+    bidi_isolate: 0,
+    auto_location: 1,
+    dim_unlabeled: 1,
+    caret_only: 0,
+    one_based_columns: 1,
+    auto_priority: 0,
+    max_depth: 0,
+    gutter_bar: 0,
+    collapse_notes: 0,
+    tab_stops: [0; ffi::sizes::TAB_STOPS],
+    label_attach: mu_LabelAttach::MU_ATTACH_MIDDLE,
+    index_type: mu_IndexType::MU_INDEX_CHAR,
+    overlap: mu_Overlap::MU_OVERLAP_MERGE,
+    footer: mu_Footer::MU_FOOTER_BORDER,
+    source_order: mu_SourceOrder::MU_SOURCE_ORDER_CACHE,
+    color: Some(default_color),
+    color_ud: ptr::null_mut(),
+    char_set: &UNICODE_CHARSET.0,
+    line_no_sep: mu_Slice {
+        p: ptr::null(),
+        e: ptr::null(),
+    },
+    line_no: None,
+    line_no_ud: ptr::null_mut(),
+});
+
+/// # Safety
+/// `config` must be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_initconfig(config: *mut mu_Config) {
+    // SAFETY: config is writable per the contract
+    unsafe { *config = DEFAULT_CONFIG.0 };
+}
+
+/* API */
+
+#[unsafe(no_mangle)]
+pub extern "C" fn mu_version() -> c_uint {
+    ffi::MU_VERSION_MAJOR * 10000 + ffi::MU_VERSION_MINOR * 100 + ffi::MU_VERSION_PATCH
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn mu_features() -> c_uint {
+    0
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn mu_structsize(which: c_uint) -> usize {
+    match which {
+        ffi::MU_STRUCT_CONFIG => size_of::<mu_Config>(),
+        ffi::MU_STRUCT_ALLOCATOR => size_of::<mu_Allocator>(),
+        ffi::MU_STRUCT_CACHE => size_of::<mu_Cache>(),
+        ffi::MU_STRUCT_SOURCE => size_of::<mu_Source>(),
+        ffi::MU_STRUCT_LINE => size_of::<mu_Line>(),
+        ffi::MU_STRUCT_COLORGEN => size_of::<ffi::mu_ColorGen>(),
+        _ => 0,
+    }
+}
+
+/// Run `f` on the report behind `r`, or return `MU_ERRPARAM` if it is null.
+///
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+unsafe fn with_report(r: *mut ffi::mu_Report, f: impl FnOnce(&mut Report) -> c_int) -> c_int {
+    if r.is_null() {
+        return ffi::MU_ERRPARAM;
+    }
+    // SAFETY: r is a valid report per the contract
+    f(unsafe { &mut *(r as *mut Report) })
+}
+
+/// Run `f` on the last label of the report, or return `MU_ERRPARAM`.
+///
+/// # Safety
+/// See [`with_report`].
+unsafe fn with_label(r: *mut ffi::mu_Report, f: impl FnOnce(&mut Label) -> c_int) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe { with_report(r, |r| r.labels.last_mut().map_or(ffi::MU_ERRPARAM, f)) }
+}
+
+/// Like [`with_label`], but drops the kept layout, as `f` changes how the
+/// labels are grouped.
+///
+/// # Safety
+/// See [`with_report`].
+unsafe fn with_layout_label(r: *mut ffi::mu_Report, f: impl FnOnce(&mut Label) -> c_int) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.layout.set(None);
+            r.labels.last_mut().map_or(ffi::MU_ERRPARAM, f)
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_writer(
+    r: *mut ffi::mu_Report,
+    writer: ffi::mu_Writer,
+    ud: *mut c_void,
+) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.writer = writer;
+            r.writer_ud = ud;
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`], and `cache` null or
+/// a valid cache.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_render(r: *mut ffi::mu_Report, cache: *const mu_Cache) -> c_int {
+    if r.is_null() || cache.is_null() {
+        return ffi::MU_ERRPARAM;
+    }
+    // SAFETY: r is a valid report per the contract
+    let r = unsafe { &*(r as *const Report) };
+    if r.writer.is_none() {
+        return MU_OK;
+    }
+    // SAFETY: cache is valid per the contract
+    let src_count = unsafe { mu_sourcecount(cache) };
+    if r.labels.iter().any(|l| l.src_id >= src_count) {
+        return ffi::MU_ERRSRC;
+    }
+    let size = r.labels.len() as u32;
+    if r.labels
+        .iter()
+        .any(|l| l.msg_ref.is_some_and(|(_, _, id)| id >= size))
+    {
+        return ffi::MU_ERRPARAM;
+    }
+    if r.contexts.iter().any(|&(_, _, src_id)| src_id >= src_count) {
+        return ffi::MU_ERRSRC;
+    }
+    if r.suggestions.iter().any(|s| s.src_id >= src_count) {
+        return ffi::MU_ERRSRC;
+    }
+    r.cur_msg.set(-1);
+    r.cur_line.set(None);
+    let mut render = Render::new(r);
+    match r.layout.take() {
+        Some(layout) if layout.reusable(r, cache, src_count, render.cfg) => {
+            render.groups = layout.groups;
+        }
+        _ => {
+            if let Err(code) = render.make_groups(cache) {
+                return code;
+            }
+        }
+    }
+    let res = render.report(cache);
+    r.layout.set(Some(Layout {
+        cache,
+        src_count,
+        index_type: render.cfg.index_type,
+        context_lines: render.cfg.context_lines,
+        max_depth: render.cfg.max_depth,
+        source_order: render.cfg.source_order,
+        groups: render.groups,
+    }));
+    match res {
+        Ok(()) => MU_OK,
+        Err(code) => code,
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_relayout(r: *mut ffi::mu_Report) {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.layout.set(None);
+            MU_OK
+        })
+    };
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_linekind(r: *const ffi::mu_Report) -> mu_LineKind {
+    if r.is_null() {
+        return mu_LineKind::MU_LINE_HEADER;
+    }
+    // SAFETY: r is a valid report per the contract
+    unsafe { (*(r as *const Report)).cur_kind.get() }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_msglabel(r: *const ffi::mu_Report) -> c_int {
+    if r.is_null() {
+        return -1;
+    }
+    // SAFETY: r is a valid report per the contract
+    unsafe { (*(r as *const Report)).cur_msg.get() }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`], and `src_id` and
+/// `line_no` null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_sourceline(
+    r: *const ffi::mu_Report,
+    src_id: *mut ffi::mu_Id,
+    line_no: *mut c_uint,
+) -> c_int {
+    if r.is_null() {
+        return 0;
+    }
+    // SAFETY: r is a valid report per the contract
+    let r = unsafe { &*(r as *const Report) };
+    let kind = r.cur_kind.get();
+    let (id, line) = match r.cur_line.get() {
+        Some(cur)
+            if matches!(
+                kind,
+                mu_LineKind::MU_LINE_SOURCE
+                    | mu_LineKind::MU_LINE_UNDERLINE
+                    | mu_LineKind::MU_LINE_ARROW
+            ) =>
+        {
+            cur
+        }
+        _ => return 0,
+    };
+    // SAFETY: the pointers are null or valid for writes per the contract
+    unsafe {
+        if !src_id.is_null() {
+            *src_id = ffi::mu_Id(id);
+        }
+        if !line_no.is_null() {
+            *line_no = line;
+        }
+    }
+    1
+}
+
+/// # Safety
+/// The allocator is not used by the Rust backend.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_new(_allocf: ffi::mu_Allocf, _ud: *mut c_void) -> *mut ffi::mu_Report {
+    let null = to_slice(&[]);
+    let null = mu_Slice {
+        p: ptr::null(),
+        ..null
+    };
+    let report = Report {
+        config: &DEFAULT_CONFIG.0,
+        writer: None,
+        writer_ud: ptr::null_mut(),
+        ellipsis_width: 0,
+        cur_src: Cell::new(ptr::null_mut()),
+        cur_kind: Cell::new(mu_LineKind::MU_LINE_HEADER),
+        cur_msg: Cell::new(-1),
+        cur_line: Cell::new(None),
+        layout: Cell::new(None),
+        level: mu_Level::MU_ERROR,
+        code: null,
+        custom_level: null,
+        title: null,
+        labels: Vec::new(),
+        contexts: Vec::new(),
+        helps: Vec::new(),
+        notes: Vec::new(),
+        suggestions: Vec::new(),
+        location: None,
+    };
+    Box::into_raw(Box::new(report)) as *mut ffi::mu_Report
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_reset(r: *mut ffi::mu_Report) {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            let null = mu_Slice {
+                p: ptr::null(),
+                e: ptr::null(),
+            };
+            r.level = mu_Level::MU_ERROR;
+            r.code = null;
+            r.custom_level = null;
+            r.title = null;
+            r.layout.set(None);
+            r.labels.clear();
+            r.contexts.clear();
+            r.helps.clear();
+            r.notes.clear();
+            r.suggestions.clear();
+            r.location = None;
+            MU_OK
+        })
+    };
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`] that is not used
+/// afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_delete(r: *mut ffi::mu_Report) {
+    if !r.is_null() {
+        // SAFETY: r was created by Box::into_raw in mu_new
+        drop(unsafe { Box::from_raw(r as *mut Report) });
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`], and `config` must
+/// be null or outlive the report; null restores the default config.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_config(r: *mut ffi::mu_Report, config: *const mu_Config) -> c_int {
+    let config = if config.is_null() {
+        &DEFAULT_CONFIG.0 as *const mu_Config
+    } else {
+        config
+    };
+    // SAFETY: config is valid per the contract
+    let cfg = unsafe { &*config };
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.config = config;
+            let ellipsis = chunk((*cfg.char_set)[mu_Draw::MU_DRAW_ELLIPSIS as usize]);
+            r.ellipsis_width = strwidth(ellipsis, cfg.ambiwidth);
+            for label in &mut r.labels {
+                label.width = msgwidth(cfg, bytes(&label.message));
+            }
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`]; the slices must
+/// outlive the report.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_title(
+    r: *mut ffi::mu_Report,
+    l: mu_Level,
+    custom: mu_Slice,
+    msg: mu_Slice,
+) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.level = l;
+            r.custom_level = custom;
+            r.title = msg;
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`]; `code` must
+/// outlive the report.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_code(r: *mut ffi::mu_Report, code: mu_Slice) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.code = code;
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_label(
+    r: *mut ffi::mu_Report,
+    start: usize,
+    end: usize,
+    src_id: mu_Id,
+) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.layout.set(None);
+            r.labels.push(Label {
+                ud: ptr::null_mut(),
+                color: None,
+                message: mu_Slice {
+                    p: ptr::null(),
+                    e: ptr::null(),
+                },
+                start_pos: start,
+                end_pos: end,
+                src_id: src_id.0,
+                width: 0,
+                primary: false,
+                order: 0,
+                priority: 0,
+                msg_ref: None,
+                kind: mu_ColorKind::MU_COLOR_RESET,
+            });
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`]; `msg` must outlive
+/// the report.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_message(r: *mut ffi::mu_Report, msg: mu_Slice, width: c_int) -> c_int {
+    if r.is_null() || msg.p.is_null() {
+        return ffi::MU_ERRPARAM;
+    }
+    // SAFETY: r is a valid report per the contract
+    let cfg = unsafe { &*(*(r as *const Report)).config };
+    // SAFETY: forwarded contract
+    unsafe {
+        with_label(r, |label| {
+            label.message = msg;
+            label.msg_ref = None;
+            label.width = if width > 0 {
+                width
+            } else {
+                msgwidth(cfg, bytes(&msg))
+            };
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`]; `color` must accept
+/// `ud` for as long as the report is rendered.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_color(
+    r: *mut ffi::mu_Report,
+    color: ffi::mu_Color,
+    ud: *mut c_void,
+) -> c_int {
+    if color.is_none() {
+        return ffi::MU_ERRPARAM;
+    }
+    // SAFETY: forwarded contract
+    unsafe {
+        with_label(r, |label| {
+            label.color = color;
+            label.ud = ud;
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_colorkind(r: *mut ffi::mu_Report, kind: mu_ColorKind) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_label(r, |label| {
+            label.kind = kind;
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_primary(r: *mut ffi::mu_Report) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_layout_label(r, |label| {
+            label.primary = true;
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_order(r: *mut ffi::mu_Report, order: c_int) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_layout_label(r, |label| {
+            label.order = order;
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_msgref(
+    r: *mut ffi::mu_Report,
+    start: usize,
+    end: usize,
+    label_id: mu_Id,
+) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_label(r, |label| {
+            if label.message.p.is_null() || start > end || end > bytes(&label.message).len() {
+                return ffi::MU_ERRPARAM;
+            }
+            label.msg_ref = Some((start, end, label_id.0));
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_setspan(
+    r: *mut ffi::mu_Report,
+    label_id: mu_Id,
+    start: usize,
+    end: usize,
+) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            let Some(label) = r.labels.get_mut(label_id.0 as usize) else {
+                return ffi::MU_ERRPARAM;
+            };
+            (label.start_pos, label.end_pos) = (start, end);
+            r.layout.set(None);
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_location(r: *mut ffi::mu_Report, pos: usize, src_id: mu_Id) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.layout.set(None);
+            r.location = Some((pos, src_id.0));
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_context(
+    r: *mut ffi::mu_Report,
+    start: usize,
+    end: usize,
+    src_id: mu_Id,
+) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.layout.set(None);
+            r.contexts.push((start, end, src_id.0));
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_priority(r: *mut ffi::mu_Report, priority: c_int) -> c_int {
+    // SAFETY: forwarded contract
+    unsafe {
+        with_layout_label(r, |label| {
+            label.priority = priority;
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`]; `help_msg` must
+/// outlive the report.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_help(r: *mut ffi::mu_Report, help_msg: mu_Slice) -> c_int {
+    if help_msg.p.is_null() {
+        return ffi::MU_ERRPARAM;
+    }
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.helps.push(help_msg);
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`]; `note_msg` must
+/// outlive the report.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_note(r: *mut ffi::mu_Report, note_msg: mu_Slice) -> c_int {
+    if note_msg.p.is_null() {
+        return ffi::MU_ERRPARAM;
+    }
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.notes.push(note_msg);
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`]; `replacement` and
+/// `msg` must outlive the report.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_suggest(
+    r: *mut ffi::mu_Report,
+    start: usize,
+    end: usize,
+    src_id: ffi::mu_Id,
+    replacement: mu_Slice,
+    msg: mu_Slice,
+) -> c_int {
+    if replacement.p.is_null() {
+        return ffi::MU_ERRPARAM;
+    }
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            r.suggestions.push(Suggestion {
+                replacement,
+                message: msg,
+                start_pos: start,
+                end_pos: end,
+                src_id: src_id.0,
+                part: false,
+            });
+            MU_OK
+        })
+    }
+}
+
+/// # Safety
+/// `r` must be null or a report created by [`mu_new`]; `replacement` must
+/// outlive the report.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mu_suggestpart(
+    r: *mut ffi::mu_Report,
+    start: usize,
+    end: usize,
+    src_id: ffi::mu_Id,
+    replacement: mu_Slice,
+) -> c_int {
+    if replacement.p.is_null() {
+        return ffi::MU_ERRPARAM;
+    }
+    // SAFETY: forwarded contract
+    unsafe {
+        with_report(r, |r| {
+            if r.suggestions.is_empty() {
+                return ffi::MU_ERRPARAM;
+            }
+            r.suggestions.push(Suggestion {
+                replacement,
+                message: mu_Slice {
+                    p: ptr::null(),
+                    e: ptr::null(),
+                },
+                start_pos: start,
+                end_pos: end,
+                src_id: src_id.0,
+                part: true,
+            });
+            MU_OK
+        })
+    }
+}
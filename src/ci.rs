@@ -0,0 +1,305 @@
+//! Structured output formats for CI systems.
+
+use std::io::{self, Write};
+
+use crate::emitter::Rendered;
+use crate::html::escape;
+use crate::{Emitter, RawCache};
+
+/// A report collected for structured output.
+struct Record {
+    level: String,
+    error: bool,
+    title: String,
+    code: Option<String>,
+    file: Option<String>,
+    location: Option<(usize, usize)>,
+    text: String,
+}
+
+impl From<Rendered<'_>> for Record {
+    fn from(rendered: Rendered<'_>) -> Self {
+        Record {
+            level: rendered.level.into(),
+            error: rendered.error,
+            title: rendered.title.into(),
+            code: rendered.code.map(Into::into),
+            file: rendered.file,
+            location: rendered.location,
+            text: rendered.text,
+        }
+    }
+}
+
+impl Record {
+    /// The title prefixed with the code, if any.
+    fn name(&self) -> String {
+        match &self.code {
+            Some(code) => format!("[{code}] {}", self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    /// Pick the name for errors, warnings or other levels from `names`.
+    fn severity<'n>(&self, names: [&'n str; 3]) -> &'n str {
+        match (self.error, self.level.as_str()) {
+            (true, _) => names[0],
+            (false, "Warning") => names[1],
+            _ => names[2],
+        }
+    }
+}
+
+/// Escape `s` for a TeamCity service message attribute.
+fn teamcity_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '|' => out.push_str("||"),
+            '\'' => out.push_str("|'"),
+            '\n' => out.push_str("|n"),
+            '\r' => out.push_str("|r"),
+            '[' => out.push_str("|["),
+            ']' => out.push_str("|]"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Quote and escape `s` as a JSON string.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 64-bit FNV-1a hash of `parts`, separated by NUL bytes.
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (i, part) in parts.iter().enumerate() {
+        let sep: &[u8] = if i == 0 { b"" } else { b"\0" };
+        for &b in sep.iter().chain(part.as_bytes()) {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+impl Emitter<'_> {
+    /// Render all reports as a JUnit XML test suite named `suite`.
+    ///
+    /// Each (merged) report becomes one test case, named after its code and
+    /// title, with the source name of its first label as class name and the
+    /// line number of that label as `line` attribute. Reports with the
+    /// [`Error`](crate::Level::Error) level are failures; others pass and
+    /// carry their rendered text as `system-out`. Reports are rendered
+    /// without colors.
+    ///
+    /// See [`Emitter::render_to_string`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Emitter, Level, Report};
+    /// let mut emitter = Emitter::new()
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Error, "unknown variable")
+    ///             .with_label(8..9)
+    ///             .with_message("not found in this scope"),
+    ///     );
+    ///
+    /// let mut xml = Vec::new();
+    /// emitter.render_to_junit(&mut xml, "lint", ("let a = b;", "main.rs"))?;
+    /// let xml = String::from_utf8(xml).unwrap();
+    /// assert!(xml.contains(r#"<testcase name="unknown variable" classname="main.rs" file="main.rs" line="1">"#));
+    /// assert!(xml.contains(r#"<failure message="unknown variable" type="Error">"#));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_junit<W: Write>(
+        &mut self,
+        writer: &mut W,
+        suite: &str,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        let records = self.records(cache)?;
+        let failures = records.iter().filter(|r| r.error).count();
+        let suite = escape(suite);
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<testsuites name="{suite}" tests="{}" failures="{failures}">"#,
+            records.len()
+        )?;
+        writeln!(
+            writer,
+            r#"<testsuite name="{suite}" tests="{}" failures="{failures}">"#,
+            records.len()
+        )?;
+        for record in &records {
+            let name = escape(&record.name());
+            let file = escape(record.file.as_deref().unwrap_or(&suite));
+            write!(writer, r#"<testcase name="{name}" classname="{file}""#)?;
+            if record.file.is_some() {
+                write!(writer, r#" file="{file}""#)?;
+            }
+            if let Some((line, _)) = record.location {
+                write!(writer, r#" line="{line}""#)?;
+            }
+            writeln!(writer, ">")?;
+            let text = escape(&record.text);
+            match record.error {
+                true => writeln!(
+                    writer,
+                    r#"<failure message="{}" type="{}">{text}</failure>"#,
+                    escape(&record.title),
+                    escape(&record.level)
+                )?,
+                false => writeln!(writer, "<system-out>{text}</system-out>")?,
+            }
+            writeln!(writer, "</testcase>")?;
+        }
+        writeln!(writer, "</testsuite>\n</testsuites>")
+    }
+
+    /// Render all reports as TeamCity service messages.
+    ///
+    /// Each distinct report code (or title, for reports without a code) is
+    /// declared once as an `inspectionType`, and every report with a label
+    /// becomes an `inspection` at the location of its first label. Reports
+    /// without labels become plain build log `message`s.
+    ///
+    /// See [`Emitter::render_to_string`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Emitter, Level, Report};
+    /// let mut emitter = Emitter::new()
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Warning, "unused variable")
+    ///             .with_code("W01")
+    ///             .with_label(4..5),
+    ///     );
+    ///
+    /// let mut out = Vec::new();
+    /// emitter.render_to_teamcity(&mut out, ("let a = 1;", "main.rs"))?;
+    /// let out = String::from_utf8(out).unwrap();
+    /// assert!(out.contains("##teamcity[inspection typeId='W01' message='unused variable' file='main.rs' line='1' SEVERITY='WARNING']"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_teamcity<W: Write>(
+        &mut self,
+        writer: &mut W,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        let records = self.records(cache)?;
+        let mut types: Vec<String> = Vec::new();
+        for record in &records {
+            let title = teamcity_escape(&record.title);
+            let (Some(file), Some((line, _))) = (&record.file, record.location) else {
+                let status = record.severity(["ERROR", "WARNING", "NORMAL"]);
+                writeln!(
+                    writer,
+                    "##teamcity[message text='{title}' status='{status}']"
+                )?;
+                continue;
+            };
+            let id = teamcity_escape(record.code.as_deref().unwrap_or(&record.title));
+            if !types.contains(&id) {
+                writeln!(
+                    writer,
+                    "##teamcity[inspectionType id='{id}' name='{id}' category='{}' description='{title}']",
+                    teamcity_escape(&record.level)
+                )?;
+                types.push(id.clone());
+            }
+            writeln!(
+                writer,
+                "##teamcity[inspection typeId='{id}' message='{title}' file='{}' line='{line}' SEVERITY='{}']",
+                teamcity_escape(file),
+                record.severity(["ERROR", "WARNING", "INFO"])
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Render all reports as a GitLab Code Quality report.
+    ///
+    /// The output is a JSON array with one issue per report with a label,
+    /// located at the first label. Reports without labels are skipped, as
+    /// Code Quality requires a location. Errors map to the `major`
+    /// severity, warnings to `minor` and other levels to `info`. The
+    /// fingerprint is derived from the code, title, file and line, so it
+    /// is stable across runs.
+    ///
+    /// See [`Emitter::render_to_string`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Emitter, Level, Report};
+    /// let mut emitter = Emitter::new()
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Error, "unknown variable")
+    ///             .with_label(8..9),
+    ///     );
+    ///
+    /// let mut out = Vec::new();
+    /// emitter.render_to_gitlab(&mut out, ("let a = b;", "main.rs"))?;
+    /// let out = String::from_utf8(out).unwrap();
+    /// assert!(out.contains(r#""location":{"path":"main.rs","lines":{"begin":1}}"#));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_gitlab<W: Write>(
+        &mut self,
+        writer: &mut W,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        let records = self.records(cache)?;
+        writer.write_all(b"[")?;
+        let mut first = true;
+        for record in &records {
+            let (Some(file), Some((line, _))) = (&record.file, record.location) else {
+                continue;
+            };
+            let check = record.code.as_deref().unwrap_or(&record.level);
+            let fingerprint = fnv1a(&[check, &record.title, file, &line.to_string()]);
+            write!(
+                writer,
+                "{}\n  {{\"description\":{},\"check_name\":{},\"fingerprint\":\"{fingerprint:016x}\",\
+                 \"severity\":\"{}\",\"location\":{{\"path\":{},\"lines\":{{\"begin\":{line}}}}}}}",
+                if first { "" } else { "," },
+                json_string(&record.title),
+                json_string(check),
+                record.severity(["major", "minor", "info"]),
+                json_string(file),
+            )?;
+            first = false;
+        }
+        writer.write_all(if first { b"]\n" } else { b"\n]\n" })
+    }
+
+    /// Render all reports without colors and collect them.
+    fn records(&mut self, cache: impl Into<RawCache>) -> io::Result<Vec<Record>> {
+        let mut records = Vec::new();
+        self.render_each(cache, true, |rendered| {
+            records.push(rendered.into());
+            Ok(())
+        })?;
+        Ok(records)
+    }
+}
@@ -0,0 +1,137 @@
+//! The parts of a source covered by spans.
+
+use std::ops::Range;
+
+use crate::{Cache, byte_to_char, ffi, source_len};
+
+/// The parts of a source covered by a set of spans, made by
+/// [`Cache::coverage`] or [`Coverage::new`].
+///
+/// An analyzer can check that its labels don't overlap, with
+/// [`overlaps`](Coverage::overlaps), or find the code no label points at,
+/// with [`gaps`](Coverage::gaps), to say that everything else is fine.
+/// [`Emitter`](crate::Emitter) finds the reports to merge when
+/// [grouping](crate::Emitter::with_grouping) by the overlaps of the lines
+/// their labels cover. Overlapping labels within one report are drawn by
+/// the renderer according to [`Overlap`](crate::Overlap).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    len: usize,
+    covered: Vec<Range<usize>>,
+    overlaps: Vec<Range<usize>>,
+}
+
+impl Coverage {
+    /// The coverage of `spans` in a source of `len` positions.
+    ///
+    /// Spans are cut at `len`, and empty spans cover nothing.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Coverage;
+    /// let coverage = Coverage::new([4..6, 0..2, 5..8], 10);
+    /// assert_eq!(coverage.covered(), [0..2, 4..8]);
+    /// assert_eq!(coverage.gaps(), [2..4, 8..10]);
+    /// assert_eq!(coverage.overlaps(), [5..6]);
+    /// ```
+    pub fn new(spans: impl IntoIterator<Item = Range<usize>>, len: usize) -> Self {
+        let mut spans: Vec<_> = (spans.into_iter())
+            .map(|span| span.start.min(len)..span.end.min(len))
+            .filter(|span| !span.is_empty())
+            .collect();
+        spans.sort_by_key(|span| (span.start, span.end));
+        let (mut covered, mut overlaps) = (Vec::<Range<usize>>::new(), Vec::new());
+        for span in spans {
+            match covered.last_mut() {
+                Some(last) if span.start <= last.end => {
+                    if span.start < last.end {
+                        push_merged(&mut overlaps, span.start..span.end.min(last.end));
+                    }
+                    last.end = last.end.max(span.end);
+                }
+                _ => covered.push(span),
+            }
+        }
+        Self {
+            len,
+            covered,
+            overlaps,
+        }
+    }
+
+    /// The length of the source.
+    #[inline]
+    pub fn source_len(&self) -> usize {
+        self.len
+    }
+
+    /// The union of the spans, sorted, without overlapping or adjacent
+    /// ranges.
+    #[inline]
+    pub fn covered(&self) -> &[Range<usize>] {
+        &self.covered
+    }
+
+    /// The ranges of the source covered by no span, sorted.
+    pub fn gaps(&self) -> Vec<Range<usize>> {
+        let mut gaps = Vec::new();
+        let mut pos = 0;
+        for span in &self.covered {
+            if pos < span.start {
+                gaps.push(pos..span.start);
+            }
+            pos = span.end;
+        }
+        if pos < self.len {
+            gaps.push(pos..self.len);
+        }
+        gaps
+    }
+
+    /// The ranges covered by more than one span, sorted and merged.
+    #[inline]
+    pub fn overlaps(&self) -> &[Range<usize>] {
+        &self.overlaps
+    }
+
+    /// Whether no position is covered by more than one span.
+    #[inline]
+    pub fn is_disjoint(&self) -> bool {
+        self.overlaps.is_empty()
+    }
+}
+
+/// Push `range` to the sorted `ranges`, merged with the last one if they
+/// overlap or touch.
+fn push_merged(ranges: &mut Vec<Range<usize>>, range: Range<usize>) {
+    match ranges.last_mut() {
+        Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+        _ => ranges.push(range),
+    }
+}
+
+impl<K> Cache<K> {
+    /// The coverage of `spans` in source `src_id`, loading it if needed.
+    ///
+    /// `spans` count characters, like labels with the default
+    /// [`IndexType`](crate::IndexType), and the gaps run to the end of the
+    /// source, its last newline included. Returns [`None`] if `src_id` is
+    /// out of range or the source fails to load.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Cache;
+    /// let cache = Cache::new().with_source("let x = y + z;");
+    /// let coverage = cache.coverage(0, [4..5, 8..9, 12..13]).unwrap();
+    /// assert!(coverage.is_disjoint());
+    /// assert_eq!(coverage.gaps(), [0..4, 5..8, 9..12, 13..14]);
+    /// ```
+    pub fn coverage(
+        &self,
+        src_id: impl Into<ffi::mu_Id>,
+        spans: impl IntoIterator<Item = Range<usize>>,
+    ) -> Option<Coverage> {
+        let src = self.source(src_id.into())?;
+        Some(Coverage::new(spans, byte_to_char(src, source_len(src))))
+    }
+}
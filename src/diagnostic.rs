@@ -0,0 +1,523 @@
+//! Inspectable diagnostic data.
+
+use std::borrow::Cow;
+
+use crate::{LabelSpan, Level, Report, TitleLevel, ffi};
+
+/// Level of a [`Diagnostic`]: a standard level or a custom level name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DiagnosticLevel<'a> {
+    Standard(Level),
+    Custom(Cow<'a, str>),
+}
+
+impl<'a> From<TitleLevel<'a>> for DiagnosticLevel<'a> {
+    fn from(level: TitleLevel<'a>) -> Self {
+        match level.level {
+            ffi::mu_Level::MU_ERROR => Self::Standard(Level::Error),
+            ffi::mu_Level::MU_WARNING => Self::Standard(Level::Warning),
+            ffi::mu_Level::MU_INFO => Self::Standard(Level::Info),
+            ffi::mu_Level::MU_NOTE => Self::Standard(Level::Note),
+            ffi::mu_Level::MU_HINT => Self::Standard(Level::Hint),
+            ffi::mu_Level::MU_ADVICE => Self::Standard(Level::Advice),
+            _ => Self::Custom(level.name().into()),
+        }
+    }
+}
+
+/// A label of a [`Diagnostic`].
+///
+/// # Example
+/// ```rust
+/// # use musubi::{DiagnosticLabel, LabelSpan};
+/// let label = DiagnosticLabel::new(4..5)
+///     .with_message("never read")
+///     .with_primary(true);
+/// assert_eq!(label.span(), LabelSpan::from(4..5));
+/// assert_eq!(label.message(), Some("never read"));
+/// assert!(label.is_primary());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticLabel<'a> {
+    span: LabelSpan,
+    message: Option<Cow<'a, str>>,
+    primary: bool,
+}
+
+impl<'a> DiagnosticLabel<'a> {
+    /// Create a label at `span`, without message.
+    #[inline]
+    pub fn new<L: Into<LabelSpan>>(span: L) -> Self {
+        Self {
+            span: span.into(),
+            message: None,
+            primary: false,
+        }
+    }
+
+    /// Move the label to `span`.
+    #[inline]
+    #[must_use]
+    pub fn with_span<L: Into<LabelSpan>>(mut self, span: L) -> Self {
+        self.span = span.into();
+        self
+    }
+
+    /// Set the message of the label.
+    #[inline]
+    #[must_use]
+    pub fn with_message(mut self, msg: impl Into<Cow<'a, str>>) -> Self {
+        self.message = Some(msg.into());
+        self
+    }
+
+    /// Mark the label as the primary label of its group.
+    ///
+    /// See [`Report::with_primary_label`](crate::Report::with_primary_label).
+    #[inline]
+    #[must_use]
+    pub fn with_primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// Span of the label.
+    #[inline]
+    pub fn span(&self) -> LabelSpan {
+        self.span
+    }
+
+    /// Message of the label, if any.
+    #[inline]
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Whether the label is a primary label.
+    #[inline]
+    pub fn is_primary(&self) -> bool {
+        self.primary
+    }
+
+    /// Set the span of the label in place.
+    pub(crate) fn set_span(&mut self, span: LabelSpan) {
+        self.span = span;
+    }
+
+    /// Set the message of the label in place.
+    pub(crate) fn set_message(&mut self, msg: impl Into<Cow<'a, str>>) {
+        self.message = Some(msg.into());
+    }
+
+    /// Take the message out of the label.
+    pub(crate) fn into_message(self) -> Option<Cow<'a, str>> {
+        self.message
+    }
+
+    /// Take ownership of all borrowed strings.
+    pub fn into_owned(self) -> DiagnosticLabel<'static> {
+        DiagnosticLabel {
+            span: self.span,
+            message: self.message.map(|m| Cow::Owned(m.into_owned())),
+            primary: self.primary,
+        }
+    }
+}
+
+/// How confident a [suggestion](DiagnosticSuggestion) is, for tools
+/// applying suggestions automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Applicability {
+    /// The suggestion is definitely what the user intended and can be
+    /// applied automatically.
+    MachineApplicable,
+    /// The suggestion may not be what the user intended, and should be
+    /// reviewed before applying.
+    #[default]
+    MaybeIncorrect,
+    /// The suggestion contains placeholders like `(...)` that must be
+    /// filled in before applying.
+    HasPlaceholders,
+}
+
+impl Applicability {
+    /// The name of the applicability, e.g. `"MachineApplicable"`.
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::MachineApplicable => "MachineApplicable",
+            Self::MaybeIncorrect => "MaybeIncorrect",
+            Self::HasPlaceholders => "HasPlaceholders",
+        }
+    }
+}
+
+/// A fix suggestion of a [`Diagnostic`]: one or more edits, each replacing
+/// a span with a text, applied together.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Applicability, DiagnosticSuggestion, LabelSpan};
+/// let fix = DiagnosticSuggestion::new(4..5, "_a")
+///     .with_edit(12..13, "_a")
+///     .with_message("rename it")
+///     .with_applicability(Applicability::MachineApplicable);
+/// assert_eq!(fix.edits().len(), 2);
+/// assert_eq!(fix.edits()[1].0, LabelSpan::from(12..13));
+/// assert_eq!(fix.message(), Some("rename it"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticSuggestion<'a> {
+    edits: Vec<(LabelSpan, Cow<'a, str>)>,
+    message: Option<Cow<'a, str>>,
+    applicability: Applicability,
+}
+
+impl<'a> DiagnosticSuggestion<'a> {
+    /// Create a suggestion replacing `span` with `replacement`, without
+    /// message.
+    #[inline]
+    pub fn new<L: Into<LabelSpan>>(span: L, replacement: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            edits: vec![(span.into(), replacement.into())],
+            message: None,
+            applicability: Applicability::default(),
+        }
+    }
+
+    /// Add another edit replacing `span` with `replacement`.
+    #[inline]
+    #[must_use]
+    pub fn with_edit<L: Into<LabelSpan>>(
+        mut self,
+        span: L,
+        replacement: impl Into<Cow<'a, str>>,
+    ) -> Self {
+        self.push_edit(span.into(), replacement.into());
+        self
+    }
+
+    /// Set the message of the suggestion.
+    #[inline]
+    #[must_use]
+    pub fn with_message(mut self, msg: impl Into<Cow<'a, str>>) -> Self {
+        self.message = Some(msg.into());
+        self
+    }
+
+    /// Set the applicability of the suggestion.
+    #[inline]
+    #[must_use]
+    pub fn with_applicability(mut self, applicability: Applicability) -> Self {
+        self.applicability = applicability;
+        self
+    }
+
+    /// The edits, as spans and their replacements, in the order they were
+    /// added.
+    #[inline]
+    pub fn edits(&self) -> &[(LabelSpan, Cow<'a, str>)] {
+        &self.edits
+    }
+
+    /// Message of the suggestion, if any.
+    #[inline]
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Applicability of the suggestion.
+    #[inline]
+    pub fn applicability(&self) -> Applicability {
+        self.applicability
+    }
+
+    /// Add an edit in place.
+    pub(crate) fn push_edit(&mut self, span: LabelSpan, replacement: Cow<'a, str>) {
+        self.edits.push((span, replacement));
+    }
+
+    /// Set the applicability in place.
+    pub(crate) fn set_applicability(&mut self, applicability: Applicability) {
+        self.applicability = applicability;
+    }
+
+    /// Take ownership of all borrowed strings.
+    pub fn into_owned(self) -> DiagnosticSuggestion<'static> {
+        DiagnosticSuggestion {
+            edits: (self.edits.into_iter())
+                .map(|(span, repl)| (span, Cow::Owned(repl.into_owned())))
+                .collect(),
+            message: self.message.map(|m| Cow::Owned(m.into_owned())),
+            applicability: self.applicability,
+        }
+    }
+}
+
+/// The content of a report as plain data.
+///
+/// A [`Report`] only builds the C report and can't be read
+/// back, while a `Diagnostic` holds the title, code, labels, helps, notes
+/// and suggestions of a report, so tools can inspect, transform and store
+/// them before rendering. Convert between the two with
+/// [`Report::from_diagnostic`](crate::Report::from_diagnostic) and
+/// [`Report::to_diagnostic`](crate::Report::to_diagnostic), or their
+/// [`From`] implementations.
+///
+/// Only the content is kept: configuration, colors, ordering and context
+/// spans stay on the report. Unlike reports, diagnostics are [`Send`] and
+/// [`Sync`], so they can be built on worker threads and rendered on another;
+/// see [threads](crate::Report#threads).
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Config, Diagnostic, DiagnosticLabel, Level, Report};
+/// let mut diag = Diagnostic::new(Level::Warning, "unused variable")
+///     .with_code("W001")
+///     .with_label(DiagnosticLabel::new(4..5).with_message("never read"))
+///     .with_help("prefix it with an underscore");
+///
+/// // move every label into the second line
+/// for label in diag.labels_mut() {
+///     *label = label.clone().with_span(label.span().offset(11));
+/// }
+///
+/// let output = Report::from_diagnostic(&diag)
+///     .with_config(Config::new().with_color_disabled())
+///     .render_to_string("let a = 1;\nlet b = 2;")?;
+/// assert!(output.contains("[W001] Warning: unused variable"));
+/// assert!(output.contains("<unknown>:2:5"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic<'a> {
+    level: DiagnosticLevel<'a>,
+    title: Cow<'a, str>,
+    code: Option<Cow<'a, str>>,
+    labels: Vec<DiagnosticLabel<'a>>,
+    helps: Vec<Cow<'a, str>>,
+    notes: Vec<Cow<'a, str>>,
+    suggestions: Vec<DiagnosticSuggestion<'a>>,
+}
+
+impl Default for Diagnostic<'_> {
+    /// An error with an empty title.
+    fn default() -> Self {
+        Self {
+            level: DiagnosticLevel::Standard(Level::Error),
+            title: Cow::Borrowed(""),
+            code: None,
+            labels: Vec::new(),
+            helps: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+}
+
+impl<'a> From<&Report<'a>> for Diagnostic<'a> {
+    /// See [`Report::to_diagnostic`].
+    #[inline]
+    fn from(report: &Report<'a>) -> Self {
+        report.to_diagnostic()
+    }
+}
+
+impl<'a> Diagnostic<'a> {
+    /// Create a diagnostic with the given level and title.
+    ///
+    /// Like [`Report::with_title`](crate::Report::with_title), `level` is a
+    /// [`Level`] or a custom level name.
+    #[inline]
+    pub fn new<L: Into<TitleLevel<'a>>>(level: L, title: impl Into<Cow<'a, str>>) -> Self {
+        Self::default().with_title(level, title)
+    }
+
+    /// Set the level and title.
+    #[inline]
+    #[must_use]
+    pub fn with_title<L: Into<TitleLevel<'a>>>(
+        mut self,
+        level: L,
+        title: impl Into<Cow<'a, str>>,
+    ) -> Self {
+        self.set_title(level.into(), title);
+        self
+    }
+
+    /// Set the error code.
+    #[inline]
+    #[must_use]
+    pub fn with_code(mut self, code: impl Into<Cow<'a, str>>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Add a label.
+    #[inline]
+    #[must_use]
+    pub fn with_label(mut self, label: DiagnosticLabel<'a>) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    /// Add a help message.
+    #[inline]
+    #[must_use]
+    pub fn with_help(mut self, msg: impl Into<Cow<'a, str>>) -> Self {
+        self.helps.push(msg.into());
+        self
+    }
+
+    /// Add a note message.
+    #[inline]
+    #[must_use]
+    pub fn with_note(mut self, msg: impl Into<Cow<'a, str>>) -> Self {
+        self.notes.push(msg.into());
+        self
+    }
+
+    /// Add a fix suggestion.
+    #[inline]
+    #[must_use]
+    pub fn with_suggestion(mut self, suggestion: DiagnosticSuggestion<'a>) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    /// The standard level, or `None` for a custom level.
+    #[inline]
+    pub fn level(&self) -> Option<Level> {
+        match self.level {
+            DiagnosticLevel::Standard(level) => Some(level),
+            DiagnosticLevel::Custom(_) => None,
+        }
+    }
+
+    /// The level name shown in the header, e.g. `"Error"`.
+    #[inline]
+    pub fn level_name(&self) -> &str {
+        match &self.level {
+            DiagnosticLevel::Standard(level) => TitleLevel::from(*level).name(),
+            DiagnosticLevel::Custom(name) => name,
+        }
+    }
+
+    /// The title message.
+    #[inline]
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The error code, if any.
+    #[inline]
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
+    /// The labels, in the order they were added.
+    #[inline]
+    pub fn labels(&self) -> &[DiagnosticLabel<'a>] {
+        &self.labels
+    }
+
+    /// The labels, for changing them in place.
+    #[inline]
+    pub fn labels_mut(&mut self) -> &mut Vec<DiagnosticLabel<'a>> {
+        &mut self.labels
+    }
+
+    /// The help messages.
+    #[inline]
+    pub fn helps(&self) -> &[Cow<'a, str>] {
+        &self.helps
+    }
+
+    /// The note messages.
+    #[inline]
+    pub fn notes(&self) -> &[Cow<'a, str>] {
+        &self.notes
+    }
+
+    /// The fix suggestions.
+    #[inline]
+    pub fn suggestions(&self) -> &[DiagnosticSuggestion<'a>] {
+        &self.suggestions
+    }
+
+    /// Take ownership of all borrowed strings, e.g. to store the diagnostic
+    /// beyond the lifetime of its sources.
+    pub fn into_owned(self) -> Diagnostic<'static> {
+        let owned = |s: Cow<'_, str>| Cow::Owned(s.into_owned());
+        Diagnostic {
+            level: match self.level {
+                DiagnosticLevel::Standard(level) => DiagnosticLevel::Standard(level),
+                DiagnosticLevel::Custom(name) => DiagnosticLevel::Custom(owned(name)),
+            },
+            title: owned(self.title),
+            code: self.code.map(owned),
+            labels: self
+                .labels
+                .into_iter()
+                .map(DiagnosticLabel::into_owned)
+                .collect(),
+            helps: self.helps.into_iter().map(owned).collect(),
+            notes: self.notes.into_iter().map(owned).collect(),
+            suggestions: (self.suggestions.into_iter())
+                .map(DiagnosticSuggestion::into_owned)
+                .collect(),
+        }
+    }
+
+    /// Every label and suggestion span.
+    pub(crate) fn spans(&self) -> impl Iterator<Item = LabelSpan> + '_ {
+        let fixes = self.suggestions.iter().flat_map(|fix| &fix.edits);
+        (self.labels.iter().map(|label| label.span)).chain(fixes.map(|(span, _)| *span))
+    }
+
+    /// Replace every label and suggestion span with `f(span)`.
+    pub(crate) fn map_spans(&mut self, mut f: impl FnMut(LabelSpan) -> LabelSpan) {
+        for label in &mut self.labels {
+            label.span = f(label.span);
+        }
+        for fix in &mut self.suggestions {
+            for (span, _) in &mut fix.edits {
+                *span = f(*span);
+            }
+        }
+    }
+
+    /// The level to pass to the C report.
+    pub(crate) fn title_level(&self) -> TitleLevel<'_> {
+        match &self.level {
+            DiagnosticLevel::Standard(level) => (*level).into(),
+            DiagnosticLevel::Custom(name) => name.as_ref().into(),
+        }
+    }
+
+    /// Set the level and title in place.
+    pub(crate) fn set_title(&mut self, level: TitleLevel<'a>, title: impl Into<Cow<'a, str>>) {
+        self.level = level.into();
+        self.title = title.into();
+    }
+
+    /// Set the code in place.
+    pub(crate) fn set_code(&mut self, code: &'a str) {
+        self.code = Some(code.into());
+    }
+
+    /// Add a help message in place.
+    pub(crate) fn push_help(&mut self, msg: Cow<'a, str>) {
+        self.helps.push(msg);
+    }
+
+    /// Add a note message in place.
+    pub(crate) fn push_note(&mut self, msg: Cow<'a, str>) {
+        self.notes.push(msg);
+    }
+
+    /// The suggestions, for adding or changing them in place.
+    pub(crate) fn suggestions_mut(&mut self) -> &mut Vec<DiagnosticSuggestion<'a>> {
+        &mut self.suggestions
+    }
+}
@@ -0,0 +1,1024 @@
+//! Rendering several reports at once.
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, IsTerminal, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::ptr;
+
+use crate::{
+    Config, ConfigPatch, Coverage, Diagnostic, IndexType, Level, Policy, RawCache, Report,
+    TitleLevel, ffi, load_source, source_location, source_name,
+};
+
+/// A report of an [`Emitter`] rendered on its own, with its structured data.
+pub(crate) struct Rendered<'e> {
+    /// Level name of the report, e.g. `"Error"`.
+    pub(crate) level: &'e str,
+    /// Whether the report has the standard error level.
+    pub(crate) error: bool,
+    pub(crate) title: &'e str,
+    pub(crate) code: Option<&'e str>,
+    /// Name of the source of the first label, if any.
+    pub(crate) file: Option<String>,
+    /// 1-based line and column of the first label, if any.
+    pub(crate) location: Option<(usize, usize)>,
+    pub(crate) text: String,
+}
+
+/// The 1-based lines covered by the labels of a report, by cache and source.
+type LineCoverage = HashMap<(usize, u32), Coverage>;
+
+/// The [`LineCoverage`] of the labels of `diag`, as far as they resolve in
+/// `cache`.
+fn label_lines(
+    diag: &Diagnostic<'_>,
+    cache: *mut ffi::mu_Cache,
+    index_type: IndexType,
+) -> LineCoverage {
+    let mut lines: HashMap<_, Vec<_>> = HashMap::new();
+    for label in diag.labels() {
+        let (span, id) = (label.span(), label.span().src_id);
+        let last = span.end.saturating_sub(1).max(span.start);
+        let Some((first, _)) = source_location(cache, id, span.start, index_type) else {
+            continue;
+        };
+        let Some((last, _)) = source_location(cache, id, last, index_type) else {
+            continue;
+        };
+        let key = (cache as usize, id.0);
+        lines.entry(key).or_default().push(first..last + 1);
+    }
+    (lines.into_iter())
+        .map(|(key, ranges)| (key, Coverage::new(ranges, usize::MAX)))
+        .collect()
+}
+
+/// Whether `a` and `b` cover a common line.
+fn share_lines(a: &LineCoverage, b: &LineCoverage) -> bool {
+    (a.iter()).any(|(key, a)| {
+        b.get(key).is_some_and(|b| {
+            let ranges = a.covered().iter().chain(b.covered()).cloned();
+            !Coverage::new(ranges, usize::MAX).is_disjoint()
+        })
+    })
+}
+
+/// Renders a batch of reports against the same sources.
+///
+/// Reports are built as usual and added with
+/// [`with_report`](Emitter::with_report) or [`push`](Emitter::push).
+/// Rendering emits them one after another, in the order they were added,
+/// each with its own config, colors and suggestions. Reports without a
+/// config use the one of the emitter.
+///
+/// With [grouping](Emitter::with_grouping) enabled, reports whose labels
+/// target the same lines of a source are merged into one report, so the
+/// shared lines are only shown once with all labels stacked on them (like
+/// clippy does for several lints on one expression). The merged report is
+/// built from the [content](Report::to_diagnostic) of its reports and uses
+/// the header and config of its first report; the headers of the others are
+/// added as notes, followed by their helps and notes.
+///
+/// Compiler frontends find diagnostics in many places during a pass; each
+/// place can build its own [`Report`] and [`push`](Emitter::push) it, and
+/// the emitter renders them all at the end, optionally followed by a
+/// [summary](Emitter::with_summary) line like `2 errors, 1 warning emitted`.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Config, Emitter, Level, Report};
+/// let mut emitter = Emitter::new()
+///     .with_config(Config::new().with_color_disabled())
+///     .with_grouping(true)
+///     .with_report(
+///         Report::new()
+///             .with_title(Level::Warning, "unused variable")
+///             .with_label(4..5)
+///             .with_message("never read"),
+///     )
+///     .with_report(
+///         Report::new()
+///             .with_title(Level::Warning, "variable does not need to be mutable")
+///             .with_label(4..5)
+///             .with_message("remove `mut`"),
+///     );
+///
+/// let output = emitter.render_to_string("let x = 42;")?;
+/// assert_eq!(output.matches("let x = 42;").count(), 1);
+/// assert!(output.contains("Note: Warning: variable does not need to be mutable"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Default)]
+pub struct Emitter<'a> {
+    config: Option<Config<'a>>,
+    grouping: bool,
+    changed_lines: Option<HashMap<PathBuf, Vec<RangeInclusive<usize>>>>,
+    policy: Option<Policy>,
+    pager: Option<String>,
+    summary: bool,
+    reports: Vec<Report<'a>>,
+}
+
+impl<'a> Emitter<'a> {
+    /// Create a new emitter without any reports.
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Configure the reports rendered by this emitter that have no config
+    /// of their own.
+    ///
+    /// see [`Config`] for configuration options.
+    #[inline]
+    #[must_use]
+    pub fn with_config(mut self, config: Config<'a>) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Enable or disable merging of reports with labels on the same lines.
+    ///
+    /// Default: [`false`]
+    #[inline]
+    #[must_use]
+    pub fn with_grouping(mut self, enabled: bool) -> Self {
+        self.grouping = enabled;
+        self
+    }
+
+    /// Only emit reports on changed lines.
+    ///
+    /// `map` gives, for each changed source (by name), the changed 1-based
+    /// line ranges, e.g. from the hunks of a diff. Reports whose primary
+    /// label (or first label, if none is primary) starts outside of these
+    /// lines are dropped, including reports on sources missing from `map`.
+    /// Reports without labels are always emitted.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::collections::HashMap;
+    /// # use std::path::PathBuf;
+    /// # use musubi::{Emitter, Level, Report};
+    /// let changed = HashMap::from([(PathBuf::from("main.rs"), vec![2..=2])]);
+    /// let mut emitter = Emitter::new()
+    ///     .with_changed_lines(changed)
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Warning, "old code")
+    ///             .with_label(0..3),
+    ///     )
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Warning, "new code")
+    ///             .with_label(11..14),
+    ///     );
+    ///
+    /// let output = emitter.render_to_string(("let a = 1;\nlet b = 2;", "main.rs"))?;
+    /// assert!(!output.contains("old code"));
+    /// assert!(output.contains("new code"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_changed_lines(mut self, map: HashMap<PathBuf, Vec<RangeInclusive<usize>>>) -> Self {
+        self.changed_lines = Some(map);
+        self
+    }
+
+    /// Apply a severity policy to all reports.
+    ///
+    /// See [`Policy`] for details.
+    #[inline]
+    #[must_use]
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Page the output of [`render_to_stdout`](Emitter::render_to_stdout)
+    /// through `command` when it doesn't fit on the screen.
+    ///
+    /// When stdout is a terminal, output taller than the screen is piped
+    /// through `command`, a program name followed by its arguments split at
+    /// whitespace, so long lint runs can be scrolled and searched. No pager
+    /// is run unless one is given here: pass the value of `$PAGER` to
+    /// follow the choice of the user. `less -R` keeps colors; other pagers
+    /// may need options of their own. An empty command disables paging,
+    /// and the output is written directly if the pager can't be started.
+    ///
+    /// The screen height is read from `$LINES` if set, or else asked from
+    /// the terminal of stdout.
+    ///
+    /// Default: no pager
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use musubi::{Emitter, Level, Report};
+    /// let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".into());
+    /// let mut emitter = Emitter::new().with_pager(pager);
+    /// for _ in 0..100 {
+    ///     emitter.push(
+    ///         Report::new()
+    ///             .with_title(Level::Warning, "unused import")
+    ///             .with_label(0..3),
+    ///     );
+    /// }
+    /// emitter.render_to_stdout("use std::io;")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_pager(mut self, command: impl Into<String>) -> Self {
+        self.pager = Some(command.into());
+        self
+    }
+
+    /// Render a [summary](Emitter::summary) line after the reports.
+    ///
+    /// Default: [`false`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Emitter, Level, Report};
+    /// let mut emitter = Emitter::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_summary(true)
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Error, "cannot find value `y`")
+    ///             .with_label(15..16),
+    ///     )
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Warning, "unused variable: `x`")
+    ///             .with_label(8..9),
+    ///     );
+    ///
+    /// let output = emitter.render_to_string("let mut x = 1; y;")?;
+    /// assert!(output.ends_with("───╯\n1 error, 1 warning emitted\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_summary(mut self, enabled: bool) -> Self {
+        self.summary = enabled;
+        self
+    }
+
+    /// Add a report, for building an emitter in a chain.
+    #[inline]
+    #[must_use]
+    pub fn with_report(mut self, report: Report<'a>) -> Self {
+        self.push(report);
+        self
+    }
+
+    /// Add a report.
+    #[inline]
+    pub fn push(&mut self, report: Report<'a>) {
+        self.reports.push(report);
+    }
+
+    /// The reports, in the order they were added.
+    #[inline]
+    pub fn reports(&self) -> &[Report<'a>] {
+        &self.reports
+    }
+
+    /// The number of reports.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.reports.len()
+    }
+
+    /// Whether the emitter has no reports.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.reports.is_empty()
+    }
+
+    /// The number of reports emitted as errors under the [policy](Emitter::with_policy).
+    ///
+    /// Errors dropped by `max_errors` or
+    /// [`with_changed_lines`](Emitter::with_changed_lines) are still counted,
+    /// which makes this suitable for deciding the exit status of a tool.
+    pub fn error_count(&self) -> usize {
+        self.counted().filter(|r| self.level(r).is_error()).count()
+    }
+
+    /// The number of reports emitted as warnings under the
+    /// [policy](Emitter::with_policy), counted like
+    /// [`error_count`](Emitter::error_count).
+    pub fn warning_count(&self) -> usize {
+        self.counted()
+            .filter(|r| self.level(r).is_warning())
+            .count()
+    }
+
+    /// The summary line rendered after the reports, without newline, e.g.
+    /// `"3 errors, 2 warnings emitted"`, or `None` if there are no reports.
+    ///
+    /// Every level is counted, errors first, then warnings, then the other
+    /// levels in the order they first appear, including custom ones. Reports
+    /// are counted like in [`error_count`](Emitter::error_count).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Emitter, Level, Report};
+    /// let emitter = Emitter::new()
+    ///     .with_report(Report::new().with_title(Level::Warning, "unused import"))
+    ///     .with_report(Report::new().with_title("Lint", "needless borrow"))
+    ///     .with_report(Report::new().with_title(Level::Warning, "unused variable"));
+    /// assert_eq!(emitter.summary().as_deref(), Some("2 warnings, 1 lint emitted"));
+    /// assert_eq!(Emitter::new().summary(), None);
+    /// ```
+    pub fn summary(&self) -> Option<String> {
+        let mut counts: Vec<(String, usize)> = vec![("error".into(), 0), ("warning".into(), 0)];
+        for report in self.counted() {
+            let name = self.level(report).name().to_lowercase();
+            match counts.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((name, 1)),
+            }
+        }
+        let parts: Vec<String> = (counts.into_iter())
+            .filter(|&(_, count)| count > 0)
+            .map(|(name, count)| match count {
+                1 => format!("1 {name}"),
+                n => format!("{n} {name}s"),
+            })
+            .collect();
+        (!parts.is_empty()).then(|| format!("{} emitted", parts.join(", ")))
+    }
+
+    /// Render all reports into a string, followed by the summary if
+    /// [enabled](Emitter::with_summary).
+    ///
+    /// See [`Report::render_to_string`] for the accepted caches.
+    pub fn render_to_string(&mut self, cache: impl Into<RawCache>) -> io::Result<String> {
+        let mut writer = Vec::new();
+        self.render_to_writer(&mut writer, cache)?;
+        Ok(String::from_utf8(writer)
+            .unwrap_or_else(|e| String::from_utf8_lossy(&e.into_bytes()).into_owned()))
+    }
+
+    /// Render all reports and the summary to stdout, through a pager if
+    /// [set](Emitter::with_pager).
+    ///
+    /// See [`Report::render_to_stdout`] for the accepted caches.
+    pub fn render_to_stdout(&mut self, cache: impl Into<RawCache>) -> io::Result<()> {
+        let stdout = io::stdout();
+        let Some(pager) = self.pager.clone().filter(|_| stdout.is_terminal()) else {
+            return self.render_to_writer(&mut stdout.lock(), cache);
+        };
+        let output = self.render_to_string(cache)?;
+        if output.lines().count() >= screen_height() && page(&pager, &output)? {
+            return Ok(());
+        }
+        stdout.lock().write_all(output.as_bytes())
+    }
+
+    /// Render all reports and the summary to any type implementing `Write`.
+    ///
+    /// See [`Report::render_to_writer`] for the accepted caches.
+    pub fn render_to_writer<W: Write>(
+        &mut self,
+        writer: &mut W,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        self.render_each(cache, false, |rendered| {
+            writer.write_all(rendered.text.as_bytes())
+        })?;
+        match self.summary.then(|| self.summary()).flatten() {
+            Some(summary) => writeln!(writer, "{summary}"),
+            None => Ok(()),
+        }
+    }
+
+    /// Render all reports in place of the reports rendered last time with
+    /// `state`, for `--watch` loops.
+    ///
+    /// Instead of printing every report again and flooding the scrollback,
+    /// the cursor is moved back to the first line that changed since the
+    /// last render, the screen is cleared from there with ANSI escape codes,
+    /// and only the lines after it are written. Nothing is written if the
+    /// output is unchanged. Reports that didn't change, and whose sources
+    /// didn't either, are not rendered again: their output is reused from
+    /// the last render.
+    ///
+    /// Nothing else may be written below the reports between two renders,
+    /// and their lines should not wrap; set a
+    /// [width limit](Config::with_limit_width) below the terminal width.
+    /// See [`Report::render_to_writer`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Emitter, Level, Report, WatchState};
+    /// let mut state = WatchState::new();
+    /// let mut out = Vec::new();
+    /// for source in ["let x = 1;", "let x = 1;", "let x = 2;"] {
+    ///     out.clear();
+    ///     Emitter::new()
+    ///         .with_config(Config::new().with_color_disabled())
+    ///         .with_report(
+    ///             Report::new()
+    ///                 .with_title(Level::Warning, "unused variable")
+    ///                 .with_label(4..5),
+    ///         )
+    ///         .render_watch(&mut state, &mut out, source)?;
+    /// }
+    /// // the last run only redraws from the changed line on
+    /// let out = String::from_utf8(out).unwrap();
+    /// assert!(out.starts_with("\x1b[3F\x1b[J"));
+    /// assert!(out.contains("let x = 2;"));
+    /// assert!(!out.contains("unused variable"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_watch<W: Write>(
+        &mut self,
+        state: &mut WatchState,
+        writer: &mut W,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        let cache = cache.into();
+        self.relayout(&cache);
+        let groups = self.groups(&self.kept(cache.as_ptr()), cache.as_ptr());
+        let mut sources = HashMap::new();
+        let mut rendered = HashMap::with_capacity(groups.len());
+        let mut frame = String::new();
+        for group in &groups {
+            let key = self.group_key(group, cache.as_ptr(), &mut sources);
+            let text = match state.rendered.remove(&key) {
+                Some(text) => text,
+                None => self.render_group(group, cache.as_ptr(), false)?,
+            };
+            frame.push_str(&text);
+            rendered.insert(key, text);
+        }
+        state.rendered = rendered;
+        if frame == state.frame {
+            return Ok(());
+        }
+
+        let old: Vec<&str> = state.frame.split_inclusive('\n').collect();
+        let new: Vec<&str> = frame.split_inclusive('\n').collect();
+        let same = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        if old.len() > same {
+            write!(writer, "\x1b[{}F", old.len() - same)?;
+        }
+        writer.write_all(b"\x1b[J")?;
+        for line in &new[same..] {
+            writer.write_all(line.as_bytes())?;
+        }
+        writer.flush()?;
+        state.frame = frame;
+        Ok(())
+    }
+
+    /// Render every (merged) report into its own string and pass it to `f`
+    /// along with the report's structured data.
+    ///
+    /// With `plain` set, colors are disabled regardless of the config.
+    pub(crate) fn render_each<F>(
+        &mut self,
+        cache: impl Into<RawCache>,
+        plain: bool,
+        mut f: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(Rendered<'_>) -> io::Result<()>,
+    {
+        let cache = cache.into();
+        self.relayout(&cache);
+        let groups = self.groups(&self.kept(cache.as_ptr()), cache.as_ptr());
+        for group in &groups {
+            let text = self.render_group(group, cache.as_ptr(), plain)?;
+            let first = &self.reports[group[0]];
+            let level = self.level(first);
+            let src_cache = first.resolve_cache(RawCache::Borrowed(cache.as_ptr()));
+            let diag = first.diagnostic_in(src_cache.as_ptr());
+            let label = diag.labels().first().map(|l| l.span());
+            f(Rendered {
+                level: level.name(),
+                error: level.is_error(),
+                title: first.diagnostic.title(),
+                code: first.diagnostic.code(),
+                file: label.and_then(|span| source_name(src_cache.as_ptr(), span.src_id)),
+                location: label.and_then(|span| {
+                    let index_type = self.index_type(first);
+                    source_location(src_cache.as_ptr(), span.src_id, span.start, index_type)
+                }),
+                text,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The reports counted in the [summary](Emitter::summary): those not
+    /// dropped by the policy.
+    fn counted(&self) -> impl Iterator<Item = &Report<'a>> {
+        let policy = self.policy.as_ref();
+        (self.reports.iter())
+            .filter(move |r| !policy.is_some_and(|p| p.is_allowed(r.diagnostic.code())))
+    }
+
+    /// Let the reports lay out again if `cache` is a temporary one, which
+    /// may reuse the address of another cache.
+    fn relayout(&mut self, cache: &RawCache) {
+        if let RawCache::Owned(_) = cache {
+            for report in &self.reports {
+                // SAFETY: report.ptr is a valid mu_Report pointer
+                unsafe { ffi::mu_relayout(report.ptr) };
+            }
+        }
+    }
+
+    /// The index type of the config `report` is rendered with.
+    fn index_type(&self, report: &Report<'a>) -> IndexType {
+        (report.config.as_ref().or(self.config.as_ref()))
+            .map_or(IndexType::Char, Config::index_type)
+    }
+
+    /// Split the reports into groups to render as one report each.
+    ///
+    /// Reports not marked in `keep` are left out.
+    fn groups(&self, keep: &[bool], cache: *mut ffi::mu_Cache) -> Vec<Vec<usize>> {
+        let lines: Vec<_> = (self.reports.iter())
+            .map(|report| match self.grouping {
+                true => {
+                    let cache = report.resolve_cache(RawCache::Borrowed(cache));
+                    let diag = report.diagnostic_in(cache.as_ptr());
+                    label_lines(&diag, cache.as_ptr(), self.index_type(report))
+                }
+                false => LineCoverage::new(),
+            })
+            .collect();
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for i in 0..self.reports.len() {
+            if !keep[i] {
+                continue;
+            }
+            if !self.grouping {
+                groups.push(vec![i]);
+                continue;
+            }
+            let mut merged: Option<usize> = None;
+            let mut g = 0;
+            while g < groups.len() {
+                if !groups[g].iter().any(|&j| share_lines(&lines[j], &lines[i])) {
+                    g += 1;
+                    continue;
+                }
+                match merged {
+                    None => {
+                        groups[g].push(i);
+                        merged = Some(g);
+                        g += 1;
+                    }
+                    Some(m) => {
+                        let other = groups.remove(g);
+                        groups[m].extend(other);
+                    }
+                }
+            }
+            if merged.is_none() {
+                groups.push(vec![i]);
+            }
+        }
+        for group in &mut groups {
+            group.sort_unstable();
+        }
+        groups
+    }
+
+    /// Whether `report` is emitted as an error by the policy, though it
+    /// isn't one.
+    fn is_escalated(&self, report: &Report<'a>) -> bool {
+        let level = report.diagnostic.title_level();
+        !level.is_error()
+            && (self.policy.as_ref())
+                .is_some_and(|p| p.is_denied(report.diagnostic.code(), level.is_warning()))
+    }
+
+    /// The level `report` is emitted at under the policy.
+    fn level<'r>(&self, report: &'r Report<'a>) -> TitleLevel<'r> {
+        match self.is_escalated(report) {
+            true => Level::Error.into(),
+            false => report.diagnostic.title_level(),
+        }
+    }
+
+    /// Which reports to emit, according to the policy and changed lines.
+    fn kept(&self, cache: *mut ffi::mu_Cache) -> Vec<bool> {
+        let mut errors = 0;
+        (self.reports.iter())
+            .map(|report| {
+                let policy = self.policy.as_ref();
+                if policy.is_some_and(|p| p.is_allowed(report.diagnostic.code()))
+                    || !self.is_changed(report, cache)
+                {
+                    return false;
+                }
+                if self.level(report).is_error() {
+                    errors += 1;
+                    let max = policy.and_then(|p| p.max_errors);
+                    return max.is_none_or(|max| errors <= max);
+                }
+                true
+            })
+            .collect()
+    }
+
+    /// Whether `report` is on a changed line, see
+    /// [`with_changed_lines`](Emitter::with_changed_lines).
+    fn is_changed(&self, report: &Report<'a>, cache: *mut ffi::mu_Cache) -> bool {
+        let Some(map) = &self.changed_lines else {
+            return true;
+        };
+        let cache = report.resolve_cache(RawCache::Borrowed(cache));
+        let diag = report.diagnostic_in(cache.as_ptr());
+        let primary = diag.labels().iter().find(|l| l.is_primary());
+        let Some(label) = primary.or(diag.labels().first()) else {
+            return true;
+        };
+        let span = label.span();
+        let (Some(name), Some((line, _))) = (
+            source_name(cache.as_ptr(), span.src_id),
+            source_location(
+                cache.as_ptr(),
+                span.src_id,
+                span.start,
+                self.index_type(report),
+            ),
+        ) else {
+            return false;
+        };
+        map.get(Path::new(&name))
+            .is_some_and(|ranges| ranges.iter().any(|r| r.contains(&line)))
+    }
+
+    /// A hash of everything the output of `group` depends on, including the
+    /// text of the sources it points into, hashed once in `sources`.
+    fn group_key(
+        &self,
+        group: &[usize],
+        cache: *mut ffi::mu_Cache,
+        sources: &mut HashMap<(usize, usize), u64>,
+    ) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for &i in group {
+            let report = &self.reports[i];
+            let cache = report.resolve_cache(RawCache::Borrowed(cache));
+            let diag = report.diagnostic_in(cache.as_ptr());
+            (self.level(report).name(), diag.title(), diag.code()).hash(&mut hasher);
+            (diag.helps(), diag.notes()).hash(&mut hasher);
+            for fix in diag.suggestions() {
+                let edits = fix
+                    .edits()
+                    .iter()
+                    .map(|(span, repl)| (span.start, span.end, repl));
+                (edits.collect::<Vec<_>>(), fix.message()).hash(&mut hasher);
+            }
+            for label in diag.labels() {
+                let span = label.span();
+                (
+                    span.start,
+                    span.end,
+                    span.src_id(),
+                    label.message(),
+                    label.is_primary(),
+                )
+                    .hash(&mut hasher);
+                let src_id = span.src_id();
+                let text = *sources
+                    .entry((cache.as_ptr() as usize, src_id))
+                    .or_insert_with(|| source_hash(cache.as_ptr(), src_id));
+                text.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    /// The header of a merged report, shown as a note of its group.
+    fn header(&self, i: usize) -> String {
+        let report = &self.reports[i];
+        let level = self.level(report).name();
+        let title = report.diagnostic.title();
+        match report.diagnostic.code() {
+            Some(code) => format!("[{code}] {level}: {title}"),
+            None => format!("{level}: {title}"),
+        }
+    }
+
+    /// The config to render `report` with in place of its own, if it must
+    /// be replaced: by the config of the emitter if it has none, and
+    /// without colors if `plain` is set.
+    fn config_for(&self, report: &Report<'a>, plain: bool) -> Option<Config<'a>> {
+        let config = report.config.as_ref().or(self.config.as_ref());
+        match plain {
+            true => {
+                let patch = ConfigPatch {
+                    color: Some(false),
+                    ..Default::default()
+                };
+                Some(config.cloned().unwrap_or_default().merge(&patch))
+            }
+            false if report.config.is_none() => config.cloned(),
+            false => None,
+        }
+    }
+
+    /// Render `group` into a string, as one report.
+    fn render_group(
+        &mut self,
+        group: &[usize],
+        cache: *mut ffi::mu_Cache,
+        plain: bool,
+    ) -> io::Result<String> {
+        let &[i] = group else {
+            let first = &self.reports[group[0]];
+            let cache = first.resolve_cache(RawCache::Borrowed(cache));
+            let diags: Vec<Diagnostic<'a>> = (group.iter())
+                .map(|&i| self.reports[i].diagnostic_in(cache.as_ptr()))
+                .collect();
+            let headers: Vec<String> = group[1..].iter().map(|&i| self.header(i)).collect();
+            let mut report = self.build(group, &diags, &headers, plain);
+            return report.render_to_string(RawCache::Borrowed(cache.as_ptr()));
+        };
+        let config = self.config_for(&self.reports[i], plain);
+        let escalated = self.is_escalated(&self.reports[i]);
+        let report = &mut self.reports[i];
+        let saved = config.map(|config| report.config.replace(config));
+        if escalated {
+            retitle(report, Level::Error.into());
+        }
+        let result = report.render_to_string(RawCache::Borrowed(cache));
+        if escalated {
+            retitle(report, report.diagnostic.title_level());
+        }
+        if let Some(saved) = saved {
+            report.config = saved;
+            if report.config.is_none() {
+                // SAFETY: report.ptr is valid; null restores the default
+                // config in place of the one dropped above
+                unsafe { ffi::mu_config(report.ptr, ptr::null()) };
+            }
+        }
+        result
+    }
+
+    /// The report merging the reports of `group`, with their contents
+    /// `diags` and the headers of all but the first in `headers`.
+    fn build<'b>(
+        &'b self,
+        group: &[usize],
+        diags: &'b [Diagnostic<'a>],
+        headers: &'b [String],
+        plain: bool,
+    ) -> Report<'b> {
+        let first = &self.reports[group[0]];
+        let mut report = Report::new().with_title(self.level(first), diags[0].title());
+        let config = match self.config_for(first, plain) {
+            Some(config) => Some(config),
+            None => first.config.clone(),
+        };
+        if let Some(config) = config {
+            report = report.with_config(config);
+        }
+        if let Some(code) = diags[0].code() {
+            report = report.with_code(code);
+        }
+        let auto_priority = (report.config.as_ref()).is_some_and(Config::is_auto_priority);
+        for (&i, diag) in group.iter().zip(diags) {
+            let severity = self.level(&self.reports[i]).severity();
+            for label in diag.labels() {
+                report = match label.is_primary() {
+                    true => report.with_primary_label(label.span()),
+                    false => report.with_label(label.span()),
+                };
+                if let Some(msg) = label.message() {
+                    report = report.with_message(msg);
+                }
+                if auto_priority {
+                    report = report.with_priority(severity);
+                }
+            }
+        }
+        report = report
+            .with_helps(diags[0].helps().iter().map(|h| &**h))
+            .with_suggestions_of(&diags[0])
+            .with_notes(diags[0].notes().iter().map(|n| &**n));
+        for (diag, header) in diags[1..].iter().zip(headers) {
+            report = report
+                .with_note(header)
+                .with_helps(diag.helps().iter().map(|h| &**h))
+                .with_suggestions_of(diag)
+                .with_notes(diag.notes().iter().map(|n| &**n));
+        }
+        report
+    }
+}
+
+/// Set the level of the header of `report`, keeping its title.
+fn retitle(report: &Report<'_>, level: TitleLevel<'_>) {
+    // SAFETY: report.ptr is valid, and the title is kept by the report; the
+    // level name is only read while rendering, before the level is restored
+    unsafe {
+        ffi::mu_title(
+            report.ptr,
+            level.level,
+            level.custom_name,
+            report.diagnostic.title().into(),
+        )
+    };
+}
+
+impl<'a> Extend<Report<'a>> for Emitter<'a> {
+    fn extend<I: IntoIterator<Item = Report<'a>>>(&mut self, iter: I) {
+        self.reports.extend(iter);
+    }
+}
+
+impl<'a> FromIterator<Report<'a>> for Emitter<'a> {
+    fn from_iter<I: IntoIterator<Item = Report<'a>>>(iter: I) -> Self {
+        Self {
+            reports: iter.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The height of the terminal, by `$LINES` or by asking the terminal of
+/// stdout, or 24 if neither is known.
+fn screen_height() -> usize {
+    let lines = std::env::var("LINES").ok();
+    (lines.and_then(|lines| lines.trim().parse().ok()))
+        .or_else(terminal_height)
+        .filter(|&lines| lines > 0)
+        .unwrap_or(24)
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly",
+))]
+fn terminal_height() -> Option<usize> {
+    use std::os::fd::AsRawFd;
+    use std::os::raw::{c_int, c_ulong, c_ushort};
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Winsize {
+        ws_row: c_ushort,
+        ws_col: c_ushort,
+        ws_xpixel: c_ushort,
+        ws_ypixel: c_ushort,
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const TIOCGWINSZ: c_ulong = 0x5413;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const TIOCGWINSZ: c_ulong = 0x4008_7468;
+    unsafe extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+    let mut size = Winsize::default();
+    // SAFETY: TIOCGWINSZ fills a winsize, and fails for files that are not
+    // terminals
+    let ok = unsafe { ioctl(io::stdout().as_raw_fd(), TIOCGWINSZ, &mut size) } == 0;
+    ok.then_some(size.ws_row.into())
+}
+
+#[cfg(windows)]
+fn terminal_height() -> Option<usize> {
+    use std::os::windows::io::{AsRawHandle, RawHandle};
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Coord {
+        x: i16,
+        y: i16,
+    }
+    #[repr(C)]
+    #[derive(Default)]
+    struct SmallRect {
+        left: i16,
+        top: i16,
+        right: i16,
+        bottom: i16,
+    }
+    #[repr(C)]
+    #[derive(Default)]
+    struct ScreenBufferInfo {
+        size: Coord,
+        cursor_position: Coord,
+        attributes: u16,
+        window: SmallRect,
+        maximum_window_size: Coord,
+    }
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetConsoleScreenBufferInfo(console: RawHandle, info: *mut ScreenBufferInfo) -> i32;
+    }
+    let mut info = ScreenBufferInfo::default();
+    // SAFETY: the handle is the standard output of this process, and
+    // GetConsoleScreenBufferInfo fails for handles that are not consoles
+    let ok = unsafe { GetConsoleScreenBufferInfo(io::stdout().as_raw_handle(), &mut info) } != 0;
+    let rows = i32::from(info.window.bottom) - i32::from(info.window.top) + 1;
+    ok.then(|| usize::try_from(rows).ok()).flatten()
+}
+
+#[cfg(not(any(
+    windows,
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly",
+)))]
+fn terminal_height() -> Option<usize> {
+    None
+}
+
+/// Write `output` through the pager `command`.
+///
+/// Returns `false` if the pager can't be run, leaving `output` unwritten.
+fn page(command: &str, output: &str) -> io::Result<bool> {
+    let mut args = command.split_whitespace();
+    let Some(program) = args.next() else {
+        return Ok(false);
+    };
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn();
+    let Ok(mut child) = child else {
+        return Ok(false);
+    };
+    let result = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(output.as_bytes()),
+        None => Ok(()),
+    };
+    child.wait()?;
+    match result {
+        // the user quit the pager before reading everything
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(true),
+        result => result.map(|()| true),
+    }
+}
+
+/// A hash of the name and lines of source `src_id`, or 0 if it fails to load.
+fn source_hash(cache: *mut ffi::mu_Cache, src_id: usize) -> u64 {
+    let Some(src) = load_source(cache, src_id.into()) else {
+        return 0;
+    };
+    let mut hasher = DefaultHasher::new();
+    source_name(cache, src_id.into()).hash(&mut hasher);
+    // SAFETY: src was loaded above, so its functions are set
+    unsafe {
+        let count = (*src).line_count.map_or(0, |f| f(src));
+        for line_no in 0..count {
+            let line: &[u8] = ((*src).get_line.unwrap())(src, line_no).into();
+            line.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// What an [`Emitter`] rendered last time in [watch mode](Emitter::render_watch).
+///
+/// Keep one across the runs of a `--watch` loop.
+#[derive(Debug, Default)]
+pub struct WatchState {
+    /// The output of the last render, still on screen
+    frame: String,
+    /// Output of each report group, by the hash of its content
+    rendered: HashMap<u64, String>,
+}
+
+impl WatchState {
+    /// Create a state with nothing rendered yet.
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Forget the reports on screen, e.g. after something else was printed
+    /// below them, so the next render starts on a fresh region.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.frame.clear();
+    }
+}
@@ -0,0 +1,109 @@
+//! Configuration overrides read from environment variables.
+
+use crate::{CharSet, Config, Theme};
+
+/// A boolean value of a variable: `1`, `true`, `yes` or `on`, and `0`,
+/// `false`, `no` or `off`.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl<'a> Config<'a> {
+    /// Create a configuration from the `MUSUBI_*` environment variables,
+    /// see [`with_env`](Config::with_env).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// // MUSUBI_CHARSET=ascii MUSUBI_COLOR=never my-tool
+    /// let output = Report::new()
+    ///     .with_config(Config::from_env())
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(8..9)
+    ///     .render_to_string("let a = b;")?;
+    /// assert!(output.contains("unknown name"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    pub fn from_env() -> Self {
+        Self::new().with_env()
+    }
+
+    /// Override options by the `MUSUBI_*` environment variables, so end
+    /// users of a tool can adjust its diagnostics without the tool
+    /// exposing flags:
+    ///
+    /// | Variable               | Values                                     |
+    /// |------------------------|--------------------------------------------|
+    /// | `MUSUBI_THEME`         | `ariadne`, `rustc`, `gcc` or `monochrome`  |
+    /// | `MUSUBI_CHARSET`       | `ascii`, `unicode` or `auto`               |
+    /// | `MUSUBI_COLOR`         | `always`, `never` or `auto`                |
+    /// | `MUSUBI_COMPACT`       | a boolean                                  |
+    /// | `MUSUBI_TAB_WIDTH`     | an integer from `1` to `80`                |
+    /// | `MUSUBI_CONTEXT_LINES` | a non-negative integer                     |
+    /// | `MUSUBI_WIDTH`         | a non-negative integer, `0` for no limit   |
+    ///
+    /// Values are case-insensitive, and booleans are `1`, `true`, `yes` or
+    /// `on`, and `0`, `false`, `no` or `off`. The theme is applied first,
+    /// so the other variables adjust it; `auto` works as
+    /// [`with_char_set_probe`](Config::with_char_set_probe) with
+    /// [`CharSet::is_displayable`] and as
+    /// [`with_color_auto`](Config::with_color_auto). Unset variables and
+    /// unknown values leave the options as they are.
+    ///
+    /// Call it last to let the variables override the defaults of the
+    /// tool.
+    #[inline]
+    pub fn with_env(self) -> Self {
+        self.with_env_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Override options by the variables read by `var`.
+    pub(crate) fn with_env_vars(mut self, var: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |key| var(key).map(|v| v.trim().to_ascii_lowercase());
+        if let Some(theme) = var("MUSUBI_THEME") {
+            match theme.as_str() {
+                "ariadne" => self = self.with_theme(Theme::Ariadne),
+                "rustc" => self = self.with_theme(Theme::Rustc),
+                "gcc" => self = self.with_theme(Theme::Gcc),
+                "monochrome" => self = self.with_theme(Theme::Monochrome),
+                _ => {}
+            }
+        }
+        if let Some(char_set) = var("MUSUBI_CHARSET") {
+            match char_set.as_str() {
+                "ascii" => self = self.with_char_set_ascii(),
+                "unicode" => self = self.with_char_set_unicode(),
+                "auto" => self = self.with_char_set_probe(CharSet::is_displayable),
+                _ => {}
+            }
+        }
+        if let Some(color) = var("MUSUBI_COLOR") {
+            match color.as_str() {
+                "always" => self = self.with_color_default(),
+                "never" => self = self.with_color_disabled(),
+                "auto" => self = self.with_color_auto(),
+                _ => {}
+            }
+        }
+        if let Some(compact) = var("MUSUBI_COMPACT").and_then(|v| parse_bool(&v)) {
+            self = self.with_compact(compact);
+        }
+        let int = |key| var(key).and_then(|v| v.parse::<u16>().ok()).map(i32::from);
+        // a zero width divides by zero, and tabs expand to at most 80 spaces
+        if let Some(width) = int("MUSUBI_TAB_WIDTH").filter(|w| (1..=80).contains(w)) {
+            self = self.with_tab_width(width);
+        }
+        if let Some(lines) = int("MUSUBI_CONTEXT_LINES") {
+            self = self.with_context_lines(lines);
+        }
+        if let Some(width) = int("MUSUBI_WIDTH") {
+            self = self.with_limit_width(width);
+        }
+        self
+    }
+}
@@ -0,0 +1,274 @@
+//! Errors of rendering.
+
+use std::ffi::c_uint;
+use std::fmt;
+use std::io;
+
+use crate::{IndexType, LabelSpan, LineKind, RawCache, Report, ffi, init_error, load_source};
+
+/// Why rendering a report failed.
+///
+/// Rendering functions return [`io::Result`], so they fit in with the
+/// writers they render to; the `io::Error`s they return wrap an `Error`,
+/// which [`Error::from_io`] gets back. Writer and source errors keep their
+/// [`io::ErrorKind`].
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Error, Level, Report};
+/// let err = Report::new()
+///     .with_title(Level::Error, "Error")
+///     .with_label((0..3, 2))
+///     .render_to_string("let x = 42;")
+///     .unwrap_err();
+/// assert!(matches!(
+///     Error::from_io(&err),
+///     Some(Error::UnknownSourceId { src_id: 2, source_count: 1 })
+/// ));
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The label at index `label` ends after the end of its source, which
+    /// is `source_len` long in the index type of the report.
+    ///
+    /// Rendering doesn't check this, and shows such labels at the end of
+    /// their source; [`Report::validate`] does.
+    SpanOutOfBounds {
+        /// Index of the label, in the order labels were added.
+        label: usize,
+        /// The span of the label.
+        span: LabelSpan,
+        /// Length of the source.
+        source_len: usize,
+    },
+    /// A label, context span or suggestion points into a source that is not
+    /// in the cache.
+    UnknownSourceId {
+        /// The source ID pointed to.
+        src_id: usize,
+        /// The number of sources in the cache.
+        source_count: usize,
+    },
+    /// A source failed to load.
+    SourceInit(io::Error),
+    /// Writing the output failed.
+    ///
+    /// The writer took the first `written` bytes of the output, so a
+    /// streaming consumer knows what it got; with related reports, their
+    /// output counts too. `section` is the kind of line being written when
+    /// the writer failed.
+    Writer {
+        /// The error of the writer.
+        error: io::Error,
+        /// Bytes written successfully before the error.
+        written: usize,
+        /// The kind of line whose output failed.
+        section: LineKind,
+    },
+    /// The C library ran out of memory.
+    OutOfMemory,
+    /// The C library failed with another error code.
+    Code(i32),
+}
+
+impl Error {
+    /// The `Error` wrapped in an [`io::Error`] returned by rendering, if any.
+    pub fn from_io(err: &io::Error) -> Option<&Error> {
+        err.get_ref()?.downcast_ref()
+    }
+
+    /// The kind of the [`io::Error`] wrapping this error.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::SpanOutOfBounds { .. } | Error::UnknownSourceId { .. } => {
+                io::ErrorKind::InvalidInput
+            }
+            Error::SourceInit(err) | Error::Writer { error: err, .. } => err.kind(),
+            Error::OutOfMemory => io::ErrorKind::OutOfMemory,
+            Error::Code(_) => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::SpanOutOfBounds {
+                label,
+                span,
+                source_len,
+            } => write!(
+                f,
+                "label {label} at {}..{} is out of bounds of source {} ({source_len} long)",
+                span.start(),
+                span.end(),
+                span.src_id()
+            ),
+            Error::UnknownSourceId {
+                src_id,
+                source_count,
+            } => write!(f, "unknown source {src_id} (the cache has {source_count})"),
+            Error::SourceInit(err) => err.fmt(f),
+            Error::Writer {
+                error,
+                written,
+                section,
+            } => write!(
+                f,
+                "writer error after {written} bytes, in a {section:?} line: {error}"
+            ),
+            Error::OutOfMemory => f.write_str("memory allocation failed"),
+            Error::Code(code) => write!(f, "rendering failed with error code {code}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SourceInit(err) | Error::Writer { error: err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    #[inline]
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+/// An invalid span found by [`Report::validate`]: an
+/// [`Error::SpanOutOfBounds`], an [`Error::UnknownSourceId`], or an
+/// [`Error::SourceInit`] for a source that failed to load.
+pub type SpanError = Error;
+
+impl Report<'_> {
+    /// Check the spans of the report against the sources of `cache` before
+    /// rendering it.
+    ///
+    /// Every label must end within its source, and every label, suggestion
+    /// and [location](Report::with_location) must point into a source of
+    /// the cache. A label given by line and column whose lines are not in
+    /// its source is out of bounds too. Bounds
+    /// are in the index type of the report's config; for sources that
+    /// rewrite their lines, like [`TransformSource`](crate::TransformSource),
+    /// they apply to the displayed text. Sources are loaded as needed, and
+    /// one that fails to load is reported once.
+    ///
+    /// Returns all the errors found, in the order of the labels, then of
+    /// the suggestions and the location. See
+    /// [`Report::render_to_writer`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Error, Level, Report};
+    /// let cache = Cache::new().with_source("let x = 42;");
+    /// let report = Report::new()
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(4..5)
+    ///     .with_label(8..20)
+    ///     .with_label((0..1, 3));
+    ///
+    /// let errors = report.validate(&cache).unwrap_err();
+    /// assert!(matches!(
+    ///     errors[..],
+    ///     [
+    ///         Error::SpanOutOfBounds { label: 1, source_len: 11, .. },
+    ///         Error::UnknownSourceId { src_id: 3, source_count: 1 },
+    ///     ]
+    /// ));
+    /// ```
+    pub fn validate(&self, cache: impl Into<RawCache>) -> Result<(), Vec<SpanError>> {
+        let cache = self.resolve_cache(cache);
+        let index_type = self
+            .config
+            .as_ref()
+            .map_or(IndexType::Char, |c| c.index_type());
+        // SAFETY: cache is null or a valid cache
+        let source_count = unsafe { ffi::mu_sourcecount(cache.as_ptr()) } as usize;
+        let mut errors = Vec::new();
+        let mut failed = Vec::new();
+        let diag = self.diagnostic_in(cache.as_ptr());
+        let outside: Vec<usize> = (self.line_col_spans(cache.as_ptr()).into_iter())
+            .filter_map(|(i, _, found)| (!found).then_some(i))
+            .collect();
+        let location = (self.location).map(|(_, src_id)| LabelSpan::new(0..0, src_id));
+        let label_count = diag.labels().len();
+        for (i, span) in diag.spans().chain(location).enumerate() {
+            let src_id = span.src_id();
+            if src_id >= source_count {
+                if !failed.contains(&src_id) {
+                    failed.push(src_id);
+                    errors.push(Error::UnknownSourceId {
+                        src_id,
+                        source_count,
+                    });
+                }
+                continue;
+            }
+            let Some(src) = load_source(cache.as_ptr(), src_id.into()) else {
+                if !failed.contains(&src_id) {
+                    failed.push(src_id);
+                    errors.push(Error::SourceInit(init_error(
+                        cache.as_ptr(),
+                        src_id as c_uint,
+                    )));
+                }
+                continue;
+            };
+            if i >= label_count {
+                continue;
+            }
+            let source_len = source_len(src, index_type);
+            let end = span.end().max(span.start());
+            // `..` spans end at usize::MAX, meaning the end of the source
+            let past_end = end != usize::MAX && map_pos(src, index_type, end, true) > source_len;
+            if past_end || outside.contains(&i) {
+                errors.push(Error::SpanOutOfBounds {
+                    label: i,
+                    span,
+                    source_len,
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The length of the displayed text of `src`, in `index_type`.
+fn source_len(src: *mut ffi::mu_Source, index_type: IndexType) -> usize {
+    // SAFETY: src was loaded by the caller, so its functions are set
+    unsafe {
+        let count = (*src).line_count.map_or(0, |f| f(src));
+        let Some(last) = count.checked_sub(1) else {
+            return 0;
+        };
+        let line = *((*src).get_line_info.unwrap())(src, last);
+        let newline = line.newline as usize;
+        match index_type {
+            IndexType::Byte => line.byte_offset + line.byte_len as usize + newline,
+            IndexType::Char => line.offset + line.len as usize + newline,
+        }
+    }
+}
+
+/// `pos` moved by the `map_pos` of `src`, if it has one.
+fn map_pos(src: *mut ffi::mu_Source, index_type: IndexType, pos: usize, end: bool) -> usize {
+    // SAFETY: src was loaded by the caller
+    let Some(map_pos) = (unsafe { (*src).map_pos }) else {
+        return pos;
+    };
+    let index_type = match index_type {
+        IndexType::Byte => ffi::mu_IndexType::MU_INDEX_BYTE,
+        IndexType::Char => ffi::mu_IndexType::MU_INDEX_CHAR,
+    };
+    // SAFETY: map_pos is a function of src
+    unsafe { map_pos(src, pos, index_type, end.into()) }
+}
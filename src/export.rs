@@ -0,0 +1,253 @@
+//! Machine-readable output of a single report, as JSON or GitHub Actions
+//! annotations.
+
+use std::io::{self, Write};
+
+use crate::ci::json_string;
+use crate::{
+    Config, Diagnostic, IndexType, LabelSpan, Level, RawCache, Report, ffi, source_location,
+    source_name,
+};
+
+/// Escape `s` for the message of a GitHub Actions workflow command.
+fn github_escape(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape `s` for a property of a GitHub Actions workflow command.
+fn github_property(s: &str) -> String {
+    github_escape(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Quote and escape `s` as a JSON string, or `null`.
+fn json_option(s: Option<&str>) -> String {
+    s.map_or_else(|| "null".into(), json_string)
+}
+
+/// The line and column of `pos` in the source of `span` as a JSON
+/// object, or `null` if it can't be resolved.
+fn json_position(
+    cache: *mut ffi::mu_Cache,
+    span: &LabelSpan,
+    pos: usize,
+    index_type: IndexType,
+) -> String {
+    match source_location(cache, span.src_id, pos, index_type) {
+        Some((line, column)) => format!("{{\"line\":{line},\"column\":{column}}}"),
+        None => "null".into(),
+    }
+}
+
+/// Serialize `diag` as a JSON object, resolving label positions in `cache`.
+fn diagnostic_json(
+    diag: &Diagnostic<'_>,
+    location: Option<(usize, ffi::mu_Id)>,
+    cache: *mut ffi::mu_Cache,
+    index_type: IndexType,
+) -> String {
+    let strings = |list: &[std::borrow::Cow<'_, str>]| {
+        let items: Vec<String> = list.iter().map(|s| json_string(s)).collect();
+        format!("[{}]", items.join(","))
+    };
+    let labels: Vec<String> = (diag.labels().iter())
+        .map(|label| {
+            let span = label.span();
+            format!(
+                "{{\"file\":{},\"span\":{{\"start\":{},\"end\":{}}},\"start\":{},\"end\":{},\
+                 \"message\":{},\"primary\":{}}}",
+                json_option(source_name(cache, span.src_id).as_deref()),
+                span.start,
+                span.end,
+                json_position(cache, &span, span.start, index_type),
+                json_position(cache, &span, span.end, index_type),
+                json_option(label.message()),
+                label.is_primary(),
+            )
+        })
+        .collect();
+    let suggestions: Vec<String> = (diag.suggestions().iter())
+        .map(|fix| {
+            let edits: Vec<String> = (fix.edits().iter())
+                .map(|(span, repl)| {
+                    format!(
+                        "{{\"file\":{},\"span\":{{\"start\":{},\"end\":{}}},\"start\":{},\"end\":{},\
+                         \"replacement\":{}}}",
+                        json_option(source_name(cache, span.src_id).as_deref()),
+                        span.start,
+                        span.end,
+                        json_position(cache, span, span.start, index_type),
+                        json_position(cache, span, span.end, index_type),
+                        json_string(repl),
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"message\":{},\"applicability\":{},\"edits\":[{}]}}",
+                json_option(fix.message()),
+                json_string(fix.applicability().name()),
+                edits.join(","),
+            )
+        })
+        .collect();
+    let location = location.map_or_else(
+        || "null".into(),
+        |(pos, src_id)| {
+            let span = LabelSpan::new(pos..pos, src_id);
+            format!(
+                "{{\"file\":{},\"offset\":{pos},\"position\":{}}}",
+                json_option(source_name(cache, src_id).as_deref()),
+                json_position(cache, &span, pos, index_type),
+            )
+        },
+    );
+    format!(
+        "{{\"level\":{},\"title\":{},\"code\":{},\"labels\":[{}],\"helps\":{},\"notes\":{},\
+         \"suggestions\":[{}],\"location\":{location}}}",
+        json_string(diag.level_name()),
+        json_string(diag.title()),
+        json_option(diag.code()),
+        labels.join(","),
+        strings(diag.helps()),
+        strings(diag.notes()),
+        suggestions.join(","),
+    )
+}
+
+impl Report<'_> {
+    /// Serialize the report as a JSON object.
+    ///
+    /// The object has the following fields:
+    /// - `level`: the level name, e.g. `"Error"`, or the custom level name
+    /// - `title`: the title message
+    /// - `code`: the error code, or `null`
+    /// - `labels`: an array of labels, each with the `file` name of its
+    ///   source (or `null`), its `span` as `start` and `end` positions, the
+    ///   1-based `line` and `column` of its `start` and `end` (or `null` if
+    ///   the source can't be read), its `message` (or `null`) and whether
+    ///   it is `primary`
+    /// - `helps` and `notes`: arrays of messages
+    /// - `suggestions`: an array of fix suggestions, each with its `message`
+    ///   (or `null`), its `applicability` (`"MachineApplicable"`,
+    ///   `"MaybeIncorrect"` or `"HasPlaceholders"`) and its `edits`, each
+    ///   with `file`, `span`, `start` and `end` like labels, and the
+    ///   `replacement` text
+    /// - `location`: the position set by
+    ///   [`with_location`](Report::with_location), with the `file` name of
+    ///   its source (or `null`), its `offset` and its 1-based `line` and
+    ///   `column` as `position` (or `null`), or `null` if not set
+    ///
+    /// Labels and locations given by line and column are placed in the
+    /// sources of `cache`, as when rendering. Span positions are interpreted
+    /// according to the configured [`IndexType`]; columns count characters.
+    /// The schema is stable: new fields may be added, but existing ones keep
+    /// their meaning.
+    ///
+    /// See [`Report::render_to_string`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let report = Report::new()
+    ///     .with_title(Level::Error, "unknown variable")
+    ///     .with_code("E01")
+    ///     .with_label(8..9)
+    ///     .with_message("not found in this scope");
+    /// let json = report.render_to_json(("let a = b;", "main.rs"));
+    /// assert!(json.starts_with(r#"{"level":"Error","title":"unknown variable","code":"E01","#));
+    /// assert!(json.contains(r#""start":{"line":1,"column":9}"#));
+    /// ```
+    pub fn render_to_json(&self, cache: impl Into<RawCache>) -> String {
+        let index_type = self
+            .config
+            .as_ref()
+            .map_or(IndexType::Char, Config::index_type);
+        let cache = self.resolve_cache(cache).as_ptr();
+        let location = self.location_in(cache);
+        diagnostic_json(&self.diagnostic_in(cache), location, cache, index_type)
+    }
+
+    /// Render the report as GitHub Actions workflow commands.
+    ///
+    /// Every label becomes one `::error`, `::warning` or `::notice` line
+    /// (depending on the level) with the `file`, `line`, `col`, `endLine`
+    /// and `endColumn` of its span, carrying the label message, or the
+    /// report title for labels without one. The title, prefixed with the
+    /// code, is passed as annotation `title`. A report without labels
+    /// becomes a single command, at the position set by
+    /// [`with_location`](Report::with_location) if any. Printed in a workflow
+    /// step, each line shows up as an annotation on the changed file.
+    ///
+    /// Span positions are interpreted according to the configured
+    /// [`IndexType`]; columns count characters.
+    ///
+    /// See [`Report::render_to_string`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let report = Report::new()
+    ///     .with_title(Level::Error, "unknown variable")
+    ///     .with_label(8..9)
+    ///     .with_message("not found in this scope");
+    ///
+    /// let mut out = Vec::new();
+    /// report.render_to_github_annotations(&mut out, ("let a = b;", "main.rs"))?;
+    /// assert_eq!(
+    ///     String::from_utf8(out).unwrap(),
+    ///     "::error file=main.rs,line=1,col=9,endLine=1,endColumn=10,title=unknown variable::not found in this scope\n"
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_github_annotations<W: Write>(
+        &self,
+        writer: &mut W,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        let cache = self.resolve_cache(cache);
+        let cache = cache.as_ptr();
+        let index_type = (self.config.as_ref()).map_or(IndexType::Char, Config::index_type);
+        let diag = self.diagnostic_in(cache);
+        let command = match diag.level() {
+            Some(Level::Error) => "error",
+            Some(Level::Warning) => "warning",
+            _ => "notice",
+        };
+        let title = match diag.code() {
+            Some(code) => github_property(&format!("[{code}] {}", diag.title())),
+            None => github_property(diag.title()),
+        };
+        let position = |props: &mut Vec<String>, span: LabelSpan, end: bool| {
+            if let Some(file) = source_name(cache, span.src_id) {
+                props.push(format!("file={}", github_property(&file)));
+            }
+            if let Some((line, col)) = source_location(cache, span.src_id, span.start, index_type) {
+                props.push(format!("line={line},col={col}"));
+                let pos = span.end.max(span.start);
+                if end
+                    && let Some((line, col)) = source_location(cache, span.src_id, pos, index_type)
+                {
+                    props.push(format!("endLine={line},endColumn={col}"));
+                }
+            }
+        };
+        if diag.labels().is_empty() {
+            let mut props = Vec::new();
+            if let Some((pos, src_id)) = self.location_in(cache) {
+                position(&mut props, LabelSpan::new(pos..pos, src_id), false);
+            }
+            props.push(format!("title={title}"));
+            let msg = github_escape(diag.title());
+            return writeln!(writer, "::{command} {}::{msg}", props.join(","));
+        }
+        for label in diag.labels() {
+            let mut props = Vec::new();
+            position(&mut props, label.span(), true);
+            props.push(format!("title={title}"));
+            let msg = github_escape(label.message().unwrap_or(diag.title()));
+            writeln!(writer, "::{command} {}::{msg}", props.join(","))?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
 #![allow(non_camel_case_types)]
 #![allow(dead_code)]
+#![allow(missing_docs)]
 
 include!("ffi_generated.rs");
 
 pub mod sizes {
     pub const COLOR_CODE: usize = super::MU_COLOR_CODE_SIZE as usize;
+    pub const TAB_STOPS: usize = super::MU_TAB_STOPS_SIZE as usize;
+    pub const LINE_NO: usize = super::MU_LINE_NO_SIZE as usize;
 }
 
 pub const MU_OK: i32 = 0;
@@ -13,7 +16,7 @@ pub const MU_ERR_SRCINIT: i32 = -100;
 
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct mu_Id(std::os::raw::c_uint);
+pub struct mu_Id(pub(crate) std::os::raw::c_uint);
 
 macro_rules! impl_from_for_mu_id {
     ($($t:ty),+) => {
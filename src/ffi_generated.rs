@@ -4,17 +4,33 @@ pub const MU_VERSION_MAJOR: u32 = 0;
 pub const MU_VERSION_MINOR: u32 = 5;
 pub const MU_VERSION_PATCH: u32 = 0;
 pub const MU_CHUNK_MAX_SIZE: u32 = 63;
-pub const MU_COLOR_CODE_SIZE: u32 = 32;
+pub const MU_COLOR_CODE_SIZE: u32 = 64;
+pub const MU_TAB_STOPS_SIZE: u32 = 16;
+pub const MU_LINE_NO_SIZE: u32 = 64;
+pub const MU_MAX_LABELS: u32 = 1073741824;
+pub const MU_FEATURE_STDIO: u32 = 1;
+pub const MU_FEATURE_DEBUG: u32 = 2;
+pub const MU_STRUCT_CONFIG: u32 = 0;
+pub const MU_STRUCT_ALLOCATOR: u32 = 1;
+pub const MU_STRUCT_CACHE: u32 = 2;
+pub const MU_STRUCT_SOURCE: u32 = 3;
+pub const MU_STRUCT_LINE: u32 = 4;
+pub const MU_STRUCT_COLORGEN: u32 = 5;
 pub const MU_ERRPARAM: i32 = -1;
 pub const MU_ERRSRC: i32 = -2;
 pub const MU_ERRLINE: i32 = -3;
 pub const MU_ERRFILE: i32 = -4;
+pub const MU_ERRMEM: i32 = -5;
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub enum mu_Level {
     MU_ERROR = 0,
     MU_WARNING = 1,
     MU_CUSTOM_LEVEL = 2,
+    MU_INFO = 3,
+    MU_NOTE = 4,
+    MU_HINT = 5,
+    MU_ADVICE = 6,
 }
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
@@ -24,6 +40,15 @@ pub enum mu_IndexType {
 }
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum mu_Newline {
+    MU_NEWLINE_AUTO = 0,
+    MU_NEWLINE_LF = 1,
+    MU_NEWLINE_CRLF = 2,
+    MU_NEWLINE_CR = 3,
+    MU_NEWLINE_MIXED = 4,
+}
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub enum mu_LabelAttach {
     MU_ATTACH_MIDDLE = 0,
     MU_ATTACH_START = 1,
@@ -31,6 +56,29 @@ pub enum mu_LabelAttach {
 }
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum mu_Overlap {
+    MU_OVERLAP_MERGE = 0,
+    MU_OVERLAP_STACK = 1,
+    MU_OVERLAP_HIDE = 2,
+}
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum mu_Footer {
+    MU_FOOTER_BORDER = 0,
+    MU_FOOTER_OMIT = 1,
+    MU_FOOTER_BLANK = 2,
+    MU_FOOTER_LABELED = 3,
+}
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum mu_SourceOrder {
+    MU_SOURCE_ORDER_CACHE = 0,
+    MU_SOURCE_ORDER_LABELS = 1,
+    MU_SOURCE_ORDER_NAME = 2,
+    MU_SOURCE_ORDER_PRIMARY = 3,
+}
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub enum mu_ColorKind {
     MU_COLOR_RESET = 0,
     MU_COLOR_ERROR = 1,
@@ -41,6 +89,28 @@ pub enum mu_ColorKind {
     MU_COLOR_UNIMPORTANT = 6,
     MU_COLOR_NOTE = 7,
     MU_COLOR_LABEL = 8,
+    MU_COLOR_EMPHASIS = 9,
+    MU_COLOR_INLINE_CODE = 10,
+    MU_COLOR_CONTEXT = 11,
+    MU_COLOR_INFO = 12,
+    MU_COLOR_HINT = 13,
+    MU_COLOR_ADVICE = 14,
+    MU_COLOR_EXPECTED = 15,
+    MU_COLOR_FOUND = 16,
+}
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum mu_LineKind {
+    MU_LINE_HEADER = 0,
+    MU_LINE_REFERENCE = 1,
+    MU_LINE_EMPTY = 2,
+    MU_LINE_SOURCE = 3,
+    MU_LINE_UNDERLINE = 4,
+    MU_LINE_ARROW = 5,
+    MU_LINE_ELLIPSIS = 6,
+    MU_LINE_HELP = 7,
+    MU_LINE_NOTE = 8,
+    MU_LINE_FOOTER = 9,
 }
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
@@ -97,6 +167,14 @@ pub type mu_Writer = ::std::option::Option<
         len: usize,
     ) -> ::std::os::raw::c_int,
 >;
+pub type mu_LineNo = ::std::option::Option<
+    unsafe extern "C" fn(
+        ud: *mut ::std::os::raw::c_void,
+        line_no: ::std::os::raw::c_uint,
+        buf: *mut ::std::os::raw::c_char,
+        size: usize,
+    ) -> mu_Slice,
+>;
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct mu_Slice {
@@ -117,13 +195,32 @@ pub struct mu_Config {
     pub tab_width: ::std::os::raw::c_int,
     pub limit_width: ::std::os::raw::c_int,
     pub ambiwidth: ::std::os::raw::c_int,
+    pub markup: ::std::os::raw::c_int,
+    pub inline_code: ::std::os::raw::c_int,
+    pub normalize: ::std::os::raw::c_int,
+    pub bidi_isolate: ::std::os::raw::c_int,
+    pub auto_location: ::std::os::raw::c_int,
+    pub dim_unlabeled: ::std::os::raw::c_int,
+    pub caret_only: ::std::os::raw::c_int,
+    pub one_based_columns: ::std::os::raw::c_int,
+    pub auto_priority: ::std::os::raw::c_int,
+    pub max_depth: ::std::os::raw::c_int,
+    pub gutter_bar: ::std::os::raw::c_int,
+    pub collapse_notes: ::std::os::raw::c_int,
+    pub tab_stops: [::std::os::raw::c_int; 16usize],
     pub label_attach: mu_LabelAttach,
     pub index_type: mu_IndexType,
+    pub overlap: mu_Overlap,
+    pub footer: mu_Footer,
+    pub source_order: mu_SourceOrder,
     pub color: mu_Color,
     pub color_ud: *mut ::std::os::raw::c_void,
     pub char_set: *const mu_Charset,
+    pub line_no_sep: mu_Slice,
+    pub line_no: mu_LineNo,
+    pub line_no_ud: *mut ::std::os::raw::c_void,
 }
-pub type mu_ColorCode = [::std::os::raw::c_char; 32usize];
+pub type mu_ColorCode = [::std::os::raw::c_char; 64usize];
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct mu_ColorGen {
@@ -136,6 +233,7 @@ pub type mu_CL = *const mu_Line;
 pub struct mu_Allocator {
     pub ud: *mut ::std::os::raw::c_void,
     pub allocf: mu_Allocf,
+    pub onerr: *mut ::std::os::raw::c_void,
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -153,6 +251,9 @@ pub struct mu_Source {
     pub lines: *mut mu_Line,
     pub line_no_offset: ::std::os::raw::c_int,
     pub inited: ::std::os::raw::c_int,
+    pub newline: ::std::os::raw::c_int,
+    pub newlines: ::std::os::raw::c_int,
+    pub cr_pending: ::std::os::raw::c_int,
     pub init:
         ::std::option::Option<unsafe extern "C" fn(src: *mut mu_Source) -> ::std::os::raw::c_int>,
     pub free: ::std::option::Option<unsafe extern "C" fn(src: *mut mu_Source)>,
@@ -178,6 +279,14 @@ pub struct mu_Source {
             out: *mut mu_CL,
         ) -> ::std::os::raw::c_uint,
     >,
+    pub map_pos: ::std::option::Option<
+        unsafe extern "C" fn(
+            src: *mut mu_Source,
+            pos: usize,
+            index_type: mu_IndexType,
+            end: ::std::os::raw::c_int,
+        ) -> usize,
+    >,
 }
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -189,6 +298,9 @@ pub struct mu_Line {
     pub newline: ::std::os::raw::c_uint,
 }
 unsafe extern "C" {
+    pub fn mu_version() -> ::std::os::raw::c_uint;
+    pub fn mu_features() -> ::std::os::raw::c_uint;
+    pub fn mu_structsize(which: ::std::os::raw::c_uint) -> usize;
     pub fn mu_new(allocf: mu_Allocf, ud: *mut ::std::os::raw::c_void) -> *mut mu_Report;
     pub fn mu_reset(R: *mut mu_Report);
     pub fn mu_delete(R: *mut mu_Report);
@@ -209,10 +321,30 @@ unsafe extern "C" {
         color: mu_Color,
         ud: *mut ::std::os::raw::c_void,
     ) -> ::std::os::raw::c_int;
+    pub fn mu_colorkind(R: *mut mu_Report, kind: mu_ColorKind) -> ::std::os::raw::c_int;
     pub fn mu_primary(R: *mut mu_Report) -> ::std::os::raw::c_int;
     pub fn mu_order(R: *mut mu_Report, order: ::std::os::raw::c_int) -> ::std::os::raw::c_int;
     pub fn mu_priority(R: *mut mu_Report, priority: ::std::os::raw::c_int)
         -> ::std::os::raw::c_int;
+    pub fn mu_msgref(
+        R: *mut mu_Report,
+        start: usize,
+        end: usize,
+        label_id: mu_Id,
+    ) -> ::std::os::raw::c_int;
+    pub fn mu_setspan(
+        R: *mut mu_Report,
+        label_id: mu_Id,
+        start: usize,
+        end: usize,
+    ) -> ::std::os::raw::c_int;
+    pub fn mu_context(
+        R: *mut mu_Report,
+        start: usize,
+        end: usize,
+        src_id: mu_Id,
+    ) -> ::std::os::raw::c_int;
+    pub fn mu_location(R: *mut mu_Report, pos: usize, src_id: mu_Id) -> ::std::os::raw::c_int;
     pub fn mu_title(
         R: *mut mu_Report,
         l: mu_Level,
@@ -222,12 +354,35 @@ unsafe extern "C" {
     pub fn mu_code(R: *mut mu_Report, code: mu_Slice) -> ::std::os::raw::c_int;
     pub fn mu_help(R: *mut mu_Report, help_msg: mu_Slice) -> ::std::os::raw::c_int;
     pub fn mu_note(R: *mut mu_Report, note_msg: mu_Slice) -> ::std::os::raw::c_int;
+    pub fn mu_suggest(
+        R: *mut mu_Report,
+        start: usize,
+        end: usize,
+        src_id: mu_Id,
+        replacement: mu_Slice,
+        msg: mu_Slice,
+    ) -> ::std::os::raw::c_int;
+    pub fn mu_suggestpart(
+        R: *mut mu_Report,
+        start: usize,
+        end: usize,
+        src_id: mu_Id,
+        replacement: mu_Slice,
+    ) -> ::std::os::raw::c_int;
     pub fn mu_writer(
         R: *mut mu_Report,
         writer: mu_Writer,
         ud: *mut ::std::os::raw::c_void,
     ) -> ::std::os::raw::c_int;
     pub fn mu_render(R: *mut mu_Report, cache: *const mu_Cache) -> ::std::os::raw::c_int;
+    pub fn mu_relayout(R: *mut mu_Report);
+    pub fn mu_linekind(R: *const mu_Report) -> mu_LineKind;
+    pub fn mu_msglabel(R: *const mu_Report) -> ::std::os::raw::c_int;
+    pub fn mu_sourceline(
+        R: *const mu_Report,
+        src_id: *mut mu_Id,
+        line_no: *mut ::std::os::raw::c_uint,
+    ) -> ::std::os::raw::c_int;
     pub fn mu_ascii() -> *const mu_Charset;
     pub fn mu_unicode() -> *const mu_Charset;
     pub fn mu_default_color(ud: *mut ::std::os::raw::c_void, kind: mu_ColorKind) -> mu_Chunk;
@@ -238,8 +393,15 @@ unsafe extern "C" {
     pub fn mu_newcache(allocf: mu_Allocf, ud: *mut ::std::os::raw::c_void) -> *mut mu_Cache;
     pub fn mu_delcache(C: *mut mu_Cache);
     pub fn mu_sourcecount(C: *const mu_Cache) -> ::std::os::raw::c_uint;
+    pub fn mu_getsource(C: *const mu_Cache, src_id: ::std::os::raw::c_uint) -> *mut mu_Source;
+    pub fn mu_setsource(
+        C: *mut mu_Cache,
+        src_id: ::std::os::raw::c_uint,
+        src: *mut mu_Source,
+    ) -> *mut mu_Source;
     pub fn mu_addsource(pC: *mut *mut mu_Cache, size: usize, name: mu_Slice) -> *mut mu_Source;
     pub fn mu_addmemory(pC: *mut *mut mu_Cache, data: mu_Slice, name: mu_Slice) -> *mut mu_Source;
+    pub fn mu_newlinekind(src: *const mu_Source) -> mu_Newline;
     pub fn mu_source(R: *mut mu_Report) -> *mut mu_Source;
     pub fn mu_updatelines(src: *mut mu_Source, data: mu_Slice);
     pub fn mu_linecount(src: *mut mu_Source) -> ::std::os::raw::c_uint;
@@ -0,0 +1,200 @@
+//! Sources read from files when they are first rendered.
+
+use std::ffi::c_int;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{AddToCache, Cache, ffi, name_slice};
+
+/// A source read from a file when it is first needed.
+///
+/// Nothing is opened when the source is added: the file is read the first
+/// time a report touches it (or by [`Cache::init_all`]), so a cache may
+/// register every file of a project and only pay for the ones diagnostics
+/// point into. The source is named after its path unless a name is given
+/// with a tuple, like `(FileSource::new(path), "name")`.
+///
+/// If the file cannot be read, rendering fails with a source init error,
+/// and the file is tried again on the next render.
+///
+/// # Example
+/// ```rust,no_run
+/// # use musubi::{Cache, FileSource, Level, Report};
+/// let cache = Cache::new()
+///     .with_file("src/main.rs")
+///     .with_source((FileSource::new("src/lib.rs"), "lib.rs"));
+///
+/// Report::new()
+///     .with_title(Level::Error, "Error")
+///     .with_label((0..2, 1))
+///     .render_to_stdout(&cache)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct FileSource {
+    path: PathBuf,
+    content: Content,
+    #[cfg(feature = "mmap")]
+    mmap_threshold: Option<u64>,
+}
+
+enum Content {
+    Unloaded,
+    Read(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Content {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Content::Unloaded => &[],
+            Content::Read(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Content::Mapped(map) => map,
+        }
+    }
+}
+
+impl FileSource {
+    /// Create a source for the file at `path`, without opening it.
+    #[inline]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            content: Content::Unloaded,
+            #[cfg(feature = "mmap")]
+            mmap_threshold: None,
+        }
+    }
+
+    /// The path of the file.
+    #[inline]
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Memory-map the file instead of reading it, if it is at least `len`
+    /// bytes long.
+    ///
+    /// Mapping saves copying large files into memory; only the pages of the
+    /// lines being rendered are read from disk, besides one pass to split
+    /// the lines.
+    ///
+    /// Requires the `mmap` feature.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated, by this or any other
+    /// process, while the source is alive.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use musubi::{Cache, FileSource};
+    /// // SAFETY: nothing writes to the generated file while we report on it
+    /// let source = unsafe { FileSource::new("gen/parser.rs").with_mmap_threshold(1 << 20) };
+    /// let cache = Cache::new().with_source(source);
+    /// ```
+    #[cfg(feature = "mmap")]
+    #[inline]
+    #[must_use]
+    pub unsafe fn with_mmap_threshold(mut self, len: u64) -> Self {
+        self.mmap_threshold = Some(len);
+        self
+    }
+
+    fn load(&mut self) -> std::io::Result<()> {
+        #[cfg(feature = "mmap")]
+        if let Some(threshold) = self.mmap_threshold {
+            let file = fs::File::open(&self.path)?;
+            if file.metadata()?.len() >= threshold {
+                // SAFETY: the caller of with_mmap_threshold guarantees the
+                // file is not modified while the source is alive
+                self.content = Content::Mapped(unsafe { memmap2::Mmap::map(&file)? });
+                return Ok(());
+            }
+        }
+        self.content = Content::Read(fs::read(&self.path)?);
+        Ok(())
+    }
+}
+
+impl AddToCache for FileSource {
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
+        #[repr(C)]
+        struct FileSrc {
+            base: ffi::mu_Source,
+            file: FileSource,
+        }
+        // SAFETY: mu_addsource initializes the cache and source correctly
+        let src = unsafe { ffi::mu_addsource(cache, size_of::<FileSrc>(), Default::default()) };
+        let file_src = src as *mut FileSrc;
+        // SAFETY: src is allocated by mu_addsource above with room for a
+        // FileSrc, and its file field is uninitialized memory
+        unsafe {
+            std::ptr::addr_of_mut!((*file_src).file).write(self);
+            // the source never moves, so its name may borrow the path
+            (*src).name = name_slice(&(*file_src).file.path);
+            (*src).init = Some(init_fn);
+            (*src).free = Some(free_fn);
+            (*src).get_line = Some(get_line_fn);
+        }
+
+        unsafe extern "C" fn init_fn(src: *mut ffi::mu_Source) -> c_int {
+            // SAFETY: src is a valid FileSrc pointer created above
+            let src = unsafe { &mut *(src as *mut FileSrc) };
+            if src.file.load().is_err() {
+                return ffi::MU_ERR_SRCINIT;
+            }
+            // SAFETY: calling mu_updatelines is safe
+            unsafe { ffi::mu_updatelines(&mut src.base, src.file.content.as_bytes().into()) };
+            ffi::MU_OK
+        }
+
+        unsafe extern "C" fn free_fn(src: *mut ffi::mu_Source) {
+            // SAFETY: src was allocated by mu_addsource and is valid here
+            // after this call, src will be freed by C library.
+            unsafe { std::ptr::drop_in_place(src as *mut FileSrc) };
+        }
+
+        unsafe extern "C" fn get_line_fn(
+            src: *mut ffi::mu_Source,
+            line_no: std::ffi::c_uint,
+        ) -> ffi::mu_Slice {
+            // SAFETY: src is a valid, loaded source, whose lines are only
+            // read here
+            let line = unsafe { *ffi::mu_getline(src, line_no) };
+            // SAFETY: src is a valid FileSrc pointer, only read once loaded
+            let src = unsafe { &*(src as *const FileSrc) };
+            src.file.content.as_bytes()[line.byte_offset as usize..][..line.byte_len as usize]
+                .into()
+        }
+
+        src
+    }
+}
+
+impl<K> Cache<K> {
+    /// Add the file at `path` as a source, read when it is first rendered.
+    ///
+    /// The source is named after `path`. See [`FileSource`] for details.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Level, Report};
+    /// let path = std::env::temp_dir().join("musubi-with-file.txt");
+    /// let cache = Cache::new().with_file(&path);
+    /// // the file is only read when rendering
+    /// std::fs::write(&path, "let x = 42;\n")?;
+    /// let output = Report::new()
+    ///     .with_config(musubi::Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(4..5)
+    ///     .render_to_string(&cache)?;
+    /// assert!(output.contains("musubi-with-file.txt:1:5"));
+    /// # std::fs::remove_file(&path)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    pub fn with_file(self, path: impl Into<PathBuf>) -> Self {
+        self.with_shared_source(FileSource::new(path))
+    }
+}
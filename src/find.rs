@@ -0,0 +1,131 @@
+//! Label spans found by searching the text of a source.
+
+use std::ops::Range;
+
+use crate::{Cache, LabelSpan, ffi};
+
+/// A pattern searched in the text of a source by [`Cache::find_spans`].
+///
+/// It is implemented for substrings and characters, and for
+/// `regex::Regex` with the `regex` feature.
+pub trait SpanPattern {
+    /// The byte ranges of the matches of the pattern in `text`, in order
+    /// and without overlaps.
+    fn find_in(&self, text: &str) -> Vec<Range<usize>>;
+}
+
+impl SpanPattern for str {
+    /// The occurrences of the substring; an empty substring matches
+    /// nothing.
+    fn find_in(&self, text: &str) -> Vec<Range<usize>> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        (text.match_indices(self))
+            .map(|(i, m)| i..i + m.len())
+            .collect()
+    }
+}
+
+impl SpanPattern for String {
+    #[inline]
+    fn find_in(&self, text: &str) -> Vec<Range<usize>> {
+        self.as_str().find_in(text)
+    }
+}
+
+impl SpanPattern for char {
+    fn find_in(&self, text: &str) -> Vec<Range<usize>> {
+        (text.match_indices(*self))
+            .map(|(i, m)| i..i + m.len())
+            .collect()
+    }
+}
+
+impl<P: SpanPattern + ?Sized> SpanPattern for &P {
+    #[inline]
+    fn find_in(&self, text: &str) -> Vec<Range<usize>> {
+        (**self).find_in(text)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl SpanPattern for regex::Regex {
+    /// The matches of the regex, as [`Regex::find_iter`](regex::Regex::find_iter)
+    /// finds them.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Cache;
+    /// let cache = Cache::new().with_source("let a = b + c1;");
+    /// let names = regex::Regex::new(r"\b[a-z]\w*\b").unwrap();
+    /// let spans = cache.find_spans(0, &names);
+    /// let starts: Vec<_> = spans.iter().map(|span| span.start()).collect();
+    /// assert_eq!(starts, [0, 4, 8, 12]);
+    /// ```
+    fn find_in(&self, text: &str) -> Vec<Range<usize>> {
+        self.find_iter(text).map(|m| m.range()).collect()
+    }
+}
+
+impl<K> Cache<K> {
+    /// The spans of the matches of `pattern` in source `src_id`, loading it
+    /// if needed.
+    ///
+    /// This gives labels to tools without a parser, like grep-like linters.
+    /// Each line is searched on its own, without its newline, so matches
+    /// don't cross lines, and lines that are not valid UTF-8 are skipped.
+    /// The spans count characters, like labels with the default
+    /// [`IndexType`](crate::IndexType), and carry `src_id`. Returns no spans
+    /// if `src_id` is out of range or the source fails to load.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Config, Level, Report};
+    /// let cache = Cache::new()
+    ///     .with_source(("fn main() {}\n", "main.rs"))
+    ///     .with_source(("// TODO: café\nlet x; // TODO\n", "lib.rs"));
+    /// let spans = cache.find_spans(1, "TODO");
+    /// assert_eq!(spans, [(3..7, 1).into(), (24..28, 1).into()]);
+    ///
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Warning, "unresolved TODOs");
+    /// for span in spans {
+    ///     report = report.with_label(span);
+    /// }
+    /// assert!(report.render_to_string(&cache)?.contains("lib.rs:1:4"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn find_spans(
+        &self,
+        src_id: impl Into<ffi::mu_Id>,
+        pattern: impl SpanPattern,
+    ) -> Vec<LabelSpan> {
+        let src_id = src_id.into();
+        let Some(src) = self.source(src_id) else {
+            return Vec::new();
+        };
+        // SAFETY: src was loaded above, so its functions are set
+        let count = unsafe { (*src).line_count.map_or(0, |f| f(src)) };
+        let mut spans = Vec::new();
+        for line_no in 0..count {
+            // SAFETY: see above, and line_no is in range
+            let (line, data) = unsafe {
+                let line = (*src).get_line_info.map(|f| *f(src, line_no));
+                let data: &[u8] = (*src).get_line.map_or(&[][..], |f| f(src, line_no).into());
+                (line, data)
+            };
+            let (Some(line), Ok(text)) = (line, std::str::from_utf8(data)) else {
+                continue;
+            };
+            let char_pos = |i: usize| line.offset + text[..i].chars().count();
+            spans.extend((pattern.find_in(text).into_iter()).map(|m| LabelSpan {
+                start: char_pos(m.start),
+                end: char_pos(m.end),
+                src_id,
+            }));
+        }
+        spans
+    }
+}
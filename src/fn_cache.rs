@@ -0,0 +1,158 @@
+//! Sources fetched by a closure when they are first rendered.
+
+use std::ffi::c_int;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io;
+use std::rc::Rc;
+
+use crate::{Cache, LabelSpan, RawCache, ffi};
+
+/// A cache fetching the text of its sources with a closure, when a report
+/// first renders them.
+///
+/// Sources are known by IDs of your own, like paths or module names. Asking
+/// for the [`id`](FnCache::id) or a [`span`](FnCache::span) of a source
+/// registers it without fetching anything; the closure is only called for
+/// the sources the rendered reports point into, and only once for each.
+/// Sources are named after their IDs.
+///
+/// If the closure fails, rendering fails with a source init error, and the
+/// source is fetched again on the next render.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Config, FnCache, Level, Report};
+/// let mut cache = FnCache::new(|name: &String| match name.as_str() {
+///     "main.rs" => Ok("fn main() { helper(); }".to_string()),
+///     _ => Err(std::io::Error::other("no such file")),
+/// });
+///
+/// let output = Report::new()
+///     .with_config(Config::new().with_color_disabled())
+///     .with_title(Level::Error, "cannot find function `helper`")
+///     .with_label(cache.span(12..18, &"main.rs".to_string()))
+///     .render_to_string(&cache)?;
+/// assert!(output.contains("main.rs:1:13"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct FnCache<I, F> {
+    cache: Cache<I>,
+    fetch: Rc<F>,
+}
+
+impl<I, F> FnCache<I, F>
+where
+    I: Eq + Hash + Clone + Display,
+    F: Fn(&I) -> io::Result<String>,
+{
+    /// Create an empty cache fetching sources with `fetch`.
+    #[inline]
+    pub fn new(fetch: F) -> Self {
+        Self {
+            cache: Cache::keyed(),
+            fetch: Rc::new(fetch),
+        }
+    }
+
+    /// The source ID of `id`, registering the source if it is new.
+    ///
+    /// The source is not fetched until a report renders it.
+    pub fn id(&mut self, id: &I) -> usize {
+        if let Some(src_id) = self.cache.id(id) {
+            return src_id;
+        }
+        let src = FnSource {
+            fetch: self.fetch.clone(),
+            name: id.to_string(),
+            id: id.clone(),
+            text: None,
+        };
+        let src_id = self.cache.len();
+        src.add_to_cache(&mut self.cache.inner);
+        self.cache.keys.insert(id.clone(), src_id);
+        src_id
+    }
+
+    /// A span over `range` in the source `id`, registering the source if it
+    /// is new.
+    #[inline]
+    pub fn span(&mut self, range: std::ops::Range<usize>, id: &I) -> LabelSpan {
+        LabelSpan::new(range, self.id(id))
+    }
+
+    /// The underlying cache, holding the sources registered so far.
+    #[inline]
+    pub fn cache(&self) -> &Cache<I> {
+        &self.cache
+    }
+}
+
+impl<I, F> From<&FnCache<I, F>> for RawCache {
+    #[inline]
+    fn from(cache: &FnCache<I, F>) -> RawCache {
+        RawCache::Borrowed(cache.cache.inner)
+    }
+}
+
+struct FnSource<I, F> {
+    fetch: Rc<F>,
+    name: String,
+    id: I,
+    text: Option<String>,
+}
+
+impl<I, F: Fn(&I) -> io::Result<String>> FnSource<I, F> {
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) {
+        #[repr(C)]
+        struct FnSrc<I, F> {
+            base: ffi::mu_Source,
+            src: FnSource<I, F>,
+        }
+        // SAFETY: mu_addsource initializes the cache and source correctly
+        let src = unsafe { ffi::mu_addsource(cache, size_of::<FnSrc<I, F>>(), Default::default()) };
+        let fn_src = src as *mut FnSrc<I, F>;
+        // SAFETY: src is allocated by mu_addsource above with room for a
+        // FnSrc, and its src field is uninitialized memory
+        unsafe {
+            std::ptr::addr_of_mut!((*fn_src).src).write(self);
+            // the source never moves, so its name may borrow the string
+            (*src).name = (*fn_src).src.name.as_str().into();
+            (*src).init = Some(init_fn::<I, F>);
+            (*src).free = Some(free_fn::<I, F>);
+            (*src).get_line = Some(get_line_fn::<I, F>);
+        }
+
+        unsafe extern "C" fn init_fn<I, F: Fn(&I) -> io::Result<String>>(
+            src: *mut ffi::mu_Source,
+        ) -> c_int {
+            // SAFETY: src is a valid FnSrc pointer created above
+            let src = unsafe { &mut *(src as *mut FnSrc<I, F>) };
+            let Ok(text) = (src.src.fetch)(&src.src.id) else {
+                return ffi::MU_ERR_SRCINIT;
+            };
+            let text = src.src.text.insert(text);
+            // SAFETY: calling mu_updatelines is safe
+            unsafe { ffi::mu_updatelines(&mut src.base, text.as_str().into()) };
+            ffi::MU_OK
+        }
+
+        unsafe extern "C" fn free_fn<I, F>(src: *mut ffi::mu_Source) {
+            // SAFETY: src was allocated by mu_addsource and is valid here
+            // after this call, src will be freed by C library.
+            unsafe { std::ptr::drop_in_place(src as *mut FnSrc<I, F>) };
+        }
+
+        unsafe extern "C" fn get_line_fn<I, F>(
+            src: *mut ffi::mu_Source,
+            line_no: std::ffi::c_uint,
+        ) -> ffi::mu_Slice {
+            // SAFETY: src is a valid FnSrc pointer
+            let src = unsafe { &mut *(src as *mut FnSrc<I, F>) };
+            // SAFETY: calling mu_getline is safe
+            let line = unsafe { *ffi::mu_getline(&mut src.base, line_no) };
+            let text = src.src.text.as_deref().unwrap_or_default().as_bytes();
+            text[line.byte_offset as usize..][..line.byte_len as usize].into()
+        }
+    }
+}
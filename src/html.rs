@@ -0,0 +1,552 @@
+//! HTML output: single reports, and many reports as one page.
+
+use std::ffi::{c_char, c_int, c_void};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::{ColorKind, Config, Emitter, LineKind, RawCache, Report, TitleLevel, ffi};
+
+/// Name of the index group for reports without any label.
+const NO_FILE: &str = "(no location)";
+
+const STYLE: &str = "\
+body{font-family:sans-serif;margin:0;display:flex}\
+nav{width:18em;padding:1em;border-right:1px solid #ccc;height:100vh;overflow:auto;position:sticky;top:0}\
+main{flex:1;padding:1em}\
+nav ul{padding-left:1em}\
+pre{background:#f6f8fa;padding:1em;overflow:auto}\
+.error{color:#c00}.warning{color:#a60}\
+.hidden{display:none}";
+
+const SCRIPT: &str = "\
+document.querySelectorAll('#filter input').forEach(function(box){\
+box.addEventListener('change',function(){\
+document.querySelectorAll('[data-level=\"'+box.value+'\"]').forEach(function(e){\
+e.classList.toggle('hidden',!box.checked);});});});";
+
+/// A rendered report recorded by an [`HtmlReportWriter`].
+struct Entry {
+    file: Option<String>,
+    level: String,
+    title: String,
+    code: Option<String>,
+    text: String,
+}
+
+impl Entry {
+    /// Severity key used for filtering, e.g. `"error"`.
+    fn key(&self) -> String {
+        let key = self.level.to_lowercase();
+        key.replace(|c: char| !c.is_alphanumeric(), "-")
+    }
+}
+
+/// Collects many rendered reports into a single self-contained HTML page.
+///
+/// The page has an index of all reports grouped by file, and can be
+/// filtered by severity, which makes it suitable for publishing analyzer
+/// results as a CI artifact. Reports are rendered without colors.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Emitter, HtmlReportWriter, Level, Report};
+/// let mut emitter = Emitter::new()
+///     .with_report(
+///         Report::new()
+///             .with_title(Level::Error, "unknown variable")
+///             .with_label(8..9)
+///             .with_message("not found in this scope"),
+///     );
+///
+/// let mut page = HtmlReportWriter::new().with_title("Lint results");
+/// page.add_emitter(&mut emitter, ("let a = b;", "main.rs"))?;
+///
+/// let html = page.to_html();
+/// assert!(html.contains("<title>Lint results</title>"));
+/// assert!(html.contains("main.rs"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct HtmlReportWriter {
+    title: String,
+    entries: Vec<Entry>,
+}
+
+impl Default for HtmlReportWriter {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            title: "Diagnostics".into(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl HtmlReportWriter {
+    /// Create a new empty page.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the title of the page.
+    ///
+    /// Default: `"Diagnostics"`
+    #[inline]
+    #[must_use]
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Render all reports of `emitter` and add them to the page.
+    ///
+    /// Each report is listed in the index under the name of the source of
+    /// its first label. See [`Emitter::render_to_string`] for the accepted
+    /// caches.
+    pub fn add_emitter(
+        &mut self,
+        emitter: &mut Emitter<'_>,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        emitter.render_each(cache, true, |rendered| {
+            self.entries.push(Entry {
+                file: rendered.file,
+                level: rendered.level.into(),
+                title: rendered.title.into(),
+                code: rendered.code.map(Into::into),
+                text: rendered.text,
+            });
+            Ok(())
+        })
+    }
+
+    /// Add an already rendered report to the page.
+    ///
+    /// `rendered` should be rendered without colors. It is listed in the
+    /// index under `file`, or a separate group if [`None`].
+    pub fn add_rendered<'a, L: Into<TitleLevel<'a>>>(
+        &mut self,
+        file: Option<&str>,
+        level: L,
+        title: &str,
+        rendered: &str,
+    ) {
+        self.entries.push(Entry {
+            file: file.map(Into::into),
+            level: level.into().name().into(),
+            title: title.into(),
+            code: None,
+            text: rendered.into(),
+        });
+    }
+
+    /// The number of reports on the page.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no report has been added.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Produce the HTML page.
+    pub fn to_html(&self) -> String {
+        let mut files: Vec<Option<&str>> = Vec::new();
+        let mut levels: Vec<(String, &str)> = Vec::new();
+        for entry in &self.entries {
+            if !files.contains(&entry.file.as_deref()) {
+                files.push(entry.file.as_deref());
+            }
+            if !levels.iter().any(|(key, _)| *key == entry.key()) {
+                levels.push((entry.key(), &entry.level));
+            }
+        }
+
+        let mut out = String::new();
+        let title = escape(&self.title);
+        let _ = write!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<nav>\n\
+             <h1>{title}</h1>\n<div id=\"filter\">\n"
+        );
+        for (key, name) in &levels {
+            let count = self.entries.iter().filter(|e| e.key() == *key).count();
+            let _ = writeln!(
+                out,
+                "<label class=\"{key}\"><input type=\"checkbox\" value=\"{key}\" checked> {} ({count})</label><br>",
+                escape(name)
+            );
+        }
+        out.push_str("</div>\n");
+        for file in &files {
+            let _ = writeln!(out, "<h2>{}</h2>\n<ul>", escape(file.unwrap_or(NO_FILE)));
+            for (i, entry) in self.entries.iter().enumerate() {
+                if entry.file.as_deref() != *file {
+                    continue;
+                }
+                let _ = writeln!(
+                    out,
+                    "<li data-level=\"{}\"><a href=\"#report-{i}\" class=\"{}\">{}</a></li>",
+                    entry.key(),
+                    entry.key(),
+                    escape(&header(entry))
+                );
+            }
+            out.push_str("</ul>\n");
+        }
+        out.push_str("</nav>\n<main>\n");
+        for (i, entry) in self.entries.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "<section id=\"report-{i}\" data-level=\"{}\">\n<pre>{}</pre>\n</section>",
+                entry.key(),
+                escape(&entry.text)
+            );
+        }
+        let _ = write!(
+            out,
+            "</main>\n<script>{SCRIPT}</script>\n</body>\n</html>\n"
+        );
+        out
+    }
+
+    /// Write the HTML page to any type implementing `Write`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.to_html().as_bytes())
+    }
+}
+
+/// The index line of a report.
+fn header(entry: &Entry) -> String {
+    match &entry.code {
+        Some(code) => format!("[{code}] {}: {}", entry.level, entry.title),
+        None => format!("{}: {}", entry.level, entry.title),
+    }
+}
+
+/// Escape `s` for HTML (or XML) text and attribute values.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The CSS class and inline style of spans of color `kind`.
+fn kind_style(kind: ColorKind) -> (&'static str, &'static str) {
+    match kind {
+        ColorKind::Reset => ("", ""),
+        ColorKind::Error => ("mu-error", "color:#c00"),
+        ColorKind::Warning => ("mu-warning", "color:#a60"),
+        ColorKind::Kind => ("mu-kind", "color:#afafff"),
+        ColorKind::Margin => ("mu-margin", "color:#949494"),
+        ColorKind::SkippedMargin => ("mu-skipped-margin", "color:#585858"),
+        ColorKind::Unimportant => ("mu-unimportant", "color:#b2b2b2"),
+        ColorKind::Note => ("mu-note", "color:#87d7af"),
+        ColorKind::Label => ("mu-label", ""),
+        ColorKind::Emphasis => ("mu-emphasis", "font-weight:bold"),
+        ColorKind::InlineCode => ("mu-inline-code", "color:#d7af87"),
+        ColorKind::Context => ("mu-context", "opacity:0.6"),
+        ColorKind::Info => ("mu-info", "color:#00c"),
+        ColorKind::Hint => ("mu-hint", "color:#0aa"),
+        ColorKind::Advice => ("mu-advice", "color:#0a0"),
+        ColorKind::Expected => ("mu-expected", "color:#0a0"),
+        ColorKind::Found => ("mu-found", "color:#c00"),
+    }
+}
+
+/// The CSS color of the xterm 256-color palette entry `n`.
+fn xterm_color(n: u8) -> String {
+    const BASIC: [&str; 16] = [
+        "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+        "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+    ];
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match n {
+        0..=15 => BASIC[n as usize].into(),
+        16..=231 => {
+            let n = n - 16;
+            let (r, g, b) = (n / 36, n / 6 % 6, n % 6);
+            let [r, g, b] = [r, g, b].map(|c| LEVELS[c as usize]);
+            format!("#{r:02x}{g:02x}{b:02x}")
+        }
+        _ => {
+            let v = 8 + (n - 232) * 10;
+            format!("#{v:02x}{v:02x}{v:02x}")
+        }
+    }
+}
+
+/// Receives the output of a report rendered as markup.
+pub(crate) trait Markup {
+    /// Start a span, closing the current one: colors replace each other.
+    fn open(&mut self, class: &str, style: &str);
+
+    /// Close the current span, if any.
+    fn close(&mut self);
+
+    /// Add plain, unescaped text.
+    fn text(&mut self, text: &str);
+
+    /// Called before each text with the kind of the line it is part of.
+    fn line_kind(&mut self, _kind: LineKind) {}
+
+    /// Translate an escape code of a per-label color into a span.
+    ///
+    /// Only the foreground colors produced by
+    /// [`ColorGenerator`](crate::ColorGenerator) are kept; other codes
+    /// close the current span.
+    fn escape_code(&mut self, code: &str) {
+        let params = code.strip_prefix("\x1b[").and_then(|c| c.strip_suffix('m'));
+        let color = params.and_then(|p| p.strip_prefix("38;5;")?.parse::<u8>().ok());
+        match color {
+            Some(n) => self.open("mu-color", &format!("color:{}", xterm_color(n))),
+            None => self.close(),
+        }
+    }
+}
+
+/// Collects the output of [`Report::render_to_html`] as lines, each with
+/// its own spans.
+#[derive(Default)]
+struct HtmlOutput {
+    lines: Vec<(LineKind, String)>,
+    line: String,
+    kind: Option<LineKind>,
+    span: Option<String>,
+    /// Whether `span` is still to be opened in the current line
+    reopen: bool,
+}
+
+impl HtmlOutput {
+    /// Finish the current line; the open color goes on in the next one.
+    fn newline(&mut self) {
+        if self.span.is_some() && !self.reopen {
+            self.line.push_str("</span>");
+            self.reopen = true;
+        }
+        self.line.push('\n');
+        let kind = self.kind.take().unwrap_or(LineKind::Footer);
+        self.lines.push((kind, std::mem::take(&mut self.line)));
+    }
+
+    /// The lines, with helps and notes of more than `shown` lines in
+    /// `<details>` sections showing their first lines as summary.
+    fn finish(mut self, messages: &[(LineKind, usize)], shown: usize) -> String {
+        if !self.line.is_empty() {
+            self.newline();
+        }
+        // start line and line count of each help, from the first help
+        // line, and of each note, up to the last note line
+        let mut collapsed = Vec::new();
+        for kind in [LineKind::Help, LineKind::Note] {
+            let lines: Vec<usize> = (0..self.lines.len())
+                .filter(|&i| self.lines[i].0 == kind)
+                .collect();
+            let counts: Vec<usize> = messages
+                .iter()
+                .filter(|(k, _)| *k == kind)
+                .map(|(_, count)| *count)
+                .collect();
+            let total: usize = counts.iter().sum();
+            if total > lines.len() {
+                continue;
+            }
+            let mut next = if kind == LineKind::Help {
+                0
+            } else {
+                lines.len() - total
+            };
+            for count in counts {
+                if count > shown {
+                    collapsed.push((lines[next], count));
+                }
+                next += count;
+            }
+        }
+        let mut out = String::from("<pre class=\"musubi\">");
+        let mut i = 0;
+        while i < self.lines.len() {
+            let Some(&(_, count)) = collapsed.iter().find(|(start, _)| *start == i) else {
+                out.push_str(&self.lines[i].1);
+                i += 1;
+                continue;
+            };
+            let text = |lines: &[(LineKind, String)]| {
+                let text: String = lines.iter().map(|(_, line)| line.as_str()).collect();
+                text.trim_end_matches('\n').to_string()
+            };
+            let _ = write!(
+                out,
+                "<details class=\"mu-collapsed\"><summary>{}</summary>{}</details>",
+                text(&self.lines[i..i + shown]),
+                text(&self.lines[i + shown..i + count])
+            );
+            i += count;
+        }
+        out.push_str("</pre>\n");
+        out
+    }
+}
+
+impl Markup for HtmlOutput {
+    fn open(&mut self, class: &str, style: &str) {
+        self.close();
+        self.span = Some(format!("<span class=\"{class}\" style=\"{style}\">"));
+        self.line.push_str(self.span.as_deref().unwrap_or_default());
+    }
+
+    fn close(&mut self) {
+        if self.span.take().is_some() && !std::mem::take(&mut self.reopen) {
+            self.line.push_str("</span>");
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.newline();
+            }
+            if part.is_empty() {
+                continue;
+            }
+            if std::mem::take(&mut self.reopen) {
+                self.line.push_str(self.span.as_deref().unwrap_or_default());
+            }
+            self.line.push_str(&escape(part));
+        }
+    }
+
+    fn line_kind(&mut self, kind: LineKind) {
+        self.kind.get_or_insert(kind);
+    }
+}
+
+impl Report<'_> {
+    /// Render the report as an HTML fragment.
+    ///
+    /// The output is a `<pre class="musubi">` block with every colored part
+    /// wrapped in a `<span>`, carrying a class per [`ColorKind`] (e.g.
+    /// `mu-error`, `mu-margin`) and an inline style matching the built-in
+    /// ANSI scheme. The fragment needs no stylesheet, which makes it suitable
+    /// for web playgrounds and documentation, while the classes allow
+    /// restyling it. Per-label colors from a
+    /// [`ColorGenerator`](crate::ColorGenerator) become `mu-color` spans.
+    ///
+    /// The color provider of the report's [`Config`] is ignored here, and
+    /// colors are always rendered. Helps and notes of more than one line, or
+    /// more than [`Config::with_collapse_notes`] lines if set, become
+    /// `<details>` sections, collapsed to their first lines.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_title(Level::Error, "Syntax <error>")
+    ///     .with_label(0..3)
+    ///     .with_message("unexpected token");
+    ///
+    /// let html = report.render_to_html("let x")?;
+    /// assert!(html.starts_with(r#"<pre class="musubi">"#));
+    /// assert!(html.contains(r#"<span class="mu-error" style="color:#c00">Error:</span>"#));
+    /// assert!(html.contains("Syntax &lt;error&gt;"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_html(&mut self, cache: impl Into<RawCache>) -> io::Result<String> {
+        let mut html = HtmlOutput::default();
+        // all lines are rendered, to be collapsed here
+        let cfg = self.config.get_or_insert_with(Config::default);
+        let saved = std::mem::take(&mut cfg.inner.collapse_notes);
+        let result = self.render_markup(cache, &mut html);
+        if let Some(cfg) = self.config.as_mut() {
+            cfg.inner.collapse_notes = saved;
+        }
+        result?;
+        html.close();
+        let diagnostic = self.to_diagnostic();
+        let count = |kind| move |msg: &std::borrow::Cow<'_, str>| (kind, msg.split('\n').count());
+        let messages: Vec<_> = (diagnostic.helps().iter().map(count(LineKind::Help)))
+            .chain(diagnostic.notes().iter().map(count(LineKind::Note)))
+            .collect();
+        Ok(html.finish(&messages, saved.max(1) as usize))
+    }
+
+    /// Render the report into `out`, with a span per color.
+    ///
+    /// The color provider of the report's [`Config`] is replaced during the
+    /// render, so colors are always rendered.
+    pub(crate) fn render_markup<M: Markup>(
+        &mut self,
+        cache: impl Into<RawCache>,
+        out: &mut M,
+    ) -> io::Result<()> {
+        /// The sink and the report it renders, for the callbacks.
+        struct Ud<'m, M> {
+            out: &'m mut M,
+            report: *const ffi::mu_Report,
+        }
+
+        extern "C" fn color_fn<M: Markup>(
+            ud: *mut c_void,
+            kind: ffi::mu_ColorKind,
+        ) -> ffi::mu_Chunk {
+            // SAFETY: ud is the Ud set as color_ud below
+            let out = unsafe { &mut *(*(ud as *mut Ud<'_, M>)).out };
+            match kind_style(ColorKind::from_ffi(kind)) {
+                ("", _) => out.close(),
+                (class, style) => out.open(class, style),
+            }
+            c"".as_ptr()
+        }
+
+        unsafe extern "C" fn writer_callback<M: Markup>(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is the Ud passed to mu_writer below
+            let ud = unsafe { &mut *(ud as *mut Ud<'_, M>) };
+            // SAFETY: the report is rendering, so it is valid
+            let kind = unsafe { ffi::mu_linekind(ud.report) };
+            let out = &mut *ud.out;
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            let text = String::from_utf8_lossy(slice);
+            out.line_kind(LineKind::from_ffi(kind));
+            match text.starts_with('\x1b') {
+                true => out.escape_code(&text),
+                false => out.text(&text),
+            }
+            ffi::MU_OK
+        }
+
+        let mut ud = Ud {
+            out,
+            report: self.ptr,
+        };
+        let ud = &mut ud as *mut Ud<'_, M> as *mut c_void;
+        let cfg = self.config.get_or_insert_with(Config::default);
+        let saved = (cfg.inner.color, cfg.inner.color_ud);
+        cfg.inner.color = Some(color_fn::<M>);
+        cfg.inner.color_ud = ud;
+        // SAFETY: self.ptr is valid, callback has correct signature, out is valid for this scope
+        unsafe { self.set_writer(Some(writer_callback::<M>), ud) };
+        let result = self.render(cache);
+        if let Some(cfg) = self.config.as_mut() {
+            (cfg.inner.color, cfg.inner.color_ud) = saved;
+        }
+        result
+    }
+}
@@ -0,0 +1,173 @@
+//! Labels built on their own, before they are added to a report.
+
+use std::borrow::Cow;
+
+use crate::{DiagnosticLabel, GenColor, IntoColor, LabelSpan, Report};
+
+/// A label with its message and style, built apart from a report.
+///
+/// The builder methods of [`Report`] apply to the label added last, which
+/// is awkward when labels are made in helper functions or collected into a
+/// `Vec` first. A `Label` carries everything about one label, and
+/// [`Report::with_label_obj`] adds it at once.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{ColorGenerator, Config, Label, Level, Report};
+/// fn unused(span: std::ops::Range<usize>) -> Label<'static> {
+///     Label::new(span).with_message("never used")
+/// }
+///
+/// let mut cg = ColorGenerator::new();
+/// let color = cg.next_color();
+/// let labels = vec![
+///     unused(4..5),
+///     Label::new(8..10).with_message("here").with_color(&color).with_order(1),
+/// ];
+///
+/// let mut report = Report::new()
+///     .with_config(Config::new().with_color_disabled())
+///     .with_title(Level::Warning, "unused values");
+/// for label in labels {
+///     report = report.with_label_obj(label);
+/// }
+/// let output = report.render_to_string("let x = 42;")?;
+/// assert!(output.contains("never used"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Label<'a> {
+    label: DiagnosticLabel<'a>,
+    color: Option<GenColor>,
+    order: i32,
+    priority: i32,
+}
+
+impl<'a> Label<'a> {
+    /// Create a label at `span`, without message or color.
+    #[inline]
+    pub fn new<L: Into<LabelSpan>>(span: L) -> Self {
+        DiagnosticLabel::new(span).into()
+    }
+
+    /// Set the message of the label.
+    ///
+    /// See [`Report::with_message`].
+    #[inline]
+    #[must_use]
+    pub fn with_message(mut self, msg: impl Into<Cow<'a, str>>) -> Self {
+        self.label = self.label.with_message(msg);
+        self
+    }
+
+    /// Set the color of the label, from a [`GenColor`] or the code a
+    /// [`Color`](crate::Color) writes for [`ColorKind::Label`](crate::ColorKind::Label).
+    ///
+    /// See [`Report::with_color`].
+    #[inline]
+    #[must_use]
+    pub fn with_color(mut self, color: impl Into<GenColor>) -> Self {
+        self.color = Some(color.into());
+        self
+    }
+
+    /// Set the display order of the label.
+    ///
+    /// See [`Report::with_order`]. Default: `0`
+    #[inline]
+    #[must_use]
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Set the priority of the label.
+    ///
+    /// See [`Report::with_priority`]. Default: `0`
+    #[inline]
+    #[must_use]
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Make the label the primary label of its group.
+    ///
+    /// See [`Report::with_primary_label`]. Default: [`false`]
+    #[inline]
+    #[must_use]
+    pub fn with_primary(mut self, primary: bool) -> Self {
+        self.label = self.label.with_primary(primary);
+        self
+    }
+
+    /// The span of the label.
+    #[inline]
+    pub fn span(&self) -> LabelSpan {
+        self.label.span()
+    }
+
+    /// The message of the label, if any.
+    #[inline]
+    pub fn message(&self) -> Option<&str> {
+        self.label.message()
+    }
+
+    /// The color of the label, if any.
+    #[inline]
+    pub fn color(&self) -> Option<&GenColor> {
+        self.color.as_ref()
+    }
+}
+
+impl<'a> From<DiagnosticLabel<'a>> for Label<'a> {
+    /// A label with the span, message and primary flag of `label`.
+    #[inline]
+    fn from(label: DiagnosticLabel<'a>) -> Self {
+        Self {
+            label,
+            color: None,
+            order: 0,
+            priority: 0,
+        }
+    }
+}
+
+impl<L: Into<LabelSpan>> From<L> for Label<'_> {
+    #[inline]
+    fn from(span: L) -> Self {
+        Self::new(span)
+    }
+}
+
+impl<'a> Report<'a> {
+    /// Add a label built with [`Label`] or [`DiagnosticLabel`], with its
+    /// message and style.
+    ///
+    /// Builder methods called afterwards, like
+    /// [`with_message`](Report::with_message), apply to this label.
+    #[must_use]
+    pub fn with_label_obj(self, label: impl Into<Label<'a>>) -> Self {
+        let Label {
+            label,
+            color,
+            order,
+            priority,
+        } = label.into();
+        let mut report = if label.is_primary() {
+            self.with_primary_label(label.span())
+        } else {
+            self.with_label(label.span())
+        };
+        if let Some(msg) = label.into_message() {
+            report = report.with_label_message(msg);
+        }
+        if let Some(color) = color {
+            let color = Box::new(color);
+            color.as_ref().into_color(&mut report);
+            // the box keeps the code at the address given to the report
+            report.label_colors.push(color);
+        }
+        report.with_order(order).with_priority(priority)
+    }
+}
@@ -204,14 +204,258 @@
 //! ```
 //!
 
+mod anchor;
+#[cfg(feature = "rust-backend")]
+mod backend;
+mod ci;
+mod coverage;
+mod diagnostic;
+mod emitter;
+mod env;
+mod error;
+mod export;
 mod ffi;
+mod file;
+mod find;
+mod fn_cache;
+mod html;
+mod label;
+#[cfg(feature = "lsp")]
+mod lsp;
+mod markdown;
+mod policy;
+#[cfg(feature = "sarif")]
+mod sarif;
+mod side;
+mod snapshot;
+mod source_map;
+mod style;
+mod svg;
+pub mod testing;
+mod theme;
+mod transform;
+#[cfg(feature = "tui")]
+mod tui;
+
+pub use anchor::Anchor;
+pub use coverage::Coverage;
+pub use diagnostic::{Applicability, Diagnostic, DiagnosticLabel, DiagnosticSuggestion};
+pub use emitter::{Emitter, WatchState};
+pub use error::{Error, SpanError};
+pub use file::FileSource;
+pub use find::SpanPattern;
+pub use fn_cache::FnCache;
+pub use html::HtmlReportWriter;
+pub use label::Label;
+pub use policy::Policy;
+#[cfg(feature = "sarif")]
+pub use sarif::SarifWriter;
+pub use snapshot::DiagnosticSnapshot;
+pub use source_map::SourceMap;
+pub use style::{Style, StyleColor};
+pub use theme::Theme;
+pub use transform::TransformSource;
+
+/// Raw bindings to the musubi C API.
+///
+/// This module re-exports the `bindgen`-generated declarations from `musubi.h`
+/// for advanced users who need a feature the safe wrapper does not cover yet.
+/// Use [`Report::as_raw`] and [`Cache::as_raw`] to get the underlying C objects
+/// of the safe types, then call the `mu_*` functions directly.
+///
+/// Everything in this module is `unsafe` to use: pointers must be valid,
+/// slices must outlive the report that references them, and the safe
+/// wrapper assumes it remains the only owner of its objects (never call
+/// `mu_delete` or `mu_delcache` on them). See `musubi.h` for the contract of
+/// each function.
+///
+/// # Example
+/// ```rust
+/// use musubi::{sys, Level, Report};
+///
+/// let mut report = Report::new()
+///     .with_title(Level::Error, "raw access")
+///     .with_label(0..3);
+/// // SAFETY: the report pointer is valid while `report` is alive, and the
+/// // note slice points to a static string.
+/// unsafe { sys::mu_note(report.as_raw(), "from C".into()) };
+/// let output = report.render_to_string("let x = 1;")?;
+/// assert!(output.contains("from C"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub mod sys {
+    pub use crate::ffi::*;
+}
 
+use std::borrow::{Borrow, Cow};
+use std::collections::HashMap;
 use std::ffi::{c_char, c_int, c_uint, c_void};
 use std::fmt::Debug;
-use std::io::{self, Write};
+use std::hash::Hash;
+use std::io::{self, IsTerminal, Write};
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
+use std::path::Path;
 use std::ptr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Weak};
+
+/// Version of the compiled musubi C library.
+///
+/// Returned by [`version()`]. This is the version of the renderer that was
+/// actually linked, which may differ from the crate version when the C
+/// library is provided externally.
+///
+/// # Example
+/// ```rust
+/// let v = musubi::version();
+/// assert!(v >= musubi::Version::new(0, 5, 0));
+/// println!("musubi {}", v);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// Major version number
+    pub major: u32,
+    /// Minor version number
+    pub minor: u32,
+    /// Patch version number
+    pub patch: u32,
+}
+
+impl Version {
+    /// Create a version from its components.
+    #[inline]
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Capabilities of the compiled musubi C library.
+///
+/// Returned by [`capabilities()`]. Use this to feature-detect at runtime
+/// instead of guessing from the crate version.
+///
+/// # Example
+/// ```rust
+/// let caps = musubi::capabilities();
+/// assert!(caps.max_labels > 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Whether file sources backed by C stdio are available
+    pub stdio: bool,
+    /// Whether the library traces internal errors to stderr
+    pub debug_log: bool,
+    /// Maximum number of labels in a single report
+    pub max_labels: usize,
+    /// Maximum byte length of a single character set glyph
+    pub max_glyph_size: usize,
+    /// Maximum byte length of a color escape sequence
+    pub max_color_size: usize,
+}
+
+/// Get the version of the compiled musubi C library.
+#[inline]
+pub fn version() -> Version {
+    // SAFETY: mu_version has no preconditions
+    let num = unsafe { ffi::mu_version() };
+    Version::new(num / 10000, num / 100 % 100, num % 100)
+}
+
+/// Error returned by [`check_version`] when the linked C library is not
+/// compatible with the header these bindings were generated from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VersionMismatch {
+    /// Version of the header the bindings were generated from
+    pub header: Version,
+    /// Version reported by the linked library
+    pub library: Version,
+    /// Whether the library lays out the public structs differently from
+    /// the header, as libraries built from other revisions of the same
+    /// version may
+    pub layout: bool,
+}
+
+impl std::fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "musubi library {} is incompatible with header {}",
+            self.library, self.header
+        )?;
+        if self.layout {
+            write!(f, " (struct layouts differ)")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
+/// Check that the linked C library matches the bundled header.
+///
+/// Versions are compatible when their major and minor numbers agree, and
+/// the library has the same sizes of the public structs, like `mu_Config`,
+/// as the header. This is always the case for the vendored build; when
+/// linking a system library (feature `system`), call this once at startup
+/// before creating reports.
+///
+/// ```rust
+/// musubi::check_version().expect("incompatible libmusubi");
+/// ```
+pub fn check_version() -> Result<(), VersionMismatch> {
+    let header = Version::new(
+        ffi::MU_VERSION_MAJOR,
+        ffi::MU_VERSION_MINOR,
+        ffi::MU_VERSION_PATCH,
+    );
+    let library = version();
+    let sizes = [
+        (ffi::MU_STRUCT_CONFIG, size_of::<ffi::mu_Config>()),
+        (ffi::MU_STRUCT_ALLOCATOR, size_of::<ffi::mu_Allocator>()),
+        (ffi::MU_STRUCT_CACHE, size_of::<ffi::mu_Cache>()),
+        (ffi::MU_STRUCT_SOURCE, size_of::<ffi::mu_Source>()),
+        (ffi::MU_STRUCT_LINE, size_of::<ffi::mu_Line>()),
+        (ffi::MU_STRUCT_COLORGEN, size_of::<ffi::mu_ColorGen>()),
+    ];
+    // SAFETY: mu_structsize has no preconditions
+    let layout = (sizes.iter()).any(|&(which, size)| unsafe { ffi::mu_structsize(which) } != size);
+    if (header.major, header.minor) == (library.major, library.minor) && !layout {
+        Ok(())
+    } else {
+        Err(VersionMismatch {
+            header,
+            library,
+            layout,
+        })
+    }
+}
+
+/// Get the capabilities of the compiled musubi C library.
+#[inline]
+pub fn capabilities() -> Capabilities {
+    // SAFETY: mu_features has no preconditions
+    let features = unsafe { ffi::mu_features() };
+    Capabilities {
+        stdio: features & ffi::MU_FEATURE_STDIO != 0,
+        debug_log: features & ffi::MU_FEATURE_DEBUG != 0,
+        max_labels: ffi::MU_MAX_LABELS as usize,
+        // first byte of a chunk is its length
+        max_glyph_size: ffi::MU_CHUNK_MAX_SIZE as usize,
+        max_color_size: ffi::sizes::COLOR_CODE - 1,
+    }
+}
 
 /// Diagnostic severity level
 ///
@@ -224,6 +468,14 @@ pub enum Level {
     Error,
     /// Warning level - indicates a potential problem
     Warning,
+    /// Info level - reports a fact without implying a problem
+    Info,
+    /// Note level - adds context to other diagnostics
+    Note,
+    /// Hint level - suggests a possible improvement
+    Hint,
+    /// Advice level - recommends a better practice
+    Advice,
 }
 
 impl From<Level> for ffi::mu_Level {
@@ -232,6 +484,10 @@ impl From<Level> for ffi::mu_Level {
         match level {
             Level::Error => ffi::mu_Level::MU_ERROR,
             Level::Warning => ffi::mu_Level::MU_WARNING,
+            Level::Info => ffi::mu_Level::MU_INFO,
+            Level::Note => ffi::mu_Level::MU_NOTE,
+            Level::Hint => ffi::mu_Level::MU_HINT,
+            Level::Advice => ffi::mu_Level::MU_ADVICE,
         }
     }
 }
@@ -283,6 +539,160 @@ impl From<LabelAttach> for ffi::mu_LabelAttach {
     }
 }
 
+impl LabelAttach {
+    #[inline]
+    fn from_ffi(attach: ffi::mu_LabelAttach) -> Self {
+        match attach {
+            ffi::mu_LabelAttach::MU_ATTACH_MIDDLE => LabelAttach::Middle,
+            ffi::mu_LabelAttach::MU_ATTACH_START => LabelAttach::Start,
+            ffi::mu_LabelAttach::MU_ATTACH_END => LabelAttach::End,
+        }
+    }
+}
+
+/// How overlapping labels on one line are drawn
+///
+/// # Example
+/// ```text
+/// MergeColors:
+///   foo(bar, baz)
+///       ^^^^^^^^
+///
+/// Stack:
+///   foo(bar, baz)
+///       ^^^^^^^^
+///       ^^^
+///
+/// HideLower:
+///   foo(bar, baz)
+///       ^^^
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overlap {
+    /// Draw each overlapping underline on a row of its own
+    Stack,
+    /// Draw one underline row, each column taken by the label with the
+    /// highest priority, or the shortest one (default)
+    #[default]
+    MergeColors,
+    /// Drop labels overlapped by a label with higher priority, or by a
+    /// shorter one
+    HideLower,
+}
+
+impl From<Overlap> for ffi::mu_Overlap {
+    #[inline]
+    fn from(overlap: Overlap) -> Self {
+        match overlap {
+            Overlap::Stack => ffi::mu_Overlap::MU_OVERLAP_STACK,
+            Overlap::MergeColors => ffi::mu_Overlap::MU_OVERLAP_MERGE,
+            Overlap::HideLower => ffi::mu_Overlap::MU_OVERLAP_HIDE,
+        }
+    }
+}
+
+impl Overlap {
+    #[inline]
+    fn from_ffi(overlap: ffi::mu_Overlap) -> Self {
+        match overlap {
+            ffi::mu_Overlap::MU_OVERLAP_STACK => Overlap::Stack,
+            ffi::mu_Overlap::MU_OVERLAP_MERGE => Overlap::MergeColors,
+            ffi::mu_Overlap::MU_OVERLAP_HIDE => Overlap::HideLower,
+        }
+    }
+}
+
+/// How the closing border of a report is drawn
+///
+/// # Example
+/// ```text
+/// Border (default):
+///   ---'
+///
+/// Labeled, for a report with code E0308:
+///   ---'-[ end E0308 ]
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Footer {
+    /// Close the margin with a corner (default)
+    #[default]
+    Border,
+    /// Draw no line after the report
+    Omit,
+    /// Draw an empty line after the report
+    Blank,
+    /// Close the margin with a corner followed by `[ end CODE ]`, or
+    /// `[ end ]` for reports without code
+    Labeled,
+}
+
+impl From<Footer> for ffi::mu_Footer {
+    #[inline]
+    fn from(footer: Footer) -> Self {
+        match footer {
+            Footer::Border => ffi::mu_Footer::MU_FOOTER_BORDER,
+            Footer::Omit => ffi::mu_Footer::MU_FOOTER_OMIT,
+            Footer::Blank => ffi::mu_Footer::MU_FOOTER_BLANK,
+            Footer::Labeled => ffi::mu_Footer::MU_FOOTER_LABELED,
+        }
+    }
+}
+
+impl Footer {
+    #[inline]
+    fn from_ffi(footer: ffi::mu_Footer) -> Self {
+        match footer {
+            ffi::mu_Footer::MU_FOOTER_BORDER => Footer::Border,
+            ffi::mu_Footer::MU_FOOTER_OMIT => Footer::Omit,
+            ffi::mu_Footer::MU_FOOTER_BLANK => Footer::Blank,
+            ffi::mu_Footer::MU_FOOTER_LABELED => Footer::Labeled,
+        }
+    }
+}
+
+/// Order of the sources of a report with labels in several sources
+///
+/// Labels with a lower [order](Report::with_order) still come first; the
+/// source order breaks ties between sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceOrder {
+    /// The order the sources were added to the cache (default)
+    #[default]
+    Cache,
+    /// The order the sources are first labeled in the report
+    Labels,
+    /// Sorted by source name
+    Name,
+    /// The source of the primary label first, then the cache order. Without
+    /// a primary label, the source of the label with the highest priority
+    /// comes first.
+    PrimaryFirst,
+}
+
+impl From<SourceOrder> for ffi::mu_SourceOrder {
+    #[inline]
+    fn from(order: SourceOrder) -> Self {
+        match order {
+            SourceOrder::Cache => ffi::mu_SourceOrder::MU_SOURCE_ORDER_CACHE,
+            SourceOrder::Labels => ffi::mu_SourceOrder::MU_SOURCE_ORDER_LABELS,
+            SourceOrder::Name => ffi::mu_SourceOrder::MU_SOURCE_ORDER_NAME,
+            SourceOrder::PrimaryFirst => ffi::mu_SourceOrder::MU_SOURCE_ORDER_PRIMARY,
+        }
+    }
+}
+
+impl SourceOrder {
+    #[inline]
+    fn from_ffi(order: ffi::mu_SourceOrder) -> Self {
+        match order {
+            ffi::mu_SourceOrder::MU_SOURCE_ORDER_CACHE => SourceOrder::Cache,
+            ffi::mu_SourceOrder::MU_SOURCE_ORDER_LABELS => SourceOrder::Labels,
+            ffi::mu_SourceOrder::MU_SOURCE_ORDER_NAME => SourceOrder::Name,
+            ffi::mu_SourceOrder::MU_SOURCE_ORDER_PRIMARY => SourceOrder::PrimaryFirst,
+        }
+    }
+}
+
 /// Index type for span positions
 ///
 /// Determines how span ranges are interpreted:
@@ -301,6 +711,10 @@ impl From<LabelAttach> for ffi::mu_LabelAttach {
 ///   span 0..3 selects "你" (3 bytes)
 ///   span 3..6 selects "好" (3 bytes)
 /// ```
+///
+/// Sources don't have to be valid UTF-8: each byte of an invalid sequence
+/// counts as one character, and is displayed as U+FFFD, so byte spans
+/// still underline exactly the bytes they cover.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum IndexType {
     /// Index by byte offset (0-indexed)
@@ -320,11 +734,71 @@ impl From<IndexType> for ffi::mu_IndexType {
     }
 }
 
+impl IndexType {
+    #[inline]
+    fn from_ffi(index_type: ffi::mu_IndexType) -> Self {
+        match index_type {
+            ffi::mu_IndexType::MU_INDEX_BYTE => IndexType::Byte,
+            ffi::mu_IndexType::MU_INDEX_CHAR => IndexType::Char,
+        }
+    }
+}
+
+/// Newline convention of a source
+///
+/// A source declares which line terminators end its lines, or detects them
+/// with [`Auto`](NewlineKind::Auto). A terminator always takes a single
+/// column, so spans covering `"\r\n"` render like spans covering `"\n"`.
+/// See [`Cache::with_newline_kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewlineKind {
+    /// Detect the convention; `"\n"`, `"\r\n"` and `"\r"` all end a line
+    /// (default). Also reported for sources without any newline.
+    #[default]
+    Auto,
+    /// Only `"\n"` ends a line; a `"\r"` is part of the line
+    Lf,
+    /// Like [`Auto`](NewlineKind::Auto), but reported as `"\r\n"`
+    CrLf,
+    /// Only `"\r"` ends a line
+    Cr,
+    /// More than one convention was detected
+    Mixed,
+}
+
+impl From<NewlineKind> for ffi::mu_Newline {
+    #[inline]
+    fn from(kind: NewlineKind) -> Self {
+        match kind {
+            NewlineKind::Auto => ffi::mu_Newline::MU_NEWLINE_AUTO,
+            NewlineKind::Lf => ffi::mu_Newline::MU_NEWLINE_LF,
+            NewlineKind::CrLf => ffi::mu_Newline::MU_NEWLINE_CRLF,
+            NewlineKind::Cr => ffi::mu_Newline::MU_NEWLINE_CR,
+            NewlineKind::Mixed => ffi::mu_Newline::MU_NEWLINE_MIXED,
+        }
+    }
+}
+
+impl NewlineKind {
+    #[inline]
+    fn from_ffi(kind: ffi::mu_Newline) -> Self {
+        match kind {
+            ffi::mu_Newline::MU_NEWLINE_AUTO => NewlineKind::Auto,
+            ffi::mu_Newline::MU_NEWLINE_LF => NewlineKind::Lf,
+            ffi::mu_Newline::MU_NEWLINE_CRLF => NewlineKind::CrLf,
+            ffi::mu_Newline::MU_NEWLINE_CR => NewlineKind::Cr,
+            ffi::mu_Newline::MU_NEWLINE_MIXED => NewlineKind::Mixed,
+        }
+    }
+}
+
 /// Color categories for diagnostic output
 ///
 /// Each category represents a different part of the diagnostic rendering
-/// that can be styled independently.
+/// that can be styled independently. New kinds may be added, so matches
+/// on it need a wildcard arm.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ColorKind {
     /// Reset all colors/styles to default
     Reset,
@@ -332,7 +806,7 @@ pub enum ColorKind {
     Error,
     /// Warning severity level and warning-related elements
     Warning,
-    /// Custom severity level names (e.g., "Hint", "Note")
+    /// Custom severity level names (e.g., "Lint")
     Kind,
     /// Line number margin (gutter)
     Margin,
@@ -340,10 +814,26 @@ pub enum ColorKind {
     SkippedMargin,
     /// Less important text (e.g., source file paths)
     Unimportant,
-    /// Note and help messages
+    /// Note and help messages, and the note severity level
     Note,
     /// Label highlights and arrows
     Label,
+    /// `**emphasized**` text in messages when markup is enabled
+    Emphasis,
+    /// `` `code` `` in the title, help and notes when inline code is enabled
+    InlineCode,
+    /// Unlabeled text inside a [context span](Report::with_context_span)
+    Context,
+    /// Info severity level
+    Info,
+    /// Hint severity level
+    Hint,
+    /// Advice severity level
+    Advice,
+    /// The expected side of an [expected/found label](Report::with_expected_found)
+    Expected,
+    /// The found side of an [expected/found label](Report::with_expected_found)
+    Found,
 }
 
 impl From<ColorKind> for ffi::mu_ColorKind {
@@ -359,6 +849,14 @@ impl From<ColorKind> for ffi::mu_ColorKind {
             ColorKind::Unimportant => ffi::mu_ColorKind::MU_COLOR_UNIMPORTANT,
             ColorKind::Note => ffi::mu_ColorKind::MU_COLOR_NOTE,
             ColorKind::Label => ffi::mu_ColorKind::MU_COLOR_LABEL,
+            ColorKind::Emphasis => ffi::mu_ColorKind::MU_COLOR_EMPHASIS,
+            ColorKind::InlineCode => ffi::mu_ColorKind::MU_COLOR_INLINE_CODE,
+            ColorKind::Context => ffi::mu_ColorKind::MU_COLOR_CONTEXT,
+            ColorKind::Info => ffi::mu_ColorKind::MU_COLOR_INFO,
+            ColorKind::Hint => ffi::mu_ColorKind::MU_COLOR_HINT,
+            ColorKind::Advice => ffi::mu_ColorKind::MU_COLOR_ADVICE,
+            ColorKind::Expected => ffi::mu_ColorKind::MU_COLOR_EXPECTED,
+            ColorKind::Found => ffi::mu_ColorKind::MU_COLOR_FOUND,
         }
     }
 }
@@ -376,6 +874,266 @@ impl ColorKind {
             ffi::mu_ColorKind::MU_COLOR_UNIMPORTANT => ColorKind::Unimportant,
             ffi::mu_ColorKind::MU_COLOR_NOTE => ColorKind::Note,
             ffi::mu_ColorKind::MU_COLOR_LABEL => ColorKind::Label,
+            ffi::mu_ColorKind::MU_COLOR_EMPHASIS => ColorKind::Emphasis,
+            ffi::mu_ColorKind::MU_COLOR_INLINE_CODE => ColorKind::InlineCode,
+            ffi::mu_ColorKind::MU_COLOR_CONTEXT => ColorKind::Context,
+            ffi::mu_ColorKind::MU_COLOR_INFO => ColorKind::Info,
+            ffi::mu_ColorKind::MU_COLOR_HINT => ColorKind::Hint,
+            ffi::mu_ColorKind::MU_COLOR_ADVICE => ColorKind::Advice,
+            ffi::mu_ColorKind::MU_COLOR_EXPECTED => ColorKind::Expected,
+            ffi::mu_ColorKind::MU_COLOR_FOUND => ColorKind::Found,
+        }
+    }
+}
+
+#[cfg(feature = "termcolor")]
+impl ColorKind {
+    /// The [`termcolor::ColorSpec`] used for this kind by
+    /// [`Report::render_to_write_color`].
+    ///
+    /// The colors match the built-in ANSI scheme of
+    /// [`Config::with_color_default`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::ColorKind;
+    /// use termcolor::Color;
+    /// assert_eq!(ColorKind::Error.color_spec().fg(), Some(&Color::Red));
+    /// assert!(ColorKind::Reset.color_spec().is_none());
+    /// ```
+    pub fn color_spec(self) -> termcolor::ColorSpec {
+        use termcolor::Color as C;
+        let fg = match self {
+            ColorKind::Reset | ColorKind::Label | ColorKind::Emphasis | ColorKind::Context => None,
+            ColorKind::Error => Some(C::Red),
+            ColorKind::Warning => Some(C::Yellow),
+            ColorKind::Kind => Some(C::Ansi256(147)),
+            ColorKind::Margin => Some(C::Ansi256(246)),
+            ColorKind::SkippedMargin => Some(C::Ansi256(240)),
+            ColorKind::Unimportant => Some(C::Ansi256(249)),
+            ColorKind::Note => Some(C::Ansi256(115)),
+            ColorKind::InlineCode => Some(C::Ansi256(180)),
+            ColorKind::Info => Some(C::Blue),
+            ColorKind::Hint => Some(C::Cyan),
+            ColorKind::Advice | ColorKind::Expected => Some(C::Green),
+            ColorKind::Found => Some(C::Red),
+        };
+        let mut spec = termcolor::ColorSpec::new();
+        spec.set_fg(fg)
+            .set_bold(self == ColorKind::Emphasis)
+            .set_dimmed(self == ColorKind::Context);
+        spec
+    }
+}
+
+/// The [`termcolor::WriteColor`] of a running
+/// [`Report::render_to_write_color`], which the color providers drive with
+/// `set_color` calls instead of returning escape codes.
+#[cfg(feature = "termcolor")]
+struct ColorSink<'a> {
+    writer: &'a mut dyn termcolor::WriteColor,
+    err: Option<io::Error>,
+}
+
+#[cfg(feature = "termcolor")]
+thread_local! {
+    /// The [`ColorSink`] of the `render_to_write_color` running on this
+    /// thread, or null; per-label colors are sent there.
+    static COLOR_SINK: std::cell::Cell<*mut c_void> = const { std::cell::Cell::new(ptr::null_mut()) };
+}
+
+#[cfg(feature = "termcolor")]
+impl ColorSink<'_> {
+    /// Keep the first error of the writer.
+    fn check(&mut self, result: io::Result<()>) -> c_int {
+        match result {
+            Ok(()) => ffi::MU_OK,
+            Err(e) => {
+                self.err = Some(e);
+                ffi::MU_ERR_WRITER
+            }
+        }
+    }
+
+    /// Switch to the color of `kind`.
+    fn set_kind(&mut self, kind: ColorKind) {
+        if self.err.is_none() {
+            let result = match kind {
+                ColorKind::Reset => self.writer.reset(),
+                kind => self.writer.set_color(&kind.color_spec()),
+            };
+            self.check(result);
+        }
+    }
+
+    /// Switch to the color of the escape code `code` of a per-label color.
+    fn set_code(&mut self, code: &[u8]) {
+        if self.err.is_none() {
+            let result = match parse_sgr(code) {
+                Some(spec) if spec.is_none() => self.writer.reset(),
+                Some(spec) => self.writer.set_color(&spec),
+                None => Ok(()),
+            };
+            self.check(result);
+        }
+    }
+}
+
+/// Send the escape code `code` of a per-label color to the running
+/// [`Report::render_to_write_color`], returning whether there is one.
+#[cfg(feature = "termcolor")]
+fn sink_label_color(code: &[u8]) -> bool {
+    let sink = COLOR_SINK.get();
+    if !sink.is_null() {
+        // SAFETY: the sink is set only while its render runs on this thread
+        unsafe { &mut *(sink as *mut ColorSink) }.set_code(code);
+    }
+    !sink.is_null()
+}
+
+/// Parse a whole ANSI SGR escape sequence (`ESC [ ... m`) into a color spec.
+///
+/// Used by [`Report::render_to_write_color`] to translate the escape codes
+/// returned by per-label colors (e.g. [`GenColor`]) into `set_color` calls.
+#[cfg(feature = "termcolor")]
+fn parse_sgr(data: &[u8]) -> Option<termcolor::ColorSpec> {
+    use termcolor::Color as C;
+    fn basic(n: u8) -> C {
+        [
+            C::Black,
+            C::Red,
+            C::Green,
+            C::Yellow,
+            C::Blue,
+            C::Magenta,
+            C::Cyan,
+            C::White,
+        ][n as usize]
+    }
+    fn extended(params: &mut impl Iterator<Item = u8>) -> Option<C> {
+        match params.next()? {
+            5 => Some(C::Ansi256(params.next()?)),
+            2 => Some(C::Rgb(params.next()?, params.next()?, params.next()?)),
+            _ => None,
+        }
+    }
+    let body = data.strip_prefix(b"\x1b[")?.strip_suffix(b"m")?;
+    let body = std::str::from_utf8(body).ok()?;
+    let mut params = body
+        .split(';')
+        .map(|p| if p.is_empty() { Ok(0) } else { p.parse::<u8>() })
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?
+        .into_iter();
+    let mut spec = termcolor::ColorSpec::new();
+    while let Some(p) = params.next() {
+        match p {
+            0 => {
+                spec.clear();
+                &mut spec
+            }
+            1 => spec.set_bold(true),
+            2 => spec.set_dimmed(true),
+            3 => spec.set_italic(true),
+            4 => spec.set_underline(true),
+            9 => spec.set_strikethrough(true),
+            30..=37 => spec.set_fg(Some(basic(p - 30))),
+            38 => spec.set_fg(Some(extended(&mut params)?)),
+            39 => spec.set_fg(None),
+            40..=47 => spec.set_bg(Some(basic(p - 40))),
+            48 => spec.set_bg(Some(extended(&mut params)?)),
+            49 => spec.set_bg(None),
+            90..=97 => spec.set_fg(Some(basic(p - 90))).set_intense(true),
+            100..=107 => spec.set_bg(Some(basic(p - 100))).set_intense(true),
+            _ => return None,
+        };
+    }
+    Some(spec)
+}
+
+/// Role of a rendered output line
+///
+/// Passed to the callback of [`Report::render_lines`] together with each
+/// line, so custom sinks can treat parts of the diagnostic differently.
+///
+/// # Example
+/// ```text
+/// Error: Something went wrong          <- Header
+///    ,-[ main.rs:1:5 ]                 <- Reference
+///    |                                 <- Empty
+///  1 | let x = 42;                     <- Source
+///    |     |                           <- Underline
+///    |     `-- here                    <- Arrow
+///    |                                 <- Empty
+///    | Help: try this                  <- Help
+/// ---'                                 <- Footer
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineKind {
+    /// The title line with the level and error code
+    Header,
+    /// The `,-[ file:line:col ]` line opening each source group
+    Reference,
+    /// A blank margin-only separator line
+    Empty,
+    /// A line of source code (including context lines)
+    Source,
+    /// Underlines and vertical bars below a source line
+    Underline,
+    /// Arrows leading to a label message
+    Arrow,
+    /// The `:` marker for skipped lines inside a multi-line label
+    Ellipsis,
+    /// A help message line
+    Help,
+    /// A note message line
+    Note,
+    /// The closing line of the diagnostic
+    Footer,
+}
+
+/// Where the message of a label is drawn, see [`Report::measure`].
+///
+/// Rows count output lines from the header at row 0, and columns count
+/// characters from the start of the output line, both 0-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LabelPosition {
+    /// Index of the label, in the order labels were added
+    pub label: usize,
+    /// Output row of the first line of the message
+    pub row: usize,
+    /// Column where the message starts
+    pub column: usize,
+    /// Output row of the source line the label annotates
+    pub source_row: usize,
+    /// 1-based line number of that source line, as shown in the margin
+    pub line: usize,
+}
+
+/// The source line shown by a rendered line, see
+/// [`Report::render_line_map`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceLine {
+    /// ID of the source
+    pub src_id: usize,
+    /// 1-based line number, as shown in the margin
+    pub line: usize,
+}
+
+impl LineKind {
+    #[inline]
+    fn from_ffi(kind: ffi::mu_LineKind) -> Self {
+        match kind {
+            ffi::mu_LineKind::MU_LINE_HEADER => LineKind::Header,
+            ffi::mu_LineKind::MU_LINE_REFERENCE => LineKind::Reference,
+            ffi::mu_LineKind::MU_LINE_EMPTY => LineKind::Empty,
+            ffi::mu_LineKind::MU_LINE_SOURCE => LineKind::Source,
+            ffi::mu_LineKind::MU_LINE_UNDERLINE => LineKind::Underline,
+            ffi::mu_LineKind::MU_LINE_ARROW => LineKind::Arrow,
+            ffi::mu_LineKind::MU_LINE_ELLIPSIS => LineKind::Ellipsis,
+            ffi::mu_LineKind::MU_LINE_HELP => LineKind::Help,
+            ffi::mu_LineKind::MU_LINE_NOTE => LineKind::Note,
+            ffi::mu_LineKind::MU_LINE_FOOTER => LineKind::Footer,
         }
     }
 }
@@ -384,13 +1142,49 @@ impl ColorKind {
 ///
 /// This enables flexible title creation:
 /// - `.with_title(Level::Error, "message")` - standard level
-/// - `.with_title("Note", "message")` - custom level name
+/// - `.with_title("Lint", "message")` - custom level name
+#[derive(Clone, Copy)]
 pub struct TitleLevel<'a> {
     level: ffi::mu_Level,
     custom_name: ffi::mu_Slice,
     _marker: PhantomData<&'a ()>,
 }
 
+impl<'a> TitleLevel<'a> {
+    /// The level name shown in the header.
+    fn name(&self) -> &'a str {
+        match self.level {
+            ffi::mu_Level::MU_ERROR => "Error",
+            ffi::mu_Level::MU_WARNING => "Warning",
+            ffi::mu_Level::MU_INFO => "Info",
+            ffi::mu_Level::MU_NOTE => "Note",
+            ffi::mu_Level::MU_HINT => "Hint",
+            ffi::mu_Level::MU_ADVICE => "Advice",
+            _ => Result::from(self.custom_name).unwrap_or_default(),
+        }
+    }
+
+    /// Whether this is the standard error level.
+    fn is_error(&self) -> bool {
+        self.level == ffi::mu_Level::MU_ERROR
+    }
+
+    /// Whether this is the standard warning level.
+    fn is_warning(&self) -> bool {
+        self.level == ffi::mu_Level::MU_WARNING
+    }
+
+    /// The label priority derived from the level: errors over warnings over
+    /// the rest.
+    fn severity(&self) -> i32 {
+        match self.level {
+            ffi::mu_Level::MU_ERROR => 2,
+            ffi::mu_Level::MU_WARNING => 1,
+            _ => 0,
+        }
+    }
+}
+
 /// Standard level
 impl From<Level> for TitleLevel<'_> {
     #[inline]
@@ -421,7 +1215,10 @@ impl<'a> From<&'a str> for TitleLevel<'a> {
 ///
 /// This enables flexible label creation:
 /// - `.with_label_at((0..10, 0))` - tuple of (range, src_id)
-#[derive(Debug, Clone, Copy)]
+/// - `.with_label(0..=9)` or `.with_label(..10)` - other ranges in source 0
+/// - `.with_label(4)` - an empty span, drawn as a caret at position 4
+/// - `.with_label(..)` - the whole of source 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LabelSpan {
     start: usize,
     end: usize,
@@ -476,21 +1273,535 @@ impl<SrcId: Into<ffi::mu_Id>> From<(std::ops::Range<i32>, SrcId)> for LabelSpan
     }
 }
 
-/// Character set for rendering diagnostic output
-///
-/// Defines all the box-drawing and decorative characters used in rendering.
-/// Two predefined sets are available:
-/// - [`CharSet::ascii()`] - Uses ASCII characters (`-`, `|`, `+`, etc.)
-/// - [`CharSet::unicode()`] - Uses Unicode box-drawing characters (`─`, `│`, `┬`, etc.)
-///
-/// You can also create custom character sets by modifying individual fields.
+// RangeInclusive<usize>
+impl From<std::ops::RangeInclusive<usize>> for LabelSpan {
+    #[inline]
+    fn from(value: std::ops::RangeInclusive<usize>) -> Self {
+        LabelSpan {
+            start: *value.start(),
+            end: value.end().saturating_add(1),
+            src_id: 0.into(),
+        }
+    }
+}
+
+// RangeTo<usize>
+impl From<std::ops::RangeTo<usize>> for LabelSpan {
+    #[inline]
+    fn from(value: std::ops::RangeTo<usize>) -> Self {
+        LabelSpan {
+            start: 0,
+            end: value.end,
+            src_id: 0.into(),
+        }
+    }
+}
+
+// usize: an empty span at a position
+impl From<usize> for LabelSpan {
+    #[inline]
+    fn from(value: usize) -> Self {
+        LabelSpan {
+            start: value,
+            end: value,
+            src_id: 0.into(),
+        }
+    }
+}
+
+// RangeFull: the whole source, whatever its length
+impl From<std::ops::RangeFull> for LabelSpan {
+    #[inline]
+    fn from(_: std::ops::RangeFull) -> Self {
+        LabelSpan {
+            start: 0,
+            end: usize::MAX,
+            src_id: 0.into(),
+        }
+    }
+}
+
+/// A range of 1-based `(line, column)` coordinates, for
+/// [`Report::with_label_at_line_col`].
+pub type LineColRange = std::ops::Range<(usize, usize)>;
+
+/// The position shown in the header of a report, for
+/// [`Report::with_location`].
 ///
-/// # Example
-/// ```rust
-/// # use musubi::CharSet;
-/// let custom = CharSet {
-///     hbar: '=',
-///     vbar: '!',
+/// A `usize` converts to [`Location::Offset`], and a `(line, column)` pair
+/// to [`Location::LineCol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    /// A position in the source, in bytes or characters following
+    /// [`Config::with_index_type`].
+    Offset(usize),
+    /// 1-based line and column, counted as in [`LineColRange`].
+    LineCol(usize, usize),
+}
+
+impl From<usize> for Location {
+    #[inline]
+    fn from(pos: usize) -> Self {
+        Self::Offset(pos)
+    }
+}
+
+impl From<(usize, usize)> for Location {
+    #[inline]
+    fn from((line, col): (usize, usize)) -> Self {
+        Self::LineCol(line, col)
+    }
+}
+
+/// A span type of your own, usable wherever a [`LabelSpan`] is expected.
+///
+/// Parsers usually have a span type already; implement `Span` for it, and
+/// pass it to [`Report::with_label`] and the other methods taking spans
+/// as is, instead of converting it to a `(range, src_id)` tuple every time.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Config, Level, Report, Span};
+/// struct TokenSpan {
+///     file: usize,
+///     lo: u32,
+///     hi: u32,
+/// }
+///
+/// impl Span for TokenSpan {
+///     fn start(&self) -> usize {
+///         self.lo as usize
+///     }
+///     fn end(&self) -> usize {
+///         self.hi as usize
+///     }
+///     fn source_id(&self) -> usize {
+///         self.file
+///     }
+/// }
+///
+/// let token = TokenSpan { file: 0, lo: 4, hi: 5 };
+/// let output = Report::new()
+///     .with_config(Config::new().with_color_disabled())
+///     .with_title(Level::Error, "unknown name")
+///     .with_label(&token)
+///     .render_to_string("let x = y;")?;
+/// assert!(output.contains("<unknown>:1:5"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub trait Span {
+    /// Start position of the span.
+    fn start(&self) -> usize;
+
+    /// End position of the span (exclusive).
+    fn end(&self) -> usize;
+
+    /// ID of the source the span is in.
+    ///
+    /// Default: `0`, the first source
+    fn source_id(&self) -> usize {
+        0
+    }
+}
+
+impl<S: Span + ?Sized> Span for &S {
+    #[inline]
+    fn start(&self) -> usize {
+        (**self).start()
+    }
+
+    #[inline]
+    fn end(&self) -> usize {
+        (**self).end()
+    }
+
+    #[inline]
+    fn source_id(&self) -> usize {
+        (**self).source_id()
+    }
+}
+
+impl<S: Span> From<S> for LabelSpan {
+    #[inline]
+    fn from(span: S) -> Self {
+        LabelSpan::new(span.start()..span.end(), span.source_id())
+    }
+}
+
+impl LabelSpan {
+    /// Create a span over `range` in the source `src_id`.
+    #[inline]
+    pub fn new(range: std::ops::Range<usize>, src_id: impl Into<ffi::mu_Id>) -> Self {
+        (range, src_id).into()
+    }
+
+    /// Start position of the span.
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// End position of the span (exclusive).
+    #[inline]
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Source ID of the span.
+    #[inline]
+    pub fn src_id(&self) -> usize {
+        self.src_id.0 as usize
+    }
+
+    /// Length of the span.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Whether the span is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Move the span by `delta` positions, saturating at 0.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::LabelSpan;
+    /// let span = LabelSpan::from(4..8);
+    /// assert_eq!(span.offset(10), LabelSpan::from(14..18));
+    /// assert_eq!(span.offset(-6), LabelSpan::from(0..2));
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn offset(self, delta: isize) -> Self {
+        let shift = |pos: usize| pos.saturating_add_signed(delta);
+        LabelSpan {
+            start: shift(self.start),
+            end: shift(self.end),
+            ..self
+        }
+    }
+
+    /// The part covered by both spans.
+    ///
+    /// Returns [`None`] if the spans are in different sources or do not
+    /// overlap.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::LabelSpan;
+    /// let span = LabelSpan::from(4..8);
+    /// assert_eq!(span.intersect((6..10).into()), Some(LabelSpan::from(6..8)));
+    /// assert_eq!(span.intersect((8..10).into()), None);
+    /// assert_eq!(span.intersect(LabelSpan::new(4..8, 1)), None);
+    /// ```
+    #[inline]
+    pub fn intersect(self, other: LabelSpan) -> Option<Self> {
+        let start = self.start.max(other.start);
+        let end = self.end.min(other.end);
+        (self.src_id == other.src_id && start < end).then_some(LabelSpan { start, end, ..self })
+    }
+
+    /// Split the span at the absolute position `pos`.
+    ///
+    /// `pos` is clamped into the span, so one half may be empty.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::LabelSpan;
+    /// let (left, right) = LabelSpan::from(4..8).split_at(6);
+    /// assert_eq!((left, right), (LabelSpan::from(4..6), LabelSpan::from(6..8)));
+    /// ```
+    #[inline]
+    pub fn split_at(self, pos: usize) -> (Self, Self) {
+        let pos = pos.clamp(self.start, self.end.max(self.start));
+        (
+            LabelSpan { end: pos, ..self },
+            LabelSpan { start: pos, ..self },
+        )
+    }
+
+    /// Convert a byte span into a character span, using the lines of its
+    /// source in `cache`.
+    ///
+    /// Returns [`None`] if the source does not exist or fails to load.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, LabelSpan};
+    /// let cache = Cache::new().with_source("let π = 3.14;");
+    /// let span = LabelSpan::from(8..12);
+    /// assert_eq!(span.to_char_span(&cache), Some(LabelSpan::from(7..11)));
+    /// ```
+    pub fn to_char_span<K>(self, cache: &Cache<K>) -> Option<Self> {
+        let src = cache.source(self.src_id)?;
+        Some(LabelSpan {
+            start: byte_to_char(src, self.start),
+            end: byte_to_char(src, self.end),
+            ..self
+        })
+    }
+
+    /// Convert a character span into a byte span, using the lines of its
+    /// source in `cache`.
+    ///
+    /// Returns [`None`] if the source does not exist or fails to load.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, LabelSpan};
+    /// let cache = Cache::new().with_source("let π = 3.14;");
+    /// let span = LabelSpan::from(4..5);
+    /// assert_eq!(span.to_byte_span(&cache), Some(LabelSpan::from(4..6)));
+    /// ```
+    pub fn to_byte_span<K>(self, cache: &Cache<K>) -> Option<Self> {
+        let src = cache.source(self.src_id)?;
+        Some(LabelSpan {
+            start: char_to_byte(src, self.start),
+            end: char_to_byte(src, self.end),
+            ..self
+        })
+    }
+}
+
+/// Get the source `src_id` of `cache`, loading it if it was not loaded yet.
+fn load_source(cache: *mut ffi::mu_Cache, src_id: ffi::mu_Id) -> Option<*mut ffi::mu_Source> {
+    // SAFETY: cache is null or a valid cache
+    let src = unsafe { ffi::mu_getsource(cache, src_id.0) };
+    if src.is_null() {
+        return None;
+    }
+    // SAFETY: src is a valid source owned by the cache
+    unsafe { init_source(src) }.then_some(src)
+}
+
+/// Run the `init` of `src` unless it was run, returning whether it succeeded.
+///
+/// # Safety
+/// `src` must be a valid source, not used by another thread.
+unsafe fn init_source(src: *mut ffi::mu_Source) -> bool {
+    // SAFETY: guaranteed by the caller
+    unsafe {
+        if (*src).inited == 0 {
+            if let Some(init) = (*src).init
+                && init(src) != ffi::MU_OK
+            {
+                return false;
+            }
+            (*src).inited = 1;
+        }
+    }
+    true
+}
+
+/// The error of source `src_id` failing to load.
+fn init_error(cache: *mut ffi::mu_Cache, src_id: c_uint) -> io::Error {
+    let name = source_name(cache, src_id.into()).unwrap_or_default();
+    io::Error::other(format!("Source {src_id} ({name}) init error"))
+}
+
+/// Get the 1-based line number and column of `pos` in source `src_id`, as
+/// shown in report headers.
+///
+/// `pos` is interpreted according to `index_type`; the column counts
+/// characters.
+pub(crate) fn source_location(
+    cache: *mut ffi::mu_Cache,
+    src_id: ffi::mu_Id,
+    pos: usize,
+    index_type: IndexType,
+) -> Option<(usize, usize)> {
+    let src = load_source(cache, src_id)?;
+    // SAFETY: src was loaded above
+    let line_for = unsafe {
+        match index_type {
+            IndexType::Byte => (*src).line_for_bytes,
+            IndexType::Char => (*src).line_for_chars,
+        }
+    };
+    // SAFETY: src was loaded above, and line_for is one of its functions
+    let (line_no, line, _) = unsafe { source_line(src, line_for, pos)? };
+    let pos = match index_type {
+        IndexType::Byte => byte_to_char(src, pos),
+        IndexType::Char => pos,
+    };
+    // SAFETY: src was loaded above
+    let offset = unsafe { (*src).line_no_offset } as isize;
+    let line_no = (line_no as isize + offset + 1).max(1) as usize;
+    Some((line_no, pos.saturating_sub(line.offset) + 1))
+}
+
+type LineFor = Option<unsafe extern "C" fn(*mut ffi::mu_Source, usize, *mut ffi::mu_CL) -> c_uint>;
+
+/// Get the line number of the line containing `pos`, the line and its
+/// content.
+///
+/// # Safety
+///
+/// `src` must be an initialized source, and `line_for` one of its lookup
+/// functions.
+unsafe fn source_line<'s>(
+    src: *mut ffi::mu_Source,
+    line_for: LineFor,
+    pos: usize,
+) -> Option<(c_uint, ffi::mu_Line, &'s [u8])> {
+    let mut line = ptr::null();
+    // SAFETY: guaranteed by the caller
+    let line_no = unsafe { line_for?(src, pos, &mut line) };
+    // SAFETY: line was set by line_for and lives in the source
+    let line = *unsafe { line.as_ref()? };
+    // SAFETY: src is initialized, so get_line is set
+    let data = unsafe { (*src).get_line?(src, line_no) };
+    Some((line_no, line, data.into()))
+}
+
+fn byte_to_char(src: *mut ffi::mu_Source, pos: usize) -> usize {
+    // SAFETY: src comes from Cache::source, which initialized it
+    let Some((_, line, data)) = (unsafe { source_line(src, (*src).line_for_bytes, pos) }) else {
+        return pos;
+    };
+    let rel = pos.saturating_sub(line.byte_offset);
+    if rel > data.len() {
+        return line.offset + line.len as usize + (rel - data.len());
+    }
+    line.offset + char_starts(data).take_while(|&i| i < rel).count()
+}
+
+/// The length in bytes of `src`, its last newline included.
+fn source_len(src: *mut ffi::mu_Source) -> usize {
+    // SAFETY: src comes from Cache::source, which initialized it
+    unsafe {
+        let count = (*src).line_count.map_or(0, |f| f(src));
+        let last = (*src).get_line_info.filter(|_| count > 0);
+        last.map_or(0, |f| {
+            let line = *f(src, count - 1);
+            line.byte_offset + line.byte_len as usize + line.newline as usize
+        })
+    }
+}
+
+fn char_to_byte(src: *mut ffi::mu_Source, pos: usize) -> usize {
+    // SAFETY: src comes from Cache::source, which initialized it
+    let Some((_, line, data)) = (unsafe { source_line(src, (*src).line_for_chars, pos) }) else {
+        return pos;
+    };
+    let rel = pos.saturating_sub(line.offset);
+    match char_starts(data).nth(rel) {
+        Some(i) => line.byte_offset + i,
+        None => line.byte_offset + data.len() + rel.saturating_sub(line.len as usize),
+    }
+}
+
+/// The position of 1-based `line` and character column `col` in `src`, as
+/// `index_type` counts positions. Lines are numbered as shown in the
+/// margin; a line outside the source gives an error with the nearest end of
+/// the source.
+fn line_col_to_pos(
+    src: *mut ffi::mu_Source,
+    (line, col): (usize, usize),
+    index_type: IndexType,
+) -> Result<usize, usize> {
+    // SAFETY: src was loaded by the caller, so its functions are set
+    unsafe {
+        let offset = (*src).line_no_offset as isize;
+        let count = (*src).line_count.map_or(0, |f| f(src));
+        let line_no = line as isize - offset - 1;
+        let Some(info) = (*src).get_line_info.filter(|_| count > 0) else {
+            return Err(0);
+        };
+        if line_no < 0 {
+            return Err(0);
+        }
+        let line_no = line_no as usize;
+        if line_no >= count as usize {
+            let last = *info(src, count - 1);
+            return Err(match index_type {
+                IndexType::Byte => last.byte_offset + last.byte_len as usize,
+                IndexType::Char => last.offset + last.len as usize,
+            });
+        }
+        let rel = col.saturating_sub(1);
+        let info = *info(src, line_no as c_uint);
+        Ok(match index_type {
+            IndexType::Char => info.offset + rel,
+            IndexType::Byte => {
+                let data: &[u8] = (*src)
+                    .get_line
+                    .map_or(Default::default(), |f| f(src, line_no as c_uint))
+                    .into();
+                match char_starts(data).nth(rel) {
+                    Some(i) => info.byte_offset + i,
+                    None => info.byte_offset + data.len() + rel.saturating_sub(info.len as usize),
+                }
+            }
+        })
+    }
+}
+
+/// The byte offsets of the characters of `data`, as the renderer counts
+/// them: each byte of an invalid UTF-8 sequence is a character of its own.
+fn char_starts(data: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    let char_len = |p: usize| {
+        let n = match data[p] {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return 1,
+        };
+        match data.get(p..p + n) {
+            Some(c) if std::str::from_utf8(c).is_ok() => n,
+            _ => 1,
+        }
+    };
+    let first = Some(0).filter(|_| !data.is_empty());
+    std::iter::successors(first, move |&p| {
+        Some(p + char_len(p)).filter(|&p| p < data.len())
+    })
+}
+
+/// `data` as displayed by the renderer, with U+FFFD in place of each
+/// invalid byte.
+fn display_text(data: &[u8]) -> Cow<'_, str> {
+    if let Ok(text) = std::str::from_utf8(data) {
+        return Cow::Borrowed(text);
+    }
+    let mut text = String::with_capacity(data.len());
+    let mut starts = char_starts(data).peekable();
+    while let Some(start) = starts.next() {
+        let end = starts.peek().copied().unwrap_or(data.len());
+        text.push_str(std::str::from_utf8(&data[start..end]).unwrap_or("\u{FFFD}"));
+    }
+    Cow::Owned(text)
+}
+
+/// Get the name of source `src_id` in `cache`.
+pub(crate) fn source_name(cache: *mut ffi::mu_Cache, src_id: ffi::mu_Id) -> Option<String> {
+    // SAFETY: cache is null or a valid cache
+    let src = unsafe { ffi::mu_getsource(cache, src_id.0) };
+    // SAFETY: src is null or a valid source, whose name is set when added
+    let name = unsafe { src.as_ref()? }.name;
+    let name: &[u8] = name.into();
+    Some(String::from_utf8_lossy(name).into_owned())
+}
+
+/// Character set for rendering diagnostic output
+///
+/// Defines all the box-drawing and decorative characters used in rendering.
+/// Two predefined sets are available:
+/// - [`CharSet::ascii()`] - Uses ASCII characters (`-`, `|`, `+`, etc.)
+/// - [`CharSet::unicode()`] - Uses Unicode box-drawing characters (`─`, `│`, `┬`, etc.)
+///
+/// You can also create custom character sets by modifying individual fields.
+///
+/// # Example
+/// ```rust
+/// # use musubi::CharSet;
+/// let custom = CharSet {
+///     hbar: '=',
+///     vbar: '!',
 ///     ..CharSet::ascii()
 /// };
 /// ```
@@ -616,6 +1927,78 @@ impl CharSet {
         // SAFETY: mu_unicode() returns a valid static charset pointer
         unsafe { ffi::mu_unicode() }.into()
     }
+
+    /// Whether every character of the set is ASCII.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::CharSet;
+    /// assert!(CharSet::ascii().is_ascii());
+    /// assert!(!CharSet::unicode().is_ascii());
+    /// ```
+    pub fn is_ascii(&self) -> bool {
+        self.chars().iter().all(char::is_ascii)
+    }
+
+    /// Guess from the environment whether the terminal can display every
+    /// character of the set.
+    ///
+    /// ASCII sets are always displayable. Otherwise, a `dumb` terminal
+    /// (by `TERM`) can't display them, and on Unix the locale (by `LC_ALL`,
+    /// `LC_CTYPE` or `LANG`, whichever is set first) must be UTF-8.
+    ///
+    /// Pass it to [`Config::with_char_set_probe`] to fall back to ASCII on
+    /// terminals that can't display the set.
+    pub fn is_displayable(&self) -> bool {
+        if self.is_ascii() {
+            return true;
+        }
+        if std::env::var_os("TERM").is_some_and(|term| term == "dumb") {
+            return false;
+        }
+        if cfg!(windows) {
+            return true;
+        }
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|key| std::env::var(key).ok().filter(|v| !v.is_empty()));
+        locale.is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+    }
+
+    /// The characters in the order of [`ffi::mu_Charset`].
+    fn chars(&self) -> [char; 26] {
+        [
+            self.space,
+            self.newline,
+            self.lbox,
+            self.rbox,
+            self.colon,
+            self.hbar,
+            self.vbar,
+            self.xbar,
+            self.vbar_gap,
+            self.line_margin,
+            self.uarrow,
+            self.rarrow,
+            self.ltop,
+            self.mtop,
+            self.rtop,
+            self.lbot,
+            self.mbot,
+            self.rbot,
+            self.lcross,
+            self.rcross,
+            self.lunderbar,
+            self.munderbar,
+            self.runderbar,
+            self.sunderbar,
+            self.underline,
+            self.ellipsis,
+        ]
+    }
 }
 
 /// Automatic color generator for creating visually distinct label colors.
@@ -641,6 +2024,39 @@ impl CharSet {
 /// ```
 pub struct ColorGenerator {
     base: ffi::mu_ColorGen,
+    mode: ColorMode,
+    /// Whether [`ColorMode::TrueColor`] falls back to 256 colors
+    downgrade: bool,
+    /// Colors made by [`next_rgb`](ColorGenerator::next_rgb)
+    rgb_count: u32,
+}
+
+/// Kind of color codes made by [`ColorGenerator::next_color`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// 256-color codes, `ESC[38;5;Nm` (default)
+    #[default]
+    Ansi256,
+    /// 24-bit codes, `ESC[38;2;R;G;Bm`, from
+    /// [`next_rgb`](ColorGenerator::next_rgb)
+    TrueColor,
+}
+
+/// Whether the terminal takes 24-bit colors, from the `COLORTERM` variable
+/// read by `var`.
+fn truecolor_supported(var: impl Fn(&str) -> Option<std::ffi::OsString>) -> bool {
+    var("COLORTERM").is_some_and(|v| v == "truecolor" || v == "24bit")
+}
+
+/// The color of the 6x6x6 cube of the 256 colors closest to `rgb`.
+fn rgb_to_ansi256((r, g, b): (u8, u8, u8)) -> u8 {
+    // levels of the cube: 0, 95, 135, 175, 215, 255
+    let level = |c: u8| match c {
+        0..48 => 0,
+        48..115 => 1,
+        c => (c - 35) / 40,
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
 }
 
 /// Trait for types that can be used as raw color codes.
@@ -664,19 +2080,148 @@ pub trait IntoColor {
 ///
 /// GenColor is more efficient than trait-object based colors because it
 /// avoids dynamic dispatch and stores the color code directly.
+#[derive(Clone, PartialEq, Eq)]
 pub struct GenColor(ffi::mu_ColorCode);
 
+impl GenColor {
+    /// Store the escape sequence `code`, cut to the size of a color code.
+    fn from_code(code: &str) -> Self {
+        let mut rc = GenColor([0; ffi::sizes::COLOR_CODE]);
+        let len = code.len().min(ffi::sizes::COLOR_CODE - 1);
+        rc.0[0] = len as c_char;
+        for (dst, &src) in rc.0[1..].iter_mut().zip(&code.as_bytes()[..len]) {
+            *dst = src as c_char;
+        }
+        rc
+    }
+
+    /// The escape sequence of the color.
+    fn code(&self) -> String {
+        let len = self.0[0] as u8 as usize;
+        let code: Vec<u8> = self.0[1..=len].iter().map(|&c| c as u8).collect();
+        String::from_utf8_lossy(&code).into_owned()
+    }
+}
+
+impl Debug for GenColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("GenColor").field(&self.code()).finish()
+    }
+}
+
+impl From<&GenColor> for GenColor {
+    #[inline]
+    fn from(color: &GenColor) -> Self {
+        color.clone()
+    }
+}
+
+impl<C: Color> From<&C> for GenColor {
+    /// The code `color` writes for [`ColorKind::Label`].
+    fn from(color: &C) -> Self {
+        let mut code = Vec::new();
+        // a failed write keeps what was written, as the label color does
+        let _ = color.color(&mut code, ColorKind::Label);
+        GenColor::from_code(&String::from_utf8_lossy(&code))
+    }
+}
+
+#[cfg(feature = "anstyle")]
+impl From<anstyle::Color> for GenColor {
+    /// The foreground color code of `color`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, GenColor, Level, Report};
+    /// let color = GenColor::from(anstyle::Color::Ansi(anstyle::AnsiColor::Magenta));
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_char_set_ascii())
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(4..5)
+    ///     .with_color(&color);
+    /// assert!(report.render_to_string("let x;")?.contains("\x1b[35mx"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn from(color: anstyle::Color) -> Self {
+        GenColor::from_code(&color.render_fg().to_string())
+    }
+}
+
+#[cfg(feature = "anstyle")]
+impl Color for anstyle::Style {
+    /// Write this style for every kind but [`ColorKind::Reset`], as
+    /// [`Style`] does.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// use anstyle::{AnsiColor, Style};
+    /// let style = Style::new().fg_color(Some(AnsiColor::Cyan.into())).bold();
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_char_set_ascii())
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(4..5)
+    ///     .with_color(&style);
+    /// assert!(report.render_to_string("let x;")?.contains("\x1b[1m\x1b[36mx"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn color(&self, w: &mut dyn Write, kind: ColorKind) -> std::io::Result<()> {
+        match kind {
+            ColorKind::Reset => write!(w, "{}", self.render_reset()),
+            _ => write!(w, "{}", self.render()),
+        }
+    }
+}
+
+#[cfg(feature = "owo-colors")]
+impl Color for owo_colors::Style {
+    /// Write this style for every kind but [`ColorKind::Reset`], as
+    /// [`Style`] does.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// use owo_colors::Style;
+    /// let style = Style::new().cyan().bold();
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_char_set_ascii())
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(4..5)
+    ///     .with_color(&style);
+    /// assert!(report.render_to_string("let x;")?.contains("\x1b[36;1mx"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn color(&self, w: &mut dyn Write, kind: ColorKind) -> std::io::Result<()> {
+        match kind {
+            ColorKind::Reset => write!(w, "{}", self.suffix_formatter()),
+            _ => write!(w, "{}", self.prefix_formatter()),
+        }
+    }
+}
+
 impl IntoColor for &GenColor {
     #[inline]
     fn into_color(self, report: &mut Report) {
-        // SAFETY: mu_fromcolorcode is a valid C callback that reads from the color code array.
+        /// `mu_fromcolorcode`, sending the code to a running
+        /// `render_to_write_color` instead if there is one.
+        #[cfg(feature = "termcolor")]
+        extern "C" fn color_fn(ud: *mut c_void, kind: ffi::mu_ColorKind) -> ffi::mu_Chunk {
+            // SAFETY: ud is the color code of a GenColor
+            let chunk = unsafe { ffi::mu_fromcolorcode(ud, kind) };
+            // SAFETY: a chunk starts with the length of the bytes after it
+            let code =
+                unsafe { std::slice::from_raw_parts(chunk.add(1).cast(), *chunk as u8 as usize) };
+            if sink_label_color(code) {
+                return c"".as_ptr();
+            }
+            chunk
+        }
+        #[cfg(not(feature = "termcolor"))]
+        let color_fn = ffi::mu_fromcolorcode;
+        // SAFETY: color_fn is a valid C callback that reads from the color code array.
         // The pointer to self.0 is valid for the duration of the mu_color call.
         unsafe {
-            ffi::mu_color(
-                report.ptr,
-                Some(ffi::mu_fromcolorcode),
-                self.0.as_ptr() as *mut c_void,
-            );
+            ffi::mu_color(report.ptr, Some(color_fn), self.0.as_ptr() as *mut c_void);
         }
     }
 }
@@ -704,18 +2249,90 @@ impl ColorGenerator {
         Self {
             // SAFETY: obj has been fully initialized by mu_initcolorgen above
             base: unsafe { obj.assume_init() },
+            mode: ColorMode::Ansi256,
+            downgrade: false,
+            rgb_count: 0,
         }
     }
 
-    /// Generate the next color in the sequence.
-    ///
-    /// Each call returns a different color code that is visually distinct from
-    /// previous colors. The sequence is deterministic based on the initial state.
+    /// Set the kind of color codes made by
+    /// [`next_color`](ColorGenerator::next_color).
     ///
-    /// # Examples
+    /// [`ColorMode::TrueColor`] falls back to the 256 colors closest to the
+    /// colors of [`next_rgb`](ColorGenerator::next_rgb) when the `COLORTERM`
+    /// environment variable doesn't announce 24-bit colors, as `truecolor`
+    /// or `24bit`. Default: [`ColorMode::Ansi256`]
     ///
+    /// # Example
     /// ```rust
-    /// use musubi::ColorGenerator;
+    /// # use musubi::{ColorGenerator, ColorMode};
+    /// let mut cg = ColorGenerator::new().with_color_mode(ColorMode::TrueColor);
+    /// let color = cg.next_color();
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_color_mode(self, mode: ColorMode) -> Self {
+        self.with_color_mode_when(mode, truecolor_supported(|key| std::env::var_os(key)))
+    }
+
+    fn with_color_mode_when(mut self, mode: ColorMode, truecolor: bool) -> Self {
+        (self.mode, self.downgrade) = (mode, !truecolor);
+        self
+    }
+
+    /// The kind of color codes made.
+    #[inline]
+    pub fn color_mode(&self) -> ColorMode {
+        self.mode
+    }
+
+    /// Generate the next 24-bit color, as red, green and blue.
+    ///
+    /// The hues turn by the golden angle in the Oklab color space, where
+    /// equal steps look equally far apart, so consecutive colors stay
+    /// distinct, at the same lightness, set by the brightness of the
+    /// generator. This sequence is apart from the one of the 256 colors.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::ColorGenerator;
+    /// let mut cg = ColorGenerator::new();
+    /// assert_ne!(cg.next_rgb(), cg.next_rgb());
+    /// ```
+    pub fn next_rgb(&mut self) -> (u8, u8, u8) {
+        let hue = (self.rgb_count as f32 * 137.507_77).to_radians();
+        self.rgb_count += 1;
+        let light = 0.6 + 0.3 * self.base.min_brightness.clamp(0.0, 1.0);
+        let (a, b) = (0.12 * hue.cos(), 0.12 * hue.sin());
+        // Oklab to linear sRGB
+        let l = (light + 0.396_337_8 * a + 0.215_803_76 * b).powi(3);
+        let m = (light - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+        let s = (light - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+        let linear = [
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+            -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+        ];
+        let [r, g, b] = linear.map(|c| {
+            let c = c.clamp(0.0, 1.0);
+            let c = match c {
+                ..=0.003_130_8 => c * 12.92,
+                c => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+            };
+            (c * 255.0).round() as u8
+        });
+        (r, g, b)
+    }
+
+    /// Generate the next color in the sequence.
+    ///
+    /// Each call returns a different color code that is visually distinct from
+    /// previous colors. The sequence is deterministic based on the initial state.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use musubi::ColorGenerator;
     ///
     /// let mut cg = ColorGenerator::new();
     /// let color1 = cg.next_color();
@@ -725,6 +2342,13 @@ impl ColorGenerator {
     #[inline]
     pub fn next_color(&mut self) -> GenColor {
         let mut rc = GenColor([0; ffi::sizes::COLOR_CODE]);
+        if self.mode == ColorMode::TrueColor {
+            let (r, g, b) = self.next_rgb();
+            return GenColor::from_code(&match self.downgrade {
+                true => format!("\x1b[38;5;{}m", rgb_to_ansi256((r, g, b))),
+                false => format!("\x1b[38;2;{r};{g};{b}m"),
+            });
+        }
         // SAFETY: &mut self ensures exclusive access to base.
         // mu_gencolor always succeeds and fills the color code array.
         unsafe { ffi::mu_gencolor(&mut self.base, &mut rc.0) };
@@ -792,17 +2416,85 @@ struct ColorUd {
     color_buf: *mut [u8; ffi::sizes::COLOR_CODE],
 }
 
+/// Arena of [`ColorUd`]s for per-label colors.
+///
+/// Entries are stored in chunks of growing size that are never reallocated,
+/// so pointers to them stay valid while the arena lives and only a few
+/// allocations are needed for many colored labels.
+#[derive(Default)]
+struct ColorArena {
+    chunks: Vec<Vec<ColorUd>>,
+}
+
+impl ColorArena {
+    /// Capacity of the first chunk; each next chunk doubles it.
+    const FIRST_CHUNK: usize = 8;
+
+    /// Store `ud` in the arena and return a stable pointer to it.
+    fn alloc(&mut self, ud: ColorUd) -> *mut ColorUd {
+        let full = self.chunks.last().is_none_or(|c| c.len() == c.capacity());
+        if full {
+            let cap = self
+                .chunks
+                .last()
+                .map_or(Self::FIRST_CHUNK, |c| c.capacity() * 2);
+            self.chunks.push(Vec::with_capacity(cap));
+        }
+        let chunk = self.chunks.last_mut().unwrap();
+        // never exceeds the capacity, so the chunk is not reallocated
+        chunk.push(ud);
+        chunk.last_mut().unwrap()
+    }
+
+    /// Iterate over all entries.
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut ColorUd> {
+        self.chunks.iter_mut().flatten()
+    }
+}
+
+/// Arena of owned messages.
+///
+/// Messages are appended to string chunks of growing size that are never
+/// reallocated, so slices into them stay valid while the arena lives, and
+/// many messages share a few contiguous allocations.
+#[derive(Default)]
+struct StrArena {
+    chunks: Vec<String>,
+}
+
+impl StrArena {
+    /// Capacity of the first chunk; each next chunk doubles it.
+    const FIRST_CHUNK: usize = 256;
+
+    /// Copy `s` into the arena and return a stable slice of it.
+    fn alloc(&mut self, s: &str) -> ffi::mu_Slice {
+        let fits = (self.chunks.last()).is_some_and(|c| c.capacity() - c.len() >= s.len());
+        if !fits {
+            let cap = self
+                .chunks
+                .last()
+                .map_or(Self::FIRST_CHUNK, |c| c.capacity() * 2);
+            self.chunks.push(String::with_capacity(cap.max(s.len())));
+        }
+        let chunk = self.chunks.last_mut().unwrap();
+        let start = chunk.len();
+        // fits in the capacity, so the chunk is not reallocated
+        chunk.push_str(s);
+        (&chunk[start..]).into()
+    }
+}
+
 impl<C: Color> IntoColor for &C {
     fn into_color(self, report: &mut Report) {
-        report.color_uds.push(Box::new(ColorUd {
+        let ud = report.color_uds.alloc(ColorUd {
             color_obj: self as *const _ as *const c_void,
             color_buf: &mut report.color_buf,
-        }));
+        });
         extern "C" fn color_fn<C: Color>(
             ud: *mut c_void,
             kind: ffi::mu_ColorKind,
         ) -> ffi::mu_Chunk {
-            // SAFETY: ud is a valid ColorUd pointer from color_uds vector
+            // SAFETY: ud is a valid ColorUd pointer from the color_uds arena
             let ud = unsafe { &mut *(ud as *mut ColorUd) };
             // SAFETY: color_obj points to a valid C reference with lifetime 'a
             let color = unsafe { &*(ud.color_obj as *const C) };
@@ -812,6 +2504,10 @@ impl<C: Color> IntoColor for &C {
             match color.color(&mut remain, ColorKind::from_ffi(kind)) {
                 Ok(_) => {
                     let used = (ffi::sizes::COLOR_CODE - remain.len() - 1) as u8;
+                    #[cfg(feature = "termcolor")]
+                    if sink_label_color(&buf[1..=used as usize]) {
+                        return c"".as_ptr();
+                    }
                     buf[0] = used;
                     buf.as_ptr() as *const c_char
                 }
@@ -819,14 +2515,57 @@ impl<C: Color> IntoColor for &C {
             }
         }
         // SAFETY: self.ptr is valid, color_fn has correct signature, ud points to valid ColorUd
-        unsafe {
-            ffi::mu_color(
-                report.ptr,
-                Some(color_fn::<C>),
-                &**report.color_uds.last().unwrap() as *const ColorUd as *mut c_void,
-            )
-        };
+        unsafe { ffi::mu_color(report.ptr, Some(color_fn::<C>), ud as *mut c_void) };
+    }
+}
+
+/// Whether colors are allowed by the variables read by `var`, like
+/// `NO_COLOR`, when the output is a terminal or not.
+fn color_allowed(var: impl Fn(&str) -> Option<std::ffi::OsString>, is_terminal: bool) -> bool {
+    let set = |key| var(key).is_some_and(|v| !v.is_empty());
+    if set("NO_COLOR") {
+        return false;
+    }
+    if set("CLICOLOR_FORCE") && var("CLICOLOR_FORCE").is_some_and(|v| v != "0") {
+        return true;
     }
+    if var("CLICOLOR").is_some_and(|v| v == "0") || var("TERM").is_some_and(|v| v == "dumb") {
+        return false;
+    }
+    is_terminal
+}
+
+/// Turn on the processing of escape sequences by the Windows consoles of
+/// stdout and stderr, returning whether both accept them.
+///
+/// Streams that are not consoles, like pipes and files, are left as they
+/// are and accept them.
+#[cfg(windows)]
+fn enable_virtual_terminal() -> bool {
+    use std::os::windows::io::{AsRawHandle, RawHandle};
+
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetConsoleMode(console: RawHandle, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: RawHandle, mode: u32) -> i32;
+    }
+    let enable = |handle: RawHandle| {
+        let mut mode = 0;
+        // SAFETY: handle is a standard handle of this process, and
+        // GetConsoleMode fails for handles that are not consoles
+        unsafe {
+            GetConsoleMode(handle, &mut mode) == 0
+                || mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+        }
+    };
+    enable(io::stdout().as_raw_handle()) & enable(io::stderr().as_raw_handle())
+}
+
+#[cfg(not(windows))]
+fn enable_virtual_terminal() -> bool {
+    true
 }
 
 /// Configuration for the diagnostic renderer
@@ -834,6 +2573,13 @@ pub struct Config<'a> {
     inner: ffi::mu_Config,
     color_ud: Option<Box<ColorUd>>,
     char_set: Option<&'a CharSet>,
+    /// C representation of `char_set`, referenced by `inner.char_set`
+    char_set_buf: Option<Box<CharSetBuf>>,
+    char_set_probe: Option<fn(&CharSet) -> bool>,
+    /// Columns each level of related reports is indented by
+    related_indent: usize,
+    /// Whether connectors link related reports to their parent
+    related_connectors: bool,
 }
 
 impl Debug for Config<'_> {
@@ -849,8 +2595,27 @@ impl Debug for Config<'_> {
             .field("tab_width", &self.inner.tab_width)
             .field("limit_width", &self.inner.limit_width)
             .field("ambi_width", &self.inner.ambiwidth)
+            .field("markup", &self.inner.markup)
+            .field("inline_code", &self.inner.inline_code)
+            .field("normalize", &self.inner.normalize)
+            .field("bidi_isolate", &self.inner.bidi_isolate)
+            .field("auto_location", &self.inner.auto_location)
+            .field("dim_unlabeled", &self.inner.dim_unlabeled)
+            .field("caret_only", &self.inner.caret_only)
+            .field("one_based_columns", &self.inner.one_based_columns)
+            .field("auto_priority", &self.inner.auto_priority)
+            .field("max_depth", &self.inner.max_depth)
+            .field("gutter_bar", &self.inner.gutter_bar)
+            .field("collapse_notes", &self.inner.collapse_notes)
+            .field("line_no_separator", &self.line_no_separator())
+            .field("tab_stops", &self.tab_stops())
             .field("label_attach", &self.inner.label_attach)
             .field("index_type", &self.inner.index_type)
+            .field("overlap", &self.inner.overlap)
+            .field("footer", &self.inner.footer)
+            .field("source_order", &self.inner.source_order)
+            .field("related_indent", &self.related_indent)
+            .field("related_connectors", &self.related_connectors)
             .finish()
     }
 }
@@ -860,10 +2625,18 @@ impl Clone for Config<'_> {
     fn clone(&self) -> Self {
         // SAFETY: mu_Config is a C struct with no Drop semantics, safe to copy
         let new: ffi::mu_Config = unsafe { std::mem::transmute_copy(&self.inner) };
-        Self {
+        let config = Self {
             inner: new,
             color_ud: None,
-            char_set: self.char_set,
+            char_set: None,
+            char_set_buf: None,
+            char_set_probe: self.char_set_probe,
+            related_indent: self.related_indent,
+            related_connectors: self.related_connectors,
+        };
+        match self.char_set {
+            Some(char_set) => config.with_char_set(char_set),
+            None => config,
         }
     }
 }
@@ -881,6 +2654,10 @@ impl Default for Config<'_> {
             inner: unsafe { obj.assume_init() },
             color_ud: None,
             char_set: None,
+            char_set_buf: None,
+            char_set_probe: None,
+            related_indent: 4,
+            related_connectors: false,
         }
     }
 }
@@ -1027,6 +2804,42 @@ impl<'a> Config<'a> {
         self
     }
 
+    /// Set custom tab stop columns.
+    ///
+    /// A tab character (`\t`) advances to the first stop after the current
+    /// display column. Past the last stop, tabs fall back to uniform
+    /// [`tab_width`](Config::with_tab_width) expansion. An empty slice clears
+    /// the stops.
+    ///
+    /// Stops must be positive and ascending: a stop not above the one
+    /// before it (or `0`, for the first) is skipped. At most 16 stops are
+    /// kept; further stops are ignored.
+    ///
+    /// Default: no tab stops
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Config;
+    /// let config = Config::new().with_tab_stops(&[8, 16, 24]);
+    /// assert_eq!(config.tab_stops(), &[8, 16, 24]);
+    ///
+    /// let config = Config::new().with_tab_stops(&[0, 8, 4, 16]);
+    /// assert_eq!(config.tab_stops(), &[8, 16]);
+    /// ```
+    pub fn with_tab_stops(mut self, stops: &[i32]) -> Self {
+        self.inner.tab_stops = [0; ffi::sizes::TAB_STOPS];
+        let mut last = 0;
+        let stops = stops.iter().filter(|&&stop| {
+            let ascending = stop > last;
+            last = last.max(stop);
+            ascending
+        });
+        for (slot, &stop) in self.inner.tab_stops.iter_mut().zip(stops) {
+            *slot = stop;
+        }
+        self
+    }
+
     /// Set the width limit for line wrapping.
     ///
     /// Lines longer than this width will be truncated with an ellipsis.
@@ -1065,1400 +2878,7334 @@ impl<'a> Config<'a> {
         self
     }
 
-    /// Set where labels attach to spans.
+    /// Enable or disable inline markup in messages.
     ///
-    /// Controls the default attachment point for all labels.
-    /// Individual labels can override this with [`Report::with_order`].
+    /// When enabled, text between a pair of `**` markers in the title, label
+    /// messages, help and notes is drawn with [`ColorKind::Emphasis`] (bold
+    /// with the default colors). The markers themselves are not printed and
+    /// do not count towards the message width. An unclosed marker emphasizes
+    /// the rest of the line. Without colors, only the markers are removed.
     ///
-    /// Default: [`LabelAttach::Middle`]
+    /// Default: [`false`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_markup(true).with_color_disabled())
+    ///     .with_title(Level::Error, "mismatched types")
+    ///     .with_label(8..10)
+    ///     .with_message("expected **`i32`**, found **`&str`**")
+    ///     .render_to_string("let x = \"\";")?;
+    /// assert!(output.contains("expected `i32`, found `&str`"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    pub fn with_label_attach(mut self, attach: LabelAttach) -> Self {
-        self.inner.label_attach = attach.into();
+    pub fn with_markup(mut self, enabled: bool) -> Self {
+        self.inner.markup = enabled as c_int;
         self
     }
 
-    /// Set the index type (character or byte).
+    /// Enable or disable styling of inline code.
     ///
-    /// Determines how span ranges are interpreted.
-    /// See [`IndexType`] for details.
+    /// When enabled, `` `code` `` segments in the title, help and notes are
+    /// drawn with [`ColorKind::InlineCode`], like rustc does. The backticks
+    /// are kept in the output, and `**` inside them is not treated as
+    /// [markup](Config::with_markup). Label messages are not affected.
     ///
-    /// Default: [`IndexType::Char`]
+    /// Default: [`false`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_inline_code(true))
+    ///     .with_title(Level::Error, "cannot find `foo`")
+    ///     .render_to_string("")?;
+    /// assert!(output.contains("cannot find \x1b[38;5;180m`foo`\x1b[0m"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    pub fn with_index_type(mut self, index_type: IndexType) -> Self {
-        self.inner.index_type = index_type.into();
+    pub fn with_inline_code(mut self, enabled: bool) -> Self {
+        self.inner.inline_code = enabled as c_int;
         self
     }
 
-    /// Set ASCII character set for rendering.
+    /// Enable or disable column normalization.
     ///
-    /// Uses ASCII characters (`-`, `|`, `+`, etc.) for box drawing.
-    /// This is compatible with all terminals and file formats.
+    /// When enabled, the column shown in the location of a report is counted
+    /// as on NFC-normalized text: zero-width combining characters don't take
+    /// a column of their own, so a decomposed `e\u{301}` counts as one column
+    /// like the composed `é`. Display widths never count combining
+    /// characters, so underlines are already aligned either way.
+    ///
+    /// Default: [`false`]
     ///
     /// # Example
-    /// ```text
-    /// Error: message
-    ///    ,-[ file.rs:1:1 ]
-    ///    |
-    ///  1 | code here
-    ///    | ^^|^
-    ///    |   `--- label
-    /// ---'
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_normalize(true))
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(6..9)
+    ///     .render_to_string("cafe\u{301} = x")?;
+    /// assert!(output.contains("<unknown>:1:6"));
+    /// # Ok::<(), std::io::Error>(())
     /// ```
     #[inline]
-    pub fn with_char_set_ascii(mut self) -> Self {
-        // SAFETY: mu_ascii() returns a valid static charset pointer
-        self.inner.char_set = unsafe { ffi::mu_ascii() };
-        self.char_set = None;
+    pub fn with_normalize(mut self, enabled: bool) -> Self {
+        self.inner.normalize = enabled as c_int;
         self
     }
 
-    /// Set Unicode character set for rendering.
+    /// Enable or disable bidi isolation of source lines.
     ///
-    /// Uses Unicode box-drawing characters (─, │, ┬, etc.) for prettier output.
-    /// Requires a terminal that supports Unicode.
+    /// When enabled, source lines containing right-to-left text (Hebrew,
+    /// Arabic, ...) or explicit directional controls are wrapped in the
+    /// Unicode isolate characters FSI (`U+2068`) and PDI (`U+2069`), so the
+    /// terminal cannot reorder them with the gutter around them. The
+    /// isolates take no columns and don't affect the underlines.
+    ///
+    /// Default: [`false`]
     ///
     /// # Example
-    /// ```text
-    /// Error: message
-    ///    ╭─[ file.rs:1:1 ]
-    ///    │
-    ///  1 │ code here
-    ///    │ ──┬─
-    ///    │   ╰─── label
-    /// ───╯
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled().with_bidi_isolate(true))
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(4..8)
+    ///     .render_to_string("let שלום = 1;")?;
+    /// assert!(output.contains("\u{2068}let שלום = 1;\u{2069}\n"));
+    /// # Ok::<(), std::io::Error>(())
     /// ```
     #[inline]
-    pub fn with_char_set_unicode(mut self) -> Self {
-        // SAFETY: mu_unicode() returns a valid static charset pointer
-        self.inner.char_set = unsafe { ffi::mu_unicode() };
-        self.char_set = None;
+    pub fn with_bidi_isolate(mut self, enabled: bool) -> Self {
+        self.inner.bidi_isolate = enabled as c_int;
         self
     }
 
-    /// Set a custom character set for rendering.
+    /// Enable or disable the location in source headers.
     ///
-    /// Allows fine-grained control over all box-drawing characters.
-    /// The character set must outlive the config.
+    /// When enabled, the header of each source shows the `line:col` of the
+    /// [primary label](Report::with_primary_label) of that source. Without
+    /// one, the label with the highest [priority](Report::with_priority) is
+    /// used, and the first of those in the source on ties. When disabled,
+    /// headers only show the source name.
+    ///
+    /// Default: [`true`]
     ///
     /// # Example
     /// ```rust
-    /// # use musubi::{Config, CharSet};
-    /// let custom = CharSet {
-    ///     hbar: '=',
-    ///     vbar: '!',
-    ///     ..CharSet::ascii()
-    /// };
-    /// let config = Config::new().with_char_set(&custom);
+    /// # use musubi::{Config, Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "mismatched types")
+    ///     .with_label((0..3, 0))
+    ///     .with_label((8..10, 0))
+    ///     .with_priority(1);
+    /// let output = report.render_to_string(("let x = 42;", "main.rs"))?;
+    /// assert!(output.contains("[ main.rs:1:9 ]"));
+    ///
+    /// let config = Config::new().with_color_disabled().with_auto_location(false);
+    /// let output = report
+    ///     .with_config(config)
+    ///     .render_to_string(("let x = 42;", "main.rs"))?;
+    /// assert!(output.contains("[ main.rs ]"));
+    /// # Ok::<(), std::io::Error>(())
     /// ```
     #[inline]
-    pub fn with_char_set(mut self, char_set: &'a CharSet) -> Self {
-        self.char_set = Some(char_set);
+    pub fn with_auto_location(mut self, enabled: bool) -> Self {
+        self.inner.auto_location = enabled as c_int;
         self
     }
 
-    /// Enable default ANSI colors.
+    /// Enable or disable dimming of unlabeled source text.
     ///
-    /// Uses the built-in color scheme with standard ANSI escape codes:
-    /// - Errors in red
-    /// - Warnings in yellow
-    /// - Margins in blue
-    /// - etc.
+    /// When enabled, the parts of source lines outside of any label are
+    /// drawn with [`ColorKind::Unimportant`], so labeled spans stand out.
+    /// When disabled, they keep the terminal's default color. Text inside a
+    /// [context span](Report::with_context_span) always uses
+    /// [`ColorKind::Context`].
     ///
-    /// This is appropriate for terminal output.
+    /// Default: [`true`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_color_default().with_dim_unlabeled(false))
+    ///     .with_title(Level::Error, "unknown variable")
+    ///     .with_label(8..9);
+    /// let output = report.render_to_string("let a = b;")?;
+    /// assert!(!output.contains("\x1b[38;5;249mlet a = "));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    pub fn with_color_default(mut self) -> Self {
-        self.inner.color = Some(ffi::mu_default_color);
-        self.color_ud = None;
+    pub fn with_dim_unlabeled(mut self, enabled: bool) -> Self {
+        self.inner.dim_unlabeled = enabled as c_int;
         self
     }
 
-    /// Disable color output.
+    /// Enable or disable caret-only mode for one-character labels.
     ///
-    /// All output will be plain text without ANSI escape codes.
-    /// This is appropriate for file output or non-color terminals.
+    /// When enabled, a label with a message spanning exactly one character
+    /// is drawn as a single caret with its arrow attached on the same row,
+    /// instead of an underline row followed by a connector. This keeps
+    /// tiny spans, such as the position of a missing semicolon, light.
     ///
-    /// Default: colors are disabled
+    /// Default: [`false`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_color_disabled().with_char_set_ascii().with_caret_only(true))
+    ///     .with_title(Level::Error, "expected `;`")
+    ///     .with_label(9..10)
+    ///     .with_message("add `;` here");
+    /// let output = report.render_to_string("let a = 1\n")?;
+    /// assert!(output.contains("|          ^-- add `;` here"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    pub fn with_color_disabled(mut self) -> Self {
-        self.inner.color = None;
-        self.color_ud = None;
+    pub fn with_caret_only(mut self, enabled: bool) -> Self {
+        self.inner.caret_only = enabled as c_int;
         self
     }
 
-    /// Set a custom color provider.
-    pub fn with_color<C>(mut self, color: &'a C) -> Self
-    where
-        C: Color,
-    {
-        extern "C" fn color_fn<C: Color>(
-            ud: *mut c_void,
-            kind: ffi::mu_ColorKind,
-        ) -> ffi::mu_Chunk {
-            // SAFETY: ud is provided by the caller and assumed valid
-            let ud = unsafe { &mut *(ud as *mut ColorUd) };
-            // SAFETY: in color_fn's call lifetime, color_obj and color_buf are valid
-            let color = unsafe { &*(ud.color_obj as *const C) };
-            // SAFETY: color_buf is initialized by Report::render_to_writer and remains valid during callback
-            let buf = unsafe { &mut *ud.color_buf };
-            let mut remain = &mut buf[1..];
-            match color.color(&mut remain, ColorKind::from_ffi(kind)) {
-                Ok(_) => {
-                    let used = (ffi::sizes::COLOR_CODE - remain.len() - 1) as u8;
-                    buf[0] = used;
-                    buf.as_ptr() as *const c_char
-                }
-                Err(_) => b"\0" as *const u8 as *const c_char,
-            }
-        }
-
-        self.color_ud = Some(Box::new(ColorUd {
-            color_obj: color as *const C as *mut c_void,
-            color_buf: ptr::null_mut(),
-        }));
-        self.inner.color = Some(color_fn::<C>);
-        self.inner.color_ud = self
-            .color_ud
-            .as_ref()
-            .map_or(ptr::null_mut(), |ud| &**ud as *const ColorUd as *mut c_void);
+    /// Set whether the column in the header location is 1-based.
+    ///
+    /// Disable this to print 0-based columns, matching tools such as some
+    /// assemblers and editors. Line numbers are controlled per source with a
+    /// line number offset, e.g. `(content, name, -1)` for 0-based lines.
+    ///
+    /// Default: [`true`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_color_disabled().with_one_based_columns(false))
+    ///     .with_title(Level::Error, "unknown variable")
+    ///     .with_label(8..9);
+    /// let output = report.render_to_string(("let a = b;", "main.rs", -1))?;
+    /// assert!(output.contains("[ main.rs:0:8 ]"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    pub fn with_one_based_columns(mut self, enabled: bool) -> Self {
+        self.inner.one_based_columns = enabled as c_int;
         self
     }
-}
 
-/// Trait for types that can be added to a cache.
-///
-/// This trait is automatically implemented for common types:
-/// - `&str` - Borrowed string content
-/// - `String` - Owned string content (stored in cache)
-/// - `OwnedSource<S>` - Any type implementing `AsRef<[u8]>` (`Vec<u8>`, `Box<[u8]>`, etc.)
-/// - Tuples with filename: `(&str, &str)`, `(String, &str)`
-/// - Custom `Source` trait implementations
-///
-/// Users typically don't need to implement this trait directly.
-pub trait AddToCache {
-    /// Add this source to the cache.
+    /// Set whether primary labels win over other labels of the same
+    /// [priority](Report::with_priority).
     ///
-    /// # Parameters
-    /// - `cache`: Mutable reference to the C cache pointer
+    /// Where labels overlap, the one with the highest priority colors the
+    /// source text and draws the underline. With this enabled, a
+    /// [primary label](Report::with_primary_label) is treated as higher than
+    /// any other label of the same priority, so conflicts resolve towards
+    /// the main point of the report without assigning priorities by hand.
+    /// [`Emitter`] also gives the labels of merged reports priorities by
+    /// their level, so errors win over warnings and warnings over the rest.
     ///
-    /// # Returns
-    /// Pointer to the created `mu_Source` in the C library
-    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source;
-}
-
-/// Wrapper for owned source content.
-///
-/// `OwnedSource` wraps any type that can be viewed as bytes (`AsRef<[u8]>`),
-/// such as `Vec<u8>`, `Box<[u8]>`, or custom buffer types. The content is
-/// stored directly in the cache's internal memory managed by the C library.
-///
-/// # Example
-/// ```rust
-/// # use musubi::{Cache, OwnedSource, Report, Level};
-/// let buffer = vec![b'c', b'o', b'd', b'e'];
-/// let cache = Cache::new()
-///     .with_source((OwnedSource::new(buffer), "data.bin"));
-///
-/// let mut report = Report::new()
-///     .with_title(Level::Error, "Error in binary data")
-///     .with_label(0..4)
-///     .render_to_string(&cache)?;
-/// # Ok::<(), std::io::Error>(())
-/// ```
-pub struct OwnedSource<S>(S);
-
-impl<S: AsRef<[u8]>> From<S> for OwnedSource<S> {
+    /// Default: [`false`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let report = Report::new()
+    ///     .with_config(Config::new().with_auto_priority(true))
+    ///     .with_title(Level::Error, "mismatched types")
+    ///     .with_primary_label(8..15)
+    ///     .with_message("expected `i32`")
+    ///     // without auto priority, this shorter label would color `"a"`
+    ///     .with_label(12..15)
+    ///     .with_message("found `&str`");
+    /// ```
     #[inline]
-    fn from(value: S) -> Self {
-        Self(value)
+    pub fn with_auto_priority(mut self, enabled: bool) -> Self {
+        self.inner.auto_priority = enabled as c_int;
+        self
     }
-}
 
-impl<S: AsRef<[u8]>> OwnedSource<S> {
-    /// Create a new owned source from any type implementing `AsRef<[u8]>`.
+    /// Set the maximum number of margin columns drawn for multi-line labels.
+    ///
+    /// Every multi-line label in a source takes a column of the left
+    /// margin, and labels crossing each other make the margin a lattice of
+    /// lines that is hard to follow. With a limit, only the first `depth`
+    /// multi-line labels of each source are drawn, longest first, plus any
+    /// [primary label](Report::with_primary_label); the rest are listed
+    /// below the source lines by their `line:col` location and message.
+    ///
+    /// Default: `0` (no limit)
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// let output = Report::new()
+    ///     .with_config(
+    ///         Config::new()
+    ///             .with_color_disabled()
+    ///             .with_char_set_ascii()
+    ///             .with_max_depth(1),
+    ///     )
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(0..9)
+    ///     .with_message("outer")
+    ///     .with_label(2..7)
+    ///     .with_message("inner")
+    ///     .render_to_string("a\nb\nc\nd\ne")?;
+    /// assert!(output.contains("   | > 2:1: inner\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    pub fn new(owned: S) -> Self {
-        owned.into()
+    pub fn with_max_depth(mut self, depth: i32) -> Self {
+        self.inner.max_depth = depth;
+        self
     }
-}
-
-impl<S: AsRef<[u8]>> AddToCache for OwnedSource<S> {
-    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
-        #[repr(C)]
-        struct OwnedSource<S> {
-            base: ffi::mu_Source,
-            owned: S,
-        }
-        // SAFETY: mu_addmemory initializes the cache and source correctly
-        let src =
-            unsafe { ffi::mu_addsource(cache, size_of::<OwnedSource<S>>(), Default::default()) };
-        // SAFETY: src is allocated by mu_addsource above and valid here
-        let owned_src = unsafe { &mut *(src as *mut OwnedSource<S>) };
-        owned_src.base.init = Some(init_fn::<S>);
-        owned_src.base.free = Some(free_fn::<S>);
-        owned_src.base.get_line = Some(get_line_fn::<S>);
-        owned_src.owned = self.0;
-
-        unsafe extern "C" fn init_fn<S: AsRef<[u8]>>(src: *mut ffi::mu_Source) -> c_int {
-            // SAFETY: src is a valid OwnedSource<S> pointer created in into_source below
-            let src = unsafe { &mut *(src as *mut OwnedSource<S>) };
-            // SAFETY: calling mu_updatelines is safe
-            unsafe { ffi::mu_updatelines(&mut src.base, src.owned.as_ref().into()) };
-            ffi::MU_OK
-        }
-
-        unsafe extern "C" fn free_fn<S: AsRef<[u8]>>(src: *mut ffi::mu_Source) {
-            let ud = src as *mut OwnedSource<S>;
-            // SAFETY: ud was allocated by mu_addsource and is valid here
-            // after this call, src will be freed by C library.
-            unsafe { std::ptr::drop_in_place(ud) };
-        }
-
-        unsafe extern "C" fn get_line_fn<S: AsRef<[u8]>>(
-            src: *mut ffi::mu_Source,
-            line_no: c_uint,
-        ) -> ffi::mu_Slice {
-            // SAFETY: src is a valid OwnedSource<S> pointer
-            let src = unsafe { &mut *(src as *mut OwnedSource<S>) };
-            // SAFETY: calling mu_getline is safe
-            let line = unsafe { *ffi::mu_getline(&mut src.base, line_no) };
-            src.owned.as_ref()[line.byte_offset as usize..][..line.byte_len as usize].into()
-        }
 
-        src
+    /// Draw a vertical bar in the color of the report level at the left of
+    /// every line below the header.
+    ///
+    /// When scanning many diagnostics, the bar tells errors from warnings
+    /// at a glance. It takes the first column of the margin, so the lines
+    /// keep their width.
+    ///
+    /// Default: [`false`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// let output = Report::new()
+    ///     .with_config(
+    ///         Config::new()
+    ///             .with_color_disabled()
+    ///             .with_char_set_ascii()
+    ///             .with_gutter_bar(true),
+    ///     )
+    ///     .with_title(Level::Warning, "unused variable")
+    ///     .with_label(4..5)
+    ///     .render_to_string("let x = 1;")?;
+    /// assert!(output.contains("\n|1 | let x = 1;\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    pub fn with_gutter_bar(mut self, enabled: bool) -> Self {
+        self.inner.gutter_bar = enabled as c_int;
+        self
     }
-}
 
-impl AddToCache for String {
+    /// Collapse helps and notes longer than `lines` lines.
+    ///
+    /// Long explanations or backtraces in notes push the source snippet
+    /// out of sight. A collapsed help or note shows its first `lines`
+    /// lines, then a line telling how many lines were left out. In
+    /// [`Report::render_to_html`], the lines past the first ones are put
+    /// in a `<details>` section instead.
+    ///
+    /// Default: `0` (never collapse)
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled().with_collapse_notes(1))
+    ///     .with_title(Level::Error, "panicked")
+    ///     .with_note("backtrace:\n  0: main\n  1: start")
+    ///     .render_to_string("")?;
+    /// assert!(output.contains("Note: backtrace:\n"));
+    /// assert!(output.contains("… 2 more lines\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
-        OwnedSource::new(self).add_to_cache(cache)
+    pub fn with_collapse_notes(mut self, lines: i32) -> Self {
+        self.inner.collapse_notes = lines;
+        self
     }
-}
 
-impl AddToCache for &str {
+    /// Set the columns each level of [related
+    /// reports](Report::with_related) is indented by.
+    ///
+    /// Every line of a related report is shifted by the same width, so its
+    /// margin stays aligned, and reports related to it are shifted again.
+    ///
+    /// Default: `4`
     #[inline]
-    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
-        // SAFETY: mu_addmemory initializes the cache and source correctly
-        unsafe { ffi::mu_addmemory(cache, self.into(), Default::default()) }
+    pub fn with_related_indent(mut self, columns: usize) -> Self {
+        self.related_indent = columns;
+        self
     }
-}
-
-impl<S: Source> AddToCache for S {
-    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
-        #[repr(C)]
-        struct BoxedSource<S: Source> {
-            base: ffi::mu_Source,
-            rust_obj: S,
-            line: ffi::mu_Line,
-            err: Option<io::Error>,
-        }
-
-        // SAFETY: mu_addsource initializes the cache and source correctly
-        let src = unsafe {
-            let src = ffi::mu_addsource(cache, size_of::<BoxedSource<S>>(), Default::default());
-            &mut *(src as *mut BoxedSource<S>)
-        };
-        src.rust_obj = self;
-        src.base.init = Some(init_fn::<S>);
-        src.base.free = Some(free_fn::<S>);
-        src.base.get_line = Some(get_line_fn::<S>);
-        src.base.get_line_info = Some(get_line_info_fn::<S>);
-        src.base.line_for_chars = Some(line_for_chars_fn::<S>);
-        src.base.line_for_bytes = Some(line_for_bytes_fn::<S>);
 
-        extern "C" fn init_fn<S: Source>(src: *mut ffi::mu_Source) -> c_int {
-            // SAFETY: src is a valid UdSource<S> pointer created in into_source below
-            let src = unsafe { &mut (*(src as *mut BoxedSource<S>)) };
-            match src.rust_obj.init() {
-                Ok(_) => 0,
-                Err(err) => {
-                    // SAFETY: report pointer is valid for the lifetime of the source
-                    src.err = Some(err);
-                    ffi::MU_ERR_SRCINIT
-                }
-            }
-        }
+    /// Draw connectors from a report to its [related
+    /// reports](Report::with_related).
+    ///
+    /// The header of each related report starts with an arrow, like
+    /// `╰─▶`, and a bar runs down the indentation to the next related
+    /// report of the same parent, so chains of child diagnostics or macro
+    /// expansions read as a tree. The glyphs come from the
+    /// [character set](Config::with_char_set); an indentation narrower
+    /// than 4 columns shortens the arrow.
+    ///
+    /// Default: [`false`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// let output = Report::new()
+    ///     .with_config(
+    ///         Config::new()
+    ///             .with_color_disabled()
+    ///             .with_char_set_ascii()
+    ///             .with_related_connectors(true),
+    ///     )
+    ///     .with_title(Level::Error, "expansion failed")
+    ///     .with_related(Report::new().with_title(Level::Note, "in `vec!`"))
+    ///     .with_related(Report::new().with_title(Level::Note, "in `main`"))
+    ///     .render_to_string("")?;
+    /// assert!(output.ends_with("|-> Note: in `vec!`\n`-> Note: in `main`\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    pub fn with_related_connectors(mut self, enabled: bool) -> Self {
+        self.related_connectors = enabled;
+        self
+    }
 
-        unsafe extern "C" fn free_fn<S: Source>(src: *mut ffi::mu_Source) {
-            let ud = src as *mut BoxedSource<S>;
-            // SAFETY: ud was allocated by mu_addsource and is valid here
-            // after this call, src will be freed by C library.
-            unsafe { std::ptr::drop_in_place(ud) };
-        }
+    /// Set a separator between groups of three digits in line numbers.
+    ///
+    /// Sources with a large line number offset, like `(text, name, offset)`,
+    /// get long line numbers in the margin, like `1234567`. With a
+    /// separator like `" "` or `","` they read as `1 234 567`. The margin
+    /// widens to fit the separators. Separators longer than 8 bytes are
+    /// cut. Ignored when a [formatter](Config::with_line_no_format) is set.
+    ///
+    /// Default: `""` (no grouping)
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Config, Level, Report};
+    /// let cache = Cache::new().with_source(("let x = 1;", "big.rs", 12344));
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled().with_line_no_separator(","))
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(4..5)
+    ///     .render_to_string(&cache)?;
+    /// assert!(output.contains(" 12,345 ┤ let x = 1;"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    pub fn with_line_no_separator(mut self, sep: &'a str) -> Self {
+        self.inner.line_no_sep = sep.into();
+        self
+    }
 
-        extern "C" fn get_line_fn<S: Source>(
-            src: *mut ffi::mu_Source,
+    /// Set a function formatting the line numbers in the margin.
+    ///
+    /// The function gets the 1-based line number, after the line number
+    /// offset of its source, and returns the text shown in the margin. The
+    /// margin is as wide as the widest text; texts over 64 bytes are cut.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// let hex = |line: usize| format!("{line:#x}");
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled().with_line_no_format(&hex))
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(4..5)
+    ///     .render_to_string("let x = 1;")?;
+    /// assert!(output.contains(" 0x1 ┤ let x = 1;"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn with_line_no_format<F>(mut self, format: &'a F) -> Self
+    where
+        F: Fn(usize) -> String,
+    {
+        extern "C" fn line_no_fn<F: Fn(usize) -> String>(
+            ud: *mut c_void,
             line_no: c_uint,
+            buf: *mut c_char,
+            size: usize,
         ) -> ffi::mu_Slice {
-            // SAFETY: src is a valid UdSource<S> pointer
-            let src = unsafe { &mut *(src as *mut BoxedSource<S>) };
-            src.rust_obj.get_line(line_no as usize).into()
-        }
-
-        extern "C" fn get_line_info_fn<S: Source>(
-            src: *mut ffi::mu_Source,
-            line_no: c_uint,
-        ) -> *const ffi::mu_Line {
-            // SAFETY: src is a valid UdSource<S> pointer
-            let src = unsafe { &mut *(src as *mut BoxedSource<S>) };
-            let line_info = src.rust_obj.get_line_info(line_no as usize);
-            src.line = line_info.into();
-            &src.line
-        }
-
-        extern "C" fn line_for_chars_fn<S: Source>(
-            src: *mut ffi::mu_Source,
-            char_pos: usize,
-            out_line: *mut *const ffi::mu_Line,
-        ) -> c_uint {
-            // SAFETY: src is a valid UdSource<S> pointer
-            let src = unsafe { &mut *(src as *mut BoxedSource<S>) };
-            let (line_no, line_info) = src.rust_obj.line_for_chars(char_pos);
-            if !out_line.is_null() {
-                src.line = line_info.into();
-                // SAFETY: out_line is checked
-                unsafe { *out_line = &src.line };
-            }
-            line_no as c_uint
-        }
-
-        extern "C" fn line_for_bytes_fn<S: Source>(
-            src: *mut ffi::mu_Source,
-            byte_pos: usize,
-            out_line: *mut *const ffi::mu_Line,
-        ) -> c_uint {
-            // SAFETY: src is a valid UdSource<S> pointer
-            let src = unsafe { &mut *(src as *mut BoxedSource<S>) };
-            let (line_no, line_info) = src.rust_obj.line_for_bytes(byte_pos);
-            if !out_line.is_null() {
-                src.line = line_info.into();
-                // SAFETY: out_line is checked
-                unsafe { *out_line = &src.line };
+            // SAFETY: ud is the `&'a F` set below, valid while the config lives
+            let format = unsafe { &*(ud as *const F) };
+            let text = format(line_no as usize);
+            let mut len = text.len().min(size);
+            while !text.is_char_boundary(len) {
+                len -= 1;
             }
-            line_no as c_uint
+            // SAFETY: buf is valid for size bytes and len <= size
+            let buf = unsafe { std::slice::from_raw_parts_mut(buf as *mut u8, len) };
+            buf.copy_from_slice(&text.as_bytes()[..len]);
+            (&*buf).into()
         }
 
-        &mut src.base
+        self.inner.line_no = Some(line_no_fn::<F>);
+        self.inner.line_no_ud = format as *const F as *mut c_void;
+        self
     }
-}
 
-impl<S: AddToCache> AddToCache for (S, &str) {
+    /// Set where labels attach to spans.
+    ///
+    /// Controls the default attachment point for all labels.
+    /// Individual labels can override this with [`Report::with_order`].
+    ///
+    /// Default: [`LabelAttach::Middle`]
     #[inline]
-    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
-        let src = self.0.add_to_cache(cache);
-        // SAFETY: src is a valid mu_Source pointer
-        unsafe { (*src).name = self.1.into() };
-        src
+    pub fn with_label_attach(mut self, attach: LabelAttach) -> Self {
+        self.inner.label_attach = attach.into();
+        self
     }
-}
 
-impl<S: AddToCache> AddToCache for (S, &str, i32) {
+    /// Set the index type (character or byte).
+    ///
+    /// Determines how span ranges are interpreted.
+    /// See [`IndexType`] for details.
+    ///
+    /// Default: [`IndexType::Char`]
     #[inline]
-    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
-        let src = self.0.add_to_cache(cache);
-        // SAFETY: src is a valid mu_Source pointer
-        unsafe {
-            (*src).name = self.1.into();
-            (*src).line_no_offset = self.2
-        };
-        src
+    pub fn with_index_type(mut self, index_type: IndexType) -> Self {
+        self.inner.index_type = index_type.into();
+        self
     }
-}
-
-/// Internal representation of a cache for rendering.
-///
-/// This enum manages the lifetime of the underlying C cache pointer:
-/// - `Owned`: Cache was created for a single render and will be freed
-/// - `Borrowed`: Cache is owned by user code and should not be freed
-///
-/// Users typically don't interact with this type directly; it's used
-/// internally by the `render_to_*` methods.
-pub enum RawCache {
-    /// Temporary cache that will be freed when dropped
-    Owned(*mut ffi::mu_Cache),
-    /// Borrowed cache that remains owned by the caller
-    Borrowed(*mut ffi::mu_Cache),
-}
 
-impl Drop for RawCache {
+    /// Set how labels overlapping on one line are drawn.
+    ///
+    /// With [`Overlap::MergeColors`], all labels share one underline row,
+    /// and where they overlap, the label winning the
+    /// [priority](Report::with_priority) draws the underline. With
+    /// [`Overlap::Stack`], labels that would overlap are moved to underline
+    /// rows of their own below the first one, so every span stays visible.
+    /// With [`Overlap::HideLower`], a label overlapped by a winning label is
+    /// not drawn at all, message included. See [`Overlap`] for details.
+    ///
+    /// Default: [`Overlap::MergeColors`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Overlap, Report};
+    /// let output = Report::new()
+    ///     .with_config(
+    ///         Config::new()
+    ///             .with_color_disabled()
+    ///             .with_char_set_ascii()
+    ///             .with_overlap_strategy(Overlap::Stack),
+    ///     )
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(4..12)
+    ///     .with_label(4..7)
+    ///     .render_to_string("foo(bar, baz)")?;
+    /// let lines: Vec<&str> = output.lines().map(str::trim_end).collect();
+    /// assert_eq!(lines[4..6], ["   |     ^^^^^^^^", "   |     ^^^"]);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    fn drop(&mut self) {
-        match self {
-            RawCache::Owned(ptr) => {
-                if !ptr.is_null() {
-                    // SAFETY: mu_delcache frees the cache allocated by mu_addmemory
-                    unsafe { ffi::mu_delcache(*ptr) };
-                }
-            }
-            RawCache::Borrowed(_) => {
-                // Do nothing for borrowed cache
-            }
-        }
+    pub fn with_overlap_strategy(mut self, overlap: Overlap) -> Self {
+        self.inner.overlap = overlap.into();
+        self
     }
-}
 
-impl RawCache {
+    /// Set how the closing border of reports is drawn.
+    ///
+    /// Reports rendered one after the other each end with a border, which
+    /// gets heavy in long outputs: [`Footer::Blank`] separates them with an
+    /// empty line instead, and [`Footer::Omit`] with nothing.
+    /// [`Footer::Labeled`] names the code of the report it closes. Compact
+    /// reports have no closing border. See [`Footer`] for details.
+    ///
+    /// Default: [`Footer::Border`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Footer, Level, Report};
+    /// let output = Report::new()
+    ///     .with_config(
+    ///         Config::new()
+    ///             .with_color_disabled()
+    ///             .with_char_set_ascii()
+    ///             .with_footer(Footer::Labeled),
+    ///     )
+    ///     .with_title(Level::Error, "mismatched types")
+    ///     .with_code("E0308")
+    ///     .with_label(8..9)
+    ///     .render_to_string("let a = b;")?;
+    /// assert!(output.ends_with("\n---'-[ end E0308 ]\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    fn as_ptr(&self) -> *mut ffi::mu_Cache {
-        match self {
-            RawCache::Owned(ptr) => *ptr,
-            RawCache::Borrowed(ptr) => *ptr,
-        }
+    pub fn with_footer(mut self, footer: Footer) -> Self {
+        self.inner.footer = footer.into();
+        self
     }
-}
 
-impl<S: AddToCache> From<S> for RawCache {
+    /// Set the order of the sources in reports with labels in several
+    /// sources.
+    ///
+    /// By default the sources come in the order they were added to the
+    /// cache, which may not put the most relevant file first. See
+    /// [`SourceOrder`] for the other orders.
+    ///
+    /// Default: [`SourceOrder::Cache`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Config, Level, Report, SourceOrder};
+    /// let cache = Cache::new()
+    ///     .with_source(("fn helper() {}", "lib.rs"))
+    ///     .with_source(("helper(1);", "main.rs"));
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_source_order(SourceOrder::PrimaryFirst))
+    ///     .with_title(Level::Error, "too many arguments")
+    ///     .with_label((3..9, 0))
+    ///     .with_message("defined here")
+    ///     .with_primary_label((7..8, 1))
+    ///     .with_message("unexpected argument")
+    ///     .render_to_string(&cache)?;
+    /// assert!(output.find("main.rs") < output.find("lib.rs"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
     #[inline]
-    fn from(value: S) -> RawCache {
-        let mut cache = ptr::null_mut();
-        value.add_to_cache(&mut cache);
-        RawCache::Owned(cache)
+    pub fn with_source_order(mut self, order: SourceOrder) -> Self {
+        self.inner.source_order = order.into();
+        self
     }
-}
 
-/// A cache of diagnostic sources.
-///
-/// `Cache` manages multiple source files and their associated data,
-/// allowing for efficient multi-source diagnostics. It can be reused
-/// across multiple render operations.
-///
-/// # Source Lifetime Management
-///
-/// The cache automatically handles different source types:
-/// - **Borrowed sources** (`&str`): Content must remain valid until rendering completes
-/// - **Owned sources** (`String`, `Vec<u8>`, etc.): Content is stored in the cache's
-///   internal memory managed by the C library
-///
-/// # Single Source Convenience
-///
-/// For simple single-source diagnostics, you can pass sources directly to
-/// rendering methods without creating an explicit `Cache`. See [`Report::render_to_string()`]
-/// for examples.
-///
-/// # Example
-/// ```rust
-/// use musubi::{Cache, Report, Level};
-///
-/// let cache = Cache::new()
-///     .with_source(("let x = 42;", "main.rs"))        // Source 0
-///     .with_source(("fn foo() {}", "lib.rs"));        // Source 1
-///
-/// let mut report = Report::new()
-///     .with_title(Level::Error, "Multiple files")
-///     .with_label((0..3, 0))   // Label in main.rs
-///     .with_message("here")
-///     .with_label((3..6, 1))   // Label in lib.rs
-///     .with_message("and here");
-///
-/// report.render_to_stdout(&cache)?;
-/// # Ok::<(), std::io::Error>(())
-/// ```
-#[derive(Default)]
-pub struct Cache {
-    inner: *mut ffi::mu_Cache,
-}
+    /// Set ASCII character set for rendering.
+    ///
+    /// Uses ASCII characters (`-`, `|`, `+`, etc.) for box drawing.
+    /// This is compatible with all terminals and file formats.
+    ///
+    /// # Example
+    /// ```text
+    /// Error: message
+    ///    ,-[ file.rs:1:1 ]
+    ///    |
+    ///  1 | code here
+    ///    | ^^|^
+    ///    |   `--- label
+    /// ---'
+    /// ```
+    #[inline]
+    pub fn with_char_set_ascii(mut self) -> Self {
+        // SAFETY: mu_ascii() returns a valid static charset pointer
+        self.inner.char_set = unsafe { ffi::mu_ascii() };
+        self.char_set = None;
+        self.char_set_buf = None;
+        self
+    }
 
-impl From<&Cache> for RawCache {
+    /// Set Unicode character set for rendering.
+    ///
+    /// Uses Unicode box-drawing characters (─, │, ┬, etc.) for prettier output.
+    /// Requires a terminal that supports Unicode.
+    ///
+    /// # Example
+    /// ```text
+    /// Error: message
+    ///    ╭─[ file.rs:1:1 ]
+    ///    │
+    ///  1 │ code here
+    ///    │ ──┬─
+    ///    │   ╰─── label
+    /// ───╯
+    /// ```
     #[inline]
-    fn from(cache: &Cache) -> RawCache {
-        RawCache::Borrowed(cache.inner)
+    pub fn with_char_set_unicode(mut self) -> Self {
+        // SAFETY: mu_unicode() returns a valid static charset pointer
+        self.inner.char_set = unsafe { ffi::mu_unicode() };
+        self.char_set = None;
+        self.char_set_buf = None;
+        self
     }
-}
 
-impl Cache {
-    /// Create a new empty cache.
+    /// Set a custom character set for rendering.
+    ///
+    /// Allows fine-grained control over all box-drawing characters.
+    /// The character set must outlive the config. It is converted for the
+    /// renderer once here, rather than on every render.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, CharSet};
+    /// let custom = CharSet {
+    ///     hbar: '=',
+    ///     vbar: '!',
+    ///     ..CharSet::ascii()
+    /// };
+    /// let config = Config::new().with_char_set(&custom);
+    /// ```
     #[inline]
-    pub fn new() -> Self {
-        Default::default()
+    pub fn with_char_set(mut self, char_set: &'a CharSet) -> Self {
+        let buf = CharSetBuf::new(*char_set);
+        // the box keeps `chars` at the same address when the config moves
+        self.inner.char_set = &buf.chars;
+        self.char_set = Some(char_set);
+        self.char_set_buf = Some(buf);
+        self
     }
 
-    /// Add a source to the cache.
+    /// Set a probe deciding at render time whether the character set can be
+    /// displayed, falling back to [`CharSet::ascii`] if it can't.
     ///
-    /// Accepts both borrowed (`&str`) and owned (`String`) content.
-    /// For other byte buffers like `Vec<u8>`, use [`OwnedSource`].
-    /// Borrowed content must remain valid until rendering completes.
-    /// Owned content is stored in the cache's internal memory.
+    /// The probe is called with the configured character set each time a
+    /// report renders, unless the set is all ASCII. Tools can then keep the
+    /// Unicode set configured and still produce readable output on dumb
+    /// terminals or non-UTF-8 locales. [`CharSet::is_displayable`] is a
+    /// probe guessing from the environment.
     ///
     /// # Example
     /// ```rust
-    /// # use musubi::{Cache, OwnedSource};
-    /// let cache = Cache::new()
-    ///     .with_source("let x = 42;")                    // &str - borrowed
-    ///     .with_source(("fn main() {}".to_string(), "main.rs"))  // String - owned
-    ///     .with_source((OwnedSource::new(vec![b'a', b'b', b'c']), "data.bin"));  // Vec<u8>
+    /// # use musubi::{CharSet, Config, Level, Report};
+    /// let config = Config::new()
+    ///     .with_color_disabled()
+    ///     .with_char_set_probe(|_| false);
+    /// let output = Report::new()
+    ///     .with_config(config)
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(0..3)
+    ///     .render_to_string("let x = 42;")?;
+    /// assert!(output.is_ascii());
+    ///
+    /// // guess from TERM and the locale
+    /// let config = Config::new().with_char_set_probe(CharSet::is_displayable);
+    /// # Ok::<(), std::io::Error>(())
     /// ```
     #[inline]
-    pub fn with_source<S: AddToCache>(mut self, content: S) -> Self {
-        content.add_to_cache(&mut self.inner);
+    pub fn with_char_set_probe(mut self, probe: fn(&CharSet) -> bool) -> Self {
+        self.char_set_probe = Some(probe);
         self
     }
-}
 
-/// A source of diagnostic content.
-///
-/// Sources can be created from in-memory strings or with custom line providers.
-/// They are typically managed through a [`Cache`], but can also be passed directly
-/// to rendering methods for single-source diagnostics.
-///
-/// # Example
-/// ```rust
-/// # use musubi::{Cache, Source, Line};
-/// # use std::default::Default;
-///
-/// // implement a custom source
-/// struct MySource { /* ... */ }
-///
-/// # impl MySource { fn new() -> Self { Self{ /* ... */ } } }
-///
-/// impl Source for MySource {
-///     // ...
-/// # fn init(&mut self) -> std::io::Result<()> { Ok(()) }
-/// # fn get_line(&self, line_no: usize) -> &[u8] { b"" }
-/// # fn get_line_info(&self, line_no: usize) -> musubi::Line { Line::new() }
-/// # fn line_for_chars(&self, char_pos: usize) -> (usize, musubi::Line) { (0, Line::new()) }
-/// # fn line_for_bytes(&self, byte_pos: usize) -> (usize, musubi::Line) { (0, Line::new()) }
-/// }
-///
-/// // Use with Cache for multiple sources
-/// let cache = Cache::new()
-///     .with_source(("let x = 42;", "main.rs"))
-///     .with_source((MySource::new(), "my_source.rs"));
-///
-/// // Or pass directly to render for single source
-/// // report.render_to_string(("code", "file.rs"))?;
-/// ```
-pub trait Source {
-    /// Initialize the source (e.g., read lines).
-    fn init(&mut self) -> io::Result<()>;
+    /// The character set drawn, ASCII if the probe says the configured one
+    /// can't be displayed.
+    fn drawn_char_set(&self) -> CharSet {
+        let char_set = self.char_set();
+        match self.char_set_probe {
+            Some(probe) if !char_set.is_ascii() && !probe(&char_set) => CharSet::ascii(),
+            _ => char_set,
+        }
+    }
 
-    /// Get a specific line by line number (0-based).
-    /// Return last line data if line_no is out of range.
-    fn get_line(&self, line_no: usize) -> &[u8];
+    /// Fall back to the ASCII character set if the probe says the
+    /// configured one can't be displayed, returning the replaced one.
+    fn probe_char_set(&mut self) -> Option<*const ffi::mu_Charset> {
+        let probe = self.char_set_probe?;
+        let char_set = self.char_set();
+        if char_set.is_ascii() || probe(&char_set) {
+            return None;
+        }
+        // SAFETY: mu_ascii() returns a valid static charset pointer
+        Some(std::mem::replace(&mut self.inner.char_set, unsafe {
+            ffi::mu_ascii()
+        }))
+    }
 
-    /// Get line info struct by line number (0-based).
-    /// Return last line info if line_no is out of range.
-    fn get_line_info(&self, line_no: usize) -> Line;
-
-    /// Get the line number and line info for a given character position.
-    /// Return last line number and info if char_pos is out of range.
-    fn line_for_chars(&self, char_pos: usize) -> (usize, Line);
-
-    /// Get the line number and line info for a given byte position.
-    /// Return last line number and info if byte_pos is out of range.
-    fn line_for_bytes(&self, byte_pos: usize) -> (usize, Line);
-}
-
-/// Information about a line in source code.
-///
-/// This structure describes a line's position and length in both
-/// character and byte offsets, which is important for proper UTF-8 handling.
-///
-/// Returned by [`Source`] trait methods to provide line metadata.
-#[derive(Default, Debug, Clone, Copy)]
-pub struct Line {
-    /// Character offset from the start of the source (0-based)
-    pub offset: usize,
-    /// Byte offset from the start of the source (0-based)
-    pub byte_offset: usize,
-    /// Line length in characters (excluding newline)
-    pub len: u32,
-    /// Line length in bytes (excluding newline)
-    pub byte_len: u32,
-    /// Newline sequence length in bytes (0, 1 for \n, 2 for \r\n)
-    pub newline: u32,
-}
-
-impl Line {
-    /// Create a new empty Line with all fields set to zero.
-    #[inline]
-    pub fn new() -> Self {
-        Self::default()
-    }
-}
-
-impl From<*const ffi::mu_Line> for Line {
-    #[allow(clippy::not_unsafe_ptr_arg_deref)]
-    #[inline]
-    fn from(line: *const ffi::mu_Line) -> Self {
-        // SAFETY: line pointer is provided by C library and assumed valid
-        let line = unsafe { &*line };
-        Line {
-            offset: line.offset,
-            byte_offset: line.byte_offset,
-            len: line.len,
-            byte_len: line.byte_len,
-            newline: line.newline,
-        }
-    }
-}
-
-impl From<Line> for ffi::mu_Line {
-    #[inline]
-    fn from(line: Line) -> Self {
-        ffi::mu_Line {
-            offset: line.offset,
-            byte_offset: line.byte_offset,
-            len: line.len,
-            byte_len: line.byte_len,
-            newline: line.newline,
-        }
-    }
-}
-
-/// A diagnostic report builder.
-///
-/// The lifetime `'a` indicates that all string references passed to the report
-/// must live at least as long as the report itself. This enables zero-copy
-/// string passing to the underlying C library.
-///
-/// # Source Management
-///
-/// Sources are managed through a [`Cache`] and assigned IDs based on registration
-/// order: first source is 0, second is 1, etc. The cache is then passed to rendering
-/// methods.
-///
-/// # Example
-/// ```rust
-/// use musubi::{Report, Cache, Level};
-///
-/// let cache = Cache::new()
-///     .with_source(("let x = 42;", "main.rs"))   // src_id = 0
-///     .with_source(("fn foo() {}", "lib.rs"));   // src_id = 1
-///
-/// let mut report = Report::new()
-///     .with_title(Level::Error, "Error")
-///     .with_label((0..3, 0)) // label in source 0
-///     .with_message("here")
-///     .with_label((3..6, 1)) // label in source 1
-///     .with_message("and here");
-///
-/// report.render_to_stdout(&cache)?;
-/// # Ok::<(), std::io::Error>(())
-/// ```
-///
-/// # Lifetime Safety
-///
-/// Source strings must outlive the report. This will not compile:
-///
-/// ```compile_fail
-/// use musubi::{Report, Level};
-///
-/// fn bad() -> String {
-///     let mut report = Report::new();
-///     {
-///         let code = String::from("let x = 42;");
-///         report.with_source((code.as_str(), "test.rs"));
-///     }  // code dropped here, but report still holds reference
-///     report.render_to_string(0, 0)
-/// }
-/// ```
-pub struct Report<'a> {
-    ptr: *mut ffi::mu_Report,
-    config: Option<Config<'a>>,
-    color_buf: [u8; ffi::sizes::COLOR_CODE],
-    /// Box is necessary to ensure pointer stability when Vec grows
-    #[allow(clippy::vec_box)]
-    color_uds: Vec<Box<ColorUd>>,
-    src_err: Option<io::Error>,
-    _marker: PhantomData<&'a str>,
-}
-
-impl Default for Report<'_> {
-    #[inline]
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl Drop for Report<'_> {
-    #[inline]
-    fn drop(&mut self) {
-        // SAFETY: self.ptr is a valid mu_Report pointer owned by this Report
-        unsafe {
-            ffi::mu_delete(self.ptr);
-        }
-    }
-}
-
-impl<'a> Report<'a> {
-    /// Create a new report.
-    #[inline]
-    pub fn new() -> Self {
-        // SAFETY: mu_new allocates a new report, returns null on failure (checked below)
-        let ptr = unsafe { ffi::mu_new(None, ptr::null_mut()) };
-        assert!(!ptr.is_null(), "Failed to allocate report");
-        Self {
-            ptr,
-            config: None,
-            color_buf: [0; ffi::sizes::COLOR_CODE],
-            color_uds: Vec::new(),
-            src_err: None,
-            _marker: PhantomData,
-        }
-    }
-
-    /// Configure the report.
+    /// Enable default ANSI colors.
     ///
-    /// see [`Config`] for configuration options.
+    /// Uses the built-in color scheme with standard ANSI escape codes:
+    /// - Errors in red
+    /// - Warnings in yellow
+    /// - Margins in blue
+    /// - etc.
     ///
-    /// # Example
-    /// ```rust
-    /// # use musubi::{Report, Config};
-    /// let config = Config::new().with_limit_width(80);
-    /// let report = Report::new().with_config(config);
-    /// ```
+    /// This is appropriate for terminal output.
     #[inline]
-    #[must_use]
-    pub fn with_config(mut self, config: Config<'a>) -> Self {
-        self.config = Some(config);
+    pub fn with_color_default(mut self) -> Self {
+        self.inner.color = Some(ffi::mu_default_color);
+        self.color_ud = None;
         self
     }
 
-    /// Reset the report for reuse.
+    /// Enable default ANSI colors if stdout is a terminal and the
+    /// environment allows colors.
     ///
-    /// Clears all labels, messages, and configuration, allowing the same
-    /// Report instance to be used for rendering a different diagnostic.
+    /// The usual conventions are followed, in this order:
+    /// - a non-empty `NO_COLOR` disables colors;
+    /// - a `CLICOLOR_FORCE` other than `0` enables them, even when not
+    ///   writing to a terminal;
+    /// - `CLICOLOR=0` or `TERM=dumb` disables them.
+    ///
+    /// This is [`with_color_auto_for`](Config::with_color_auto_for) with
+    /// stdout, where [`Report::render_to_stdout`] writes; use that one to
+    /// decide for another stream, e.g. stderr.
     ///
     /// # Example
     /// ```rust
-    /// # use musubi::{Report, Level};
-    /// let mut report = Report::new()
-    ///     .with_title(Level::Error, "First error");
-    /// // ... render ...
-    /// report.render_to_string("")?;
-    ///
-    /// let mut report = report.reset()
-    ///     .with_title(Level::Warning, "Second warning");
-    /// // ... render again ...
-    /// report.render_to_string("")?;
+    /// # use musubi::{Config, Level, Report};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_auto())
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(0..3)
+    ///     .render_to_string("abc")?;
+    /// assert!(output.contains("abc"));
     /// # Ok::<(), std::io::Error>(())
     /// ```
     #[inline]
-    #[must_use]
-    pub fn reset(self) -> Self {
-        // SAFETY: self.ptr is a valid mu_Report pointer owned by this Report
-        unsafe { ffi::mu_reset(self.ptr) };
-        self
+    pub fn with_color_auto(self) -> Self {
+        self.with_color_auto_for(&io::stdout())
     }
 
-    /// Set the title and level.
+    /// Enable default ANSI colors if `stream` is a terminal and the
+    /// environment allows colors.
     ///
-    /// Accepts either a standard level or a custom level name:
-    /// - `with_title(Level::Error, "message")` - standard level
-    /// - `with_title("Note", "message")` - custom level name
+    /// See [`with_color_auto`](Config::with_color_auto) for the variables
+    /// checked.
     ///
     /// # Example
     /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_title(Level::Error, "Something went wrong")
-    ///     // Or with custom level:
-    ///     .with_title("Note", "Something to note")
-    ///     // ...
-    ///     # ;
+    /// # use musubi::Config;
+    /// let config = Config::new().with_color_auto_for(&std::io::stderr());
     /// ```
     #[inline]
-    #[must_use]
-    pub fn with_title<L: Into<TitleLevel<'a>>>(self, level: L, message: &'a str) -> Self {
-        let tl = level.into();
-        // SAFETY: self.ptr is valid, message lifetime is bound to 'a
-        unsafe { ffi::mu_title(self.ptr, tl.level, tl.custom_name, message.into()) };
-        self
+    pub fn with_color_auto_for(self, stream: &impl IsTerminal) -> Self {
+        let is_terminal = stream.is_terminal();
+        self.with_color_when(color_allowed(|key| std::env::var_os(key), is_terminal))
     }
 
-    /// Set the error code for this diagnostic.
+    /// Enable default ANSI colors if the Windows console can show them.
     ///
-    /// The error code is typically displayed in brackets before the title,
-    /// like `[E0001]` or `[W123]`.
+    /// Windows consoles print ANSI escape sequences as they are, unless
+    /// virtual terminal processing is on. This turns it on for the consoles
+    /// of stdout and stderr, and disables colors if the console is too old
+    /// to support it, instead of printing garbage. Legacy consoles can
+    /// still be colored through the Console API, by rendering with
+    /// `Report::render_to_write_color` of the `termcolor` feature to a
+    /// `termcolor::StandardStream`.
+    ///
+    /// On other platforms this is
+    /// [`with_color_default`](Config::with_color_default).
     ///
     /// # Example
     /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_title(Level::Error, "Type mismatch")
-    ///     .with_code("E0308")  // Displayed as [E0308]
-    ///     // ...
-    ///     # ;
+    /// # use musubi::{Config, Level, Report};
+    /// let mut report = Report::new()
+    ///     .with_config(Config::new().with_color_windows_console())
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(0..3);
+    /// report.render_to_stdout("abc")?;
+    /// # Ok::<(), std::io::Error>(())
     /// ```
     #[inline]
-    #[must_use]
-    pub fn with_code(self, code: &'a str) -> Self {
-        // SAFETY: self.ptr is valid, code lifetime is bound to 'a
-        unsafe { ffi::mu_code(self.ptr, code.into()) };
-        self
+    pub fn with_color_windows_console(self) -> Self {
+        self.with_color_when(enable_virtual_terminal())
     }
 
-    /// Set the primary label for its group.
+    fn with_color_when(self, enabled: bool) -> Self {
+        if enabled {
+            self.with_color_default()
+        } else {
+            self.with_color_disabled()
+        }
+    }
+
+    /// Disable color output.
     ///
-    /// This location is displayed in the diagnostic header, showing
-    /// where the error occurred.
+    /// All output will be plain text without ANSI escape codes.
+    /// This is appropriate for file output or non-color terminals.
     ///
-    /// # Example
-    /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_title(Level::Error, "Syntax error")
-    ///     .with_primary_label((0..3, 0))  // Primary label in source 0
-    ///     // ...
-    ///     # ;
-    /// ```
+    /// Default: colors are disabled
     #[inline]
-    #[must_use]
-    pub fn with_primary_label<L: Into<LabelSpan>>(self, span: L) -> Self {
-        let span = span.into();
-        // SAFETY: self.ptr is valid, span values are checked by C library
-        unsafe { ffi::mu_label(self.ptr, span.start, span.end, span.src_id) };
-        // SAFETY: self.ptr is valid
-        unsafe { ffi::mu_primary(self.ptr) };
+    pub fn with_color_disabled(mut self) -> Self {
+        self.inner.color = None;
+        self.color_ud = None;
         self
     }
 
-    /// Add a label at the given byte range.
-    ///
-    /// The `src_id` is the source registration order (0 for first source, 1 for second, etc.).
-    ///
-    /// # Example
-    /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_title(Level::Error, "Error")
-    ///     .with_label((0..3, 0))  // label in source 0
-    ///     .with_message("here")
-    ///     // ...
-    ///     # ;
-    /// ```
-    #[inline]
-    #[must_use]
-    pub fn with_label<L: Into<LabelSpan>>(self, span: L) -> Self {
-        let span = span.into();
-        // SAFETY: self.ptr is valid, span values are checked by C library
-        unsafe { ffi::mu_label(self.ptr, span.start, span.end, span.src_id) };
+    /// Set a custom color provider.
+    pub fn with_color<C>(mut self, color: &'a C) -> Self
+    where
+        C: Color,
+    {
+        extern "C" fn color_fn<C: Color>(
+            ud: *mut c_void,
+            kind: ffi::mu_ColorKind,
+        ) -> ffi::mu_Chunk {
+            // SAFETY: ud is provided by the caller and assumed valid
+            let ud = unsafe { &mut *(ud as *mut ColorUd) };
+            // SAFETY: in color_fn's call lifetime, color_obj and color_buf are valid
+            let color = unsafe { &*(ud.color_obj as *const C) };
+            // SAFETY: color_buf is initialized by Report::render_to_writer and remains valid during callback
+            let buf = unsafe { &mut *ud.color_buf };
+            let mut remain = &mut buf[1..];
+            match color.color(&mut remain, ColorKind::from_ffi(kind)) {
+                Ok(_) => {
+                    let used = (ffi::sizes::COLOR_CODE - remain.len() - 1) as u8;
+                    buf[0] = used;
+                    buf.as_ptr() as *const c_char
+                }
+                Err(_) => b"\0" as *const u8 as *const c_char,
+            }
+        }
+
+        self.color_ud = Some(Box::new(ColorUd {
+            color_obj: color as *const C as *mut c_void,
+            color_buf: ptr::null_mut(),
+        }));
+        self.inner.color = Some(color_fn::<C>);
+        self.inner.color_ud = self
+            .color_ud
+            .as_ref()
+            .map_or(ptr::null_mut(), |ud| &**ud as *const ColorUd as *mut c_void);
         self
     }
 
-    /// Set the message for the last added label.
+    /// Whether compact mode is enabled.
     ///
-    /// The message is displayed next to the label's marker/arrow,
-    /// providing explanation or context for the highlighted code.
+    /// See [`with_compact`](Config::with_compact).
     ///
     /// # Example
     /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_label(0..3)
-    ///     .with_message("expected identifier here")  // ← message for this label
-    ///     .with_label(10..15)
-    ///     .with_message("found number instead")      // ← message for next label
-    ///     // ...
-    ///     # ;
+    /// # use musubi::Config;
+    /// assert!(!Config::new().is_compact());
+    /// assert!(Config::new().with_compact(true).is_compact());
     /// ```
     #[inline]
-    #[must_use]
-    pub fn with_message(self, msg: &'a str) -> Self {
-        let width = unicode_width(msg);
-        // SAFETY: self.ptr is valid, msg lifetime is bound to 'a
-        unsafe { ffi::mu_message(self.ptr, msg.into(), width) };
-        self
+    pub fn is_compact(&self) -> bool {
+        self.inner.compact != 0
     }
 
-    /// Set the color for the last added label.
-    ///
-    /// This method accepts anything that implements [`IntoColor`], including:
-    /// - `&dyn Color` - Custom color trait objects
-    /// - `&GenColor` - Pre-generated colors from [`ColorGenerator`]
+    /// Whether crossing gaps are drawn.
     ///
-    /// # Examples
+    /// See [`with_cross_gap`](Config::with_cross_gap).
+    #[inline]
+    pub fn is_cross_gap(&self) -> bool {
+        self.inner.cross_gap != 0
+    }
+
+    /// Whether labels are underlined.
     ///
-    /// Using a custom color:
-    /// ```rust
-    /// # use musubi::{Report, Level, Color, ColorKind};
-    /// # use std::io::Write;
-    /// struct MyColor;
-    /// impl Color for MyColor {
-    ///     fn color(&self, w: &mut dyn Write, kind: ColorKind) -> std::io::Result<()> {
-    ///         write!(w, "\x1b[31m") // Red
-    ///     }
-    /// }
+    /// See [`with_underlines`](Config::with_underlines).
+    #[inline]
+    pub fn is_underlines(&self) -> bool {
+        self.inner.underlines != 0
+    }
+
+    /// Whether label crossings are minimised.
     ///
-    /// let color = MyColor;
-    /// Report::new()
-    ///     .with_label(0..4)
-    ///     .with_color(&color)
-    ///     // ...
-    ///     # ;
-    /// ```
+    /// See [`with_minimise_crossings`](Config::with_minimise_crossings).
+    #[inline]
+    pub fn is_minimise_crossings(&self) -> bool {
+        self.inner.minimise_crossings != 0
+    }
+
+    /// Whether label messages are aligned.
     ///
-    /// Using a color generator:
-    /// ```rust
-    /// # use musubi::{Report, Level, ColorGenerator};
-    /// let mut cg = ColorGenerator::new();
+    /// See [`with_align_messages`](Config::with_align_messages).
+    #[inline]
+    pub fn is_align_messages(&self) -> bool {
+        self.inner.align_messages != 0
+    }
+
+    /// Whether multi-line labels are drawn with arrows.
     ///
-    /// let report = Report::new()
-    ///     .with_label(0..4)
-    ///     .with_color(&cg.next_color())
-    ///     // ...;
-    ///     # ;
-    /// ```
+    /// See [`with_multiline_arrows`](Config::with_multiline_arrows).
     #[inline]
-    #[must_use]
-    pub fn with_color<C: IntoColor>(mut self, color: C) -> Self {
-        color.into_color(&mut self);
-        self
+    pub fn is_multiline_arrows(&self) -> bool {
+        self.inner.multiline_arrows != 0
     }
 
-    /// Set the display order for the last added label.
+    /// Number of context lines shown around labels.
     ///
-    /// Labels with lower order values are displayed first (closer to the code).
-    /// Labels with the same order are displayed in the order they were added.
+    /// See [`with_context_lines`](Config::with_context_lines).
+    #[inline]
+    pub fn context_lines(&self) -> i32 {
+        self.inner.context_lines
+    }
+
+    /// Number of spaces a tab expands to.
     ///
-    /// Default: `0`
+    /// See [`with_tab_width`](Config::with_tab_width).
     ///
     /// # Example
     /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_label(0..4)
-    ///         .with_message("second")
-    ///         .with_order(1)   // Display this label later
-    ///     .with_title(Level::Error, "Error")
-    ///         .with_label(0..4)
-    ///         .with_message("first")
-    ///         .with_order(-1)  // Display this label first
-    ///     // ...
-    ///     # ;
+    /// # use musubi::Config;
+    /// let config = Config::new().with_tab_width(8);
+    /// assert_eq!(config.tab_width(), 8);
     /// ```
     #[inline]
-    #[must_use]
-    pub fn with_order(self, order: i32) -> Self {
-        // SAFETY: self.ptr is valid
-        unsafe { ffi::mu_order(self.ptr, order) };
-        self
+    pub fn tab_width(&self) -> i32 {
+        self.inner.tab_width
     }
 
-    /// Set the priority for the last added label.
+    /// Custom tab stop columns.
     ///
-    /// Priority controls how overlapping labels are rendered when multiple
-    /// labels cover the same source location. Labels with higher priority
-    /// will be drawn on top, potentially obscuring lower-priority labels.
+    /// See [`with_tab_stops`](Config::with_tab_stops).
+    #[inline]
+    pub fn tab_stops(&self) -> &[i32] {
+        let stops = &self.inner.tab_stops;
+        &stops[..stops.iter().take_while(|&&stop| stop > 0).count()]
+    }
+
+    /// Width limit of source lines, `0` for no limit.
     ///
-    /// Higher values = higher priority = drawn on top.
+    /// See [`with_limit_width`](Config::with_limit_width).
+    #[inline]
+    pub fn limit_width(&self) -> i32 {
+        self.inner.limit_width
+    }
+
+    /// Display width of ambiguous-width characters.
     ///
-    /// Default: `0`
+    /// See [`with_ambi_width`](Config::with_ambi_width).
+    #[inline]
+    pub fn ambi_width(&self) -> i32 {
+        self.inner.ambiwidth
+    }
+
+    /// Whether `**` markup in messages is interpreted.
     ///
-    /// # Example
-    /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_label(0..10)
-    ///         .with_message("low priority")
-    ///         .with_priority(0)   // May be obscured by overlapping labels
-    ///     .with_label(5..15)
-    ///         .with_message("high priority")
-    ///         .with_priority(10)  // Will be drawn on top
-    ///     // ...
-    ///     # ;
-    /// ```
+    /// See [`with_markup`](Config::with_markup).
     #[inline]
-    #[must_use]
-    pub fn with_priority(self, priority: i32) -> Self {
-        // SAFETY: self.ptr is valid
-        unsafe { ffi::mu_priority(self.ptr, priority) };
-        self
+    pub fn is_markup(&self) -> bool {
+        self.inner.markup != 0
     }
 
-    /// Add a help message to the diagnostic.
+    /// Whether `` `code` `` in the title, help and notes is styled.
     ///
-    /// Help messages appear at the end of the diagnostic,
-    /// providing suggestions or additional context.
+    /// See [`with_inline_code`](Config::with_inline_code).
+    #[inline]
+    pub fn is_inline_code(&self) -> bool {
+        self.inner.inline_code != 0
+    }
+
+    /// Whether columns are counted as on NFC-normalized text.
     ///
-    /// Multiple help messages can be added and will be displayed in order.
+    /// See [`with_normalize`](Config::with_normalize).
+    #[inline]
+    pub fn is_normalize(&self) -> bool {
+        self.inner.normalize != 0
+    }
+
+    /// Whether source lines with right-to-left text are bidi isolated.
     ///
-    /// # Example
-    /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_title(Level::Error, "Type error")
-    ///     .with_label(0..4)
-    ///         .with_message("expected String")
-    ///     .with_help("try converting with .to_string()")
-    ///     // ...
-    ///     # ;
-    /// ```
+    /// See [`with_bidi_isolate`](Config::with_bidi_isolate).
     #[inline]
-    #[must_use]
-    pub fn with_help(self, msg: &'a str) -> Self {
-        // SAFETY: self.ptr is valid, msg lifetime is bound to 'a
-        unsafe { ffi::mu_help(self.ptr, msg.into()) };
-        self
+    pub fn is_bidi_isolate(&self) -> bool {
+        self.inner.bidi_isolate != 0
     }
 
-    /// Add a note message to the diagnostic.
+    /// Whether source headers show the location of the primary label.
     ///
-    /// Notes appear at the end of the diagnostic,
-    /// providing additional information or context.
+    /// See [`with_auto_location`](Config::with_auto_location).
+    #[inline]
+    pub fn is_auto_location(&self) -> bool {
+        self.inner.auto_location != 0
+    }
+
+    /// Whether unlabeled source text is drawn as unimportant.
     ///
-    /// Multiple notes can be added and will be displayed in order.
+    /// See [`with_dim_unlabeled`](Config::with_dim_unlabeled).
+    #[inline]
+    pub fn is_dim_unlabeled(&self) -> bool {
+        self.inner.dim_unlabeled != 0
+    }
+
+    /// Whether one-character labels are drawn as a single caret.
     ///
-    /// # Example
-    /// ```rust
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_title(Level::Warning, "Unused variable")
-    ///     .with_label(0..4)
-    ///         .with_message("never used")
-    ///     .with_note("consider prefixing with an underscore: `_code`")
-    ///     // ...
-    ///     # ;
-    /// ```
+    /// See [`with_caret_only`](Config::with_caret_only).
     #[inline]
-    #[must_use]
-    pub fn with_note(self, msg: &'a str) -> Self {
-        // SAFETY: self.ptr is valid, msg lifetime is bound to 'a
-        unsafe { ffi::mu_note(self.ptr, msg.into()) };
-        self
+    pub fn is_caret_only(&self) -> bool {
+        self.inner.caret_only != 0
     }
 
-    /// Render the report to a String.
+    /// Whether header columns are counted from 1.
     ///
-    /// This is a convenience method that captures the rendered output
-    /// into a String instead of writing to stdout or a file.
+    /// See [`with_one_based_columns`](Config::with_one_based_columns).
+    #[inline]
+    pub fn is_one_based_columns(&self) -> bool {
+        self.inner.one_based_columns != 0
+    }
+
+    /// Whether primary labels win ties of priority.
     ///
-    /// # Parameters
-    /// - `cache`: Source cache containing the code to display. Can be:
-    ///   - `&Cache` - A persistent cache with multiple sources
-    ///   - `&str` - A single source string (borrowed)
-    ///   - `(&str, &str)` - Source content and filename
-    ///   - `(&str, &str, i32)` - Source content, filename, and line offset for adjusting displayed line numbers
-    ///   - Custom types implementing `Source` trait
+    /// See [`with_auto_priority`](Config::with_auto_priority).
+    #[inline]
+    pub fn is_auto_priority(&self) -> bool {
+        self.inner.auto_priority != 0
+    }
+
+    /// The maximum number of margin columns for multi-line labels.
     ///
-    /// # Example
-    /// ```rust
-    /// # use musubi::{Report, Level};
-    /// let output = Report::new()
-    ///     .with_title(Level::Error, "Syntax error")
-    ///     .with_label(0..3)
-    ///     .with_message("unexpected token")
-    ///     .render_to_string(("let x", "main.rs"))?;
-    /// println!("{}", output);
-    /// # Ok::<(), std::io::Error>(())
-    /// ```
-    pub fn render_to_string(&mut self, cache: impl Into<RawCache>) -> io::Result<String> {
-        let mut writer = Vec::new();
-        unsafe extern "C" fn string_writer_callback(
-            ud: *mut c_void,
-            data: *const c_char,
-            len: usize,
-        ) -> c_int {
-            // SAFETY: ud is a valid &mut Vec<u8> pointer passed to mu_writer below
-            let writer = unsafe { &mut *(ud as *mut Vec<u8>) };
-            // SAFETY: data and len are provided by C library, guaranteed to be valid
-            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
-            writer.extend_from_slice(slice);
-            ffi::MU_OK
+    /// See [`with_max_depth`](Config::with_max_depth).
+    #[inline]
+    pub fn max_depth(&self) -> i32 {
+        self.inner.max_depth
+    }
+
+    /// Whether a bar in the level color is drawn left of the lines.
+    ///
+    /// See [`with_gutter_bar`](Config::with_gutter_bar).
+    #[inline]
+    pub fn is_gutter_bar(&self) -> bool {
+        self.inner.gutter_bar != 0
+    }
+
+    /// The number of lines helps and notes are collapsed to.
+    ///
+    /// See [`with_collapse_notes`](Config::with_collapse_notes).
+    #[inline]
+    pub fn collapse_notes(&self) -> i32 {
+        self.inner.collapse_notes
+    }
+
+    /// The columns each level of related reports is indented by.
+    ///
+    /// See [`with_related_indent`](Config::with_related_indent).
+    #[inline]
+    pub fn related_indent(&self) -> usize {
+        self.related_indent
+    }
+
+    /// Whether connectors are drawn to related reports.
+    ///
+    /// See [`with_related_connectors`](Config::with_related_connectors).
+    #[inline]
+    pub fn is_related_connectors(&self) -> bool {
+        self.related_connectors
+    }
+
+    /// The separator between digit groups of line numbers.
+    ///
+    /// See [`with_line_no_separator`](Config::with_line_no_separator).
+    #[inline]
+    pub fn line_no_separator(&self) -> &'a str {
+        let sep = self.inner.line_no_sep;
+        if sep.p.is_null() {
+            return "";
         }
-        // SAFETY: self.ptr is valid, callback has correct signature, writer is valid for this scope
-        unsafe {
-            ffi::mu_writer(
-                self.ptr,
-                Some(string_writer_callback),
-                &mut writer as *mut Vec<u8> as *mut c_void,
-            )
-        };
-        self.render(cache).map(|_| {
-            String::from_utf8(writer)
-                .unwrap_or_else(|e| String::from_utf8_lossy(&e.into_bytes()).into_owned())
-        })
+        // SAFETY: the slice is set from a `&'a str` by with_line_no_separator
+        unsafe { std::str::from_utf8_unchecked(sep.into()) }
     }
 
-    /// Render the report directly to stdout.
+    /// Where labels attach to their spans.
     ///
-    /// This is the most efficient way to display diagnostics,
-    /// writing directly to the terminal without intermediate buffering.
+    /// See [`with_label_attach`](Config::with_label_attach).
+    #[inline]
+    pub fn label_attach(&self) -> LabelAttach {
+        LabelAttach::from_ffi(self.inner.label_attach)
+    }
+
+    /// How labels overlapping on one line are drawn.
     ///
-    /// # Parameters
-    /// - `cache`: Source cache or source content. Can be `&Cache`, `&str`,
-    ///   `(&str, &str)`, `(&str, &str, i32)`, or custom `Source` implementations.
-    ///   The third element (if present) is a line offset for adjusting displayed line numbers.
+    /// See [`with_overlap_strategy`](Config::with_overlap_strategy).
+    #[inline]
+    pub fn overlap_strategy(&self) -> Overlap {
+        Overlap::from_ffi(self.inner.overlap)
+    }
+
+    /// How the closing border of reports is drawn.
+    ///
+    /// See [`with_footer`](Config::with_footer).
+    #[inline]
+    pub fn footer(&self) -> Footer {
+        Footer::from_ffi(self.inner.footer)
+    }
+
+    /// The order of the sources in a report.
+    ///
+    /// See [`with_source_order`](Config::with_source_order).
+    #[inline]
+    pub fn source_order(&self) -> SourceOrder {
+        SourceOrder::from_ffi(self.inner.source_order)
+    }
+
+    /// How span positions are interpreted.
+    ///
+    /// See [`with_index_type`](Config::with_index_type).
     ///
     /// # Example
-    /// ```no_run
-    /// # use musubi::{Report, Level};
-    /// Report::new()
-    ///     .with_title(Level::Error, "Error message")
-    ///     .with_label(0..5)
-    ///     .render_to_stdout(("let x = 42;", "main.rs"))?;
-    /// # Ok::<(), std::io::Error>(())
+    /// ```rust
+    /// # use musubi::{Config, IndexType};
+    /// let config = Config::new().with_index_type(IndexType::Byte);
+    /// assert_eq!(config.index_type(), IndexType::Byte);
     /// ```
-    pub fn render_to_stdout(&mut self, cache: impl Into<RawCache>) -> io::Result<()> {
-        unsafe extern "C" fn stdout_writer_callback(
-            _ud: *mut c_void,
-            data: *const c_char,
-            len: usize,
-        ) -> c_int {
-            // SAFETY: data and len are provided by C library, guaranteed to be valid
-            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
-            let mut stdout = io::stdout();
-            if stdout.write_all(slice).is_ok() && stdout.flush().is_ok() {
-                ffi::MU_OK
-            } else {
-                ffi::MU_ERRPARAM
-            }
-        }
+    #[inline]
+    pub fn index_type(&self) -> IndexType {
+        IndexType::from_ffi(self.inner.index_type)
+    }
 
-        // SAFETY: self.ptr is valid, callback has correct signature
-        unsafe { ffi::mu_writer(self.ptr, Some(stdout_writer_callback), ptr::null_mut()) };
-        self.render(cache)
+    /// The character set used for drawing.
+    ///
+    /// Returns a copy of the custom set passed to
+    /// [`with_char_set`](Config::with_char_set), or the built-in set
+    /// otherwise.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{CharSet, Config};
+    /// let config = Config::new().with_char_set_ascii();
+    /// assert_eq!(config.char_set().hbar, '-');
+    ///
+    /// let mut cs = config.char_set();
+    /// cs.hbar = '=';
+    /// assert_eq!(config.with_char_set(&cs).char_set().hbar, '=');
+    /// ```
+    pub fn char_set(&self) -> CharSet {
+        match self.char_set {
+            Some(char_set) => *char_set,
+            None => self.inner.char_set.into(),
+        }
     }
 
-    /// Render the report to any type implementing `Write`.
+    /// Whether any color provider is set.
     ///
-    /// This allows rendering to files, buffers, or any custom writer.
+    /// See [`with_color_default`](Config::with_color_default) and
+    /// [`with_color`](Config::with_color).
+    #[inline]
+    pub fn is_color_enabled(&self) -> bool {
+        self.inner.color.is_some()
+    }
+
+    /// Create a new config with the fields set in `patch` overridden.
     ///
-    /// # Parameters
-    /// - `writer`: Mutable reference to any type implementing `std::io::Write`
-    /// - `cache`: Source cache or source content. Can be `&Cache`, `&str`,
-    ///   `(&str, &str)`, `(&str, &str, i32)`, or custom `Source` implementations.
-    ///   The third element (if present) is a line offset for adjusting displayed line numbers.
+    /// Fields left as [`None`] in the patch keep their value from `self`,
+    /// so a base config can be layered with user overrides (e.g. from
+    /// command-line flags).
     ///
     /// # Example
     /// ```rust
-    /// # use musubi::{Report, Level};
-    /// # use std::io::Write;
-    /// let mut buffer = Vec::new();
-    /// Report::new()
-    ///     .with_title(Level::Warning, "Deprecated")
-    ///     .with_label(0..3)
-    ///     .render_to_writer(&mut buffer, "let x = 1;")?;
-    /// assert!(!buffer.is_empty());
-    /// # Ok::<(), std::io::Error>(())
+    /// # use musubi::{Config, ConfigPatch};
+    /// let base = Config::new().with_compact(true).with_tab_width(8);
+    /// let patch = ConfigPatch {
+    ///     tab_width: Some(2),
+    ///     color: Some(false),
+    ///     ..Default::default()
+    /// };
+    /// let config = base.merge(&patch);
+    /// assert!(config.is_compact());
+    /// assert_eq!(config.tab_width(), 2);
+    /// assert!(!config.is_color_enabled());
     /// ```
-    pub fn render_to_writer<'b, W: Write>(
-        &'b mut self,
-        writer: &'b mut W,
-        cache: impl Into<RawCache>,
-    ) -> io::Result<()> {
-        struct WriterWrapper<'a, W: Write> {
-            writer: &'a mut W,
-            report: *mut Report<'a>,
+    pub fn merge(&self, patch: &ConfigPatch<'a>) -> Self {
+        let mut config = self.clone();
+        if let Some(enabled) = patch.compact {
+            config = config.with_compact(enabled);
+        }
+        if let Some(enabled) = patch.cross_gap {
+            config = config.with_cross_gap(enabled);
+        }
+        if let Some(enabled) = patch.underlines {
+            config = config.with_underlines(enabled);
+        }
+        if let Some(enabled) = patch.minimise_crossings {
+            config = config.with_minimise_crossings(enabled);
+        }
+        if let Some(enabled) = patch.align_messages {
+            config = config.with_align_messages(enabled);
+        }
+        if let Some(enabled) = patch.multiline_arrows {
+            config = config.with_multiline_arrows(enabled);
+        }
+        if let Some(lines) = patch.context_lines {
+            config = config.with_context_lines(lines);
+        }
+        if let Some(width) = patch.tab_width {
+            config = config.with_tab_width(width);
         }
+        if let Some(stops) = patch.tab_stops {
+            config = config.with_tab_stops(stops);
+        }
+        if let Some(width) = patch.limit_width {
+            config = config.with_limit_width(width);
+        }
+        if let Some(width) = patch.ambi_width {
+            config = config.with_ambi_width(width);
+        }
+        if let Some(enabled) = patch.markup {
+            config = config.with_markup(enabled);
+        }
+        if let Some(enabled) = patch.inline_code {
+            config = config.with_inline_code(enabled);
+        }
+        if let Some(enabled) = patch.normalize {
+            config = config.with_normalize(enabled);
+        }
+        if let Some(enabled) = patch.bidi_isolate {
+            config = config.with_bidi_isolate(enabled);
+        }
+        if let Some(enabled) = patch.auto_location {
+            config = config.with_auto_location(enabled);
+        }
+        if let Some(enabled) = patch.dim_unlabeled {
+            config = config.with_dim_unlabeled(enabled);
+        }
+        if let Some(enabled) = patch.caret_only {
+            config = config.with_caret_only(enabled);
+        }
+        if let Some(enabled) = patch.one_based_columns {
+            config = config.with_one_based_columns(enabled);
+        }
+        if let Some(enabled) = patch.auto_priority {
+            config = config.with_auto_priority(enabled);
+        }
+        if let Some(depth) = patch.max_depth {
+            config = config.with_max_depth(depth);
+        }
+        if let Some(enabled) = patch.gutter_bar {
+            config = config.with_gutter_bar(enabled);
+        }
+        if let Some(lines) = patch.collapse_notes {
+            config = config.with_collapse_notes(lines);
+        }
+        if let Some(columns) = patch.related_indent {
+            config = config.with_related_indent(columns);
+        }
+        if let Some(enabled) = patch.related_connectors {
+            config = config.with_related_connectors(enabled);
+        }
+        if let Some(sep) = patch.line_no_separator {
+            config = config.with_line_no_separator(sep);
+        }
+        if let Some(attach) = patch.label_attach {
+            config = config.with_label_attach(attach);
+        }
+        if let Some(index_type) = patch.index_type {
+            config = config.with_index_type(index_type);
+        }
+        if let Some(overlap) = patch.overlap_strategy {
+            config = config.with_overlap_strategy(overlap);
+        }
+        if let Some(footer) = patch.footer {
+            config = config.with_footer(footer);
+        }
+        if let Some(order) = patch.source_order {
+            config = config.with_source_order(order);
+        }
+        if let Some(char_set) = patch.char_set {
+            config = config.with_char_set(char_set);
+        }
+        match patch.color {
+            Some(true) => config.with_color_default(),
+            Some(false) => config.with_color_disabled(),
+            None => config,
+        }
+    }
+}
 
-        unsafe extern "C" fn writer_callback<W: Write>(
-            ud: *mut c_void,
-            data: *const c_char,
-            len: usize,
-        ) -> c_int {
-            // SAFETY: ud is a valid WriterWrapper<W> pointer passed to mu_writer below
-            let w = unsafe { &mut *(ud as *mut WriterWrapper<W>) };
-            // SAFETY: data and len are provided by C library, guaranteed to be valid
-            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
-            match w.writer.write_all(slice) {
-                Ok(_) => ffi::MU_OK,
-                Err(e) => {
-                    // SAFETY: report pointer is setted below, and this function only called during render()
-                    unsafe { &mut *w.report }.src_err = Some(e);
-                    ffi::MU_ERR_WRITER
-                }
-            }
+/// A set of optional overrides for a [`Config`].
+///
+/// Each field corresponds to a `Config::with_*` method; [`None`] means
+/// "not set". Apply it with [`Config::merge`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfigPatch<'a> {
+    /// See [`Config::with_compact`].
+    pub compact: Option<bool>,
+    /// See [`Config::with_cross_gap`].
+    pub cross_gap: Option<bool>,
+    /// See [`Config::with_underlines`].
+    pub underlines: Option<bool>,
+    /// See [`Config::with_minimise_crossings`].
+    pub minimise_crossings: Option<bool>,
+    /// See [`Config::with_align_messages`].
+    pub align_messages: Option<bool>,
+    /// See [`Config::with_multiline_arrows`].
+    pub multiline_arrows: Option<bool>,
+    /// See [`Config::with_context_lines`].
+    pub context_lines: Option<i32>,
+    /// See [`Config::with_tab_width`].
+    pub tab_width: Option<i32>,
+    /// See [`Config::with_tab_stops`].
+    pub tab_stops: Option<&'a [i32]>,
+    /// See [`Config::with_limit_width`].
+    pub limit_width: Option<i32>,
+    /// See [`Config::with_ambi_width`].
+    pub ambi_width: Option<i32>,
+    /// See [`Config::with_markup`].
+    pub markup: Option<bool>,
+    /// See [`Config::with_inline_code`].
+    pub inline_code: Option<bool>,
+    /// See [`Config::with_normalize`].
+    pub normalize: Option<bool>,
+    /// See [`Config::with_bidi_isolate`].
+    pub bidi_isolate: Option<bool>,
+    /// See [`Config::with_auto_location`].
+    pub auto_location: Option<bool>,
+    /// See [`Config::with_dim_unlabeled`].
+    pub dim_unlabeled: Option<bool>,
+    /// See [`Config::with_caret_only`].
+    pub caret_only: Option<bool>,
+    /// See [`Config::with_one_based_columns`].
+    pub one_based_columns: Option<bool>,
+    /// See [`Config::with_auto_priority`].
+    pub auto_priority: Option<bool>,
+    /// See [`Config::with_max_depth`].
+    pub max_depth: Option<i32>,
+    /// See [`Config::with_gutter_bar`].
+    pub gutter_bar: Option<bool>,
+    /// See [`Config::with_collapse_notes`].
+    pub collapse_notes: Option<i32>,
+    /// See [`Config::with_related_indent`].
+    pub related_indent: Option<usize>,
+    /// See [`Config::with_related_connectors`].
+    pub related_connectors: Option<bool>,
+    /// See [`Config::with_line_no_separator`].
+    pub line_no_separator: Option<&'a str>,
+    /// See [`Config::with_label_attach`].
+    pub label_attach: Option<LabelAttach>,
+    /// See [`Config::with_index_type`].
+    pub index_type: Option<IndexType>,
+    /// See [`Config::with_overlap_strategy`].
+    pub overlap_strategy: Option<Overlap>,
+    /// See [`Config::with_footer`].
+    pub footer: Option<Footer>,
+    /// See [`Config::with_source_order`].
+    pub source_order: Option<SourceOrder>,
+    /// See [`Config::with_char_set`].
+    pub char_set: Option<&'a CharSet>,
+    /// `true` for [`Config::with_color_default`], `false` for
+    /// [`Config::with_color_disabled`].
+    pub color: Option<bool>,
+}
+
+/// Trait for types that can be added to a cache.
+///
+/// This trait is automatically implemented for common types:
+/// - `&str` - Borrowed string content
+/// - `String` - Owned string content (stored in cache)
+/// - `OwnedSource<S>` - Any type implementing `AsRef<[u8]>` (`Vec<u8>`, `Box<[u8]>`, etc.)
+/// - `FileSource` - A file, read when it is first rendered
+/// - Tuples with filename: `(&str, &str)`, `(String, &Path)`, `(&str, &PathBuf)`,
+///   or any reference to a type implementing `AsRef<Path>`
+/// - Custom `Source` trait implementations
+///
+/// Filenames are stored as the bytes of the path, so non-UTF-8 paths are
+/// kept losslessly. Like [`Path::display`], rendering to a `String` replaces
+/// the invalid sequences with `U+FFFD`, while byte sinks like
+/// [`Report::render_to_writer`] receive the name as-is.
+///
+/// Users typically don't need to implement this trait directly.
+pub trait AddToCache {
+    /// Add this source to the cache.
+    ///
+    /// # Parameters
+    /// - `cache`: Mutable reference to the C cache pointer
+    ///
+    /// # Returns
+    /// Pointer to the created `mu_Source` in the C library
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source;
+}
+
+/// Wrapper for owned source content.
+///
+/// `OwnedSource` wraps any type that can be viewed as bytes (`AsRef<[u8]>`),
+/// such as `Vec<u8>`, `Box<[u8]>`, or custom buffer types. The content is
+/// stored directly in the cache's internal memory managed by the C library.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Cache, OwnedSource, Report, Level};
+/// let buffer = vec![b'c', b'o', b'd', b'e'];
+/// let cache = Cache::new()
+///     .with_source((OwnedSource::new(buffer), "data.bin"));
+///
+/// let mut report = Report::new()
+///     .with_title(Level::Error, "Error in binary data")
+///     .with_label(0..4)
+///     .render_to_string(&cache)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct OwnedSource<S>(S);
+
+impl<S: AsRef<[u8]>> From<S> for OwnedSource<S> {
+    #[inline]
+    fn from(value: S) -> Self {
+        Self(value)
+    }
+}
+
+impl<S: AsRef<[u8]>> OwnedSource<S> {
+    /// Create a new owned source from any type implementing `AsRef<[u8]>`.
+    #[inline]
+    pub fn new(owned: S) -> Self {
+        owned.into()
+    }
+}
+
+impl<S: AsRef<[u8]>> AddToCache for OwnedSource<S> {
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
+        #[repr(C)]
+        struct OwnedSource<S> {
+            base: ffi::mu_Source,
+            owned: S,
         }
-        #[allow(clippy::unnecessary_cast)]
-        let mut wrapper = WriterWrapper {
-            writer,
-            report: self as *mut Report<'a> as *mut Report<'b>,
-        };
-        // SAFETY: mu_writer expects a valid Report pointer and writer callback
+        // SAFETY: mu_addmemory initializes the cache and source correctly
+        let src =
+            unsafe { ffi::mu_addsource(cache, size_of::<OwnedSource<S>>(), Default::default()) };
+        let owned_src = src as *mut OwnedSource<S>;
+        // SAFETY: src is allocated by mu_addsource above with room for an
+        // OwnedSource, and its owned field is uninitialized memory
         unsafe {
-            ffi::mu_writer(
-                self.ptr,
-                Some(writer_callback::<W>),
-                &mut wrapper as *mut _ as *mut c_void,
-            );
+            std::ptr::addr_of_mut!((*owned_src).owned).write(self.0);
+            (*src).init = Some(init_fn::<S>);
+            (*src).free = Some(free_fn::<S>);
+            (*src).get_line = Some(get_line_fn::<S>);
         }
-        self.render(cache)
+
+        unsafe extern "C" fn init_fn<S: AsRef<[u8]>>(src: *mut ffi::mu_Source) -> c_int {
+            // SAFETY: src is a valid OwnedSource<S> pointer created in into_source below
+            let src = unsafe { &mut *(src as *mut OwnedSource<S>) };
+            // SAFETY: calling mu_updatelines is safe
+            unsafe { ffi::mu_updatelines(&mut src.base, src.owned.as_ref().into()) };
+            ffi::MU_OK
+        }
+
+        unsafe extern "C" fn free_fn<S: AsRef<[u8]>>(src: *mut ffi::mu_Source) {
+            let ud = src as *mut OwnedSource<S>;
+            // SAFETY: ud was allocated by mu_addsource and is valid here
+            // after this call, src will be freed by C library.
+            unsafe { std::ptr::drop_in_place(ud) };
+        }
+
+        unsafe extern "C" fn get_line_fn<S: AsRef<[u8]>>(
+            src: *mut ffi::mu_Source,
+            line_no: c_uint,
+        ) -> ffi::mu_Slice {
+            // SAFETY: src is a valid, loaded source, whose lines are only
+            // read here
+            let line = unsafe { *ffi::mu_getline(src, line_no) };
+            // SAFETY: src is a valid OwnedSource<S> pointer, only read once
+            // loaded
+            let src = unsafe { &*(src as *const OwnedSource<S>) };
+            src.owned.as_ref()[line.byte_offset as usize..][..line.byte_len as usize].into()
+        }
+
+        src
+    }
+}
+
+impl AddToCache for String {
+    #[inline]
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
+        OwnedSource::new(self).add_to_cache(cache)
+    }
+}
+
+impl AddToCache for &str {
+    #[inline]
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
+        // SAFETY: mu_addmemory initializes the cache and source correctly
+        unsafe { ffi::mu_addmemory(cache, self.into(), Default::default()) }
+    }
+}
+
+impl<S: Source> AddToCache for S {
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
+        #[repr(C)]
+        struct BoxedSource<S: Source> {
+            base: ffi::mu_Source,
+            rust_obj: S,
+            line: ffi::mu_Line,
+            err: Option<io::Error>,
+        }
+
+        // SAFETY: mu_addsource initializes the cache and source correctly,
+        // with room for a BoxedSource whose other fields are uninitialized
+        let src = unsafe {
+            let src = ffi::mu_addsource(cache, size_of::<BoxedSource<S>>(), Default::default());
+            let src = src as *mut BoxedSource<S>;
+            std::ptr::addr_of_mut!((*src).rust_obj).write(self);
+            std::ptr::addr_of_mut!((*src).err).write(None);
+            &mut *src
+        };
+        src.base.init = Some(init_fn::<S>);
+        src.base.free = Some(free_fn::<S>);
+        src.base.get_line = Some(get_line_fn::<S>);
+        src.base.get_line_info = Some(get_line_info_fn::<S>);
+        src.base.line_for_chars = Some(line_for_chars_fn::<S>);
+        src.base.line_for_bytes = Some(line_for_bytes_fn::<S>);
+
+        extern "C" fn init_fn<S: Source>(src: *mut ffi::mu_Source) -> c_int {
+            // SAFETY: src is a valid UdSource<S> pointer created in into_source below
+            let src = unsafe { &mut (*(src as *mut BoxedSource<S>)) };
+            match src.rust_obj.init() {
+                Ok(_) => 0,
+                Err(err) => {
+                    // SAFETY: report pointer is valid for the lifetime of the source
+                    src.err = Some(err);
+                    ffi::MU_ERR_SRCINIT
+                }
+            }
+        }
+
+        unsafe extern "C" fn free_fn<S: Source>(src: *mut ffi::mu_Source) {
+            let ud = src as *mut BoxedSource<S>;
+            // SAFETY: ud was allocated by mu_addsource and is valid here
+            // after this call, src will be freed by C library.
+            unsafe { std::ptr::drop_in_place(ud) };
+        }
+
+        extern "C" fn get_line_fn<S: Source>(
+            src: *mut ffi::mu_Source,
+            line_no: c_uint,
+        ) -> ffi::mu_Slice {
+            // SAFETY: src is a valid UdSource<S> pointer
+            let src = unsafe { &mut *(src as *mut BoxedSource<S>) };
+            src.rust_obj.get_line(line_no as usize).into()
+        }
+
+        extern "C" fn get_line_info_fn<S: Source>(
+            src: *mut ffi::mu_Source,
+            line_no: c_uint,
+        ) -> *const ffi::mu_Line {
+            // SAFETY: src is a valid UdSource<S> pointer
+            let src = unsafe { &mut *(src as *mut BoxedSource<S>) };
+            let line_info = src.rust_obj.get_line_info(line_no as usize);
+            src.line = line_info.into();
+            &src.line
+        }
+
+        extern "C" fn line_for_chars_fn<S: Source>(
+            src: *mut ffi::mu_Source,
+            char_pos: usize,
+            out_line: *mut *const ffi::mu_Line,
+        ) -> c_uint {
+            // SAFETY: src is a valid UdSource<S> pointer
+            let src = unsafe { &mut *(src as *mut BoxedSource<S>) };
+            let (line_no, line_info) = src.rust_obj.line_for_chars(char_pos);
+            if !out_line.is_null() {
+                src.line = line_info.into();
+                // SAFETY: out_line is checked
+                unsafe { *out_line = &src.line };
+            }
+            line_no as c_uint
+        }
+
+        extern "C" fn line_for_bytes_fn<S: Source>(
+            src: *mut ffi::mu_Source,
+            byte_pos: usize,
+            out_line: *mut *const ffi::mu_Line,
+        ) -> c_uint {
+            // SAFETY: src is a valid UdSource<S> pointer
+            let src = unsafe { &mut *(src as *mut BoxedSource<S>) };
+            let (line_no, line_info) = src.rust_obj.line_for_bytes(byte_pos);
+            if !out_line.is_null() {
+                src.line = line_info.into();
+                // SAFETY: out_line is checked
+                unsafe { *out_line = &src.line };
+            }
+            line_no as c_uint
+        }
+
+        &mut src.base
+    }
+}
+
+/// The bytes of a source name, kept as-is even if they are not UTF-8 for
+/// lookups; the renderer shows invalid bytes as U+FFFD.
+#[inline]
+fn name_slice<P: AsRef<Path> + ?Sized>(name: &P) -> ffi::mu_Slice {
+    name.as_ref().as_os_str().as_encoded_bytes().into()
+}
+
+impl<S: AddToCache, P: AsRef<Path> + ?Sized> AddToCache for (S, &P) {
+    #[inline]
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
+        let src = self.0.add_to_cache(cache);
+        // SAFETY: src is a valid mu_Source pointer
+        unsafe { (*src).name = name_slice(self.1) };
+        src
+    }
+}
+
+impl<S: AddToCache, P: AsRef<Path> + ?Sized> AddToCache for (S, &P, i32) {
+    #[inline]
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
+        let src = self.0.add_to_cache(cache);
+        // SAFETY: src is a valid mu_Source pointer
+        unsafe {
+            (*src).name = name_slice(self.1);
+            (*src).line_no_offset = self.2
+        };
+        src
+    }
+}
+
+/// A source that a [`SharedCache`] may read from several threads at once.
+///
+/// Sources are [`Send`] and [`Sync`], and only read their state once
+/// loaded: `&str`, `String`, [`FileSource`], and [`OwnedSource`]s and
+/// [`TransformSource`]s whose contents are `Send` and `Sync`, with or
+/// without a name. Custom [`Source`]s are not, as the cache writes the
+/// line info they return into their shared state.
+///
+/// This trait is sealed; add sources with
+/// [`Cache::with_shared_source`].
+pub trait SharedSource: AddToCache + Send + Sync + private::Sealed {}
+
+mod private {
+    /// Keeps [`SharedSource`](super::SharedSource) to the sources of this
+    /// crate.
+    pub trait Sealed {}
+}
+
+impl private::Sealed for &str {}
+impl SharedSource for &str {}
+impl private::Sealed for String {}
+impl SharedSource for String {}
+impl private::Sealed for FileSource {}
+impl SharedSource for FileSource {}
+impl<S: AsRef<[u8]> + Send + Sync> private::Sealed for OwnedSource<S> {}
+impl<S: AsRef<[u8]> + Send + Sync> SharedSource for OwnedSource<S> {}
+impl<F> private::Sealed for TransformSource<F> where
+    F: FnMut(&str) -> Vec<(std::ops::Range<usize>, String)> + Send + Sync
+{
+}
+impl<F> SharedSource for TransformSource<F> where
+    F: FnMut(&str) -> Vec<(std::ops::Range<usize>, String)> + Send + Sync
+{
+}
+impl<S: SharedSource, P: AsRef<Path> + Sync + ?Sized> private::Sealed for (S, &P) {}
+impl<S: SharedSource, P: AsRef<Path> + Sync + ?Sized> SharedSource for (S, &P) {}
+impl<S: SharedSource, P: AsRef<Path> + Sync + ?Sized> private::Sealed for (S, &P, i32) {}
+impl<S: SharedSource, P: AsRef<Path> + Sync + ?Sized> SharedSource for (S, &P, i32) {}
+
+/// Internal representation of a cache for rendering.
+///
+/// This enum manages the lifetime of the underlying C cache pointer:
+/// - `Owned`: Cache was created for a single render and will be freed
+/// - `Borrowed`: Cache is owned by user code and should not be freed
+///
+/// Users typically don't interact with this type directly; it's used
+/// internally by the `render_to_*` methods.
+pub enum RawCache {
+    /// Temporary cache that will be freed when dropped
+    Owned(*mut ffi::mu_Cache),
+    /// Borrowed cache that remains owned by the caller
+    Borrowed(*mut ffi::mu_Cache),
+}
+
+impl Drop for RawCache {
+    #[inline]
+    fn drop(&mut self) {
+        match self {
+            RawCache::Owned(ptr) => {
+                if !ptr.is_null() {
+                    // SAFETY: mu_delcache frees the cache allocated by mu_addmemory
+                    unsafe { ffi::mu_delcache(*ptr) };
+                }
+            }
+            RawCache::Borrowed(_) => {
+                // Do nothing for borrowed cache
+            }
+        }
+    }
+}
+
+impl RawCache {
+    #[inline]
+    fn as_ptr(&self) -> *mut ffi::mu_Cache {
+        match self {
+            RawCache::Owned(ptr) => *ptr,
+            RawCache::Borrowed(ptr) => *ptr,
+        }
+    }
+}
+
+impl<S: AddToCache> From<S> for RawCache {
+    #[inline]
+    fn from(value: S) -> RawCache {
+        let mut cache = ptr::null_mut();
+        value.add_to_cache(&mut cache);
+        RawCache::Owned(cache)
+    }
+}
+
+/// A cache of diagnostic sources.
+///
+/// `Cache` manages multiple source files and their associated data,
+/// allowing for efficient multi-source diagnostics. It can be reused
+/// across multiple render operations.
+///
+/// # Source Lifetime Management
+///
+/// The cache automatically handles different source types:
+/// - **Borrowed sources** (`&str`): Content must remain valid until rendering completes
+/// - **Owned sources** (`String`, `Vec<u8>`, etc.): Content is stored in the cache's
+///   internal memory managed by the C library
+///
+/// # Single Source Convenience
+///
+/// For simple single-source diagnostics, you can pass sources directly to
+/// rendering methods without creating an explicit `Cache`. See [`Report::render_to_string()`]
+/// for examples.
+///
+/// # Example
+/// ```rust
+/// use musubi::{Cache, Report, Level};
+///
+/// let cache = Cache::new()
+///     .with_source(("let x = 42;", "main.rs"))        // Source 0
+///     .with_source(("fn foo() {}", "lib.rs"));        // Source 1
+///
+/// let mut report = Report::new()
+///     .with_title(Level::Error, "Multiple files")
+///     .with_label((0..3, 0))   // Label in main.rs
+///     .with_message("here")
+///     .with_label((3..6, 1))   // Label in lib.rs
+///     .with_message("and here");
+///
+/// report.render_to_stdout(&cache)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Source Keys
+///
+/// Sources added from several places are easier to find by a key of your
+/// own than by position. A cache created with [`Cache::keyed`] maps keys of
+/// type `K` to source IDs; see [`with_source_keyed`](Cache::with_source_keyed).
+pub struct Cache<K = ()> {
+    inner: *mut ffi::mu_Cache,
+    keys: HashMap<K, usize>,
+    anchors: Vec<(ffi::mu_Id, Weak<AtomicUsize>)>,
+    /// ID of the first source not added as a `SharedSource`
+    local: Option<usize>,
+}
+
+impl Default for Cache {
+    #[inline]
+    fn default() -> Self {
+        Cache::keyed()
+    }
+}
+
+impl<K> From<&Cache<K>> for RawCache {
+    #[inline]
+    fn from(cache: &Cache<K>) -> RawCache {
+        RawCache::Borrowed(cache.inner)
+    }
+}
+
+/// A loaded cache shared by threads rendering in parallel.
+///
+/// Created by [`Cache::into_shared`], which loads every source first, so
+/// renders only read the cache, and only takes [`SharedSource`]s, which
+/// can be read from several threads. Clones share the same cache, and derefs to
+/// it for queries like [`Cache::excerpt`].
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Cache, Config, Level, Report};
+/// let cache = Cache::new()
+///     .with_shared_source(("let x = 42;", "main.rs"))
+///     .into_shared()?;
+///
+/// let outputs: Vec<String> = std::thread::scope(|s| {
+///     let workers: Vec<_> = (0..4)
+///         .map(|i| {
+///             let cache = cache.clone();
+///             s.spawn(move || {
+///                 let title = format!("error {i}");
+///                 Report::new()
+///                     .with_config(Config::new().with_color_disabled())
+///                     .with_title(Level::Error, &title)
+///                     .with_label(4..5)
+///                     .render_to_string(&cache)
+///             })
+///         })
+///         .collect();
+///     workers.into_iter().map(|w| w.join().unwrap().unwrap()).collect()
+/// });
+/// assert!(outputs[3].starts_with("Error: error 3"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct SharedCache<K = ()>(Arc<Cache<K>>);
+
+// SAFETY: every source was loaded, and is a SharedSource, which is Send and
+// Sync and only read once loaded
+unsafe impl<K: Send + Sync> Send for SharedCache<K> {}
+// SAFETY: see above
+unsafe impl<K: Send + Sync> Sync for SharedCache<K> {}
+
+impl<K> Clone for SharedCache<K> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K> std::ops::Deref for SharedCache<K> {
+    type Target = Cache<K>;
+
+    #[inline]
+    fn deref(&self) -> &Cache<K> {
+        &self.0
+    }
+}
+
+impl<K> From<&SharedCache<K>> for RawCache {
+    #[inline]
+    fn from(cache: &SharedCache<K>) -> RawCache {
+        RawCache::Borrowed(cache.0.inner)
+    }
+}
+
+impl From<()> for RawCache {
+    /// No cache: render with the sources attached to the report.
+    #[inline]
+    fn from(_: ()) -> RawCache {
+        RawCache::Borrowed(ptr::null_mut())
+    }
+}
+
+impl Cache {
+    /// Create a new empty cache.
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<K: Eq + Hash> Cache<K> {
+    /// Add a source to the cache under `key`.
+    ///
+    /// The source gets the next ID like with
+    /// [`with_source`](Cache::with_source); look it up later with
+    /// [`id`](Cache::id), or make label spans with [`span`](Cache::span).
+    /// Adding another source under the same key makes the key refer to the
+    /// new source.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Config, Level, Report};
+    /// #[derive(PartialEq, Eq, Hash)]
+    /// enum File {
+    ///     Prelude,
+    ///     Main,
+    /// }
+    ///
+    /// let cache = Cache::keyed()
+    ///     .with_source_keyed(File::Prelude, ("use std::io;", "prelude.rs"))
+    ///     .with_source_keyed(File::Main, ("let x = 42;", "main.rs"));
+    /// assert_eq!(cache.id(&File::Main), Some(1));
+    ///
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label(cache.span(4..5, &File::Main))
+    ///     .render_to_string(&cache)?;
+    /// assert!(output.contains("main.rs:1:5"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn with_source_keyed<S: AddToCache>(mut self, key: K, content: S) -> Self {
+        let id = self.len();
+        self = self.with_source(content);
+        self.keys.insert(key, id);
+        self
+    }
+
+    /// The ID of the source added under `key`.
+    ///
+    /// See [`with_source_keyed`](Cache::with_source_keyed).
+    #[inline]
+    pub fn id<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.keys.get(key).copied()
+    }
+
+    /// A span over `range` in the source added under `key`.
+    ///
+    /// See [`with_source_keyed`](Cache::with_source_keyed).
+    ///
+    /// # Panics
+    ///
+    /// Panics if no source was added under `key`.
+    #[inline]
+    pub fn span<Q>(&self, range: std::ops::Range<usize>, key: &Q) -> LabelSpan
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let id = self.id(key).expect("no source added under this key");
+        LabelSpan::new(range, id)
+    }
+}
+
+impl<K> Cache<K> {
+    /// Create a new empty cache whose sources may be added under keys of
+    /// type `K`.
+    ///
+    /// See [`with_source_keyed`](Cache::with_source_keyed).
+    #[inline]
+    pub fn keyed() -> Self {
+        Cache {
+            inner: ptr::null_mut(),
+            keys: HashMap::new(),
+            anchors: Vec::new(),
+            local: None,
+        }
+    }
+
+    /// Get the underlying C cache pointer.
+    ///
+    /// The pointer is null until the first source is added, and is
+    /// invalidated when the cache is dropped or another source is added.
+    /// See [`sys`] for the raw API.
+    #[inline]
+    pub fn as_raw(&self) -> *mut sys::mu_Cache {
+        self.inner
+    }
+
+    /// The number of sources in the cache.
+    #[inline]
+    pub fn len(&self) -> usize {
+        // SAFETY: inner is null or a valid cache
+        unsafe { ffi::mu_sourcecount(self.inner) as usize }
+    }
+
+    /// Whether the cache has no sources.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The ID of the first source named `name`.
+    ///
+    /// Names are compared as paths are stored, byte by byte, so
+    /// `"./main.rs"` does not match `"main.rs"`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Cache;
+    /// # use std::path::Path;
+    /// let cache = Cache::new()
+    ///     .with_source(("fn main() {}", "main.rs"))
+    ///     .with_source(("mod lib;", Path::new("src/lib.rs")));
+    /// assert_eq!(cache.len(), 2);
+    /// assert_eq!(cache.source_id("src/lib.rs"), Some(1));
+    /// assert_eq!(cache.source_id("lib.rs"), None);
+    /// ```
+    pub fn source_id(&self, name: impl AsRef<Path>) -> Option<usize> {
+        let name = name.as_ref().as_os_str().as_encoded_bytes();
+        (0..self.len()).find(|&id| {
+            // SAFETY: inner is a valid cache, and id is in range
+            let src = unsafe { ffi::mu_getsource(self.inner, id as c_uint) };
+            // SAFETY: src is a valid source, whose name is set when added
+            let src_name: &[u8] = unsafe { (*src).name }.into();
+            src_name == name
+        })
+    }
+
+    /// Iterate over the sources, as `(id, name, len_bytes, line_count)`.
+    ///
+    /// Each source is loaded if it was not loaded yet, to count its bytes
+    /// and lines; a source that fails to load counts zero of both. Names
+    /// that are not valid UTF-8 are converted lossily.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Cache;
+    /// let cache = Cache::new()
+    ///     .with_source(("fn main() {}\n", "main.rs"))
+    ///     .with_source(("mod a;\nmod b;", "lib.rs"));
+    /// let sources: Vec<_> = cache.iter().collect();
+    /// assert_eq!(sources[0], (0, "main.rs".into(), 13, 2));
+    /// assert_eq!(sources[1], (1, "lib.rs".into(), 13, 2));
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Cow<'_, str>, usize, usize)> + '_ {
+        (0..self.len()).map(|id| {
+            // SAFETY: inner is a valid cache, and id is in range
+            let src = unsafe { ffi::mu_getsource(self.inner, id as c_uint) };
+            // SAFETY: src is a valid source, whose name is set when added
+            let name: &[u8] = unsafe { (*src).name }.into();
+            let (len, lines) = self.source(id.into()).map_or((0, 0), |src| {
+                // SAFETY: src was loaded, so its functions are set
+                let count = unsafe { (*src).line_count.map_or(0, |f| f(src)) };
+                (source_len(src), count as usize)
+            });
+            (id, String::from_utf8_lossy(name), len, lines)
+        })
+    }
+
+    /// Get the source `src_id`, loading it if it was not loaded yet.
+    fn source(&self, src_id: ffi::mu_Id) -> Option<*mut ffi::mu_Source> {
+        load_source(self.inner, src_id)
+    }
+
+    /// Load every source now, instead of when it is first rendered.
+    ///
+    /// Loading a source splits it into lines, which may take a while for
+    /// large files. Call this at startup, so the first report rendered on a
+    /// latency-sensitive path doesn't pay for it. Sources that are already
+    /// loaded are skipped.
+    ///
+    /// # Errors
+    /// Returns an error naming the first source that fails to load; the
+    /// sources before it stay loaded.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Cache;
+    /// let cache = Cache::new()
+    ///     .with_source(("fn main() {}", "main.rs"))
+    ///     .with_source(("mod lib;", "lib.rs"));
+    /// cache.init_all()?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn init_all(&self) -> io::Result<()> {
+        // SAFETY: inner is null or a valid cache
+        let count = unsafe { ffi::mu_sourcecount(self.inner) };
+        for id in 0..count {
+            // SAFETY: id is in range, and the cache is not shared with other threads
+            let ok = unsafe { init_source(ffi::mu_getsource(self.inner, id)) };
+            if !ok {
+                return Err(init_error(self.inner, id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Load every source now, in parallel on the rayon thread pool.
+    ///
+    /// Like [`init_all`](Cache::init_all), but the sources are split into
+    /// lines concurrently. On failure, the error names one of the sources
+    /// that failed to load.
+    ///
+    /// Requires the `rayon` feature.
+    ///
+    /// # Safety
+    /// The `init` of every source is called from another thread, so every
+    /// source must be safe to send to another thread. Sources added from
+    /// `&str`, `String` or a [`FileSource`] are; custom [`Source`]s and
+    /// [`OwnedSource`]s are if their type is [`Send`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Cache;
+    /// let cache = Cache::new()
+    ///     .with_source(("fn main() {}", "main.rs"))
+    ///     .with_source(("mod lib;", "lib.rs"));
+    /// // SAFETY: both sources are strings
+    /// unsafe { cache.par_init_all()? };
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub unsafe fn par_init_all(&self) -> io::Result<()> {
+        use rayon::prelude::*;
+
+        struct SourcePtr(*mut ffi::mu_Source);
+        // SAFETY: each source is only used by one thread, and the caller
+        // guarantees they can be sent to other threads
+        unsafe impl Send for SourcePtr {}
+        // SAFETY: see above
+        unsafe impl Sync for SourcePtr {}
+
+        // SAFETY: inner is null or a valid cache
+        let count = unsafe { ffi::mu_sourcecount(self.inner) };
+        let sources: Vec<SourcePtr> = (0..count)
+            // SAFETY: id is in range
+            .map(|id| SourcePtr(unsafe { ffi::mu_getsource(self.inner, id) }))
+            .collect();
+        (sources.par_iter().enumerate())
+            // SAFETY: every source appears once, guaranteed by the caller
+            .try_for_each(|(id, src)| unsafe { init_source(src.0) }.then_some(()).ok_or(id))
+            .map_err(|id| init_error(self.inner, id as c_uint))
+    }
+
+    /// Load every source and share the cache between threads.
+    ///
+    /// Rendering only writes to a cache to load its sources, so once they
+    /// are all loaded, reports may render against the [`SharedCache`] from
+    /// any number of threads at once. Every source must have been added
+    /// with [`with_shared_source`](Cache::with_shared_source), or with
+    /// [`with_file`](Cache::with_file).
+    ///
+    /// # Errors
+    /// Returns an [`InvalidInput`](io::ErrorKind::InvalidInput) error if a
+    /// source was added otherwise, or an error naming the first source that
+    /// fails to load.
+    pub fn into_shared(self) -> io::Result<SharedCache<K>> {
+        if let Some(src_id) = self.local {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("source {src_id} was not added with with_shared_source"),
+            ));
+        }
+        self.init_all()?;
+        Ok(SharedCache(Arc::new(self)))
+    }
+
+    /// Add a source that can be shared between threads, see
+    /// [`into_shared`](Cache::into_shared).
+    ///
+    /// Works as [`with_source`](Cache::with_source) for the sources that
+    /// are [`Send`] and [`Sync`] and only read once loaded, see
+    /// [`SharedSource`].
+    #[inline]
+    pub fn with_shared_source<S: SharedSource>(mut self, content: S) -> Self {
+        content.add_to_cache(&mut self.inner);
+        self
+    }
+
+    /// Add a source to the cache.
+    ///
+    /// Accepts both borrowed (`&str`) and owned (`String`) content.
+    /// For other byte buffers like `Vec<u8>`, use [`OwnedSource`].
+    /// Borrowed content must remain valid until rendering completes.
+    /// Owned content is stored in the cache's internal memory.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, OwnedSource};
+    /// let cache = Cache::new()
+    ///     .with_source("let x = 42;")                    // &str - borrowed
+    ///     .with_source(("fn main() {}".to_string(), "main.rs"))  // String - owned
+    ///     .with_source((OwnedSource::new(vec![b'a', b'b', b'c']), "data.bin"));  // Vec<u8>
+    /// ```
+    #[inline]
+    pub fn with_source<S: AddToCache>(mut self, content: S) -> Self {
+        self.local.get_or_insert(self.len());
+        content.add_to_cache(&mut self.inner);
+        self
+    }
+
+    /// Declare the newline convention of source `src_id`.
+    ///
+    /// Must be called before the source is loaded, i.e. before rendering or
+    /// querying it. Has no effect on custom [`Source`]s, which split their
+    /// own lines, or if `src_id` is out of range.
+    ///
+    /// Default: [`NewlineKind::Auto`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, NewlineKind};
+    /// let cache = Cache::new()
+    ///     .with_source(("a\rb\r\n", "legacy.txt"))
+    ///     .with_newline_kind(0, NewlineKind::Lf);
+    /// assert_eq!(cache.newline_kind(0), Some(NewlineKind::Lf));
+    /// ```
+    pub fn with_newline_kind(self, src_id: impl Into<ffi::mu_Id>, kind: NewlineKind) -> Self {
+        // SAFETY: inner is null or a valid cache
+        let src = unsafe { ffi::mu_getsource(self.inner, src_id.into().0) };
+        if !src.is_null() {
+            // SAFETY: src is a valid source owned by the cache
+            unsafe { (*src).newline = ffi::mu_Newline::from(kind) as c_int };
+        }
+        self
+    }
+
+    /// The newline convention of source `src_id`, loading it if needed.
+    ///
+    /// Returns the declared convention, or the detected one for sources
+    /// declared as [`NewlineKind::Auto`]. Returns [`None`] if `src_id` is out
+    /// of range or the source fails to load.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, NewlineKind};
+    /// let cache = Cache::new()
+    ///     .with_source("a\r\nb\r\n")
+    ///     .with_source("a\nb\r\n");
+    /// assert_eq!(cache.newline_kind(0), Some(NewlineKind::CrLf));
+    /// assert_eq!(cache.newline_kind(1), Some(NewlineKind::Mixed));
+    /// assert_eq!(cache.newline_kind(2), None);
+    /// ```
+    pub fn newline_kind(&self, src_id: impl Into<ffi::mu_Id>) -> Option<NewlineKind> {
+        let src = self.source(src_id.into())?;
+        // SAFETY: src is a valid, loaded source
+        Some(NewlineKind::from_ffi(unsafe { ffi::mu_newlinekind(src) }))
+    }
+
+    /// Quote the code covered by `span` in source `src_id` on one line, for
+    /// log messages and summary tables.
+    ///
+    /// `span` counts characters, like labels with the default
+    /// [`IndexType`]; convert byte spans with [`LabelSpan::to_char_span`].
+    /// Runs of whitespace, including newlines, are collapsed into one space
+    /// and trimmed at both ends. If the quote is longer than `max_len`
+    /// characters, it is cut short and ends with `…`.
+    ///
+    /// Returns an empty string if `src_id` is out of range or the source
+    /// fails to load.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::Cache;
+    /// let cache = Cache::new().with_source("fn main() {\n    let x = 42;\n}\n");
+    /// assert_eq!(cache.excerpt(0..27, 0, 40), "fn main() { let x = 42;");
+    /// assert_eq!(cache.excerpt(0..27, 0, 12), "fn main() {…");
+    /// assert_eq!(cache.excerpt(16..21, 0, 40), "let x");
+    /// ```
+    pub fn excerpt(
+        &self,
+        span: std::ops::Range<usize>,
+        src_id: impl Into<ffi::mu_Id>,
+        max_len: usize,
+    ) -> String {
+        let Some(src) = self.source(src_id.into()) else {
+            return String::new();
+        };
+        let (start, end) = (char_to_byte(src, span.start), char_to_byte(src, span.end));
+        // SAFETY: src was loaded above, and line_for_bytes is its function
+        let Some((mut line_no, first, _)) =
+            (unsafe { source_line(src, (*src).line_for_bytes, start) })
+        else {
+            return String::new();
+        };
+        let mut text = Vec::new();
+        let mut prev = None;
+        // SAFETY: src was loaded above, so its functions are set
+        while let Some(line) = unsafe { (*src).get_line_info.map(|f| *f(src, line_no)) } {
+            // stop past the span, or at the last line, which repeats
+            if (line.byte_offset >= end && line.byte_offset > first.byte_offset)
+                || prev.is_some_and(|p| line.byte_offset <= p)
+            {
+                break;
+            }
+            // SAFETY: see above
+            let data: &[u8] =
+                unsafe { (*src).get_line.map_or(&[][..], |f| f(src, line_no).into()) };
+            let from = start.saturating_sub(line.byte_offset).min(data.len());
+            let to = end.saturating_sub(line.byte_offset).min(data.len());
+            text.extend_from_slice(&data[from..to.max(from)]);
+            text.push(b' ');
+            prev = Some(line.byte_offset);
+            line_no += 1;
+        }
+        let text = String::from_utf8_lossy(&text);
+        let mut words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if let Some((cut, _)) = words.char_indices().nth(max_len) {
+            let keep = max_len.saturating_sub(1);
+            let cut = words.char_indices().nth(keep).map_or(cut, |(i, _)| i);
+            words.truncate(cut);
+            if max_len > 0 {
+                words.push('…');
+            }
+        }
+        words
+    }
+}
+
+/// A source of diagnostic content.
+///
+/// Sources can be created from in-memory strings or with custom line providers.
+/// They are typically managed through a [`Cache`], but can also be passed directly
+/// to rendering methods for single-source diagnostics.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Cache, Source, Line};
+/// # use std::default::Default;
+///
+/// // implement a custom source
+/// struct MySource { /* ... */ }
+///
+/// # impl MySource { fn new() -> Self { Self{ /* ... */ } } }
+///
+/// impl Source for MySource {
+///     // ...
+/// # fn init(&mut self) -> std::io::Result<()> { Ok(()) }
+/// # fn get_line(&self, line_no: usize) -> &[u8] { b"" }
+/// # fn get_line_info(&self, line_no: usize) -> musubi::Line { Line::new() }
+/// # fn line_for_chars(&self, char_pos: usize) -> (usize, musubi::Line) { (0, Line::new()) }
+/// # fn line_for_bytes(&self, byte_pos: usize) -> (usize, musubi::Line) { (0, Line::new()) }
+/// }
+///
+/// // Use with Cache for multiple sources
+/// let cache = Cache::new()
+///     .with_source(("let x = 42;", "main.rs"))
+///     .with_source((MySource::new(), "my_source.rs"));
+///
+/// // Or pass directly to render for single source
+/// // report.render_to_string(("code", "file.rs"))?;
+/// ```
+pub trait Source {
+    /// Initialize the source (e.g., read lines).
+    fn init(&mut self) -> io::Result<()>;
+
+    /// Get a specific line by line number (0-based).
+    /// Return last line data if line_no is out of range.
+    fn get_line(&self, line_no: usize) -> &[u8];
+
+    /// Get line info struct by line number (0-based).
+    /// Return last line info if line_no is out of range.
+    fn get_line_info(&self, line_no: usize) -> Line;
+
+    /// Get the line number and line info for a given character position.
+    /// Return last line number and info if char_pos is out of range.
+    fn line_for_chars(&self, char_pos: usize) -> (usize, Line);
+
+    /// Get the line number and line info for a given byte position.
+    /// Return last line number and info if byte_pos is out of range.
+    fn line_for_bytes(&self, byte_pos: usize) -> (usize, Line);
+}
+
+/// Information about a line in source code.
+///
+/// This structure describes a line's position and length in both
+/// character and byte offsets, which is important for proper UTF-8 handling.
+///
+/// Returned by [`Source`] trait methods to provide line metadata.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Line {
+    /// Character offset from the start of the source (0-based)
+    pub offset: usize,
+    /// Byte offset from the start of the source (0-based)
+    pub byte_offset: usize,
+    /// Line length in characters (excluding newline)
+    pub len: u32,
+    /// Line length in bytes (excluding newline)
+    pub byte_len: u32,
+    /// Newline sequence length in bytes (0, 1 for \n, 2 for \r\n)
+    pub newline: u32,
+}
+
+impl Line {
+    /// Create a new empty Line with all fields set to zero.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl From<*const ffi::mu_Line> for Line {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    #[inline]
+    fn from(line: *const ffi::mu_Line) -> Self {
+        // SAFETY: line pointer is provided by C library and assumed valid
+        let line = unsafe { &*line };
+        Line {
+            offset: line.offset,
+            byte_offset: line.byte_offset,
+            len: line.len,
+            byte_len: line.byte_len,
+            newline: line.newline,
+        }
+    }
+}
+
+impl From<Line> for ffi::mu_Line {
+    #[inline]
+    fn from(line: Line) -> Self {
+        ffi::mu_Line {
+            offset: line.offset,
+            byte_offset: line.byte_offset,
+            len: line.len,
+            byte_len: line.byte_len,
+            newline: line.newline,
+        }
+    }
+}
+
+/// Error returned when the C library fails to allocate memory.
+///
+/// Returned by [`Report::try_new`]. Rendering out of memory fails with
+/// [`Error::OutOfMemory`] instead.
+///
+/// Allocations made while building a report (labels, notes, helps) or
+/// while loading source lines still abort the process on failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AllocError;
+
+impl std::fmt::Display for AllocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+impl std::error::Error for AllocError {}
+
+/// A diagnostic report builder.
+///
+/// The lifetime `'a` indicates that all string references passed to the report
+/// must live at least as long as the report itself. This enables zero-copy
+/// string passing to the underlying C library.
+///
+/// # Source Management
+///
+/// Sources are managed through a [`Cache`] and assigned IDs based on registration
+/// order: first source is 0, second is 1, etc. The cache is then passed to rendering
+/// methods. A report can also carry its own sources, see [`Report::with_source`].
+///
+/// # Example
+/// ```rust
+/// use musubi::{Report, Cache, Level};
+///
+/// let cache = Cache::new()
+///     .with_source(("let x = 42;", "main.rs"))   // src_id = 0
+///     .with_source(("fn foo() {}", "lib.rs"));   // src_id = 1
+///
+/// let mut report = Report::new()
+///     .with_title(Level::Error, "Error")
+///     .with_label((0..3, 0)) // label in source 0
+///     .with_message("here")
+///     .with_label((3..6, 1)) // label in source 1
+///     .with_message("and here");
+///
+/// report.render_to_stdout(&cache)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Lifetime Safety
+///
+/// Source strings must outlive the report. This will not compile:
+///
+/// ```compile_fail
+/// use musubi::{Report, Level};
+///
+/// fn bad() -> String {
+///     let mut report = Report::new();
+///     {
+///         let code = String::from("let x = 42;");
+///         report = report.with_source((code.as_str(), "test.rs"));
+///     }  // code dropped here, but report still holds reference
+///     report.render_to_string(()).unwrap()
+/// }
+/// ```
+///
+/// # Threads
+///
+/// A report may hold colors and sources of any type, so it can't be sent to
+/// another thread. To build diagnostics on worker threads and render them
+/// on another, send [`Diagnostic`]s instead, owned with
+/// [`Diagnostic::into_owned`], and turn them into reports where they are
+/// rendered:
+///
+/// ```rust
+/// # use musubi::{Config, Diagnostic, Level, Report};
+/// let (tx, rx) = std::sync::mpsc::channel();
+/// std::thread::spawn(move || {
+///     let report = Report::new()
+///         .with_title(Level::Error, "unknown name")
+///         .with_label(4..5);
+///     tx.send(report.to_diagnostic().into_owned()).unwrap();
+/// });
+///
+/// let diag: Diagnostic<'static> = rx.recv().unwrap();
+/// let output = Report::from(&diag)
+///     .with_config(Config::new().with_color_disabled())
+///     .render_to_string("let x = 1;")?;
+/// assert!(output.starts_with("Error: unknown name"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Report<'a> {
+    ptr: *mut ffi::mu_Report,
+    config: Option<Config<'a>>,
+    color_buf: [u8; ffi::sizes::COLOR_CODE],
+    /// Per-label colors, kept at stable addresses
+    color_uds: ColorArena,
+    /// Owned messages, kept alive until the report is dropped
+    owned: StrArena,
+    /// Colors of the labels added with `with_label_obj`, boxed to keep
+    /// them at stable addresses
+    #[allow(clippy::vec_box)]
+    label_colors: Vec<Box<GenColor>>,
+    /// Estimated rendered size in bytes, used to pre-size string buffers
+    size_hint: usize,
+    /// Content of the report, read back by `to_diagnostic`
+    diagnostic: Diagnostic<'a>,
+    /// Labels given by line and column, by index, placed when rendering
+    line_cols: Vec<(usize, LineColRange)>,
+    /// Location set by `with_location`; one given by line and column is
+    /// placed when rendering
+    location: Option<(Location, ffi::mu_Id)>,
+    /// Sources attached with `with_source`, used when rendering with `()`
+    sources: *mut ffi::mu_Cache,
+    /// Reports rendered indented after this one
+    related: Vec<Report<'a>>,
+    /// Last writer set, also used by the related reports
+    writer: (ffi::mu_Writer, *mut c_void),
+    /// Where a writer reads the report being written, or null
+    writing: *mut *const ffi::mu_Report,
+    src_err: Option<io::Error>,
+    /// Bytes the writer took in the last render, with the related reports
+    written: usize,
+    _marker: PhantomData<&'a str>,
+}
+
+impl Default for Report<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> From<&'a Diagnostic<'_>> for Report<'a> {
+    /// See [`Report::from_diagnostic`].
+    #[inline]
+    fn from(diag: &'a Diagnostic<'_>) -> Self {
+        Self::from_diagnostic(diag)
+    }
+}
+
+impl Drop for Report<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: self.ptr is a valid mu_Report pointer owned by this Report
+        unsafe {
+            ffi::mu_delete(self.ptr);
+        }
+        if !self.sources.is_null() {
+            // SAFETY: self.sources is a cache owned by this Report
+            unsafe { ffi::mu_delcache(self.sources) };
+        }
+    }
+}
+
+impl<'a> Report<'a> {
+    /// Create a new report.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the report cannot be allocated. Use [`Report::try_new`]
+    /// to handle this case.
+    #[inline]
+    pub fn new() -> Self {
+        Self::try_new().expect("Failed to allocate report")
+    }
+
+    /// Create a new report, returning [`AllocError`] if allocation fails.
+    ///
+    /// ```rust
+    /// use musubi::Report;
+    ///
+    /// let report = Report::try_new()?;
+    /// # drop(report);
+    /// # Ok::<(), musubi::AllocError>(())
+    /// ```
+    #[inline]
+    pub fn try_new() -> Result<Self, AllocError> {
+        // SAFETY: mu_new allocates a new report, returns null on failure (checked below)
+        let ptr = unsafe { ffi::mu_new(None, ptr::null_mut()) };
+        if ptr.is_null() {
+            return Err(AllocError);
+        }
+        Ok(Self {
+            ptr,
+            config: None,
+            color_buf: [0; ffi::sizes::COLOR_CODE],
+            color_uds: ColorArena::default(),
+            owned: StrArena::default(),
+            label_colors: Vec::new(),
+            size_hint: 0,
+            diagnostic: Diagnostic::default(),
+            line_cols: Vec::new(),
+            location: None,
+            sources: ptr::null_mut(),
+            related: Vec::new(),
+            writer: (None, ptr::null_mut()),
+            writing: ptr::null_mut(),
+            src_err: None,
+            written: 0,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Get the underlying C report pointer.
+    ///
+    /// The pointer remains owned by this report and is valid until it is
+    /// dropped. See [`sys`] for the raw API.
+    #[inline]
+    pub fn as_raw(&self) -> *mut sys::mu_Report {
+        self.ptr
+    }
+
+    /// Create a report from the content of `diag`.
+    ///
+    /// Strings are borrowed from `diag`. See [`Diagnostic`] for an example.
+    #[must_use]
+    pub fn from_diagnostic(diag: &'a Diagnostic<'_>) -> Self {
+        let mut report = Self::new().with_title(diag.title_level(), diag.title());
+        if let Some(code) = diag.code() {
+            report = report.with_code(code);
+        }
+        for label in diag.labels() {
+            report = if label.is_primary() {
+                report.with_primary_label(label.span())
+            } else {
+                report.with_label(label.span())
+            };
+            if let Some(msg) = label.message() {
+                report = report.with_message(msg);
+            }
+        }
+        report = report.with_helps(diag.helps().iter().map(|h| &**h));
+        report = report.with_suggestions_of(diag);
+        report.with_notes(diag.notes().iter().map(|n| &**n))
+    }
+
+    /// Add the suggestions of `diag`.
+    pub(crate) fn with_suggestions_of(mut self, diag: &'a Diagnostic<'_>) -> Self {
+        for fix in diag.suggestions() {
+            let [(span, repl), parts @ ..] = fix.edits() else {
+                continue;
+            };
+            self = self.with_suggestion(*span, repl, fix.message().unwrap_or(""));
+            for (span, repl) in parts {
+                self = self.with_suggestion_part(*span, repl);
+            }
+            self = self.with_applicability(fix.applicability());
+        }
+        self
+    }
+
+    /// Read back the content of the report.
+    ///
+    /// The result holds the title, code, labels, helps, notes and
+    /// suggestions added so far; a report without title gives an error with
+    /// an empty title.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Level, Report};
+    /// let report = Report::new()
+    ///     .with_title(Level::Error, "Type mismatch")
+    ///     .with_label(0..3)
+    ///     .with_message("expected `i32`")
+    ///     .with_note("found `&str`");
+    /// let diag = report.to_diagnostic();
+    /// assert_eq!(diag.level(), Some(Level::Error));
+    /// assert_eq!(diag.labels()[0].message(), Some("expected `i32`"));
+    /// assert_eq!(diag.notes(), ["found `&str`"]);
+    /// ```
+    #[inline]
+    pub fn to_diagnostic(&self) -> Diagnostic<'a> {
+        self.diagnostic.clone()
+    }
+
+    /// Configure the report.
+    ///
+    /// see [`Config`] for configuration options.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Config};
+    /// let config = Config::new().with_limit_width(80);
+    /// let report = Report::new().with_config(config);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_config(mut self, config: Config<'a>) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Attach a source to the report.
+    ///
+    /// Attached sources are kept in a cache owned by the report, and are
+    /// used when rendering with `()` in place of a cache, so a single-file
+    /// report can carry its own source. Source IDs follow the attach order,
+    /// as in [`Cache::with_source`], which accepts the same sources. Use a
+    /// [`Cache`] instead to share sources between reports.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_source(("let x = 42;", "main.rs"))
+    ///     .with_title(Level::Error, "Syntax error")
+    ///     .with_label(4..5)
+    ///     .render_to_string(())?;
+    /// assert!(output.contains("main.rs:1:5"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn with_source<S: AddToCache + 'a>(mut self, content: S) -> Self {
+        content.add_to_cache(&mut self.sources);
+        // the cache may have moved, and source IDs now resolve differently
+        // SAFETY: self.ptr is a valid mu_Report pointer
+        unsafe { ffi::mu_relayout(self.ptr) };
+        self
+    }
+
+    /// Reset the report for reuse.
+    ///
+    /// Clears all labels, messages, related reports and configuration,
+    /// allowing the same Report instance to be used for rendering a
+    /// different diagnostic. Sources attached with [`Report::with_source`]
+    /// are kept.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_title(Level::Error, "First error");
+    /// // ... render ...
+    /// report.render_to_string("")?;
+    ///
+    /// let mut report = report.reset()
+    ///     .with_title(Level::Warning, "Second warning");
+    /// // ... render again ...
+    /// report.render_to_string("")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn reset(mut self) -> Self {
+        // SAFETY: self.ptr is a valid mu_Report pointer owned by this Report
+        unsafe { ffi::mu_reset(self.ptr) };
+        self.size_hint = 0;
+        self.diagnostic = Diagnostic::default();
+        self.line_cols.clear();
+        self.location = None;
+        self.related.clear();
+        self
+    }
+
+    /// Set the title and level.
+    ///
+    /// Accepts either a standard level or a custom level name:
+    /// - `with_title(Level::Error, "message")` - standard level
+    /// - `with_title("Lint", "message")` - custom level name
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_title(Level::Error, "Something went wrong")
+    ///     // Or with custom level:
+    ///     .with_title("Lint", "Something to check")
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_title<L: Into<TitleLevel<'a>>>(mut self, level: L, message: &'a str) -> Self {
+        let tl = level.into();
+        self.size_hint += Self::HEADER_HINT + message.len();
+        self.diagnostic.set_title(tl, message);
+        // SAFETY: self.ptr is valid, message lifetime is bound to 'a
+        unsafe { ffi::mu_title(self.ptr, tl.level, tl.custom_name, message.into()) };
+        self
+    }
+
+    /// Set the error code for this diagnostic.
+    ///
+    /// The error code is typically displayed in brackets before the title,
+    /// like `[E0001]` or `[W123]`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_title(Level::Error, "Type mismatch")
+    ///     .with_code("E0308")  // Displayed as [E0308]
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_code(mut self, code: &'a str) -> Self {
+        self.diagnostic.set_code(code);
+        // SAFETY: self.ptr is valid, code lifetime is bound to 'a
+        unsafe { ffi::mu_code(self.ptr, code.into()) };
+        self
+    }
+
+    /// Set the primary label for its group.
+    ///
+    /// This location is displayed in the diagnostic header, showing
+    /// where the error occurred.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_title(Level::Error, "Syntax error")
+    ///     .with_primary_label((0..3, 0))  // Primary label in source 0
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_primary_label<L: Into<LabelSpan>>(mut self, span: L) -> Self {
+        let span = span.into();
+        self.hint_span(&span);
+        let label = DiagnosticLabel::new(span).with_primary(true);
+        self.diagnostic.labels_mut().push(label);
+        // SAFETY: self.ptr is valid, span values are checked by C library
+        unsafe { ffi::mu_label(self.ptr, span.start, span.end, span.src_id) };
+        // SAFETY: self.ptr is valid
+        unsafe { ffi::mu_primary(self.ptr) };
+        self
+    }
+
+    /// Add a label at the given byte range.
+    ///
+    /// The `src_id` is the source registration order (0 for first source, 1 for second, etc.).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_title(Level::Error, "Error")
+    ///     .with_label((0..3, 0))  // label in source 0
+    ///     .with_message("here")
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_label<L: Into<LabelSpan>>(mut self, span: L) -> Self {
+        let span = span.into();
+        self.hint_span(&span);
+        self.diagnostic
+            .labels_mut()
+            .push(DiagnosticLabel::new(span));
+        // SAFETY: self.ptr is valid, span values are checked by C library
+        unsafe { ffi::mu_label(self.ptr, span.start, span.end, span.src_id) };
+        self
+    }
+
+    /// Add a label from 1-based `(line, column)` coordinates, like
+    /// `(3, 5)..(3, 9)`.
+    ///
+    /// Lines are numbered as in the margin, so they include the line number
+    /// offset of the source, and columns count characters. The end is
+    /// exclusive. The span is found from the lines of source `src_id` when
+    /// the report is rendered or exported, like with
+    /// [`render_to_json`](Report::render_to_json);
+    /// [`to_diagnostic`](Report::to_diagnostic) has no sources, so it reads
+    /// the label as an empty span at the start of the source until the
+    /// report is rendered. Lines outside the source are placed at its
+    /// nearest end, and [`validate`](Report::validate) reports them as
+    /// [`Error::SpanOutOfBounds`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label_at_line_col((2, 9)..(2, 10), 0)
+    ///     .with_message("not found")
+    ///     .render_to_string("let x = 1;\nlet y = z;")?;
+    /// assert!(output.contains("<unknown>:2:9"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn with_label_at_line_col(
+        mut self,
+        range: LineColRange,
+        src_id: impl Into<ffi::mu_Id>,
+    ) -> Self {
+        self.line_cols.push((self.diagnostic.labels().len(), range));
+        self.with_label(LabelSpan::new(0..0, src_id))
+    }
+
+    /// The spans of the labels given by line and column, by label index,
+    /// placed in the sources of `cache`, and whether their lines are in the
+    /// source. Lines outside the source are placed at its nearest end.
+    pub(crate) fn line_col_spans(
+        &self,
+        cache: *mut ffi::mu_Cache,
+    ) -> Vec<(usize, LabelSpan, bool)> {
+        let index_type = (self.config.as_ref()).map_or(IndexType::Char, Config::index_type);
+        let labels = self.diagnostic.labels();
+        (self.line_cols.iter())
+            .filter_map(|(i, range)| {
+                let span = labels[*i].span();
+                let src = load_source(cache, span.src_id)?;
+                let start = line_col_to_pos(src, range.start, index_type);
+                let end = line_col_to_pos(src, range.end, index_type);
+                let found = start.is_ok() && end.is_ok();
+                let (start, end) = (start.unwrap_or_else(|p| p), end.unwrap_or_else(|p| p));
+                Some((*i, LabelSpan { start, end, ..span }, found))
+            })
+            .collect()
+    }
+
+    /// The position and source shown in the header, set by
+    /// [`with_location`](Report::with_location), placed in the sources of
+    /// `cache`.
+    pub(crate) fn location_in(&self, cache: *mut ffi::mu_Cache) -> Option<(usize, ffi::mu_Id)> {
+        let index_type = (self.config.as_ref()).map_or(IndexType::Char, Config::index_type);
+        match self.location? {
+            (Location::Offset(pos), src_id) => Some((pos, src_id)),
+            (Location::LineCol(line, col), src_id) => {
+                let src = load_source(cache, src_id)?;
+                let pos = line_col_to_pos(src, (line, col), index_type);
+                Some((pos.unwrap_or_else(|p| p), src_id))
+            }
+        }
+    }
+
+    /// The content of the report, with the labels given by line and column
+    /// placed in the sources of `cache`.
+    ///
+    /// Exporters read the report through this instead of
+    /// [`to_diagnostic`](Report::to_diagnostic), which can't place them.
+    pub(crate) fn diagnostic_in(&self, cache: *mut ffi::mu_Cache) -> Diagnostic<'a> {
+        let mut diag = self.diagnostic.clone();
+        for (i, span, _) in self.line_col_spans(cache) {
+            diag.labels_mut()[i].set_span(span);
+        }
+        diag
+    }
+
+    /// Place the labels and location given by line and column in the
+    /// sources of `cache`.
+    fn place_line_cols(&mut self, cache: *mut ffi::mu_Cache) {
+        for (i, span, _) in self.line_col_spans(cache) {
+            self.diagnostic.labels_mut()[i].set_span(span);
+            // SAFETY: self.ptr is valid, and label i was added to it
+            unsafe { ffi::mu_setspan(self.ptr, i.into(), span.start, span.end) };
+        }
+        if let Some((Location::LineCol(..), _)) = self.location
+            && let Some((pos, src_id)) = self.location_in(cache)
+        {
+            // SAFETY: self.ptr is a valid mu_Report pointer
+            unsafe { ffi::mu_location(self.ptr, pos, src_id) };
+        }
+    }
+
+    /// Set the position shown as `line:col` in the header, instead of the
+    /// start of the primary label.
+    ///
+    /// `loc` is an offset in source `src_id` or a 1-based `(line, column)`
+    /// pair, see [`Location`]. It is shown in the header of the labels of
+    /// that source, even when
+    /// [`with_auto_location`](Config::with_auto_location) is disabled.
+    /// [`render_to_json`](Report::render_to_json) writes it as the
+    /// `location` field, and
+    /// [`render_to_github_annotations`](Report::render_to_github_annotations)
+    /// places a report without labels at it.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(19..20)
+    ///     .with_location((2, 1), 0)
+    ///     .render_to_string("let x = 1;\nlet y = z;")?;
+    /// assert!(output.contains("<unknown>:2:1"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn with_location<L: Into<Location>>(
+        mut self,
+        loc: L,
+        src_id: impl Into<ffi::mu_Id>,
+    ) -> Self {
+        let (loc, src_id) = (loc.into(), src_id.into());
+        self.location = Some((loc, src_id));
+        if let Location::Offset(pos) = loc {
+            // SAFETY: self.ptr is a valid mu_Report pointer
+            unsafe { ffi::mu_location(self.ptr, pos, src_id) };
+        }
+        self
+    }
+
+    /// Set the message for the last added label.
+    ///
+    /// The message is displayed next to the label's marker/arrow,
+    /// providing explanation or context for the highlighted code.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_label(0..3)
+    ///     .with_message("expected identifier here")  // ← message for this label
+    ///     .with_label(10..15)
+    ///     .with_message("found number instead")      // ← message for next label
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_message(self, msg: &'a str) -> Self {
+        self.with_label_message(Cow::Borrowed(msg))
+    }
+
+    /// Set the message of the last label, taking ownership of it if needed.
+    pub(crate) fn with_label_message(mut self, msg: Cow<'a, str>) -> Self {
+        let width = unicode_width(&msg);
+        self.size_hint += msg.len();
+        if let Some(label) = self.diagnostic.labels_mut().last_mut() {
+            label.set_message(msg.clone());
+        }
+        let msg = self.store(msg);
+        // SAFETY: self.ptr is valid, msg lives as long as 'a or the report
+        unsafe { ffi::mu_message(self.ptr, msg, width) };
+        self
+    }
+
+    /// Mark a part of the last label's message as a reference to another
+    /// label.
+    ///
+    /// `range` is a byte range in the message, and `label` the index of the
+    /// referred label, counting [`with_label`](Report::with_label) calls
+    /// from 0. The part is drawn with the color of the referred label, tying
+    /// the two annotations together visually. A label has at most one
+    /// reference, and setting a new message clears it.
+    ///
+    /// The call is ignored if the label has no message or `range` is out of
+    /// its bounds; rendering fails if `label` does not exist.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{ColorGenerator, Report, Level};
+    /// let mut cg = ColorGenerator::new();
+    /// let (decl, usage) = (cg.next_color(), cg.next_color());
+    /// Report::new()
+    ///     .with_title(Level::Error, "mismatched types")
+    ///     .with_label(7..10)
+    ///     .with_message("declared here")
+    ///     .with_color(&decl)
+    ///     .with_label(14..16)
+    ///     .with_message("must match the type declared here")
+    ///     .with_message_ref(20..33, 0) // "declared here"
+    ///     .with_color(&usage)
+    ///     .render_to_string("let x: i32 = \"\";")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_message_ref(self, range: std::ops::Range<usize>, label: usize) -> Self {
+        // SAFETY: self.ptr is valid, range is checked by C library
+        unsafe { ffi::mu_msgref(self.ptr, range.start, range.end, label.into()) };
+        self
+    }
+
+    /// Show a whole enclosing region (e.g. a function) as context.
+    ///
+    /// All lines of `span` are shown, even when they have no label, and
+    /// their unlabeled text is drawn with [`ColorKind::Context`] (dimmed by
+    /// default), while labels inside keep their colors. The span is only
+    /// shown if its source also has a label. Unlike a label, it draws no
+    /// underline or message.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let src = "fn main() {\n    let x = 1;\n    foo(x);\n}";
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "unknown function")
+    ///     .with_label(31..34)
+    ///     .with_context_span(0..src.len())
+    ///     .render_to_string(src)?;
+    /// assert!(output.contains("let x = 1;"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_context_span<L: Into<LabelSpan>>(mut self, span: L) -> Self {
+        let span = span.into();
+        self.hint_span(&span);
+        // SAFETY: self.ptr is valid, span values are checked by C library
+        unsafe { ffi::mu_context(self.ptr, span.start, span.end, span.src_id) };
+        self
+    }
+
+    /// Add a pair of labels on `span` comparing an `expected` value with
+    /// the `actual` one.
+    ///
+    /// The labels show ``expected `expected` `` and ``found    `actual` ``,
+    /// with the values aligned under each other, drawn with
+    /// [`ColorKind::Expected`] (green by default) and [`ColorKind::Found`]
+    /// (red by default). [`with_color`](Report::with_color) after this call
+    /// only affects the second label.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "mismatched types")
+    ///     .with_expected_found(13..15, "i32", "&str")
+    ///     .render_to_string("let x: i32 = \"\";")?;
+    /// assert!(output.contains("expected `i32`"));
+    /// assert!(output.contains("found    `&str`"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn with_expected_found<L: Into<LabelSpan>>(
+        mut self,
+        span: L,
+        expected: &str,
+        actual: &str,
+    ) -> Self {
+        let span = span.into();
+        let pairs = [
+            (
+                format!("expected `{expected}`"),
+                ffi::mu_ColorKind::MU_COLOR_EXPECTED,
+            ),
+            (
+                format!("found    `{actual}`"),
+                ffi::mu_ColorKind::MU_COLOR_FOUND,
+            ),
+        ];
+        for (msg, kind) in pairs {
+            self = self.with_label(span);
+            let width = unicode_width(&msg);
+            self.size_hint += msg.len();
+            if let Some(label) = self.diagnostic.labels_mut().last_mut() {
+                label.set_message(msg.clone());
+            }
+            let msg = self.store(Cow::Owned(msg));
+            // SAFETY: self.ptr is valid, msg is owned by the report
+            unsafe {
+                ffi::mu_message(self.ptr, msg, width);
+                ffi::mu_colorkind(self.ptr, kind);
+            }
+        }
+        self
+    }
+
+    /// Set the color for the last added label.
+    ///
+    /// This method accepts anything that implements [`IntoColor`], including:
+    /// - `&dyn Color` - Custom color trait objects
+    /// - `&GenColor` - Pre-generated colors from [`ColorGenerator`]
+    ///
+    /// # Examples
+    ///
+    /// Using a custom color:
+    /// ```rust
+    /// # use musubi::{Report, Level, Color, ColorKind};
+    /// # use std::io::Write;
+    /// struct MyColor;
+    /// impl Color for MyColor {
+    ///     fn color(&self, w: &mut dyn Write, kind: ColorKind) -> std::io::Result<()> {
+    ///         write!(w, "\x1b[31m") // Red
+    ///     }
+    /// }
+    ///
+    /// let color = MyColor;
+    /// Report::new()
+    ///     .with_label(0..4)
+    ///     .with_color(&color)
+    ///     // ...
+    ///     # ;
+    /// ```
+    ///
+    /// Using a color generator:
+    /// ```rust
+    /// # use musubi::{Report, Level, ColorGenerator};
+    /// let mut cg = ColorGenerator::new();
+    ///
+    /// let report = Report::new()
+    ///     .with_label(0..4)
+    ///     .with_color(&cg.next_color())
+    ///     // ...;
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_color<C: IntoColor>(mut self, color: C) -> Self {
+        color.into_color(&mut self);
+        self
+    }
+
+    /// Set the display order for the last added label.
+    ///
+    /// Labels with lower order values are displayed first (closer to the code).
+    /// Labels with the same order are displayed in the order they were added.
+    ///
+    /// Default: `0`
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_label(0..4)
+    ///         .with_message("second")
+    ///         .with_order(1)   // Display this label later
+    ///     .with_title(Level::Error, "Error")
+    ///         .with_label(0..4)
+    ///         .with_message("first")
+    ///         .with_order(-1)  // Display this label first
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_order(self, order: i32) -> Self {
+        // SAFETY: self.ptr is valid
+        unsafe { ffi::mu_order(self.ptr, order) };
+        self
+    }
+
+    /// Set the priority for the last added label.
+    ///
+    /// Priority controls how overlapping labels are rendered when multiple
+    /// labels cover the same source location. Labels with higher priority
+    /// will be drawn on top, potentially obscuring lower-priority labels.
+    ///
+    /// Higher values = higher priority = drawn on top.
+    ///
+    /// Default: `0`
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_label(0..10)
+    ///         .with_message("low priority")
+    ///         .with_priority(0)   // May be obscured by overlapping labels
+    ///     .with_label(5..15)
+    ///         .with_message("high priority")
+    ///         .with_priority(10)  // Will be drawn on top
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_priority(self, priority: i32) -> Self {
+        // SAFETY: self.ptr is valid
+        unsafe { ffi::mu_priority(self.ptr, priority) };
+        self
+    }
+
+    /// Add a help message to the diagnostic.
+    ///
+    /// Help messages appear at the end of the diagnostic,
+    /// providing suggestions or additional context.
+    ///
+    /// Multiple help messages can be added and will be displayed in order.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_title(Level::Error, "Type error")
+    ///     .with_label(0..4)
+    ///         .with_message("expected String")
+    ///     .with_help("try converting with .to_string()")
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_help(mut self, msg: &'a str) -> Self {
+        self.size_hint += Self::LINE_HINT + msg.len();
+        self.diagnostic.push_help(msg.into());
+        // SAFETY: self.ptr is valid, msg lifetime is bound to 'a
+        unsafe { ffi::mu_help(self.ptr, msg.into()) };
+        self
+    }
+
+    /// Add a note message to the diagnostic.
+    ///
+    /// Notes appear at the end of the diagnostic,
+    /// providing additional information or context.
+    ///
+    /// Multiple notes can be added and will be displayed in order.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_title(Level::Warning, "Unused variable")
+    ///     .with_label(0..4)
+    ///         .with_message("never used")
+    ///     .with_note("consider prefixing with an underscore: `_code`")
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_note(mut self, msg: &'a str) -> Self {
+        self.size_hint += Self::LINE_HINT + msg.len();
+        self.diagnostic.push_note(msg.into());
+        // SAFETY: self.ptr is valid, msg lifetime is bound to 'a
+        unsafe { ffi::mu_note(self.ptr, msg.into()) };
+        self
+    }
+
+    /// Add several help messages to the diagnostic.
+    ///
+    /// Accepts borrowed or owned strings, so advice collected during
+    /// analysis can be attached in one call. Owned strings are kept alive
+    /// by the report. See [`with_help`](Report::with_help).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let candidates = ["len", "length"];
+    /// Report::new()
+    ///     .with_title(Level::Error, "Unknown field")
+    ///     .with_label(0..4)
+    ///     .with_helps(candidates.iter().map(|c| format!("did you mean `{c}`?")))
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[must_use]
+    pub fn with_helps<I>(mut self, msgs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'a, str>>,
+    {
+        for msg in msgs {
+            let msg = msg.into();
+            self.size_hint += Self::LINE_HINT + msg.len();
+            self.diagnostic.push_help(msg.clone());
+            let msg = self.store(msg);
+            // SAFETY: self.ptr is valid, msg lives as long as 'a or the report
+            unsafe { ffi::mu_help(self.ptr, msg) };
+        }
+        self
+    }
+
+    /// Add several note messages to the diagnostic.
+    ///
+    /// Accepts borrowed or owned strings, like [`with_helps`](Report::with_helps).
+    /// See [`with_note`](Report::with_note).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_title(Level::Warning, "Unused variable")
+    ///     .with_label(0..4)
+    ///     .with_notes(["declared here", "never read afterwards"])
+    ///     // ...
+    ///     # ;
+    /// ```
+    #[must_use]
+    pub fn with_notes<I>(mut self, msgs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Cow<'a, str>>,
+    {
+        for msg in msgs {
+            let msg = msg.into();
+            self.size_hint += Self::LINE_HINT + msg.len();
+            self.diagnostic.push_note(msg.clone());
+            let msg = self.store(msg);
+            // SAFETY: self.ptr is valid, msg lives as long as 'a or the report
+            unsafe { ffi::mu_note(self.ptr, msg) };
+        }
+        self
+    }
+
+    /// Suggest replacing `span` with `replacement`.
+    ///
+    /// The suggestion is shown after the help messages, numbered along with
+    /// them, as a help message followed by the source lines of `span` with
+    /// the replacement applied and underlined. An empty `message` shows
+    /// ``replace with `replacement` `` (or `remove this` for an empty
+    /// replacement). `span` is interpreted according to the configured
+    /// [`IndexType`], and its source doesn't need a label.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Warning, "unused variable")
+    ///     .with_label(4..5)
+    ///     .with_suggestion(4..5, "_a", "")
+    ///     .render_to_string("let a = 1;")?;
+    /// assert!(output.contains("Help: replace with `_a`\n 1 ┤ let _a = 1;\n   │     ──\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn with_suggestion<L: Into<LabelSpan>>(
+        mut self,
+        span: L,
+        replacement: &'a str,
+        message: &'a str,
+    ) -> Self {
+        let span = span.into();
+        self.size_hint += Self::LINE_HINT * 4 + message.len() + replacement.len();
+        let mut fix = DiagnosticSuggestion::new(span, replacement);
+        if !message.is_empty() {
+            fix = fix.with_message(message);
+        }
+        self.diagnostic.suggestions_mut().push(fix);
+        let msg = match message {
+            "" => ffi::mu_Slice::default(),
+            msg => msg.into(),
+        };
+        // SAFETY: self.ptr is valid, both strings' lifetimes are bound to 'a
+        unsafe {
+            ffi::mu_suggest(
+                self.ptr,
+                span.start,
+                span.end,
+                span.src_id,
+                replacement.into(),
+                msg,
+            )
+        };
+        self
+    }
+
+    /// Add another edit to the last suggestion, replacing `span` with
+    /// `replacement`.
+    ///
+    /// All edits of a suggestion are shown under one help message, titled
+    /// `apply these changes` if the suggestion has no message. Edits on the
+    /// same line are shown together on that line; they must be added in
+    /// source order. Does nothing if no suggestion was added.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "mismatched types")
+    ///     .with_suggestion(8..8, "(", "")
+    ///     .with_suggestion_part(13..13, ") as i64")
+    ///     .render_to_string("let x = a + b;")?;
+    /// assert!(output.contains("Help: apply these changes\n 1 ┤ let x = (a + b) as i64;\n   │         ─     ────────\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn with_suggestion_part<L: Into<LabelSpan>>(
+        mut self,
+        span: L,
+        replacement: &'a str,
+    ) -> Self {
+        let span = span.into();
+        let Some(fix) = self.diagnostic.suggestions_mut().last_mut() else {
+            return self;
+        };
+        fix.push_edit(span, replacement.into());
+        self.size_hint += Self::LINE_HINT * 2 + replacement.len();
+        // SAFETY: self.ptr is valid, replacement's lifetime is bound to 'a
+        unsafe {
+            ffi::mu_suggestpart(
+                self.ptr,
+                span.start,
+                span.end,
+                span.src_id,
+                replacement.into(),
+            )
+        };
+        self
+    }
+
+    /// Set the [`Applicability`] of the last suggestion.
+    ///
+    /// Suggestions are [`Applicability::MaybeIncorrect`] by default. The
+    /// applicability doesn't change the rendered text, but is kept in
+    /// [`Report::to_diagnostic`] and [`Report::render_to_json`] for tools
+    /// applying suggestions automatically.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Applicability, Report, Level};
+    /// let report = Report::new()
+    ///     .with_title(Level::Warning, "unused variable")
+    ///     .with_suggestion(4..5, "_a", "")
+    ///     .with_applicability(Applicability::MachineApplicable);
+    /// let json = report.render_to_json("let a = 1;");
+    /// assert!(json.contains(r#""applicability":"MachineApplicable""#));
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn with_applicability(mut self, applicability: Applicability) -> Self {
+        if let Some(fix) = self.diagnostic.suggestions_mut().last_mut() {
+            fix.set_applicability(applicability);
+        }
+        self
+    }
+
+    /// Attach a related report, rendered indented beneath this one.
+    ///
+    /// Related reports explain where the main diagnostic comes from, like
+    /// `note: required by this bound` in rustc. They are rendered after this
+    /// report, with the same cache and writer, each line indented by four
+    /// spaces, or as set by [`Config::with_related_indent`] and
+    /// [`Config::with_related_connectors`]; a related report without
+    /// [`Config`] uses the config of this report. Related reports may have related reports themselves, indented
+    /// further. They only appear in the text renderers, e.g. not in
+    /// [`Report::render_to_json`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level};
+    /// let src = "fn f<T: Copy>(t: T) {}\nf(String::new());";
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "the trait `Copy` is not implemented")
+    ///     .with_label(25..38)
+    ///     .with_related(
+    ///         Report::new()
+    ///             .with_title(Level::Note, "required by this bound")
+    ///             .with_label(8..12),
+    ///     )
+    ///     .render_to_string(src)?;
+    /// assert!(output.contains("\n    Note: required by this bound\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[must_use]
+    pub fn with_related(mut self, related: Report<'a>) -> Self {
+        self.size_hint += related.size_hint + related.size_hint / 4;
+        self.related.push(related);
+        self
+    }
+
+    /// Bytes estimated for the header and the source location lines.
+    const HEADER_HINT: usize = 128;
+    /// Bytes estimated for a line of decorations (gutter, arrows, colors).
+    const LINE_HINT: usize = 64;
+    /// Upper bound of the buffer reserved up front.
+    const MAX_HINT: usize = 1 << 20;
+
+    /// Account for the source lines and decorations drawn for `span`.
+    fn hint_span(&mut self, span: &LabelSpan) {
+        // labeled text is drawn once, with an underline and an arrow line
+        let len = span.end.saturating_sub(span.start).min(Self::MAX_HINT);
+        self.size_hint = self
+            .size_hint
+            .saturating_add(len.saturating_mul(2).saturating_add(Self::LINE_HINT * 3));
+    }
+
+    /// Get a slice for `msg`, taking ownership of it if needed.
+    fn store(&mut self, msg: Cow<'a, str>) -> ffi::mu_Slice {
+        match msg {
+            Cow::Borrowed(msg) => msg.into(),
+            Cow::Owned(msg) => self.owned.alloc(&msg),
+        }
+    }
+
+    /// Render the report to a String.
+    ///
+    /// This is a convenience method that captures the rendered output
+    /// into a String instead of writing to stdout or a file.
+    ///
+    /// # Parameters
+    /// - `cache`: Source cache containing the code to display. Can be:
+    ///   - `&Cache` - A persistent cache with multiple sources
+    ///   - `&str` - A single source string (borrowed)
+    ///   - `(&str, &str)` - Source content and filename
+    ///   - `(&str, &str, i32)` - Source content, filename, and line offset for adjusting displayed line numbers
+    ///   - Custom types implementing `Source` trait
+    ///   - `()` - The sources attached with [`Report::with_source`]
+    ///
+    /// Rendering again with the same `&Cache`, e.g. to another output,
+    /// reuses the label layout of the last render until labels are added or
+    /// changed. Temporary caches are laid out on every render.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let output = Report::new()
+    ///     .with_title(Level::Error, "Syntax error")
+    ///     .with_label(0..3)
+    ///     .with_message("unexpected token")
+    ///     .render_to_string(("let x", "main.rs"))?;
+    /// println!("{}", output);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_string(&mut self, cache: impl Into<RawCache>) -> io::Result<String> {
+        self.render_to_bytes(cache).map(|bytes| {
+            String::from_utf8(bytes)
+                .unwrap_or_else(|e| String::from_utf8_lossy(&e.into_bytes()).into_owned())
+        })
+    }
+
+    /// Render the report to a String without validating the output.
+    ///
+    /// Same as [`render_to_string`](Report::render_to_string), but skips
+    /// the UTF-8 validation pass over the output, which matters for large
+    /// reports. Everything the renderer writes comes from the sources,
+    /// messages, character set and color codes, so the output is valid UTF-8
+    /// whenever those are.
+    ///
+    /// # Safety
+    ///
+    /// All sources in `cache`, their names and all bytes written by custom
+    /// [`Color`]s must be valid UTF-8. Sources built from `&str` or `String`
+    /// always are; [`OwnedSource`]s of raw bytes, custom [`Source`]s and
+    /// names from non-UTF-8 paths may not be.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_title(Level::Error, "Syntax error")
+    ///     .with_label(0..3)
+    ///     .with_message("unexpected token");
+    /// // SAFETY: the source is a `&str` and the default colors are ASCII
+    /// let output = unsafe { report.render_to_string_unchecked(("let x", "main.rs"))? };
+    /// assert_eq!(output, report.render_to_string(("let x", "main.rs"))?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub unsafe fn render_to_string_unchecked(
+        &mut self,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<String> {
+        // SAFETY: the caller guarantees all rendered input is valid UTF-8
+        self.render_to_bytes(cache)
+            .map(|bytes| unsafe { String::from_utf8_unchecked(bytes) })
+    }
+
+    /// Render the report to a byte buffer.
+    ///
+    /// The buffer is reserved up front from the estimated size of the
+    /// report, or from the size of the last render if it was larger.
+    fn render_to_bytes(&mut self, cache: impl Into<RawCache>) -> io::Result<Vec<u8>> {
+        let mut writer = Vec::with_capacity(self.size_hint.min(Self::MAX_HINT));
+        unsafe extern "C" fn string_writer_callback(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is a valid &mut Vec<u8> pointer passed to mu_writer below
+            let writer = unsafe { &mut *(ud as *mut Vec<u8>) };
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            writer.extend_from_slice(slice);
+            ffi::MU_OK
+        }
+        // SAFETY: self.ptr is valid, callback has correct signature, writer is valid for this scope
+        unsafe {
+            self.set_writer(
+                Some(string_writer_callback),
+                &mut writer as *mut Vec<u8> as *mut c_void,
+            )
+        };
+        self.render(cache)?;
+        self.size_hint = self.size_hint.max(writer.len());
+        Ok(writer)
+    }
+
+    /// Render the report directly to stdout.
+    ///
+    /// This is the most efficient way to display diagnostics,
+    /// writing directly to the terminal without intermediate buffering.
+    ///
+    /// # Parameters
+    /// - `cache`: Source cache or source content. Can be `&Cache`, `&str`,
+    ///   `(&str, &str)`, `(&str, &str, i32)`, or custom `Source` implementations.
+    ///   The third element (if present) is a line offset for adjusting displayed line numbers.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use musubi::{Report, Level};
+    /// Report::new()
+    ///     .with_title(Level::Error, "Error message")
+    ///     .with_label(0..5)
+    ///     .render_to_stdout(("let x = 42;", "main.rs"))?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_stdout(&mut self, cache: impl Into<RawCache>) -> io::Result<()> {
+        unsafe extern "C" fn stdout_writer_callback(
+            _ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            let mut stdout = io::stdout();
+            if stdout.write_all(slice).is_ok() && stdout.flush().is_ok() {
+                ffi::MU_OK
+            } else {
+                ffi::MU_ERRPARAM
+            }
+        }
+
+        // SAFETY: self.ptr is valid, callback has correct signature
+        unsafe { self.set_writer(Some(stdout_writer_callback), ptr::null_mut()) };
+        self.render(cache)
+    }
+
+    /// Render the report to any type implementing `Write`.
+    ///
+    /// This allows rendering to files, buffers, or any custom writer.
+    ///
+    /// # Parameters
+    /// - `writer`: Mutable reference to any type implementing `std::io::Write`
+    /// - `cache`: Source cache or source content. Can be `&Cache`, `&str`,
+    ///   `(&str, &str)`, `(&str, &str, i32)`, or custom `Source` implementations.
+    ///   The third element (if present) is a line offset for adjusting displayed line numbers.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// # use std::io::Write;
+    /// let mut buffer = Vec::new();
+    /// Report::new()
+    ///     .with_title(Level::Warning, "Deprecated")
+    ///     .with_label(0..3)
+    ///     .render_to_writer(&mut buffer, "let x = 1;")?;
+    /// assert!(!buffer.is_empty());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_writer<'b, W: Write>(
+        &'b mut self,
+        writer: &'b mut W,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        struct WriterWrapper<'a, W: Write> {
+            writer: &'a mut W,
+            report: *mut Report<'a>,
+        }
+
+        unsafe extern "C" fn writer_callback<W: Write>(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is a valid WriterWrapper<W> pointer passed to mu_writer below
+            let w = unsafe { &mut *(ud as *mut WriterWrapper<W>) };
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            match w.writer.write_all(slice) {
+                Ok(_) => ffi::MU_OK,
+                Err(e) => {
+                    // SAFETY: report pointer is setted below, and this function only called during render()
+                    unsafe { &mut *w.report }.src_err = Some(e);
+                    ffi::MU_ERR_WRITER
+                }
+            }
+        }
+        #[allow(clippy::unnecessary_cast)]
+        let mut wrapper = WriterWrapper {
+            writer,
+            report: self as *mut Report<'a> as *mut Report<'b>,
+        };
+        // SAFETY: mu_writer expects a valid Report pointer and writer callback
+        unsafe {
+            self.set_writer(
+                Some(writer_callback::<W>),
+                &mut wrapper as *mut _ as *mut c_void,
+            );
+        }
+        self.render(cache)
+    }
+
+    /// Render the report to a [`termcolor::WriteColor`].
+    ///
+    /// Instead of writing raw ANSI escape codes, every color change is turned
+    /// into a [`set_color`](termcolor::WriteColor::set_color) or
+    /// [`reset`](termcolor::WriteColor::reset) call on `writer`, using
+    /// [`ColorKind::color_spec`] for each kind. This works on Windows consoles
+    /// and with [`termcolor::BufferWriter`], and whether colors are actually
+    /// shown is decided by the writer's color choice.
+    ///
+    /// The color provider of the report's [`Config`] is ignored here, and
+    /// the config itself is left unchanged. Labels with their own
+    /// [`with_color`](Report::with_color) still work: the escape codes their
+    /// colors return are translated into color specs as well. Source text is
+    /// always written as is, even if it contains escape codes.
+    ///
+    /// Requires the `termcolor` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// use termcolor::{Buffer, ColorChoice, StandardStream};
+    ///
+    /// let mut report = Report::new()
+    ///     .with_title(Level::Error, "Syntax error")
+    ///     .with_label(0..3)
+    ///     .with_message("unexpected token");
+    ///
+    /// let mut buffer = Buffer::ansi();
+    /// report.render_to_write_color(&mut buffer, "let x")?;
+    /// assert!(buffer.as_slice().starts_with(b"\x1b[0m\x1b[31m"));
+    ///
+    /// let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+    /// report.render_to_write_color(&mut stdout, "let x")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "termcolor")]
+    pub fn render_to_write_color(
+        &mut self,
+        writer: &mut dyn termcolor::WriteColor,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<()> {
+        extern "C" fn color_fn(ud: *mut c_void, kind: ffi::mu_ColorKind) -> ffi::mu_Chunk {
+            // SAFETY: ud is the ColorSink set as the color provider below
+            let sink = unsafe { &mut *(ud as *mut ColorSink) };
+            sink.set_kind(ColorKind::from_ffi(kind));
+            c"".as_ptr()
+        }
+
+        unsafe extern "C" fn writer_callback(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is the ColorSink passed to mu_writer below
+            let sink = unsafe { &mut *(ud as *mut ColorSink) };
+            if sink.err.is_some() {
+                return ffi::MU_ERR_WRITER;
+            }
+            if len == 0 {
+                return ffi::MU_OK;
+            }
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            let result = sink.writer.write_all(slice);
+            sink.check(result)
+        }
+
+        let mut sink = ColorSink { writer, err: None };
+        let ud = &mut sink as *mut ColorSink as *mut c_void;
+        let outer = COLOR_SINK.replace(ud);
+        // SAFETY: self.ptr is valid, callback has correct signature, sink is valid for this scope
+        unsafe { self.set_writer(Some(writer_callback), ud) };
+        let result = self.render_colored(cache, Some((Some(color_fn), ud)));
+        COLOR_SINK.set(outer);
+        match sink.err.take() {
+            Some(error) => Err(self.writer_error(error).into()),
+            None => result,
+        }
+    }
+
+    /// Render the report line by line.
+    ///
+    /// The callback receives each whole output line (without the trailing
+    /// newline) together with its [`LineKind`], instead of the arbitrary byte
+    /// chunks a [`Write`] sink would see. Color codes, if enabled, are kept
+    /// inside the line they belong to.
+    ///
+    /// # Parameters
+    /// - `cache`: Source cache or source content. Can be `&Cache`, `&str`,
+    ///   `(&str, &str)`, `(&str, &str, i32)`, or custom `Source` implementations.
+    /// - `f`: Called once per rendered line, in output order.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Report, Level, LineKind};
+    /// let mut kinds = Vec::new();
+    /// Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "Syntax error")
+    ///     .with_label(0..3)
+    ///     .with_message("unexpected token")
+    ///     .render_lines("let x", |line, kind| kinds.push((kind, line.to_string())))?;
+    /// assert_eq!(kinds[0], (LineKind::Header, "Error: Syntax error".to_string()));
+    /// assert!(kinds.iter().any(|(k, l)| *k == LineKind::Source && l.ends_with("let x")));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_lines<F: FnMut(&str, LineKind)>(
+        &mut self,
+        cache: impl Into<RawCache>,
+        f: F,
+    ) -> io::Result<()> {
+        struct LineSplitter<F> {
+            report: *const ffi::mu_Report,
+            buf: Vec<u8>,
+            f: F,
+        }
+
+        impl<F: FnMut(&str, LineKind)> LineSplitter<F> {
+            fn emit(&mut self) {
+                // SAFETY: report is the valid mu_Report currently rendering
+                let kind = LineKind::from_ffi(unsafe { ffi::mu_linekind(self.report) });
+                (self.f)(&String::from_utf8_lossy(&self.buf), kind);
+                self.buf.clear();
+            }
+        }
+
+        unsafe extern "C" fn lines_writer_callback<F: FnMut(&str, LineKind)>(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is a valid LineSplitter<F> pointer passed to mu_writer below
+            let w = unsafe { &mut *(ud as *mut LineSplitter<F>) };
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let mut slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            while let Some(pos) = slice.iter().position(|&b| b == b'\n') {
+                w.buf.extend_from_slice(&slice[..pos]);
+                w.emit();
+                slice = &slice[pos + 1..];
+            }
+            w.buf.extend_from_slice(slice);
+            ffi::MU_OK
+        }
+
+        let mut splitter = LineSplitter {
+            report: self.ptr,
+            buf: Vec::new(),
+            f,
+        };
+        self.writing = &mut splitter.report;
+        // SAFETY: self.ptr is valid, callback has correct signature, splitter is valid for this scope
+        unsafe {
+            self.set_writer(
+                Some(lines_writer_callback::<F>),
+                &mut splitter as *mut LineSplitter<F> as *mut c_void,
+            )
+        };
+        let result = self.render(cache);
+        self.writing = ptr::null_mut();
+        result?;
+        if !splitter.buf.is_empty() {
+            splitter.emit();
+        }
+        Ok(())
+    }
+
+    /// Lay out the report and tell where each label message ends up.
+    ///
+    /// The report is rendered without producing output, recording for every
+    /// drawn label message its output row and display column, and the
+    /// source line it annotates. Editor integrations can use them to place
+    /// inline hints consistent with the terminal rendering. Labels without a
+    /// message are left out; the result is in output order.
+    ///
+    /// Columns count characters, like the rest of the layout for the
+    /// built-in character sets.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{LabelPosition, Level, Report};
+    /// let positions = Report::new()
+    ///     .with_title(Level::Error, "Syntax error")
+    ///     .with_label(4..5)
+    ///     .with_message("unexpected token")
+    ///     .measure("let x")?;
+    /// assert_eq!(positions, [LabelPosition { label: 0, row: 5, column: 13, source_row: 3, line: 1 }]);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn measure(&mut self, cache: impl Into<RawCache>) -> io::Result<Vec<LabelPosition>> {
+        struct Measure {
+            report: *const ffi::mu_Report,
+            owner: *const ffi::mu_Report,
+            row: usize,
+            column: usize,
+            source_row: usize,
+            lines: Vec<usize>,
+            positions: Vec<LabelPosition>,
+        }
+
+        unsafe extern "C" fn measure_writer_callback(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is a valid Measure pointer passed to mu_writer below
+            let m = unsafe { &mut *(ud as *mut Measure) };
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            if slice.first() == Some(&0x1b) {
+                return ffi::MU_OK;
+            }
+            // SAFETY: report is the valid mu_Report currently rendering
+            let (kind, label) = unsafe { (ffi::mu_linekind(m.report), ffi::mu_msglabel(m.report)) };
+            if kind == ffi::mu_LineKind::MU_LINE_SOURCE {
+                m.source_row = m.row;
+            }
+            if let Ok(label) = usize::try_from(label)
+                && m.report == m.owner
+                && m.positions.last().is_none_or(|p| p.label != label)
+            {
+                m.positions.push(LabelPosition {
+                    label,
+                    row: m.row,
+                    column: m.column,
+                    source_row: m.source_row,
+                    line: m.lines.get(label).copied().unwrap_or(0),
+                });
+            }
+            let text = String::from_utf8_lossy(slice);
+            for (i, part) in text.split('\n').enumerate() {
+                if i > 0 {
+                    (m.row, m.column) = (m.row + 1, 0);
+                }
+                m.column += unicode_width(part) as usize;
+            }
+            ffi::MU_OK
+        }
+
+        let cache = self.resolve_cache(cache);
+        let index_type = (self.config.as_ref()).map_or(IndexType::Char, Config::index_type);
+        let lines = (self.diagnostic_in(cache.as_ptr()).labels().iter())
+            .map(|label| {
+                let span = label.span();
+                let last = span.end.saturating_sub(1).max(span.start);
+                source_location(cache.as_ptr(), span.src_id, last, index_type)
+                    .map_or(0, |(line, _)| line)
+            })
+            .collect();
+        let mut measure = Measure {
+            report: self.ptr,
+            owner: self.ptr,
+            row: 0,
+            column: 0,
+            source_row: 0,
+            lines,
+            positions: Vec::new(),
+        };
+        // SAFETY: self.ptr is valid, callback has correct signature, measure is valid for this scope
+        unsafe {
+            self.set_writer(
+                Some(measure_writer_callback),
+                &mut measure as *mut Measure as *mut c_void,
+            )
+        };
+        self.writing = &mut measure.report;
+        let result = self.render(cache);
+        self.writing = ptr::null_mut();
+        result?;
+        Ok(measure.positions)
+    }
+
+    /// Render the report to a string, with the source line shown by each
+    /// output line.
+    ///
+    /// The map has an entry per output line, from the header at index 0:
+    /// source lines, and the underline and arrow lines below them, map to
+    /// the line they show; other lines map to [`None`]. Wrappers that
+    /// paginate or hyperlink the output use it to resolve a position in the
+    /// output back to the source.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report, SourceLine};
+    /// let (output, map) = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(12..13)
+    ///     .with_message("not found")
+    ///     .render_line_map(("let a = 1;\nlet b = c;\n", "main.rs"))?;
+    /// assert_eq!(output.lines().count(), map.len());
+    /// assert_eq!(map[3], Some(SourceLine { src_id: 0, line: 2 }));
+    /// assert!(output.lines().nth(3).unwrap().ends_with("let b = c;"));
+    /// assert_eq!(map[0], None);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_line_map(
+        &mut self,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<(String, Vec<Option<SourceLine>>)> {
+        struct LineMap {
+            report: *const ffi::mu_Report,
+            cache: *mut ffi::mu_Cache,
+            output: Vec<u8>,
+            map: Vec<Option<SourceLine>>,
+            row: usize,
+        }
+
+        unsafe extern "C" fn line_map_writer_callback(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is a valid LineMap pointer passed to mu_writer below
+            let m = unsafe { &mut *(ud as *mut LineMap) };
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            let (mut src_id, mut line_no) = (ffi::mu_Id(0), 0);
+            // SAFETY: report is the valid mu_Report currently rendering
+            let shown = unsafe { ffi::mu_sourceline(m.report, &mut src_id, &mut line_no) } != 0;
+            let line = shown.then(|| {
+                let src = load_source(m.cache, src_id);
+                // SAFETY: src was loaded by the render
+                let offset = src.map_or(0, |src| unsafe { (*src).line_no_offset }) as isize;
+                SourceLine {
+                    src_id: src_id.0 as usize,
+                    line: (line_no as isize + offset + 1).max(1) as usize,
+                }
+            });
+            let mut parts = slice.split(|&b| b == b'\n').peekable();
+            while let Some(part) = parts.next() {
+                let ended = parts.peek().is_some();
+                if m.map.len() == m.row && (ended || !part.is_empty()) {
+                    m.map.push(line);
+                }
+                m.row += ended as usize;
+            }
+            m.output.extend_from_slice(slice);
+            ffi::MU_OK
+        }
+
+        let cache = self.resolve_cache(cache);
+        let mut line_map = LineMap {
+            report: self.ptr,
+            cache: cache.as_ptr(),
+            output: Vec::new(),
+            map: Vec::new(),
+            row: 0,
+        };
+        // SAFETY: self.ptr is valid, callback has correct signature, line_map is valid for this scope
+        unsafe {
+            self.set_writer(
+                Some(line_map_writer_callback),
+                &mut line_map as *mut LineMap as *mut c_void,
+            )
+        };
+        self.writing = &mut line_map.report;
+        let result = self.render(cache);
+        self.writing = ptr::null_mut();
+        result?;
+        let output = String::from_utf8(line_map.output)
+            .unwrap_or_else(|e| String::from_utf8_lossy(&e.into_bytes()).into_owned());
+        Ok((output, line_map.map))
+    }
+
+    /// Set the writer of the report, remembered for the related reports.
+    ///
+    /// # Safety
+    /// `ud` must be valid for `writer` until the next render completes.
+    unsafe fn set_writer(&mut self, writer: ffi::mu_Writer, ud: *mut c_void) {
+        self.writer = (writer, ud);
+        // SAFETY: self.ptr is valid, ud is valid as guaranteed by the caller
+        unsafe { ffi::mu_writer(self.ptr, writer, ud) };
+    }
+
+    /// Render the related reports with the writer of this report, indented.
+    fn render_related(
+        &mut self,
+        cache: &RawCache,
+        color: Option<(ffi::mu_Color, *mut c_void)>,
+    ) -> io::Result<()> {
+        struct Indent {
+            writer: (ffi::mu_Writer, *mut c_void),
+            line_start: bool,
+            written: usize,
+            /// Prefix of the header line of the related report
+            first: String,
+            /// Prefix of the other lines
+            rest: String,
+        }
+
+        /// The prefixes of the header and the other lines of a related
+        /// report, `width` columns wide, with the connectors of `char_set`
+        /// if any.
+        fn related_prefixes(
+            width: usize,
+            char_set: Option<&CharSet>,
+            last: bool,
+        ) -> (String, String) {
+            let Some(cs) = char_set.filter(|_| width > 0) else {
+                return (" ".repeat(width), " ".repeat(width));
+            };
+            let mut first = String::from(if last { cs.lbot } else { cs.lcross });
+            if width > 1 {
+                first.extend(std::iter::repeat_n(cs.hbar, width.saturating_sub(3)));
+                first.push(cs.rarrow);
+            }
+            if width > 2 {
+                first.push(' ');
+            }
+            let bar = if last { ' ' } else { cs.vbar };
+            (first, format!("{bar}{}", " ".repeat(width - 1)))
+        }
+
+        unsafe extern "C" fn indent_writer_callback(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is a valid Indent pointer passed to set_writer below
+            let w = unsafe { &mut *(ud as *mut Indent) };
+            let (Some(writer), inner) = w.writer else {
+                return ffi::MU_OK;
+            };
+            // SAFETY: data and len are provided by C library, guaranteed to be valid
+            let slice = unsafe { std::slice::from_raw_parts(data as *const u8, len) };
+            for line in slice.split_inclusive(|&b| b == b'\n') {
+                if w.line_start {
+                    let prefix = std::mem::replace(&mut w.first, w.rest.clone());
+                    let prefix = match line {
+                        b"\n" => prefix.trim_end(),
+                        _ => &prefix,
+                    };
+                    // SAFETY: inner is valid for writer, set by the parent report
+                    let r = unsafe { writer(inner, prefix.as_ptr().cast(), prefix.len()) };
+                    if r != ffi::MU_OK {
+                        return r;
+                    }
+                    w.written += prefix.len();
+                }
+                w.line_start = line.ends_with(b"\n");
+                // SAFETY: see above
+                let r = unsafe { writer(inner, line.as_ptr().cast(), line.len()) };
+                if r != ffi::MU_OK {
+                    return r;
+                }
+                w.written += line.len();
+            }
+            ffi::MU_OK
+        }
+
+        let default;
+        let config = match &self.config {
+            Some(config) => config,
+            None => {
+                default = Config::new();
+                &default
+            }
+        };
+        let width = config.related_indent;
+        let char_set = config.related_connectors.then(|| config.drawn_char_set());
+        let mut indent = Indent {
+            writer: self.writer,
+            line_start: true,
+            written: 0,
+            first: String::new(),
+            rest: String::new(),
+        };
+        let ud = &mut indent as *mut Indent as *mut c_void;
+        let writing = self.writing;
+        let mut result = Ok(());
+        let count = self.related.len();
+        for (i, related) in self.related.iter_mut().enumerate() {
+            // SAFETY: ud points to indent, which is only used by the writer
+            // while the related report renders
+            let indent = unsafe { &mut *(ud as *mut Indent) };
+            (indent.first, indent.rest) =
+                related_prefixes(width, char_set.as_ref(), i + 1 == count);
+            indent.line_start = true;
+            let inherit = related.config.is_none();
+            if inherit {
+                related.config = self.config.clone();
+            }
+            if let RawCache::Owned(_) = cache {
+                // SAFETY: related.ptr is a valid mu_Report pointer
+                unsafe { ffi::mu_relayout(related.ptr) };
+            }
+            related.writing = writing;
+            if !writing.is_null() {
+                // SAFETY: writing points into the writer state of this render
+                unsafe { *writing = related.ptr };
+            }
+            // SAFETY: indent outlives the render below
+            unsafe { related.set_writer(Some(indent_writer_callback), ud) };
+            result = related.render_colored(RawCache::Borrowed(cache.as_ptr()), color);
+            related.writing = ptr::null_mut();
+            if inherit {
+                related.config = None;
+            }
+            if result.is_err() {
+                break;
+            }
+        }
+        if !writing.is_null() {
+            // SAFETY: see above
+            unsafe { *writing = self.ptr };
+        }
+        // SAFETY: the renders using ud are done
+        self.written += unsafe { &*(ud as *mut Indent) }.written;
+        match (result, self.src_err.take()) {
+            (Err(err), error) => match Error::from_io(&err) {
+                /* counting the bytes of this report, and with our writer's error */
+                Some(Error::Writer { section, .. }) => {
+                    let section = *section;
+                    let error = error.unwrap_or_else(|| io::Error::other(err.to_string()));
+                    let written = self.written;
+                    Err(Error::Writer {
+                        error,
+                        written,
+                        section,
+                    }
+                    .into())
+                }
+                _ => Err(err),
+            },
+            (result, _) => result,
+        }
+    }
+
+    /// The cache to render with: `cache`, or the sources attached with
+    /// [`Report::with_source`] if `cache` is empty.
+    pub(crate) fn resolve_cache(&self, cache: impl Into<RawCache>) -> RawCache {
+        let cache = cache.into();
+        if cache.as_ptr().is_null() {
+            return RawCache::Borrowed(self.sources);
+        }
+        cache
+    }
+
+    fn render(&mut self, cache: impl Into<RawCache>) -> io::Result<()> {
+        self.render_colored(cache, None)
+    }
+
+    /// Render with the color provider of the config replaced by `color`,
+    /// for this report and its related reports.
+    fn render_colored(
+        &mut self,
+        cache: impl Into<RawCache>,
+        color: Option<(ffi::mu_Color, *mut c_void)>,
+    ) -> io::Result<()> {
+        let replaced = self.config.as_mut().and_then(Config::probe_char_set);
+        let result = self.render_config(cache, color);
+        // the writer state lives on the stack of the caller, don't keep
+        // pointing at it
+        // SAFETY: a null writer is never called
+        unsafe { self.set_writer(None, ptr::null_mut()) };
+        if let (Some(cfg), Some(char_set)) = (self.config.as_mut(), replaced) {
+            cfg.inner.char_set = char_set;
+        }
+        result
+    }
+
+    /// Render once the char set probe has run.
+    fn render_config(
+        &mut self,
+        cache: impl Into<RawCache>,
+        color: Option<(ffi::mu_Color, *mut c_void)>,
+    ) -> io::Result<()> {
+        let mut buf = [0u8; ffi::sizes::COLOR_CODE];
+        if let Some(cfg) = self.config.as_mut()
+            && let Some(color_ud) = cfg.color_ud.as_mut()
+        {
+            color_ud.color_buf = &mut buf as *mut [u8; ffi::sizes::COLOR_CODE];
+        }
+        for color_ud in self.color_uds.iter_mut() {
+            color_ud.color_buf = &mut buf as *mut [u8; ffi::sizes::COLOR_CODE];
+        }
+        // a copy of the config with the color provider replaced, leaving
+        // the one of the report untouched
+        let mut colored = None;
+        if let Some((color, color_ud)) = color {
+            let mut inner =
+                (self.config.as_ref()).map_or_else(|| Config::default().inner, |cfg| cfg.inner);
+            (inner.color, inner.color_ud) = (color, color_ud);
+            colored = Some(inner);
+        }
+        if let Some(inner) = &colored {
+            // SAFETY: self.ptr is valid, inner outlives the render below and
+            // is replaced before returning
+            unsafe { ffi::mu_config(self.ptr, inner) };
+        } else if let Some(cfg) = &self.config {
+            // SAFETY: self.ptr is valid, cfg.inner is a valid config with lifetime guarantees
+            unsafe { ffi::mu_config(self.ptr, &cfg.inner) };
+        }
+        let cache = self.resolve_cache(cache);
+        self.place_line_cols(cache.as_ptr());
+        if let RawCache::Owned(_) = cache {
+            // a temporary cache may reuse the address of the last one
+            // SAFETY: self.ptr is a valid mu_Report pointer
+            unsafe { ffi::mu_relayout(self.ptr) };
+        }
+        struct Counter {
+            writer: (ffi::mu_Writer, *mut c_void),
+            written: usize,
+        }
+
+        unsafe extern "C" fn counting_writer_callback(
+            ud: *mut c_void,
+            data: *const c_char,
+            len: usize,
+        ) -> c_int {
+            // SAFETY: ud is a valid Counter pointer passed to mu_writer below
+            let c = unsafe { &mut *(ud as *mut Counter) };
+            let (Some(writer), inner) = c.writer else {
+                return ffi::MU_OK;
+            };
+            // SAFETY: inner is valid for writer, as set by set_writer
+            let r = unsafe { writer(inner, data, len) };
+            if r == ffi::MU_OK {
+                c.written += len;
+            }
+            r
+        }
+
+        let mut counter = Counter {
+            writer: self.writer,
+            written: 0,
+        };
+        if self.writer.0.is_some() {
+            let ud = &mut counter as *mut Counter as *mut c_void;
+            // SAFETY: self.ptr is valid, counter outlives the render below
+            unsafe { ffi::mu_writer(self.ptr, Some(counting_writer_callback), ud) };
+        }
+        // SAFETY: self.ptr is valid, all sources and labels have been properly registered
+        let code = unsafe { ffi::mu_render(self.ptr, cache.as_ptr()) };
+        if self.writer.0.is_some() {
+            // SAFETY: self.writer was set by set_writer, and is still valid
+            unsafe { ffi::mu_writer(self.ptr, self.writer.0, self.writer.1) };
+        }
+        self.written = counter.written;
+        if colored.is_some() {
+            let cfg = self.config.as_ref().map_or(ptr::null(), |cfg| &cfg.inner);
+            // SAFETY: self.ptr is valid; a null config restores the default
+            unsafe { ffi::mu_config(self.ptr, cfg) };
+        }
+        match code {
+            ffi::MU_OK => self.render_related(&cache, color),
+            code => Err(self.render_error(code, cache.as_ptr()).into()),
+        }
+    }
+
+    /// The [`Error::Writer`] of `error`, with how far the last render got.
+    fn writer_error(&self, error: io::Error) -> Error {
+        Error::Writer {
+            error,
+            written: self.written,
+            // SAFETY: self.ptr is a valid mu_Report pointer
+            section: LineKind::from_ffi(unsafe { ffi::mu_linekind(self.ptr) }),
+        }
+    }
+
+    /// The [`Error`] of a failed `mu_render`.
+    fn render_error(&mut self, code: c_int, cache: *mut ffi::mu_Cache) -> Error {
+        match code {
+            ffi::MU_ERR_SRCINIT => Error::SourceInit(self.src_err.take().unwrap_or_else(|| {
+                // SAFETY: self.ptr is valid; its current source is the one
+                // whose init failed
+                let src = unsafe { ffi::mu_source(self.ptr) };
+                // SAFETY: cache is null or a valid cache
+                let count = unsafe { ffi::mu_sourcecount(cache) };
+                // SAFETY: id is in range
+                match (0..count).find(|&id| unsafe { ffi::mu_getsource(cache, id) } == src) {
+                    Some(id) => init_error(cache, id),
+                    None => io::Error::other("source init error"),
+                }
+            })),
+            ffi::MU_ERR_WRITER => {
+                let error = self.src_err.take();
+                self.writer_error(error.unwrap_or_else(|| io::Error::other("writer error")))
+            }
+            ffi::MU_ERRSRC => {
+                // SAFETY: cache is null or a valid cache
+                let source_count = unsafe { ffi::mu_sourcecount(cache) } as usize;
+                let src_id = (self.diagnostic.spans())
+                    .map(|span| span.src_id())
+                    .find(|&id| id >= source_count)
+                    .unwrap_or(source_count);
+                Error::UnknownSourceId {
+                    src_id,
+                    source_count,
+                }
+            }
+            ffi::MU_ERRMEM => Error::OutOfMemory,
+            code => Error::Code(code),
+        }
+    }
+}
+
+/// Internal buffer for character set conversion to C representation.
+///
+/// Converts Rust [`CharSet`] into a C-compatible array of chunk pointers.
+/// Each character is encoded as: `[length_byte, utf8_byte1, utf8_byte2, ...]`
+///
+/// The buffer contains 23 entries (one for each CharSet field), each up to
+/// 8 bytes (1 length byte + up to 7 UTF-8 bytes, though most characters are 1-3 bytes).
+///
+/// It is built once by [`Config::with_char_set`] and boxed, so `chars` can
+/// point into `buf` for as long as the config lives.
+struct CharSetBuf {
+    /// 23 characters × 8 bytes each (length prefix + UTF-8 data)
+    buf: [[u8; 8]; 26],
+    /// Pointers to the entries of `buf`, as passed to the C library
+    chars: ffi::mu_Charset,
+}
+
+impl CharSetBuf {
+    /// Convert `char_set` into a boxed buffer.
+    fn new(char_set: CharSet) -> Box<Self> {
+        #[inline]
+        fn char_to_slice(c: char) -> [u8; 8] {
+            if c == '.' {
+                return [3, b'.', b'.', b'.', 0, 0, 0, 0];
+            }
+            let mut buf = [0u8; 8];
+            let s = c.encode_utf8(&mut buf);
+            let len = s.len() as u8;
+            let mut result = [0u8; 8];
+            result[0] = len;
+            result[1..(len as usize + 1)].copy_from_slice(s.as_bytes());
+            result
+        }
+        let mut cs = Box::new(CharSetBuf {
+            chars: [ptr::null(); 26],
+            buf: char_set.chars().map(char_to_slice),
+        });
+        let cs_ref = &mut *cs;
+        for (chunk, slice) in cs_ref.chars.iter_mut().zip(&cs_ref.buf) {
+            *chunk = slice.as_ptr() as *const c_char;
+        }
+        cs
+    }
+}
+
+/// Calculate the display width of a string (simple ASCII version).
+/// For full Unicode support, consider using the unicode-width crate.
+fn unicode_width(s: &str) -> i32 {
+    s.chars().count() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_snapshot;
+
+    fn remove_trailing_whitespace(s: &str) -> String {
+        s.lines()
+            .map(|line| line.trim_end())
+            .collect::<Vec<&str>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_basic_report() {
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "Test error")
+            .with_code("E001")
+            .with_label(0..3)
+            .with_message("this is a test");
+
+        let output = report.render_to_string(("let x = 42;", "test.rs")).unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output),
+            @r##"
+            [E001] Error: Test error
+               ,-[ test.rs:1:1 ]
+               |
+             1 | let x = 42;
+               | ^|^
+               |  `--- this is a test
+            ---'
+            "##
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let config = Config::new()
+            .with_compact(true)
+            .with_char_set_ascii()
+            .with_color_disabled();
+
+        let mut report = Report::new()
+            .with_config(config)
+            .with_title(Level::Warning, "Test warning")
+            .with_label(0..5)
+            .with_message("test");
+
+        let output = report.render_to_string(("hello", "test.rs")).unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output),
+            @r##"
+            Warning: Test warning
+               ,-[ test.rs:1:1 ]
+             1 |hello
+               |^^|^^
+               |  `--- test
+            "##
+        );
+    }
+
+    #[test]
+    fn test_config_getters() {
+        let config = Config::new();
+        assert!(!config.is_compact());
+        assert!(config.is_underlines());
+        assert_eq!(config.tab_width(), 4);
+        assert_eq!(config.limit_width(), 0);
+        assert_eq!(config.label_attach(), LabelAttach::Middle);
+        assert_eq!(config.index_type(), IndexType::Char);
+        assert_eq!(config.char_set().hbar, CharSet::unicode().hbar);
+
+        let config = config
+            .with_compact(true)
+            .with_context_lines(2)
+            .with_label_attach(LabelAttach::End)
+            .with_char_set_ascii()
+            .with_color_default();
+        assert!(config.is_compact());
+        assert_eq!(config.context_lines(), 2);
+        assert_eq!(config.label_attach(), LabelAttach::End);
+        assert_eq!(config.char_set().ellipsis, '.');
+        assert!(config.is_color_enabled());
+        assert!(!config.with_color_disabled().is_color_enabled());
+    }
+
+    #[test]
+    fn test_markup() {
+        let mut report = Report::new()
+            .with_config(
+                Config::new()
+                    .with_char_set_ascii()
+                    .with_color_default()
+                    .with_markup(true),
+            )
+            .with_title(Level::Error, "mismatched **types**")
+            .with_label(8..10)
+            .with_message("expected **`i32`**")
+            .with_label(0..3)
+            .with_message("**unclosed")
+            .with_note("found **`&str`**");
+
+        let output = report.render_to_string("let x = \"\";").unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output.replace('\x1b', "~")),
+            @r##"
+        ~[31mError:~[0m mismatched ~[1mtypes~[0m
+        ~[38;5;246m   ,-[~[0m <unknown>:1:1 ~[38;5;246m]~[0m
+        ~[38;5;246m   |~[0m
+        ~[38;5;246m 1 |~[0m ~[39mlet~[0m~[38;5;249m x = ~[0m~[39m""~[0m~[38;5;249m;~[0m
+        ~[38;5;240m   |~[0m ~[39m^|^~[0m     ~[39m^|~[0m
+        ~[38;5;240m   |~[0m  ~[39m|~[0m       ~[39m`--~[0m expected ~[1m`i32`~[0m
+        ~[38;5;240m   |~[0m  ~[39m|~[0m
+        ~[38;5;240m   |~[0m  ~[39m`----------~[0m ~[1munclosed~[0m
+        ~[38;5;240m   |~[0m
+        ~[38;5;240m   |~[0m ~[38;5;115mNote: found ~[0m~[1m`&str`~[0m~[38;5;115m~[0m
+        ~[38;5;246m---'~[0m
+        "##
+        );
+
+        let config = Config::new()
+            .with_char_set_ascii()
+            .with_color_disabled()
+            .with_markup(true);
+        let mut report = report.with_config(config);
+        let output = report.render_to_string("let x = \"\";").unwrap();
+        assert!(output.contains("|  `---------- unclosed\n"));
+        assert!(output.contains("Note: found `&str`\n"));
+    }
+
+    #[test]
+    fn test_inline_code() {
+        let mut report = Report::new()
+            .with_config(
+                Config::new()
+                    .with_char_set_ascii()
+                    .with_color_default()
+                    .with_markup(true)
+                    .with_inline_code(true),
+            )
+            .with_title(Level::Error, "cannot find `foo` in **this `**` scope**")
+            .with_label(0..3)
+            .with_message("not `styled`")
+            .with_help("use `bar` instead")
+            .with_note("unclosed `code");
+
+        let output = report.render_to_string("foo;").unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output.replace('\x1b', "~")),
+            @r##"
+        ~[31mError:~[0m cannot find ~[38;5;180m`foo`~[0m in ~[1mthis ~[0m~[38;5;180m`**`~[0m~[1m scope~[0m
+        ~[38;5;246m   ,-[~[0m <unknown>:1:1 ~[38;5;246m]~[0m
+        ~[38;5;246m   |~[0m
+        ~[38;5;246m 1 |~[0m ~[39mfoo~[0m~[38;5;249m;~[0m
+        ~[38;5;240m   |~[0m ~[39m^|^~[0m
+        ~[38;5;240m   |~[0m  ~[39m`---~[0m not `styled`
+        ~[38;5;240m   |~[0m
+        ~[38;5;240m   |~[0m ~[38;5;115mHelp: use ~[0m~[38;5;180m`bar`~[0m~[38;5;115m instead~[0m
+        ~[38;5;240m   |~[0m
+        ~[38;5;240m   |~[0m ~[38;5;115mNote: unclosed ~[0m~[38;5;180m`code~[0m~[38;5;115m~[0m
+        ~[38;5;246m---'~[0m
+        "##
+        );
+        assert!(report.config.as_ref().unwrap().is_inline_code());
+    }
+
+    #[test]
+    fn test_emitter_summary() {
+        let config = Config::new().with_char_set_ascii().with_color_disabled();
+        let mut emitter: Emitter = [Level::Error, Level::Warning, Level::Error]
+            .into_iter()
+            .enumerate()
+            .map(|(i, level)| {
+                Report::new()
+                    .with_config(config.clone())
+                    .with_title(level, "problem")
+                    .with_label(i * 4..i * 4 + 3)
+            })
+            .collect();
+        emitter.extend([
+            Report::new()
+                .with_config(config.clone())
+                .with_title(Level::Note, "context"),
+            Report::new()
+                .with_config(config)
+                .with_title("Lint", "style"),
+        ]);
+        assert_eq!(
+            (
+                emitter.len(),
+                emitter.error_count(),
+                emitter.warning_count()
+            ),
+            (5, 2, 1)
+        );
+        let mut emitter = emitter.with_summary(true);
+        let output = emitter.render_to_string("foo bar baz").unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: problem
+           ,-[ <unknown>:1:1 ]
+           |
+         1 | foo bar baz
+           | ^^^
+        ---'
+        Warning: problem
+           ,-[ <unknown>:1:5 ]
+           |
+         1 | foo bar baz
+           |     ^^^
+        ---'
+        Error: problem
+           ,-[ <unknown>:1:9 ]
+           |
+         1 | foo bar baz
+           |         ^^^
+        ---'
+        Note: context
+        Lint: style
+        2 errors, 1 warning, 1 note, 1 lint emitted
+        ");
+    }
+
+    #[test]
+    fn test_emitter_grouping() {
+        let emitter = Emitter::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "unused variable: `x`")
+                    .with_code("W001")
+                    .with_label(8..9)
+                    .with_message("never read")
+                    .with_help("prefix it with an underscore: `_x`"),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "cannot find value `y`")
+                    .with_label(17..18)
+                    .with_message("not found"),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "variable does not need to be mutable")
+                    .with_label(4..9)
+                    .with_message("remove `mut`"),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "cannot find value `z`")
+                    .with_label(18..19)
+                    .with_message("not found"),
+            );
+
+        let mut emitter = emitter.with_grouping(true);
+        let output = emitter.render_to_string("let mut x = 1; y;\nz;").unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output),
+            @r"
+        [W001] Warning: unused variable: `x`
+           ,-[ <unknown>:1:5 ]
+           |
+         1 | let mut x = 1; y;
+           |     ^^|^|        |
+           |       | |        `-- not found
+           |       | |
+           |       | `----------- never read
+           |       |
+           |       `------------- remove `mut`
+           |
+           | Help: prefix it with an underscore: `_x`
+           |
+           | Note 1: Error: cannot find value `y`
+           |
+           | Note 2: Warning: variable does not need to be mutable
+        ---'
+        Error: cannot find value `z`
+           ,-[ <unknown>:2:1 ]
+           |
+         2 | z;
+           | |
+           | `-- not found
+        ---'
+        "
+        );
+
+        let mut emitter = emitter.with_grouping(false);
+        let output = emitter.render_to_string("let mut x = 1; y;\nz;").unwrap();
+        assert_eq!(output.matches("let mut x = 1; y;").count(), 3);
+
+        // a label over both lines joins the reports on either
+        let mut emitter = Emitter::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_grouping(true)
+            .with_report(Report::new().with_title(Level::Error, "a").with_label(0..3))
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "b")
+                    .with_label(18..19),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "c")
+                    .with_label(15..19),
+            );
+        let output = emitter.render_to_string("let mut x = 1; y;\nz;").unwrap();
+        let headers = output.lines().filter(|l| l.starts_with("Error"));
+        assert_eq!(headers.collect::<Vec<_>>(), ["Error: a"]);
+
+        // reports keep their own config, the one of the emitter fills in
+        let mut emitter = Emitter::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_report(
+                Report::new()
+                    .with_config(Config::new().with_color_disabled())
+                    .with_title(Level::Error, "own config")
+                    .with_label(0..3),
+            )
+            .with_report(Report::new().with_title(Level::Error, "emitter config"));
+        let output = emitter.render_to_string("let").unwrap();
+        assert!(output.contains("╭─[ <unknown>:1:1 ]"));
+        assert!(output.ends_with("Error: emitter config\n"));
+    }
+
+    #[test]
+    fn test_emitter_watch() {
+        let emitter = |title| {
+            Emitter::new()
+                .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+                .with_report(
+                    Report::new()
+                        .with_title(Level::Error, "cannot find value `y`")
+                        .with_label(17..18),
+                )
+                .with_report(
+                    Report::new()
+                        .with_title(Level::Warning, title)
+                        .with_label(8..9),
+                )
+        };
+        let mut state = WatchState::new();
+        let render = |state: &mut WatchState, title, src| {
+            let mut out = Vec::new();
+            emitter(title).render_watch(state, &mut out, src).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let output = render(&mut state, "unused variable", "let mut x = 1; y;");
+        assert!(output.starts_with("\x1b[J"));
+        assert_eq!(output.matches("let mut x = 1; y;").count(), 2);
+        assert_eq!(
+            render(&mut state, "unused variable", "let mut x = 1; y;"),
+            ""
+        );
+
+        // only the second report changed: redraw from its title on
+        let output = render(&mut state, "variable never read", "let mut x = 1; y;");
+        assert_snapshot!(
+            remove_trailing_whitespace(&output).replace('\x1b', "<ESC>"),
+            @r"
+        <ESC>[6F<ESC>[JWarning: variable never read
+           ,-[ <unknown>:1:9 ]
+           |
+         1 | let mut x = 1; y;
+           |         ^
+        ---'
+        "
+        );
+
+        // the source changed: both reports are rendered again, but only
+        // redrawn from the first changed line on
+        let output = render(&mut state, "variable never read", "let mut x = 2; y;");
+        assert!(output.starts_with("\x1b[9F\x1b[J 1 | let mut x = 2; y;"));
+        assert_eq!(output.matches("let mut x = 2; y;").count(), 2);
+
+        state.reset();
+        let output = render(&mut state, "variable never read", "let mut x = 2; y;");
+        assert!(output.starts_with("\x1b[J"));
+    }
+
+    #[test]
+    fn test_label_obj() {
+        let mut cg = ColorGenerator::new();
+        let color = cg.next_color();
+        let chained = Report::new()
+            .with_title(Level::Error, "mismatched types")
+            .with_label(13..15)
+            .with_message("found string")
+            .with_color(&color)
+            .with_order(1)
+            .with_primary_label(7..10)
+            .with_message("expected `i32`")
+            .with_priority(2)
+            .render_to_string("let x: i32 = \"\";")
+            .unwrap();
+
+        let labels: Vec<Label> = vec![
+            Label::new(13..15)
+                .with_message("found string")
+                .with_color(&color)
+                .with_order(1),
+            Label::new(7..10)
+                .with_message("expected `i32`")
+                .with_primary(true)
+                .with_priority(2),
+        ];
+        assert_eq!(labels[1].span(), LabelSpan::from(7..10));
+        assert_eq!(labels[1].message(), Some("expected `i32`"));
+        let report = labels.into_iter().fold(
+            Report::new().with_title(Level::Error, "mismatched types"),
+            Report::with_label_obj,
+        );
+        assert_eq!(
+            report.to_diagnostic().labels()[1],
+            DiagnosticLabel::new(7..10)
+                .with_message("expected `i32`")
+                .with_primary(true)
+        );
+        let mut report = report;
+        let output = report.render_to_string("let x: i32 = \"\";").unwrap();
+        assert_eq!(output, chained);
+
+        let mut report = Report::new().with_label_obj(0..3);
+        let output = report.render_to_string("let").unwrap();
+        assert!(output.contains("let"));
+
+        let label = Label::new(13..15)
+            .with_message(String::from("found string"))
+            .with_color(&color)
+            .with_order(1);
+        assert_eq!(label.clone().color(), Some(&color));
+        assert!(format!("{label:?}").contains("found string"));
+        let mut report = Report::new()
+            .with_title(Level::Error, "mismatched types")
+            .with_label_obj(label)
+            .with_label_obj(
+                DiagnosticLabel::new(7..10)
+                    .with_message("expected `i32`")
+                    .with_primary(true),
+            )
+            .with_priority(2);
+        let output = report.render_to_string("let x: i32 = \"\";").unwrap();
+        assert_eq!(output, chained);
+    }
+
+    #[test]
+    fn test_label_span() {
+        let span = LabelSpan::new(3..9, 1);
+        assert_eq!((span.start(), span.end(), span.src_id()), (3, 9, 1));
+        assert_eq!(span.len(), 6);
+        assert!(LabelSpan::from(5..5).is_empty());
+        assert_eq!(span.offset(-4), LabelSpan::new(0..5, 1));
+        assert_eq!(
+            span.intersect(LabelSpan::new(0..4, 1)),
+            Some(LabelSpan::new(3..4, 1))
+        );
+        assert_eq!(span.split_at(100), (span, LabelSpan::new(9..9, 1)));
+
+        let cache = Cache::new().with_source("abc").with_source("αβ\r\nγδ\nε");
+        let bytes = LabelSpan::new(2..11, 1);
+        let chars = bytes.to_char_span(&cache).unwrap();
+        assert_eq!(chars, LabelSpan::new(1..7, 1));
+        assert_eq!(chars.to_byte_span(&cache), Some(bytes));
+        assert_eq!(
+            LabelSpan::new(4..8, 1).to_byte_span(&cache),
+            Some(LabelSpan::new(6..13, 1))
+        );
+        assert_eq!(LabelSpan::new(0..1, 2).to_char_span(&cache), None);
+    }
+
+    #[test]
+    fn test_label_span_ranges() {
+        assert_eq!(LabelSpan::from(4..=6), LabelSpan::new(4..7, 0));
+        assert_eq!(LabelSpan::from(..3), LabelSpan::new(0..3, 0));
+        assert_eq!(LabelSpan::from(5usize), LabelSpan::new(5..5, 0));
+        assert_eq!(LabelSpan::from(..).end(), usize::MAX);
+
+        let src = "let x = y;\nx";
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "Error")
+            .with_label(..)
+            .with_message("whole")
+            .with_label(4..=4)
+            .with_message("inclusive")
+            .with_label(8)
+            .with_message("point");
+        assert!(report.validate(src).is_ok());
+        let output = report.render_to_string(src).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: Error
+           ,-[ <unknown>:1:1 ]
+           |
+         1 | ,-> let x = y;
+           | |       |   ^
+           | |       |   `- point
+           | |       |
+           | |       `----- inclusive
+         2 | |-> x
+           | |
+           | `------ whole
+        ---'
+        ");
+    }
+
+    #[test]
+    fn test_label_at_line_col() {
+        let src = "fn π() {\n    let x = π;\n}";
+        let cache = Cache::new().with_source((src, "a.rs", 9));
+        let render = |index_type: IndexType| {
+            let mut report = Report::new()
+                .with_config(
+                    Config::new()
+                        .with_char_set_ascii()
+                        .with_color_disabled()
+                        .with_index_type(index_type),
+                )
+                .with_title(Level::Error, "Error")
+                .with_label_at_line_col((11, 13)..(11, 14), 0)
+                .with_message("pi")
+                .with_label_at_line_col((11, 9)..(11, 10), 0)
+                .with_message("x");
+            assert_eq!(
+                report.to_diagnostic().labels()[0].span(),
+                LabelSpan::new(0..0, 0)
+            );
+            let output = report.render_to_string(&cache).unwrap();
+            let span = report.to_diagnostic().labels()[0].span();
+            (remove_trailing_whitespace(&output), span)
+        };
+        let (chars, span) = render(IndexType::Char);
+        assert_eq!(span, LabelSpan::new(21..22, 0));
+        let (bytes, span) = render(IndexType::Byte);
+        assert_eq!(span, LabelSpan::new(22..24, 0));
+        assert_eq!(chars, bytes);
+        assert_snapshot!(chars, @r"
+        Error: Error
+            ,-[ a.rs:11:9 ]
+            |
+         11 |     let x = π;
+            |         |   |
+            |         |   `-- pi
+            |         |
+            |         `------ x
+        ----'
+        ");
+
+        let mut report = Report::new()
+            .with_title(Level::Error, "Error")
+            .with_label_at_line_col((20, 1)..(30, 1), 0);
+        assert!(matches!(
+            report.validate(&cache).unwrap_err()[..],
+            [Error::SpanOutOfBounds { label: 0, .. }]
+        ));
+        report.render_to_string(&cache).unwrap();
+        assert_eq!(
+            report.to_diagnostic().labels()[0].span(),
+            LabelSpan::new(25..25, 0)
+        );
+
+        // exporters place the labels without rendering
+        let report = Report::new()
+            .with_title(Level::Error, "Error")
+            .with_label_at_line_col((11, 9)..(11, 10), 0);
+        assert!(report.validate(&cache).is_ok());
+        let json = report.render_to_json(&cache);
+        assert!(json.contains(r#""span":{"start":17,"end":18},"start":{"line":11,"column":9}"#));
+        let mut out = Vec::new();
+        report
+            .render_to_github_annotations(&mut out, &cache)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("line=11,col=9,endLine=11,endColumn=10"));
+    }
+
+    #[test]
+    fn test_location() {
+        let src = "fn π() {\n    let x = π;\n}";
+        let cache = Cache::new().with_source((src, "a.rs", 9));
+        let render = |loc: Location, auto: bool| {
+            Report::new()
+                .with_config(
+                    Config::new()
+                        .with_char_set_ascii()
+                        .with_color_disabled()
+                        .with_auto_location(auto),
+                )
+                .with_title(Level::Error, "Error")
+                .with_label(21..22)
+                .with_message("pi")
+                .with_location(loc, 0)
+                .render_to_string(&cache)
+                .unwrap()
+        };
+        let output = render(Location::LineCol(11, 9), true);
+        assert_eq!(output, render(17.into(), false));
+        assert_eq!(output, render((11, 9).into(), false));
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: Error
+            ,-[ a.rs:11:9 ]
+            |
+         11 |     let x = π;
+            |             |
+            |             `-- pi
+        ----'
+        ");
+
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "Error")
+            .with_label(21..22)
+            .with_location(0, 0);
+        assert!(
+            report
+                .render_to_string(&cache)
+                .unwrap()
+                .contains("a.rs:10:1")
+        );
+        let mut report = report.reset().with_label(21..22);
+        assert!(
+            report
+                .render_to_string(&cache)
+                .unwrap()
+                .contains("a.rs:11:13")
+        );
+
+        let report = Report::new()
+            .with_title(Level::Error, "Error")
+            .with_location((11, 9), 0);
+        let json = report.render_to_json(&cache);
+        assert!(json.ends_with(
+            r#""location":{"file":"a.rs","offset":17,"position":{"line":11,"column":9}}}"#
+        ));
+        let mut out = Vec::new();
+        report
+            .render_to_github_annotations(&mut out, &cache)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "::error file=a.rs,line=11,col=9,title=Error::Error\n"
+        );
+        let report = report.with_location(0, 2);
+        assert!(matches!(
+            report.validate(&cache).unwrap_err()[..],
+            [Error::UnknownSourceId { src_id: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_span_trait() {
+        struct Tok(usize, usize);
+        impl Span for Tok {
+            fn start(&self) -> usize {
+                self.0
+            }
+            fn end(&self) -> usize {
+                self.1
+            }
+        }
+        struct InFile(Tok, usize);
+        impl Span for InFile {
+            fn start(&self) -> usize {
+                self.0.start()
+            }
+            fn end(&self) -> usize {
+                self.0.end()
+            }
+            fn source_id(&self) -> usize {
+                self.1
+            }
+        }
+
+        assert_eq!(LabelSpan::from(Tok(1, 3)), LabelSpan::new(1..3, 0));
+        assert_eq!(
+            LabelSpan::from(&InFile(Tok(0, 2), 1)),
+            LabelSpan::new(0..2, 1)
+        );
+
+        let spans = [Tok(4, 5), Tok(8, 9)];
+        let cache = Cache::new().with_source("let x = y;").with_source("y");
+        let mut report = Report::new()
+            .with_title(Level::Error, "unknown name")
+            .with_primary_label(&spans[1])
+            .with_label(InFile(Tok(0, 1), 1))
+            .with_label_obj(Label::new(&spans[0]))
+            .with_context_span(Tok(0, 10));
+        assert_eq!(
+            report.to_diagnostic().labels()[1].span(),
+            LabelSpan::new(0..1, 1)
+        );
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains("<unknown>:1:9"));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let render = |src: &str, start: usize, normalize: bool| {
+            Report::new()
+                .with_config(
+                    Config::new()
+                        .with_color_disabled()
+                        .with_normalize(normalize),
+                )
+                .with_title(Level::Error, "unknown name")
+                .with_label(start..start + 1)
+                .with_message("here")
+                .render_to_string(src)
+                .unwrap()
+        };
+        let composed = render("caf\u{e9} = x", 7, false);
+        assert!(composed.contains("<unknown>:1:8"));
+        assert!(render("cafe\u{301} = x", 8, false).contains("<unknown>:1:9"));
+        let decomposed = render("cafe\u{301} = x", 8, true);
+        assert_eq!(
+            decomposed.replace("e\u{301}", "\u{e9}"),
+            render("caf\u{e9} = x", 7, true)
+        );
+        assert_eq!(render("caf\u{e9} = x", 7, true), composed);
+    }
+
+    #[test]
+    fn test_bidi_isolate() {
+        let mut report = Report::new()
+            .with_config(
+                Config::new()
+                    .with_char_set_ascii()
+                    .with_color_disabled()
+                    .with_bidi_isolate(true),
+            )
+            .with_title(Level::Error, "unknown name")
+            .with_label((4..8, 0))
+            .with_message("not found")
+            .with_label((0..3, 1))
+            .with_message("plain");
+        let cache = Cache::new()
+            .with_source(("let \u{5e9}\u{5dc}\u{5d5}\u{5dd} = 1;", "rtl.rs"))
+            .with_source(("let x = 1;", "ltr.rs"));
+        let output = report.render_to_string(&cache).unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output.replace(['\u{2068}', '\u{2069}'], "~")),
+            @r##"
+        Error: unknown name
+           ,-[ rtl.rs:1:5 ]
+           |
+         1 | ~let שלום = 1;~
+           |     ^^|^
+           |       `--- not found
+           |
+           |-[ ltr.rs:1:1 ]
+           |
+         1 | let x = 1;
+           | ^|^
+           |  `--- plain
+        ---'
+        "##
+        );
+    }
+
+    #[test]
+    fn test_auto_location() {
+        let cache = Cache::new()
+            .with_source(("let x = 42;", "main.rs"))
+            .with_source(("fn foo() {}", "lib.rs"));
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "mismatched types")
+            .with_label((8..10, 0))
+            .with_label((4..5, 0))
+            .with_label((3..6, 1))
+            .with_label((0..2, 1))
+            .with_priority(-1);
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains("[ main.rs:1:5 ]"));
+        assert!(output.contains("[ lib.rs:1:4 ]"));
+
+        let config = Config::new()
+            .with_char_set_ascii()
+            .with_color_disabled()
+            .with_auto_location(false);
+        assert!(!config.is_auto_location());
+        let mut report = report.with_config(config);
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains(",-[ main.rs ]"));
+        assert!(output.contains("|-[ lib.rs ]"));
+    }
+
+    #[test]
+    fn test_message_ref() {
+        let mut cg = ColorGenerator::new();
+        let (decl, usage) = (cg.next_color(), cg.next_color());
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii())
+            .with_title(Level::Error, "mismatched types")
+            .with_label(7..10)
+            .with_message("declared here")
+            .with_color(&decl)
+            .with_label(13..15)
+            .with_message("must match the type declared here")
+            .with_message_ref(20..33, 0)
+            .with_color(&usage);
+        let output = report.render_to_string("let x: i32 = \"\";").unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output.replace('\x1b', "~")),
+            @r##"
+        ~[31mError:~[0m mismatched types
+        ~[38;5;246m   ,-[~[0m <unknown>:1:8 ~[38;5;246m]~[0m
+        ~[38;5;246m   |~[0m
+        ~[38;5;246m 1 |~[0m ~[38;5;249mlet x: ~[0m~[38;5;201mi32~[0m~[38;5;249m = ~[0m~[38;5;155m""~[0m~[38;5;249m;~[0m
+        ~[38;5;240m   |~[0m        ~[38;5;201m^|^~[0m   ~[38;5;155m^|~[0m
+        ~[38;5;240m   |~[0m         ~[38;5;201m|~[0m     ~[38;5;155m`--~[0m must match the type ~[38;5;201mdeclared here~[0m
+        ~[38;5;240m   |~[0m         ~[38;5;201m|~[0m
+        ~[38;5;240m   |~[0m         ~[38;5;201m`--------~[0m declared here
+        ~[38;5;246m---'~[0m
+        "##
+        );
+
+        let mut report = report
+            .with_label(0..3)
+            .with_message("x")
+            .with_message_ref(0..1, 5);
+        assert!(report.render_to_string("let x: i32 = \"\";").is_err());
+    }
+
+    #[test]
+    fn test_helps_notes_iter() {
+        let fields = ["len", "length"];
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled().with_char_set_ascii())
+            .with_title(Level::Error, "unknown field")
+            .with_label(2..6)
+            .with_helps(fields.iter().map(|f| format!("did you mean `{f}`?")))
+            .with_notes(["fields are case sensitive"])
+            .with_notes(Vec::<String>::new());
+        let output = report.render_to_string("s.size").unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: unknown field
+           ,-[ <unknown>:1:3 ]
+           |
+         1 | s.size
+           |   ^^^^
+           |
+           | Help 1: did you mean `len`?
+           |
+           | Help 2: did you mean `length`?
+           |
+           | Note: fields are case sensitive
+        ---'
+        "##);
+    }
+
+    #[test]
+    fn test_config_merge() {
+        let custom = CharSet {
+            hbar: '=',
+            ..CharSet::ascii()
+        };
+        let base = Config::new()
+            .with_color_disabled()
+            .with_char_set_ascii()
+            .with_context_lines(3)
+            .with_label_attach(LabelAttach::Start);
+
+        let same = base.merge(&ConfigPatch::default());
+        assert_eq!(same.context_lines(), 3);
+        assert_eq!(same.label_attach(), LabelAttach::Start);
+        assert!(!same.is_color_enabled());
+
+        let patch = ConfigPatch {
+            context_lines: Some(0),
+            compact: Some(true),
+            char_set: Some(&custom),
+            color: Some(true),
+            ..Default::default()
+        };
+        let merged = base.merge(&patch);
+        assert_eq!(merged.context_lines(), 0);
+        assert!(merged.is_compact());
+        assert_eq!(merged.char_set().hbar, '=');
+        assert!(merged.is_color_enabled());
+        assert_eq!(merged.label_attach(), LabelAttach::Start);
+        assert_eq!(base.context_lines(), 3);
+    }
+
+    #[test]
+    fn test_html_report_writer() {
+        let cache = Cache::new()
+            .with_source(("fn main() {}", "a.rs"))
+            .with_source(("let x = <y>;", "b.rs"));
+        let mut emitter = Emitter::new()
+            .with_config(Config::new().with_char_set_ascii())
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "bad main")
+                    .with_code("E01")
+                    .with_label((3..7, 0)),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "odd generics")
+                    .with_label((8..11, 1)),
+            )
+            .with_report(
+                Report::new()
+                    .with_title("Hint", "custom level")
+                    .with_label((0..2, 0)),
+            );
+        let mut page = HtmlReportWriter::new();
+        page.add_emitter(&mut emitter, &cache).unwrap();
+        page.add_rendered(None, Level::Warning, "standalone", "plain text");
+        assert_eq!(page.len(), 4);
+
+        let html = page.to_html();
+        assert!(html.contains("<title>Diagnostics</title>"));
+        assert!(!html.contains('\x1b'));
+        let index: Vec<&str> = html.lines().filter(|l| l.starts_with("<h2>")).collect();
+        assert_eq!(
+            index,
+            ["<h2>a.rs</h2>", "<h2>b.rs</h2>", "<h2>(no location)</h2>"]
+        );
+        assert!(html.contains(
+            "<li data-level=\"error\"><a href=\"#report-0\" class=\"error\">[E01] Error: bad main</a></li>"
+        ));
+        assert!(html.contains("value=\"warning\" checked> Warning (2)"));
+        assert!(html.contains("value=\"hint\" checked> Hint (1)"));
+        assert!(html.contains("let x = &lt;y&gt;;"));
+        assert!(
+            html.contains(
+                "<section id=\"report-3\" data-level=\"warning\">\n<pre>plain text</pre>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_junit() {
+        let cache = Cache::new().with_source(("fn main() {\n    let x = a < b;\n}", "a.rs"));
+        let mut emitter = Emitter::new()
+            .with_config(Config::new().with_char_set_ascii())
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "unknown name")
+                    .with_code("E01")
+                    .with_label(24..25)
+                    .with_message("not found"),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "unused variable")
+                    .with_label(20..21),
+            );
+        let mut xml = Vec::new();
+        emitter.render_to_junit(&mut xml, "lint", &cache).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&xml), @r##"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <testsuites name="lint" tests="2" failures="1">
+        <testsuite name="lint" tests="2" failures="1">
+        <testcase name="[E01] unknown name" classname="a.rs" file="a.rs" line="2">
+        <failure message="unknown name" type="Error">[E01] Error: unknown name
+           ,-[ a.rs:2:13 ]
+           |
+         2 |     let x = a &lt; b;
+           |             |
+           |             `-- not found
+        ---&#39;
+        </failure>
+        </testcase>
+        <testcase name="unused variable" classname="a.rs" file="a.rs" line="2">
+        <system-out>Warning: unused variable
+           ,-[ a.rs:2:9 ]
+           |
+         2 |     let x = a &lt; b;
+           |         ^
+        ---&#39;
+        </system-out>
+        </testcase>
+        </testsuite>
+        </testsuites>
+        "##);
+    }
+
+    #[test]
+    fn test_teamcity_gitlab() {
+        let cache = Cache::new().with_source(("fn main() {\n    let x = a[0];\n}", "a.rs"));
+        let mut emitter = Emitter::new()
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "index out of bounds")
+                    .with_code("E01")
+                    .with_label(24..28),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "unused variable 'x'")
+                    .with_label(20..21),
+            )
+            .with_report(Report::new().with_title("Info", "no location"));
+
+        let mut out = Vec::new();
+        emitter.render_to_teamcity(&mut out, &cache).unwrap();
+        assert_snapshot!(String::from_utf8(out).unwrap(), @r##"
+        ##teamcity[inspectionType id='E01' name='E01' category='Error' description='index out of bounds']
+        ##teamcity[inspection typeId='E01' message='index out of bounds' file='a.rs' line='2' SEVERITY='ERROR']
+        ##teamcity[inspectionType id='unused variable |'x|'' name='unused variable |'x|'' category='Warning' description='unused variable |'x|'']
+        ##teamcity[inspection typeId='unused variable |'x|'' message='unused variable |'x|'' file='a.rs' line='2' SEVERITY='WARNING']
+        ##teamcity[message text='no location' status='NORMAL']
+        "##);
+
+        let mut out = Vec::new();
+        emitter.render_to_gitlab(&mut out, &cache).unwrap();
+        assert_snapshot!(String::from_utf8(out).unwrap(), @r##"
+        [
+          {"description":"index out of bounds","check_name":"E01","fingerprint":"cb3be521648d090d","severity":"major","location":{"path":"a.rs","lines":{"begin":2}}},
+          {"description":"unused variable 'x'","check_name":"Warning","fingerprint":"8e1e7678479e9e85","severity":"minor","location":{"path":"a.rs","lines":{"begin":2}}}
+        ]
+        "##);
+
+        let mut out = Vec::new();
+        Emitter::new().render_to_gitlab(&mut out, &cache).unwrap();
+        assert_eq!(out, b"[]\n");
+    }
+
+    #[test]
+    fn test_changed_lines() {
+        use std::collections::HashMap;
+        use std::path::PathBuf;
+
+        let cache = Cache::new()
+            .with_source(("a\nb\nc\nd", "a.rs"))
+            .with_source(("x", "b.rs"));
+        let changed = HashMap::from([(PathBuf::from("a.rs"), vec![2..=3])]);
+        let mut emitter = Emitter::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_changed_lines(changed)
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "line 1")
+                    .with_label((0..1, 0)),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "line 3")
+                    .with_label((4..5, 0)),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "primary on line 4")
+                    .with_label((2..3, 0))
+                    .with_primary_label((6..7, 0)),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "primary on line 2")
+                    .with_label((0..1, 0))
+                    .with_primary_label((2..3, 0)),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "unchanged file")
+                    .with_label((0..1, 1)),
+            )
+            .with_report(Report::new().with_title(Level::Error, "no label"));
+        let output = emitter.render_to_string(&cache).unwrap();
+        let titles: Vec<&str> = output.lines().filter(|l| l.starts_with("Error")).collect();
+        assert_eq!(
+            titles,
+            [
+                "Error: line 3",
+                "Error: primary on line 2",
+                "Error: no label"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy {
+            allow: vec!["W02".into()],
+            warn_as_error: true,
+            max_errors: Some(2),
+            ..Default::default()
+        };
+        let mut emitter = Emitter::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_policy(policy)
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "first")
+                    .with_code("W01")
+                    .with_label(0..1),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "allowed")
+                    .with_code("W02")
+                    .with_label(0..1),
+            )
+            .with_report(
+                Report::new()
+                    .with_title("Hint", "custom level")
+                    .with_label(0..1),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "second")
+                    .with_label(0..1),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "third")
+                    .with_label(0..1),
+            );
+        assert_eq!(emitter.error_count(), 3);
+        let output = emitter.render_to_string("x").unwrap();
+        let titles: Vec<&str> = output.lines().filter(|l| l.contains(": ")).collect();
+        assert_eq!(
+            titles,
+            ["[W01] Error: first", "Hint: custom level", "Error: second"]
+        );
+    }
+
+    #[test]
+    fn test_context_span() {
+        let src = "fn main() {\n    let x = 1;\n\n    foo(x);\n}\nfn bar() {}";
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled().with_char_set_ascii())
+            .with_title(Level::Error, "unknown function")
+            .with_label(32..35)
+            .with_message("not found")
+            .with_context_span(0..42);
+        let output = report.render_to_string(src).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: unknown function
+           ,-[ <unknown>:4:5 ]
+           |
+         1 | fn main() {
+         2 |     let x = 1;
+         3 |
+         4 |     foo(x);
+           |     ^|^
+           |      `--- not found
+         5 | }
+        ---'
+        "##);
+
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii())
+            .with_title(Level::Error, "unknown function")
+            .with_label(32..35)
+            .with_context_span(12..43);
+        let output = report.render_to_string(src).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output.replace('\x1b', "~")), @r##"
+        ~[31mError:~[0m unknown function
+        ~[38;5;246m   ,-[~[0m <unknown>:4:5 ~[38;5;246m]~[0m
+        ~[38;5;246m   |~[0m
+        ~[38;5;246m 2 |~[0m ~[2m    let x = 1;~[0m
+        ~[38;5;246m 3 |~[0m
+        ~[38;5;246m 4 |~[0m ~[2m    ~[0m~[39mfoo~[0m~[2m(x);~[0m
+        ~[38;5;240m   |~[0m     ~[39m^^^~[0m
+        ~[38;5;246m 5 |~[0m ~[2m}~[0m
+        ~[38;5;246m 6 |~[0m ~[2mfn bar() {}~[0m
+        ~[38;5;246m---'~[0m
+        "##);
+
+        let mut report = Report::new().with_label(0..1).with_context_span((0..1, 3));
+        assert!(report.render_to_string(src).is_err());
+    }
+
+    #[test]
+    fn test_dim_unlabeled() {
+        let src = "let a = b + 1;";
+        let mut report = Report::new()
+            .with_config(
+                Config::new()
+                    .with_char_set_ascii()
+                    .with_dim_unlabeled(false),
+            )
+            .with_title(Level::Error, "unknown variable")
+            .with_label(8..9);
+        let output = report.render_to_string(src).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output.replace('\x1b', "~")), @r##"
+        ~[31mError:~[0m unknown variable
+        ~[38;5;246m   ,-[~[0m <unknown>:1:9 ~[38;5;246m]~[0m
+        ~[38;5;246m   |~[0m
+        ~[38;5;246m 1 |~[0m let a = ~[39mb~[0m + 1;
+        ~[38;5;240m   |~[0m         ~[39m^~[0m
+        ~[38;5;246m---'~[0m
+        "##);
+    }
+
+    #[test]
+    fn test_caret_only() {
+        let config = Config::new()
+            .with_color_disabled()
+            .with_char_set_ascii()
+            .with_caret_only(true);
+        let mut report = Report::new()
+            .with_config(config.clone())
+            .with_title(Level::Error, "expected `;`")
+            .with_label(9..10)
+            .with_message("add `;` here");
+        let output = report.render_to_string("let a = 1\n").unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: expected `;`
+           ,-[ <unknown>:1:10 ]
+           |
+         1 | let a = 1
+           |          ^-- add `;` here
+        ---'
+        "##);
+
+        let mut report = Report::new()
+            .with_config(config.clone())
+            .with_title(Level::Error, "mismatched types")
+            .with_label(4..5)
+            .with_message("variable")
+            .with_label(8..13)
+            .with_message("string")
+            .with_label(14..15)
+            .with_message("operator");
+        let output = report.render_to_string("let a = \"hi\" + 1;").unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: mismatched types
+           ,-[ <unknown>:1:5 ]
+           |
+         1 | let a = "hi" + 1;
+           |         ^^|^^
+           |           |   ^-- operator
+           |           |
+           |           `------ string
+           |     ^------------ variable
+        ---'
+        "##);
+
+        let mut report = Report::new()
+            .with_config(config.with_char_set_unicode().with_compact(true))
+            .with_title(Level::Error, "mismatched types")
+            .with_label(4..5)
+            .with_message("variable")
+            .with_label(14..15)
+            .with_message("operator");
+        let output = report.render_to_string("let a = \"hi\" + 1;").unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: mismatched types
+           ╭─[ <unknown>:1:5 ]
+         1 ┤let a = "hi" + 1;
+           │              ▲─ operator
+           │    ▲─────────── variable
+        "##);
+    }
+
+    #[test]
+    fn test_one_based_columns() {
+        let mut report = Report::new()
+            .with_config(
+                Config::new()
+                    .with_color_disabled()
+                    .with_char_set_ascii()
+                    .with_one_based_columns(false),
+            )
+            .with_title(Level::Error, "unknown label")
+            .with_label(13..17)
+            .with_message("not defined");
+        let output = report
+            .render_to_string(("mov ax, 1\njmp done", "boot.s", -1))
+            .unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: unknown label
+           ,-[ boot.s:1:3 ]
+           |
+         1 | jmp done
+           |    ^^|^
+           |      `--- not defined
+        ---'
+        "##);
+    }
+
+    #[test]
+    fn test_tab_stops() {
+        let src = "a\tbb\tccc\tx\ty";
+        let mut report = Report::new()
+            .with_config(
+                Config::new()
+                    .with_color_disabled()
+                    .with_char_set_ascii()
+                    .with_tab_stops(&[4, 6, 12]),
+            )
+            .with_title(Level::Error, "bad cell")
+            .with_label(9..10)
+            .with_message("here")
+            .with_label(11..12)
+            .with_message("and here");
+        let output = report.render_to_string(src).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: bad cell
+           ,-[ <unknown>:1:10 ]
+           |
+         1 | a   bb      ccc x   y
+           |                 |   |
+           |                 |   `-- and here
+           |                 |
+           |                 `------ here
+        ---'
+        "##);
+
+        // stops out of order or beyond the 16th are left out
+        let config = Config::new().with_tab_stops(&[-4, 0, 4, 2, 4, 6]);
+        assert_eq!(config.tab_stops(), [4, 6]);
+        let stops: Vec<i32> = (1..=20).map(|i| i * 2).collect();
+        let config = Config::new().with_tab_stops(&stops);
+        assert_eq!(config.tab_stops(), &stops[..16]);
+    }
+
+    #[test]
+    fn test_newline_kind() {
+        let src = "let a = 1\r\nlet b = a +\r\n  2;\r\n";
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled().with_char_set_ascii())
+            .with_title(Level::Error, "expected `;`")
+            .with_label(9..11)
+            .with_message("missing `;`")
+            .with_label(20..26)
+            .with_message("expression");
+        let cache = Cache::new().with_source(src);
+        let output = report.render_to_string(&cache).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: expected `;`
+           ,-[ <unknown>:1:10 ]
+           |
+         1 |     let a = 1
+           |              |
+           |              `-- missing `;`
+         2 | ,-> let b = a +
+         3 | |->   2;
+           | |
+           | `---------- expression
+        ---'
+        "##);
+        assert_eq!(cache.newline_kind(0), Some(NewlineKind::CrLf));
+
+        let cache = Cache::new()
+            .with_source("a\rb\r")
+            .with_source("a\rb\r\n")
+            .with_newline_kind(1, NewlineKind::Lf)
+            .with_source("ab");
+        assert_eq!(cache.newline_kind(0), Some(NewlineKind::Cr));
+        assert_eq!(cache.newline_kind(1), Some(NewlineKind::Lf));
+        assert_eq!(cache.newline_kind(2), Some(NewlineKind::Auto));
+
+        // SAFETY: the source is only fed through mu_updatelines
+        unsafe {
+            let mut cache = ptr::null_mut();
+            let src = ffi::mu_addmemory(&mut cache, "".into(), "".into());
+            (*src).inited = 1;
+            ffi::mu_updatelines(src, "ab\r".into());
+            assert_eq!(ffi::mu_newlinekind(src), ffi::mu_Newline::MU_NEWLINE_CR);
+            ffi::mu_updatelines(src, "\ncd".into());
+            assert_eq!(ffi::mu_newlinekind(src), ffi::mu_Newline::MU_NEWLINE_CRLF);
+            assert_eq!(ffi::mu_linecount(src), 2);
+            assert_eq!((*ffi::mu_getline(src, 0)).newline, 2);
+            assert_eq!((*ffi::mu_getline(src, 1)).offset, 4);
+            ffi::mu_delcache(cache);
+        }
+    }
+
+    #[test]
+    fn test_info_note_hint_advice() {
+        let levels = [Level::Info, Level::Note, Level::Hint, Level::Advice];
+        let output: String = levels
+            .into_iter()
+            .map(|level| {
+                let mut report = Report::new()
+                    .with_config(Config::new().with_compact(true))
+                    .with_title(level, "message");
+                report.render_to_string("").unwrap()
+            })
+            .collect();
+        assert_snapshot!(remove_trailing_whitespace(&output.replace('\x1b', "~")), @r##"
+        ~[34mInfo:~[0m message
+        ~[38;5;115mNote:~[0m message
+        ~[36mHint:~[0m message
+        ~[32mAdvice:~[0m message
+        "##);
+    }
+
+    #[test]
+    fn test_render_to_string_unchecked() {
+        let src = "let x = été;";
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "Bad value")
+            .with_label(8..14)
+            .with_message("hé");
+        let checked = report.render_to_string(src).unwrap();
+        // SAFETY: the source and message are `&str`, colors are disabled
+        let unchecked = unsafe { report.render_to_string_unchecked(src).unwrap() };
+        assert_eq!(checked, unchecked);
+    }
+
+    #[test]
+    fn test_render_size_hint() {
+        let src = "let x = 1;\nlet y = x + \"a\";\n";
+        let mut report = Report::new()
+            .with_title(Level::Error, "Type mismatch")
+            .with_label(19..30)
+            .with_message("cannot add a string to a number")
+            .with_help("convert the string first");
+        assert!(report.size_hint > 0);
+        let first = report.render_to_string(src).unwrap();
+        assert!(report.size_hint >= first.len());
+        // the second render reserves room for the whole output at once
+        let second = report.render_to_string(src).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(report.reset().size_hint, 0);
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let src = "let x = 1;\nlet y = x + \"a\";\n";
+        let report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title("Lint", "Suspicious addition")
+            .with_code("L01")
+            .with_primary_label(19..30)
+            .with_message("string added here")
+            .with_label(4..5)
+            .with_helps([String::from("convert it first")])
+            .with_suggestion(4..5, "z", "rename it")
+            .with_suggestion_part(23..24, "z")
+            .with_applicability(Applicability::MachineApplicable)
+            .with_note("see the manual");
+        let diag = report.to_diagnostic();
+        assert_eq!(diag.level(), None);
+        assert_eq!(diag.level_name(), "Lint");
+        assert_eq!(diag.title(), "Suspicious addition");
+        assert_eq!(diag.code(), Some("L01"));
+        assert_eq!(diag.labels().len(), 2);
+        assert!(diag.labels()[0].is_primary());
+        assert_eq!(diag.labels()[0].message(), Some("string added here"));
+        assert_eq!(diag.labels()[1].span(), LabelSpan::from(4..5));
+        assert_eq!(diag.labels()[1].message(), None);
+        assert_eq!(diag.helps(), ["convert it first"]);
+        assert_eq!(diag.notes(), ["see the manual"]);
+        let fix = &diag.suggestions()[0];
+        assert_eq!(fix.message(), Some("rename it"));
+        assert_eq!(fix.applicability(), Applicability::MachineApplicable);
+        assert_eq!(fix.edits()[1], (LabelSpan::from(23..24), "z".into()));
+
+        // a round trip renders the same output
+        let mut report = report;
+        let expected = report.render_to_string(src).unwrap();
+        let round_trip = Diagnostic::from(&report);
+        assert_eq!(round_trip, diag);
+        let mut copy = Report::from(&round_trip).with_config(Config::new().with_color_disabled());
+        assert_eq!(copy.render_to_string(src).unwrap(), expected);
+        let diag = diag.into_owned();
+        // owned diagnostics may cross threads
+        let diag = std::thread::spawn(move || diag).join().unwrap();
+        let mut copy = Report::from(&diag).with_config(Config::new().with_color_disabled());
+        assert_eq!(copy.to_diagnostic(), diag);
+        assert_eq!(copy.render_to_string(src).unwrap(), expected);
+        assert_eq!(report.reset().to_diagnostic(), Diagnostic::default());
+    }
+
+    #[test]
+    fn test_path_names() {
+        use std::path::PathBuf;
+
+        let path = PathBuf::from("src").join("main.rs");
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "Error")
+            .with_label((4..5, 1));
+        let cache = Cache::new()
+            .with_source(("let x = 42;", &path))
+            .with_source(("let y = 42;", Path::new("lib.rs"), 9));
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains("lib.rs:10:5"));
+        assert_eq!(
+            source_name(cache.as_raw(), 0.into()),
+            Some(path.display().to_string())
+        );
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.source_id(&path), Some(0));
+        assert_eq!(cache.source_id("lib.rs"), Some(1));
+        assert_eq!(cache.source_id("main.rs"), None);
+        assert!(Cache::new().is_empty());
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            // non-UTF-8 names are kept for lookups, and shown like
+            // `Path::display`
+            let path = Path::new(OsStr::from_bytes(b"caf\xff.rs"));
+            let cache = Cache::new().with_source(("let x = 42;", path));
+            assert_eq!(cache.source_id(path), Some(0));
+            let mut out = Vec::new();
+            let mut report = report.reset().with_label(4..5);
+            report.render_to_writer(&mut out, &cache).unwrap();
+            let output = String::from_utf8(out).unwrap();
+            assert!(output.contains(&format!("{}:1:5", path.display())));
+            assert!(output.contains("caf\u{FFFD}.rs:1:5"));
+        }
+    }
+
+    #[test]
+    fn test_fn_cache() {
+        use std::cell::{Cell, RefCell};
+        let fetched = RefCell::new(Vec::new());
+        let fail = Cell::new(true);
+        let mut cache = FnCache::new(|name: &&str| {
+            fetched.borrow_mut().push(*name);
+            match *name {
+                "b.rs" if fail.get() => Err(io::Error::other("not yet")),
+                _ => Ok(format!("// {name}\nlet x = 42;\n")),
+            }
+        });
+        assert_eq!(cache.id(&"a.rs"), 0);
+        assert_eq!(cache.id(&"b.rs"), 1);
+        assert_eq!(cache.id(&"a.rs"), 0);
+        assert_eq!(cache.cache().len(), 2);
+
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "Error")
+            .with_label(cache.span(12..13, &"a.rs"));
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains("a.rs:2:5"), "{output}");
+        report.render_to_string(&cache).unwrap();
+        // only the referenced source is fetched, once
+        assert_eq!(*fetched.borrow(), ["a.rs"]);
+
+        let mut report = (report.reset())
+            .with_title(Level::Error, "Error")
+            .with_label(cache.span(12..13, &"b.rs"));
+        assert!(report.render_to_string(&cache).is_err());
+        fail.set(false);
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains("b.rs:2:5"), "{output}");
+        assert_eq!(*fetched.borrow(), ["a.rs", "b.rs", "b.rs"]);
+    }
+
+    #[test]
+    fn test_transform_source() {
+        // expand `{{name}}` placeholders, with labels on the template
+        let text = "hello {{name}}, {{greeting}}!\nbye {{name}}\n";
+        let expand = |line: &str| {
+            let mut edits = Vec::new();
+            let mut rest = 0;
+            while let Some(start) = line[rest..].find("{{").map(|i| rest + i) {
+                let end = start + line[start..].find("}}").unwrap() + 2;
+                let value = match &line[start + 2..end - 2] {
+                    "name" => "Ferris",
+                    _ => "ça va",
+                };
+                edits.push((start..end, value.to_string()));
+                rest = end;
+            }
+            edits
+        };
+        let render = |index_type| {
+            let cache = Cache::new().with_source((TransformSource::new(text, expand), "hi.txt"));
+            Report::new()
+                .with_config(
+                    Config::new()
+                        .with_char_set_ascii()
+                        .with_color_disabled()
+                        .with_index_type(index_type),
+                )
+                .with_title(Level::Error, "Error")
+                .with_label(6..14)
+                .with_message("expanded")
+                .with_label(16..28)
+                .with_message("also expanded")
+                .with_label(28..29)
+                .with_message("after")
+                .with_label(34..42)
+                .with_message("next line")
+                .render_to_string(&cache)
+                .unwrap()
+        };
+        let output = render(IndexType::Char);
+        assert_snapshot!(output, @r"
+        Error: Error
+           ,-[ hi.txt:1:7 ]
+           |
+         1 | hello Ferris, ça va!
+           |       ^^^|^^  ^^|^^|  
+           |          |      |  `-- after
+           |          |      |     
+           |          |      `----- also expanded
+           |          |            
+           |          `------------ expanded
+         2 | bye Ferris
+           |     ^^^|^^  
+           |        `---- next line
+        ---'
+        ");
+        // the template is ASCII, so byte positions are the same
+        assert_eq!(render(IndexType::Byte), output);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let cache = Cache::new()
+            .with_source(("x = a ?? b\n", "in.ts"))
+            .with_source(("x = a != null ? a : b\n", "out.js"));
+        let map = SourceMap::new(1, 0)
+            .with_pair(0, 0)
+            .with_pair(5, 5)
+            .with_pair(19, 8);
+        assert_eq!(map.original_pos(2), Some(2));
+        assert_eq!(map.original_pos(12), Some(7));
+        assert_eq!(map.original_pos(20), Some(9));
+        assert_eq!(map.original_span((0..1, 0)), None);
+        assert_eq!(map.original_span((6..15, 1)), Some((6..8, 0).into()));
+
+        // label the generated code and the original code it came from
+        let span = LabelSpan::new(6..15, 1);
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "`??` is not supported")
+            .with_label(map.original_span(span).unwrap())
+            .with_message("written here")
+            .with_label(span)
+            .with_message("generated here");
+        assert_snapshot!(report.render_to_string(&cache).unwrap(), @r"
+        Error: `??` is not supported
+           ,-[ in.ts:1:7 ]
+           |
+         1 | x = a ?? b
+           |       ^|  
+           |        `-- written here
+           |
+           |-[ out.js:1:7 ]
+           |
+         1 | x = a != null ? a : b
+           |       ^^^^|^^^^  
+           |           `------ generated here
+        ---'
+        ");
+    }
+
+    #[test]
+    fn test_shared_cache() {
+        let cache = Cache::new()
+            .with_shared_source("let x = 42;\n")
+            .with_file("/nonexistent/musubi.rs")
+            .into_shared();
+        assert_ne!(cache.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        let cache = Cache::new()
+            .with_shared_source("let x = 42;\n")
+            .with_source(OwnedSource::new(std::rc::Rc::<[u8]>::from(&b"x"[..])))
+            .into_shared();
+        assert_eq!(cache.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+        let text = "let x = 42;\n";
+        let source = TransformSource::new(text, |line: &str| match line.find("42") {
+            Some(start) => vec![(start..start + 2, "forty-two".to_string())],
+            None => vec![],
+        });
+        let cache = Cache::new()
+            .with_shared_source((source, "a.rs"))
+            .into_shared()
+            .unwrap();
+        let render = |cache: &SharedCache| {
+            Report::new()
+                .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+                .with_title(Level::Error, "Error")
+                .with_label(4..5)
+                .render_to_string(cache)
+                .unwrap()
+        };
+        let expected = render(&cache);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| assert_eq!(render(&cache), expected));
+            }
+        });
+        assert!(expected.contains("let x = forty-two;"));
+        assert_eq!(cache.excerpt(0..5, 0, 80), "let x");
+    }
+
+    #[test]
+    fn test_freeze() {
+        let main = "// 前言\nfn main() {\n    let 名 = 1;\n    helper(名);\n}\n// end\n";
+        let cache = Cache::new().with_source((main, "main.rs")).with_source((
+            "fn other() {}\nfn helper() {}\n",
+            "lib.rs",
+            10,
+        ));
+        let config = || {
+            Config::new()
+                .with_char_set_ascii()
+                .with_color_disabled()
+                .with_context_lines(1)
+        };
+        let mut report = Report::new()
+            .with_config(config().with_index_type(IndexType::Byte))
+            .with_title(Level::Error, "wrong arguments")
+            .with_label(43..54)
+            .with_message("called here")
+            .with_label((17..23, 1))
+            .with_message("defined here")
+            .with_suggestion(50..53, "", "remove the argument");
+        let expected = report.render_to_string(&cache).unwrap();
+
+        let snapshot = report.freeze(&cache).unwrap();
+        drop(cache);
+        let excerpts: Vec<_> = snapshot.excerpts().collect();
+        assert_eq!(
+            excerpts,
+            [
+                ("main.rs", "    let 名 = 1;\n    helper(名);\n}\n"),
+                ("lib.rs", "fn other() {}\nfn helper() {}\n"),
+            ]
+        );
+        assert_eq!(
+            snapshot.diagnostic().labels()[0].span(),
+            LabelSpan::from(19..28)
+        );
+        let snapshot = std::thread::spawn(move || snapshot).join().unwrap();
+        let mut copy = snapshot.to_report().with_config(config());
+        assert_eq!(copy.render_to_string(()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_render_errors() {
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut report = Report::new()
+            .with_title(Level::Error, "Error")
+            .with_label(0..3);
+        let err = report.render_to_writer(&mut Broken, "abc").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(matches!(
+            Error::from_io(&err),
+            Some(Error::Writer { written: 0, .. })
+        ));
+
+        let cache = Cache::new()
+            .with_source("abc")
+            .with_file("/nonexistent/musubi.rs");
+        let mut report = report.with_label((0..3, 1));
+        let err = report.render_to_string(&cache).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(
+            err.to_string(),
+            "Source 1 (/nonexistent/musubi.rs) init error"
+        );
+        assert!(matches!(Error::from_io(&err), Some(Error::SourceInit(_))));
+
+        let mut report = report.with_context_span((0..1, 5));
+        let err = report.render_to_string("abc").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "unknown source 1 (the cache has 1)");
+
+        assert!(Error::from_io(&io::Error::other("other")).is_none());
     }
 
-    fn render(&mut self, cache: impl Into<RawCache>) -> io::Result<()> {
-        let mut buf = [0u8; ffi::sizes::COLOR_CODE];
-        let cs_buf: CharSetBuf;
-        let cs: ffi::mu_Charset;
-        if let Some(config) = &mut self.config
-            && let Some(char_set) = config.char_set
-        {
-            cs_buf = (*char_set).into();
-            cs = cs_buf.into();
-            config.inner.char_set = &cs as *const ffi::mu_Charset;
-        }
-        if let Some(cfg) = self.config.as_mut()
-            && let Some(color_ud) = cfg.color_ud.as_mut()
-        {
-            color_ud.color_buf = &mut buf as *mut [u8; ffi::sizes::COLOR_CODE];
-        }
-        for color_ud in &mut self.color_uds {
-            color_ud.color_buf = &mut buf as *mut [u8; ffi::sizes::COLOR_CODE];
-        }
-        if let Some(cfg) = &self.config {
-            // SAFETY: self.ptr is valid, cfg.inner is a valid config with lifetime guarantees
-            unsafe { ffi::mu_config(self.ptr, &cfg.inner) };
+    #[test]
+    fn test_writer_error_context() {
+        struct Limited {
+            out: Vec<u8>,
+            limit: usize,
         }
-        // SAFETY: self.ptr is valid, all sources and labels have been properly registered
-        match unsafe { ffi::mu_render(self.ptr, cache.into().as_ptr()) } {
-            ffi::MU_OK => Ok(()),
-            ffi::MU_ERR_SRCINIT => {
-                if let Some(err) = self.src_err.take() {
-                    return Err(err);
+        impl Write for Limited {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.out.len() + buf.len() > self.limit {
+                    return Err(io::ErrorKind::WriteZero.into());
                 }
-                Err(io::Error::other("Source init error during rendering"))
+                self.out.extend_from_slice(buf);
+                Ok(buf.len())
             }
-            ffi::MU_ERR_WRITER => {
-                if let Some(err) = self.src_err.take() {
-                    return Err(err);
-                }
-                Err(io::Error::other("Writer error during rendering"))
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
             }
-            err_code => Err(io::Error::other(format!(
-                "Rendering failed with error code {}",
-                err_code
-            ))),
         }
+
+        let report = |msg| {
+            Report::new()
+                .with_config(Config::new().with_color_disabled())
+                .with_title(Level::Error, msg)
+                .with_label(0..3)
+                .with_note("a note")
+        };
+        let full = report("first").render_to_string("abc").unwrap();
+        let fail_at = |report: &mut Report, limit| {
+            let mut w = Limited {
+                out: Vec::new(),
+                limit,
+            };
+            let err = report.render_to_writer(&mut w, "abc").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+            let Some(Error::Writer {
+                written, section, ..
+            }) = Error::from_io(&err)
+            else {
+                panic!("not a writer error: {err}");
+            };
+            assert_eq!(*written, w.out.len());
+            (*written, *section)
+        };
+        let (written, section) = fail_at(&mut report("first"), full.len() - 1);
+        assert!(written < full.len());
+        assert_eq!(section, LineKind::Footer);
+        let (_, section) = fail_at(&mut report("first"), full.find("a note").unwrap());
+        assert_eq!(section, LineKind::Note);
+
+        let mut nested = report("first").with_related(report("second"));
+        let (written, section) = fail_at(&mut nested, full.len() + 20);
+        assert!(written > full.len());
+        assert_eq!(section, LineKind::Reference);
     }
-}
 
-/// Internal buffer for character set conversion to C representation.
-///
-/// Converts Rust [`CharSet`] into a C-compatible array of chunk pointers.
-/// Each character is encoded as: `[length_byte, utf8_byte1, utf8_byte2, ...]`
-///
-/// The buffer contains 23 entries (one for each CharSet field), each up to
-/// 8 bytes (1 length byte + up to 7 UTF-8 bytes, though most characters are 1-3 bytes).
-struct CharSetBuf {
-    /// 23 characters × 8 bytes each (length prefix + UTF-8 data)
-    buf: [[u8; 8]; 26],
-}
+    #[test]
+    fn test_diff_reports() {
+        let src = "let x = 1;\nlet y = x;";
+        let report = |msg: &'static str| {
+            Report::new()
+                .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+                .with_title(Level::Warning, "unused")
+                .with_label(4..5)
+                .with_message(msg)
+        };
+        let mut old = report("x").with_note("remove it");
+        let mut new = report("y")
+            .with_label_at_line_col((2, 5)..(2, 6), 0)
+            .with_message("y")
+            .with_help("rename it");
+        let diff = testing::diff_reports(&mut old, &mut new, src).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&diff.to_string()), @r#"
+        label 0 message: Some("x") -> Some("y")
+        label 1 added: 15..16 "y"
+        help 0 added: "rename it"
+        note 0 removed: "remove it"
+
+           1 | let x = 1;
+             |     |
+        -    |     `-- x
+        +    |     `-- y
+        +  2 | let y = x;
+        +    |     |
+        +    |     `-- y
+             |
+        -    | Note: remove it
+        +    | Help: rename it
+          ---'
+        "#);
+        assert!(
+            testing::diff_reports(&mut old, &mut report("x").with_note("remove it"), src)
+                .unwrap()
+                .is_empty()
+        );
+    }
 
-impl From<CharSetBuf> for ffi::mu_Charset {
-    #[inline]
-    fn from(value: CharSetBuf) -> Self {
-        let mut chars: ffi::mu_Charset = [ptr::null(); 26];
-        for (i, slice) in value.buf.iter().enumerate() {
-            chars[i] = slice.as_ptr() as *const c_char;
-        }
-        chars
+    #[test]
+    fn test_validate() {
+        let cache = Cache::new()
+            .with_source("名前\r\n")
+            .with_file("/nonexistent/musubi.rs")
+            .with_source(TransformSource::new("{x}", |_: &str| {
+                vec![(0..3, "42".to_string())]
+            }));
+        let report = Report::new()
+            .with_title(Level::Error, "Error")
+            .with_label(0..4)
+            .with_label(4..4)
+            .with_label((0..3, 2))
+            .with_suggestion((0..1, 0), "x", "");
+        assert!(report.validate(&cache).is_ok());
+
+        let report = report
+            .with_label(4..5)
+            .with_label((0..1, 1))
+            .with_label((4..5, 2))
+            .with_suggestion((0..1, 7), "x", "");
+        let errors = report.validate(&cache).unwrap_err();
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            [
+                "label 3 at 4..5 is out of bounds of source 0 (4 long)",
+                "Source 1 (/nonexistent/musubi.rs) init error",
+                "label 5 at 4..5 is out of bounds of source 2 (2 long)",
+                "unknown source 7 (the cache has 3)",
+            ]
+        );
+
+        let report = Report::new()
+            .with_config(Config::new().with_index_type(IndexType::Byte))
+            .with_label(0..8);
+        assert!(report.validate(&cache).is_ok());
+        let report = report.with_label(0..9);
+        let errors = report.validate(&cache).unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [Error::SpanOutOfBounds {
+                label: 1,
+                source_len: 8,
+                ..
+            }]
+        ));
     }
-}
 
-impl From<CharSet> for CharSetBuf {
-    fn from(char_set: CharSet) -> Self {
-        #[inline]
-        fn char_to_slice(c: char) -> [u8; 8] {
-            if c == '.' {
-                return [3, b'.', b'.', b'.', 0, 0, 0, 0];
-            }
-            let mut buf = [0u8; 8];
-            let s = c.encode_utf8(&mut buf);
-            let len = s.len() as u8;
-            let mut result = [0u8; 8];
-            result[0] = len;
-            result[1..(len as usize + 1)].copy_from_slice(s.as_bytes());
-            result
-        }
-        CharSetBuf {
-            buf: [
-                char_to_slice(char_set.space),
-                char_to_slice(char_set.newline),
-                char_to_slice(char_set.lbox),
-                char_to_slice(char_set.rbox),
-                char_to_slice(char_set.colon),
-                char_to_slice(char_set.hbar),
-                char_to_slice(char_set.vbar),
-                char_to_slice(char_set.xbar),
-                char_to_slice(char_set.vbar_gap),
-                char_to_slice(char_set.line_margin),
-                char_to_slice(char_set.uarrow),
-                char_to_slice(char_set.rarrow),
-                char_to_slice(char_set.ltop),
-                char_to_slice(char_set.mtop),
-                char_to_slice(char_set.rtop),
-                char_to_slice(char_set.lbot),
-                char_to_slice(char_set.mbot),
-                char_to_slice(char_set.rbot),
-                char_to_slice(char_set.lcross),
-                char_to_slice(char_set.rcross),
-                char_to_slice(char_set.lunderbar),
-                char_to_slice(char_set.munderbar),
-                char_to_slice(char_set.runderbar),
-                char_to_slice(char_set.sunderbar),
-                char_to_slice(char_set.underline),
-                char_to_slice(char_set.ellipsis),
-            ],
+    #[test]
+    fn test_invalid_utf8() {
+        // two invalid bytes, a valid 3-byte character, a truncated sequence
+        let data = b"ab\xff\xfecd \xe4\xb8\x80x \xe4\xb8 y\n".to_vec();
+        let cache = Cache::new().with_source((OwnedSource::new(data), "bin"));
+        let render = |index_type, span: std::ops::Range<usize>| {
+            let mut out = Vec::new();
+            Report::new()
+                .with_config(
+                    Config::new()
+                        .with_color_disabled()
+                        .with_index_type(index_type),
+                )
+                .with_title(Level::Error, "bad bytes")
+                .with_label(span)
+                .with_message("here")
+                .render_to_writer(&mut out, &cache)
+                .unwrap();
+            String::from_utf8(out).expect("invalid bytes are replaced")
+        };
+
+        let output = render(IndexType::Byte, 12..16);
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: bad bytes
+           ╭─[ bin:1:11 ]
+           │
+         1 ┤ ab��cd 一x �� y
+           │            ──┬─
+           │              ╰─── here
+        ───╯
+        ");
+        assert_eq!(render(IndexType::Char, 10..14), output);
+        assert_eq!(render(IndexType::Byte, 2..4), render(IndexType::Char, 2..4));
+        assert_eq!(
+            render(IndexType::Byte, 6..10),
+            render(IndexType::Char, 6..8)
+        );
+
+        let report = Report::new()
+            .with_config(Config::new().with_index_type(IndexType::Byte))
+            .with_label(12..16);
+        let snapshot = report.freeze(&cache).unwrap();
+        let (_, text) = snapshot.excerpts().next().unwrap();
+        assert_eq!(text, "ab\u{FFFD}\u{FFFD}cd 一x \u{FFFD}\u{FFFD} y\n");
+        let span = snapshot.diagnostic().labels()[0].span();
+        assert_eq!(span, LabelSpan::from(10..14));
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let cache = Cache::new()
+            .with_source("let 名前 =\r\n    \"値\";\r\n")
+            .with_source(("fn f() {}", "f.rs"));
+        assert_eq!(cache.excerpt(0..18, 0, 80), "let 名前 = \"値\";");
+        assert_eq!(cache.excerpt(4..6, 0, 80), "名前");
+        assert_eq!(cache.excerpt(0..18, 0, 7), "let 名前…");
+        assert_eq!(cache.excerpt(0..18, 0, 1), "…");
+        assert_eq!(cache.excerpt(0..18, 0, 0), "");
+        assert_eq!(cache.excerpt(9..12, 0, 80), "");
+        assert_eq!(cache.excerpt(3..100, 1, 80), "f() {}");
+        assert_eq!(cache.excerpt(0..2, 2, 80), "");
+    }
+
+    #[test]
+    fn test_file_source() {
+        let dir = std::env::temp_dir().join(format!("musubi-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (main, lib) = (dir.join("main.rs"), dir.join("lib.rs"));
+        std::fs::write(&main, "fn main() {\n    let x = 42;\n}\n").unwrap();
+        let cache = Cache::new()
+            .with_file(&main)
+            .with_source((FileSource::new(&lib), "lib.rs"));
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "Error")
+            .with_label((20..21, 0));
+        // lib.rs doesn't exist, but is never read
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains(&format!("{}:2:9", main.display())));
+        assert_eq!(
+            source_name(cache.as_raw(), 1.into()).as_deref(),
+            Some("lib.rs")
+        );
+
+        let mut report = (report.reset())
+            .with_title(Level::Error, "Error")
+            .with_label((0..3, 1));
+        assert!(report.render_to_string(&cache).is_err());
+        let sources: Vec<_> = cache
+            .iter()
+            .map(|(id, _, len, lines)| (id, len, lines))
+            .collect();
+        assert_eq!(sources, [(0, 30, 4), (1, 0, 0)]);
+        // a failed source is read again on the next render
+        std::fs::write(&lib, "mod a;\n").unwrap();
+        let output = report.render_to_string(&cache).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Error: Error
+           ,-[ lib.rs:1:1 ]
+           |
+         1 | mod a;
+           | ^^^
+        ---'
+        "##);
+
+        #[cfg(feature = "mmap")]
+        {
+            // SAFETY: the file is not modified while the cache is alive
+            let source = unsafe { FileSource::new(&main).with_mmap_threshold(0) };
+            let mut report = report.reset().with_label(0..2);
+            let output = report.render_to_string((source, "mapped.rs")).unwrap();
+            assert!(output.contains("mapped.rs:1:1"));
         }
+        std::fs::remove_dir_all(&dir).unwrap();
     }
-}
 
-/// Calculate the display width of a string (simple ASCII version).
-/// For full Unicode support, consider using the unicode-width crate.
-fn unicode_width(s: &str) -> i32 {
-    s.chars().count() as i32
-}
+    #[test]
+    fn test_keyed_sources() {
+        let cache = Cache::keyed()
+            .with_source(("// generated", "gen.rs"))
+            .with_source_keyed(String::from("main"), ("let x = 42;", "main.rs"))
+            .with_source_keyed(String::from("lib"), ("fn foo() {}", "lib.rs"))
+            .with_source_keyed(String::from("lib"), ("fn bar() {}", "lib2.rs"));
+        assert_eq!(cache.len(), 4);
+        assert_eq!((cache.id("main"), cache.id("lib")), (Some(1), Some(3)));
+        assert_eq!(cache.id("gen"), None);
+        assert_eq!(cache.span(3..6, "lib"), LabelSpan::new(3..6, 3));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use insta::assert_snapshot;
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "Error")
+            .with_label(cache.span(4..5, "main"))
+            .with_label(cache.span(3..6, "lib"));
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains("main.rs:1:5"));
+        assert!(output.contains("lib2.rs:1:4"));
+    }
 
-    fn remove_trailing_whitespace(s: &str) -> String {
-        s.lines()
-            .map(|line| line.trim_end())
-            .collect::<Vec<&str>>()
-            .join("\n")
+    #[test]
+    fn test_report_sources() {
+        let code = String::from("fn foo() {}");
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_source(("let x = 42;", "main.rs"))
+            .with_source((code.as_str(), "lib.rs"))
+            .with_title(Level::Error, "Error")
+            .with_label((4..5, 0))
+            .with_label((3..6, 1));
+        let output = report.render_to_string(()).unwrap();
+        assert_snapshot!(output, @r##"
+        Error: Error
+           ╭─[ main.rs:1:5 ]
+           │
+         1 ┤ let x = 42;
+           │     ─  
+           │
+           │─[ lib.rs:1:4 ]
+           │
+         1 ┤ fn foo() {}
+           │    ───  
+        ───╯
+        "##);
+        assert!(report.render_to_json(()).contains(r#""file":"lib.rs""#));
+
+        // a cache given to the render takes precedence
+        let cache = Cache::new()
+            .with_source(("let y = 42;", "other.rs"))
+            .with_source(("fn bar() {}", "lib.rs"));
+        let output = report.render_to_string(&cache).unwrap();
+        assert!(output.contains("other.rs:1:5") && output.contains("fn bar() {}"));
+
+        // reset keeps the sources
+        let mut report = report.reset().with_label((0..2, 1));
+        assert!(report.render_to_string(()).unwrap().contains("lib.rs:1:1"));
     }
 
     #[test]
-    fn test_basic_report() {
+    fn test_related() {
+        let src = "fn f<T: Copy>(t: T) {}\nf(String::new());";
         let mut report = Report::new()
-            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
-            .with_title(Level::Error, "Test error")
-            .with_code("E001")
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "the trait `Copy` is not implemented")
+            .with_label(25..38)
+            .with_message("the trait `Copy` is not implemented for `String`")
+            .with_related(
+                Report::new()
+                    .with_title(Level::Note, "required by a bound in `f`")
+                    .with_label(8..12)
+                    .with_message("required by this bound")
+                    .with_related(Report::new().with_title(Level::Note, "in this call")),
+            )
+            .with_note("consider cloning the value");
+        let output = report.render_to_string(src).unwrap();
+        assert_snapshot!(output, @r##"
+        Error: the trait `Copy` is not implemented
+           ╭─[ <unknown>:2:3 ]
+           │
+         2 ┤ f(String::new());
+           │   ──────┬──────  
+           │         ╰──────── the trait `Copy` is not implemented for `String`
+           │ 
+           │ Note: consider cloning the value
+        ───╯
+            Note: required by a bound in `f`
+               ╭─[ <unknown>:1:9 ]
+               │
+             1 ┤ fn f<T: Copy>(t: T) {}
+               │         ──┬─  
+               │           ╰─── required by this bound
+            ───╯
+                Note: in this call
+        "##);
+
+        // line kinds come from the report being written
+        let mut kinds = Vec::new();
+        report
+            .render_lines(src, |line, kind| kinds.push((kind, line.to_string())))
+            .unwrap();
+        let headers: Vec<_> = (kinds.iter())
+            .filter(|(kind, _)| *kind == LineKind::Header)
+            .map(|(_, line)| line.as_str())
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                "Error: the trait `Copy` is not implemented",
+                "    Note: required by a bound in `f`",
+                "        Note: in this call"
+            ]
+        );
+        let positions = report.measure(src).unwrap();
+        assert_eq!(positions.len(), 1);
+    }
+
+    #[test]
+    fn test_related_connectors() {
+        let src = "macro_rules! m { () => { 1 + \"a\" } }\nfn main() { m!(); }";
+        let report = |config: Config<'static>| {
+            Report::new()
+                .with_config(config.with_color_disabled().with_related_connectors(true))
+                .with_title(Level::Error, "cannot add `&str` to `{integer}`")
+                .with_label(25..32)
+                .with_related(
+                    Report::new()
+                        .with_title(Level::Note, "in this macro invocation")
+                        .with_label(50..55)
+                        .with_related(Report::new().with_title(Level::Note, "in `main`")),
+                )
+                .with_related(Report::new().with_title(Level::Hint, "see `std::ops::Add`"))
+        };
+        let output = report(Config::new()).render_to_string(src).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r#"
+        Error: cannot add `&str` to `{integer}`
+           ╭─[ <unknown>:1:26 ]
+           │
+         1 ┤ macro_rules! m { () => { 1 + "a" } }
+           │                          ───────
+        ───╯
+        ├─▶ Note: in this macro invocation
+        │      ╭─[ <unknown>:2:14 ]
+        │      │
+        │    2 ┤ fn main() { m!(); }
+        │      │              ─────
+        │   ───╯
+        │   ╰─▶ Note: in `main`
+        ╰─▶ Hint: see `std::ops::Add`
+        "#);
+
+        let config = Config::new().with_char_set_ascii().with_related_indent(2);
+        let output = report(config).render_to_string(src).unwrap();
+        let headers: Vec<_> = (output.lines())
+            .filter(|line| line.contains(": "))
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                "Error: cannot add `&str` to `{integer}`",
+                "|>Note: in this macro invocation",
+                "| `>Note: in `main`",
+                "`>Hint: see `std::ops::Add`"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_to_json() {
+        let cache = Cache::new()
+            .with_source(("let a = \"x\";\nlet b = a * 2;", "a.rs"))
+            .with_source(("b", "b.rs"));
+        let report = Report::new()
+            .with_title(Level::Warning, "suspicious \"multiplication\"")
+            .with_primary_label((21..26, 0))
+            .with_message("string times number")
+            .with_label((4..5, 0))
+            .with_label((0..1, 1))
+            .with_label((0..1, 5))
+            .with_help("use `repeat`")
+            .with_suggestion((21..22, 0), "a.repeat(", "")
+            .with_suggestion_part((25..26, 0), ")")
+            .with_applicability(Applicability::MachineApplicable);
+        assert_snapshot!(report.render_to_json(&cache), @r##"{"level":"Warning","title":"suspicious \"multiplication\"","code":null,"labels":[{"file":"a.rs","span":{"start":21,"end":26},"start":{"line":2,"column":9},"end":{"line":2,"column":14},"message":"string times number","primary":true},{"file":"a.rs","span":{"start":4,"end":5},"start":{"line":1,"column":5},"end":{"line":1,"column":6},"message":null,"primary":false},{"file":"b.rs","span":{"start":0,"end":1},"start":{"line":1,"column":1},"end":{"line":1,"column":2},"message":null,"primary":false},{"file":null,"span":{"start":0,"end":1},"start":null,"end":null,"message":null,"primary":false}],"helps":["use `repeat`"],"notes":[],"suggestions":[{"message":null,"applicability":"MachineApplicable","edits":[{"file":"a.rs","span":{"start":21,"end":22},"start":{"line":2,"column":9},"end":{"line":2,"column":10},"replacement":"a.repeat("},{"file":"a.rs","span":{"start":25,"end":26},"start":{"line":2,"column":13},"end":{"line":2,"column":14},"replacement":")"}]}],"location":null}"##);
+    }
+
+    #[cfg(feature = "sarif")]
+    #[test]
+    fn test_sarif() {
+        let cache = Cache::new()
+            .with_source(("let a = \"x\";\nlet b = a * 2;", "src/a.rs"))
+            .with_source("b");
+        let mut sarif = SarifWriter::new("lint");
+        assert!(sarif.is_empty());
+        let report = Report::new()
+            .with_title(Level::Warning, "suspicious multiplication")
+            .with_code("W01")
+            .with_primary_label((21..26, 0))
+            .with_message("string times number")
+            .with_label((0..1, 1))
+            .with_note("strings can't be multiplied")
+            .with_help("use `repeat`");
+        sarif.add_report(&report, &cache);
+        sarif.add_report(&report, &cache);
+        let report = Report::new().with_title(Level::Hint, "no code here");
+        sarif.add_report(&report, &cache);
+        assert_eq!(sarif.len(), 3);
+        assert_snapshot!(sarif.to_run(), @r##"{"tool":{"driver":{"name":"lint","rules":[{"id":"W01"}]}},"columnKind":"unicodeCodePoints","results":[{"ruleId":"W01","level":"warning","message":{"text":"suspicious multiplication"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"src/a.rs"},"region":{"startLine":2,"startColumn":9,"endLine":2,"endColumn":14}},"message":{"text":"string times number"}},{"physicalLocation":{"artifactLocation":{"uri":"<unknown>"},"region":{"startLine":1,"startColumn":1,"endLine":1,"endColumn":2}}}],"relatedLocations":[{"id":0,"message":{"text":"strings can't be multiplied"}}],"properties":{"helps":["use `repeat`"]}},{"ruleId":"W01","level":"warning","message":{"text":"suspicious multiplication"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"src/a.rs"},"region":{"startLine":2,"startColumn":9,"endLine":2,"endColumn":14}},"message":{"text":"string times number"}},{"physicalLocation":{"artifactLocation":{"uri":"<unknown>"},"region":{"startLine":1,"startColumn":1,"endLine":1,"endColumn":2}}}],"relatedLocations":[{"id":0,"message":{"text":"strings can't be multiplied"}}],"properties":{"helps":["use `repeat`"]}},{"level":"note","message":{"text":"no code here"},"locations":[],"relatedLocations":[],"properties":{"helps":[]}}]}"##);
+    }
+
+    #[test]
+    fn test_owned_message_arena() {
+        let helps: Vec<String> = (0..100).map(|i| format!("help number {i}")).collect();
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "Many helps")
             .with_label(0..3)
-            .with_message("this is a test");
+            .with_helps(helps.clone())
+            .with_notes([String::new(), "x".repeat(2000)]);
+        // 1390 bytes of helps fill chunks of 256, 512 and 1024 bytes, and the
+        // long note, which doesn't fit the rest, starts a chunk of 2048
+        let chunks: Vec<_> = (report.owned.chunks.iter())
+            .map(|c| (c.len(), c.capacity()))
+            .collect();
+        assert_eq!(chunks, [(256, 256), (504, 512), (630, 1024), (2000, 2048)]);
+        let output = report.render_to_string("let a = 1;").unwrap();
+        assert!(helps.iter().all(|h| output.contains(h.as_str())));
+        assert!(output.contains(&"x".repeat(2000)));
+    }
 
-        let output = report.render_to_string(("let x = 42;", "test.rs")).unwrap();
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_review() {
+        use crossterm::event::KeyCode;
+
+        let mut emitter = Emitter::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "unknown variable")
+                    .with_label(8..9)
+                    .with_message("not found"),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "unused function")
+                    .with_label((3..4, 1)),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "unused variable")
+                    .with_code("W001")
+                    .with_label(4..5),
+            );
+        let cache = Cache::new()
+            .with_source(("let a = b;", "main.rs"))
+            .with_source(("fn f() {}", "lib.rs"));
+        // the selected row is in reverse video
+        let screen = |lines: Vec<String>| {
+            let screen = lines.join("\n").replace("\x1b[7m", "");
+            remove_trailing_whitespace(&screen.replace("\x1b[0m", ""))
+        };
+        let mut review = tui::Review::new(&mut emitter, &cache, true).unwrap();
+        assert!(review.key(KeyCode::Char('j')));
+        assert_snapshot!(screen(review.draw(60, 12)), @r"
+        3 of 3 reports | file: all (f) | level: all (s) | j/k: selec
+          main.rs:1:9  Error: unknown variable
+        > lib.rs:1:4  Warning: unused function
+          main.rs:1:5  [W001] Warning: unused variable
+        ────────────────────────────────────────────────────────────
+        Warning: unused function
+           ,-[ lib.rs:1:4 ]
+           |
+         1 | fn f() {}
+           |    ^
+        ---'
+        ");
+
+        // only the warnings of main.rs
+        review.key(KeyCode::Char('f'));
+        review.key(KeyCode::Char('s'));
+        review.key(KeyCode::Char('s'));
+        assert_snapshot!(screen(review.draw(60, 4)), @r"
+        1 of 3 reports | file: main.rs (f) | level: Warning (s) | j/
+        > main.rs:1:5  [W001] Warning: unused variable
+        ────────────────────────────────────────────────────────────
+        [W001] Warning: unused variable
+        ");
+        assert!(!review.key(KeyCode::Char('q')));
+    }
+
+    #[cfg(feature = "lsp")]
+    #[test]
+    fn test_lsp() {
+        use lsp_types::{DiagnosticSeverity, Position, Range, Uri};
+
+        let cache = Cache::new()
+            .with_source(("let é = 1;\r\nlet 😀 = é;", "main.rs"))
+            .with_source(("fn é() {}", "file:///lib.rs"));
+        for (pos, position) in [(0, (0, 0)), (4, (0, 4)), (16, (1, 4)), (17, (1, 6))] {
+            let position = Position::new(position.0, position.1);
+            assert_eq!(cache.lsp_position(0, pos, IndexType::Char), Some(position));
+            assert_eq!(cache.lsp_offset(0, position, IndexType::Char), Some(pos));
+        }
+        // in the middle of the emoji, and past the line end
+        assert_eq!(
+            cache.lsp_offset(0, Position::new(1, 5), IndexType::Byte),
+            Some(17)
+        );
+        assert_eq!(
+            cache.lsp_offset(0, Position::new(0, 99), IndexType::Char),
+            Some(10)
+        );
+        assert_eq!(
+            cache.lsp_offset(0, Position::new(2, 0), IndexType::Char),
+            None
+        );
+        assert_eq!(cache.lsp_position(2, 0, IndexType::Char), None);
+
+        let uri: Uri = "file:///main.rs".parse().unwrap();
+        let report = Report::new()
+            .with_title(Level::Hint, "shadowed name")
+            .with_code("H01")
+            .with_label(20..21)
+            .with_message("used here")
+            .with_primary_label(16..17)
+            .with_label((3..4, 1))
+            .with_message("also defined here")
+            .with_note("names should be unique");
+        let lsp = report.to_lsp(&cache, &uri);
+        assert_eq!(
+            lsp.range,
+            Range::new(Position::new(1, 4), Position::new(1, 6))
+        );
+        assert_eq!(lsp.severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(lsp.message, "shadowed name\nnote: names should be unique");
+        let related: Vec<_> = (lsp.related_information.iter().flatten())
+            .map(|r| (r.location.uri.as_str(), r.location.range.start, &*r.message))
+            .collect();
+        assert_eq!(
+            related,
+            [
+                ("file:///main.rs", Position::new(1, 9), "used here"),
+                ("file:///lib.rs", Position::new(0, 3), "also defined here"),
+            ]
+        );
+
+        let diag = Diagnostic::from_lsp(&lsp, &cache, 0, IndexType::Char);
+        assert_eq!(diag.level(), Some(Level::Hint));
+        assert_eq!(diag.code(), Some("H01"));
+        assert_eq!(
+            diag.labels(),
+            [DiagnosticLabel::new(16..17).with_primary(true)]
+        );
+        assert_eq!(diag.notes(), ["used here", "also defined here"]);
+    }
+
+    #[test]
+    fn test_render_to_html() {
+        let mut cg = ColorGenerator::new();
+        let color = cg.next_color();
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled().with_char_set_ascii())
+            .with_title(Level::Warning, "a < b")
+            .with_label(4..9)
+            .with_message("compared here")
+            .with_color(&color);
+        let plain = report.render_to_string("if (a < b) {}").unwrap();
+        let html = report.render_to_html("if (a < b) {}").unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&html), @r##"
+        <pre class="musubi"><span class="mu-warning" style="color:#a60">Warning:</span> a &lt; b
+        <span class="mu-margin" style="color:#949494">   ,-[</span> &lt;unknown&gt;:1:5 <span class="mu-margin" style="color:#949494">]</span>
+        <span class="mu-margin" style="color:#949494">   |</span>
+        <span class="mu-margin" style="color:#949494"> 1 |</span> <span class="mu-unimportant" style="color:#b2b2b2">if (</span><span class="mu-color" style="color:#ff00ff">a &lt; b</span><span class="mu-unimportant" style="color:#b2b2b2">) {}</span>
+        <span class="mu-skipped-margin" style="color:#585858">   |</span>     <span class="mu-color" style="color:#ff00ff">^^|^^</span>
+        <span class="mu-skipped-margin" style="color:#585858">   |</span>       <span class="mu-color" style="color:#ff00ff">`----</span> compared here
+        <span class="mu-margin" style="color:#949494">---&#39;</span>
+        </pre>
+        "##);
+        // the config is left as it was
+        assert_eq!(report.render_to_string("if (a < b) {}").unwrap(), plain);
+    }
+
+    #[test]
+    fn test_render_to_svg() {
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii())
+            .with_title(Level::Error, "a < b")
+            .with_label(4..9)
+            .with_message("compared here");
+        let svg = report.render_to_svg("if (a < b) {}").unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&svg), @r##"
+        <svg xmlns="http://www.w3.org/2000/svg" width="284" height="188" viewBox="0 0 284 188">
+        <rect width="284" height="188" rx="8" fill="#1e1e1e"/>
+        <circle cx="20" cy="16" r="6" fill="#ff5f56"/>
+        <circle cx="40" cy="16" r="6" fill="#ffbd2e"/>
+        <circle cx="60" cy="16" r="6" fill="#27c93f"/>
+        <text font-family="ui-monospace,Menlo,Consolas,monospace" font-size="14" fill="#d4d4d4" xml:space="preserve">
+        <tspan x="16" y="47"><tspan class="mu-error" style="fill:#c00">Error:</tspan> a &lt; b</tspan>
+        <tspan x="16" y="67"><tspan class="mu-margin" style="fill:#949494">   ,-[</tspan> &lt;unknown&gt;:1:5 <tspan class="mu-margin" style="fill:#949494">]</tspan></tspan>
+        <tspan x="16" y="87"><tspan class="mu-margin" style="fill:#949494">   |</tspan></tspan>
+        <tspan x="16" y="107"><tspan class="mu-margin" style="fill:#949494"> 1 |</tspan> <tspan class="mu-unimportant" style="fill:#b2b2b2">if (</tspan><tspan class="mu-label" style="">a &lt; b</tspan><tspan class="mu-unimportant" style="fill:#b2b2b2">) {}</tspan></tspan>
+        <tspan x="16" y="127"><tspan class="mu-skipped-margin" style="fill:#585858">   |</tspan>     <tspan class="mu-label" style="">^^|^^</tspan>  </tspan>
+        <tspan x="16" y="147"><tspan class="mu-skipped-margin" style="fill:#585858">   |</tspan>       <tspan class="mu-label" style="">`----</tspan> compared here</tspan>
+        <tspan x="16" y="167"><tspan class="mu-margin" style="fill:#949494">---&#39;</tspan></tspan>
+        </text>
+        </svg>
+        "##);
+    }
+
+    #[test]
+    fn test_render_to_markdown() {
+        let mut cg = ColorGenerator::new();
+        let color = cg.next_color();
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii())
+            .with_title(Level::Warning, "unused `a_b` <here>")
+            .with_label(4..7)
+            .with_message("never read")
+            .with_color(&color)
+            .with_help("prefix it with an underscore")
+            .with_note("first")
+            .with_note("second");
+        let md = report
+            .render_to_markdown_with_fix("let a_b = 1;\nlet c = 2;", 4..7, "_a_b")
+            .unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&md), @r##"
+        **Warning:** unused `a_b` \<here\>
+
+        ```text
+           ,-[ <unknown>:1:5 ]
+           |
+         1 | let a_b = 1;
+           |     ^|^
+           |      `--- never read
+        ---'
+        ```
+
+        **Help:** prefix it with an underscore
+
+        **Note 1:** first
+
+        **Note 2:** second
+
+        ```suggestion
+        let _a_b = 1;
+        ```
+        "##);
+    }
+
+    #[test]
+    fn test_layout_reuse() {
+        let cache = Cache::new()
+            .with_source(("let a = 1;\nlet b = a;\n", "a.rs"))
+            .with_source(("let c = b;", "b.rs"));
+        let cfg = || Config::new().with_color_disabled();
+        let mut report = Report::new()
+            .with_config(cfg())
+            .with_title(Level::Error, "moved value")
+            .with_label((15..16, 0))
+            .with_message("moved here")
+            .with_label((4..5, 0))
+            .with_message("declared here");
+        let first = report.render_to_string(&cache).unwrap();
+        assert_eq!(report.render_to_string(&cache).unwrap(), first);
+
+        // a temporary cache is laid out again
+        let other = report.render_to_string(("let a = 1;", "c.rs")).unwrap();
+        assert!(other.contains("c.rs"));
+        assert_eq!(report.render_to_string(&cache).unwrap(), first);
+
+        // adding labels and changing the config drop the layout
+        let mut report = report
+            .with_config(cfg().with_context_lines(1))
+            .with_label((8..9, 1))
+            .with_message("used here")
+            .with_order(-1);
         assert_snapshot!(
-            remove_trailing_whitespace(&output),
+            remove_trailing_whitespace(&report.render_to_string(&cache).unwrap()),
             @r##"
-            [E001] Error: Test error
-               ,-[ test.rs:1:1 ]
-               |
-             1 | let x = 42;
-               | ^|^
-               |  `--- this is a test
-            ---'
-            "##
+        Error: moved value
+           ╭─[ b.rs:1:9 ]
+           │
+         1 ┤ let c = b;
+           │         ┌
+           │         ╰── used here
+           │
+           │─[ a.rs:1:5 ]
+           │
+         1 ┤ let a = 1;
+           │     ┌
+           │     ╰── declared here
+         2 ┤ let b = a;
+           │     ┌
+           │     ╰── moved here
+         3 ┤
+        ───╯
+        "##
         );
     }
 
     #[test]
-    fn test_config() {
-        let config = Config::new()
-            .with_compact(true)
-            .with_char_set_ascii()
-            .with_color_disabled();
+    fn test_cache_init_all() {
+        struct Failing;
+        impl Source for Failing {
+            fn init(&mut self) -> io::Result<()> {
+                Err(io::Error::other("unreadable"))
+            }
+            fn get_line(&self, _: usize) -> &[u8] {
+                b""
+            }
+            fn get_line_info(&self, _: usize) -> Line {
+                Line::new()
+            }
+            fn line_for_chars(&self, _: usize) -> (usize, Line) {
+                (0, Line::new())
+            }
+            fn line_for_bytes(&self, _: usize) -> (usize, Line) {
+                (0, Line::new())
+            }
+        }
+
+        Cache::new().init_all().unwrap();
+        let cache = Cache::new()
+            .with_source(("a\nb\n", "a.rs"))
+            .with_source(("c", "b.rs"));
+        cache.init_all().unwrap();
+        // SAFETY: loaded sources are initialized
+        unsafe {
+            assert_eq!((*ffi::mu_getsource(cache.inner, 0)).inited, 1);
+            assert_eq!(ffi::mu_linecount(ffi::mu_getsource(cache.inner, 0)), 3);
+        }
+
+        let cache = cache.with_source((Failing, "c.rs"));
+        let err = cache.init_all().unwrap_err();
+        assert_eq!(err.to_string(), "Source 2 (c.rs) init error");
+
+        #[cfg(feature = "rayon")]
+        {
+            let cache = Cache::new()
+                .with_source(("a\nb\n", "a.rs"))
+                .with_source(("c", "b.rs"));
+            // SAFETY: both sources are strings
+            unsafe { cache.par_init_all() }.unwrap();
+            // SAFETY: loaded sources are initialized
+            unsafe { assert_eq!((*ffi::mu_getsource(cache.inner, 1)).inited, 1) };
+        }
+    }
+
+    #[test]
+    fn test_measure() {
+        let src = ("fn main() {\n    let 名前 = 1;\n    名前\n}", "main.rs", 9);
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Warning, "unused value")
+            .with_label(16..18)
+            .with_message("bound here")
+            .with_label(26..28)
+            .with_label(10..35)
+            .with_message("in this function");
+        let positions = report.measure(src).unwrap();
+        assert_eq!(
+            positions
+                .iter()
+                .map(|p| (p.label, p.line))
+                .collect::<Vec<_>>(),
+            [(0, 11), (2, 12)]
+        );
+        let output = report.render_to_string(src).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        for (p, msg) in positions.iter().zip(["bound here", "in this function"]) {
+            let rest: String = lines[p.row].chars().skip(p.column).collect();
+            assert_eq!(rest, msg);
+            let gutter = format!("{} ", p.line);
+            assert!(lines[p.source_row].trim_start().starts_with(&gutter));
+        }
+    }
+
+    #[test]
+    fn test_render_to_github_annotations() {
+        let src = ("fn main() {\n    let a = 1;\n}", "src/main.rs");
+        let report = Report::new()
+            .with_title(Level::Warning, "unused variable: a, b")
+            .with_code("W01")
+            .with_label(20..21)
+            .with_message("100% unused\nremove it")
+            .with_label(10..28);
+        let mut out = Vec::new();
+        report.render_to_github_annotations(&mut out, src).unwrap();
+        assert_snapshot!(String::from_utf8(out).unwrap(), @r##"
+        ::warning file=src/main.rs,line=2,col=9,endLine=2,endColumn=10,title=[W01] unused variable%3A a%2C b::100%25 unused%0Aremove it
+        ::warning file=src/main.rs,line=1,col=11,endLine=3,endColumn=2,title=[W01] unused variable%3A a%2C b::unused variable: a, b
+        "##);
+
+        let report = Report::new().with_title(Level::Note, "no labels");
+        let mut out = Vec::new();
+        report.render_to_github_annotations(&mut out, src).unwrap();
+        assert_snapshot!(String::from_utf8(out).unwrap(), @r##"
+        ::notice title=no labels::no labels
+        "##);
+    }
+
+    #[test]
+    fn test_suggestion() {
+        let src = "fn main() {\n\tlet a = 1;\n}";
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Warning, "unused variable")
+            .with_label(17..18)
+            .with_message("never read")
+            .with_help("variables starting with `_` may be unused")
+            .with_suggestion(17..18, "_a", "")
+            .with_suggestion(13..23, "// let a = 1;\n\tlet _b = 2;", "comment it out")
+            .with_suggestion(16..18, "", "");
+        let output = report.render_to_string(src).unwrap();
+        assert_snapshot!(output, @r##"
+        Warning: unused variable
+           ╭─[ <unknown>:2:6 ]
+           │
+         2 ┤     let a = 1;
+           │         ┌  
+           │         ╰── never read
+           │ 
+           │ Help 1: variables starting with `_` may be unused
+           │ 
+           │ Help 2: replace with `_a`
+         2 ┤     let _a = 1;
+           │         ──
+           │ 
+           │ Help 3: comment it out
+         2 ┤     // let a = 1;
+           │     ─────────────
+         3 ┤     let _b = 2;
+           │ ───────────────
+           │ 
+           │ Help 4: remove this
+         2 ┤     let = 1;
+        ───╯
+        "##);
+
+        // without labels, and with a span of a source without labels
+        let mut report = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "syntax error")
+            .with_suggestion((1..1, 1), ";", "add a semicolon");
+        let cache = Cache::new()
+            .with_source(("", "a.rs"))
+            .with_source(("x = 1", "b.rs"));
+        let output = report.render_to_string(&cache).unwrap();
+        assert_snapshot!(output, @r##"
+        Error: syntax error
+           │ 
+           │ Help: add a semicolon
+         1 ┤ x; = 1
+           │  ─
+        ───╯
+        "##);
+    }
+
+    #[test]
+    fn test_render_side_by_side() {
+        let cache = Cache::new()
+            .with_source(("fn f() -> i32 {\n    1\n}", "expected.rs"))
+            .with_source(("fn f() -> i64 {\n    1\n}\n// generated", "actual.rs"));
+        let mut expected = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Note, "expected")
+            .with_label((10..13, 0))
+            .with_message("here");
+        let mut actual = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "mismatched type")
+            .with_label((10..13, 1))
+            .with_message("should be `i32`")
+            .with_note("generated by `gen.py`");
+        let output = expected.render_side_by_side(&mut actual, &cache).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r##"
+        Note: expected               Error: mismatched type
+           ╭─[ expected.rs:1:11 ]       ╭─[ actual.rs:1:11 ]
+           │                            │
+         1 ┤ fn f() -> i32 {          1 ┤ fn f() -> i64 {
+           │           ─┬─              │           ─┬─
+           │            ╰─── here       │            ╰─── should be `i32`
+        ───╯                            │
+                                        │ Note: generated by `gen.py`
+                                     ───╯
+        "##);
+    }
 
+    #[test]
+    fn test_suggestion_parts() {
+        let src = "fn f(a: i32) {\n\tg(a, a)\n}";
         let mut report = Report::new()
-            .with_config(config)
-            .with_title(Level::Warning, "Test warning")
-            .with_label(0..5)
-            .with_message("test");
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "mismatched types")
+            .with_label(21..22)
+            .with_message("expected `i64`")
+            .with_suggestion(18..18, "(", "")
+            .with_suggestion_part(19..19, ") as i64")
+            .with_suggestion_part(21..22, "i64::from(a)")
+            .with_suggestion(8..11, "i64", "change the parameter type")
+            .with_suggestion_part(16..16, "let b = (...);\n\t")
+            .with_applicability(Applicability::HasPlaceholders);
+        let output = report.render_to_string(src).unwrap();
+        assert_snapshot!(output, @r##"
+        Error: mismatched types
+           ╭─[ <unknown>:2:7 ]
+           │
+         2 ┤     g(a, a)
+           │          ┌  
+           │          ╰── expected `i64`
+           │ 
+           │ Help 1: apply these changes
+         2 ┤     g((a) as i64, i64::from(a))
+           │       ─ ────────  ────────────
+           │ 
+           │ Help 2: change the parameter type
+         1 ┤ fn f(a: i64) {
+           │         ───
+         2 ┤     let b = (...);
+           │     ──────────────
+         3 ┤     g(a, a)
+           │ ────
+        ───╯
+        "##);
+    }
 
-        let output = report.render_to_string(("hello", "test.rs")).unwrap();
-        assert_snapshot!(
-            remove_trailing_whitespace(&output),
-            @r##"
-            Warning: Test warning
-               ,-[ test.rs:1:1 ]
-             1 |hello
-               |^^|^^
-               |  `--- test
-            "##
-        );
+    #[test]
+    fn test_expected_found() {
+        let src = "let x: i32 = \"\";";
+        let output = Report::new()
+            .with_config(Config::new().with_color_disabled())
+            .with_title(Level::Error, "mismatched types")
+            .with_expected_found(13..15, "i32", "&str")
+            .render_to_string(src)
+            .unwrap();
+        assert_snapshot!(output, @r##"
+        Error: mismatched types
+           ╭─[ <unknown>:1:14 ]
+           │
+         1 ┤ let x: i32 = "";
+           │              ─┐  
+           │               ╰── expected `i32`
+           │               │  
+           │               ╰── found    `&str`
+        ───╯
+        "##);
+
+        // expected in green, found in red
+        let output = Report::new()
+            .with_title(Level::Error, "mismatched types")
+            .with_expected_found(13..15, "i32", "&str")
+            .render_to_string(src)
+            .unwrap();
+        let expected = output.find("\x1b[32m╰──\x1b[0m expected").unwrap();
+        let found = output.find("\x1b[31m╰──\x1b[0m found").unwrap();
+        assert!(expected < found);
     }
 
     #[test]
@@ -2650,6 +10397,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_custom_charset_reuse() {
+        let custom = CharSet {
+            hbar: '=',
+            vbar: '¦',
+            ..CharSet::ascii()
+        };
+        let config = Config::new().with_char_set(&custom).with_color_disabled();
+        assert_eq!(config.char_set(), custom);
+        let render = |config: Config<'_>| {
+            Report::new()
+                .with_config(config)
+                .with_title(Level::Error, "Test")
+                .with_label(0..5usize)
+                .with_message("here")
+                .render_to_string(("hello", "test.rs"))
+                .unwrap()
+        };
+        // clones get their own buffer, and outlive the original
+        let copy = config.clone();
+        let first = render(config);
+        assert!(first.contains("¦ ^^|^^"));
+        assert_eq!(render(copy.clone()), first);
+        assert_eq!(render(copy), first);
+    }
+
     #[test]
     fn test_custom_color() {
         struct CustomColor;
@@ -2684,6 +10457,196 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_coverage() {
+        let cache = Cache::new()
+            .with_source("let é = 1;\r\nlet b = é;\n")
+            .with_source("");
+        let coverage = cache
+            .coverage(0, [8..9, 4..5, 20..21, 4..6, 16..30])
+            .unwrap();
+        assert_eq!(coverage.source_len(), 23);
+        assert_eq!(coverage.covered(), [4..6, 8..9, 16..23]);
+        assert_eq!(coverage.gaps(), [0..4, 6..8, 9..16]);
+        assert_eq!(coverage.overlaps(), [4..5, 20..21]);
+        assert!(!coverage.is_disjoint());
+
+        let coverage = cache.coverage(1, Some(0..3)).unwrap();
+        assert_eq!((coverage.covered(), coverage.gaps()), (&[][..], vec![]));
+        assert!(cache.coverage(2, Some(0..3)).is_none());
+        assert_eq!(
+            Coverage::new([0..0, 3..5, 8..9, 5..6], 9).covered(),
+            [3..6, 8..9]
+        );
+    }
+
+    #[test]
+    fn test_find_spans() {
+        let cache = Cache::new()
+            .with_source("let é = 1;\r\nlet b = é;\n")
+            .with_source(OwnedSource::new(b"\xff let\nlet \xc3\xa9\n"));
+        let ranges = |spans: Vec<LabelSpan>| {
+            (spans.iter())
+                .map(|span| (span.start(), span.end(), span.src_id()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ranges(cache.find_spans(0, "let")), [(0, 3, 0), (12, 15, 0)]);
+        assert_eq!(ranges(cache.find_spans(0, 'é')), [(4, 5, 0), (20, 21, 0)]);
+        assert_eq!(ranges(cache.find_spans(1, "let".to_string())), [(6, 9, 1)]);
+        assert!(cache.find_spans(0, "").is_empty());
+        assert!(cache.find_spans(0, ";\r").is_empty());
+        assert!(cache.find_spans(2, "let").is_empty());
+
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Warning, "non-ASCII name");
+        for span in cache.find_spans(0, 'é') {
+            report = report.with_label(span).with_message("here");
+        }
+        let output = report.render_to_string(&cache).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Warning: non-ASCII name
+           ,-[ <unknown>:1:5 ]
+           |
+         1 | let é = 1;
+           |     |
+           |     `-- here
+         2 | let b = é;
+           |         |
+           |         `-- here
+        ---'
+        ");
+    }
+
+    #[test]
+    fn test_anchor() {
+        let mut cache = Cache::new()
+            .with_source(("let a = 1;\n", "a.rs"))
+            .with_source(("let x = y;\r\nlet z = x;\r\n", "b.rs"));
+        let (x, y, z) = (cache.anchor(1, 4), cache.anchor(1, 8), cache.anchor(1, 16));
+        let head = cache.anchor(0, 4);
+        let mut before = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "unknown name")
+            .with_label(y.span_to(&y))
+            .with_message("here");
+        assert!(
+            before
+                .render_to_string(&cache)
+                .unwrap()
+                .contains("let x = y;")
+        );
+
+        cache.apply_edit(1, 4..5, "xs").unwrap();
+        cache.apply_edit(1, 0..0, "// é\r\n").unwrap();
+        cache.apply_edit(1, 13..14, "").unwrap();
+        assert_eq!((x.offset(), y.offset(), z.offset()), (10, 14, 22));
+        assert_eq!(head.offset(), 4);
+        assert!(cache.apply_edit(2, 0..0, "").is_err());
+        assert!(
+            before
+                .render_to_string(&cache)
+                .unwrap()
+                .contains("let xs  y;")
+        );
+
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "unknown name")
+            .with_label(x.span_to(&y))
+            .with_message("shadowed");
+        report = report.with_label(&z).with_message("here");
+        let output = report.render_to_string(&cache).unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: unknown name
+           ,-[ b.rs:2:5 ]
+           |
+         2 | let xs  y;
+           |     ^^|^
+           |       `--- shadowed
+         3 | let z = x;
+           |     ^
+           |     `- here
+        ---'
+        ");
+
+        let mut cache = Cache::new().with_source("a + b");
+        let b = cache.anchor(0, 4);
+        cache.apply_edit(0, 0..1, "left").unwrap();
+        assert_eq!(b.offset(), 7);
+        let output = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "unknown name")
+            .with_label(&b)
+            .render_to_string(&cache)
+            .unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: unknown name
+           ,-[ <unknown>:1:8 ]
+           |
+         1 | left + b
+           |        ^
+        ---'
+        ");
+
+        // the name of a file source is owned by the source being replaced
+        let path = std::env::temp_dir().join(format!("musubi-edit-{}.rs", std::process::id()));
+        std::fs::write(&path, "let x = y;\n").unwrap();
+        let mut cache = Cache::new().with_file(&path);
+        let y = cache.anchor(0, 8);
+        cache.apply_edit(0, 0..0, "// x\n").unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let output = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "unknown name")
+            .with_label(&y)
+            .render_to_string(&cache)
+            .unwrap();
+        assert!(output.contains(&format!("[ {}:2:9 ]", path.display())));
+    }
+
+    #[test]
+    fn test_render_line_map() {
+        let cache = Cache::new()
+            .with_source(("fn f() {\n    g(1);\n}\n", "a.rs", 9))
+            .with_source(("fn g(x: u8) {}\n", "b.rs"));
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "mismatched types")
+            .with_label((0..20, 0))
+            .with_message("in this call")
+            .with_label((5..10, 1))
+            .with_message("expected u8")
+            .with_help("pass a u8");
+        let (output, map) = report.render_line_map(&cache).unwrap();
+        assert_eq!(output, report.render_to_string(&cache).unwrap());
+        let annotated: Vec<_> = (output.lines().zip(&map))
+            .map(|(text, line)| match line {
+                Some(l) => format!("{}:{:<2} {text}", l.src_id, l.line),
+                None => format!("     {text}"),
+            })
+            .collect();
+        assert_snapshot!(remove_trailing_whitespace(&annotated.join("\n")), @r"
+             Error: mismatched types
+                 ,-[ a.rs:10:1 ]
+                 |
+        0:10  10 | ,-> fn f() {
+                 : :
+        0:12  12 | |-> }
+        0:12     | |
+        0:12     | `------- in this call
+                 |
+                 |-[ b.rs:1:6 ]
+                 |
+        1:1    1 | fn g(x: u8) {}
+        1:1      |      ^^|^^
+        1:1      |        `---- expected u8
+                 |
+                 | Help: pass a u8
+             ----'
+        ");
+    }
+
     #[test]
     fn test_color_gen() {
         let mut cg = ColorGenerator::new();
@@ -2711,6 +10674,132 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_color_mode() {
+        let colors = |cg: &mut ColorGenerator| {
+            let codes = (0..4).map(|_| {
+                let code = cg.next_color();
+                let bytes = code.0[1..=code.0[0] as usize].iter().map(|&c| c as u8);
+                String::from_utf8(bytes.collect())
+                    .unwrap()
+                    .replace('\x1b', "ESC")
+            });
+            codes.collect::<Vec<_>>().join(" ")
+        };
+        let mut cg = ColorGenerator::new().with_color_mode_when(ColorMode::TrueColor, true);
+        assert_snapshot!(colors(&mut cg), @r"
+        ESC[38;2;236;141;171m ESC[38;2;136;192;115m ESC[38;2;152;167;250m ESC[38;2;233;151;99m
+        ");
+        let mut cg = ColorGenerator::new().with_color_mode_when(ColorMode::TrueColor, false);
+        assert_eq!(cg.color_mode(), ColorMode::TrueColor);
+        assert_snapshot!(colors(&mut cg), @r"
+        ESC[38;5;211m ESC[38;5;108m ESC[38;5;111m ESC[38;5;173m
+        ");
+        assert_eq!(rgb_to_ansi256((255, 0, 95)), 197);
+        assert!(truecolor_supported(|_| Some("24bit".into())));
+        assert!(!truecolor_supported(|_| None));
+    }
+
+    #[test]
+    fn test_style() {
+        struct Theme;
+        impl Color for Theme {
+            fn color(&self, w: &mut dyn Write, kind: ColorKind) -> std::io::Result<()> {
+                let style = match kind {
+                    ColorKind::Reset => return w.write_all(b"\x1b[0m"),
+                    ColorKind::Error => Style::new().with_fg(1).with_bold(true),
+                    ColorKind::Note => Style::new().with_italic(true).with_dim(true),
+                    _ => Style::new(),
+                };
+                write!(w, "{style}")
+            }
+        }
+
+        let highlight = Style::new()
+            .with_fg((0, 0, 0))
+            .with_bg((255, 215, 0))
+            .with_bold(true)
+            .with_underline(true);
+        assert_eq!(highlight.to_string().len(), 32);
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color(&Theme))
+            .with_title(Level::Error, "unknown name")
+            .with_label(8..9)
+            .with_message("not found")
+            .with_color(&highlight)
+            .with_note("names are case sensitive");
+        let output = report.render_to_string("let a = b;").unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output).replace('\x1b', "ESC"),
+            @r"
+        ESC[1;38;5;1mError:ESC[0m unknown name
+           ,-[ESC[0m <unknown>:1:9 ]ESC[0m
+           |ESC[0m
+         1 |ESC[0m let a = ESC[0mESC[1;4;38;2;0;0;0;48;2;255;215;0mbESC[0m;ESC[0m
+           |ESC[0m         ESC[1;4;38;2;0;0;0;48;2;255;215;0m|ESC[0m
+           |ESC[0m         ESC[1;4;38;2;0;0;0;48;2;255;215;0m`--ESC[0m not found
+           |ESC[0m
+           |ESC[0m ESC[2;3mNote: names are case sensitiveESC[0m
+        ---'ESC[0m
+        "
+        );
+    }
+
+    #[cfg(feature = "anstyle")]
+    #[test]
+    fn test_anstyle() {
+        use anstyle::{Ansi256Color, AnsiColor, RgbColor};
+
+        let margin = anstyle::Style::new().dimmed();
+        let label = GenColor::from(anstyle::Color::Rgb(RgbColor(255, 128, 0)));
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color(&margin))
+            .with_title(Level::Error, "unknown name")
+            .with_label(4..5)
+            .with_color(&label)
+            .with_label(8..9)
+            .with_color(&GenColor::from(anstyle::Color::Ansi256(Ansi256Color(42))))
+            .with_label(0..3)
+            .with_color(&GenColor::from(anstyle::Color::Ansi(AnsiColor::BrightBlue)));
+        let output = report.render_to_string("let x = y;").unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output).replace('\x1b', "ESC"),
+            @r"
+        ESC[2mError:ESC[0m unknown name
+        ESC[2m   ,-[ESC[0m <unknown>:1:1 ESC[2m]ESC[0m
+        ESC[2m   |ESC[0m
+        ESC[2m 1 |ESC[0m ESC[94mletESC[0mESC[2m ESC[0mESC[38;2;255;128;0mxESC[0mESC[2m = ESC[0mESC[38;5;42myESC[0mESC[2m;ESC[0m
+        ESC[2m   |ESC[0m ESC[94m^^^ESC[0m ESC[38;2;255;128;0m^ESC[0m   ESC[38;5;42m^ESC[0m
+        ESC[2m---'ESC[0m
+        "
+        );
+    }
+
+    #[cfg(feature = "owo-colors")]
+    #[test]
+    fn test_owo_colors() {
+        let margin = owo_colors::Style::new().dimmed();
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color(&margin))
+            .with_title(Level::Error, "unknown name")
+            .with_label(4..5)
+            .with_color(&owo_colors::Style::new().truecolor(255, 128, 0).underline())
+            .with_label(8..9)
+            .with_color(&owo_colors::Style::new().bright_blue().on_black());
+        let output = report.render_to_string("let x = y;").unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&output).replace('\x1b', "ESC"),
+            @r"
+        ESC[2mError:ESC[0m unknown name
+        ESC[2m   ,-[ESC[0m <unknown>:1:5 ESC[2m]ESC[0m
+        ESC[2m   |ESC[0m
+        ESC[2m 1 |ESC[0m ESC[2mlet ESC[0mESC[38;2;255;128;0;4mxESC[0mESC[2m = ESC[0mESC[94;40myESC[0mESC[2m;ESC[0m
+        ESC[2m   |ESC[0m     ESC[38;2;255;128;0;4m^ESC[0m   ESC[94;40m^ESC[0m
+        ESC[2m---'ESC[0m
+        "
+        );
+    }
+
     #[test]
     fn test_custom_label_color() {
         struct CustomColor;
@@ -2745,6 +10834,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_many_label_colors() {
+        struct Tag(usize);
+        impl Color for Tag {
+            fn color(&self, w: &mut dyn Write, kind: ColorKind) -> std::io::Result<()> {
+                match kind {
+                    ColorKind::Reset => Ok(()),
+                    _ => write!(w, "<{}>", self.0),
+                }
+            }
+        }
+
+        let tags: Vec<Tag> = (0..30).map(Tag).collect();
+        let src = "x".repeat(tags.len());
+        let mut report = Report::new().with_config(Config::new().with_char_set_ascii());
+        for (i, tag) in tags.iter().enumerate() {
+            report = report.with_label(i..i + 1).with_color(tag);
+        }
+        // 30 colors fit in chunks of 8, 16 and 32 entries
+        let chunks: Vec<_> = report.color_uds.chunks.iter().map(Vec::capacity).collect();
+        assert_eq!(chunks, [8, 16, 32]);
+        let output = report.render_to_string(src.as_str()).unwrap();
+        assert!(tags.iter().all(|t| output.contains(&format!("<{}>", t.0))));
+    }
+
     #[test]
     fn test_source_with_line_offset() {
         let mut report = Report::new()
@@ -2952,6 +11066,571 @@ mod tests {
         assert!(output.contains("second"));
     }
 
+    #[test]
+    fn test_auto_priority() {
+        let mut cg = ColorGenerator::new();
+        let (wide, short) = (cg.next_color(), cg.next_color());
+        let render = |auto: bool| {
+            Report::new()
+                .with_config(Config::new().with_auto_priority(auto))
+                .with_title(Level::Error, "mismatched types")
+                .with_primary_label(8..15)
+                .with_color(&wide)
+                .with_label(12..15)
+                .with_color(&short)
+                .render_to_string("let x = 1 + \"a\";")
+                .unwrap()
+        };
+        let (off, on) = (render(false), render(true));
+        assert!(on.contains("1 + \"a\"\x1b[0m"), "{on:?}");
+        assert!(!off.contains("1 + \"a\"\x1b[0m"), "{off:?}");
+
+        // merged reports: the error wins over the warning on the same span
+        let mut emitter = Emitter::new()
+            .with_config(Config::new().with_char_set_ascii().with_auto_priority(true))
+            .with_grouping(true)
+            .with_report(
+                Report::new()
+                    .with_title(Level::Warning, "unused")
+                    .with_label(12..15),
+            )
+            .with_report(
+                Report::new()
+                    .with_title(Level::Error, "mismatched types")
+                    .with_label(8..15),
+            );
+        let output = emitter.render_to_string("let x = 1 + \"a\";").unwrap();
+        assert!(output.contains("1 + \"a\"\x1b[0m"), "{output:?}");
+    }
+
+    #[test]
+    fn test_overlap_strategy() {
+        let render = |overlap: Overlap| {
+            let output = Report::new()
+                .with_config(
+                    Config::new()
+                        .with_char_set_ascii()
+                        .with_color_disabled()
+                        .with_compact(true)
+                        .with_overlap_strategy(overlap),
+                )
+                .with_title(Level::Error, "Error")
+                .with_label(4..12)
+                .with_message("args")
+                .with_label(4..7)
+                .with_message("first")
+                .with_label(9..12)
+                .with_message("second")
+                .render_to_string("foo(bar, baz)")
+                .unwrap();
+            remove_trailing_whitespace(&output)
+        };
+        assert_eq!(Config::new().overlap_strategy(), Overlap::MergeColors);
+        assert_snapshot!(render(Overlap::MergeColors), @r"
+        Error: Error
+           ,-[ <unknown>:1:5 ]
+         1 |foo(bar, baz)
+           |    ^|^^|^|^
+           |     |  | `-- second
+           |     |  `---- args
+           |     `------- first
+        ");
+        assert_snapshot!(render(Overlap::Stack), @r"
+        Error: Error
+           ,-[ <unknown>:1:5 ]
+         1 |foo(bar, baz)
+           |    ^|^  ^|^
+           |    ^|^^|^|^
+           |     |  | `-- second
+           |     |  `---- args
+           |     `------- first
+        ");
+        assert_snapshot!(render(Overlap::HideLower), @r"
+        Error: Error
+           ,-[ <unknown>:1:5 ]
+         1 |foo(bar, baz)
+           |    ^|^  ^|^
+           |     |    `-- second
+           |     `------- first
+        ");
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let src = "fn main() {\n    let a = 1;\n    let b = 2;\n    a + b\n}";
+        let render = |depth: i32| {
+            let output = Report::new()
+                .with_config(
+                    Config::new()
+                        .with_char_set_ascii()
+                        .with_color_disabled()
+                        .with_max_depth(depth),
+                )
+                .with_title(Level::Error, "Error")
+                .with_label(10..49)
+                .with_message("body")
+                .with_label(16..33)
+                .with_message("bindings")
+                .with_label(24..45)
+                .with_message("uses")
+                .render_to_string(src)
+                .unwrap();
+            remove_trailing_whitespace(&output)
+        };
+        assert_eq!(render(0), render(3));
+        assert_snapshot!(render(1), @r"
+        Error: Error
+           ,-[ <unknown>:1:11 ]
+           |
+         1 | ,-> fn main() {
+           : :
+         4 | |->     a + b
+           | |
+           | `--------------- body
+           |
+           | > 2:5: bindings
+           | > 2:13: uses
+        ---'
+        ");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_color_windows_console() {
+        let render = |config: Config| {
+            Report::new()
+                .with_config(config)
+                .with_title(Level::Error, "unknown name")
+                .with_label(4..5)
+                .render_to_string("let x;")
+                .unwrap()
+        };
+        let colored = render(Config::new().with_color_windows_console());
+        assert!(colored.contains("\x1b["));
+        assert_eq!(colored, render(Config::new().with_color_default()));
+    }
+
+    #[test]
+    fn test_color_auto() {
+        let allowed = |vars: &[(&str, &str)], is_terminal| {
+            let var = |key: &str| {
+                let v = vars.iter().find(|(k, _)| *k == key)?;
+                Some(std::ffi::OsString::from(v.1))
+            };
+            color_allowed(var, is_terminal)
+        };
+        assert!(allowed(&[], true));
+        assert!(!allowed(&[], false));
+        assert!(allowed(&[("NO_COLOR", "")], true));
+        assert!(!allowed(
+            &[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")],
+            true
+        ));
+        assert!(allowed(&[("CLICOLOR_FORCE", "1"), ("TERM", "dumb")], false));
+        assert!(!allowed(&[("CLICOLOR_FORCE", "0")], false));
+        assert!(!allowed(&[("CLICOLOR", "0")], true));
+        assert!(allowed(&[("CLICOLOR", "1")], true));
+        assert!(!allowed(&[("TERM", "dumb")], true));
+
+        let file = std::fs::File::open("Cargo.toml").unwrap();
+        let config = Config::new().with_color_auto_for(&file);
+        let output = Report::new()
+            .with_config(config)
+            .with_title(Level::Error, "Error")
+            .with_label(0..3)
+            .render_to_string("abc")
+            .unwrap();
+        assert_eq!(
+            output.contains('\x1b'),
+            color_allowed(|k| std::env::var_os(k), false)
+        );
+
+        // only stdout decides, whatever stderr is
+        assert_eq!(
+            Config::new().with_color_auto().is_color_enabled(),
+            (Config::new().with_color_auto_for(&io::stdout())).is_color_enabled()
+        );
+    }
+
+    #[test]
+    fn test_gutter_bar() {
+        let src = "fn main() {\n    let a = 1;\n    let b = 2;\n    a + b\n}";
+        let report = |config: Config<'static>| {
+            Report::new()
+                .with_config(config.with_gutter_bar(true))
+                .with_title(Level::Warning, "Warning")
+                .with_label(10..49)
+                .with_message("body")
+                .with_label(20..21)
+                .with_message("unused")
+                .with_help("remove it")
+                .render_to_string(src)
+                .unwrap()
+        };
+        let output = report(Config::new().with_char_set_ascii().with_color_disabled());
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Warning: Warning
+        |  ,-[ <unknown>:1:11 ]
+        |  |
+        |1 | ,-> fn main() {
+        |2 | |       let a = 1;
+        |  | |           |
+        |  | |           `-- unused
+        |  : :
+        |4 | |->     a + b
+        |  | |
+        |  | `--------------- body
+        |  |
+        |  | Help: remove it
+        |--'
+        ");
+
+        let output = report(Config::new());
+        let (header, body) = output.split_once('\n').unwrap();
+        let code = &header[..header.find("Warning").unwrap()];
+        assert!(!code.is_empty());
+        assert!(body.lines().all(|line| line.starts_with(code)));
+    }
+
+    #[test]
+    fn test_footer() {
+        let render = |footer, code| {
+            let config = Config::new().with_char_set_ascii().with_color_disabled();
+            let mut report = Report::new()
+                .with_config(config.with_footer(footer))
+                .with_title(Level::Error, "mismatched types")
+                .with_label(8..9);
+            if let Some(code) = code {
+                report = report.with_code(code);
+            }
+            report.render_to_string("let a = b;").unwrap()
+        };
+        let output = render(Footer::Labeled, Some("E0308"));
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        [E0308] Error: mismatched types
+           ,-[ <unknown>:1:9 ]
+           |
+         1 | let a = b;
+           |         ^
+        ---'-[ end E0308 ]
+        ");
+        let last = |output: String| output.lines().last().unwrap().trim_end().to_string();
+        assert_eq!(last(render(Footer::Labeled, None)), "---'-[ end ]");
+        assert_eq!(last(render(Footer::Border, None)), "---'");
+        let omitted = render(Footer::Omit, None);
+        assert!(last(omitted.clone()).ends_with('^'));
+        assert_eq!(render(Footer::Blank, None), omitted + "\n");
+    }
+
+    #[test]
+    fn test_theme() {
+        let render = |theme| {
+            Report::new()
+                .with_config(Config::new().with_theme(theme))
+                .with_title(Level::Error, "mismatched types")
+                .with_label(8..13)
+                .with_message("expected `i32`")
+                .render_to_string(("let a = \"one\";", "main.rs"))
+                .unwrap()
+                .replace('\x1b', "ESC")
+        };
+        let output = [Theme::Ariadne, Theme::Rustc, Theme::Gcc, Theme::Monochrome].map(render);
+        assert_snapshot!(remove_trailing_whitespace(&output.join("\n")), @r#"
+        ESC[31mError:ESC[0m mismatched types
+        ESC[38;5;246m   ╭─[ESC[0m main.rs:1:9 ESC[38;5;246m]ESC[0m
+        ESC[38;5;246m   │ESC[0m
+        ESC[38;5;246m 1 ┤ESC[0m ESC[38;5;249mlet a = ESC[0mESC[39m"one"ESC[0mESC[38;5;249m;ESC[0m
+        ESC[38;5;240m   │ESC[0m         ESC[39m──┬──ESC[0m
+        ESC[38;5;240m   │ESC[0m           ESC[39m╰────ESC[0m expected `i32`
+        ESC[38;5;246m───╯ESC[0m
+
+        ESC[1;91mError:ESC[0m mismatched types
+        ESC[1;94m   ,-[ESC[0m main.rs:1:9 ESC[1;94m]ESC[0m
+        ESC[1;94m   |ESC[0m
+        ESC[1;94m 1 |ESC[0m let a = ESC[0mESC[1;94m"one"ESC[0m;ESC[0m
+        ESC[1;94m   |ESC[0m         ESC[1;94m|^^^^ESC[0m
+        ESC[1;94m   |ESC[0m         ESC[1;94m`------ESC[0m expected `i32`
+        ESC[1;94m---'ESC[0m
+
+        ESC[1;31mError:ESC[0m mismatched types
+           ,-[ESC[0m main.rs:1:9 ]ESC[0m
+         1 |ESC[0mlet a = ESC[0mESC[32m"one"ESC[0m;ESC[0m
+           |ESC[0m        ESC[32m|^^^^ESC[0m
+           |ESC[0m        ESC[32m`-----ESC[0m expected `i32`
+
+        Error: mismatched types
+           ,-[ main.rs:1:9 ]
+           |
+         1 | let a = "one";
+           |         ^^|^^
+           |           `---- expected `i32`
+        ---'
+        "#);
+        let config = Config::new()
+            .with_theme(Theme::Gcc)
+            .with_theme(Theme::Ariadne);
+        assert!(!config.is_compact());
+        assert_eq!(config.label_attach(), LabelAttach::Middle);
+        assert!(!config.char_set().is_ascii());
+    }
+
+    #[test]
+    fn test_config_env() {
+        let env = |vars: &[(&str, &str)]| {
+            Config::new().with_env_vars(|key| {
+                let v = vars.iter().find(|(k, _)| *k == key)?;
+                Some(v.1.to_string())
+            })
+        };
+        let config = env(&[
+            ("MUSUBI_THEME", "Rustc"),
+            ("MUSUBI_COLOR", "never"),
+            ("MUSUBI_COMPACT", "on"),
+            ("MUSUBI_TAB_WIDTH", " 8 "),
+            ("MUSUBI_CONTEXT_LINES", "-1"),
+            ("MUSUBI_WIDTH", "wide"),
+        ]);
+        assert!(config.char_set().is_ascii() && config.is_compact());
+        assert!(!config.is_color_enabled());
+        assert_eq!(config.label_attach(), LabelAttach::Start);
+        assert_eq!(config.tab_width(), 8);
+        assert_eq!(config.context_lines(), 0);
+        assert_eq!(config.limit_width(), 0);
+
+        let config = env(&[("MUSUBI_THEME", "gcc"), ("MUSUBI_CHARSET", "UNICODE")]);
+        assert!(!config.char_set().is_ascii() && config.is_compact());
+        assert!(env(&[("MUSUBI_COLOR", "always")]).is_color_enabled());
+        assert!(!env(&[("MUSUBI_COMPACT", "yes please")]).is_compact());
+        for width in ["0", "81", "100", "65535"] {
+            let config = env(&[("MUSUBI_TAB_WIDTH", width)]);
+            assert_eq!(config.tab_width(), 4);
+            let output = Report::new()
+                .with_config(config)
+                .with_label(1..2)
+                .render_to_string("\tabc");
+            assert!(output.is_ok());
+        }
+        assert_eq!(env(&[("MUSUBI_TAB_WIDTH", "80")]).tab_width(), 80);
+
+        let output = Report::new()
+            .with_config(env(&[
+                ("MUSUBI_CHARSET", "ascii"),
+                ("MUSUBI_COLOR", "Never"),
+                ("MUSUBI_TAB_WIDTH", "2"),
+            ]))
+            .with_title(Level::Error, "unknown name")
+            .with_label(5..6)
+            .with_message("not found")
+            .render_to_string("\tlet\tx;")
+            .unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: unknown name
+           ,-[ <unknown>:1:6 ]
+           |
+         1 |   let x;
+           |       |
+           |       `-- not found
+        ---'
+        ");
+    }
+
+    #[test]
+    fn test_source_order() {
+        let cache = Cache::new()
+            .with_source(("fn b() {}", "b.rs"))
+            .with_source(("fn c() {}", "c.rs"))
+            .with_source(("fn a() {}", "a.rs"));
+        let render = |order| {
+            let config = Config::new().with_char_set_ascii().with_color_disabled();
+            Report::new()
+                .with_config(config.with_source_order(order))
+                .with_title(Level::Error, "cyclic calls")
+                .with_label((3..4, 2))
+                .with_primary_label((3..4, 1))
+                .with_message("called here")
+                .with_label((3..4, 0))
+                .render_to_string(&cache)
+                .unwrap()
+        };
+        let output = render(SourceOrder::PrimaryFirst);
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: cyclic calls
+           ,-[ c.rs:1:4 ]
+           |
+         1 | fn c() {}
+           |    |
+           |    `-- called here
+           |
+           |-[ b.rs:1:4 ]
+           |
+         1 | fn b() {}
+           |    ^
+           |
+           |-[ a.rs:1:4 ]
+           |
+         1 | fn a() {}
+           |    ^
+        ---'
+        ");
+        let names = |output: String| {
+            (output.lines())
+                .filter_map(|line| line.split_once("[ ")?.1.split_once(':'))
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(render(SourceOrder::Cache)), ["b.rs", "c.rs", "a.rs"]);
+        assert_eq!(names(render(SourceOrder::Labels)), ["a.rs", "c.rs", "b.rs"]);
+        assert_eq!(names(render(SourceOrder::Name)), ["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(names(output), ["c.rs", "b.rs", "a.rs"]);
+    }
+
+    #[test]
+    fn test_collapse_notes() {
+        let report = |lines: i32| {
+            Report::new()
+                .with_config(
+                    Config::new()
+                        .with_char_set_ascii()
+                        .with_color_disabled()
+                        .with_collapse_notes(lines),
+                )
+                .with_title(Level::Error, "panicked")
+                .with_label(0..3)
+                .with_help("one line")
+                .with_help("run with\nRUST_BACKTRACE=1")
+                .with_note("backtrace:\n  0: main\n  1: start")
+        };
+        let output = report(2).render_to_string("abc").unwrap();
+        assert_snapshot!(remove_trailing_whitespace(&output), @r"
+        Error: panicked
+           ,-[ <unknown>:1:1 ]
+           |
+         1 | abc
+           | ^^^
+           |
+           | Help 1: one line
+           |
+           | Help 2: run with
+           |         RUST_BACKTRACE=1
+           |
+           | Note: backtrace:
+           |         0: main
+           |       ... 1 more line
+        ---'
+        ");
+        assert_eq!(
+            report(0).render_to_string("abc").unwrap(),
+            report(3).render_to_string("abc").unwrap()
+        );
+
+        let mut report = report(0);
+        let html = report.render_to_html("abc").unwrap();
+        assert!(html.contains(
+            "<details class=\"mu-collapsed\"><summary><span class=\"mu-skipped-margin\" style=\"color:#585858\">   |</span> <span class=\"mu-note\" style=\"color:#87d7af\">Help 2: run with</span></summary>"
+        ));
+        assert!(html.contains("  0: main</span>\n"));
+        assert_eq!(html.matches("<details").count(), 2);
+        assert_eq!(html.matches("</details>").count(), 2);
+        assert!(!html.contains("more line"));
+        let mut report = report.with_config(Config::new().with_collapse_notes(2));
+        let html = report.render_to_html("abc").unwrap();
+        assert_eq!(html.matches("<details").count(), 1);
+        assert_eq!(report.config.as_ref().unwrap().collapse_notes(), 2);
+    }
+
+    #[test]
+    fn test_line_no_format() {
+        let src = "a\nb\nc\n";
+        let render = |config: Config| {
+            let cache = Cache::new().with_source((src, "big.rs", 999_998));
+            let output = Report::new()
+                .with_config(config.with_char_set_ascii().with_color_disabled())
+                .with_title(Level::Error, "Error")
+                .with_label(0..1)
+                .with_message("first")
+                .with_label(4..5)
+                .with_message("third")
+                .render_to_string(&cache)
+                .unwrap();
+            remove_trailing_whitespace(&output)
+        };
+        let config = Config::new().with_line_no_separator("\u{2009}");
+        assert_eq!(config.line_no_separator(), "\u{2009}");
+        assert_snapshot!(render(config), @r"
+        Error: Error
+                   ,-[ big.rs:999999:1 ]
+                   |
+           999 999 | a
+                   | |
+                   | `-- first
+                   |
+         1 000 001 | c
+                   | |
+                   | `-- third
+        -----------'
+        ");
+
+        let roman = |line: usize| ["", "I", "II", "III"][line - 999_998].to_string();
+        assert_snapshot!(render(Config::new().with_line_no_format(&roman)), @r"
+        Error: Error
+             ,-[ big.rs:999999:1 ]
+             |
+           I | a
+             | |
+             | `-- first
+             |
+         III | c
+             | |
+             | `-- third
+        -----'
+        ");
+    }
+
+    #[test]
+    fn test_char_set_probe() {
+        let render = |config: Config| {
+            Report::new()
+                .with_config(config.with_color_disabled())
+                .with_title(Level::Error, "Error")
+                .with_label(4..5)
+                .with_message("here")
+                .render_to_string("let x = 42;")
+                .unwrap()
+        };
+        let ascii = render(Config::new().with_char_set_ascii());
+        let unicode = render(Config::new().with_char_set_unicode());
+        assert_eq!(render(Config::new().with_char_set_probe(|_| false)), ascii);
+        assert_eq!(render(Config::new().with_char_set_probe(|_| true)), unicode);
+        // ASCII sets are not probed
+        let probe = |_: &CharSet| panic!("probed");
+        assert_eq!(
+            render(
+                Config::new()
+                    .with_char_set_ascii()
+                    .with_char_set_probe(probe)
+            ),
+            ascii
+        );
+
+        // the configured set is restored after rendering
+        let custom = CharSet {
+            hbar: '=',
+            ..CharSet::unicode()
+        };
+        let mut report = Report::new()
+            .with_config(
+                (Config::new().with_color_disabled())
+                    .with_char_set(&custom)
+                    .with_char_set_probe(|cs| cs.hbar != '='),
+            )
+            .with_title(Level::Error, "Error")
+            .with_label(4..5);
+        assert!(report.render_to_string("let x = 42;").unwrap().is_ascii());
+        let config = report.config.as_ref().unwrap();
+        assert_eq!(CharSet::from(config.inner.char_set).hbar, '=');
+        assert!(CharSet::ascii().is_displayable());
+    }
+
     #[test]
     fn test_with_priority() {
         let mut report = Report::new()
@@ -3135,6 +11814,106 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "termcolor")]
+    #[test]
+    fn test_render_to_write_color() {
+        let mut cg = ColorGenerator::new();
+        let color = cg.next_color();
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "Test")
+            .with_label(0..4)
+            .with_message("test")
+            .with_color(&color);
+
+        let mut plain = termcolor::Buffer::no_color();
+        report.render_to_write_color(&mut plain, "code").unwrap();
+        assert_snapshot!(
+            remove_trailing_whitespace(&String::from_utf8_lossy(plain.as_slice())),
+            @r##"
+        Error: Test
+           ,-[ <unknown>:1:1 ]
+           |
+         1 | code
+           | ^^|^
+           |   `--- test
+        ---'
+        "##
+        );
+
+        let mut ansi = termcolor::Buffer::ansi();
+        report.render_to_write_color(&mut ansi, "code").unwrap();
+        let output = String::from_utf8_lossy(ansi.as_slice()).replace('\x1b', "~");
+        assert_snapshot!(
+            remove_trailing_whitespace(&output),
+            @r##"
+        ~[0m~[31mError:~[0m Test
+        ~[0m~[38;5;246m   ,-[~[0m <unknown>:1:1 ~[0m~[38;5;246m]~[0m
+        ~[0m~[38;5;246m   |~[0m
+        ~[0m~[38;5;246m 1 |~[0m ~[0m~[38;5;201mcode~[0m
+        ~[0m~[38;5;240m   |~[0m ~[0m~[38;5;201m^^|^~[0m
+        ~[0m~[38;5;240m   |~[0m   ~[0m~[38;5;201m`---~[0m test
+        ~[0m~[38;5;246m---'~[0m
+        "##
+        );
+
+        let cs = parse_sgr(b"\x1b[1;38;2;1;2;3;44m").unwrap();
+        assert!(cs.bold());
+        assert_eq!(cs.fg(), Some(&termcolor::Color::Rgb(1, 2, 3)));
+        assert_eq!(cs.bg(), Some(&termcolor::Color::Blue));
+        assert!(parse_sgr(b"\x1b[0m").unwrap().is_none());
+        assert!(parse_sgr(b"code").is_none());
+
+        // escape codes in the source are text, and the config is untouched
+        let mut report = Report::new()
+            .with_title(Level::Error, "Test")
+            .with_label(0..10);
+        let mut ansi = termcolor::Buffer::ansi();
+        report
+            .render_to_write_color(&mut ansi, "\x1b[3;4mcode")
+            .unwrap();
+        assert!(report.config.is_none());
+        let output = String::from_utf8_lossy(ansi.as_slice()).replace('\x1b', "~");
+        assert!(output.contains("~[3;4m"), "{output}");
+        let plain = report.render_to_string("\x1b[3;4mcode").unwrap();
+        assert!(plain.contains("\x1b[3;4m"));
+    }
+
+    #[test]
+    fn test_render_lines() {
+        let mut report = Report::new()
+            .with_config(Config::new().with_char_set_ascii().with_color_disabled())
+            .with_title(Level::Error, "Test")
+            .with_label(0..9)
+            .with_message("multi")
+            .with_help("help\nmore");
+
+        let mut output = String::new();
+        let result = report.render_lines(("a\nb\nc\nd\ne", "test.rs"), |line, kind| {
+            output.push_str(&format!("{:<9} {}\n", format!("{kind:?}"), line.trim_end()));
+        });
+        assert!(result.is_ok());
+        assert_snapshot!(
+            output,
+            @r##"
+        Header    Error: Test
+        Reference    ,-[ test.rs:1:1 ]
+        Empty        |
+        Source     1 | ,-> a
+        Ellipsis     : :
+        Source     5 | |-> e
+        Underline    | |
+        Arrow        | `------ multi
+        Empty        |
+        Help         | Help: help
+        Help         |       more
+        Footer    ---'
+        "##
+        );
+        // the report doesn't keep the writer state of the render
+        assert!(report.writer.0.is_none() && report.writer.1.is_null());
+    }
+
     #[test]
     fn test_reset() {
         let report = Report::new()
@@ -3165,6 +11944,98 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_version() {
+        let v = version();
+        assert_eq!(
+            v,
+            Version::new(
+                ffi::MU_VERSION_MAJOR,
+                ffi::MU_VERSION_MINOR,
+                ffi::MU_VERSION_PATCH
+            )
+        );
+        assert_eq!(v.to_string(), "0.5.0");
+        assert_eq!(check_version(), Ok(()));
+        // SAFETY: mu_structsize has no preconditions
+        let config_size = unsafe { ffi::mu_structsize(ffi::MU_STRUCT_CONFIG) };
+        assert_eq!(config_size, size_of::<ffi::mu_Config>());
+        // SAFETY: see above
+        assert_eq!(unsafe { ffi::mu_structsize(99) }, 0);
+        let mismatch = VersionMismatch {
+            header: v,
+            library: v,
+            layout: true,
+        };
+        assert_eq!(
+            mismatch.to_string(),
+            "musubi library 0.5.0 is incompatible with header 0.5.0 (struct layouts differ)"
+        );
+
+        let caps = capabilities();
+        assert_eq!(caps.stdio, cfg!(not(feature = "rust-backend")));
+        assert_eq!(caps.max_color_size, 63);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rust-backend"))]
+    fn test_render_out_of_memory() {
+        use std::alloc::{Layout, alloc, dealloc, realloc};
+        use std::cell::Cell;
+
+        unsafe extern "C" fn failing_alloc(
+            ud: *mut c_void,
+            p: *mut c_void,
+            nsize: usize,
+            osize: usize,
+        ) -> *mut c_void {
+            // SAFETY: ud points to the Cell<bool> owned by the test body
+            let fail = unsafe { &*(ud as *const Cell<bool>) };
+            let align = std::mem::align_of::<usize>() * 2;
+            // SAFETY: p was allocated by this function with (osize, align)
+            unsafe {
+                if nsize == 0 {
+                    dealloc(
+                        p as *mut u8,
+                        Layout::from_size_align_unchecked(osize, align),
+                    );
+                    ptr::null_mut()
+                } else if fail.get() {
+                    ptr::null_mut()
+                } else if p.is_null() {
+                    alloc(Layout::from_size_align_unchecked(nsize, align)) as *mut c_void
+                } else {
+                    let layout = Layout::from_size_align_unchecked(osize, align);
+                    realloc(p as *mut u8, layout, nsize) as *mut c_void
+                }
+            }
+        }
+
+        unsafe extern "C" fn null_writer(_: *mut c_void, _: *const c_char, _: usize) -> c_int {
+            ffi::MU_OK
+        }
+
+        let fail = Cell::new(false);
+        let cache = Cache::new().with_source(("let x = 1;", "oom.rs"));
+        // SAFETY: fail outlives the report, which is deleted below
+        unsafe {
+            let r = ffi::mu_new(Some(failing_alloc), &fail as *const _ as *mut c_void);
+            assert!(!r.is_null());
+            assert_eq!(ffi::mu_label(r, 0, 3, 0.into()), ffi::MU_OK);
+            ffi::mu_writer(r, Some(null_writer), ptr::null_mut());
+
+            fail.set(true);
+            assert_eq!(ffi::mu_render(r, cache.as_raw()), ffi::MU_ERRMEM);
+
+            fail.set(false);
+            assert_eq!(ffi::mu_render(r, cache.as_raw()), ffi::MU_OK);
+            ffi::mu_delete(r);
+        }
+
+        let err = io::Error::new(io::ErrorKind::OutOfMemory, AllocError);
+        assert_eq!(err.to_string(), "memory allocation failed");
+    }
+
     #[test]
     fn test_char_set_conversion() {
         let ascii = CharSet::ascii();
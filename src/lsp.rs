@@ -0,0 +1,281 @@
+//! Conversion to and from Language Server Protocol diagnostics.
+
+use std::borrow::Cow;
+
+use lsp_types::{
+    DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Position, Range,
+    Uri,
+};
+
+use crate::{
+    Cache, Diagnostic, DiagnosticLabel, IndexType, LabelSpan, Level, Report, char_to_byte, ffi,
+    source_line, source_name,
+};
+
+/// Walk `data` as UTF-8 until `units` UTF-16 code units are consumed.
+///
+/// Returns the bytes, characters and code units consumed. Invalid bytes
+/// count as one character and one code unit each.
+fn walk_utf16(data: &[u8], units: usize) -> (usize, usize, usize) {
+    let (mut bytes, mut chars, mut used) = (0, 0, 0);
+    for chunk in data.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if used + c.len_utf16() > units {
+                return (bytes, chars, used);
+            }
+            (bytes, chars, used) = (bytes + c.len_utf8(), chars + 1, used + c.len_utf16());
+        }
+        for _ in chunk.invalid() {
+            if used >= units {
+                return (bytes, chars, used);
+            }
+            (bytes, chars, used) = (bytes + 1, chars + 1, used + 1);
+        }
+    }
+    (bytes, chars, used)
+}
+
+impl<K> Cache<K> {
+    /// The LSP position of `pos` in source `src_id`.
+    ///
+    /// `pos` is interpreted according to `index_type`. The position has a
+    /// 0-based line, ignoring the line offset of the source, and counts
+    /// UTF-16 code units, the default position encoding of LSP. Positions
+    /// past the end of a line count the newline as one unit per byte.
+    ///
+    /// Returns [`None`] if `src_id` is out of range or the source fails to
+    /// load.
+    ///
+    /// Requires the `lsp` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, IndexType};
+    /// # use lsp_types::Position;
+    /// let cache = Cache::new().with_source("let a = 1;\nlet 😀 = 2;");
+    /// // the emoji is 4 bytes, but 2 UTF-16 code units
+    /// assert_eq!(cache.lsp_position(0, 20, IndexType::Byte), Some(Position::new(1, 7)));
+    /// assert_eq!(cache.lsp_offset(0, Position::new(1, 7), IndexType::Byte), Some(20));
+    /// ```
+    pub fn lsp_position(
+        &self,
+        src_id: impl Into<ffi::mu_Id>,
+        pos: usize,
+        index_type: IndexType,
+    ) -> Option<Position> {
+        let src = self.source(src_id.into())?;
+        // SAFETY: src was loaded above
+        let line_for = unsafe {
+            match index_type {
+                IndexType::Byte => (*src).line_for_bytes,
+                IndexType::Char => (*src).line_for_chars,
+            }
+        };
+        // SAFETY: src was loaded above, and line_for is one of its functions
+        let (line_no, line, data) = unsafe { source_line(src, line_for, pos)? };
+        let pos = match index_type {
+            IndexType::Byte => pos,
+            IndexType::Char => char_to_byte(src, pos),
+        };
+        let rel = pos.saturating_sub(line.byte_offset);
+        let character = match data.get(..rel) {
+            Some(head) => walk_utf16(head, usize::MAX).2,
+            None => walk_utf16(data, usize::MAX).2 + (rel - data.len()),
+        };
+        Some(Position::new(line_no, character as u32))
+    }
+
+    /// The position in source `src_id` of the LSP `position`.
+    ///
+    /// The inverse of [`lsp_position`](Cache::lsp_position): the result is
+    /// interpreted according to `index_type`. Characters past the end of
+    /// the line are clamped to the line end, as LSP requires.
+    ///
+    /// Returns [`None`] if `src_id` is out of range, the source fails to
+    /// load or `position` is past its last line.
+    ///
+    /// Requires the `lsp` feature.
+    pub fn lsp_offset(
+        &self,
+        src_id: impl Into<ffi::mu_Id>,
+        position: Position,
+        index_type: IndexType,
+    ) -> Option<usize> {
+        let src = self.source(src_id.into())?;
+        // SAFETY: src was loaded above, so its functions are set
+        unsafe {
+            if position.line >= (*src).line_count?(src) {
+                return None;
+            }
+            let line = *(*src).get_line_info?(src, position.line).as_ref()?;
+            let data: &[u8] = (*src).get_line?(src, position.line).into();
+            let (bytes, chars, _) = walk_utf16(data, position.character as usize);
+            Some(match index_type {
+                IndexType::Byte => line.byte_offset + bytes,
+                IndexType::Char => line.offset + chars,
+            })
+        }
+    }
+
+    /// The LSP range of `span`, see [`lsp_position`](Cache::lsp_position).
+    ///
+    /// Requires the `lsp` feature.
+    pub fn lsp_range(&self, span: impl Into<LabelSpan>, index_type: IndexType) -> Option<Range> {
+        let span = span.into();
+        Some(Range::new(
+            self.lsp_position(span.src_id, span.start, index_type)?,
+            self.lsp_position(span.src_id, span.end, index_type)?,
+        ))
+    }
+}
+
+impl<'a> Diagnostic<'a> {
+    /// Convert to an LSP diagnostic.
+    ///
+    /// The range of the LSP diagnostic is the primary label, or the first
+    /// label if none is primary; `uri` is the document of its source. The
+    /// title becomes the message, followed by a `help:` or `note:` line per
+    /// help and note. Labels with a message, including the main one, become
+    /// related information; labels in other sources are only kept if their
+    /// source name is a valid URI. Spans are interpreted according to
+    /// `index_type`.
+    ///
+    /// Errors map to the `ERROR` severity, warnings to `WARNING`, infos and
+    /// notes to `INFORMATION`, hints and advices to `HINT`. Custom levels
+    /// have no severity.
+    ///
+    /// Requires the `lsp` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Diagnostic, DiagnosticLabel, IndexType, Level};
+    /// # use lsp_types::{DiagnosticSeverity, Position, Range, Uri};
+    /// let cache = Cache::new().with_source(("let a = b;", "main.rs"));
+    /// let diag = Diagnostic::new(Level::Error, "unknown variable")
+    ///     .with_label(DiagnosticLabel::new(8..9))
+    ///     .with_help("declare it first");
+    ///
+    /// let uri: Uri = "file:///main.rs".parse().unwrap();
+    /// let lsp = diag.to_lsp(&cache, IndexType::Char, &uri);
+    /// assert_eq!(lsp.range, Range::new(Position::new(0, 8), Position::new(0, 9)));
+    /// assert_eq!(lsp.severity, Some(DiagnosticSeverity::ERROR));
+    /// assert_eq!(lsp.message, "unknown variable\nhelp: declare it first");
+    /// ```
+    pub fn to_lsp<K>(
+        &self,
+        cache: &Cache<K>,
+        index_type: IndexType,
+        uri: &Uri,
+    ) -> lsp_types::Diagnostic {
+        let labels = self.labels();
+        let main = labels.iter().find(|l| l.is_primary()).or(labels.first());
+        let main_src = main.map(|l| l.span().src_id);
+        let range = |span: LabelSpan| cache.lsp_range(span, index_type).unwrap_or_default();
+        let related: Vec<_> = (labels.iter())
+            .filter_map(|label| {
+                let span = label.span();
+                let uri = match Some(span.src_id) == main_src {
+                    true => uri.clone(),
+                    false => source_name(cache.inner, span.src_id)?.parse().ok()?,
+                };
+                Some(DiagnosticRelatedInformation {
+                    location: Location::new(uri, range(span)),
+                    message: label.message()?.into(),
+                })
+            })
+            .collect();
+        let mut message = self.title().to_owned();
+        for help in self.helps() {
+            message += &format!("\nhelp: {help}");
+        }
+        for note in self.notes() {
+            message += &format!("\nnote: {note}");
+        }
+        lsp_types::Diagnostic {
+            range: main.map(|l| range(l.span())).unwrap_or_default(),
+            severity: self.level().map(|level| match level {
+                Level::Error => DiagnosticSeverity::ERROR,
+                Level::Warning => DiagnosticSeverity::WARNING,
+                Level::Info | Level::Note => DiagnosticSeverity::INFORMATION,
+                Level::Hint | Level::Advice => DiagnosticSeverity::HINT,
+            }),
+            code: self.code().map(|code| NumberOrString::String(code.into())),
+            message,
+            related_information: (!related.is_empty()).then_some(related),
+            ..Default::default()
+        }
+    }
+
+    /// Convert from an LSP diagnostic on source `src_id`.
+    ///
+    /// The range becomes a primary label, unless it can't be resolved in
+    /// `cache`, and the message the title. The messages of the related
+    /// information become notes, as their documents can't be mapped to
+    /// sources. Positions are resolved according to `index_type`. A missing
+    /// severity is read as an error.
+    ///
+    /// Requires the `lsp` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Diagnostic, IndexType, LabelSpan, Level};
+    /// # use lsp_types::{Position, Range};
+    /// let cache = Cache::new().with_source(("let a = b;", "main.rs"));
+    /// let lsp = lsp_types::Diagnostic {
+    ///     range: Range::new(Position::new(0, 8), Position::new(0, 9)),
+    ///     message: "unknown variable".into(),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let diag = Diagnostic::from_lsp(&lsp, &cache, 0, IndexType::Char);
+    /// assert_eq!(diag.level(), Some(Level::Error));
+    /// assert_eq!(diag.labels()[0].span(), LabelSpan::from(8..9));
+    /// ```
+    pub fn from_lsp<K>(
+        diag: &'a lsp_types::Diagnostic,
+        cache: &Cache<K>,
+        src_id: impl Into<ffi::mu_Id>,
+        index_type: IndexType,
+    ) -> Self {
+        let src_id = src_id.into();
+        let level = match diag.severity {
+            Some(DiagnosticSeverity::WARNING) => Level::Warning,
+            Some(DiagnosticSeverity::INFORMATION) => Level::Info,
+            Some(DiagnosticSeverity::HINT) => Level::Hint,
+            _ => Level::Error,
+        };
+        let mut result = Diagnostic::new(level, diag.message.as_str());
+        if let Some(code) = &diag.code {
+            result = result.with_code(match code {
+                NumberOrString::Number(n) => Cow::Owned(n.to_string()),
+                NumberOrString::String(s) => Cow::Borrowed(s.as_str()),
+            });
+        }
+        let start = cache.lsp_offset(src_id, diag.range.start, index_type);
+        let end = cache.lsp_offset(src_id, diag.range.end, index_type);
+        if let (Some(start), Some(end)) = (start, end) {
+            let label = DiagnosticLabel::new((start..end, src_id)).with_primary(true);
+            result = result.with_label(label);
+        }
+        for info in diag.related_information.iter().flatten() {
+            result = result.with_note(info.message.as_str());
+        }
+        result
+    }
+}
+
+impl Report<'_> {
+    /// Convert the report to an LSP diagnostic.
+    ///
+    /// Spans are interpreted according to the configured [`IndexType`].
+    /// See [`Diagnostic::to_lsp`] for details.
+    ///
+    /// Requires the `lsp` feature.
+    pub fn to_lsp<K>(&self, cache: &Cache<K>, uri: &Uri) -> lsp_types::Diagnostic {
+        let index_type = self
+            .config
+            .as_ref()
+            .map_or(IndexType::Char, |c| c.index_type());
+        (self.diagnostic_in(cache.inner)).to_lsp(cache, index_type, uri)
+    }
+}
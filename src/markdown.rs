@@ -0,0 +1,200 @@
+//! Markdown output, for GitHub issues and pull request comments.
+
+use std::borrow::Cow;
+use std::io;
+
+use crate::{
+    Config, IndexType, LabelSpan, LineKind, RawCache, Report, char_to_byte, ffi, load_source,
+    source_line,
+};
+
+/// Escape the characters of `s` that would start Markdown inline markup.
+///
+/// Backticks are kept and `code` spans left as they are, so code in
+/// messages stays code.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_code = false;
+    for c in s.chars() {
+        match c {
+            '`' => in_code = !in_code,
+            '\\' | '*' | '_' | '[' | ']' | '<' | '>' if !in_code => out.push('\\'),
+            _ => {}
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Remove the escape codes of per-label colors, which are written even
+/// when colors are disabled.
+pub(crate) fn strip_escapes(line: &str) -> Cow<'_, str> {
+    if !line.contains('\x1b') {
+        return Cow::Borrowed(line);
+    }
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(pos) = rest.find('\x1b') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        rest = match rest.find('m') {
+            Some(end) => &rest[end + 1..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// A code fence longer than any backtick run in `text`.
+fn fence(text: &str) -> String {
+    let longest = (text.split(|c| c != '`')).map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// The whole lines covering `span`, with `span` replaced by `replacement`.
+fn fixed_lines(
+    cache: *mut ffi::mu_Cache,
+    span: LabelSpan,
+    replacement: &str,
+    index_type: IndexType,
+) -> Option<String> {
+    let src = load_source(cache, span.src_id)?;
+    let (start, end) = match index_type {
+        IndexType::Byte => (span.start, span.end.max(span.start)),
+        IndexType::Char => (
+            char_to_byte(src, span.start),
+            char_to_byte(src, span.end.max(span.start)),
+        ),
+    };
+    // SAFETY: src was loaded above; the line data is copied before the next
+    // line is fetched
+    let (first, head) = unsafe {
+        let (_, line, data) = source_line(src, (*src).line_for_bytes, start)?;
+        (line, data.to_vec())
+    };
+    // SAFETY: src was loaded above
+    let (last, tail) = unsafe {
+        let (_, line, data) = source_line(src, (*src).line_for_bytes, end)?;
+        (line, data)
+    };
+    let head = &head[..(start - first.byte_offset).min(head.len())];
+    let tail = &tail[(end - last.byte_offset).min(tail.len())..];
+    Some(format!(
+        "{}{replacement}{}",
+        String::from_utf8_lossy(head),
+        String::from_utf8_lossy(tail)
+    ))
+}
+
+impl Report<'_> {
+    /// Render the report as GitHub-flavored Markdown.
+    ///
+    /// The header becomes a bold line, the labeled source a fenced code
+    /// block rendered without colors, and every help and note a bold
+    /// paragraph after it, ready to paste into an issue or a pull request
+    /// comment.
+    ///
+    /// See [`Report::render_to_string`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_title(Level::Error, "unknown variable")
+    ///     .with_code("E01")
+    ///     .with_label(8..9)
+    ///     .with_message("not found in this scope")
+    ///     .with_help("declare it first");
+    ///
+    /// let md = report.render_to_markdown(("let a = b;", "main.rs"))?;
+    /// assert!(md.starts_with("**[E01] Error:** unknown variable\n\n```text\n"));
+    /// assert!(md.ends_with("```\n\n**Help:** declare it first\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_markdown(&mut self, cache: impl Into<RawCache>) -> io::Result<String> {
+        self.markdown(self.resolve_cache(cache), None)
+    }
+
+    /// Render the report as GitHub-flavored Markdown, with a fix.
+    ///
+    /// Like [`render_to_markdown`](Report::render_to_markdown), followed by
+    /// a GitHub `suggestion` block holding the lines of `span` with `span`
+    /// replaced by `replacement`. Posted as a review comment on those lines,
+    /// the block lets the fix be applied with one click. `span` is
+    /// interpreted according to the configured [`IndexType`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_title(Level::Warning, "unused variable")
+    ///     .with_label(4..5)
+    ///     .with_message("never read");
+    ///
+    /// let md = report.render_to_markdown_with_fix("let a = 1;", 4..5, "_a")?;
+    /// assert!(md.ends_with("```suggestion\nlet _a = 1;\n```\n"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_markdown_with_fix<L: Into<LabelSpan>>(
+        &mut self,
+        cache: impl Into<RawCache>,
+        span: L,
+        replacement: &str,
+    ) -> io::Result<String> {
+        self.markdown(self.resolve_cache(cache), Some((span.into(), replacement)))
+    }
+
+    fn markdown(&mut self, cache: RawCache, fix: Option<(LabelSpan, &str)>) -> io::Result<String> {
+        let index_type = (self.config.as_ref()).map_or(IndexType::Char, Config::index_type);
+        let fix = fix.and_then(|(span, replacement)| {
+            fixed_lines(cache.as_ptr(), span, replacement, index_type)
+        });
+
+        let mut lines: Vec<(LineKind, String)> = Vec::new();
+        let cfg = self.config.get_or_insert_with(Config::default);
+        let saved = cfg.inner.color;
+        cfg.inner.color = None;
+        let result = self.render_lines(cache, |line, kind| match kind {
+            LineKind::Header => {}
+            LineKind::Help | LineKind::Note => {
+                // drop the separators before the footer messages
+                while lines.last().is_some_and(|(k, _)| *k == LineKind::Empty) {
+                    lines.pop();
+                }
+            }
+            _ => lines.push((kind, strip_escapes(line).into_owned())),
+        });
+        if let Some(cfg) = self.config.as_mut() {
+            cfg.inner.color = saved;
+        }
+        result?;
+
+        let diag = &self.diagnostic;
+        let mut out = match diag.code() {
+            Some(code) => format!("**[{}] {}:**", escape(code), diag.level_name()),
+            None => format!("**{}:**", diag.level_name()),
+        };
+        out += &format!(" {}\n", escape(diag.title()));
+        if !lines.is_empty() {
+            let block: Vec<&str> = lines.iter().map(|(_, line)| line.as_str()).collect();
+            let block = block.join("\n");
+            let fence = fence(&block);
+            out += &format!("\n{fence}text\n{block}\n{fence}\n");
+        }
+        for (name, msgs) in [("Help", diag.helps()), ("Note", diag.notes())] {
+            for (i, msg) in msgs.iter().enumerate() {
+                let msg = escape(msg);
+                match msgs.len() {
+                    1 => out += &format!("\n**{name}:** {msg}\n"),
+                    _ => out += &format!("\n**{name} {}:** {msg}\n", i + 1),
+                }
+            }
+        }
+        if let Some(fix) = fix {
+            let fence = fence(&fix);
+            out += &format!("\n{fence}suggestion\n{fix}\n{fence}\n");
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,65 @@
+//! Severity policies shared between tools.
+
+/// A policy deciding which reports an [`Emitter`](crate::Emitter) emits and
+/// at which level.
+///
+/// Reports are matched by their [code](crate::Report::with_code). With the
+/// `serde` feature, policies can be loaded from configuration files shared
+/// between tools; missing fields take their default value.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Config, Emitter, Level, Policy, Report};
+/// let policy = Policy {
+///     deny: vec!["W01".into()],
+///     allow: vec!["W02".into()],
+///     ..Default::default()
+/// };
+/// let mut emitter = Emitter::new()
+///     .with_config(Config::new().with_color_disabled())
+///     .with_policy(policy)
+///     .with_report(
+///         Report::new()
+///             .with_title(Level::Warning, "denied")
+///             .with_code("W01")
+///             .with_label(0..3),
+///     )
+///     .with_report(
+///         Report::new()
+///             .with_title(Level::Warning, "allowed")
+///             .with_code("W02")
+///             .with_label(0..3),
+///     );
+///
+/// assert_eq!(emitter.error_count(), 1);
+/// let output = emitter.render_to_string("let a = 1;")?;
+/// assert!(output.contains("Error: denied"));
+/// assert!(!output.contains("allowed"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Policy {
+    /// Codes of reports to emit as errors.
+    pub deny: Vec<String>,
+    /// Codes of reports to drop.
+    pub allow: Vec<String>,
+    /// Emit warnings as errors.
+    pub warn_as_error: bool,
+    /// Maximum number of errors to emit; further errors are dropped.
+    pub max_errors: Option<usize>,
+}
+
+impl Policy {
+    /// Whether reports with `code` are dropped.
+    pub(crate) fn is_allowed(&self, code: Option<&str>) -> bool {
+        code.is_some_and(|code| self.allow.iter().any(|c| c == code))
+    }
+
+    /// Whether reports with `code` and `warning` level are emitted as errors.
+    pub(crate) fn is_denied(&self, code: Option<&str>, warning: bool) -> bool {
+        (warning && self.warn_as_error)
+            || code.is_some_and(|code| self.deny.iter().any(|c| c == code))
+    }
+}
@@ -0,0 +1,175 @@
+//! SARIF 2.1.0 export for code scanning services.
+
+use crate::ci::json_string;
+use crate::{Config, IndexType, Level, RawCache, Report, source_location, source_name};
+
+/// URI of the SARIF 2.1.0 JSON schema.
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Collects reports into a SARIF 2.1.0 run.
+///
+/// Each added report becomes a `result`: its code is the `ruleId`, its
+/// title the message, and its labels the `locations`, in the order they
+/// were added. Notes become `relatedLocations` carrying only a message, and
+/// helps are kept in the `helps` property of the result. Errors map to the
+/// `error` level, warnings to `warning` and other levels to `note`.
+///
+/// Columns count Unicode code points (`columnKind` is `unicodeCodePoints`),
+/// and the `uri` of a location is the source name, so sources should be
+/// named after their paths relative to the repository root. Labels whose
+/// source is not in the cache only keep their message.
+///
+/// Requires the `sarif` feature.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Cache, Level, Report, SarifWriter};
+/// let cache = Cache::new().with_source(("let a = b;", "src/main.rs"));
+/// let report = Report::new()
+///     .with_title(Level::Error, "unknown variable")
+///     .with_code("E01")
+///     .with_label(8..9)
+///     .with_message("not found in this scope");
+///
+/// let mut sarif = SarifWriter::new("my-linter").with_version("1.0.0");
+/// sarif.add_report(&report, &cache);
+///
+/// let log = sarif.to_sarif();
+/// assert!(log.contains(r#""version":"2.1.0""#));
+/// assert!(log.contains(r#""ruleId":"E01","level":"error""#));
+/// assert!(log.contains(r#""artifactLocation":{"uri":"src/main.rs"}"#));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SarifWriter {
+    name: String,
+    version: Option<String>,
+    rules: Vec<String>,
+    results: Vec<String>,
+}
+
+impl SarifWriter {
+    /// Create an empty run of the tool `name`.
+    #[inline]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            version: None,
+            rules: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+
+    /// Set the version of the tool.
+    #[inline]
+    #[must_use]
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Add `report` as a result, resolving its labels in `cache`.
+    ///
+    /// See [`Report::render_to_string`] for the accepted caches.
+    pub fn add_report(&mut self, report: &Report<'_>, cache: impl Into<RawCache>) {
+        let cache = report.resolve_cache(cache);
+        let cache = cache.as_ptr();
+        let index_type = (report.config.as_ref()).map_or(IndexType::Char, Config::index_type);
+        let diag = report.diagnostic_in(cache);
+        let locations: Vec<String> = (diag.labels().iter())
+            .map(|label| {
+                let span = label.span();
+                let message = match label.message() {
+                    Some(msg) => format!(",\"message\":{{\"text\":{}}}", json_string(msg)),
+                    None => String::new(),
+                };
+                let Some(uri) = source_name(cache, span.src_id) else {
+                    return format!("{{{}}}", message.trim_start_matches(','));
+                };
+                let start = source_location(cache, span.src_id, span.start, index_type);
+                let end = source_location(cache, span.src_id, span.end, index_type);
+                let region = match (start, end) {
+                    (Some((start_line, start_col)), Some((end_line, end_col))) => format!(
+                        ",\"region\":{{\"startLine\":{start_line},\"startColumn\":{start_col},\
+                         \"endLine\":{end_line},\"endColumn\":{end_col}}}"
+                    ),
+                    _ => String::new(),
+                };
+                format!(
+                    "{{\"physicalLocation\":{{\"artifactLocation\":{{\"uri\":{}}}{region}}}{message}}}",
+                    json_string(&uri)
+                )
+            })
+            .collect();
+        let related: Vec<String> = (diag.notes().iter().enumerate())
+            .map(|(i, note)| {
+                format!(
+                    "{{\"id\":{i},\"message\":{{\"text\":{}}}}}",
+                    json_string(note)
+                )
+            })
+            .collect();
+        let helps: Vec<String> = diag.helps().iter().map(|h| json_string(h)).collect();
+        let level = match diag.level() {
+            Some(Level::Error) => "error",
+            Some(Level::Warning) => "warning",
+            _ => "note",
+        };
+        if let Some(code) = diag.code()
+            && !self.rules.iter().any(|r| r == code)
+        {
+            self.rules.push(code.into());
+        }
+        let rule = match diag.code() {
+            Some(code) => format!("\"ruleId\":{},", json_string(code)),
+            None => String::new(),
+        };
+        self.results.push(format!(
+            "{{{rule}\"level\":\"{level}\",\"message\":{{\"text\":{}}},\
+             \"locations\":[{}],\"relatedLocations\":[{}],\"properties\":{{\"helps\":[{}]}}}}",
+            json_string(diag.title()),
+            locations.join(","),
+            related.join(","),
+            helps.join(","),
+        ));
+    }
+
+    /// The number of results in the run.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Whether no report has been added.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Serialize the run as a SARIF `run` object.
+    ///
+    /// Every distinct report code is declared as a rule of the tool.
+    pub fn to_run(&self) -> String {
+        let rules: Vec<String> = (self.rules.iter())
+            .map(|id| format!("{{\"id\":{}}}", json_string(id)))
+            .collect();
+        let version = match &self.version {
+            Some(version) => format!(",\"version\":{}", json_string(version)),
+            None => String::new(),
+        };
+        format!(
+            "{{\"tool\":{{\"driver\":{{\"name\":{}{version},\"rules\":[{}]}}}},\
+             \"columnKind\":\"unicodeCodePoints\",\"results\":[{}]}}",
+            json_string(&self.name),
+            rules.join(","),
+            self.results.join(",")
+        )
+    }
+
+    /// Serialize the run as a complete SARIF 2.1.0 log, ready to upload.
+    pub fn to_sarif(&self) -> String {
+        format!(
+            "{{\"$schema\":\"{SCHEMA}\",\"version\":\"2.1.0\",\"runs\":[{}]}}",
+            self.to_run()
+        )
+    }
+}
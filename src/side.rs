@@ -0,0 +1,81 @@
+//! Side-by-side output, for comparing two sources.
+
+use std::io;
+
+use crate::markdown::strip_escapes;
+use crate::{RawCache, Report, ffi, unicode_width};
+
+/// Columns between the widest line of the left report and the right one.
+const GAP: usize = 4;
+
+impl Report<'_> {
+    /// Render the report and `other` side by side.
+    ///
+    /// Both reports are rendered with `cache`, so each one can label its
+    /// own source of a shared cache, e.g. the expected and the actual
+    /// output of a code generator. The lines of `other` start a few columns
+    /// after the widest line of this report; escape codes don't take a
+    /// column. When one report is longer, the other column is left blank.
+    ///
+    /// See [`Report::render_to_string`] for the accepted caches.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Cache, Config, Report, Level};
+    /// let cache = Cache::new()
+    ///     .with_source(("x = 1", "expected"))
+    ///     .with_source(("x = 2", "actual"));
+    /// let mut expected = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Note, "expected")
+    ///     .with_label((4..5, 0));
+    /// let mut actual = Report::new()
+    ///     .with_config(Config::new().with_color_disabled())
+    ///     .with_title(Level::Error, "actual")
+    ///     .with_label((4..5, 1));
+    ///
+    /// let output = expected.render_side_by_side(&mut actual, &cache)?;
+    /// let first = output.lines().next().unwrap();
+    /// assert!(first.starts_with("Note: expected ") && first.ends_with(" Error: actual"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_side_by_side(
+        &mut self,
+        other: &mut Report<'_>,
+        cache: impl Into<RawCache>,
+    ) -> io::Result<String> {
+        let cache = self.resolve_cache(cache);
+        let relayout = |report: &Report<'_>| {
+            if let RawCache::Owned(_) = cache {
+                // a temporary cache may reuse the address of another one
+                // SAFETY: report.ptr is a valid mu_Report pointer
+                unsafe { ffi::mu_relayout(report.ptr) };
+            }
+        };
+        relayout(self);
+        relayout(other);
+        let left = self.render_to_string(RawCache::Borrowed(cache.as_ptr()));
+        let right = other.render_to_string(RawCache::Borrowed(cache.as_ptr()));
+        relayout(self);
+        relayout(other);
+        let (left, right) = (left?, right?);
+
+        let width = |line: &str| unicode_width(&strip_escapes(line)) as usize;
+        let column = left.lines().map(width).max().unwrap_or(0) + GAP;
+        let mut out = String::with_capacity((left.len() + right.len()) * 2);
+        let (mut lefts, mut rights) = (left.lines(), right.lines());
+        loop {
+            let (l, r) = match (lefts.next(), rights.next()) {
+                (None, None) => break,
+                (l, r) => (l.unwrap_or(""), r),
+            };
+            out.push_str(l);
+            if let Some(r) = r.filter(|r| !r.is_empty()) {
+                out.extend(std::iter::repeat_n(' ', column - width(l)));
+                out.push_str(r);
+            }
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,178 @@
+//! Immutable copies of reports, kept after their sources are gone.
+
+use std::ffi::c_uint;
+use std::io;
+
+use crate::{
+    Config, Diagnostic, IndexType, LabelSpan, RawCache, Report, byte_to_char, display_text, ffi,
+    init_error, load_source, source_line, source_name,
+};
+
+/// An immutable copy of a report and the source lines it shows, to render
+/// it again later without the cache.
+///
+/// Created by [`Report::freeze`]. IDEs and build servers keep diagnostics
+/// around long after the buffers they were found in have changed; a
+/// snapshot holds the [`Diagnostic`] of the report along with copies of the
+/// lines its labels and suggestions touch, plus the context lines around
+/// them, so it renders the same as long as it lives.
+///
+/// Snapshots are [`Send`], [`Sync`] and cheap to keep: only the lines shown
+/// are copied. Their positions count characters, so render them with the
+/// default [`IndexType::Char`]. Configuration, colors and context spans are
+/// not kept; give the report a config again before rendering.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Cache, Config, Level, Report};
+/// let snapshot = {
+///     let code = String::from("fn main() {\n    let x = 42;\n}\n");
+///     let cache = Cache::new().with_source((code.as_str(), "main.rs"));
+///     Report::new()
+///         .with_title(Level::Warning, "unused variable")
+///         .with_label(20..21)
+///         .with_message("never read")
+///         .freeze(&cache)?
+/// }; // the code and the cache are gone
+///
+/// let output = snapshot
+///     .to_report()
+///     .with_config(Config::new().with_color_disabled())
+///     .render_to_string(())?;
+/// assert!(output.contains("main.rs:2:9"));
+/// assert!(output.contains("let x = 42;"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosticSnapshot {
+    diagnostic: Diagnostic<'static>,
+    excerpts: Vec<Excerpt>,
+}
+
+/// The lines copied from a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Excerpt {
+    name: String,
+    text: String,
+    line_no_offset: i32,
+}
+
+impl DiagnosticSnapshot {
+    /// The content of the report, with spans in the copied excerpts.
+    #[inline]
+    pub fn diagnostic(&self) -> &Diagnostic<'static> {
+        &self.diagnostic
+    }
+
+    /// The names and copied lines of the sources the report points into,
+    /// in the order of their source IDs in [`diagnostic`](Self::diagnostic).
+    pub fn excerpts(&self) -> impl Iterator<Item = (&str, &str)> {
+        (self.excerpts.iter()).map(|excerpt| (excerpt.name.as_str(), excerpt.text.as_str()))
+    }
+
+    /// A report for the snapshot, with the excerpts attached as its sources.
+    ///
+    /// Render it with `()` in place of a cache.
+    pub fn to_report(&self) -> Report<'_> {
+        let mut report = Report::from_diagnostic(&self.diagnostic);
+        for excerpt in &self.excerpts {
+            let name = excerpt.name.as_str();
+            report = report.with_source((excerpt.text.as_str(), name, excerpt.line_no_offset));
+        }
+        report
+    }
+}
+
+impl Report<'_> {
+    /// Copy the content of the report and the source lines it shows into a
+    /// [`DiagnosticSnapshot`].
+    ///
+    /// Lines are copied from `cache`, or from the sources attached to the
+    /// report when `cache` is `()`, with as many context lines as the
+    /// report's config shows.
+    ///
+    /// # Errors
+    /// Returns an error if a source the report points into fails to load.
+    pub fn freeze(&self, cache: impl Into<RawCache>) -> io::Result<DiagnosticSnapshot> {
+        let cache = self.resolve_cache(cache);
+        let default = Config::default();
+        let config = self.config.as_ref().unwrap_or(&default);
+        let context = config.context_lines().max(0) as c_uint;
+
+        let mut diagnostic = self.to_diagnostic().into_owned();
+        let mut ids = Vec::new();
+        for span in diagnostic.spans() {
+            if !ids.contains(&span.src_id()) {
+                ids.push(span.src_id());
+            }
+        }
+        let mut sources = Vec::with_capacity(ids.len());
+        let mut excerpts = Vec::with_capacity(ids.len());
+        for &id in &ids {
+            let src = load_source(cache.as_ptr(), id.into())
+                .ok_or_else(|| init_error(cache.as_ptr(), id as c_uint))?;
+            let to_char = |pos| char_pos(src, config.index_type(), pos);
+            // SAFETY: src was loaded above, and line_for_chars is its function
+            let line_of = |pos| unsafe { source_line(src, (*src).line_for_chars, pos) };
+            let (mut first, mut last) = (c_uint::MAX, 0);
+            for span in diagnostic.spans().filter(|span| span.src_id() == id) {
+                let (start, end) = (to_char(span.start()), to_char(span.end()));
+                let start_line = line_of(start).map_or(0, |(no, _, _)| no);
+                let end_line = line_of(end.saturating_sub(1).max(start)).map_or(0, |(no, _, _)| no);
+                first = first.min(start_line);
+                last = last.max(end_line);
+            }
+
+            // SAFETY: src was loaded above, so its functions are set
+            let count = unsafe { (*src).line_count.map_or(0, |f| f(src)) };
+            let first = first.saturating_sub(context);
+            let last = last.saturating_add(context).min(count.saturating_sub(1));
+            let mut text = String::new();
+            let mut offset = None;
+            for line_no in (first..=last).take_while(|_| count > 0) {
+                // SAFETY: src was loaded above, and line_no is in range
+                let (line, data) = unsafe {
+                    let line = *((*src).get_line_info.unwrap())(src, line_no);
+                    (line, ((*src).get_line.unwrap())(src, line_no))
+                };
+                let data: &[u8] = data.into();
+                offset.get_or_insert(line.offset);
+                text.push_str(&display_text(data));
+                text.push_str(match line.newline {
+                    0 => "",
+                    1 => "\n",
+                    _ => "\r\n",
+                });
+            }
+            excerpts.push(Excerpt {
+                name: source_name(cache.as_ptr(), id.into()).unwrap_or_default(),
+                text,
+                // SAFETY: src was loaded above
+                line_no_offset: unsafe { (*src).line_no_offset }.saturating_add(first as i32),
+            });
+            sources.push((src, offset.unwrap_or_default()));
+        }
+
+        diagnostic.map_spans(|span| {
+            let i = ids
+                .iter()
+                .position(|&id| id == span.src_id())
+                .unwrap_or_default();
+            let (src, offset) = sources[i];
+            let shift = |pos| char_pos(src, config.index_type(), pos).saturating_sub(offset);
+            LabelSpan::new(shift(span.start())..shift(span.end()), i)
+        });
+        Ok(DiagnosticSnapshot {
+            diagnostic,
+            excerpts,
+        })
+    }
+}
+
+/// The character position of `pos` in `src`, given in `index_type`.
+fn char_pos(src: *mut ffi::mu_Source, index_type: IndexType, pos: usize) -> usize {
+    match index_type {
+        IndexType::Byte => byte_to_char(src, pos),
+        IndexType::Char => pos,
+    }
+}
@@ -0,0 +1,131 @@
+//! Mapping positions of generated code back to its original source.
+
+use crate::LabelSpan;
+
+/// A table of position pairs mapping a generated source back to the
+/// original source it was generated from.
+///
+/// Transpilers and macro expanders report errors found in the code they
+/// generate; a source map moves those labels to the original file, so the
+/// user sees the code they wrote. Labels may also be kept on the generated
+/// code, to show both.
+///
+/// Each pair starts a segment: generated positions from the pair on map to
+/// original positions from its original position on, one to one, up to the
+/// next pair. Generated code longer than its original sticks to the last
+/// original position of the segment. Positions are in the index type of the
+/// reports they are used with.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Cache, Config, Level, Report, SourceMap};
+/// let cache = Cache::new()
+///     .with_source(("let x = (y ?? 0)", "main.ts"))
+///     .with_source(("let x = (y !== null && y !== void 0 ? y : 0)", "main.js"));
+/// // `(y` and ` 0)` are copied; `??` became a conditional
+/// let map = SourceMap::new(1, 0)
+///     .with_pair(8, 8)
+///     .with_pair(10, 10)
+///     .with_pair(42, 13);
+///
+/// let span = map.original_span((10..42, 1)).unwrap();
+/// assert_eq!(span, (10..13, 0).into());
+///
+/// let output = Report::new()
+///     .with_config(Config::new().with_color_disabled())
+///     .with_title(Level::Error, "`??` requires ES2020")
+///     .with_label(span)
+///     .with_message("used here")
+///     .render_to_string(&cache)?;
+/// assert!(output.contains("main.ts:1:11"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    generated: usize,
+    original: usize,
+    pairs: Vec<(usize, usize)>,
+}
+
+impl SourceMap {
+    /// Create an empty map from the source `generated` to the source
+    /// `original`, both given by their IDs.
+    #[inline]
+    pub fn new(generated: usize, original: usize) -> Self {
+        Self {
+            generated,
+            original,
+            pairs: Vec::new(),
+        }
+    }
+
+    /// Add a pair, for building a map in a chain.
+    #[inline]
+    #[must_use]
+    pub fn with_pair(mut self, generated: usize, original: usize) -> Self {
+        self.add_pair(generated, original);
+        self
+    }
+
+    /// Map the position `generated` of the generated source to the position
+    /// `original` of the original source.
+    ///
+    /// Pairs may be added in any order; a pair at the same generated
+    /// position as an earlier one replaces it.
+    pub fn add_pair(&mut self, generated: usize, original: usize) {
+        match self.pairs.binary_search_by_key(&generated, |&(g, _)| g) {
+            Ok(i) => self.pairs[i].1 = original,
+            Err(i) => self.pairs.insert(i, (generated, original)),
+        }
+    }
+
+    /// The ID of the generated source.
+    #[inline]
+    pub fn generated_id(&self) -> usize {
+        self.generated
+    }
+
+    /// The ID of the original source.
+    #[inline]
+    pub fn original_id(&self) -> usize {
+        self.original
+    }
+
+    /// The pairs of the map, sorted by generated position.
+    #[inline]
+    pub fn pairs(&self) -> &[(usize, usize)] {
+        &self.pairs
+    }
+
+    /// The original position of the generated position `pos`, or `None` if
+    /// it comes before the first pair.
+    pub fn original_pos(&self, pos: usize) -> Option<usize> {
+        let i = self.pairs.partition_point(|&(g, _)| g <= pos);
+        let &(generated, original) = self.pairs[..i].last()?;
+        let r = original + (pos - generated);
+        match self.pairs.get(i) {
+            Some(&(_, next)) if next > original => Some(r.min(next - 1)),
+            _ => Some(r),
+        }
+    }
+
+    /// The span of the original source that `span` of the generated source
+    /// was generated from.
+    ///
+    /// The end of the span is mapped from its last position, so a span
+    /// ending at a segment boundary stays in its segment. Returns `None` if
+    /// `span` is not in the generated source, or starts before the first
+    /// pair.
+    pub fn original_span(&self, span: impl Into<LabelSpan>) -> Option<LabelSpan> {
+        let span = span.into();
+        if span.src_id() != self.generated {
+            return None;
+        }
+        let start = self.original_pos(span.start())?;
+        let end = match span.end().checked_sub(1) {
+            Some(last) if !span.is_empty() => self.original_pos(last)? + 1,
+            _ => start,
+        };
+        Some(LabelSpan::new(start..end.max(start), self.original))
+    }
+}
@@ -0,0 +1,206 @@
+//! Text styles, with attributes and a background beside the foreground
+//! color.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::{Color, ColorKind};
+
+/// A color of a [`Style`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StyleColor {
+    /// A color of the 256-color palette; the first 16 are the basic colors
+    /// of the terminal theme
+    Index(u8),
+    /// A 24-bit color, as red, green and blue
+    Rgb(u8, u8, u8),
+}
+
+impl From<u8> for StyleColor {
+    #[inline]
+    fn from(index: u8) -> Self {
+        StyleColor::Index(index)
+    }
+}
+
+impl From<(u8, u8, u8)> for StyleColor {
+    #[inline]
+    fn from((r, g, b): (u8, u8, u8)) -> Self {
+        StyleColor::Rgb(r, g, b)
+    }
+}
+
+/// A text style: foreground and background colors, bold, dim, italic and
+/// underlined text.
+///
+/// Its [`Display`](fmt::Display) writes the escape sequence of the style,
+/// for [`Color`] implementations that style each [`ColorKind`] apart, like
+/// a bold title. A style is also a [`Color`] itself, giving every kind the
+/// same style, so it can be passed wherever a color is, like
+/// [`Report::with_color`](crate::Report::with_color) to highlight a label
+/// with a background.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Color, ColorKind, Config, Level, Report, Style};
+/// # use std::io::Write;
+/// struct Bold;
+///
+/// impl Color for Bold {
+///     fn color(&self, w: &mut dyn Write, kind: ColorKind) -> std::io::Result<()> {
+///         match kind {
+///             ColorKind::Error => write!(w, "{}", Style::new().with_fg(9).with_bold(true)),
+///             ColorKind::Reset => write!(w, "\x1b[0m"),
+///             _ => Ok(()),
+///         }
+///     }
+/// }
+///
+/// let highlight = Style::new().with_fg(0).with_bg((255, 215, 0));
+/// assert_eq!(highlight.to_string(), "\x1b[38;5;0;48;2;255;215;0m");
+/// let mut report = Report::new()
+///     .with_config(Config::new().with_color(&Bold))
+///     .with_title(Level::Error, "unknown name")
+///     .with_label(4..5)
+///     .with_color(&highlight);
+/// let output = report.render_to_string("let x = y;")?;
+/// assert!(output.contains("\x1b[1;38;5;9mError:"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    fg: Option<StyleColor>,
+    bg: Option<StyleColor>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Style {
+    /// Create a style that changes nothing.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            fg: None,
+            bg: None,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+        }
+    }
+
+    /// Set the foreground color.
+    #[inline]
+    #[must_use]
+    pub fn with_fg(mut self, color: impl Into<StyleColor>) -> Self {
+        self.fg = Some(color.into());
+        self
+    }
+
+    /// Set the background color.
+    #[inline]
+    #[must_use]
+    pub fn with_bg(mut self, color: impl Into<StyleColor>) -> Self {
+        self.bg = Some(color.into());
+        self
+    }
+
+    /// Make the text bold.
+    ///
+    /// Default: [`false`]
+    #[inline]
+    #[must_use]
+    pub fn with_bold(mut self, bold: bool) -> Self {
+        self.bold = bold;
+        self
+    }
+
+    /// Make the text dim.
+    ///
+    /// Default: [`false`]
+    #[inline]
+    #[must_use]
+    pub fn with_dim(mut self, dim: bool) -> Self {
+        self.dim = dim;
+        self
+    }
+
+    /// Make the text italic.
+    ///
+    /// Default: [`false`]
+    #[inline]
+    #[must_use]
+    pub fn with_italic(mut self, italic: bool) -> Self {
+        self.italic = italic;
+        self
+    }
+
+    /// Underline the text.
+    ///
+    /// Default: [`false`]
+    #[inline]
+    #[must_use]
+    pub fn with_underline(mut self, underline: bool) -> Self {
+        self.underline = underline;
+        self
+    }
+
+    /// The foreground color, if any.
+    #[inline]
+    pub fn fg(&self) -> Option<StyleColor> {
+        self.fg
+    }
+
+    /// The background color, if any.
+    #[inline]
+    pub fn bg(&self) -> Option<StyleColor> {
+        self.bg
+    }
+
+    /// Whether the style changes nothing, and writes no escape sequence.
+    #[inline]
+    pub fn is_plain(&self) -> bool {
+        *self == Self::new()
+    }
+}
+
+impl fmt::Display for Style {
+    /// Write the escape sequence of the style, or nothing for a plain
+    /// style.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_plain() {
+            return Ok(());
+        }
+        let mut sep = "\x1b[";
+        let flags = [
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+        ];
+        for (_, code) in flags.iter().filter(|(on, _)| *on) {
+            write!(f, "{sep}{code}")?;
+            sep = ";";
+        }
+        for (color, base) in [(self.fg, 38), (self.bg, 48)] {
+            match color {
+                Some(StyleColor::Index(i)) => write!(f, "{sep}{base};5;{i}")?,
+                Some(StyleColor::Rgb(r, g, b)) => write!(f, "{sep}{base};2;{r};{g};{b}")?,
+                None => continue,
+            }
+            sep = ";";
+        }
+        f.write_str("m")
+    }
+}
+
+impl Color for Style {
+    fn color(&self, w: &mut dyn Write, kind: ColorKind) -> io::Result<()> {
+        match kind {
+            ColorKind::Reset => w.write_all(b"\x1b[0m"),
+            _ => write!(w, "{self}"),
+        }
+    }
+}
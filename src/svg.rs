@@ -0,0 +1,150 @@
+//! SVG output, for sharing reports as images.
+
+use std::fmt::Write as _;
+use std::io;
+
+use crate::html::{Markup, escape};
+use crate::{RawCache, Report, unicode_width};
+
+/// Font size of the text, in pixels.
+const FONT_SIZE: usize = 14;
+/// Advance of a monospace column, about 0.6em.
+const COLUMN_WIDTH: f64 = 8.4;
+/// Distance between baselines.
+const LINE_HEIGHT: usize = 20;
+/// Space around the text.
+const PADDING: usize = 16;
+/// Height of the title bar with the window buttons.
+const TITLE_BAR: usize = 32;
+
+const BACKGROUND: &str = "#1e1e1e";
+const FOREGROUND: &str = "#d4d4d4";
+const BUTTONS: [&str; 3] = ["#ff5f56", "#ffbd2e", "#27c93f"];
+
+/// Collects the output of [`Report::render_to_svg`] as lines of `<tspan>`s.
+struct SvgOutput {
+    lines: Vec<String>,
+    line: String,
+    width: i32,     /* columns of the current line */
+    max_width: i32, /* columns of the longest line */
+    style: Option<(String, String)>,
+}
+
+impl SvgOutput {
+    fn push_span(&mut self) {
+        if let Some((class, style)) = &self.style {
+            let _ = write!(self.line, "<tspan class=\"{class}\" style=\"{style}\">");
+        }
+    }
+
+    fn pop_span(&mut self) {
+        if self.style.is_some() {
+            self.line.push_str("</tspan>");
+        }
+    }
+
+    /// Finish the current line, carrying the open color over to the next.
+    fn newline(&mut self) {
+        self.pop_span();
+        self.lines.push(std::mem::take(&mut self.line));
+        self.max_width = self.max_width.max(self.width);
+        self.width = 0;
+        self.push_span();
+    }
+}
+
+impl Markup for SvgOutput {
+    fn open(&mut self, class: &str, style: &str) {
+        self.close();
+        self.style = Some((class.into(), style.replace("color:", "fill:")));
+        self.push_span();
+    }
+
+    fn close(&mut self) {
+        self.pop_span();
+        self.style = None;
+    }
+
+    fn text(&mut self, text: &str) {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.newline();
+            }
+            self.width += unicode_width(part);
+            self.line.push_str(&escape(part));
+        }
+    }
+}
+
+impl Report<'_> {
+    /// Render the report as a standalone SVG image.
+    ///
+    /// The report is drawn in a monospace font on a dark, rounded window
+    /// with a title bar, like the code screenshots of `carbon` or
+    /// `silicon`, ready to be posted in issues and blog posts. Colors are
+    /// styled like [`render_to_html`](Report::render_to_html), with a
+    /// `<tspan>` per colored part carrying the same classes.
+    ///
+    /// The image width is estimated from the character count of the longest
+    /// line, so lines with wide characters may be clipped.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Report, Level};
+    /// let mut report = Report::new()
+    ///     .with_title(Level::Error, "Syntax <error>")
+    ///     .with_label(0..3)
+    ///     .with_message("unexpected token");
+    ///
+    /// let svg = report.render_to_svg("let x")?;
+    /// assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    /// assert!(svg.contains(r#"<tspan class="mu-error" style="fill:#c00">Error:</tspan>"#));
+    /// assert!(svg.contains("Syntax &lt;error&gt;"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn render_to_svg(&mut self, cache: impl Into<RawCache>) -> io::Result<String> {
+        let mut svg = SvgOutput {
+            lines: Vec::new(),
+            line: String::new(),
+            width: 0,
+            max_width: 0,
+            style: None,
+        };
+        self.render_markup(cache, &mut svg)?;
+        svg.close();
+        if !svg.line.is_empty() {
+            svg.newline();
+        }
+
+        let width = (svg.max_width as f64 * COLUMN_WIDTH).ceil() as usize + 2 * PADDING;
+        let height = TITLE_BAR + svg.lines.len() * LINE_HEIGHT + PADDING;
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             viewBox=\"0 0 {width} {height}\">"
+        );
+        let _ = writeln!(
+            out,
+            "<rect width=\"{width}\" height=\"{height}\" rx=\"8\" fill=\"{BACKGROUND}\"/>"
+        );
+        for (i, color) in BUTTONS.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "<circle cx=\"{}\" cy=\"16\" r=\"6\" fill=\"{color}\"/>",
+                PADDING + 4 + i * 20
+            );
+        }
+        let _ = writeln!(
+            out,
+            "<text font-family=\"ui-monospace,Menlo,Consolas,monospace\" \
+             font-size=\"{FONT_SIZE}\" fill=\"{FOREGROUND}\" xml:space=\"preserve\">"
+        );
+        for (i, line) in svg.lines.iter().enumerate() {
+            let y = TITLE_BAR + (i + 1) * LINE_HEIGHT - LINE_HEIGHT / 4;
+            let _ = writeln!(out, "<tspan x=\"{PADDING}\" y=\"{y}\">{line}</tspan>");
+        }
+        out.push_str("</text>\n</svg>\n");
+        Ok(out)
+    }
+}
@@ -0,0 +1,298 @@
+//! Helpers for testing the diagnostics of a tool.
+//!
+//! Golden tests compare a rendered report with the one expected, and when
+//! they fail, two walls of box-drawing text are hard to tell apart.
+//! [`diff_reports`] says what changed: which labels moved or got another
+//! message, which notes were added, and which rendered lines differ.
+
+use std::fmt;
+use std::io;
+
+use crate::{Diagnostic, LabelSpan, RawCache, Report, ffi};
+
+/// The differences between two reports, made by [`diff_reports`].
+///
+/// Its [`Display`](fmt::Display) lists the changes of content first, then
+/// the rendered lines, `-` for lines only in the first report and `+` for
+/// lines only in the second, with the lines around them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportDiff {
+    changes: Vec<String>,
+    rendered: String,
+}
+
+impl ReportDiff {
+    /// Whether the reports have the same content and render the same.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.rendered.is_empty()
+    }
+
+    /// The changes of content, one line each, like
+    /// `label 1 span: 4..5 -> 4..6`.
+    #[inline]
+    pub fn changes(&self) -> &[String] {
+        &self.changes
+    }
+
+    /// The diff of the rendered output, or `""` if it is the same.
+    #[inline]
+    pub fn rendered(&self) -> &str {
+        &self.rendered
+    }
+}
+
+impl fmt::Display for ReportDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        if !self.rendered.is_empty() {
+            if !self.changes.is_empty() {
+                writeln!(f)?;
+            }
+            f.write_str(&self.rendered)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two reports, rendered with the sources of `cache`.
+///
+/// Labels, suggestions, helps and notes are compared by their index. Both
+/// reports are rendered with their own configs, so give them the same
+/// config, with colors disabled, to compare only what they show.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Config, Level, Report};
+/// # use musubi::testing::diff_reports;
+/// let report = |end: usize, msg: &'static str| {
+///     Report::new()
+///         .with_config(Config::new().with_color_disabled())
+///         .with_title(Level::Error, "unknown name")
+///         .with_label(4..end)
+///         .with_message(msg)
+/// };
+/// let diff = diff_reports(&mut report(5, "here"), &mut report(6, "there"), "let xy = 1;")?;
+/// assert_eq!(
+///     diff.changes(),
+///     ["label 0 span: 4..5 -> 4..6", r#"label 0 message: Some("here") -> Some("there")"#]
+/// );
+/// assert!(diff.rendered().contains("+    │      ╰── there"));
+/// assert!(diff_reports(&mut report(5, "here"), &mut report(5, "here"), "let xy = 1;")?.is_empty());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn diff_reports(
+    a: &mut Report<'_>,
+    b: &mut Report<'_>,
+    cache: impl Into<RawCache>,
+) -> io::Result<ReportDiff> {
+    let cache = cache.into();
+    if let RawCache::Owned(_) = cache {
+        // a temporary cache may reuse the address of the last one
+        relayout(a);
+        relayout(b);
+    }
+    // rendering first places the labels given by line and column
+    let old = a.render_to_string(RawCache::Borrowed(cache.as_ptr()))?;
+    let new = b.render_to_string(RawCache::Borrowed(cache.as_ptr()))?;
+    Ok(ReportDiff {
+        changes: diff_diagnostics(&a.to_diagnostic(), &b.to_diagnostic()),
+        rendered: diff_lines(&old, &new),
+    })
+}
+
+/// Drop the layouts kept by `report` and its related reports.
+fn relayout(report: &mut Report<'_>) {
+    // SAFETY: report.ptr is a valid mu_Report pointer
+    unsafe { ffi::mu_relayout(report.ptr) };
+    report.related.iter_mut().for_each(relayout);
+}
+
+/// Lines of context shown around changed lines.
+const CONTEXT: usize = 2;
+
+fn span_text(span: LabelSpan) -> String {
+    match span.src_id() {
+        0 => format!("{}..{}", span.start(), span.end()),
+        id => format!("({}..{}, {id})", span.start(), span.end()),
+    }
+}
+
+/// Compare the items of `old` and `new` by index, with `item` comparing
+/// two items present in both.
+fn diff_items<T>(
+    changes: &mut Vec<String>,
+    name: &str,
+    old: &[T],
+    new: &[T],
+    show: impl Fn(&T) -> String,
+    mut item: impl FnMut(&mut Vec<String>, String, &T, &T),
+) {
+    for i in 0..old.len().max(new.len()) {
+        match (old.get(i), new.get(i)) {
+            (Some(a), Some(b)) => item(changes, format!("{name} {i}"), a, b),
+            (Some(a), None) => changes.push(format!("{name} {i} removed: {}", show(a))),
+            (None, Some(b)) => changes.push(format!("{name} {i} added: {}", show(b))),
+            (None, None) => {}
+        }
+    }
+}
+
+fn diff_field<T: PartialEq + fmt::Debug>(changes: &mut Vec<String>, what: &str, a: T, b: T) {
+    if a != b {
+        changes.push(format!("{what}: {a:?} -> {b:?}"));
+    }
+}
+
+fn diff_diagnostics(a: &Diagnostic<'_>, b: &Diagnostic<'_>) -> Vec<String> {
+    let mut changes = Vec::new();
+    diff_field(&mut changes, "level", a.level_name(), b.level_name());
+    diff_field(&mut changes, "title", a.title(), b.title());
+    diff_field(&mut changes, "code", a.code(), b.code());
+    let show_label = |l: &crate::DiagnosticLabel<'_>| match l.message() {
+        Some(msg) => format!("{} {msg:?}", span_text(l.span())),
+        None => span_text(l.span()),
+    };
+    diff_items(
+        &mut changes,
+        "label",
+        a.labels(),
+        b.labels(),
+        show_label,
+        |changes, name, a, b| {
+            if a.span() != b.span() {
+                changes.push(format!(
+                    "{name} span: {} -> {}",
+                    span_text(a.span()),
+                    span_text(b.span())
+                ));
+            }
+            diff_field(
+                changes,
+                &format!("{name} message"),
+                a.message(),
+                b.message(),
+            );
+            diff_field(
+                changes,
+                &format!("{name} primary"),
+                a.is_primary(),
+                b.is_primary(),
+            );
+        },
+    );
+    let show_text = |s: &std::borrow::Cow<'_, str>| format!("{s:?}");
+    let diff_text = |changes: &mut Vec<String>, name: String, a: &_, b: &_| {
+        diff_field(changes, &name, a, b);
+    };
+    diff_items(
+        &mut changes,
+        "help",
+        a.helps(),
+        b.helps(),
+        show_text,
+        diff_text,
+    );
+    diff_items(
+        &mut changes,
+        "note",
+        a.notes(),
+        b.notes(),
+        show_text,
+        diff_text,
+    );
+    let show_edits = |s: &crate::DiagnosticSuggestion<'_>| {
+        let edits = s
+            .edits()
+            .iter()
+            .map(|(span, text)| format!("{} {text:?}", span_text(*span)));
+        edits.collect::<Vec<_>>().join(", ")
+    };
+    diff_items(
+        &mut changes,
+        "suggestion",
+        a.suggestions(),
+        b.suggestions(),
+        show_edits,
+        |changes, name, a, b| {
+            diff_field(
+                changes,
+                &format!("{name} edits"),
+                show_edits(a),
+                show_edits(b),
+            );
+            diff_field(
+                changes,
+                &format!("{name} message"),
+                a.message(),
+                b.message(),
+            );
+            diff_field(
+                changes,
+                &format!("{name} applicability"),
+                a.applicability(),
+                b.applicability(),
+            );
+        },
+    );
+    changes
+}
+
+/// A line diff of `old` and `new`, from their longest common subsequence
+/// of lines, with [`CONTEXT`] lines around the changes.
+fn diff_lines(old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let (a, b): (Vec<_>, Vec<_>) = (old.lines().collect(), new.lines().collect());
+    // lcs[i][j]: length of the common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((' ', a[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', a[i]));
+            i += 1;
+        } else {
+            ops.push(('+', b[j]));
+            j += 1;
+        }
+    }
+    let changed: Vec<_> = (ops.iter().enumerate())
+        .filter(|(_, (op, _))| *op != ' ')
+        .map(|(k, _)| k)
+        .collect();
+    let mut out = String::new();
+    let mut last = None;
+    for (k, (op, line)) in ops.iter().enumerate() {
+        let near = changed
+            .iter()
+            .any(|&c| k + CONTEXT >= c && k <= c + CONTEXT);
+        if !near {
+            continue;
+        }
+        if last.is_some_and(|l| l + 1 != k) {
+            out.push_str("...\n");
+        }
+        last = Some(k);
+        out.push(*op);
+        out.push(' ');
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,122 @@
+//! Presets of the look of reports, after well-known tools.
+
+use std::io::{self, Write};
+
+use crate::{Color, ColorKind, Config, LabelAttach};
+
+/// A preset of the character set, colors, compactness and label attachment
+/// of reports, applied with [`Config::with_theme`]
+///
+/// # Example
+/// ```text
+/// Ariadne (default):
+///    ╭─[ main.rs:1:9 ]
+///    │
+///  1 ┤ let a = b;
+///    │         ┌
+///    │         ╰── not found
+/// ───╯
+///
+/// Rustc:
+///    ,-[ main.rs:1:9 ]
+///    |
+///  1 | let a = b;
+///    |         |
+///    |         `-- not found
+/// ---'
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    /// Box-drawing characters and the default colors, the look of the
+    /// `ariadne` crate (default)
+    #[default]
+    Ariadne,
+    /// ASCII characters, labels attached at their start, and the bold
+    /// colors of rustc: red errors, yellow warnings, blue margins and
+    /// secondary labels, green notes and cyan hints
+    Rustc,
+    /// ASCII characters, compact reports, labels attached at their start,
+    /// and the colors of GCC: red errors, magenta warnings, cyan notes and
+    /// green labels
+    Gcc,
+    /// ASCII characters without colors, for logs and plain text files
+    Monochrome,
+}
+
+/// A color scheme mapping each [`ColorKind`] to an escape sequence.
+struct Scheme(fn(ColorKind) -> &'static str);
+
+impl Color for Scheme {
+    fn color(&self, w: &mut dyn Write, kind: ColorKind) -> io::Result<()> {
+        w.write_all((self.0)(kind).as_bytes())
+    }
+}
+
+static RUSTC: Scheme = Scheme(|kind| match kind {
+    ColorKind::Reset => "\x1b[0m",
+    ColorKind::Error => "\x1b[1;91m",
+    ColorKind::Warning => "\x1b[1;93m",
+    ColorKind::Margin | ColorKind::SkippedMargin | ColorKind::Label => "\x1b[1;94m",
+    ColorKind::Note | ColorKind::Info => "\x1b[1;92m",
+    ColorKind::Hint | ColorKind::Advice => "\x1b[1;96m",
+    ColorKind::Kind | ColorKind::Emphasis | ColorKind::Expected | ColorKind::Found => "\x1b[1m",
+    ColorKind::Unimportant | ColorKind::InlineCode | ColorKind::Context => "",
+});
+
+static GCC: Scheme = Scheme(|kind| match kind {
+    ColorKind::Reset => "\x1b[0m",
+    ColorKind::Error => "\x1b[1;31m",
+    ColorKind::Warning | ColorKind::Kind => "\x1b[1;35m",
+    ColorKind::Note | ColorKind::Info | ColorKind::Hint | ColorKind::Advice => "\x1b[1;36m",
+    ColorKind::Label | ColorKind::Expected => "\x1b[32m",
+    ColorKind::Found => "\x1b[31m",
+    ColorKind::Emphasis | ColorKind::InlineCode => "\x1b[1m",
+    ColorKind::Margin | ColorKind::SkippedMargin | ColorKind::Unimportant | ColorKind::Context => {
+        ""
+    }
+});
+
+impl<'a> Config<'a> {
+    /// Apply the character set, colors, compactness and label attachment
+    /// of `theme`.
+    ///
+    /// Other options are left as they are, and later calls override the
+    /// theme, so apply it first and adjust it afterwards.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use musubi::{Config, Level, Report, Theme};
+    /// let output = Report::new()
+    ///     .with_config(Config::new().with_theme(Theme::Rustc))
+    ///     .with_title(Level::Error, "unknown name")
+    ///     .with_label(8..9)
+    ///     .with_message("not found")
+    ///     .render_to_string("let a = b;")?;
+    /// assert!(output.starts_with("\x1b[1;91mError:"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn with_theme(self, theme: Theme) -> Self {
+        match theme {
+            Theme::Ariadne => self
+                .with_char_set_unicode()
+                .with_color_default()
+                .with_compact(false)
+                .with_label_attach(LabelAttach::Middle),
+            Theme::Rustc => self
+                .with_char_set_ascii()
+                .with_color(&RUSTC)
+                .with_compact(false)
+                .with_label_attach(LabelAttach::Start),
+            Theme::Gcc => self
+                .with_char_set_ascii()
+                .with_color(&GCC)
+                .with_compact(true)
+                .with_label_attach(LabelAttach::Start),
+            Theme::Monochrome => self
+                .with_char_set_ascii()
+                .with_color_disabled()
+                .with_compact(false)
+                .with_label_attach(LabelAttach::Middle),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Sources whose lines are rewritten before they are displayed.
+
+use std::ffi::{c_int, c_uint};
+use std::ops::Range;
+
+use crate::{AddToCache, ffi};
+
+/// A source whose lines are rewritten by a closure before they are
+/// displayed, e.g. to expand template placeholders, mask credentials or
+/// decode escapes.
+///
+/// The closure is called once for each line, without its newline, and
+/// returns the edits to make: byte ranges of the line with their
+/// replacements, sorted and not overlapping. Edits not on character
+/// boundaries, out of order or out of bounds are ignored, and replacements
+/// should not contain newlines.
+///
+/// Label and context positions still refer to the original text: they are
+/// moved along with the edits, so labels stay on the same text once it is
+/// rewritten. A position inside a replaced range sticks to the replacement,
+/// starting at its start or ending at its end. Columns in locations and
+/// suggestion positions refer to the displayed text.
+///
+/// # Example
+/// ```rust
+/// # use musubi::{Cache, Config, Level, Report, TransformSource};
+/// let text = "connect(\"admin:hunter2@db\", timeout)";
+/// let source = TransformSource::new(text, |line: &str| match line.find("hunter2") {
+///     Some(start) => vec![(start..start + 7, "***".to_string())],
+///     None => vec![],
+/// });
+/// let cache = Cache::new().with_source(source);
+///
+/// let output = Report::new()
+///     .with_config(Config::new().with_color_disabled())
+///     .with_title(Level::Error, "unknown argument")
+///     .with_label(28..35)
+///     .render_to_string(&cache)?;
+/// assert!(output.contains("connect(\"admin:***@db\", timeout)"));
+/// assert!(output.contains("1:25"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct TransformSource<F> {
+    text: String,
+    transform: F,
+    shown: String,
+    lines: Vec<LineMap>,
+}
+
+/// Where a line of the original text is displayed.
+struct LineMap {
+    bytes: (usize, usize), /* start in the original text, start when shown */
+    chars: (usize, usize),
+    edits: Vec<Edit>,
+}
+
+/// An edit of a line; ranges are relative to the start of the line.
+struct Edit {
+    bytes: Range<usize>,
+    chars: Range<usize>,
+    new_bytes: usize,
+    new_chars: usize,
+}
+
+impl<F: FnMut(&str) -> Vec<(Range<usize>, String)>> TransformSource<F> {
+    /// Create a source for `text`, with its lines rewritten by `transform`
+    /// when the source is first rendered.
+    #[inline]
+    pub fn new(text: impl Into<String>, transform: F) -> Self {
+        Self {
+            text: text.into(),
+            transform,
+            shown: String::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// The original text.
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn load(&mut self) {
+        self.shown.clear();
+        self.lines.clear();
+        let (mut bytes, mut chars, mut shown_chars) = (0, 0, 0);
+        for raw in self.text.split_inclusive('\n') {
+            let line = raw.trim_end_matches('\n').trim_end_matches('\r');
+            let shown_start = self.shown.len();
+            let mut map = LineMap {
+                bytes: (bytes, shown_start),
+                chars: (chars, shown_chars),
+                edits: Vec::new(),
+            };
+            let mut last = 0;
+            for (range, new) in (self.transform)(line) {
+                let Some(old) = line.get(range.clone()) else {
+                    continue;
+                };
+                if range.start < last {
+                    continue;
+                }
+                self.shown.push_str(&line[last..range.start]);
+                self.shown.push_str(&new);
+                let start = line[..range.start].chars().count();
+                map.edits.push(Edit {
+                    bytes: range.clone(),
+                    chars: start..start + old.chars().count(),
+                    new_bytes: new.len(),
+                    new_chars: new.chars().count(),
+                });
+                last = range.end;
+            }
+            self.shown.push_str(&raw[last..]);
+            bytes += raw.len();
+            chars += raw.chars().count();
+            shown_chars += self.shown[shown_start..].chars().count();
+            self.lines.push(map);
+        }
+    }
+
+    fn map_pos(&self, pos: usize, bytes: bool, end: bool) -> usize {
+        let starts = |map: &LineMap| if bytes { map.bytes } else { map.chars };
+        let i = self.lines.partition_point(|map| starts(map).0 <= pos);
+        let Some(map) = i.checked_sub(1).map(|i| &self.lines[i]) else {
+            return pos;
+        };
+        let (start, shown_start) = starts(map);
+        let mut col = (pos - start) as isize;
+        for edit in &map.edits {
+            let (old, new) = if bytes {
+                (&edit.bytes, edit.new_bytes)
+            } else {
+                (&edit.chars, edit.new_chars)
+            };
+            if pos - start >= old.end {
+                col += new as isize - old.len() as isize;
+            } else if pos - start > old.start {
+                /* inside the replaced range: stick to the replacement */
+                col += old.start as isize - (pos - start) as isize;
+                col += if end { new as isize } else { 0 };
+                break;
+            } else {
+                break;
+            }
+        }
+        shown_start + col as usize
+    }
+}
+
+impl<F: FnMut(&str) -> Vec<(Range<usize>, String)>> AddToCache for TransformSource<F> {
+    fn add_to_cache(self, cache: &mut *mut ffi::mu_Cache) -> *mut ffi::mu_Source {
+        #[repr(C)]
+        struct TransformSrc<F> {
+            base: ffi::mu_Source,
+            src: TransformSource<F>,
+        }
+        // SAFETY: mu_addsource initializes the cache and source correctly
+        let src =
+            unsafe { ffi::mu_addsource(cache, size_of::<TransformSrc<F>>(), Default::default()) };
+        let transform_src = src as *mut TransformSrc<F>;
+        // SAFETY: src is allocated by mu_addsource above with room for a
+        // TransformSrc, and its src field is uninitialized memory
+        unsafe {
+            std::ptr::addr_of_mut!((*transform_src).src).write(self);
+            (*src).init = Some(init_fn::<F>);
+            (*src).free = Some(free_fn::<F>);
+            (*src).get_line = Some(get_line_fn::<F>);
+            (*src).map_pos = Some(map_pos_fn::<F>);
+        }
+
+        unsafe extern "C" fn init_fn<F: FnMut(&str) -> Vec<(Range<usize>, String)>>(
+            src: *mut ffi::mu_Source,
+        ) -> c_int {
+            // SAFETY: src is a valid TransformSrc pointer created above
+            let src = unsafe { &mut *(src as *mut TransformSrc<F>) };
+            src.src.load();
+            // SAFETY: calling mu_updatelines is safe
+            unsafe { ffi::mu_updatelines(&mut src.base, src.src.shown.as_str().into()) };
+            ffi::MU_OK
+        }
+
+        unsafe extern "C" fn free_fn<F>(src: *mut ffi::mu_Source) {
+            // SAFETY: src was allocated by mu_addsource and is valid here
+            // after this call, src will be freed by C library.
+            unsafe { std::ptr::drop_in_place(src as *mut TransformSrc<F>) };
+        }
+
+        unsafe extern "C" fn get_line_fn<F>(
+            src: *mut ffi::mu_Source,
+            line_no: c_uint,
+        ) -> ffi::mu_Slice {
+            // SAFETY: src is a valid, loaded source, whose lines are only
+            // read here
+            let line = unsafe { *ffi::mu_getline(src, line_no) };
+            // SAFETY: src is a valid TransformSrc pointer; the lines were
+            // rewritten by init, so it is only read here
+            let src = unsafe { &*(src as *const TransformSrc<F>) };
+            src.src.shown.as_bytes()[line.byte_offset..][..line.byte_len as usize].into()
+        }
+
+        unsafe extern "C" fn map_pos_fn<F: FnMut(&str) -> Vec<(Range<usize>, String)>>(
+            src: *mut ffi::mu_Source,
+            pos: usize,
+            index_type: ffi::mu_IndexType,
+            end: c_int,
+        ) -> usize {
+            // SAFETY: src is a valid TransformSrc pointer
+            let src = unsafe { &*(src as *const TransformSrc<F>) };
+            let bytes = index_type == ffi::mu_IndexType::MU_INDEX_BYTE;
+            src.src.map_pos(pos, bytes, end != 0)
+        }
+
+        src
+    }
+}
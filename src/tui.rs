@@ -0,0 +1,279 @@
+//! Interactive review of the reports of an emitter, in the terminal.
+
+use std::io::{self, IsTerminal, Write};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::emitter::Rendered;
+use crate::{Emitter, RawCache, unicode_width};
+
+/// Name of the file filter for reports without any label.
+const NO_FILE: &str = "(no location)";
+
+/// A report listed in the review.
+struct Entry {
+    file: Option<String>,
+    level: String,
+    header: String,
+    text: String,
+}
+
+impl From<Rendered<'_>> for Entry {
+    fn from(rendered: Rendered<'_>) -> Self {
+        let location = match (&rendered.file, rendered.location) {
+            (Some(file), Some((line, col))) => format!("{file}:{line}:{col}"),
+            (Some(file), None) => file.clone(),
+            (None, _) => NO_FILE.into(),
+        };
+        let header = match rendered.code {
+            Some(code) => format!(
+                "{location}  [{code}] {}: {}",
+                rendered.level, rendered.title
+            ),
+            None => format!("{location}  {}: {}", rendered.level, rendered.title),
+        };
+        Entry {
+            file: rendered.file,
+            level: rendered.level.into(),
+            header,
+            text: rendered.text,
+        }
+    }
+}
+
+/// State of the review screen: the list of reports, filtered by file and
+/// level, and the selected report.
+pub(crate) struct Review {
+    entries: Vec<Entry>,
+    files: Vec<Option<String>>,
+    levels: Vec<String>,
+    /// Index in `files` of the shown file, or all files
+    file: Option<usize>,
+    /// Index in `levels` of the shown level, or all levels
+    level: Option<usize>,
+    /// Index of the selected report among the shown ones
+    selected: usize,
+    /// First line of the selected report on screen
+    scroll: usize,
+    /// Lines of the report pane at the last draw
+    page: usize,
+}
+
+impl Review {
+    /// Render the reports of `emitter` for the review.
+    pub(crate) fn new(
+        emitter: &mut Emitter<'_>,
+        cache: impl Into<RawCache>,
+        plain: bool,
+    ) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        emitter.render_each(cache, plain, |rendered| {
+            entries.push(Entry::from(rendered));
+            Ok(())
+        })?;
+        let (mut files, mut levels) = (Vec::new(), Vec::new());
+        for entry in &entries {
+            if !files.contains(&entry.file) {
+                files.push(entry.file.clone());
+            }
+            if !levels.contains(&entry.level) {
+                levels.push(entry.level.clone());
+            }
+        }
+        Ok(Self {
+            entries,
+            files,
+            levels,
+            file: None,
+            level: None,
+            selected: 0,
+            scroll: 0,
+            page: 1,
+        })
+    }
+
+    /// Indices of the reports passing the filters.
+    fn shown(&self) -> Vec<usize> {
+        (0..self.entries.len())
+            .filter(|&i| {
+                self.file
+                    .is_none_or(|f| self.files[f] == self.entries[i].file)
+            })
+            .filter(|&i| {
+                self.level
+                    .is_none_or(|l| self.levels[l] == self.entries[i].level)
+            })
+            .collect()
+    }
+
+    /// Handle a key press, returning `false` to quit.
+    pub(crate) fn key(&mut self, code: KeyCode) -> bool {
+        let count = self.shown().len();
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') if self.selected + 1 < count => {
+                (self.selected, self.scroll) = (self.selected + 1, 0);
+            }
+            KeyCode::Up | KeyCode::Char('k') if self.selected > 0 => {
+                (self.selected, self.scroll) = (self.selected - 1, 0);
+            }
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll += self.page,
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(self.page),
+            KeyCode::Char('f') => {
+                self.file = next_filter(self.file, self.files.len());
+                (self.selected, self.scroll) = (0, 0);
+            }
+            KeyCode::Char('s') => {
+                self.level = next_filter(self.level, self.levels.len());
+                (self.selected, self.scroll) = (0, 0);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// The lines of a screen of `width` columns and `height` lines.
+    pub(crate) fn draw(&mut self, width: usize, height: usize) -> Vec<String> {
+        let shown = self.shown();
+        let file = match self.file {
+            Some(f) => self.files[f].as_deref().unwrap_or(NO_FILE),
+            None => "all",
+        };
+        let level = self.level.map_or("all", |l| &self.levels[l]);
+        let mut lines = vec![fit(
+            &format!(
+                "{} of {} reports | file: {file} (f) | level: {level} (s) | j/k: select, PgUp/PgDn: scroll, q: quit",
+                shown.len(),
+                self.entries.len()
+            ),
+            width,
+        )];
+
+        let list_height = shown.len().clamp(1, (height / 3).max(1));
+        let top = (self.selected + 1).saturating_sub(list_height);
+        for (row, &i) in shown.iter().enumerate().skip(top).take(list_height) {
+            let header = fit(&self.entries[i].header, width.saturating_sub(2));
+            match row == self.selected {
+                true => lines.push(format!("\x1b[7m> {header}\x1b[0m")),
+                false => lines.push(format!("  {header}")),
+            }
+        }
+        if shown.is_empty() {
+            lines.push("  no reports".into());
+        }
+        lines.push("─".repeat(width));
+
+        self.page = height.saturating_sub(lines.len()).max(1);
+        if let Some(&i) = shown.get(self.selected) {
+            let text: Vec<&str> = self.entries[i].text.lines().collect();
+            self.scroll = self.scroll.min(text.len().saturating_sub(self.page));
+            let rest = text.iter().skip(self.scroll).take(self.page);
+            lines.extend(rest.map(|line| line.to_string()));
+        }
+        lines.truncate(height);
+        lines
+    }
+
+    /// Show the screen and handle keys until the user quits.
+    fn run(mut self, out: &mut impl Write) -> io::Result<()> {
+        loop {
+            let (width, height) = terminal::size()?;
+            queue!(out, terminal::Clear(terminal::ClearType::All))?;
+            for (row, line) in self.draw(width.into(), height.into()).iter().enumerate() {
+                queue!(out, cursor::MoveTo(0, row as u16))?;
+                out.write_all(line.as_bytes())?;
+            }
+            out.flush()?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.key(key.code)
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The filter after `filter` among `len` values, from all values to each
+/// value and back.
+fn next_filter(filter: Option<usize>, len: usize) -> Option<usize> {
+    match filter {
+        None if len > 0 => Some(0),
+        Some(i) if i + 1 < len => Some(i + 1),
+        _ => None,
+    }
+}
+
+/// Cut `s` to `width` columns.
+fn fit(s: &str, width: usize) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = unicode_width(c.encode_utf8(&mut [0; 4])) as usize;
+        if used + w > width {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out
+}
+
+impl Emitter<'_> {
+    /// Browse the reports in the terminal.
+    ///
+    /// The reports are listed on the alternate screen, above the rendered
+    /// report selected, which turns a linter into a basic review tool:
+    /// `j`/`k` or the arrow keys select a report, `PgUp`/`PgDn` scroll it,
+    /// `f` and `s` cycle through the files and levels to show, and `q`
+    /// quits. The screen is restored when it returns.
+    ///
+    /// Requires the `tui` feature.
+    ///
+    /// If stdout is not a terminal, the reports are written as with
+    /// [`render_to_stdout`](Emitter::render_to_stdout). See
+    /// [`Report::render_to_stdout`](crate::Report::render_to_stdout) for the
+    /// accepted caches.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use musubi::{Emitter, Level, Report};
+    /// let mut emitter = Emitter::new()
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Error, "unknown variable")
+    ///             .with_label(8..9),
+    ///     )
+    ///     .with_report(
+    ///         Report::new()
+    ///             .with_title(Level::Warning, "unused variable")
+    ///             .with_label(4..5),
+    ///     );
+    /// emitter.review(("let a = b;", "main.rs"))?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn review(&mut self, cache: impl Into<RawCache>) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        if !stdout.is_terminal() {
+            return self.render_to_stdout(cache);
+        }
+        let review = Review::new(self, cache, false)?;
+        terminal::enable_raw_mode()?;
+        let result = execute!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            terminal::DisableLineWrap,
+            cursor::Hide
+        )
+        .and_then(|()| review.run(&mut stdout));
+        let restored = execute!(
+            stdout,
+            cursor::Show,
+            terminal::EnableLineWrap,
+            terminal::LeaveAlternateScreen
+        );
+        terminal::disable_raw_mode()?;
+        result.and(restored)
+    }
+}
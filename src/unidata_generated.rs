@@ -0,0 +1,606 @@
+/* generated from unidata.h by `just unidata-rs` */
+pub static ZEROWIDTH_TABLE: &[RangeTable] = &[
+    (0xAD, 0x300, 595),
+    (0x301, 0x36F, 1),
+    (0x483, 0x489, 1),
+    (0x591, 0x5BD, 1),
+    (0x5BF, 0x5C1, 2),
+    (0x5C2, 0x5C4, 2),
+    (0x5C5, 0x5C7, 2),
+    (0x610, 0x61A, 1),
+    (0x61C, 0x64B, 47),
+    (0x64C, 0x65F, 1),
+    (0x670, 0x6D6, 102),
+    (0x6D7, 0x6DC, 1),
+    (0x6DF, 0x6E4, 1),
+    (0x6E7, 0x6E8, 1),
+    (0x6EA, 0x6ED, 1),
+    (0x711, 0x730, 31),
+    (0x731, 0x74A, 1),
+    (0x7A6, 0x7B0, 1),
+    (0x7EB, 0x7F3, 1),
+    (0x7FD, 0x816, 25),
+    (0x817, 0x819, 1),
+    (0x81B, 0x823, 1),
+    (0x825, 0x827, 1),
+    (0x829, 0x82D, 1),
+    (0x859, 0x85B, 1),
+    (0x897, 0x89F, 1),
+    (0x8CA, 0x8E1, 1),
+    (0x8E3, 0x902, 1),
+    (0x93A, 0x93C, 2),
+    (0x941, 0x948, 1),
+    (0x94D, 0x951, 4),
+    (0x952, 0x957, 1),
+    (0x962, 0x963, 1),
+    (0x981, 0x9BC, 59),
+    (0x9BE, 0x9C1, 3),
+    (0x9C2, 0x9C4, 1),
+    (0x9CD, 0x9D7, 10),
+    (0x9E2, 0x9E3, 1),
+    (0x9FE, 0xA01, 3),
+    (0xA02, 0xA3C, 58),
+    (0xA41, 0xA42, 1),
+    (0xA47, 0xA48, 1),
+    (0xA4B, 0xA4D, 1),
+    (0xA51, 0xA70, 31),
+    (0xA71, 0xA75, 4),
+    (0xA81, 0xA82, 1),
+    (0xABC, 0xAC1, 5),
+    (0xAC2, 0xAC5, 1),
+    (0xAC7, 0xAC8, 1),
+    (0xACD, 0xAE2, 21),
+    (0xAE3, 0xAFA, 23),
+    (0xAFB, 0xAFF, 1),
+    (0xB01, 0xB3C, 59),
+    (0xB3E, 0xB3F, 1),
+    (0xB41, 0xB44, 1),
+    (0xB4D, 0xB55, 8),
+    (0xB56, 0xB57, 1),
+    (0xB62, 0xB63, 1),
+    (0xB82, 0xBBE, 60),
+    (0xBC0, 0xBCD, 13),
+    (0xBD7, 0xC00, 41),
+    (0xC04, 0xC3C, 56),
+    (0xC3E, 0xC40, 1),
+    (0xC46, 0xC48, 1),
+    (0xC4A, 0xC4D, 1),
+    (0xC55, 0xC56, 1),
+    (0xC62, 0xC63, 1),
+    (0xC81, 0xCBC, 59),
+    (0xCBF, 0xCC0, 1),
+    (0xCC2, 0xCC6, 4),
+    (0xCC7, 0xCC8, 1),
+    (0xCCA, 0xCCD, 1),
+    (0xCD5, 0xCD6, 1),
+    (0xCE2, 0xCE3, 1),
+    (0xD00, 0xD01, 1),
+    (0xD3B, 0xD3C, 1),
+    (0xD3E, 0xD41, 3),
+    (0xD42, 0xD44, 1),
+    (0xD4D, 0xD57, 10),
+    (0xD62, 0xD63, 1),
+    (0xD81, 0xDCA, 73),
+    (0xDCF, 0xDD2, 3),
+    (0xDD3, 0xDD4, 1),
+    (0xDD6, 0xDDF, 9),
+    (0xE31, 0xE34, 3),
+    (0xE35, 0xE3A, 1),
+    (0xE47, 0xE4E, 1),
+    (0xEB1, 0xEB4, 3),
+    (0xEB5, 0xEBC, 1),
+    (0xEC8, 0xECE, 1),
+    (0xF18, 0xF19, 1),
+    (0xF35, 0xF39, 2),
+    (0xF71, 0xF7E, 1),
+    (0xF80, 0xF84, 1),
+    (0xF86, 0xF87, 1),
+    (0xF8D, 0xF97, 1),
+    (0xF99, 0xFBC, 1),
+    (0xFC6, 0x102D, 103),
+    (0x102E, 0x1030, 1),
+    (0x1032, 0x1037, 1),
+    (0x1039, 0x103A, 1),
+    (0x103D, 0x103E, 1),
+    (0x1058, 0x1059, 1),
+    (0x105E, 0x1060, 1),
+    (0x1071, 0x1074, 1),
+    (0x1082, 0x1085, 3),
+    (0x1086, 0x108D, 7),
+    (0x109D, 0x115F, 194),
+    (0x1160, 0x135D, 509),
+    (0x135E, 0x135F, 1),
+    (0x1712, 0x1715, 1),
+    (0x1732, 0x1734, 1),
+    (0x1752, 0x1753, 1),
+    (0x1772, 0x1773, 1),
+    (0x17B4, 0x17B5, 1),
+    (0x17B7, 0x17BD, 1),
+    (0x17C6, 0x17C9, 3),
+    (0x17CA, 0x17D3, 1),
+    (0x17DD, 0x180B, 46),
+    (0x180C, 0x180F, 1),
+    (0x1885, 0x1886, 1),
+    (0x18A9, 0x1920, 119),
+    (0x1921, 0x1922, 1),
+    (0x1927, 0x1928, 1),
+    (0x1932, 0x1939, 7),
+    (0x193A, 0x193B, 1),
+    (0x1A17, 0x1A18, 1),
+    (0x1A1B, 0x1A56, 59),
+    (0x1A58, 0x1A5E, 1),
+    (0x1A60, 0x1A62, 2),
+    (0x1A65, 0x1A6C, 1),
+    (0x1A73, 0x1A7C, 1),
+    (0x1A7F, 0x1AB0, 49),
+    (0x1AB1, 0x1ADD, 1),
+    (0x1AE0, 0x1AEB, 1),
+    (0x1B00, 0x1B03, 1),
+    (0x1B34, 0x1B3D, 1),
+    (0x1B42, 0x1B44, 1),
+    (0x1B6B, 0x1B73, 1),
+    (0x1B80, 0x1B81, 1),
+    (0x1BA2, 0x1BA5, 1),
+    (0x1BA8, 0x1BAD, 1),
+    (0x1BE6, 0x1BE8, 2),
+    (0x1BE9, 0x1BED, 4),
+    (0x1BEF, 0x1BF3, 1),
+    (0x1C2C, 0x1C33, 1),
+    (0x1C36, 0x1C37, 1),
+    (0x1CD0, 0x1CD2, 1),
+    (0x1CD4, 0x1CE0, 1),
+    (0x1CE2, 0x1CE8, 1),
+    (0x1CED, 0x1CF4, 7),
+    (0x1CF8, 0x1CF9, 1),
+    (0x1DC0, 0x1DFF, 1),
+    (0x200B, 0x200F, 1),
+    (0x202A, 0x202E, 1),
+    (0x2060, 0x206F, 1),
+    (0x20D0, 0x20F0, 1),
+    (0x2CEF, 0x2CF1, 1),
+    (0x2D7F, 0x2DE0, 97),
+    (0x2DE1, 0x2DFF, 1),
+    (0x302A, 0x302F, 1),
+    (0x3099, 0x309A, 1),
+    (0x3164, 0xA66F, 29963),
+    (0xA670, 0xA672, 1),
+    (0xA674, 0xA67D, 1),
+    (0xA69E, 0xA69F, 1),
+    (0xA6F0, 0xA6F1, 1),
+    (0xA802, 0xA806, 4),
+    (0xA80B, 0xA825, 26),
+    (0xA826, 0xA82C, 6),
+    (0xA8C4, 0xA8C5, 1),
+    (0xA8E0, 0xA8F1, 1),
+    (0xA8FF, 0xA926, 39),
+    (0xA927, 0xA92D, 1),
+    (0xA947, 0xA951, 1),
+    (0xA953, 0xA980, 45),
+    (0xA981, 0xA982, 1),
+    (0xA9B3, 0xA9B6, 3),
+    (0xA9B7, 0xA9B9, 1),
+    (0xA9BC, 0xA9BD, 1),
+    (0xA9C0, 0xA9E5, 37),
+    (0xAA29, 0xAA2E, 1),
+    (0xAA31, 0xAA32, 1),
+    (0xAA35, 0xAA36, 1),
+    (0xAA43, 0xAA4C, 9),
+    (0xAA7C, 0xAAB0, 52),
+    (0xAAB2, 0xAAB4, 1),
+    (0xAAB7, 0xAAB8, 1),
+    (0xAABE, 0xAABF, 1),
+    (0xAAC1, 0xAAEC, 43),
+    (0xAAED, 0xAAF6, 9),
+    (0xABE5, 0xABE8, 3),
+    (0xABED, 0xFB1E, 20273),
+    (0xFE00, 0xFE0F, 1),
+    (0xFE20, 0xFE2F, 1),
+    (0xFEFF, 0xFF9E, 159),
+    (0xFF9F, 0xFFA0, 1),
+    (0xFFF0, 0xFFF8, 1),
+    (0x101FD, 0x102E0, 227),
+    (0x10376, 0x1037A, 1),
+    (0x10A01, 0x10A03, 1),
+    (0x10A05, 0x10A06, 1),
+    (0x10A0C, 0x10A0F, 1),
+    (0x10A38, 0x10A3A, 1),
+    (0x10A3F, 0x10AE5, 166),
+    (0x10AE6, 0x10D24, 574),
+    (0x10D25, 0x10D27, 1),
+    (0x10D69, 0x10D6D, 1),
+    (0x10EAB, 0x10EAC, 1),
+    (0x10EFA, 0x10EFF, 1),
+    (0x10F46, 0x10F50, 1),
+    (0x10F82, 0x10F85, 1),
+    (0x11001, 0x11038, 55),
+    (0x11039, 0x11046, 1),
+    (0x11070, 0x11073, 3),
+    (0x11074, 0x1107F, 11),
+    (0x11080, 0x11081, 1),
+    (0x110B3, 0x110B6, 1),
+    (0x110B9, 0x110BA, 1),
+    (0x110C2, 0x11100, 62),
+    (0x11101, 0x11102, 1),
+    (0x11127, 0x1112B, 1),
+    (0x1112D, 0x11134, 1),
+    (0x11173, 0x11180, 13),
+    (0x11181, 0x111B6, 53),
+    (0x111B7, 0x111BE, 1),
+    (0x111C0, 0x111C9, 9),
+    (0x111CA, 0x111CC, 1),
+    (0x111CF, 0x1122F, 96),
+    (0x11230, 0x11231, 1),
+    (0x11234, 0x11237, 1),
+    (0x1123E, 0x11241, 3),
+    (0x112DF, 0x112E3, 4),
+    (0x112E4, 0x112EA, 1),
+    (0x11300, 0x11301, 1),
+    (0x1133B, 0x1133C, 1),
+    (0x1133E, 0x11340, 2),
+    (0x1134D, 0x11357, 10),
+    (0x11366, 0x1136C, 1),
+    (0x11370, 0x11374, 1),
+    (0x113B8, 0x113BB, 3),
+    (0x113BC, 0x113C0, 1),
+    (0x113C2, 0x113C5, 3),
+    (0x113C7, 0x113C9, 1),
+    (0x113CE, 0x113D0, 1),
+    (0x113D2, 0x113E1, 15),
+    (0x113E2, 0x11438, 86),
+    (0x11439, 0x1143F, 1),
+    (0x11442, 0x11444, 1),
+    (0x11446, 0x1145E, 24),
+    (0x114B0, 0x114B3, 3),
+    (0x114B4, 0x114B8, 1),
+    (0x114BA, 0x114BD, 3),
+    (0x114BF, 0x114C0, 1),
+    (0x114C2, 0x114C3, 1),
+    (0x115AF, 0x115B2, 3),
+    (0x115B3, 0x115B5, 1),
+    (0x115BC, 0x115BD, 1),
+    (0x115BF, 0x115C0, 1),
+    (0x115DC, 0x115DD, 1),
+    (0x11633, 0x1163A, 1),
+    (0x1163D, 0x1163F, 2),
+    (0x11640, 0x116AB, 107),
+    (0x116AD, 0x116B0, 3),
+    (0x116B1, 0x116B7, 1),
+    (0x1171D, 0x1171F, 2),
+    (0x11722, 0x11725, 1),
+    (0x11727, 0x1172B, 1),
+    (0x1182F, 0x11837, 1),
+    (0x11839, 0x1183A, 1),
+    (0x11930, 0x1193B, 11),
+    (0x1193C, 0x1193E, 1),
+    (0x11943, 0x119D4, 145),
+    (0x119D5, 0x119D7, 1),
+    (0x119DA, 0x119DB, 1),
+    (0x119E0, 0x11A01, 33),
+    (0x11A02, 0x11A0A, 1),
+    (0x11A33, 0x11A38, 1),
+    (0x11A3B, 0x11A3E, 1),
+    (0x11A47, 0x11A51, 10),
+    (0x11A52, 0x11A56, 1),
+    (0x11A59, 0x11A5B, 1),
+    (0x11A8A, 0x11A96, 1),
+    (0x11A98, 0x11A99, 1),
+    (0x11B60, 0x11B62, 2),
+    (0x11B63, 0x11B64, 1),
+    (0x11B66, 0x11C30, 202),
+    (0x11C31, 0x11C36, 1),
+    (0x11C38, 0x11C3D, 1),
+    (0x11C3F, 0x11C92, 83),
+    (0x11C93, 0x11CA7, 1),
+    (0x11CAA, 0x11CB0, 1),
+    (0x11CB2, 0x11CB3, 1),
+    (0x11CB5, 0x11CB6, 1),
+    (0x11D31, 0x11D36, 1),
+    (0x11D3A, 0x11D3C, 2),
+    (0x11D3D, 0x11D3F, 2),
+    (0x11D40, 0x11D45, 1),
+    (0x11D47, 0x11D90, 73),
+    (0x11D91, 0x11D95, 4),
+    (0x11D97, 0x11EF3, 348),
+    (0x11EF4, 0x11F00, 12),
+    (0x11F01, 0x11F36, 53),
+    (0x11F37, 0x11F3A, 1),
+    (0x11F40, 0x11F42, 1),
+    (0x11F5A, 0x13440, 5350),
+    (0x13447, 0x13455, 1),
+    (0x1611E, 0x16129, 1),
+    (0x1612D, 0x1612F, 1),
+    (0x16AF0, 0x16AF4, 1),
+    (0x16B30, 0x16B36, 1),
+    (0x16F4F, 0x16F8F, 64),
+    (0x16F90, 0x16F92, 1),
+    (0x16FE4, 0x16FF0, 12),
+    (0x16FF1, 0x1BC9D, 19628),
+    (0x1BC9E, 0x1BCA0, 2),
+    (0x1BCA1, 0x1BCA3, 1),
+    (0x1CF00, 0x1CF2D, 1),
+    (0x1CF30, 0x1CF46, 1),
+    (0x1D165, 0x1D169, 1),
+    (0x1D16D, 0x1D182, 1),
+    (0x1D185, 0x1D18B, 1),
+    (0x1D1AA, 0x1D1AD, 1),
+    (0x1D242, 0x1D244, 1),
+    (0x1DA00, 0x1DA36, 1),
+    (0x1DA3B, 0x1DA6C, 1),
+    (0x1DA75, 0x1DA84, 15),
+    (0x1DA9B, 0x1DA9F, 1),
+    (0x1DAA1, 0x1DAAF, 1),
+    (0x1E000, 0x1E006, 1),
+    (0x1E008, 0x1E018, 1),
+    (0x1E01B, 0x1E021, 1),
+    (0x1E023, 0x1E024, 1),
+    (0x1E026, 0x1E02A, 1),
+    (0x1E08F, 0x1E130, 161),
+    (0x1E131, 0x1E136, 1),
+    (0x1E2AE, 0x1E2EC, 62),
+    (0x1E2ED, 0x1E2EF, 1),
+    (0x1E4EC, 0x1E4EF, 1),
+    (0x1E5EE, 0x1E5EF, 1),
+    (0x1E6E3, 0x1E6E6, 3),
+    (0x1E6EE, 0x1E6EF, 1),
+    (0x1E6F5, 0x1E8D0, 475),
+    (0x1E8D1, 0x1E8D6, 1),
+    (0x1E944, 0x1E94A, 1),
+    (0xE0000, 0xE0FFF, 1),
+];
+pub static DOUBLEWIDTH_TABLE: &[RangeTable] = &[
+    (0x1100, 0x115F, 1),
+    (0x231A, 0x231B, 1),
+    (0x2329, 0x232A, 1),
+    (0x23E9, 0x23EC, 1),
+    (0x23F0, 0x23F3, 3),
+    (0x25FD, 0x25FE, 1),
+    (0x2614, 0x2615, 1),
+    (0x2630, 0x2637, 1),
+    (0x2648, 0x2653, 1),
+    (0x267F, 0x268A, 11),
+    (0x268B, 0x268F, 1),
+    (0x2693, 0x26A1, 14),
+    (0x26AA, 0x26AB, 1),
+    (0x26BD, 0x26BE, 1),
+    (0x26C4, 0x26C5, 1),
+    (0x26CE, 0x26D4, 6),
+    (0x26EA, 0x26F2, 8),
+    (0x26F3, 0x26F5, 2),
+    (0x26FA, 0x26FD, 3),
+    (0x2705, 0x270A, 5),
+    (0x270B, 0x2728, 29),
+    (0x274C, 0x274E, 2),
+    (0x2753, 0x2755, 1),
+    (0x2757, 0x2795, 62),
+    (0x2796, 0x2797, 1),
+    (0x27B0, 0x27BF, 15),
+    (0x2B1B, 0x2B1C, 1),
+    (0x2B50, 0x2B55, 5),
+    (0x2E80, 0x2E99, 1),
+    (0x2E9B, 0x2EF3, 1),
+    (0x2F00, 0x2FD5, 1),
+    (0x2FF0, 0x303E, 1),
+    (0x3041, 0x3096, 1),
+    (0x3099, 0x30FF, 1),
+    (0x3105, 0x312F, 1),
+    (0x3131, 0x318E, 1),
+    (0x3190, 0x31E5, 1),
+    (0x31EF, 0x321E, 1),
+    (0x3220, 0x3247, 1),
+    (0x3250, 0xA48C, 1),
+    (0xA490, 0xA4C6, 1),
+    (0xA960, 0xA97C, 1),
+    (0xAC00, 0xD7A3, 1),
+    (0xF900, 0xFAFF, 1),
+    (0xFE10, 0xFE19, 1),
+    (0xFE30, 0xFE52, 1),
+    (0xFE54, 0xFE66, 1),
+    (0xFE68, 0xFE6B, 1),
+    (0xFF01, 0xFF60, 1),
+    (0xFFE0, 0xFFE6, 1),
+    (0x16FE0, 0x16FE4, 1),
+    (0x16FF0, 0x16FF6, 1),
+    (0x17000, 0x18CD5, 1),
+    (0x18CFF, 0x18D1E, 1),
+    (0x18D80, 0x18DF2, 1),
+    (0x1AFF0, 0x1AFF3, 1),
+    (0x1AFF5, 0x1AFFB, 1),
+    (0x1AFFD, 0x1AFFE, 1),
+    (0x1B000, 0x1B122, 1),
+    (0x1B132, 0x1B150, 30),
+    (0x1B151, 0x1B152, 1),
+    (0x1B155, 0x1B164, 15),
+    (0x1B165, 0x1B167, 1),
+    (0x1B170, 0x1B2FB, 1),
+    (0x1D300, 0x1D356, 1),
+    (0x1D360, 0x1D376, 1),
+    (0x1F004, 0x1F0CF, 203),
+    (0x1F18E, 0x1F191, 3),
+    (0x1F192, 0x1F19A, 1),
+    (0x1F200, 0x1F202, 1),
+    (0x1F210, 0x1F23B, 1),
+    (0x1F240, 0x1F248, 1),
+    (0x1F250, 0x1F251, 1),
+    (0x1F260, 0x1F265, 1),
+    (0x1F300, 0x1F320, 1),
+    (0x1F32D, 0x1F335, 1),
+    (0x1F337, 0x1F37C, 1),
+    (0x1F37E, 0x1F393, 1),
+    (0x1F3A0, 0x1F3CA, 1),
+    (0x1F3CF, 0x1F3D3, 1),
+    (0x1F3E0, 0x1F3F0, 1),
+    (0x1F3F4, 0x1F3F8, 4),
+    (0x1F3F9, 0x1F43E, 1),
+    (0x1F440, 0x1F442, 2),
+    (0x1F443, 0x1F4FC, 1),
+    (0x1F4FF, 0x1F53D, 1),
+    (0x1F54B, 0x1F54E, 1),
+    (0x1F550, 0x1F567, 1),
+    (0x1F57A, 0x1F595, 27),
+    (0x1F596, 0x1F5A4, 14),
+    (0x1F5FB, 0x1F64F, 1),
+    (0x1F680, 0x1F6C5, 1),
+    (0x1F6CC, 0x1F6D0, 4),
+    (0x1F6D1, 0x1F6D2, 1),
+    (0x1F6D5, 0x1F6D8, 1),
+    (0x1F6DC, 0x1F6DF, 1),
+    (0x1F6EB, 0x1F6EC, 1),
+    (0x1F6F4, 0x1F6FC, 1),
+    (0x1F7E0, 0x1F7EB, 1),
+    (0x1F7F0, 0x1F90C, 284),
+    (0x1F90D, 0x1F93A, 1),
+    (0x1F93C, 0x1F945, 1),
+    (0x1F947, 0x1F9FF, 1),
+    (0x1FA70, 0x1FA7C, 1),
+    (0x1FA80, 0x1FA8A, 1),
+    (0x1FA8E, 0x1FAC6, 1),
+    (0x1FAC8, 0x1FACD, 5),
+    (0x1FACE, 0x1FADC, 1),
+    (0x1FADF, 0x1FAEA, 1),
+    (0x1FAEF, 0x1FAF8, 1),
+    (0x20000, 0x2FFFD, 1),
+    (0x30000, 0x3FFFD, 1),
+];
+pub static AMBIWIDTH_TABLE: &[RangeTable] = &[
+    (0xA1, 0xA7, 3),
+    (0xA8, 0xAA, 2),
+    (0xAD, 0xAE, 1),
+    (0xB0, 0xB4, 1),
+    (0xB6, 0xBA, 1),
+    (0xBC, 0xBF, 1),
+    (0xC6, 0xD0, 10),
+    (0xD7, 0xD8, 1),
+    (0xDE, 0xE1, 1),
+    (0xE6, 0xE8, 2),
+    (0xE9, 0xEA, 1),
+    (0xEC, 0xED, 1),
+    (0xF0, 0xF2, 2),
+    (0xF3, 0xF7, 4),
+    (0xF8, 0xFA, 1),
+    (0xFC, 0xFE, 2),
+    (0x101, 0x111, 16),
+    (0x113, 0x11B, 8),
+    (0x126, 0x127, 1),
+    (0x12B, 0x131, 6),
+    (0x132, 0x133, 1),
+    (0x138, 0x13F, 7),
+    (0x140, 0x142, 1),
+    (0x144, 0x148, 4),
+    (0x149, 0x14B, 1),
+    (0x14D, 0x152, 5),
+    (0x153, 0x166, 19),
+    (0x167, 0x16B, 4),
+    (0x1CE, 0x1DC, 2),
+    (0x251, 0x261, 16),
+    (0x2C4, 0x2C7, 3),
+    (0x2C9, 0x2CB, 1),
+    (0x2CD, 0x2D0, 3),
+    (0x2D8, 0x2DB, 1),
+    (0x2DD, 0x2DF, 2),
+    (0x300, 0x36F, 1),
+    (0x391, 0x3A1, 1),
+    (0x3A3, 0x3A9, 1),
+    (0x3B1, 0x3C1, 1),
+    (0x3C3, 0x3C9, 1),
+    (0x401, 0x410, 15),
+    (0x411, 0x44F, 1),
+    (0x451, 0x2010, 7103),
+    (0x2013, 0x2016, 1),
+    (0x2018, 0x2019, 1),
+    (0x201C, 0x201D, 1),
+    (0x2020, 0x2022, 1),
+    (0x2024, 0x2027, 1),
+    (0x2030, 0x2032, 2),
+    (0x2033, 0x2035, 2),
+    (0x203B, 0x203E, 3),
+    (0x2074, 0x207F, 11),
+    (0x2081, 0x2084, 1),
+    (0x20AC, 0x2103, 87),
+    (0x2105, 0x2109, 4),
+    (0x2113, 0x2116, 3),
+    (0x2121, 0x2122, 1),
+    (0x2126, 0x212B, 5),
+    (0x2153, 0x2154, 1),
+    (0x215B, 0x215E, 1),
+    (0x2160, 0x216B, 1),
+    (0x2170, 0x2179, 1),
+    (0x2189, 0x2190, 7),
+    (0x2191, 0x2199, 1),
+    (0x21B8, 0x21B9, 1),
+    (0x21D2, 0x21D4, 2),
+    (0x21E7, 0x2200, 25),
+    (0x2202, 0x2203, 1),
+    (0x2207, 0x2208, 1),
+    (0x220B, 0x220F, 4),
+    (0x2211, 0x2215, 4),
+    (0x221A, 0x221D, 3),
+    (0x221E, 0x2220, 1),
+    (0x2223, 0x2227, 2),
+    (0x2228, 0x222C, 1),
+    (0x222E, 0x2234, 6),
+    (0x2235, 0x2237, 1),
+    (0x223C, 0x223D, 1),
+    (0x2248, 0x224C, 4),
+    (0x2252, 0x2260, 14),
+    (0x2261, 0x2264, 3),
+    (0x2265, 0x2267, 1),
+    (0x226A, 0x226B, 1),
+    (0x226E, 0x226F, 1),
+    (0x2282, 0x2283, 1),
+    (0x2286, 0x2287, 1),
+    (0x2295, 0x2299, 4),
+    (0x22A5, 0x22BF, 26),
+    (0x2312, 0x2460, 334),
+    (0x2461, 0x24E9, 1),
+    (0x24EB, 0x254B, 1),
+    (0x2550, 0x2573, 1),
+    (0x2580, 0x258F, 1),
+    (0x2592, 0x2595, 1),
+    (0x25A0, 0x25A1, 1),
+    (0x25A3, 0x25A9, 1),
+    (0x25B2, 0x25B3, 1),
+    (0x25B6, 0x25B7, 1),
+    (0x25BC, 0x25BD, 1),
+    (0x25C0, 0x25C1, 1),
+    (0x25C6, 0x25C8, 1),
+    (0x25CB, 0x25CE, 3),
+    (0x25CF, 0x25D1, 1),
+    (0x25E2, 0x25E5, 1),
+    (0x25EF, 0x2605, 22),
+    (0x2606, 0x2609, 3),
+    (0x260E, 0x260F, 1),
+    (0x261C, 0x261E, 2),
+    (0x2640, 0x2642, 2),
+    (0x2660, 0x2661, 1),
+    (0x2663, 0x2665, 1),
+    (0x2667, 0x266A, 1),
+    (0x266C, 0x266D, 1),
+    (0x266F, 0x269E, 47),
+    (0x269F, 0x26BF, 32),
+    (0x26C6, 0x26CD, 1),
+    (0x26CF, 0x26D3, 1),
+    (0x26D5, 0x26E1, 1),
+    (0x26E3, 0x26E8, 5),
+    (0x26E9, 0x26EB, 2),
+    (0x26EC, 0x26F1, 1),
+    (0x26F4, 0x26F6, 2),
+    (0x26F7, 0x26F9, 1),
+    (0x26FB, 0x26FC, 1),
+    (0x26FE, 0x26FF, 1),
+    (0x273D, 0x2776, 57),
+    (0x2777, 0x277F, 1),
+    (0x2B56, 0x2B59, 1),
+    (0x3248, 0x324F, 1),
+    (0xE000, 0xF8FF, 1),
+    (0xFE00, 0xFE0F, 1),
+    (0xFFFD, 0x1F100, 61699),
+    (0x1F101, 0x1F10A, 1),
+    (0x1F110, 0x1F12D, 1),
+    (0x1F130, 0x1F169, 1),
+    (0x1F170, 0x1F18D, 1),
+    (0x1F18F, 0x1F190, 1),
+    (0x1F19B, 0x1F1AC, 1),
+    (0xE0100, 0xE01EF, 1),
+    (0xF0000, 0xFFFFD, 1),
+    (0x100000, 0x10FFFD, 1),
+];
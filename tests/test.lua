@@ -53,6 +53,124 @@ do
     lu.assertNotEquals(colors[2], colors[3])
     lu.assertNotEquals(colors[1], colors[3])
   end
+
+  local function marker(name)
+    return function(kind) return "<" .. name .. kind .. ">" end
+  end
+
+  function TestColor.test_inline_code()
+    local msg = remove_trailing(
+      mu.report("unknown `foo`")
+      :config(mu.config():inline_code(true):char_set "ascii")
+      :source("apple"):render()
+    )
+    lu.assertEquals(msg,
+      "\27[31mError:\27[0m unknown \27[38;5;180m`foo`\27[0m\n")
+    msg = remove_trailing(
+      mu.report("unknown `foo`")
+      :config(no_color_ascii():inline_code(true))
+      :source("apple"):render()
+    )
+    lu.assertEquals(msg, "Error: unknown `foo`\n")
+  end
+
+  function TestColor.test_dim_unlabeled()
+    local function render(dim)
+      return mu.report("dim")
+          :config(mu.config():char_set("ascii"):dim_unlabeled(dim))
+          :label(1, 1)
+          :source("apple"):render()
+    end
+    lu.assertStrContains(render(true), "\27[38;5;249mpple\27[0m\n")
+    lu.assertStrContains(render(false), "\27[0mpple\n")
+  end
+
+  function TestColor.test_context()
+    local msg = mu.report("context")
+        :config(mu.config():char_set "ascii")
+        :label(7, 9):message("here")
+        :context(1, 15)
+        :source("fn f() {\n  x\n}\nfn g() {}"):render()
+    lu.assertStrContains(msg, "\27[2m  x\27[0m\n")
+  end
+
+  function TestColor.test_msgref()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "msgref")
+      :label(1, 5):message("apple"):color(marker "a")
+      :label(10, 15):message("not an apple"):color(marker "b")
+      :msgref(8, 12, 1)
+      :source("apple == orange"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: msgref
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | <alabel>apple<areset> == <blabel>orange<breset>
+   | <alabel>^^|^^<areset>    <blabel>^^^|^^<breset>
+   |   <alabel>|<areset>         <blabel>`----<breset> not an <alabel>apple<areset>
+   |   <alabel>|<areset>
+   |   <alabel>`--------------<areset> apple
+---'
+]])
+  end
+
+  function TestColor.test_auto_priority()
+    local function render(auto)
+      return remove_trailing(
+        mu.report()
+        :config(no_color_ascii():auto_priority(auto))
+        :title("Error", "auto_priority")
+        :label(1, 9):color(marker "a"):primary()
+        :label(3, 5):color(marker "b")
+        :source("apple pie"):render()
+      )
+    end
+    lu.assertEquals(render(false), [[
+Error: auto_priority
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | <alabel>ap<areset><blabel>ple<breset><alabel> pie<areset>
+   | <alabel>^^<areset><blabel>^^^<breset><alabel>^^^^<areset>
+---'
+]])
+    lu.assertEquals(render(true), [[
+Error: auto_priority
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | <alabel>apple pie<areset>
+   | <alabel>^^^^^^^^^<areset>
+---'
+]])
+  end
+
+  function TestColor.test_colorkind()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "colorkind")
+      :label(1, 5):message("expected"):color(marker "a"):colorkind "expected"
+      :label(10, 15):message("found"):color(marker "b"):colorkind "found"
+      :source("apple == orange"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: colorkind
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | <aexpected>apple<areset> == <bfound>orange<breset>
+   | <aexpected>^^|^^<areset>    <bfound>^^^|^^<breset>
+   |   <aexpected>|<areset>         <bfound>`----<breset> found
+   |   <aexpected>|<areset>
+   |   <aexpected>`--------------<areset> expected
+---'
+]])
+    lu.assertErrorMsgContains("invalid option 'margin'",
+      function() mu.report():label(1, 1):colorkind "margin" end)
+    lu.assertErrorMsgContains("invalid parameter",
+      function() mu.report():colorkind "found" end)
+  end
 end
 
 local TestWrite = {}
@@ -1823,6 +1941,598 @@ Error: context lines test
    |    `---- label on line 8
  9 | ninth line
 ---'
+]])
+  end
+
+  function TestWrite.test_levels()
+    for _, level in ipairs { "Info", "Note", "Hint", "Advice" } do
+      local msg = remove_trailing(
+        mu.report()
+        :config(no_color_ascii())
+        :title(level, "level test")
+        :label(1, 5):message("here")
+        :source("apple"):render()
+      )
+      lu.assertEquals(msg, level .. [[: level test
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple
+   | ^^|^^
+   |   `---- here
+---'
+]])
+    end
+  end
+
+  function TestWrite.test_markup()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():markup(true))
+      :title("Error", "markup")
+      :label(1, 5):message("this is **not** an orange")
+      :source("apple"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: markup
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple
+   | ^^|^^
+   |   `---- this is not an orange
+---'
+]])
+  end
+
+  function TestWrite.test_normalize()
+    local code = "cafe\204\129 x"
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():normalize(true))
+      :title("Error", "normalize")
+      :label(6, 6):message("here")
+      :source(code):render()
+    )
+    lu.assertEquals(msg, [[
+Error: normalize
+   ,-[ <unknown>:1:5 ]
+   |
+ 1 | ]] .. code .. "\n" .. [[
+   |     |
+   |     `-- here
+---'
+]])
+    msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "normalize")
+      :label(6, 6):message("here")
+      :source(code):render()
+    )
+    lu.assertStrContains(msg, "<unknown>:1:6")
+  end
+
+  function TestWrite.test_bidi_isolate()
+    local code = "x = \215\169\215\156\215\149\215\157"
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():bidi_isolate(true))
+      :title("Error", "bidi")
+      :label(1, 3):message("here")
+      :source(code):render()
+    )
+    lu.assertEquals(msg, [[
+Error: bidi
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | ]] .. "\226\129\168" .. code .. "\226\129\169\n" .. [[
+   | ^|^
+   |  `--- here
+---'
+]])
+  end
+
+  function TestWrite.test_auto_location()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():auto_location(false))
+      :title("Error", "auto_location")
+      :label(7, 12):message("here")
+      :source("apple\norange"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: auto_location
+   ,-[ <unknown> ]
+   |
+ 2 | orange
+   | ^^^|^^
+   |    `---- here
+---'
+]])
+  end
+
+  function TestWrite.test_location()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "location")
+      :label(1, 5)
+      :location(8)
+      :source("apple\norange"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: location
+   ,-[ <unknown>:2:2 ]
+   |
+ 1 | apple
+   | ^^^^^
+---'
+]])
+  end
+
+  function TestWrite.test_caret_only()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():caret_only(true))
+      :title("Error", "caret_only")
+      :label(3, 3):message("here")
+      :source("a + b"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: caret_only
+   ,-[ <unknown>:1:3 ]
+   |
+ 1 | a + b
+   |   ^-- here
+---'
+]])
+  end
+
+  function TestWrite.test_one_based_columns()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():one_based_columns(false))
+      :title("Error", "one_based_columns")
+      :label(3, 3):message("here")
+      :source("a + b"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: one_based_columns
+   ,-[ <unknown>:1:2 ]
+   |
+ 1 | a + b
+   |   |
+   |   `-- here
+---'
+]])
+  end
+
+  function TestWrite.test_tab_stops()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():tab_stops { 2, 8 })
+      :title("Error", "tab_stops")
+      :label(5, 5):message("here")
+      :source("a\tb\tc\td"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: tab_stops
+   ,-[ <unknown>:1:5 ]
+   |
+ 1 | a b     c   d
+   |         |
+   |         `---- here
+---'
+]])
+    lu.assertErrorMsgContains("too many tab stops", function()
+      local stops = {}
+      for i = 1, 17 do stops[i] = i * 2 end
+      mu.config():tab_stops(stops)
+    end)
+  end
+
+  function TestWrite.test_overlap()
+    local function render(overlap)
+      return remove_trailing(
+        mu.report()
+        :config(no_color_ascii():overlap(overlap))
+        :title("Error", "overlap")
+        :label(1, 5):message("first")
+        :label(3, 8):message("second")
+        :source("apple pie"):render()
+      )
+    end
+    lu.assertEquals(render "merge", [[
+Error: overlap
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple pie
+   | ^^|^^|^^
+   |   |  `---- second
+   |   |
+   |   `------- first
+---'
+]])
+    lu.assertEquals(render "stack", [[
+Error: overlap
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple pie
+   |   ^^^|^^
+   | ^^|^^|
+   |   |  `---- second
+   |   |
+   |   `------- first
+---'
+]])
+    lu.assertEquals(render "hide", [[
+Error: overlap
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple pie
+   | ^^|^^
+   |   `---- first
+---'
+]])
+  end
+
+  function TestWrite.test_max_depth()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():max_depth(1))
+      :title("Error", "max_depth")
+      :label(1, 13):message("outer")
+      :label(3, 9):message("inner")
+      :source("one\ntwo\nthree\n"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: max_depth
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | ,-> one
+   : :
+ 3 | |-> three
+   | |
+   | `----------- outer
+   |
+   | > 1:3: inner
+---'
+]])
+  end
+
+  function TestWrite.test_line_no_sep()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():line_no_sep ",")
+      :title("Error", "line_no_sep")
+      :label(1, 5):message("here")
+      :source("apple", nil, 12344):render()
+    )
+    lu.assertEquals(msg, [[
+Error: line_no_sep
+        ,-[ <unknown>:12345:1 ]
+        |
+ 12,345 | apple
+        | ^^|^^
+        |   `---- here
+--------'
+]])
+  end
+
+  function TestWrite.test_gutter_bar()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():gutter_bar(true))
+      :title("Error", "gutter_bar")
+      :label(1, 5):message("here")
+      :source("apple"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: gutter_bar
+|  ,-[ <unknown>:1:1 ]
+|  |
+|1 | apple
+|  | ^^|^^
+|  |   `---- here
+|--'
+]])
+  end
+
+  function TestWrite.test_collapse_notes()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii():collapse_notes(2))
+      :title("Error", "collapse_notes")
+      :label(1, 5)
+      :note("one\ntwo\nthree\nfour")
+      :source("apple"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: collapse_notes
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple
+   | ^^^^^
+   |
+   | Note: one
+   |       two
+   |       ... 2 more lines
+---'
+]])
+  end
+
+  function TestWrite.test_context()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "context")
+      :label(7, 9):message("here")
+      :context(1, 15)
+      :source("fn f() {\n  x\n}\nfn g() {}"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: context
+   ,-[ <unknown>:1:7 ]
+   |
+ 1 | fn f() {
+   |       ^|^
+   |        `--- here
+ 2 |   x
+ 3 | }
+---'
+]])
+  end
+
+  function TestWrite.test_suggest()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "suggest")
+      :label(7, 12)
+      :suggest(7, 12, "apple", "use an apple")
+      :source("apple orange"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: suggest
+   ,-[ <unknown>:1:7 ]
+   |
+ 1 | apple orange
+   |       ^^^^^^
+   |
+   | Help: use an apple
+ 1 | apple apple
+   |       ^^^^^
+---'
+]])
+    msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "suggest")
+      :label(7, 12)
+      :suggest(7, 12, "apple")
+      :source("apple orange"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: suggest
+   ,-[ <unknown>:1:7 ]
+   |
+ 1 | apple orange
+   |       ^^^^^^
+   |
+   | Help: replace with `apple`
+ 1 | apple apple
+   |       ^^^^^
+---'
+]])
+  end
+
+  function TestWrite.test_suggestpart()
+    local msg = remove_trailing(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "suggestpart")
+      :label(1, 12)
+      :suggest(1, 5, "orange", "swap them")
+      :suggestpart(7, 12, "apple")
+      :source("apple orange"):render()
+    )
+    lu.assertEquals(msg, [[
+Error: suggestpart
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple orange
+   | ^^^^^^^^^^^^
+   |
+   | Help: swap them
+ 1 | orange apple
+   | ^^^^^^ ^^^^^
+---'
+]])
+    lu.assertErrorMsgContains("invalid parameter", function()
+      mu.report():suggestpart(1, 5, "orange")
+    end)
+  end
+
+  function TestWrite.test_source_order()
+    local function render(order)
+      return remove_trailing(
+        mu.report()
+        :config(no_color_ascii():source_order(order))
+        :title("Error", "source_order")
+        :source("orange", "b.lua")
+        :source("apple", "a.lua")
+        :source("pear", "c.lua")
+        :label(1, 4, 3)
+        :label(1, 5, 2):primary()
+        :label(1, 6, 1)
+        :render()
+      )
+    end
+    lu.assertEquals(render "cache", [[
+Error: source_order
+   ,-[ b.lua:1:1 ]
+   |
+ 1 | orange
+   | ^^^^^^
+   |
+   |-[ a.lua:1:1 ]
+   |
+ 1 | apple
+   | ^^^^^
+   |
+   |-[ c.lua:1:1 ]
+   |
+ 1 | pear
+   | ^^^^
+---'
+]])
+    lu.assertEquals(render "labels", [[
+Error: source_order
+   ,-[ c.lua:1:1 ]
+   |
+ 1 | pear
+   | ^^^^
+   |
+   |-[ a.lua:1:1 ]
+   |
+ 1 | apple
+   | ^^^^^
+   |
+   |-[ b.lua:1:1 ]
+   |
+ 1 | orange
+   | ^^^^^^
+---'
+]])
+    lu.assertEquals(render "name", [[
+Error: source_order
+   ,-[ a.lua:1:1 ]
+   |
+ 1 | apple
+   | ^^^^^
+   |
+   |-[ b.lua:1:1 ]
+   |
+ 1 | orange
+   | ^^^^^^
+   |
+   |-[ c.lua:1:1 ]
+   |
+ 1 | pear
+   | ^^^^
+---'
+]])
+    lu.assertEquals(render "primary", [[
+Error: source_order
+   ,-[ a.lua:1:1 ]
+   |
+ 1 | apple
+   | ^^^^^
+   |
+   |-[ b.lua:1:1 ]
+   |
+ 1 | orange
+   | ^^^^^^
+   |
+   |-[ c.lua:1:1 ]
+   |
+ 1 | pear
+   | ^^^^
+---'
+]])
+  end
+
+  function TestWrite.test_cache_newline()
+    local cache = mu.cache()
+        :source("one\r\ntwo\r\n", "a.lua")
+        :source("apple", "b.lua")
+        :source("a\rb\nc", "c.lua")
+        :source("a\rb", "d.lua")
+    lu.assertEquals(cache:newline(4, "lf"), cache)
+    lu.assertEquals(cache:newline(1), "crlf")
+    lu.assertEquals(cache:newline(2), "auto")
+    lu.assertEquals(cache:newline(3), "mixed")
+    lu.assertEquals(cache:newline(4), "lf")
+    lu.assertErrorMsgContains("source id out of range",
+      function() cache:newline(5) end)
+    local msg = remove_trailing(cache:render(
+      mu.report()
+      :config(no_color_ascii())
+      :title("Error", "newline")
+      :label(6, 8):message("here")
+    ))
+    lu.assertEquals(msg, [[
+Error: newline
+   ,-[ a.lua:2:1 ]
+   |
+ 2 | two
+   | ^|^
+   |  `--- here
+---'
+]])
+  end
+
+  function TestWrite.test_render_with_other_cache()
+    local report = mu.report()
+        :config(no_color_ascii())
+        :title("Error", "relayout")
+        :label(1, 5):message("here")
+    local cache1 = mu.cache():source("apple", "a.lua")
+    local cache2 = mu.cache():source("one\ntwo", "b.lua")
+    local msg = remove_trailing(cache1:render(report))
+    lu.assertEquals(msg, [[
+Error: relayout
+   ,-[ a.lua:1:1 ]
+   |
+ 1 | apple
+   | ^^|^^
+   |   `---- here
+---'
+]])
+    lu.assertEquals(remove_trailing(cache2:render(report)), [[
+Error: relayout
+   ,-[ b.lua:1:1 ]
+   |
+ 1 | ,-> one
+ 2 | |-> two
+   | |
+   | `-------- here
+---'
+]])
+    lu.assertEquals(remove_trailing(cache1:render(report)), msg)
+  end
+
+  function TestWrite.test_footer()
+    local function render(footer)
+      return mu.report()
+          :config(no_color_ascii():footer(footer))
+          :title("Error", "footer")
+          :code("E001")
+          :label(1, 5)
+          :source("apple"):render()
+    end
+    lu.assertEquals(remove_trailing(render "border"), [[
+[E001] Error: footer
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple
+   | ^^^^^
+---'
+]])
+    lu.assertEquals(remove_trailing(render "omit"), [[
+[E001] Error: footer
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple
+   | ^^^^^
+]])
+    lu.assertEquals(render("blank"), render("omit") .. "\n")
+    lu.assertEquals(remove_trailing(render "labeled"), [[
+[E001] Error: footer
+   ,-[ <unknown>:1:1 ]
+   |
+ 1 | apple
+   | ^^^^^
+---'-[ end E001 ]
 ]])
   end
 end